@@ -0,0 +1,38 @@
+//! Runtimes left in the working tree by earlier versions
+//!
+//! Node.js and Ruby runtimes used to be installed into `.runtime` in the
+//! directory hooks ran from, inside the repository, where they could be
+//! committed or linted. They now live in the shared [`runtimes_dir`], so a
+//! `.runtime` holding only runtimes is stale and can be removed.
+//!
+//! [`runtimes_dir`]: super::runtimes_dir
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the directory runtimes were installed into
+pub const LEGACY_RUNTIME_DIR: &str = ".runtime";
+
+/// Directories earlier versions created in `.runtime`
+const RUNTIME_NAMES: &[&str] = &["node", "ruby"];
+
+/// Find a stale `.runtime` directory in `dir`
+///
+/// A `.runtime` holding anything besides the `node` and `ruby` directories
+/// RustyHook created isn't RustyHook's, and isn't reported.
+pub fn find_legacy_runtime(dir: &Path) -> Option<PathBuf> {
+    let runtime_dir = dir.join(LEGACY_RUNTIME_DIR);
+    let entries: Vec<_> = fs::read_dir(&runtime_dir).ok()?.filter_map(Result::ok).collect();
+    let ours = !entries.is_empty()
+        && entries.iter().all(|entry| {
+            entry.path().is_dir() && RUNTIME_NAMES.iter().any(|name| entry.file_name() == *name)
+        });
+    ours.then_some(runtime_dir)
+}
+
+/// Remove a stale `.runtime` directory, returning how many bytes it held
+pub fn remove_legacy_runtime(runtime_dir: &Path) -> std::io::Result<u64> {
+    let size = crate::toolchains::downloads::dir_size(runtime_dir);
+    fs::remove_dir_all(runtime_dir)?;
+    Ok(size)
+}
@@ -1,11 +1,228 @@
 //! Cache module for RustyHook
 //!
 //! This module provides functionality for caching environments and tools.
+//!
+//! Metadata fetched from the network, like hook repo definitions and the
+//! versions `latest` resolves to, and the classification of files by their
+//! Git attributes, is cached with a [`CacheManager`] per [`CacheCategory`],
+//! each with the TTL the config's `cache_ttl` sets. Hits and misses are
+//! counted for the whole process, see [`stats`].
 
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+use crate::config::parser::CacheTtl;
+
+pub mod legacy_runtime;
+pub mod orphans;
+
+/// Environment variable overriding the cache directory
+pub const CACHE_DIR_ENV: &str = "RUSTYHOOK_CACHE_DIR";
+
+/// Name of the default cache directory, in the user's cache directory
+pub const DEFAULT_CACHE_DIR: &str = "rustyhook";
+
+/// Directory of the cache root holding the caches of hooks' tools
+pub const HOOK_CACHE_DIR: &str = "hook-cache";
+
+/// Directory of the cache root holding runtimes, like Node.js and Ruby builds
+pub const RUNTIMES_DIR: &str = "runtimes";
+
+/// Directory of the cache root holding the home directories of tool environments
+pub const TOOL_HOME_DIR: &str = "home";
+
+/// Environment variable giving a hook its cache directory
+pub const HOOK_CACHE_ENV: &str = "RH_HOOK_CACHE_DIR";
+
+/// Placeholder replaced with the hook's cache directory in `args` and `env`
+pub const HOOK_CACHE_PLACEHOLDER: &str = "{hook_cache_dir}";
+
+/// Directory of the cache root holding cached metadata
+pub const METADATA_DIR: &str = "metadata";
+
+/// A kind of cached metadata
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheCategory {
+    /// Hook definitions read from the `.pre-commit-hooks.yaml` of hook repos
+    HookRepos,
+    /// Versions `latest` resolved to in package registries
+    Versions,
+    /// Attributes of files, as `.gitattributes` classifies them
+    FileAttributes,
+}
+
+impl CacheCategory {
+    /// Every category
+    pub const ALL: [CacheCategory; 3] = [CacheCategory::HookRepos, CacheCategory::Versions, CacheCategory::FileAttributes];
+
+    /// Get the name of the category, which is also its directory
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheCategory::HookRepos => "hook-repos",
+            CacheCategory::Versions => "versions",
+            CacheCategory::FileAttributes => "file-attributes",
+        }
+    }
+
+    /// Get how long entries of the category are reused
+    pub fn ttl(&self, ttl: &CacheTtl) -> Duration {
+        Duration::from_secs(match self {
+            CacheCategory::HookRepos => ttl.hook_repos,
+            CacheCategory::Versions => ttl.versions,
+            CacheCategory::FileAttributes => ttl.file_attributes,
+        })
+    }
+
+    /// Get the position of the category in [`CacheCategory::ALL`]
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// TTLs set for the rest of the process, for caches used without a config at hand
+static TTLS: Mutex<Option<CacheTtl>> = Mutex::new(None);
+
+/// Hits of each category's cache in this process
+static HITS: [AtomicUsize; 3] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+/// Misses of each category's cache in this process
+static MISSES: [AtomicUsize; 3] = [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+/// Set the TTLs of caches for the rest of the process, from the config
+pub fn set_ttls(ttl: CacheTtl) {
+    if let Ok(mut ttls) = TTLS.lock() {
+        *ttls = Some(ttl);
+    }
+}
+
+/// Get the TTLs of caches set for the process, or the defaults
+pub fn ttls() -> CacheTtl {
+    TTLS.lock().ok().and_then(|ttls| *ttls).unwrap_or_default()
+}
+
+/// How often a category's cache was used in this process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The category
+    pub category: CacheCategory,
+    /// Lookups that found a fresh entry
+    pub hits: usize,
+    /// Lookups that found no entry, or an expired one
+    pub misses: usize,
+}
+
+/// Get how often each category's cache was used in this process
+pub fn stats() -> Vec<CacheStats> {
+    CacheCategory::ALL
+        .iter()
+        .map(|category| CacheStats {
+            category: *category,
+            hits: HITS[category.index()].load(Ordering::Relaxed),
+            misses: MISSES[category.index()].load(Ordering::Relaxed),
+        })
+        .collect()
+}
+
+/// Turn a name into a file name, replacing the characters that don't belong in one
+pub fn file_name_key(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect()
+}
+
+/// Get the name of a hook's cache directory
+///
+/// Projects sharing a cache root, and repos of one config, can have hooks
+/// with the same ID, so the name is the hook ID followed by a hash of the
+/// project directory, the repo and the hook ID. Matrix expansions get their
+/// own directory, with the characters of their `id[value]` form that don't
+/// belong in a file name replaced.
+pub fn hook_cache_key(project: &Path, repo: &str, hook_id: &str) -> String {
+    let project = project.canonicalize().unwrap_or_else(|_| project.to_path_buf());
+    let mut hasher = Sha256::new();
+    for part in [project.to_string_lossy().as_ref(), repo, hook_id] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    let hash: String = hasher.finalize().iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}", file_name_key(hook_id), hash)
+}
+
+/// Get the cache directory of a hook of a project
+///
+/// The directory persists across runs and is only removed by `clean`, or by
+/// pruning once no project configures the hook.
+pub fn hook_cache_dir(cache_root: &Path, project: &Path, repo: &str, hook_id: &str) -> PathBuf {
+    cache_root.join(HOOK_CACHE_DIR).join(hook_cache_key(project, repo, hook_id))
+}
+
+/// Make a path absolute, relative to the working directory
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    }
+}
+
+/// Get the user's cache directory
+///
+/// This is `%LOCALAPPDATA%` on Windows, and `$XDG_CACHE_HOME` or
+/// `~/.cache` elsewhere. Relative values are ignored, like the XDG base
+/// directory specification asks.
+fn user_cache_dir() -> Option<PathBuf> {
+    let absolute_var = |name: &str| std::env::var_os(name).map(PathBuf::from).filter(|dir| dir.is_absolute());
+    if cfg!(windows) {
+        return absolute_var("LOCALAPPDATA");
+    }
+    absolute_var("XDG_CACHE_HOME").or_else(|| absolute_var("HOME").map(|home| home.join(".cache")))
+}
+
+/// Get the cache directory to use when there's no configuration
+///
+/// This is `RUSTYHOOK_CACHE_DIR` if set, and `rustyhook` in the user's cache
+/// directory otherwise, so every project shares one cache root. Tool
+/// environments go in its `venvs` directory and downloads in its `cache`
+/// directory. Without a home directory, it is `.rustyhook` in the working
+/// directory.
+pub fn default_cache_dir() -> PathBuf {
+    match std::env::var_os(CACHE_DIR_ENV) {
+        Some(dir) if !dir.is_empty() => absolute(Path::new(&dir)),
+        _ => match user_cache_dir() {
+            Some(dir) => dir.join(DEFAULT_CACHE_DIR),
+            None => absolute(Path::new(".rustyhook")),
+        },
+    }
+}
+
+/// Get the directory of runtimes in a cache root, like Node.js and Ruby builds
+///
+/// Every repository using the cache root shares them, and `clean` removes them.
+pub fn runtimes_dir(cache_root: &Path) -> PathBuf {
+    cache_root.join(RUNTIMES_DIR)
+}
+
+/// Resolve the cache directory
+///
+/// The `--cache-dir` option wins over `RUSTYHOOK_CACHE_DIR`, which wins over
+/// the `cache_dir` config setting.
+pub fn resolve_cache_dir(cli_cache_dir: Option<&Path>, config: &Config) -> PathBuf {
+    if let Some(dir) = cli_cache_dir {
+        return absolute(dir);
+    }
+    match (std::env::var_os(CACHE_DIR_ENV), &config.cache_dir) {
+        (Some(dir), _) if !dir.is_empty() => absolute(Path::new(&dir)),
+        (_, Some(dir)) => absolute(dir),
+        _ => default_cache_dir(),
+    }
+}
+
 /// Error type for cache operations
 #[derive(Debug)]
 pub enum CacheError {
@@ -33,6 +250,8 @@ pub struct CacheManager {
     cache_dir: PathBuf,
     /// Maximum age of cache entries
     max_age: Duration,
+    /// Category whose hits and misses are counted
+    category: Option<CacheCategory>,
 }
 
 impl CacheManager {
@@ -41,8 +260,23 @@ impl CacheManager {
         CacheManager {
             cache_dir,
             max_age,
+            category: None,
         }
     }
+
+    /// Create the cache manager of a category of metadata in a cache root
+    pub fn for_category(cache_root: &Path, category: CacheCategory, ttl: &CacheTtl) -> Self {
+        CacheManager {
+            cache_dir: cache_root.join(METADATA_DIR).join(category.name()),
+            max_age: category.ttl(ttl),
+            category: Some(category),
+        }
+    }
+
+    /// Get the name of the entry file of a key, which may contain any character
+    pub fn entry_name(key: &str) -> String {
+        format!("{}.yaml", file_name_key(key))
+    }
     
     /// Initialize the cache directory
     pub fn init(&self) -> Result<(), CacheError> {
@@ -73,13 +307,28 @@ impl CacheManager {
     
     /// Get a cache entry
     pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CacheError> {
-        let path = self.cache_dir.join(key);
-        
         // Check if the cache entry exists and is valid
-        if !self.is_valid(key) {
+        let valid = self.is_valid(key);
+        if let Some(category) = self.category {
+            let counter = if valid { &HITS } else { &MISSES };
+            counter[category.index()].fetch_add(1, Ordering::Relaxed);
+        }
+        if !valid {
             return Ok(None);
         }
         
+        self.get_stale(key)
+    }
+
+    /// Get a cache entry, even if it is too old
+    ///
+    /// This is a fallback for when fresh data can't be fetched.
+    pub fn get_stale<T: serde::de::DeserializeOwned>(&self, key: &str) -> Result<Option<T>, CacheError> {
+        let path = self.cache_dir.join(key);
+        if !path.exists() {
+            return Ok(None);
+        }
+
         // Read the cache entry
         let data = fs::read_to_string(path)?;
         let value = serde_yaml::from_str(&data)?;
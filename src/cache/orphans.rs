@@ -0,0 +1,200 @@
+//! Detection of unused environments
+//!
+//! Tool environments and downloads are keyed by what they hold, so they
+//! linger after the last hook using them is removed from the config. Projects
+//! sharing a cache share environments too, so each environment lists the
+//! projects that used it, and stays in use while one of their configs still
+//! needs it. This module finds orphans and prunes them once they have been
+//! unused for a while.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use super::CacheError;
+use crate::config::Config;
+use crate::runner::HookResolver;
+
+/// File touched whenever an environment is used
+pub const LAST_USED_MARKER: &str = ".last-used";
+
+/// File of an environment listing the projects that used it, one directory per line
+pub const REFERENCES_FILE: &str = ".refs";
+
+/// How long an orphaned environment is kept by default
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Directories of the cache root holding per-tool and per-hook directories
+const TOOL_DIRS: &[&str] = &["venvs", "cache", super::TOOL_HOME_DIR, super::HOOK_CACHE_DIR];
+
+/// Directories of the cache root that aren't per-tool directories
+const SHARED_DIRS: &[&str] = &["repos"];
+
+/// A tool directory that no configured hook uses
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanedEnvironment {
+    /// Path of the directory
+    pub path: PathBuf,
+    /// Time since the directory was last used
+    pub unused_for: Duration,
+}
+
+/// Record that an environment was used, if it exists
+pub fn touch_last_used(dir: &Path) {
+    if dir.is_dir() {
+        if let Err(e) = fs::write(dir.join(LAST_USED_MARKER), b"") {
+            log::debug!("Could not mark {} as used: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Get the projects that used an environment
+pub fn references(dir: &Path) -> Vec<PathBuf> {
+    fs::read_to_string(dir.join(REFERENCES_FILE))
+        .map(|content| content.lines().filter(|line| !line.is_empty()).map(PathBuf::from).collect())
+        .unwrap_or_default()
+}
+
+/// Record that a project uses an environment, if it exists
+pub fn add_reference(dir: &Path, project: &Path) {
+    let project = project.canonicalize().unwrap_or_else(|_| project.to_path_buf());
+    let mut projects = references(dir);
+    if !dir.is_dir() || projects.contains(&project) {
+        return;
+    }
+    projects.push(project);
+    let content: String = projects.iter().map(|project| format!("{}\n", project.display())).collect();
+    if let Err(e) = fs::write(dir.join(REFERENCES_FILE), content) {
+        log::debug!("Could not record the projects using {}: {}", dir.display(), e);
+    }
+}
+
+/// Get the keys of the environments the hooks of a config use
+///
+/// Legacy keys are mapped to the current key, as their directories are
+/// migrated on the next run.
+fn environment_keys(config: &Config) -> (HashSet<String>, HashMap<String, String>) {
+    let mut keys = HashSet::new();
+    let mut legacy = HashMap::new();
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        let key = HookResolver::tool_key(hook);
+        for legacy_key in HookResolver::legacy_tool_keys(hook) {
+            legacy.insert(legacy_key, key.clone());
+        }
+        keys.insert(key);
+    }
+    (keys, legacy)
+}
+
+/// Get the cache directory names of the hooks of a project's config
+fn hook_cache_keys(project: &Path, config: &Config) -> HashSet<String> {
+    config
+        .repos
+        .iter()
+        .flat_map(|repo| repo.hooks.iter().map(|hook| super::hook_cache_key(project, &repo.repo, &hook.id)))
+        .collect()
+}
+
+/// Check whether the config of another project still uses an environment
+///
+/// Projects whose config can't be read are assumed to still use it.
+fn project_uses(project: &Path, key: &str) -> bool {
+    project_config(project).is_none_or(|config| environment_keys(&config).0.contains(key))
+}
+
+/// Check whether the config of another project still has the hook of a hook cache directory
+///
+/// Projects whose config can't be read are assumed to still have it.
+fn project_uses_hook_cache(project: &Path, key: &str) -> bool {
+    project_config(project).is_none_or(|config| hook_cache_keys(project, &config).contains(key))
+}
+
+/// Read the configs of a project, with matrices expanded and versions pinned
+///
+/// `None` means one of them can't be read.
+fn project_config(project: &Path) -> Option<Config> {
+    let native_path = project.join(".rustyhook").join("config.yaml");
+    let precommit_path = project.join(".pre-commit-config.yaml");
+    let mut all = Config::default();
+    if native_path.is_file() {
+        match crate::config::parse_config(&native_path) {
+            Ok(native) => all = native,
+            Err(e) => {
+                log::debug!("Could not read the config of {}, keeping its environments: {:?}", project.display(), e);
+                return None;
+            }
+        }
+    }
+    if precommit_path.is_file() {
+        match crate::config::parse_precommit_config(&precommit_path) {
+            Ok(precommit) => all.repos.extend(crate::config::convert_to_rustyhook_config(&precommit).repos),
+            Err(e) => {
+                log::debug!("Could not read the pre-commit config of {}, keeping its environments: {:?}", project.display(), e);
+                return None;
+            }
+        }
+    }
+    let all = crate::config::expand_matrix(&all, project).unwrap_or(all);
+    Some(crate::runner::pin_locked_versions(&all, &crate::config::Lockfile::path(project)))
+}
+
+/// Get the time since a tool directory was last used
+fn unused_for(dir: &Path) -> Duration {
+    let last_used = fs::metadata(dir.join(LAST_USED_MARKER))
+        .or_else(|_| fs::metadata(dir))
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or_else(|_| SystemTime::now());
+    last_used.elapsed().unwrap_or_default()
+}
+
+/// Find the tool directories under `cache_root` that no hook uses
+///
+/// A directory is in use when a hook of `config`, the config of the current
+/// project, uses it, or when another project that used it still does.
+pub fn find_orphaned_environments(cache_root: &Path, config: &Config) -> Vec<OrphanedEnvironment> {
+    let (in_use, legacy) = environment_keys(config);
+    let current = std::env::current_dir().and_then(|dir| dir.canonicalize()).unwrap_or_default();
+    let hook_caches = hook_cache_keys(&current, config);
+
+    let mut orphans = Vec::new();
+    for tool_dir in TOOL_DIRS {
+        let Ok(entries) = fs::read_dir(cache_root.join(tool_dir)) else { continue };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if !path.is_dir() || SHARED_DIRS.contains(&name.as_str()) {
+                continue;
+            }
+            let used = if *tool_dir == super::HOOK_CACHE_DIR {
+                hook_caches.contains(&name)
+                    || references(&path).iter().any(|project| *project != current && project_uses_hook_cache(project, &name))
+            } else {
+                in_use.contains(&name)
+                    // Directories with a legacy key are migrated on the next run, unless the current key already exists
+                    || legacy.get(&name).is_some_and(|key| !cache_root.join(tool_dir).join(key).exists())
+                    || references(&cache_root.join("venvs").join(&name))
+                        .iter()
+                        .any(|project| *project != current && project_uses(project, &name))
+            };
+            if !used {
+                orphans.push(OrphanedEnvironment { unused_for: unused_for(&path), path });
+            }
+        }
+    }
+
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    orphans
+}
+
+/// Remove the orphaned environments unused for longer than the grace period
+///
+/// Returns the removed directories.
+pub fn prune_orphaned_environments(orphans: &[OrphanedEnvironment], grace_period: Duration) -> Result<Vec<PathBuf>, CacheError> {
+    let mut removed = Vec::new();
+    for orphan in orphans.iter().filter(|orphan| orphan.unused_for >= grace_period) {
+        fs::remove_dir_all(&orphan.path)?;
+        removed.push(orphan.path.clone());
+    }
+    Ok(removed)
+}
@@ -0,0 +1,160 @@
+//! Dynamic shell completions
+//!
+//! The scripts of `rustyhook completions` complete subcommands and flags
+//! statically. Values that depend on the config, like the hook ids to
+//! `--skip`, are completed by the hidden `rustyhook __complete` command: the
+//! scripts pass it the words typed so far and fall back to the static
+//! completions when it prints nothing. It isn't a clap subcommand, so it
+//! stays out of the help and of the generated scripts.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::config::drift::GIT_HOOK_STAGES;
+use crate::hooks::HookFactory;
+
+/// Name of the hidden subcommand the completion scripts call
+pub const COMPLETE_COMMAND: &str = "__complete";
+
+/// Global options that take a value
+const VALUE_OPTIONS: &[&str] = &["-p", "--parallelism", "--setup-parallelism", "--run-parallelism", "--log-file", "--log-level", "--config", "--skip", "--changed-since", "--cache-dir", "--hook-stage", "--hook", "--repos-file", "-j", "--jobs"];
+
+/// Get the words to complete if the CLI was invoked as `__complete -- <words>...`
+pub fn complete_args() -> Option<Vec<String>> {
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() != Some(COMPLETE_COMMAND) {
+        return None;
+    }
+    let mut words: Vec<String> = args.collect();
+    if words.first().map(String::as_str) == Some("--") {
+        words.remove(0);
+    }
+    Some(words)
+}
+
+/// Get the completions of the last word of a command line
+///
+/// `words` starts with the binary name and ends with the word being
+/// completed, which is empty when the cursor follows a space. Only values
+/// that can't be completed statically have candidates.
+pub fn candidates(words: &[String]) -> Vec<String> {
+    let Some((current, typed)) = words.split_last() else {
+        return Vec::new();
+    };
+    let typed = typed.get(1..).unwrap_or_default();
+
+    let candidates: Vec<String> = match typed.last().map(String::as_str) {
+        // Complete the id after the last comma of a list being typed
+        Some("--skip") => {
+            let (done, partial) = current.rsplit_once(',').map_or(("", current.as_str()), |(done, partial)| (done, partial));
+            let skipped: Vec<&str> = done.split(',').collect();
+            configured_hook_ids(config_option(typed))
+                .into_iter()
+                .filter(|id| id.starts_with(partial) && !skipped.contains(&id.as_str()))
+                .map(|id| if done.is_empty() { id } else { format!("{},{}", done, id) })
+                .collect()
+        }
+        Some("--hook-type") if subcommand(typed) == Some("install") => GIT_HOOK_STAGES.iter().map(|stage| stage.to_string()).collect(),
+        Some("--hook-stage") if subcommand(typed) == Some("run") => GIT_HOOK_STAGES.iter().map(|stage| stage.to_string()).collect(),
+        Some("--hook") if subcommand(typed) == Some("multi-run") => {
+            let mut ids: BTreeSet<String> = configured_hook_ids(config_option(typed)).into_iter().collect();
+            ids.extend(HookFactory::HOOK_IDS.iter().map(|id| id.to_string()));
+            ids.into_iter().collect()
+        }
+        Some("-a" | "--args") => Vec::new(),
+        _ if subcommand(typed) == Some("hook") && !current.starts_with('-') && positionals(typed).len() == 1 => {
+            HookFactory::HOOK_IDS.iter().map(|id| id.to_string()).collect()
+        }
+        _ => Vec::new(),
+    };
+    candidates.into_iter().filter(|candidate| candidate.starts_with(current.as_str())).collect()
+}
+
+/// Get the words of a command line that aren't options or option values
+fn positionals(typed: &[String]) -> Vec<&str> {
+    let mut positionals = Vec::new();
+    let mut words = typed.iter().map(String::as_str);
+    while let Some(word) = words.next() {
+        if word == "--" {
+            break;
+        } else if VALUE_OPTIONS.contains(&word) || (positionals.first() == Some(&"hook") && matches!(word, "-a" | "--args")) {
+            words.next();
+        } else if !word.starts_with('-') {
+            positionals.push(word);
+        }
+    }
+    positionals
+}
+
+/// Get the subcommand of a command line
+fn subcommand(typed: &[String]) -> Option<&str> {
+    positionals(typed).first().copied()
+}
+
+/// Get the value of `--config` on a command line
+fn config_option(typed: &[String]) -> Option<&Path> {
+    typed.windows(2).rev().find(|pair| pair[0] == "--config").map(|pair| Path::new(&pair[1]))
+}
+
+/// Get the ids of the configured hooks, sorted and without duplicates
+fn configured_hook_ids(config_path: Option<&Path>) -> Vec<String> {
+    let config = crate::all_configured_hooks(config_path);
+    let ids: BTreeSet<String> = config.repos.iter().flat_map(|repo| repo.hooks.iter().map(|hook| hook.id.clone())).collect();
+    ids.into_iter().collect()
+}
+
+/// Get the shell code completing a binary's values with `__complete`
+///
+/// It runs after the static completions of the binary are defined, as
+/// `_<name>`, and takes over their registration.
+pub fn dynamic_script(shell: crate::Shell, bin_name: &str) -> String {
+    match shell {
+        crate::Shell::Bash => format!(
+            r#"
+_{name}_dynamic() {{
+    local candidates
+    candidates="$("${{COMP_WORDS[0]}}" {command} -- "${{COMP_WORDS[@]:0:COMP_CWORD+1}}" 2>/dev/null)"
+    if [[ -n "$candidates" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($candidates)
+        return 0
+    fi
+    _{name} "$@"
+}}
+
+complete -F _{name}_dynamic -o bashdefault -o default {name}
+"#,
+            name = bin_name,
+            command = COMPLETE_COMMAND
+        ),
+        crate::Shell::Zsh => format!(
+            r#"
+_{name}_dynamic() {{
+    local -a candidates
+    candidates=(${{(f)"$(${{words[1]}} {command} -- "${{(@)words[1,CURRENT]}}" 2>/dev/null)"}})
+    if (( ${{#candidates}} )); then
+        compadd -a candidates
+    else
+        _{name} "$@"
+    fi
+}}
+"#,
+            name = bin_name,
+            command = COMPLETE_COMMAND
+        ),
+        crate::Shell::Fish => format!(
+            r#"
+function __{name}_dynamic
+    set -l tokens (commandline -opc)
+    set -l current (commandline -ct)
+    $tokens[1] {command} -- $tokens "$current" 2>/dev/null
+end
+complete -c {name} -a '(__{name}_dynamic)'
+"#,
+            name = bin_name,
+            command = COMPLETE_COMMAND
+        ),
+        // PowerShell completers can't be layered, so it keeps the static completions
+        crate::Shell::PowerShell => String::new(),
+    }
+}
@@ -3,12 +3,13 @@
 //! This module provides functionality for parsing .pre-commit-config.yaml files.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use git2;
 
-use super::parser::{Config, Hook, Repo, ConfigError, HookType, AccessMode};
+use crate::cache::{self, CacheCategory, CacheManager};
+use super::hook_repo::{checkout, checkout_dir, refresh};
+use super::parser::{normalize_stages, Config, Hook, Repo, ConfigError, HookType, AccessMode};
 
 /// Represents a hook in a .pre-commit-hooks.yaml file
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,6 +41,10 @@ pub struct PreCommitHookDefinition {
     /// Stages to run this hook on
     #[serde(default)]
     pub stages: Vec<String>,
+
+    /// Oldest rustyhook version that can run this hook
+    #[serde(default, deserialize_with = "super::parser::deserialize_version", skip_serializing_if = "Option::is_none")]
+    pub minimum_rustyhook_version: Option<String>,
 }
 
 /// Represents a .pre-commit-hooks.yaml file
@@ -74,47 +79,40 @@ pub fn parse_precommit_hooks_file<P: AsRef<Path>>(path: P) -> Result<PreCommitHo
 
 /// Find and parse the .pre-commit-hooks.yaml file for a repository
 /// 
-/// This function clones the repository to the local cache directory and looks for
+/// This function clones the repository to the local cache directory (only the
+/// files it needs, where possible) and looks for
 /// .pre-commit-hooks.yaml in the root of the repository.
 /// If found, it parses the file and returns the hooks defined in it.
 /// If the file can't be found or parsed, it returns None.
-pub fn find_precommit_hooks_for_repo(repo_url: &str) -> Option<PreCommitHooksFile> {
-    // Create a cache directory for repositories
-    let cache_dir = std::env::current_dir().unwrap_or_default().join(".rustyhook").join("cache").join("repos");
-
-    // Create a subdirectory for this specific repository
-    // Use a hash of the repo URL to create a unique directory name
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-
-    let mut hasher = DefaultHasher::new();
-    repo_url.hash(&mut hasher);
-    let repo_hash = hasher.finish();
-
-    let repo_dir = cache_dir.join(format!("{}", repo_hash));
-
-    // Create the directory if it doesn't exist
-    if !repo_dir.exists() {
-        if let Err(err) = std::fs::create_dir_all(&repo_dir) {
-            log::warn!("Failed to create cache directory: {}", err);
-            return None;
-        }
+///
+/// The repository is checked out at `rev`, or its default branch when `rev`
+/// is empty. The hooks are cached for `cache_ttl.hook_repos`. Once they
+/// expire, the checkout is updated and the file is read again.
+pub fn find_precommit_hooks_for_repo(repo_url: &str, rev: &str) -> Option<PreCommitHooksFile> {
+    let cache = CacheManager::for_category(&cache::default_cache_dir(), CacheCategory::HookRepos, &cache::ttls());
+    let key = CacheManager::entry_name(&format!("{}@{}", repo_url, rev));
+    if let Ok(Some(hooks_file)) = cache.get::<PreCommitHooksFile>(&key) {
+        return Some(hooks_file);
+    }
 
-        log::debug!("Cloning repository {} into {}", repo_url, repo_dir.display());
+    // Expired hooks come from a checkout that may be out of date
+    let expired = cache.get_stale::<PreCommitHooksFile>(&key).ok().flatten().is_some();
+    let repo_dir = checkout_dir(repo_url, rev);
+    if expired && repo_dir.exists() && !refresh(&repo_dir, rev) {
+        log::warn!("Could not update the checkout of {}, using its hooks as they were", repo_url);
+    }
 
-        // Clone the repository
-        match git2::Repository::clone(repo_url, &repo_dir) {
-            Ok(_repo) => {},
-            Err(err) => {
-                log::warn!("Failed to clone repository {}: {}", repo_url, err);
-                // Clean up the directory if the clone failed
-                let _ = std::fs::remove_dir_all(&repo_dir);
-                return None;
-            }
-        };
-    } else {
-        log::debug!("Using cached repository at {}", repo_dir.display());
+    let hooks_file = read_precommit_hooks_for_repo(repo_url, rev)?;
+    if let Err(e) = cache.set(&key, &hooks_file) {
+        log::debug!("Could not cache the hooks of {}: {:?}", repo_url, e);
     }
+    Some(hooks_file)
+}
+
+/// Read the .pre-commit-hooks.yaml file of a repository's checkout
+fn read_precommit_hooks_for_repo(repo_url: &str, rev: &str) -> Option<PreCommitHooksFile> {
+    // Only the hooks file is needed, so skip the history and other files
+    let repo_dir = checkout(repo_url, rev, false)?;
 
     // Look for .pre-commit-hooks.yaml in the repository
     let path = repo_dir.join(".pre-commit-hooks.yaml");
@@ -162,8 +160,8 @@ pub fn find_precommit_hooks_for_repo(repo_url: &str) -> Option<PreCommitHooksFil
 /// Represents a pre-commit configuration
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PreCommitConfig {
-    /// Default stages to run hooks on
-    #[serde(default = "default_stages")]
+    /// Stages hooks without `stages` run on (every stage when empty)
+    #[serde(default)]
     pub default_stages: Vec<String>,
 
     /// Whether to stop running hooks after the first failure
@@ -184,6 +182,14 @@ pub struct PreCommitRepo {
     #[serde(default)]
     pub rev: String,
 
+    /// Oldest version tag `rev` may be, checked when the config is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum_rev: Option<String>,
+
+    /// Ref a commit SHA `rev` was frozen from, read from its `# frozen:` comment
+    #[serde(skip)]
+    pub frozen_rev: Option<String>,
+
     /// List of hooks in this repository
     pub hooks: Vec<PreCommitHook>,
 }
@@ -198,6 +204,10 @@ pub struct PreCommitHook {
     #[serde(default)]
     pub name: Option<String>,
 
+    /// Description of the hook (optional)
+    #[serde(default)]
+    pub description: Option<String>,
+
     /// Command or script to run (optional)
     #[serde(default)]
     pub entry: Option<String>,
@@ -221,17 +231,23 @@ pub struct PreCommitHook {
     /// Additional environment variables (optional)
     #[serde(default)]
     pub env: Option<HashMap<String, String>>,
-}
 
-/// Default stages for hooks
-fn default_stages() -> Vec<String> {
-    vec!["commit".to_string()]
+    /// Whether to stop running hooks when this one fails (optional)
+    #[serde(default)]
+    pub fail_fast: Option<bool>,
 }
 
 /// Parse a pre-commit configuration file
+///
+/// The refs of frozen revs are read from their `# frozen:` comments.
 pub fn parse_precommit_config<P: AsRef<Path>>(path: P) -> Result<PreCommitConfig, ConfigError> {
     let config_str = fs::read_to_string(path)?;
-    let config: PreCommitConfig = serde_yaml::from_str(&config_str)?;
+    let mut config: PreCommitConfig = serde_yaml::from_str(&config_str)?;
+    if let Ok(frozen_revs) = super::freeze::frozen_revs(&config_str) {
+        for (repo, frozen_rev) in config.repos.iter_mut().zip(frozen_revs) {
+            repo.frozen_rev = frozen_rev;
+        }
+    }
     Ok(config)
 }
 
@@ -269,6 +285,33 @@ pub fn find_precommit_config() -> Result<PreCommitConfig, ConfigError> {
     parse_precommit_config(config_path)
 }
 
+/// Merge a converted pre-commit configuration into a native configuration
+///
+/// Hooks are de-duplicated by ID, preferring the native definition, so hooks
+/// can be migrated one at a time. Global settings come from the native config.
+pub fn merge_compat_config(native: &Config, compat: &Config) -> Config {
+    let native_ids: HashSet<&str> = native
+        .repos
+        .iter()
+        .flat_map(|repo| repo.hooks.iter().map(|hook| hook.id.as_str()))
+        .collect();
+
+    let mut merged = native.clone();
+    for repo in &compat.repos {
+        let hooks: Vec<Hook> = repo
+            .hooks
+            .iter()
+            .filter(|hook| !native_ids.contains(hook.id.as_str()))
+            .cloned()
+            .collect();
+        if !hooks.is_empty() {
+            merged.repos.push(Repo { repo: repo.repo.clone(), hooks });
+        }
+    }
+
+    merged
+}
+
 /// Convert a pre-commit configuration to a RustyHook configuration
 pub fn convert_to_rustyhook_config(precommit_config: &PreCommitConfig) -> Config {
     let mut repos = Vec::new();
@@ -277,39 +320,58 @@ pub fn convert_to_rustyhook_config(precommit_config: &PreCommitConfig) -> Config
         let mut hooks = Vec::new();
 
         for precommit_hook in &precommit_repo.hooks {
-            // Determine the appropriate language and entry based on the hook
-            let (language, entry) = if let Some(lang) = &precommit_hook.language {
+            // Determine the appropriate language, entry and description based on the hook
+            let (language, entry, definition_description, minimum_rustyhook_version, definition_args, definition_stages) = if let Some(lang) = &precommit_hook.language {
                 // If the hook specifies a language, use it
                 (
                     lang.clone(),
-                    precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone())
+                    precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone()),
+                    None,
+                    None,
+                    Vec::new(),
+                    Vec::new()
                 )
             } else {
                 // If no language is specified, look up the hook in the repository's .pre-commit-hooks.yaml file
-                if let Some(hooks_file) = find_precommit_hooks_for_repo(&precommit_repo.repo) {
+                if let Some(hooks_file) = find_precommit_hooks_for_repo(&precommit_repo.repo, &precommit_repo.rev) {
                     // Try to find the hook in the hooks file
                     if let Some(hook_def) = hooks_file.hooks.iter().find(|h| h.id == precommit_hook.id) {
-                        // Use the language and entry from the hook definition
+                        // Use the language, entry and description from the hook definition
                         (
                             hook_def.language.clone(),
-                            hook_def.entry.clone()
+                            hook_def.entry.clone(),
+                            Some(hook_def.description.clone()),
+                            hook_def.minimum_rustyhook_version.clone(),
+                            hook_def.args.clone(),
+                            hook_def.stages.clone()
                         )
                     } else {
                         // If the hook is not found in the hooks file, use system language as a fallback
                         (
                             "system".to_string(),
-                            precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone())
+                            precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone()),
+                            None,
+                            None,
+                            Vec::new(),
+                            Vec::new()
                         )
                     }
                 } else {
                     // If no hooks file is found, use system language as a fallback
                     (
                         "system".to_string(),
-                        precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone())
+                        precommit_hook.entry.clone().unwrap_or_else(|| precommit_hook.id.clone()),
+                        None,
+                        None,
+                        Vec::new(),
+                        Vec::new()
                     )
                 }
             };
 
+            // The config's description overrides the one of the hook definition
+            let description = precommit_hook.description.clone().or(definition_description).filter(|description| !description.is_empty());
+
             // Determine the hook type based on the hook definition
             // This should be determined from the hook definition in the .pre-commit-hooks.yaml file
             // For now, we'll use a simple heuristic: hooks with simple entry points that match their IDs
@@ -322,19 +384,32 @@ pub fn convert_to_rustyhook_config(precommit_config: &PreCommitConfig) -> Config
                 HookType::External
             };
 
+            // Fail and pygrep hooks only report matching files, so they never write
+            let access_mode = if matches!(language.as_str(), "fail" | "pygrep") {
+                AccessMode::Read
+            } else {
+                AccessMode::ReadWrite // Default to read-write for safety
+            };
+
             let hook = Hook {
                 id: precommit_hook.id.clone(),
                 name: precommit_hook.name.clone().unwrap_or_else(|| precommit_hook.id.clone()),
+                description,
                 entry,
                 language,
                 files: precommit_hook.files.clone().unwrap_or_default(),
-                stages: precommit_hook.stages.clone().unwrap_or_else(|| precommit_config.default_stages.clone()),
-                args: precommit_hook.args.clone().unwrap_or_default(),
+                // Like pre-commit, the config's stages come first, then the definition's, then `default_stages`
+                stages: normalize_stages(precommit_hook.stages.as_deref().unwrap_or(&definition_stages)),
+                // Like pre-commit, the config's args replace the definition's, kept as is with any `--`
+                args: precommit_hook.args.clone().unwrap_or(definition_args),
                 env: precommit_hook.env.clone().unwrap_or_default(),
                 version: Some(precommit_repo.rev.clone()),
                 hook_type,
                 separate_process: false,
-                access_mode: AccessMode::ReadWrite, // Default to read-write for safety
+                access_mode,
+                fail_fast: precommit_hook.fail_fast.unwrap_or(false),
+                minimum_rustyhook_version,
+                ..Default::default()
             };
 
             hooks.push(hook);
@@ -349,9 +424,12 @@ pub fn convert_to_rustyhook_config(precommit_config: &PreCommitConfig) -> Config
     }
 
     Config {
-        default_stages: precommit_config.default_stages.clone(),
+        default_stages: normalize_stages(&precommit_config.default_stages),
         fail_fast: precommit_config.fail_fast,
         parallelism: 0,
+        // pre-commit runs hooks on every file
+        no_default_excludes: true,
         repos,
+        ..Default::default()
     }
 }
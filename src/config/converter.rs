@@ -6,7 +6,9 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use super::compat::{find_precommit_config, find_precommit_config_path, parse_precommit_config, convert_to_rustyhook_config};
-use super::parser::{Config, ConfigError};
+use super::drift::converted_from_header;
+use super::parser::{AccessMode, Config, ConfigError, Hook, Repo};
+use super::workspace::discover_projects;
 
 /// Error type for conversion operations
 #[derive(Debug)]
@@ -64,6 +66,14 @@ pub fn convert_from_precommit<P: AsRef<Path>>(
         None => find_precommit_config()?,
     };
 
+    // Record what was converted, so `doctor --check-config-drift` notices changes
+    let header = original_path
+        .clone()
+        .or_else(|| find_precommit_config_path().ok())
+        .and_then(|path| fs::read(path).ok())
+        .map(|content| converted_from_header(&content))
+        .unwrap_or_default();
+
     // Convert the pre-commit configuration to a RustyHook configuration
     let rustyhook_config = convert_to_rustyhook_config(&precommit_config);
 
@@ -81,7 +91,7 @@ pub fn convert_from_precommit<P: AsRef<Path>>(
 
     // Write the RustyHook configuration to the output file
     let yaml = serde_yaml::to_string(&rustyhook_config)?;
-    fs::write(output_path, yaml)?;
+    fs::write(output_path, header + &yaml)?;
 
     // Delete the original pre-commit config file if requested
     if delete_original {
@@ -109,14 +119,39 @@ pub fn convert_from_precommit<P: AsRef<Path>>(
     Ok(())
 }
 
+/// Native hooks of the starter configuration, with their names and descriptions
+const STARTER_HOOKS: &[(&str, &str, &str)] = &[
+    ("trailing-whitespace", "Trim trailing whitespace", "Removes whitespace at the end of lines"),
+    ("end-of-file-fixer", "Fix end of files", "Makes sure files end in exactly one newline"),
+];
+
 /// Create a starter RustyHook configuration and write it to a file
+///
+/// The member packages of the workspaces in the current directory are listed
+/// as `projects`.
 pub fn create_starter_config<P: AsRef<Path>>(output_path: Option<P>) -> Result<(), ConversionError> {
     // Create a simple starter configuration
     let config = Config {
-        default_stages: vec!["commit".to_string()],
+        default_stages: vec!["pre-commit".to_string()],
         fail_fast: false,
         parallelism: 0,
-        repos: vec![],
+        projects: discover_projects(&std::env::current_dir()?)?,
+        repos: vec![Repo {
+            repo: "local".to_string(),
+            hooks: STARTER_HOOKS
+                .iter()
+                .map(|(id, name, description)| Hook {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    description: Some(description.to_string()),
+                    entry: id.to_string(),
+                    language: "native".to_string(),
+                    access_mode: AccessMode::ReadWrite,
+                    ..Default::default()
+                })
+                .collect(),
+        }],
+        ..Default::default()
     };
 
     // Determine the output path
@@ -0,0 +1,210 @@
+//! Configuration drift detection for RustyHook
+//!
+//! Installed Git hook scripts and converted configs are snapshots: the hook
+//! scripts embed the path of the binary that installed them, and a converted
+//! `.rustyhook/config.yaml` records a fingerprint of the
+//! `.pre-commit-config.yaml` it came from. This module compares those
+//! snapshots with the current state and says how to resync each one.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::parser::{normalize_stage, Config};
+
+/// Line identifying the Git hook scripts RustyHook installs
+pub const HOOK_SCRIPT_MARKER: &str = "# RustyHook Git hook";
+
+/// Prefix of the header line recording what a config was converted from
+pub const CONVERTED_FROM_PREFIX: &str = "# Converted from .pre-commit-config.yaml, sha256 ";
+
+/// Git hook types that hooks can be configured to run in
+pub const GIT_HOOK_STAGES: &[&str] = &[
+    "pre-commit",
+    "pre-merge-commit",
+    "pre-push",
+    "prepare-commit-msg",
+    "commit-msg",
+    "post-checkout",
+    "post-commit",
+    "post-merge",
+    "post-rewrite",
+    "pre-rebase",
+];
+
+/// A difference between what is installed and what is configured
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    /// A hook script runs a binary other than the current one
+    StaleBinary {
+        /// Git hook type of the script
+        hook_type: String,
+        /// Binary the script runs
+        binary: PathBuf,
+    },
+    /// A hook script is installed for a stage no hook runs in
+    UnconfiguredStage {
+        /// Git hook type of the script
+        hook_type: String,
+        /// Path of the script
+        script: PathBuf,
+    },
+    /// Hooks run in a stage that has no hook script
+    MissingScript {
+        /// Git hook type missing a script
+        hook_type: String,
+    },
+    /// `.pre-commit-config.yaml` changed since it was converted
+    PreCommitConfigChanged {
+        /// Path of the pre-commit config
+        path: PathBuf,
+    },
+}
+
+impl Drift {
+    /// Get the command that resyncs this drift
+    pub fn remedy(&self) -> String {
+        match self {
+            Drift::StaleBinary { hook_type, .. } => format!("rustyhook install --hook-type {} --force", hook_type),
+            Drift::UnconfiguredStage { script, .. } => format!("rm {}", script.display()),
+            Drift::MissingScript { hook_type } => format!("rustyhook install --hook-type {}", hook_type),
+            Drift::PreCommitConfigChanged { path } => {
+                format!("rustyhook convert --from-precommit --config-path {}", path.display())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Drift::StaleBinary { hook_type, binary } => {
+                write!(f, "The {} hook runs {}, not the current rustyhook binary", hook_type, binary.display())
+            }
+            Drift::UnconfiguredStage { hook_type, .. } => {
+                write!(f, "The {} hook is installed, but no configured hook runs in that stage", hook_type)
+            }
+            Drift::MissingScript { hook_type } => {
+                write!(f, "Hooks are configured for the {} stage, but its Git hook is not installed", hook_type)
+            }
+            Drift::PreCommitConfigChanged { path } => {
+                write!(f, "{} changed since it was converted", path.display())
+            }
+        }
+    }
+}
+
+/// Get the fingerprint of a config file's content
+pub fn fingerprint(content: &[u8]) -> String {
+    Sha256::digest(content).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Get the header line recording the pre-commit config a config was converted from
+pub fn converted_from_header(precommit_content: &[u8]) -> String {
+    format!("{}{}\n", CONVERTED_FROM_PREFIX, fingerprint(precommit_content))
+}
+
+/// Get the fingerprint recorded in a converted config, if it has one
+pub fn converted_fingerprint(native_content: &str) -> Option<&str> {
+    native_content
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .find_map(|line| line.strip_prefix(CONVERTED_FROM_PREFIX))
+        .map(str::trim)
+}
+
+/// Get the binary a RustyHook hook script runs, if the file is one
+pub fn installed_binary(script: &str) -> Option<PathBuf> {
+    if !script.lines().any(|line| line.trim() == HOOK_SCRIPT_MARKER) {
+        return None;
+    }
+    script
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .find_map(|line| line.strip_suffix(" run").or_else(|| line.split_once(" run --hook-stage ").map(|(binary, _)| binary)))
+        .map(PathBuf::from)
+}
+
+/// Check whether an installed hook script runs the current binary
+///
+/// The `rustyhook` and `rh` binaries next to each other, and symlinks to the
+/// current binary, count as the same binary.
+pub fn is_same_binary(installed: &Path, current_exe: &Path) -> bool {
+    if installed == current_exe {
+        return true;
+    }
+    if let (Ok(installed), Ok(current_exe)) = (installed.canonicalize(), current_exe.canonicalize()) {
+        if installed == current_exe {
+            return true;
+        }
+    }
+    let is_alias = |path: &Path| path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| crate::BIN_NAMES.contains(&stem));
+    installed.parent() == current_exe.parent() && is_alias(installed) && is_alias(current_exe)
+}
+
+/// Get the Git hook types the hooks of a config run in
+///
+/// Returns `None` when a hook runs in every stage.
+fn configured_stages(config: &Config) -> Option<BTreeSet<String>> {
+    let mut stages = BTreeSet::new();
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        let hook_stages = if hook.stages.is_empty() { &config.default_stages } else { &hook.stages };
+        if hook_stages.is_empty() {
+            return None;
+        }
+        stages.extend(hook_stages.iter().map(|stage| normalize_stage(stage).to_string()));
+    }
+    Some(stages)
+}
+
+/// Compare the installed hook scripts with a config
+///
+/// `current_exe` is the binary the scripts should run. Missing scripts are
+/// only reported once at least one RustyHook script is installed.
+pub fn check_hook_scripts(git_dir: &Path, config: &Config, current_exe: &Path) -> Vec<Drift> {
+    let hooks_dir = git_dir.join("hooks");
+    let stages = configured_stages(config);
+    let mut drifts = Vec::new();
+    let mut installed = BTreeSet::new();
+
+    for hook_type in GIT_HOOK_STAGES {
+        let script = hooks_dir.join(hook_type);
+        let Ok(content) = fs::read_to_string(&script) else { continue };
+        let Some(binary) = installed_binary(&content) else { continue };
+        installed.insert(hook_type.to_string());
+
+        if !is_same_binary(&binary, current_exe) {
+            drifts.push(Drift::StaleBinary { hook_type: hook_type.to_string(), binary });
+        }
+        if stages.as_ref().is_some_and(|stages| !stages.contains(*hook_type)) {
+            drifts.push(Drift::UnconfiguredStage { hook_type: hook_type.to_string(), script });
+        }
+    }
+
+    if let Some(stages) = stages.filter(|_| !installed.is_empty()) {
+        for stage in stages.iter().filter(|stage| GIT_HOOK_STAGES.contains(&stage.as_str())) {
+            if !installed.contains(stage) {
+                drifts.push(Drift::MissingScript { hook_type: stage.clone() });
+            }
+        }
+    }
+
+    drifts
+}
+
+/// Check whether a pre-commit config changed since a native config was converted from it
+///
+/// Native configs that weren't converted never drift.
+pub fn check_converted_config(native_path: &Path, precommit_path: &Path) -> Option<Drift> {
+    let native = fs::read_to_string(native_path).ok()?;
+    let recorded = converted_fingerprint(&native)?;
+    let precommit = fs::read(precommit_path).ok()?;
+    if fingerprint(&precommit) == recorded {
+        return None;
+    }
+    Some(Drift::PreCommitConfigChanged { path: precommit_path.to_path_buf() })
+}
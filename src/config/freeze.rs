@@ -0,0 +1,440 @@
+//! Revision pinning for RustyHook
+//!
+//! This module detects mutable `rev:` values (branches and moving tags) in
+//! pre-commit configurations and rewrites them into the exact commit SHAs they
+//! currently resolve to, keeping the original ref as a `# frozen:` comment.
+//! It also checks the `minimum_rev` of repos against their rev, or the ref a
+//! SHA was frozen from.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::toolchains::queries::{self, RemoteRef};
+use super::compat::PreCommitConfig;
+use super::parser::ConfigError;
+
+/// A repository whose `rev` is a mutable reference
+#[derive(Debug, Clone, PartialEq)]
+pub struct MutableRev {
+    /// Repository URL
+    pub repo: String,
+    /// The mutable revision
+    pub rev: String,
+}
+
+/// Check whether a revision is a full commit SHA
+pub fn is_commit_sha(rev: &str) -> bool {
+    rev.len() == 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Check whether a revision looks like a mutable reference
+///
+/// Like pre-commit, anything that is neither hexadecimal nor contains a `.`
+/// (as version tags do) is treated as a branch or moving tag.
+pub fn is_mutable_rev(rev: &str) -> bool {
+    !rev.is_empty() && !rev.contains('.') && !rev.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Find the repositories in a pre-commit configuration pinned to mutable refs
+pub fn find_mutable_revs(config: &PreCommitConfig) -> Vec<MutableRev> {
+    config
+        .repos
+        .iter()
+        .filter(|repo| repo.repo != "local" && repo.repo != "meta")
+        .filter(|repo| is_mutable_rev(&repo.rev))
+        .map(|repo| MutableRev {
+            repo: repo.repo.clone(),
+            rev: repo.rev.clone(),
+        })
+        .collect()
+}
+
+/// Check a pre-commit configuration for mutable refs
+///
+/// Each mutable ref is logged as a warning. When `frozen` is set they are an
+/// error instead, so CI can insist on reproducible configurations.
+pub fn check_mutable_revs(config: &PreCommitConfig, frozen: bool) -> Result<(), ConfigError> {
+    let mutable = find_mutable_revs(config);
+    for entry in &mutable {
+        log::warn!(
+            "The rev '{}' of repo '{}' appears to be a mutable reference (branch or moving tag). Run 'rustyhook freeze' to pin it to a commit SHA.",
+            entry.rev, entry.repo
+        );
+    }
+
+    if frozen && !mutable.is_empty() {
+        let repos: Vec<&str> = mutable.iter().map(|entry| entry.repo.as_str()).collect();
+        return Err(ConfigError::ValidationError(format!(
+            "--frozen requires every repo to be pinned to a commit SHA, but these use mutable refs: {}",
+            repos.join(", ")
+        )));
+    }
+
+    Ok(())
+}
+
+/// A repository whose `rev` is older than its `minimum_rev`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutdatedRev {
+    /// Repository URL
+    pub repo: String,
+    /// The version the rev is, or was frozen from
+    pub rev: String,
+    /// The oldest version allowed
+    pub minimum_rev: String,
+}
+
+/// Compare version numbers one by one, missing ones being zero
+fn compare_versions(a: &[u64], b: &[u64]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|index| a.get(index).unwrap_or(&0).cmp(b.get(index).unwrap_or(&0)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Find the repositories in a pre-commit configuration older than their `minimum_rev`
+///
+/// Commit SHAs are compared through the ref they were frozen from. Revs
+/// that aren't version tags, like branches and SHAs without a `# frozen:`
+/// comment, can't be compared and are only logged.
+pub fn find_outdated_revs(config: &PreCommitConfig) -> Vec<OutdatedRev> {
+    let mut outdated = Vec::new();
+    for repo in &config.repos {
+        let Some(minimum_rev) = &repo.minimum_rev else { continue };
+        let Some(minimum) = version_key(minimum_rev) else {
+            log::warn!("The minimum_rev '{}' of repo '{}' isn't a version, like v1.2.3, so it isn't checked", minimum_rev, repo.repo);
+            continue;
+        };
+        let rev = repo.frozen_rev.as_deref().filter(|_| is_commit_sha(&repo.rev)).unwrap_or(&repo.rev);
+        let Some(version) = version_key(rev) else {
+            log::warn!("Can't check the minimum_rev '{}' of repo '{}', since its rev '{}' isn't a version", minimum_rev, repo.repo, rev);
+            continue;
+        };
+        if compare_versions(&version, &minimum).is_lt() {
+            outdated.push(OutdatedRev { repo: repo.repo.clone(), rev: rev.to_string(), minimum_rev: minimum_rev.clone() });
+        }
+    }
+    outdated
+}
+
+/// Check the repos of a pre-commit configuration against their `minimum_rev`
+///
+/// Each repo older than its `minimum_rev` is logged as a warning. When
+/// `frozen` is set they are an error instead.
+pub fn check_minimum_revs(config: &PreCommitConfig, frozen: bool) -> Result<(), ConfigError> {
+    let outdated = find_outdated_revs(config);
+    for entry in &outdated {
+        log::warn!(
+            "The rev '{}' of repo '{}' is older than its minimum_rev '{}'. Run 'pre-commit autoupdate' to update it.",
+            entry.rev, entry.repo, entry.minimum_rev
+        );
+    }
+
+    if frozen && !outdated.is_empty() {
+        let repos: Vec<String> = outdated.iter().map(|entry| format!("{} ({} < {})", entry.repo, entry.rev, entry.minimum_rev)).collect();
+        return Err(ConfigError::ValidationError(format!("--frozen requires every repo to be at least its minimum_rev: {}", repos.join(", "))));
+    }
+
+    Ok(())
+}
+
+/// Get the ref each repo's rev was frozen from, read from its `# frozen:` comment
+///
+/// The refs are in the order of the repos, `None` for repos without one.
+pub fn frozen_revs(content: &str) -> Result<Vec<Option<String>>, ConfigError> {
+    let lines: Vec<&str> = content.lines().collect();
+    Ok(repo_items(content)?
+        .into_iter()
+        .map(|item| {
+            lines[item.lines].iter().filter(|line| line.contains("rev:")).find_map(|line| {
+                let (_, frozen) = line.split_once("# frozen:")?;
+                frozen.split_whitespace().next().map(str::to_string)
+            })
+        })
+        .collect())
+}
+
+/// Resolve a ref of a remote repository to the commit SHA it points to
+///
+/// Annotated tags are peeled to the commit they reference.
+pub fn resolve_remote_rev(repo_url: &str, rev: &str) -> Result<String, ConfigError> {
+    let heads = list_refs(repo_url)?;
+    let candidates = [
+        format!("refs/tags/{}^{{}}", rev),
+        format!("refs/tags/{}", rev),
+        format!("refs/heads/{}", rev),
+        rev.to_string(),
+    ];
+
+    for candidate in &candidates {
+        if let Some(head) = heads.iter().find(|head| head.name == *candidate) {
+            return Ok(head.oid.clone());
+        }
+    }
+
+    Err(ConfigError::ValidationError(format!("Could not resolve rev '{}' of {}", rev, repo_url)))
+}
+
+/// A repo of a pre-commit configuration, with the lines of its item
+struct RepoItem {
+    /// Repository URL or identifier
+    repo: String,
+    /// Current revision, empty when there is none
+    rev: String,
+    /// Lines of the item in the configuration text
+    lines: Range<usize>,
+}
+
+/// Find the repos of a pre-commit configuration and the lines of their items
+///
+/// The repos and their revs come from parsing the YAML, so the order of the
+/// keys in an item doesn't matter. The lines of each item come from scanning
+/// the `repos:` sequence, whose items start with `-` at the same indentation.
+fn repo_items(content: &str) -> Result<Vec<RepoItem>, ConfigError> {
+    let config: serde_yaml::Value = serde_yaml::from_str(content)?;
+    let repos: Vec<(String, String)> = config
+        .get("repos")
+        .and_then(serde_yaml::Value::as_sequence)
+        .map(|repos| {
+            repos
+                .iter()
+                .map(|repo| {
+                    let field = |key: &str| match repo.get(key) {
+                        Some(serde_yaml::Value::String(value)) => value.clone(),
+                        Some(serde_yaml::Value::Number(value)) => value.to_string(),
+                        _ => String::new(),
+                    };
+                    (field("repo"), field("rev"))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let lines: Vec<&str> = content.lines().collect();
+    let indent = |line: &str| line.len() - line.trim_start().len();
+    let is_content = |line: &str| !line.trim().is_empty() && !line.trim_start().starts_with('#');
+    let mut starts = Vec::new();
+    let mut end = lines.len();
+    if let Some(repos_line) = lines.iter().position(|line| line.trim_end() == "repos:" || line.starts_with("repos: #")) {
+        let mut item_indent = None;
+        for (index, line) in lines.iter().enumerate().skip(repos_line + 1).filter(|(_, line)| is_content(line)) {
+            let is_item = line.trim_start().starts_with('-');
+            match item_indent {
+                None if is_item => item_indent = Some(indent(line)),
+                Some(item_indent) if indent(line) == item_indent && is_item => {}
+                Some(item_indent) if indent(line) > item_indent => continue,
+                _ => {
+                    end = index;
+                    break;
+                }
+            }
+            starts.push(index);
+        }
+    }
+
+    if starts.len() != repos.len() {
+        return Err(ConfigError::ValidationError(
+            "Could not find the repos of the pre-commit configuration, which must be a block sequence under 'repos:'".to_string(),
+        ));
+    }
+    let ends = starts.iter().skip(1).copied().chain(std::iter::once(end));
+    Ok(repos.into_iter().zip(starts.iter().copied().zip(ends)).map(|((repo, rev), (start, end))| RepoItem { repo, rev, lines: start..end }).collect())
+}
+
+/// Replace the `rev:` value in the lines of a repo item
+///
+/// Block mappings get a new `rev:` line; flow mappings, like
+/// `- {repo: ..., rev: main}`, get their value replaced and any comment
+/// appended to the line. Returns false when the item has no `rev:` key.
+fn replace_rev(lines: &mut [String], new_value: &str) -> bool {
+    let (value, comment) = match new_value.split_once("  #") {
+        Some((value, comment)) => (value, Some(comment)),
+        None => (new_value, None),
+    };
+    let key_column = |line: &str| line.len() - line.trim_start().trim_start_matches('-').trim_start().len();
+    let item_column = key_column(&lines[0]);
+
+    for line in lines.iter_mut() {
+        let column = key_column(line);
+        if column == item_column && line[column..].starts_with("rev:") {
+            *line = format!("{}rev: {}", &line[..column], new_value);
+            return true;
+        }
+        let flow_rev = line.match_indices("rev:").map(|(index, _)| index).find(|index| {
+            line[..*index].trim_end().ends_with(['{', ','])
+        });
+        if let Some(index) = flow_rev {
+            let after = &line[index + "rev:".len()..];
+            let value_start = index + "rev:".len() + (after.len() - after.trim_start().len());
+            let value_len = match line[value_start..].chars().next() {
+                Some(quote @ ('"' | '\'')) => line[value_start + 1..].find(quote).map_or(line.len() - value_start, |end| end + 2),
+                _ => line[value_start..].find([',', '}', ' ', '#']).unwrap_or(line.len() - value_start),
+            };
+            let mut replaced = format!("{}{}{}", &line[..value_start], value, &line[value_start + value_len..]);
+            if let Some(comment) = comment.filter(|_| !replaced.contains(" #")) {
+                replaced = format!("{}  #{}", replaced.trim_end(), comment);
+            }
+            *line = replaced;
+            return true;
+        }
+    }
+    false
+}
+
+/// Rewrite the `rev:` values of remote repos in a pre-commit configuration
+///
+/// The text is rewritten in place so comments and formatting survive.
+/// `rewrite` is called with the repository URL and current rev of every
+/// remote repo, and returns the new `rev:` value (including any comment), or
+/// None to keep the line. Returns the new text and the number of revs that
+/// were rewritten.
+fn rewrite_revs<F>(content: &str, mut rewrite: F) -> Result<(String, usize), ConfigError>
+where
+    F: FnMut(&str, &str) -> Result<Option<String>, ConfigError>,
+{
+    let mut lines: Vec<String> = content.lines().map(str::to_string).collect();
+    let mut rewritten = 0;
+
+    for item in repo_items(content)? {
+        if item.rev.is_empty() || item.repo == "local" || item.repo == "meta" {
+            continue;
+        }
+        if let Some(new_value) = rewrite(&item.repo, &item.rev)? {
+            if replace_rev(&mut lines[item.lines], &new_value) {
+                rewritten += 1;
+            }
+        }
+    }
+
+    let mut result = lines.join("\n");
+    if content.ends_with('\n') {
+        result.push('\n');
+    }
+    Ok((result, rewritten))
+}
+
+/// Rewrite the `rev:` values of a pre-commit configuration into commit SHAs
+///
+/// `resolve` is called with the repository URL and current rev of every repo
+/// that is not already pinned to a SHA. Returns the new text and the number of
+/// revs that were rewritten.
+pub fn freeze_revs<F>(content: &str, mut resolve: F) -> Result<(String, usize), ConfigError>
+where
+    F: FnMut(&str, &str) -> Result<String, ConfigError>,
+{
+    rewrite_revs(content, |repo, rev| {
+        if is_commit_sha(rev) {
+            return Ok(None);
+        }
+        let sha = resolve(repo, rev)?;
+        Ok(Some(format!("{}  # frozen: {}", sha, rev)))
+    })
+}
+
+/// Parse a version tag such as `v1.2.3` into its numeric components
+fn version_key(tag: &str) -> Option<Vec<u64>> {
+    let version = tag.strip_prefix('v').unwrap_or(tag);
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Find the highest version tag of a remote repository, with its commit SHA
+///
+/// Only tags made of dot-separated numbers, optionally prefixed with `v`, are
+/// considered, so pre-releases are skipped.
+pub fn latest_remote_tag(repo_url: &str) -> Result<Option<(String, String)>, ConfigError> {
+    let heads = list_refs(repo_url)?;
+
+    // Peeled entries (`^{}`) point at the commit of an annotated tag
+    let mut tags: Vec<(Vec<u64>, String, String)> = Vec::new();
+    for head in &heads {
+        let Some(name) = head.name.strip_prefix("refs/tags/") else { continue };
+        let (tag, peeled) = match name.strip_suffix("^{}") {
+            Some(tag) => (tag, true),
+            None => (name, false),
+        };
+        let Some(key) = version_key(tag) else { continue };
+        let sha = head.oid.clone();
+        match tags.iter_mut().find(|(_, existing, _)| existing == tag) {
+            Some(entry) if peeled => entry.2 = sha,
+            Some(_) => {}
+            None => tags.push((key, tag.to_string(), sha)),
+        }
+    }
+
+    Ok(tags.into_iter().max_by(|a, b| a.0.cmp(&b.0)).map(|(_, tag, sha)| (tag, sha)))
+}
+
+/// Update the `rev:` values of a pre-commit configuration to newer tags
+///
+/// `latest` is called with the repository URL of every remote repo and
+/// returns its latest tag and commit SHA. Only repos in `only_repos` are
+/// updated when it isn't empty. With `freeze`, revs are pinned to the SHA
+/// with a `# frozen:` comment. Returns the new text and the number of revs
+/// that changed.
+pub fn autoupdate_revs<F>(content: &str, only_repos: &[String], freeze: bool, mut latest: F) -> Result<(String, usize), ConfigError>
+where
+    F: FnMut(&str) -> Result<Option<(String, String)>, ConfigError>,
+{
+    rewrite_revs(content, |repo, rev| {
+        if !only_repos.is_empty() && !only_repos.iter().any(|r| r == repo) {
+            return Ok(None);
+        }
+        let Some((tag, sha)) = latest(repo)? else { return Ok(None) };
+        let new_value = if freeze { format!("{}  # frozen: {}", sha, tag) } else { tag };
+        Ok((new_value.split(' ').next() != Some(rev)).then_some(new_value))
+    })
+}
+
+/// List the refs of a remote repository
+fn list_refs(repo_url: &str) -> Result<Vec<RemoteRef>, ConfigError> {
+    queries::list_refs(repo_url).map_err(|e| ConfigError::ValidationError(format!("Failed to query {}: {}", repo_url, e)))
+}
+
+/// Update every repo of a pre-commit configuration file to its latest tag
+///
+/// The repos are queried in parallel, up to the query concurrency limit.
+pub fn autoupdate_precommit_config<P: AsRef<Path>>(path: P, only_repos: &[String], freeze: bool) -> Result<usize, ConfigError> {
+    let content = fs::read_to_string(&path)?;
+
+    let mut repos = Vec::new();
+    autoupdate_revs(&content, only_repos, freeze, |repo| {
+        if !repos.iter().any(|listed| listed == repo) {
+            repos.push(repo.to_string());
+        }
+        Ok(None)
+    })?;
+    let mut tags: HashMap<String, Result<Option<(String, String)>, ConfigError>> =
+        repos.iter().cloned().zip(queries::batch(&repos, |repo| latest_remote_tag(repo))).collect();
+
+    let (updated_content, updated) = autoupdate_revs(&content, only_repos, freeze, |repo| {
+        let latest = tags.remove(repo).unwrap_or_else(|| latest_remote_tag(repo))?;
+        match &latest {
+            Some((tag, _)) => log::info!("Updating {} to {}", repo, tag),
+            None => log::warn!("No version tags found for {}", repo),
+        }
+        Ok(latest)
+    })?;
+
+    if updated > 0 {
+        fs::write(&path, updated_content)?;
+    }
+    Ok(updated)
+}
+
+/// Pin every repo of a pre-commit configuration file to a commit SHA
+pub fn freeze_precommit_config<P: AsRef<Path>>(path: P) -> Result<usize, ConfigError> {
+    let content = fs::read_to_string(&path)?;
+    let (frozen_content, frozen) = freeze_revs(&content, |repo, rev| {
+        let sha = resolve_remote_rev(repo, rev)?;
+        log::info!("Froze {} at {} ({})", repo, rev, sha);
+        Ok(sha)
+    })?;
+
+    if frozen > 0 {
+        fs::write(&path, frozen_content)?;
+    }
+    Ok(frozen)
+}
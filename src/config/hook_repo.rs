@@ -0,0 +1,276 @@
+//! Checkouts of hook repositories
+//!
+//! Reading a hook repository's `.pre-commit-hooks.yaml` doesn't need its
+//! history or most of its files, and repos like mirrors-prettier are large.
+//! Hook repos are therefore cloned as cheaply as the available tools allow:
+//!
+//! 1. With the `git` CLI: a shallow, blobless clone with a sparse checkout of
+//!    [`SPARSE_PATHS`] (the hooks file and package manifests)
+//! 2. With libgit2: a shallow clone
+//! 3. A full clone, for servers and transports that support neither
+//!
+//! Each step checks out the repo's configured `rev`, fetching just that
+//! revision where it can, and checkouts are keyed by URL and rev, so two
+//! configs pinning different revs of a repo don't share one.
+//!
+//! When installing from a repo needs its sources, the sparse step is skipped,
+//! and [`ensure_sources`] widens a sparse checkout made earlier.
+//!
+//! `script` hooks run a file of their hook repo, so [`resolve_entry`] points
+//! their entry at the file in the checkout.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::Hook;
+
+/// Files a sparse checkout of a hook repo contains
+pub const SPARSE_PATHS: &[&str] = &[
+    "/.pre-commit-hooks.yaml",
+    "/package.json",
+    "/package-lock.json",
+    "/setup.py",
+    "/setup.cfg",
+    "/pyproject.toml",
+    "/requirements.txt",
+    "/Gemfile",
+    "/*.gemspec",
+    "/Cargo.toml",
+    "/go.mod",
+];
+
+/// How much of a hook repo was checked out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checkout {
+    /// Only the latest commit, with only [`SPARSE_PATHS`] in the working tree
+    Sparse,
+    /// Only the latest commit, with every file
+    Shallow,
+    /// The whole history, with every file
+    Full,
+}
+
+/// Run a git command, returning whether it succeeded
+fn git(args: &[&str], dir: Option<&Path>) -> bool {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    // Never wait for credentials on a hook that runs unattended
+    command.args(args).env("GIT_TERMINAL_PROMPT", "0");
+    match command.output() {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            log::debug!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+            false
+        }
+        Err(err) => {
+            log::debug!("Could not run git: {}", err);
+            false
+        }
+    }
+}
+
+/// Clone a shallow, blobless, sparse checkout of `rev` with the git CLI
+fn clone_sparse(url: &str, dir: &Path, rev: &str) -> bool {
+    let Some(target) = dir.to_str() else { return false };
+    if which::which("git").is_err() {
+        return false;
+    }
+    let mut set = vec!["sparse-checkout", "set", "--no-cone"];
+    set.extend(SPARSE_PATHS);
+    if !git(&["clone", "--quiet", "--depth", "1", "--filter=blob:none", "--no-checkout", url, target], None) || !git(&set, Some(dir)) {
+        return false;
+    }
+    if rev.is_empty() {
+        return git(&["checkout", "--quiet"], Some(dir));
+    }
+    git(&["fetch", "--quiet", "--depth", "1", "--filter=blob:none", "origin", rev], Some(dir))
+        && git(&["checkout", "--quiet", "--detach", "FETCH_HEAD"], Some(dir))
+}
+
+/// Clone the commit `rev` names with libgit2, without its history
+fn clone_shallow(url: &str, dir: &Path, rev: &str) -> Result<(), git2::Error> {
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.depth(1);
+    let repo = git2::build::RepoBuilder::new().fetch_options(fetch_options).clone(url, dir)?;
+    if rev.is_empty() {
+        return Ok(());
+    }
+
+    let mut fetch_options = git2::FetchOptions::new();
+    fetch_options.depth(1);
+    repo.find_remote("origin")?.fetch(&[rev], Some(&mut fetch_options), None)?;
+    check_out(&repo, "FETCH_HEAD")
+}
+
+/// Check out the commit a revision names, detaching `HEAD`
+///
+/// Branch names are looked up on the remote, since a clone only has a local
+/// branch for the default one.
+fn check_out(repo: &git2::Repository, rev: &str) -> Result<(), git2::Error> {
+    let object = repo
+        .revparse_single(rev)
+        .or_else(|_| repo.revparse_single(&format!("origin/{}", rev)))?;
+    let commit = object.peel_to_commit()?;
+    repo.checkout_tree(commit.as_object(), Some(git2::build::CheckoutBuilder::new().force()))?;
+    repo.set_head_detached(commit.id())
+}
+
+/// Remove what a failed clone left behind, so the next attempt starts clean
+fn reset_dir(dir: &Path) {
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+/// Clone a hook repo into `dir` at `rev`, as cheaply as possible
+///
+/// `rev` is a commit, tag or branch; an empty one is the default branch.
+/// `needs_sources` asks for every file, for installing from the repo.
+pub fn clone_hook_repo(url: &str, dir: &Path, rev: &str, needs_sources: bool) -> Result<Checkout, git2::Error> {
+    if !needs_sources {
+        if clone_sparse(url, dir, rev) {
+            return Ok(Checkout::Sparse);
+        }
+        reset_dir(dir);
+    }
+
+    match clone_shallow(url, dir, rev) {
+        Ok(()) => return Ok(Checkout::Shallow),
+        Err(err) => log::debug!("Shallow clone of {} failed, cloning everything: {}", url, err),
+    }
+    reset_dir(dir);
+
+    let repo = git2::Repository::clone(url, dir)?;
+    if !rev.is_empty() {
+        check_out(&repo, rev)?;
+    }
+    Ok(Checkout::Full)
+}
+
+/// Check whether a checkout only has some of its files
+///
+/// Only the git CLI makes sparse checkouts, and it may keep the setting in
+/// the worktree config, which libgit2 doesn't read, so ask git.
+pub fn is_sparse(dir: &Path) -> bool {
+    Command::new("git")
+        .current_dir(dir)
+        .args(["config", "--bool", "core.sparseCheckout"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+/// Make sure a checkout has every file, widening a sparse checkout
+///
+/// Returns whether the sources are available.
+pub fn ensure_sources(dir: &Path) -> bool {
+    !is_sparse(dir) || git(&["sparse-checkout", "disable"], Some(dir))
+}
+
+/// Update a checkout to the latest commit `rev` names
+///
+/// Only branches move, so this matters for mutable revs; an empty `rev` is
+/// the default branch. Returns whether it was updated. The checkout stays as
+/// it was when it can't be, such as when network access is forbidden.
+pub fn refresh(dir: &Path, rev: &str) -> bool {
+    if crate::toolchains::network::is_forbidden() {
+        return false;
+    }
+    let rev = if rev.is_empty() { "HEAD" } else { rev };
+    git(&["fetch", "--quiet", "--depth", "1", "origin", rev], Some(dir)) && git(&["reset", "--quiet", "--hard", "FETCH_HEAD"], Some(dir))
+}
+
+/// Get the directory a hook repo is checked out to at `rev` in the cache
+pub fn checkout_dir(repo_url: &str, rev: &str) -> PathBuf {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    repo_url.hash(&mut hasher);
+    rev.hash(&mut hasher);
+    crate::cache::default_cache_dir().join("cache").join("repos").join(hasher.finish().to_string())
+}
+
+/// Check out a hook repo at `rev` into the cache, unless it already is
+///
+/// With `needs_sources`, an earlier sparse checkout is widened. Returns the
+/// checkout, or `None` when the repo can't be cloned or has no such rev.
+pub fn checkout(repo_url: &str, rev: &str, needs_sources: bool) -> Option<PathBuf> {
+    let repo_dir = checkout_dir(repo_url, rev);
+    if repo_dir.exists() {
+        log::debug!("Using cached repository at {}", repo_dir.display());
+        if needs_sources && !ensure_sources(&repo_dir) {
+            log::warn!("Could not check out every file of repository {}", repo_url);
+        }
+        return Some(repo_dir);
+    }
+
+    if let Err(err) = repo_dir.parent().map_or(Ok(()), std::fs::create_dir_all) {
+        log::warn!("Failed to create cache directory: {}", err);
+        return None;
+    }
+    if crate::toolchains::network::is_forbidden() {
+        log::warn!("Not cloning repository {}: network access is forbidden by --forbid-network", repo_url);
+        return None;
+    }
+
+    log::debug!("Cloning repository {} at {:?} into {}", repo_url, rev, repo_dir.display());
+    match clone_hook_repo(repo_url, &repo_dir, rev, needs_sources) {
+        Ok(checkout) => {
+            log::debug!("Cloned repository {} as a {:?} checkout", repo_url, checkout);
+            Some(repo_dir)
+        }
+        Err(err) => {
+            log::warn!("Failed to clone repository {} at {:?}: {}", repo_url, rev, err);
+            // Clean up the directory if the clone failed
+            let _ = std::fs::remove_dir_all(&repo_dir);
+            None
+        }
+    }
+}
+
+/// Check whether a repo id names a hook repo rather than the project itself
+fn is_hook_repo(repo: &str) -> bool {
+    !matches!(repo, "local" | "meta")
+}
+
+/// Point the entry of a hook from a hook repo at the file in its checkout
+///
+/// Like pre-commit, `script` hooks run a file of their repo, and so do
+/// `system` hooks whose entry is a relative path missing from the project,
+/// and `python` hooks whose entry is such a `.py` script.
+/// The hook still runs in the project, on the project's files, and its
+/// `version` is the rev of the repo to check out. Other hooks are returned
+/// unchanged.
+pub fn resolve_entry(repo: &str, hook: &Hook) -> Hook {
+    let commands = hook.commands();
+    let resolved: Vec<String> = commands.iter().map(|command| resolve_command(repo, hook, command).unwrap_or_else(|| command.to_string())).collect();
+    if resolved.iter().zip(&commands).all(|(resolved, command)| resolved == command) {
+        return hook.clone();
+    }
+    let entry = super::parser::join_commands(&resolved);
+    log::debug!("Resolved the entry of hook '{}' to {}", hook.id, entry);
+    Hook { entry, ..hook.clone() }
+}
+
+/// Resolve one command of a hook's entry to a file of its repo, if it runs one
+fn resolve_command(repo: &str, hook: &Hook, command: &str) -> Option<String> {
+    let mut parts = command.split_whitespace();
+    let program = parts.next()?;
+    let runs_repo_file = match hook.language.as_str() {
+        "script" => true,
+        "system" => program.contains(['/', '\\']) && !Path::new(hook.working_dir.as_deref().unwrap_or(".")).join(program).exists(),
+        "python" => program.ends_with(".py") && !Path::new(hook.working_dir.as_deref().unwrap_or(".")).join(program).exists(),
+        _ => false,
+    };
+    if !is_hook_repo(repo) || !runs_repo_file || Path::new(program).is_absolute() {
+        return None;
+    }
+
+    let repo_dir = checkout(repo, hook.version.as_deref().unwrap_or_default(), true)?;
+    let script = repo_dir.join(program);
+    if !script.is_file() {
+        log::warn!("Hook '{}' runs {}, which repository {} doesn't have", hook.id, program, repo);
+        return None;
+    }
+    Some(std::iter::once(script.to_string_lossy().into_owned()).chain(parts.map(String::from)).collect::<Vec<_>>().join(" "))
+}
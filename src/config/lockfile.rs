@@ -0,0 +1,50 @@
+//! Lockfile for RustyHook
+//!
+//! Hooks asking for `version: latest` get the newest release of their package
+//! when they first run. The version it resolved to is recorded in
+//! `.rustyhook/lock.yaml`, which is meant to be committed, so later runs and
+//! other machines keep using it. Removing an entry resolves it again.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::parser::ConfigError;
+
+/// Path of the lockfile, relative to the repository root
+pub const LOCKFILE: &str = ".rustyhook/lock.yaml";
+
+/// The versions `latest` resolved to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    /// Resolved versions, by `<registry>:<package>`
+    #[serde(default)]
+    pub versions: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Get the path of the lockfile of a repository
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(LOCKFILE)
+    }
+
+    /// Load a lockfile, or an empty one if it doesn't exist
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        if !path.exists() {
+            return Ok(Lockfile::default());
+        }
+        Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Save the lockfile
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = format!("# Versions `latest` resolved to, remove an entry to resolve it again\n{}", serde_yaml::to_string(self)?);
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
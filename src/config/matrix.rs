@@ -0,0 +1,187 @@
+//! Matrix expansion for RustyHook
+//!
+//! This module expands hooks that declare a `matrix:` into one hook per
+//! combination of matched values, so monorepos don't need a near-identical
+//! hook block for every package.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use globset::Glob;
+use walkdir::WalkDir;
+
+use super::parser::{Config, ConfigError, Hook};
+use super::workspace::{Project, ALL_PROJECTS};
+
+/// Find the directories matching a matrix glob, relative to the given root
+///
+/// Paths are returned sorted with forward slashes so the expansion order is stable.
+pub fn match_matrix_values(root: &Path, pattern: &str) -> Result<Vec<String>, ConfigError> {
+    let matcher = Glob::new(pattern)
+        .map_err(|e| ConfigError::ValidationError(format!("Invalid matrix pattern '{}': {}", pattern, e)))?
+        .compile_matcher();
+
+    // Only descend as deep as the pattern can match, unless it is recursive
+    let max_depth = if pattern.contains("**") {
+        usize::MAX
+    } else {
+        pattern.split('/').count()
+    };
+
+    let mut values = Vec::new();
+    for entry in WalkDir::new(root)
+        .min_depth(1)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git")
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_dir())
+    {
+        let relative = match entry.path().strip_prefix(root) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if matcher.is_match(&relative) {
+            values.push(relative);
+        }
+    }
+
+    values.sort();
+    Ok(values)
+}
+
+/// Substitute `{matrix.<key>}` placeholders in a string
+fn substitute(template: &str, vars: &BTreeMap<String, String>, escape_regex: bool) -> String {
+    let mut result = template.to_string();
+    for (key, value) in vars {
+        let value = if escape_regex {
+            regex::escape(value)
+        } else {
+            value.clone()
+        };
+        result = result.replace(&format!("{{matrix.{}}}", key), &value);
+    }
+    result
+}
+
+/// Expand a single hook over its matrix
+///
+/// Hooks without a matrix are returned unchanged. A matrix that matches nothing
+/// expands to no hooks at all.
+pub fn expand_hook(hook: &Hook, root: &Path) -> Result<Vec<Hook>, ConfigError> {
+    if hook.matrix.is_empty() {
+        return Ok(vec![hook.clone()]);
+    }
+
+    // Build the cartesian product of all matrix variables
+    let mut combinations: Vec<BTreeMap<String, String>> = vec![BTreeMap::new()];
+    for (key, pattern) in &hook.matrix {
+        let values = match_matrix_values(root, pattern)?;
+        if values.is_empty() {
+            log::warn!("Matrix variable '{}' of hook '{}' matched no paths for '{}'", key, hook.id, pattern);
+        }
+
+        let mut next = Vec::new();
+        for combination in &combinations {
+            for value in &values {
+                let mut combination = combination.clone();
+                combination.insert(key.clone(), value.clone());
+                next.push(combination);
+            }
+        }
+        combinations = next;
+    }
+
+    let expanded = combinations
+        .into_iter()
+        .map(|vars| {
+            let label = vars.values().cloned().collect::<Vec<_>>().join(",");
+            Hook {
+                id: format!("{}[{}]", hook.id, label),
+                name: format!("{} ({})", hook.name, label),
+                entry: substitute(&hook.entry, &vars, false),
+                files: substitute(&hook.files, &vars, true),
+                args: hook.args.iter().map(|arg| substitute(arg, &vars, false)).collect(),
+                args_after_files: hook.args_after_files.iter().map(|arg| substitute(arg, &vars, false)).collect(),
+                working_dir: hook.working_dir.as_ref().map(|dir| substitute(dir, &vars, false)),
+                matrix: BTreeMap::new(),
+                ..hook.clone()
+            }
+        })
+        .collect();
+
+    Ok(expanded)
+}
+
+/// Scope a hook's file pattern to a project directory
+///
+/// The pattern is matched relative to the project, so a leading `^` anchors
+/// at the project directory.
+fn project_files(path: &str, files: &str) -> String {
+    let prefix = format!("^{}/", regex::escape(path));
+    if files.is_empty() {
+        prefix
+    } else if let Some(anchored) = files.strip_prefix('^') {
+        format!("{}(?:{})", prefix, anchored)
+    } else {
+        format!("{}.*(?:{})", prefix, files)
+    }
+}
+
+/// Expand a hook with `per_project` into one hook per selected project
+///
+/// `{project.name}` and `{project.path}` are substituted in `entry` and
+/// `args`. Hooks without `per_project` are returned unchanged.
+pub fn expand_projects(hook: &Hook, projects: &[Project]) -> Result<Vec<Hook>, ConfigError> {
+    let Some(selector) = &hook.per_project else {
+        return Ok(vec![hook.clone()]);
+    };
+    if ![ALL_PROJECTS, "cargo", "node", "python"].contains(&selector.as_str()) {
+        return Err(ConfigError::ValidationError(format!(
+            "Invalid per_project value '{}' for hook '{}': expected cargo, node, python or all",
+            selector, hook.id
+        )));
+    }
+
+    let selected: Vec<&Project> = projects.iter().filter(|project| project.is_selected_by(selector)).collect();
+    if selected.is_empty() {
+        log::warn!("Hook '{}' runs per {} project, but the config lists none; run 'rustyhook init --refresh-projects'", hook.id, selector);
+    }
+
+    let substitute_project = |value: &str, project: &Project| {
+        value.replace("{project.name}", &project.name).replace("{project.path}", &project.path)
+    };
+    Ok(selected
+        .into_iter()
+        .map(|project| Hook {
+            id: format!("{}[{}]", hook.id, project.path),
+            name: format!("{} ({})", hook.name, project.name),
+            entry: substitute_project(&hook.entry, project),
+            files: project_files(&project.path, &hook.files),
+            args: hook.args.iter().map(|arg| substitute_project(arg, project)).collect(),
+            args_after_files: hook.args_after_files.iter().map(|arg| substitute_project(arg, project)).collect(),
+            working_dir: Some(match &hook.working_dir {
+                Some(dir) => format!("{}/{}", project.path, dir),
+                None => project.path.clone(),
+            }),
+            per_project: None,
+            ..hook.clone()
+        })
+        .collect())
+}
+
+/// Expand every matrix and per-project hook in a configuration
+pub fn expand_matrix(config: &Config, root: &Path) -> Result<Config, ConfigError> {
+    let mut expanded = config.clone();
+    for repo in &mut expanded.repos {
+        let mut hooks = Vec::new();
+        for hook in &repo.hooks {
+            for hook in expand_projects(hook, &config.projects)? {
+                hooks.extend(expand_hook(&hook, root)?);
+            }
+        }
+        repo.hooks = hooks;
+    }
+    Ok(expanded)
+}
@@ -0,0 +1,62 @@
+//! Minimum RustyHook version of configs and hooks
+//!
+//! A config can set `minimum_rustyhook_version`, and so can each hook,
+//! including the hook definitions of hook repos. Configs using features of
+//! newer versions then fail to load on older binaries with a message to
+//! upgrade, instead of misbehaving, like pre-commit's
+//! `minimum_pre_commit_version`.
+//!
+//! Versions are compared by their dot-separated numbers, so `1.2` is the
+//! same as `1.2.0`. Anything after a `-` or `+`, like a pre-release, is
+//! ignored.
+
+use std::cmp::Ordering;
+
+use super::parser::{Config, ConfigError};
+
+/// Version of the running binary
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Parse a version into its numbers
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let release = version.trim().trim_start_matches('v').split(['-', '+']).next()?;
+    release.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Compare two versions by their numbers, padding the shorter one with zeros
+fn compare(a: &[u64], b: &[u64]) -> Ordering {
+    let len = a.len().max(b.len());
+    let pad = |v: &[u64]| v.iter().copied().chain(std::iter::repeat(0)).take(len).collect::<Vec<_>>();
+    pad(a).cmp(&pad(b))
+}
+
+/// Check that `current` is at least the version `required` by `what`
+pub fn check_version(required: &str, current: &str, what: &str) -> Result<(), ConfigError> {
+    let Some(required_parts) = parse_version(required) else {
+        return Err(ConfigError::ValidationError(format!(
+            "Invalid minimum_rustyhook_version '{}' of {}, expected a version like 0.3.0",
+            required, what
+        )));
+    };
+    let current_parts = parse_version(current).unwrap_or_default();
+    if compare(&current_parts, &required_parts) == Ordering::Less {
+        return Err(ConfigError::ValidationError(format!(
+            "{} requires rustyhook {} or newer, but this is rustyhook {}. Upgrade rustyhook to use it.",
+            what, required, current
+        )));
+    }
+    Ok(())
+}
+
+/// Check the minimum versions of a config and its hooks against the running binary
+pub fn check_minimum_versions(config: &Config) -> Result<(), ConfigError> {
+    if let Some(required) = &config.minimum_rustyhook_version {
+        check_version(required, CURRENT_VERSION, "The configuration")?;
+    }
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        if let Some(required) = &hook.minimum_rustyhook_version {
+            check_version(required, CURRENT_VERSION, &format!("Hook {}", hook.id))?;
+        }
+    }
+    Ok(())
+}
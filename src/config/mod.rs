@@ -5,7 +5,26 @@
 pub mod parser;
 pub mod compat;
 pub mod converter;
+pub mod matrix;
+pub mod freeze;
+pub mod drift;
+pub mod workspace;
+pub mod hook_repo;
+pub mod policy;
+pub mod lockfile;
+pub mod min_version;
+pub mod templates;
+pub mod perf;
 
-pub use parser::{Config, ConfigError, Hook, Repo, find_config, parse_config};
-pub use compat::{PreCommitConfig, PreCommitRepo, PreCommitHook, find_precommit_config, find_precommit_config_path, parse_precommit_config, convert_to_rustyhook_config};
+pub use parser::{Config, ConfigError, Hook, PerOs, Repo, find_config, find_config_path, find_config_with, parse_config};
+pub use compat::{PreCommitConfig, PreCommitRepo, PreCommitHook, find_precommit_config, find_precommit_config_path, parse_precommit_config, convert_to_rustyhook_config, merge_compat_config};
 pub use converter::{ConversionError, convert_from_precommit, create_starter_config};
+pub use matrix::expand_matrix;
+pub use drift::{check_converted_config, check_hook_scripts, Drift};
+pub use workspace::{discover_projects, refresh_projects, Project, ProjectKind};
+pub use policy::{apply_policy, load_policy, merge_policy, GlobalConfig};
+pub use lockfile::Lockfile;
+pub use min_version::check_minimum_versions;
+pub use templates::create_config_from_template;
+pub use perf::{lint_performance, PerfIssue, PerfIssueKind};
+pub use freeze::{autoupdate_precommit_config, check_minimum_revs, check_mutable_revs, freeze_precommit_config};
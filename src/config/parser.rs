@@ -3,16 +3,24 @@
 //! This module provides functionality for parsing RustyHook configuration files.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::fmt;
 
+use super::workspace::Project;
+use crate::toolchains::output::Truncation;
+use crate::toolchains::parsers::{check_output_parsers, OutputFormat};
+
 /// Represents a complete RustyHook configuration
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
-    /// Default stages to run hooks on
-    #[serde(default = "default_stages")]
+    /// Oldest rustyhook version that can load this config
+    #[serde(default, deserialize_with = "deserialize_version", skip_serializing_if = "Option::is_none")]
+    pub minimum_rustyhook_version: Option<String>,
+
+    /// Stages hooks without `stages` run on (every stage when empty)
+    #[serde(default = "default_stages", deserialize_with = "deserialize_stages")]
     pub default_stages: Vec<String>,
 
     /// Whether to stop running hooks after the first failure
@@ -23,12 +31,117 @@ pub struct Config {
     #[serde(default = "default_parallelism")]
     pub parallelism: usize,
 
+    /// Maximum number of hook environments to set up in parallel (default: parallelism, 0 means unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setup_parallelism: Option<usize>,
+
+    /// Maximum number of hooks to run in parallel once set up (default: parallelism, 0 means unlimited)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_parallelism: Option<usize>,
+
+    /// Directory for cached environments and tool installs (default: .rustyhook)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Also run the hooks of .pre-commit-config.yaml that the native config doesn't define
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub compat_merge: bool,
+
+    /// Notify when a run takes longer than this many seconds (disabled when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify_after_seconds: Option<u64>,
+
+    /// How to notify when a long run finishes (default: bell and desktop)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notify: Vec<NotifyMethod>,
+
+    /// How long cached metadata is reused before it is fetched again
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub cache_ttl: CacheTtl,
+
+    /// What to do when hooks are run as root (default: warn)
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub run_as_root: RunAsRoot,
+
+    /// Whether hooks also run on vendored, built and generated files, which are skipped by default
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_default_excludes: bool,
+
+    /// Directory the home directories of tool environments are created in (default: `<cache_dir>/home`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_home: Option<PathBuf>,
+
+    /// Whether downloaded runtime archives are kept after installing them (default: true)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub keep_downloads: bool,
+
+    /// Whether Ruby hooks use a matching Ruby installed by rbenv, rvm or asdf (default: true)
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
+    pub reuse_installed_rubies: bool,
+
+    /// File to append a tamper-evident entry to after each run (disabled when unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Message printed when hooks run by a Git hook fail, with `{stage}`, `{hooks}` and `{bin}` placeholders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_message: Option<String>,
+
+    /// Member packages of the repository's workspaces, written by `rustyhook init`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub projects: Vec<Project>,
+
     /// List of repositories containing hooks
     pub repos: Vec<Repo>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            minimum_rustyhook_version: None,
+            default_stages: default_stages(),
+            fail_fast: false,
+            parallelism: default_parallelism(),
+            setup_parallelism: None,
+            run_parallelism: None,
+            cache_dir: None,
+            compat_merge: false,
+            notify_after_seconds: None,
+            notify: Vec::new(),
+            cache_ttl: CacheTtl::default(),
+            run_as_root: RunAsRoot::default(),
+            no_default_excludes: false,
+            tool_home: None,
+            keep_downloads: true,
+            reuse_installed_rubies: true,
+            audit_log: None,
+            blocked_message: None,
+            projects: Vec::new(),
+            repos: Vec::new(),
+        }
+    }
+}
+
+/// How long each kind of cached metadata is reused, in seconds
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct CacheTtl {
+    /// The hook definitions of hook repos, before their checkout is refreshed (default: a week)
+    pub hook_repos: u64,
+    /// The versions `latest` resolved to in package registries (default: a day)
+    pub versions: u64,
+    /// The files `.gitattributes` marks as binary or generated, and their line endings (default: a day)
+    pub file_attributes: u64,
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        CacheTtl { hook_repos: 7 * 24 * 60 * 60, versions: 24 * 60 * 60, file_attributes: 24 * 60 * 60 }
+    }
+}
+
 /// Represents a repository containing hooks
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Repo {
     /// Repository identifier
     pub repo: String,
@@ -60,6 +173,22 @@ fn default_hook_type() -> HookType {
     HookType::External
 }
 
+impl Default for HookType {
+    fn default() -> Self {
+        default_hook_type()
+    }
+}
+
+/// Ways to notify the user when a long run finishes
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyMethod {
+    /// Ring the terminal bell
+    Bell,
+    /// Show a desktop notification
+    Desktop,
+}
+
 /// Access mode for hooks (read-only or read-write)
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum AccessMode {
@@ -78,13 +207,56 @@ impl fmt::Display for AccessMode {
     }
 }
 
+/// What to do with files over a hook's `max_file_size`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OversizedFiles {
+    /// Run the hook on the other files and report the skipped ones
+    #[default]
+    Skip,
+    /// Fail the hook without running it
+    Fail,
+}
+
+/// Which `node_modules` a Node.js hook runs with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NodeModules {
+    /// An environment RustyHook installs the hook's package into
+    #[default]
+    Managed,
+    /// The repository's own, which must be installed from its lockfile
+    Repo,
+    /// The repository's own, installed from its lockfile (`npm ci`) when missing or stale
+    Ci,
+}
+
+/// What to do when hooks are run as root, like with `sudo git commit`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunAsRoot {
+    /// Run the hooks without a warning
+    Allow,
+    /// Warn, then run the hooks
+    #[default]
+    Warn,
+    /// Fail without running the hooks
+    Fail,
+}
+
 /// Default access mode (read-write for safety)
 fn default_access_mode() -> AccessMode {
     AccessMode::ReadWrite
 }
 
+impl Default for AccessMode {
+    fn default() -> Self {
+        default_access_mode()
+    }
+}
+
 /// Represents a single hook
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Hook {
     /// Hook identifier
     pub id: String,
@@ -92,7 +264,16 @@ pub struct Hook {
     /// Human-readable name
     pub name: String,
 
+    /// What the hook checks or fixes, shown by `list` and `plan`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
     /// Command or script to run
+    ///
+    /// A list of commands is kept joined by [`COMMAND_SEPARATOR`], see
+    /// [`Hook::commands`]. Hooks with an `npm_script` need none. Given per OS,
+    /// see [`PerOs`].
+    #[serde(default, deserialize_with = "deserialize_entry", serialize_with = "serialize_entry")]
     pub entry: String,
 
     /// Programming language or environment
@@ -102,16 +283,20 @@ pub struct Hook {
     #[serde(default)]
     pub files: String,
 
-    /// Stages to run this hook on
-    #[serde(default = "default_stages")]
+    /// Stages to run this hook on (default: the config's `default_stages`)
+    #[serde(default, deserialize_with = "deserialize_stages", skip_serializing_if = "Vec::is_empty")]
     pub stages: Vec<String>,
 
-    /// Additional arguments to pass to the hook
-    #[serde(default)]
+    /// Additional arguments to pass to the hook, or a map of them per OS
+    #[serde(default, deserialize_with = "deserialize_args")]
     pub args: Vec<String>,
 
-    /// Additional environment variables
-    #[serde(default)]
+    /// Arguments to pass to the hook after the files, like `--config x.toml` for tools taking files before options
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args_after_files: Vec<String>,
+
+    /// Additional environment variables, or a map of them per OS
+    #[serde(default, deserialize_with = "deserialize_env")]
     pub env: HashMap<String, String>,
 
     /// Version of the tool to use
@@ -129,11 +314,470 @@ pub struct Hook {
     /// Access mode for this hook (read-only or read-write)
     #[serde(default = "default_access_mode")]
     pub access_mode: AccessMode,
+
+    /// Directory to run the hook in, relative to the repository root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_dir: Option<String>,
+
+    /// Matrix of variables to expand this hook over
+    ///
+    /// Each key names a variable and each value is a glob matched against
+    /// directories relative to the repository root. The hook is expanded into
+    /// one invocation per combination of matches, with `{matrix.<key>}`
+    /// substituted in `entry`, `args`, `files` and `working_dir`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub matrix: BTreeMap<String, String>,
+
+    /// Kind of project to run this hook in each of (`cargo`, `node`, `python` or `all`)
+    ///
+    /// The hook is expanded into one invocation per matching entry of
+    /// `projects`, which runs in the project's directory on the project's
+    /// files only. `files` is matched relative to the project.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub per_project: Option<String>,
+
+    /// Policy this hook is enforced by, set when the policy is merged in
+    ///
+    /// Hooks from a policy always run: `--skip` and `RUSTYHOOK_SKIP` don't
+    /// apply to them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+
+    /// Whether to pass files deleted in the changeset to the hook
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub include_deleted: bool,
+
+    /// Whether to pass absolute paths to the hook
+    ///
+    /// By default, files are passed relative to the directory the hook runs
+    /// in, so tools match them against their relative ignore patterns.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub absolute_paths: bool,
+
+    /// Most bytes of output to show from each of the hook's output streams, 0 for no limit
+    ///
+    /// Defaults to 1 MiB. The full output of a truncated stream is saved in
+    /// `<cache_dir>/logs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output: Option<usize>,
+
+    /// Which part of output over `max_output` to show (`head`, `tail` or `head-tail`)
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub output_truncation: Truncation,
+
+    /// Exit codes the hook passes with, like `[0, 1]` for tools exiting with 1 for findings that aren't fatal
+    ///
+    /// Defaults to `[0]`. Passing with another code is logged as a warning.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub success_exit_codes: Vec<i32>,
+
+    /// Format of the problems the hook's tool prints, parsed into diagnostics for reports
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub output_format: OutputFormat,
+
+    /// Pattern of output lines with a problem, for `output_format: regex`
+    ///
+    /// Its named groups `file`, `line` and `message` are required, and
+    /// `column`, `code` and `severity` optional.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_pattern: Option<String>,
+
+    /// Container image to run the entry in, instead of setting up a toolchain
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+
+    /// Whether to stop the whole run when this hook fails
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub fail_fast: bool,
+
+    /// Whether to only report failures of this hook instead of failing the run
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub continue_on_error: bool,
+
+    /// Whether to refuse to run when the hook's files have unstaged changes
+    ///
+    /// Keeps the fixes of mutating hooks apart from work in progress.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub require_clean: bool,
+
+    /// Whether to skip files `.gitattributes` marks as binary or generated
+    ///
+    /// Defaults to true for native hooks that read files as text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_only: Option<bool>,
+
+    /// Whether to pass file paths with the platform's separators
+    ///
+    /// Paths use forward slashes by default, which only differs on Windows.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub native_path_separators: bool,
+
+    /// Largest file in bytes to pass to the hook, 0 for no limit
+    ///
+    /// Defaults to 100 MiB for native hooks that read files as text, and no
+    /// limit for other hooks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<u64>,
+
+    /// What to do with files over `max_file_size` (`skip` or `fail`)
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub oversized_files: OversizedFiles,
+
+    /// Whether to also run on vendored, built and generated files, which are skipped by default
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_default_excludes: bool,
+
+    /// Cheap command checking the hook's environment works, like `eslint --version`
+    ///
+    /// It runs once per session before the hook, in the hook's environment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preflight: Option<String>,
+
+    /// Script of the repository's package.json to run with `npm run` instead of the entry
+    ///
+    /// Arguments and files are passed to the script after `--`. The script
+    /// runs with the repository's `node_modules`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub npm_script: Option<String>,
+
+    /// Which `node_modules` a Node.js hook runs with (`managed`, `repo` or `ci`)
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub node_modules: NodeModules,
+
+    /// Oldest rustyhook version that can run this hook
+    #[serde(default, deserialize_with = "deserialize_version", skip_serializing_if = "Option::is_none")]
+    pub minimum_rustyhook_version: Option<String>,
+}
+
+impl Hook {
+    /// Get the commands the entry runs one after another
+    pub fn commands(&self) -> Vec<&str> {
+        entry_commands(&self.language, &self.entry)
+    }
+
+    /// Get a hook per command of the entry, and whether the command gets the files
+    ///
+    /// The last command gets the hook's arguments and files. Earlier ones get
+    /// no arguments, and the files only when they say where with `{files}`,
+    /// which is removed from the entry of their hook.
+    pub fn command_hooks(&self) -> Vec<(Hook, bool)> {
+        let commands = self.commands();
+        if commands.len() <= 1 {
+            return vec![(self.clone(), true)];
+        }
+        commands
+            .iter()
+            .enumerate()
+            .map(|(index, command)| {
+                let takes_files = index + 1 == commands.len() || command.split_whitespace().any(|part| part == FILES_PLACEHOLDER);
+                let entry = command.split_whitespace().filter(|part| *part != FILES_PLACEHOLDER).collect::<Vec<_>>().join(" ");
+                let hook = if index + 1 == commands.len() {
+                    Hook { entry, ..self.clone() }
+                } else {
+                    Hook { entry, args: Vec::new(), args_after_files: Vec::new(), ..self.clone() }
+                };
+                (hook, takes_files)
+            })
+            .collect()
+    }
+
+    /// Check whether the hook runs with the repository's `node_modules` rather than a managed environment
+    pub fn uses_repo_node_modules(&self) -> bool {
+        self.npm_script.is_some() || self.node_modules != NodeModules::Managed
+    }
+
+    /// Get the identifier of the hook this one was expanded from
+    ///
+    /// Matrix expansion gives each invocation an id of the form `id[value]`, so
+    /// skipping the base id should skip every expansion.
+    pub fn base_id(&self) -> &str {
+        match self.id.find('[') {
+            Some(pos) if self.id.ends_with(']') => &self.id[..pos],
+            _ => &self.id,
+        }
+    }
+
+    /// Check whether this hook is listed in the given skip list
+    ///
+    /// Hooks enforced by a policy are never skipped.
+    pub fn is_skipped_by(&self, hooks_to_skip: &[String]) -> bool {
+        self.policy.is_none() && hooks_to_skip.iter().any(|skip| skip == &self.id || skip == self.base_id())
+    }
+
+    /// Get the stages this hook runs on: its own, or else `default_stages`
+    pub fn stages_or<'a>(&'a self, default_stages: &'a [String]) -> &'a [String] {
+        if self.stages.is_empty() { default_stages } else { &self.stages }
+    }
+
+    /// Check whether this hook runs in a Git hook stage
+    ///
+    /// Hooks without stages use `default_stages`, and run in every stage when
+    /// both are empty. The legacy names `commit`, `push` and `merge-commit` are
+    /// accepted for `pre-commit`, `pre-push` and `pre-merge-commit`.
+    pub fn runs_in_stage(&self, default_stages: &[String], stage: &str) -> bool {
+        let stages = self.stages_or(default_stages);
+        stages.is_empty() || stages.iter().any(|s| normalize_stage(s) == normalize_stage(stage))
+    }
+}
+
+/// Map legacy stage names to their Git hook names
+pub fn normalize_stage(stage: &str) -> &str {
+    match stage {
+        "commit" => "pre-commit",
+        "push" => "pre-push",
+        "merge-commit" => "pre-merge-commit",
+        other => other,
+    }
+}
+
+/// Map every legacy stage name of a list to its Git hook name
+pub fn normalize_stages(stages: &[String]) -> Vec<String> {
+    stages.iter().map(|stage| normalize_stage(stage).to_string()).collect()
+}
+
+/// Languages whose entry is a message, pattern or hook ID rather than commands
+const NON_COMMAND_LANGUAGES: &[&str] = &["fail", "pygrep", "native"];
+
+/// Separator of the commands of an entry given as a list
+///
+/// A string entry is a single command even when it spans several lines, so
+/// the commands of a list are joined by a character no command contains.
+pub const COMMAND_SEPARATOR: char = '\u{1e}';
+
+/// Placeholder for the files in a command of an entry
+pub const FILES_PLACEHOLDER: &str = "{files}";
+
+/// Split an entry into the commands it runs one after another
+///
+/// Only entries given as a list have several commands. Fail, pygrep and
+/// native hooks have a single message, pattern or hook ID.
+pub fn entry_commands<'a>(language: &str, entry: &'a str) -> Vec<&'a str> {
+    if NON_COMMAND_LANGUAGES.contains(&language) {
+        return vec![entry];
+    }
+    entry.split(COMMAND_SEPARATOR).map(str::trim).filter(|command| !command.is_empty()).collect()
+}
+
+/// Join commands into the entry running them one after another
+pub fn join_commands<S: AsRef<str>>(commands: &[S]) -> String {
+    commands.iter().map(AsRef::as_ref).collect::<Vec<_>>().join(&COMMAND_SEPARATOR.to_string())
+}
+
+/// Get the command line of a command of an entry, with the hook's arguments and files
+///
+/// The hook's `args`, files and `args_after_files` are added to the last
+/// command. `{files}` in a command is replaced with the files, which are then
+/// not added after the `args`.
+pub fn command_line<T: From<String> + Clone>(command: &str, last: bool, args: &[String], files: &[T], args_after_files: &[String]) -> Vec<T> {
+    let mut line = Vec::new();
+    let mut placed_files = false;
+    for part in command.split_whitespace() {
+        if part == FILES_PLACEHOLDER {
+            line.extend(files.iter().cloned());
+            placed_files = true;
+        } else {
+            line.push(T::from(part.to_string()));
+        }
+    }
+    if last {
+        line.extend(args.iter().cloned().map(T::from));
+        if !placed_files {
+            line.extend(files.iter().cloned());
+        }
+        line.extend(args_after_files.iter().cloned().map(T::from));
+    }
+    line
+}
+
+/// Deserialize a version, which must be quoted
+///
+/// YAML reads an unquoted version with a single dot as a number, which loses
+/// trailing zeros: `0.10` reads as `0.1`. Numbers are rejected rather than
+/// guessed at.
+pub(crate) fn deserialize_version<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Version {
+        Text(String),
+        Number(serde_yaml::Number),
+    }
+    Option::<Version>::deserialize(deserializer)?
+        .map(|version| match version {
+            Version::Text(text) => Ok(text),
+            Version::Number(number) => Err(serde::de::Error::custom(format!(
+                "version {} must be quoted, like \"{}\": YAML reads unquoted versions as numbers, dropping trailing zeros",
+                number, number
+            ))),
+        })
+        .transpose()
+}
+
+/// Values of a hook setting for each OS, picked for the host when the config loads
+///
+/// The most specific key wins: `linux` or `macos`, then `unix` or `windows`,
+/// then `default`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PerOs<T> {
+    /// Value on Windows
+    pub windows: Option<T>,
+    /// Value on Linux, macOS and other Unix systems
+    pub unix: Option<T>,
+    /// Value on Linux
+    pub linux: Option<T>,
+    /// Value on macOS
+    pub macos: Option<T>,
+    /// Value on any other OS
+    pub default: Option<T>,
+}
+
+impl<T> PerOs<T> {
+    /// Get the value for an OS, like `linux`, of a family, like `unix`
+    pub fn resolve_for(self, os: &str, family: &str) -> Option<T> {
+        let specific = match os {
+            "linux" => self.linux,
+            "macos" => self.macos,
+            _ => None,
+        };
+        let family = match family {
+            "unix" => self.unix,
+            "windows" => self.windows,
+            _ => None,
+        };
+        specific.or(family).or(self.default)
+    }
+
+    /// Get the value for the host OS
+    pub fn resolve(self) -> Option<T> {
+        self.resolve_for(std::env::consts::OS, std::env::consts::FAMILY)
+    }
+}
+
+/// A command or a list of commands
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Commands {
+    Command(String),
+    Commands(Vec<String>),
+}
+
+impl Commands {
+    /// Join the commands into an entry
+    fn joined(self) -> String {
+        match self {
+            Commands::Command(command) => command,
+            Commands::Commands(commands) => join_commands(&commands),
+        }
+    }
+}
+
+/// A hook setting given once, or per OS
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybePerOs<T> {
+    PerOs(PerOs<T>),
+    Value(T),
+}
+
+impl<T> MaybePerOs<T> {
+    /// Get the value for the host OS, `None` if no key matches it
+    fn resolve(self) -> Option<T> {
+        match self {
+            MaybePerOs::PerOs(per_os) => per_os.resolve(),
+            MaybePerOs::Value(value) => Some(value),
+        }
+    }
+}
+
+/// Deserialize an entry given as a command, a list of commands, or either per OS
+fn deserialize_entry<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match MaybePerOs::<Commands>::deserialize(deserializer)?.resolve() {
+        Some(commands) => Ok(commands.joined()),
+        None => Err(serde::de::Error::custom(format!(
+            "entry has no value for {}, add a `{}`, `{}` or `default` key",
+            std::env::consts::OS,
+            std::env::consts::OS,
+            std::env::consts::FAMILY
+        ))),
+    }
+}
+
+/// Serialize an entry, as a list when it has several commands
+fn serialize_entry<S: serde::Serializer>(entry: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    if entry.contains(COMMAND_SEPARATOR) {
+        serializer.collect_seq(entry.split(COMMAND_SEPARATOR))
+    } else {
+        serializer.serialize_str(entry)
+    }
+}
+
+/// Deserialize arguments given as a list, or a list per OS
+///
+/// Hooks get no arguments on an OS without a matching key.
+fn deserialize_args<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(MaybePerOs::<Vec<String>>::deserialize(deserializer)?.resolve().unwrap_or_default())
+}
+
+/// Deserialize environment variables given as a map, or a map per OS
+///
+/// Hooks get no variables on an OS without a matching key.
+fn deserialize_env<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<HashMap<String, String>, D::Error> {
+    Ok(MaybePerOs::<HashMap<String, String>>::deserialize(deserializer)?.resolve().unwrap_or_default())
+}
+
+/// Deserialize a list of stages, with legacy names mapped to Git hook names
+fn deserialize_stages<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Vec::<String>::deserialize(deserializer).map(|stages| normalize_stages(&stages))
+}
+
+impl Config {
+    /// Get the maximum number of environments to set up in parallel (0 means unlimited)
+    pub fn setup_limit(&self) -> usize {
+        self.setup_parallelism.unwrap_or(self.parallelism)
+    }
+
+    /// Get the maximum number of hooks to run in parallel (0 means unlimited)
+    pub fn run_limit(&self) -> usize {
+        self.run_parallelism.unwrap_or(self.parallelism)
+    }
+
+    /// Keep only the hooks with the given ID and stage, dropping empty repos
+    pub fn select_hooks(&self, hook_id: Option<&str>, stage: Option<&str>) -> Config {
+        let mut config = self.clone();
+        for repo in &mut config.repos {
+            repo.hooks.retain(|hook| {
+                hook_id.is_none_or(|id| hook.id == id || hook.base_id() == id)
+                    && stage.is_none_or(|stage| hook.runs_in_stage(&self.default_stages, stage))
+            });
+        }
+        config.repos.retain(|repo| !repo.hooks.is_empty());
+        config
+    }
 }
 
 /// Default stages for hooks
 fn default_stages() -> Vec<String> {
-    vec!["commit".to_string()]
+    vec!["pre-commit".to_string()]
+}
+
+/// Default for flags that are on unless the config turns them off
+fn default_true() -> bool {
+    true
+}
+
+/// Skip serializing flags that are on by default when they are on
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Skip serializing flags that are off
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Check whether a value is its type's default, to leave it out of serialized configs
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 /// Default parallelism for hook execution (0 means unlimited)
@@ -148,6 +792,8 @@ pub enum ConfigError {
     IoError(std::io::Error),
     /// Error parsing the YAML configuration
     ParseError(serde_yaml::Error),
+    /// The configuration is syntactically valid but semantically wrong
+    ValidationError(String),
 }
 
 impl From<std::io::Error> for ConfigError {
@@ -166,18 +812,50 @@ impl From<serde_yaml::Error> for ConfigError {
 pub fn parse_config<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
     let config_str = fs::read_to_string(path)?;
     let config: Config = serde_yaml::from_str(&config_str)?;
+    super::min_version::check_minimum_versions(&config)?;
+    check_output_parsers(&config).map_err(ConfigError::ValidationError)?;
     Ok(config)
 }
 
+/// Environment variable pointing at an explicit configuration file
+pub const CONFIG_ENV: &str = "RUSTYHOOK_CONFIG";
+
 /// Find and parse the RustyHook configuration file
 pub fn find_config() -> Result<Config, ConfigError> {
+    find_config_with(None)
+}
+
+/// Find and parse the configuration file, preferring an explicit path
+pub fn find_config_with(explicit: Option<&Path>) -> Result<Config, ConfigError> {
+    parse_config(find_config_path(explicit)?)
+}
+
+/// Find the configuration file
+///
+/// An explicit path (from `--config`) wins over the `RUSTYHOOK_CONFIG`
+/// environment variable, which wins over searching for
+/// `.rustyhook/config.yaml` in the current directory and its parents.
+pub fn find_config_path(explicit: Option<&Path>) -> Result<PathBuf, ConfigError> {
+    let explicit = explicit
+        .map(Path::to_path_buf)
+        .or_else(|| std::env::var_os(CONFIG_ENV).filter(|value| !value.is_empty()).map(PathBuf::from));
+    if let Some(config_path) = explicit {
+        if !config_path.is_file() {
+            return Err(ConfigError::IoError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Config file {} not found", config_path.display()),
+            )));
+        }
+        return Ok(config_path);
+    }
+
     // Look for .rustyhook/config.yaml in the current directory and parent directories
     let mut current_dir = std::env::current_dir().map_err(ConfigError::IoError)?;
 
     loop {
         let config_path = current_dir.join(".rustyhook").join("config.yaml");
         if config_path.exists() {
-            return parse_config(config_path);
+            return Ok(config_path);
         }
 
         // Move to the parent directory
@@ -0,0 +1,160 @@
+//! Performance linting for RustyHook configurations
+//!
+//! A config can be valid and still make every run slower than it needs to
+//! be. This module flags the patterns that cost the most: hooks matching
+//! every file, hooks that could share one environment and one invocation,
+//! unpinned versions that send setup to a registry, and read-write hooks
+//! that hold up the rest of the plan. Each finding comes with the config
+//! edit that fixes it.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::parser::{AccessMode, Config, Hook, FILES_PLACEHOLDER};
+use crate::runner::latest::is_latest;
+use crate::runner::HookResolver;
+
+/// What makes a hook slow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PerfIssueKind {
+    /// A hook has no `files` pattern, so it gets every file
+    MatchesEverything,
+    /// Several hooks use the same environment on the same files
+    SharedEnvironment,
+    /// A registry hook has no exact version
+    UnpinnedVersion,
+    /// A read-write hook without a `files` pattern runs on its own
+    SerialHook,
+}
+
+/// A performance anti-pattern found in a config
+#[derive(Debug, Clone, PartialEq)]
+pub struct PerfIssue {
+    /// What the issue is
+    pub kind: PerfIssueKind,
+    /// IDs of the hooks involved
+    pub hook_ids: Vec<String>,
+    /// Why it makes runs slower
+    pub message: String,
+    /// The config edit that fixes it
+    pub suggestion: String,
+}
+
+impl fmt::Display for PerfIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n  suggestion: {}", self.message, self.suggestion)
+    }
+}
+
+/// Get the `files` pattern usually matching the sources of a language
+fn language_pattern(language: &str) -> Option<&'static str> {
+    match language {
+        "python" => Some(r"\.py$"),
+        "node" | "javascript" | "typescript" => Some(r"\.(js|jsx|ts|tsx)$"),
+        "ruby" => Some(r"\.rb$"),
+        "rust" => Some(r"\.rs$"),
+        "go" | "golang" => Some(r"\.go$"),
+        _ => None,
+    }
+}
+
+/// Suggest a `files` pattern for a hook
+fn files_suggestion(hook: &Hook) -> String {
+    match language_pattern(&hook.language) {
+        Some(pattern) => format!("add `files: {}` to '{}'", pattern, hook.id),
+        None => format!("add a `files` pattern matching the files '{}' checks", hook.id),
+    }
+}
+
+/// Find the performance anti-patterns of a config
+///
+/// Issues are returned in the order of the checks, and in config order within each check.
+pub fn lint_performance(config: &Config) -> Vec<PerfIssue> {
+    let hooks: Vec<&Hook> = config.repos.iter().flat_map(|repo| repo.hooks.iter()).collect();
+    let mut issues = Vec::new();
+
+    // Hooks without a pattern get every file, and match each of them
+    for hook in hooks.iter().filter(|hook| hook.files.is_empty()) {
+        issues.push(PerfIssue {
+            kind: PerfIssueKind::MatchesEverything,
+            hook_ids: vec![hook.id.clone()],
+            message: format!("'{}' has no files pattern, so it runs on every file", hook.id),
+            suggestion: files_suggestion(hook),
+        });
+    }
+
+    // Hooks of the same environment and files can be one hook with several commands
+    let mut shared: BTreeMap<(String, String), Vec<&Hook>> = BTreeMap::new();
+    for hook in &hooks {
+        shared.entry((HookResolver::tool_key(hook), hook.files.clone())).or_default().push(hook);
+    }
+    let mut groups: Vec<Vec<&Hook>> = shared.into_values().filter(|group| group.len() > 1).collect();
+    groups.sort_by_key(|group| hooks.iter().position(|hook| std::ptr::eq(*hook, group[0])));
+    for group in groups {
+        let ids: Vec<String> = group.iter().map(|hook| hook.id.clone()).collect();
+        issues.push(PerfIssue {
+            kind: PerfIssueKind::SharedEnvironment,
+            message: format!("{} use the same environment on the same files, and each runs on its own", ids.join(", ")),
+            suggestion: format!(
+                "merge them into '{}' with one command per hook in its `entry`:\n{}",
+                ids[0],
+                group
+                    .iter()
+                    .enumerate()
+                    .map(|(index, hook)| match index + 1 == group.len() {
+                        true => format!("    - {}", command(hook)),
+                        false => format!("    - {} {}", command(hook), FILES_PLACEHOLDER),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ),
+            hook_ids: ids,
+        });
+    }
+
+    // Hooks without an exact version query their registry for it
+    for hook in hooks.iter().filter(|hook| HookResolver::package(hook).is_some()) {
+        let message = match hook.version.as_deref() {
+            None => format!("'{}' has no version, so setting up its environment installs whatever is newest", hook.id),
+            Some(version) if is_latest(version) => format!(
+                "'{}' uses `version: latest`, which queries the registry until it is recorded in .rustyhook/lock.yaml",
+                hook.id
+            ),
+            Some(_) => continue,
+        };
+        issues.push(PerfIssue {
+            kind: PerfIssueKind::UnpinnedVersion,
+            hook_ids: vec![hook.id.clone()],
+            message,
+            suggestion: format!("set `version` of '{}' to an exact release, or commit .rustyhook/lock.yaml", hook.id),
+        });
+    }
+
+    // Read-write hooks without a pattern conflict with every other read-write hook
+    let write_hooks: Vec<&&Hook> = hooks.iter().filter(|hook| hook.access_mode == AccessMode::ReadWrite).collect();
+    let serial = write_hooks.iter().enumerate().filter(|(_, hook)| hook.files.is_empty() && write_hooks.len() > 1);
+    for (index, hook) in serial {
+        let later = write_hooks.len() - index - 1;
+        let message = if later > 0 {
+            format!(
+                "'{}' may write any file, so it runs alone, and the {} read-write hook(s) after it wait for it",
+                hook.id, later
+            )
+        } else {
+            format!("'{}' may write any file, so it runs alone after the other read-write hooks", hook.id)
+        };
+        issues.push(PerfIssue {
+            kind: PerfIssueKind::SerialHook,
+            hook_ids: vec![hook.id.clone()],
+            message,
+            suggestion: format!("{}, or set `access_mode: Read` if it only checks files", files_suggestion(hook)),
+        });
+    }
+
+    issues
+}
+
+/// Get a hook's command with its arguments
+fn command(hook: &Hook) -> String {
+    std::iter::once(hook.entry.as_str()).chain(hook.args.iter().map(String::as_str)).collect::<Vec<_>>().join(" ")
+}
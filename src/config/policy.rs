@@ -0,0 +1,151 @@
+//! Organization policies for RustyHook
+//!
+//! A policy is a config whose hooks every repository must run, such as secret
+//! scanning or license checks. Its location, a path or an `http(s)` URL, comes
+//! from the `RUSTYHOOK_POLICY` environment variable or the `policy` key of the
+//! user's global config (`$XDG_CONFIG_HOME/rustyhook/config.yaml`).
+//!
+//! Policy hooks are merged into every run and replace repository hooks with
+//! the same id. They record the policy they come from, which makes them
+//! immune to `--skip` and `RUSTYHOOK_SKIP` and shows up in `rustyhook list`.
+//! Policies fetched from a URL are cached, so runs keep enforcing them
+//! offline.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::parser::{Config, ConfigError};
+
+/// Environment variable with the location of the policy
+pub const POLICY_ENV: &str = "RUSTYHOOK_POLICY";
+
+/// Directory of the cache root holding policies fetched from URLs
+pub const POLICY_CACHE_DIR: &str = "policy";
+
+/// The user's global config
+#[derive(Debug, Default, Deserialize)]
+pub struct GlobalConfig {
+    /// Location of the policy, a path or an `http(s)` URL
+    #[serde(default)]
+    pub policy: Option<String>,
+    /// Message printed when hooks run by a Git hook fail, unless the config or policy sets one
+    #[serde(default)]
+    pub blocked_message: Option<String>,
+}
+
+impl GlobalConfig {
+    /// Read the user's global config, which is empty when there is none
+    pub fn load() -> Result<Self, ConfigError> {
+        match global_config_path().filter(|path| path.is_file()) {
+            Some(path) => Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?),
+            None => Ok(GlobalConfig::default()),
+        }
+    }
+}
+
+/// Get the path of the user's global config
+pub fn global_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").filter(|_| cfg!(windows)).map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("rustyhook").join("config.yaml"))
+}
+
+/// Find the location of the policy, if one is configured
+pub fn policy_location() -> Result<Option<String>, ConfigError> {
+    if let Some(location) = std::env::var(POLICY_ENV).ok().filter(|value| !value.is_empty()) {
+        return Ok(Some(location));
+    }
+    Ok(GlobalConfig::load()?.policy.filter(|location| !location.is_empty()))
+}
+
+/// Check whether a policy location is a URL
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+/// Fetch a policy from a URL
+fn fetch(url: &str) -> Result<String, String> {
+    if crate::toolchains::network::is_forbidden() {
+        return Err("network access is forbidden by --forbid-network".to_string());
+    }
+    let response = reqwest::blocking::get(url).map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.text().map_err(|e| e.to_string())
+}
+
+/// Read a policy from a URL, falling back to the copy fetched last
+fn read_url(url: &str, cache_root: &Path) -> Result<String, ConfigError> {
+    let digest: String = Sha256::digest(url.as_bytes()).iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+    let cached = cache_root.join(POLICY_CACHE_DIR).join(format!("{}.yaml", digest));
+    match fetch(url) {
+        Ok(content) => {
+            if let Err(e) = fs::create_dir_all(cached.parent().unwrap()).and_then(|_| fs::write(&cached, &content)) {
+                log::debug!("Could not cache the policy {}: {}", url, e);
+            }
+            Ok(content)
+        }
+        Err(e) if cached.is_file() => {
+            log::warn!("Could not fetch the policy {} ({}), using the copy fetched last", url, e);
+            Ok(fs::read_to_string(cached)?)
+        }
+        // Without any copy the policy can't be enforced, so don't run at all
+        Err(e) => Err(ConfigError::ValidationError(format!("Could not fetch the policy {}: {}", url, e))),
+    }
+}
+
+/// Load the policy at a location
+///
+/// Every hook of the returned config records the location as its policy.
+pub fn load_policy(location: &str, cache_root: &Path) -> Result<Config, ConfigError> {
+    let content = if is_url(location) { read_url(location, cache_root)? } else { fs::read_to_string(location)? };
+    let mut policy: Config = serde_yaml::from_str(&content)?;
+    for hook in policy.repos.iter_mut().flat_map(|repo| repo.hooks.iter_mut()) {
+        hook.policy = Some(location.to_string());
+    }
+    Ok(policy)
+}
+
+/// Merge the hooks of a policy into a config
+///
+/// Policy hooks replace the config's hooks with the same id, so a repository
+/// can't weaken a mandatory hook by redefining it. The policy's
+/// `blocked_message` likewise replaces the config's.
+pub fn merge_policy(config: &Config, policy: &Config) -> Config {
+    let policy_ids: HashSet<&str> = policy.repos.iter().flat_map(|repo| repo.hooks.iter().map(|hook| hook.id.as_str())).collect();
+
+    let mut merged = config.clone();
+    for repo in &mut merged.repos {
+        repo.hooks.retain(|hook| {
+            let replaced = policy_ids.contains(hook.id.as_str());
+            if replaced {
+                log::debug!("Hook {} is replaced by the policy's hook", hook.id);
+            }
+            !replaced
+        });
+    }
+    merged.repos.extend(policy.repos.iter().cloned());
+    if policy.blocked_message.is_some() {
+        merged.blocked_message = policy.blocked_message.clone();
+    }
+    merged
+}
+
+/// Merge the configured policy, if any, into a config
+pub fn apply_policy(config: &Config, cache_root: &Path) -> Result<Config, ConfigError> {
+    match policy_location()? {
+        Some(location) => {
+            log::debug!("Enforcing the policy {}", location);
+            Ok(merge_policy(config, &load_policy(&location, cache_root)?))
+        }
+        None => Ok(config.clone()),
+    }
+}
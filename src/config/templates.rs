@@ -0,0 +1,180 @@
+//! Starter config templates
+//!
+//! `rustyhook init --template` writes a config from a template instead of the
+//! default starter config. A template is a built-in name, a path, or an
+//! `https://` URL of a template an organization hosts. Remote templates must
+//! pin their SHA-256 checksum in the URL fragment
+//! (`https://example.com/python.yaml#sha256=<hex>`), so a changed template is
+//! never used without review.
+//!
+//! Templates are configs with `{{ name }}` placeholders. A placeholder can
+//! give a default, `{{ name | default }}`, used when the variable isn't set.
+//! `project_name` is the name of the current directory, and `--var
+//! name=value` sets or overrides variables. A placeholder without a value or
+//! default is an error.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::converter::ConversionError;
+use super::parser::{Config, ConfigError};
+
+/// Templates built into the binary, by name
+pub const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("python-service", include_str!("templates/python-service.yaml")),
+    ("node-app", include_str!("templates/node-app.yaml")),
+    ("rust-crate", include_str!("templates/rust-crate.yaml")),
+    ("terraform-module", include_str!("templates/terraform-module.yaml")),
+];
+
+/// Where a template comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateSource {
+    /// A template built into the binary
+    BuiltIn(&'static str),
+    /// A template file
+    Path(PathBuf),
+    /// A template fetched over HTTPS
+    Url {
+        /// URL of the template, without the fragment
+        url: String,
+        /// SHA-256 checksum pinned in the fragment
+        sha256: Option<String>,
+    },
+}
+
+/// Create the error of a template
+fn template_error(message: String) -> ConversionError {
+    ConversionError::ConfigError(ConfigError::ValidationError(message))
+}
+
+impl TemplateSource {
+    /// Find where a template given to `--template` comes from
+    pub fn parse(template: &str) -> Result<Self, ConversionError> {
+        if let Some((name, _)) = BUILTIN_TEMPLATES.iter().find(|(name, _)| *name == template) {
+            return Ok(TemplateSource::BuiltIn(name));
+        }
+        if template.starts_with("http://") {
+            return Err(template_error(format!("Template {} must be fetched over https://", template)));
+        }
+        if template.starts_with("https://") {
+            let (url, fragment) = template.split_once('#').unwrap_or((template, ""));
+            let sha256 = fragment.strip_prefix("sha256=").map(|hex| hex.to_ascii_lowercase());
+            if !fragment.is_empty() && sha256.is_none() {
+                return Err(template_error(format!("Template {} has an unknown fragment, expected #sha256=<checksum>", template)));
+            }
+            return Ok(TemplateSource::Url { url: url.to_string(), sha256 });
+        }
+        let path = PathBuf::from(template);
+        if path.is_file() {
+            return Ok(TemplateSource::Path(path));
+        }
+        let names: Vec<&str> = BUILTIN_TEMPLATES.iter().map(|(name, _)| *name).collect();
+        Err(template_error(format!(
+            "Unknown template '{}': not a file, an https:// URL or a built-in template ({})",
+            template,
+            names.join(", ")
+        )))
+    }
+
+    /// Read the template
+    pub fn load(&self) -> Result<String, ConversionError> {
+        match self {
+            TemplateSource::BuiltIn(name) => Ok(BUILTIN_TEMPLATES.iter().find(|(builtin, _)| builtin == name).map(|(_, template)| template.to_string()).unwrap_or_default()),
+            TemplateSource::Path(path) => Ok(fs::read_to_string(path)?),
+            TemplateSource::Url { url, sha256 } => {
+                let content = fetch(url).map_err(|e| template_error(format!("Could not fetch the template {}: {}", url, e)))?;
+                let actual: String = Sha256::digest(content.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect();
+                match sha256 {
+                    None => Err(template_error(format!("Remote templates must pin their checksum. After reviewing the template, use {}#sha256={}", url, actual))),
+                    Some(expected) if *expected != actual => Err(template_error(format!(
+                        "The template {} changed: its checksum is {}, but {} is pinned",
+                        url, actual, expected
+                    ))),
+                    Some(_) => Ok(content),
+                }
+            }
+        }
+    }
+}
+
+/// Fetch a template from a URL
+fn fetch(url: &str) -> Result<String, String> {
+    if crate::toolchains::network::is_forbidden() {
+        return Err("network access is forbidden by --forbid-network".to_string());
+    }
+    let response = reqwest::blocking::get(url).map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("HTTP {}", response.status()));
+    }
+    response.text().map_err(|e| e.to_string())
+}
+
+/// Parse a `name=value` variable given to `--var`
+pub fn parse_var(var: &str) -> Result<(String, String), String> {
+    match var.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => Ok((name.trim().to_string(), value.to_string())),
+        _ => Err(format!("expected name=value, got '{}'", var)),
+    }
+}
+
+/// Fill in the placeholders of a template
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find("}}") else {
+            return Err(format!("Unclosed placeholder: {}", rest[start..].lines().next().unwrap_or_default()));
+        };
+        let placeholder = &rest[start + 2..start + end];
+        let (name, default) = match placeholder.split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default.trim())),
+            None => (placeholder.trim(), None),
+        };
+        match vars.get(name).map(String::as_str).or(default) {
+            Some(value) => rendered.push_str(value),
+            None => return Err(format!("No value for {{{{ {} }}}}, set it with --var {}=<value>", name, name)),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Create a config from a template and write it to a file
+///
+/// Without an output path, it is written to `.rustyhook/config.yaml` in the
+/// current directory. The rendered config must be valid, or nothing is
+/// written. Returns the path written to.
+pub fn create_config_from_template<P: AsRef<Path>>(
+    template: &str,
+    vars: &[(String, String)],
+    output_path: Option<P>,
+) -> Result<PathBuf, ConversionError> {
+    let cwd = std::env::current_dir()?;
+    let mut values = HashMap::new();
+    if let Some(name) = cwd.file_name() {
+        values.insert("project_name".to_string(), name.to_string_lossy().into_owned());
+    }
+    values.extend(vars.iter().cloned());
+
+    let source = TemplateSource::parse(template)?;
+    let rendered = render(&source.load()?, &values).map_err(|e| template_error(format!("Error in template {}: {}", template, e)))?;
+    let config: Config = serde_yaml::from_str(&rendered)?;
+    super::min_version::check_minimum_versions(&config)?;
+
+    let output_path = match output_path {
+        Some(path) => path.as_ref().to_path_buf(),
+        None => {
+            let dir = cwd.join(".rustyhook");
+            fs::create_dir_all(&dir)?;
+            dir.join("config.yaml")
+        }
+    };
+    fs::write(&output_path, rendered)?;
+    Ok(output_path)
+}
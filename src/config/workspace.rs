@@ -0,0 +1,294 @@
+//! Workspace discovery for RustyHook
+//!
+//! Monorepos declare their member packages in the workspace manifest of their
+//! ecosystem. This module reads those declarations so `rustyhook init` can
+//! list the projects of a repository in the config, and hooks with
+//! `per_project` can be scoped to each of them. Supported workspaces:
+//!
+//! - Cargo: `[workspace] members` and `exclude` in `Cargo.toml`
+//! - pnpm: `packages` in `pnpm-workspace.yaml`
+//! - npm and Yarn: `workspaces` in `package.json`
+//! - uv: `[tool.uv.workspace] members` and `exclude` in `pyproject.toml`
+//! - Poetry: path dependencies in `pyproject.toml`
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::matrix::match_matrix_values;
+use super::parser::ConfigError;
+
+/// Value of `per_project` that selects projects of every kind
+pub const ALL_PROJECTS: &str = "all";
+
+/// Ecosystem of a project
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+    /// A Cargo package
+    Cargo,
+    /// A Node package
+    Node,
+    /// A Python project
+    Python,
+}
+
+impl ProjectKind {
+    /// The manifest file every project of this kind has
+    pub fn manifest(&self) -> &'static str {
+        match self {
+            ProjectKind::Cargo => "Cargo.toml",
+            ProjectKind::Node => "package.json",
+            ProjectKind::Python => "pyproject.toml",
+        }
+    }
+}
+
+impl fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectKind::Cargo => write!(f, "cargo"),
+            ProjectKind::Node => write!(f, "node"),
+            ProjectKind::Python => write!(f, "python"),
+        }
+    }
+}
+
+/// A member package of a workspace
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Project {
+    /// Name of the package, or its directory name if the manifest has none
+    pub name: String,
+    /// Directory of the package, relative to the repository root with forward slashes
+    pub path: String,
+    /// Ecosystem of the package
+    pub kind: ProjectKind,
+}
+
+impl Project {
+    /// Check whether a `per_project` value selects this project
+    pub fn is_selected_by(&self, selector: &str) -> bool {
+        selector == ALL_PROJECTS || selector == self.kind.to_string()
+    }
+}
+
+/// Get the lines of a TOML table, without its header
+fn toml_table(content: &str, table: &str) -> Option<String> {
+    let header = format!("[{}]", table);
+    let mut lines = content.lines().skip_while(|line| line.trim() != header);
+    lines.next()?;
+    let body: Vec<&str> = lines.take_while(|line| !line.trim_start().starts_with('[')).collect();
+    Some(body.join("\n"))
+}
+
+/// Get a string value of a TOML table
+fn toml_string(table: &str, key: &str) -> Option<String> {
+    let pattern = format!(r#"(?m)^\s*{}\s*=\s*["']([^"']*)["']"#, regex::escape(key));
+    let re = Regex::new(&pattern).ok()?;
+    re.captures(table).map(|captures| captures[1].to_string())
+}
+
+/// Get a string array of a TOML table, which may span several lines
+fn toml_string_array(table: &str, key: &str) -> Vec<String> {
+    let pattern = format!(r#"(?ms)^\s*{}\s*=\s*\[(.*?)\]"#, regex::escape(key));
+    let Ok(re) = Regex::new(&pattern) else { return Vec::new() };
+    let Some(captures) = re.captures(table) else { return Vec::new() };
+    let Ok(item) = Regex::new(r#""([^"]*)"|'([^']*)'"#) else { return Vec::new() };
+
+    // Drop comments, which may contain quotes
+    let items: String = captures[1]
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect::<Vec<_>>()
+        .join("\n");
+    item.captures_iter(&items)
+        .filter_map(|captures| captures.get(1).or_else(|| captures.get(2)))
+        .map(|value| value.as_str().to_string())
+        .collect()
+}
+
+/// Get the package name in a member's manifest
+fn package_name(root: &Path, path: &str, kind: ProjectKind) -> Option<String> {
+    let content = fs::read_to_string(root.join(path).join(kind.manifest())).ok()?;
+    match kind {
+        ProjectKind::Cargo => toml_table(&content, "package").and_then(|table| toml_string(&table, "name")),
+        ProjectKind::Node => serde_json::from_str::<serde_json::Value>(&content)
+            .ok()?
+            .get("name")?
+            .as_str()
+            .map(String::from),
+        ProjectKind::Python => toml_table(&content, "project")
+            .and_then(|table| toml_string(&table, "name"))
+            .or_else(|| toml_table(&content, "tool.poetry").and_then(|table| toml_string(&table, "name"))),
+    }
+}
+
+/// Normalize a member path to forward slashes without `./` or a trailing slash
+fn normalize_member(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let path = path.trim_start_matches("./").trim_end_matches('/');
+    path.to_string()
+}
+
+/// Expand member globs to the directories that have the manifest of `kind`
+///
+/// Globs prefixed with `!` and the `excludes` remove directories again.
+fn expand_members(root: &Path, kind: ProjectKind, members: &[String], excludes: &[String]) -> Result<Vec<String>, ConfigError> {
+    let mut included = Vec::new();
+    let mut excluded = Vec::new();
+    for member in members {
+        match member.strip_prefix('!') {
+            Some(pattern) => excluded.extend(match_matrix_values(root, &normalize_member(pattern))?),
+            None => included.extend(match_matrix_values(root, &normalize_member(member))?),
+        }
+    }
+    for pattern in excludes {
+        excluded.extend(match_matrix_values(root, &normalize_member(pattern))?);
+    }
+
+    Ok(included
+        .into_iter()
+        .filter(|path| !excluded.contains(path))
+        .filter(|path| root.join(path).join(kind.manifest()).is_file())
+        .collect())
+}
+
+/// Get the members of a Cargo workspace
+fn cargo_members(root: &Path) -> Result<Vec<String>, ConfigError> {
+    let Ok(content) = fs::read_to_string(root.join("Cargo.toml")) else { return Ok(Vec::new()) };
+    let Some(workspace) = toml_table(&content, "workspace") else { return Ok(Vec::new()) };
+    expand_members(
+        root,
+        ProjectKind::Cargo,
+        &toml_string_array(&workspace, "members"),
+        &toml_string_array(&workspace, "exclude"),
+    )
+}
+
+/// Get the members of a pnpm, npm or Yarn workspace
+fn node_members(root: &Path) -> Result<Vec<String>, ConfigError> {
+    let mut members = Vec::new();
+
+    if let Ok(content) = fs::read_to_string(root.join("pnpm-workspace.yaml")) {
+        let workspace: serde_yaml::Value = serde_yaml::from_str(&content)?;
+        if let Some(packages) = workspace.get("packages").and_then(|packages| packages.as_sequence()) {
+            members.extend(packages.iter().filter_map(|package| package.as_str()).map(String::from));
+        }
+    }
+
+    if let Ok(content) = fs::read_to_string(root.join("package.json")) {
+        let package: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| ConfigError::ValidationError(format!("Invalid package.json: {}", e)))?;
+        // Yarn also accepts `{ "packages": [...] }`
+        let workspaces = package
+            .get("workspaces")
+            .map(|workspaces| workspaces.get("packages").unwrap_or(workspaces));
+        if let Some(workspaces) = workspaces.and_then(|workspaces| workspaces.as_array()) {
+            members.extend(workspaces.iter().filter_map(|member| member.as_str()).map(String::from));
+        }
+    }
+
+    expand_members(root, ProjectKind::Node, &members, &[])
+}
+
+/// Get the members of a uv workspace and the path dependencies of a Poetry project
+fn python_members(root: &Path) -> Result<Vec<String>, ConfigError> {
+    let Ok(content) = fs::read_to_string(root.join("pyproject.toml")) else { return Ok(Vec::new()) };
+    let mut members = Vec::new();
+    let mut excludes = Vec::new();
+
+    if let Some(workspace) = toml_table(&content, "tool.uv.workspace") {
+        members.extend(toml_string_array(&workspace, "members"));
+        excludes.extend(toml_string_array(&workspace, "exclude"));
+    }
+
+    // Poetry has no workspaces, monorepos use path dependencies instead
+    let Ok(header) = Regex::new(r"^tool\.poetry(\.group\.[^.]+)?\.(dev-)?dependencies$") else { return Ok(Vec::new()) };
+    let Ok(path) = Regex::new(r#"path\s*=\s*["']([^"']*)["']"#) else { return Ok(Vec::new()) };
+    let tables: Vec<&str> = content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('[').and_then(|line| line.strip_suffix(']')))
+        .filter(|table| header.is_match(table))
+        .collect();
+    for table in tables {
+        if let Some(body) = toml_table(&content, table) {
+            members.extend(path.captures_iter(&body).map(|captures| captures[1].to_string()));
+        }
+    }
+
+    expand_members(root, ProjectKind::Python, &members, &excludes)
+}
+
+/// Find the member packages of every workspace at the repository root
+///
+/// Projects are sorted by path, and by kind for directories that belong to
+/// several workspaces. The root package itself is never a project.
+pub fn discover_projects(root: &Path) -> Result<Vec<Project>, ConfigError> {
+    let mut projects = BTreeMap::new();
+    for (kind, members) in [
+        (ProjectKind::Cargo, cargo_members(root)?),
+        (ProjectKind::Node, node_members(root)?),
+        (ProjectKind::Python, python_members(root)?),
+    ] {
+        for path in members.into_iter().filter(|path| !path.is_empty() && path != ".") {
+            let name = package_name(root, &path, kind)
+                .unwrap_or_else(|| path.rsplit('/').next().unwrap_or(&path).to_string());
+            projects.insert((path.clone(), kind), Project { name, path, kind });
+        }
+    }
+    Ok(projects.into_values().collect())
+}
+
+/// Replace the top-level `projects:` block of a config file's content
+///
+/// The rest of the file, comments included, is kept as it is. The block is
+/// appended when the config has none, and removed when there are no projects.
+pub fn replace_projects_block(content: &str, projects: &[Project]) -> Result<String, ConfigError> {
+    #[derive(Serialize)]
+    struct Block<'a> {
+        projects: &'a [Project],
+    }
+    let block = if projects.is_empty() { String::new() } else { serde_yaml::to_string(&Block { projects })? };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let Some(start) = lines.iter().position(|line| line.starts_with("projects:")) else {
+        let mut updated = content.to_string();
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(&block);
+        return Ok(updated);
+    };
+
+    // The block ends at the next top-level key or comment
+    let end = lines[start + 1..]
+        .iter()
+        .position(|line| !(line.is_empty() || line.starts_with(' ') || line.starts_with('\t') || line.starts_with('-')))
+        .map_or(lines.len(), |offset| start + 1 + offset);
+
+    let mut updated = String::new();
+    for line in &lines[..start] {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    updated.push_str(&block);
+    for line in &lines[end..] {
+        updated.push_str(line);
+        updated.push('\n');
+    }
+    Ok(updated)
+}
+
+/// Rediscover the projects of a repository and update them in a config file
+///
+/// Returns the projects now listed in the config.
+pub fn refresh_projects(config_path: &Path, root: &Path) -> Result<Vec<Project>, ConfigError> {
+    let projects = discover_projects(root)?;
+    let content = fs::read_to_string(config_path)?;
+    fs::write(config_path, replace_projects_block(&content, &projects)?)?;
+    Ok(projects)
+}
@@ -0,0 +1,295 @@
+//! Stable error codes for RustyHook
+//!
+//! Each failure of the error hierarchy (config, toolchain, hook and run
+//! errors, wrapped by [`HookResolverError`] and [`ParallelExecutionError`])
+//! maps to an [`ErrorCode`] like `RH1001`. Machine-readable output, such as
+//! the metrics file of `rustyhook run --metrics-file`, reports failures as an
+//! [`ErrorInfo`] carrying the code and structured fields, so wrappers and
+//! bots can branch on the kind of failure instead of matching messages.
+//!
+//! Codes are grouped by the thousands digit:
+//!
+//! - `RH1xxx`: configuration
+//! - `RH2xxx`: toolchains and environments
+//! - `RH3xxx`: hooks
+//! - `RH4xxx`: the run as a whole
+//!
+//! Codes are never reused or renumbered. New failures get new codes.
+//!
+//! [`HookResolverError`]: crate::runner::HookResolverError
+//! [`ParallelExecutionError`]: crate::runner::ParallelExecutionError
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use serde::{Serialize, Serializer};
+
+use crate::config::ConfigError;
+use crate::hooks::HookError;
+use crate::messages::MessageId;
+use crate::runner::{HookResolverError, ParallelExecutionError};
+use crate::toolchains::ToolError;
+
+/// Stable code of a kind of failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The config isn't valid YAML or doesn't match the schema
+    ConfigParse,
+    /// The config is well-formed but invalid
+    ConfigInvalid,
+    /// The config couldn't be read
+    ConfigUnreadable,
+    /// A tool isn't installed
+    ToolNotFound,
+    /// A tool couldn't be downloaded or installed
+    ToolInstallFailed,
+    /// A file operation of a tool failed
+    ToolIo,
+    /// A hook's environment failed its preflight command, even after a rebuild
+    ProvisioningFailed,
+    /// A native hook failed
+    HookFailed,
+    /// A hook's command failed
+    ProcessFailed,
+    /// A file operation of a native hook failed
+    HookIo,
+    /// A native hook read a file that isn't UTF-8
+    HookEncoding,
+    /// A hook isn't defined
+    HookNotFound,
+    /// A hook uses an unknown language
+    UnsupportedLanguage,
+    /// A hook's command wasn't found
+    CommandNotFound,
+    /// A hook's file pattern is invalid
+    InvalidFilePattern,
+    /// Another file operation failed
+    Io,
+    /// A hook requiring a clean working tree has files with unstaged changes
+    UncleanFiles,
+    /// Commands the hooks need are missing
+    MissingCommands,
+    /// A hook task panicked or was cancelled
+    TaskFailed,
+}
+
+impl ErrorCode {
+    /// Every error code, with its stable code and the catalog message describing it
+    ///
+    /// Failures without a message of their own, like config errors, have none.
+    const TABLE: &'static [(ErrorCode, &'static str, Option<MessageId>)] = &[
+        (ErrorCode::ConfigParse, "RH1001", None),
+        (ErrorCode::ConfigInvalid, "RH1002", None),
+        (ErrorCode::ConfigUnreadable, "RH1003", None),
+        (ErrorCode::ToolNotFound, "RH2001", Some(MessageId::ToolError)),
+        (ErrorCode::ToolInstallFailed, "RH2002", Some(MessageId::ToolError)),
+        (ErrorCode::ToolIo, "RH2003", Some(MessageId::ToolError)),
+        (ErrorCode::ProvisioningFailed, "RH2004", Some(MessageId::ProvisioningError)),
+        (ErrorCode::HookFailed, "RH3001", Some(MessageId::HookError)),
+        (ErrorCode::ProcessFailed, "RH3002", Some(MessageId::ProcessError)),
+        (ErrorCode::HookIo, "RH3003", Some(MessageId::HookError)),
+        (ErrorCode::HookEncoding, "RH3004", Some(MessageId::HookError)),
+        (ErrorCode::HookNotFound, "RH3005", Some(MessageId::HookNotFound)),
+        (ErrorCode::UnsupportedLanguage, "RH3006", Some(MessageId::UnsupportedLanguage)),
+        (ErrorCode::CommandNotFound, "RH3007", Some(MessageId::FileNotFound)),
+        (ErrorCode::InvalidFilePattern, "RH3008", Some(MessageId::FileMatcherError)),
+        (ErrorCode::Io, "RH3009", Some(MessageId::IoError)),
+        (ErrorCode::UncleanFiles, "RH3010", Some(MessageId::UncleanFiles)),
+        (ErrorCode::MissingCommands, "RH4001", None),
+        (ErrorCode::TaskFailed, "RH4002", None),
+    ];
+
+    /// Get every error code
+    pub fn all() -> impl Iterator<Item = ErrorCode> {
+        Self::TABLE.iter().map(|(code, _, _)| *code)
+    }
+
+    /// Get the row of the code in the table
+    fn row(&self) -> &'static (ErrorCode, &'static str, Option<MessageId>) {
+        Self::TABLE.iter().find(|(code, _, _)| code == self).expect("every error code is in the table")
+    }
+
+    /// Get the stable code, like `RH1001`
+    pub fn code(&self) -> &'static str {
+        self.row().1
+    }
+
+    /// Get the catalog message describing failures of the code, if it has one
+    pub fn message_id(&self) -> Option<MessageId> {
+        self.row().2
+    }
+
+    /// Get the category of the code: `config`, `toolchain`, `hook` or `run`
+    pub fn category(&self) -> &'static str {
+        match self.code().as_bytes()[2] {
+            b'1' => "config",
+            b'2' => "toolchain",
+            b'3' => "hook",
+            _ => "run",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+/// A failure, for machine-readable output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorInfo {
+    /// Code of the failure
+    pub code: ErrorCode,
+    /// Category of the code
+    pub category: &'static str,
+    /// The message, in the current locale
+    pub message: String,
+    /// Structured details, like the hook or path involved
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<&'static str, String>,
+}
+
+/// An error with a stable code
+pub trait HasErrorCode: fmt::Debug {
+    /// Get the code of the error
+    fn error_code(&self) -> ErrorCode;
+
+    /// Get the message of the error
+    fn error_message(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    /// Get the structured details of the error
+    fn error_fields(&self) -> BTreeMap<&'static str, String> {
+        BTreeMap::new()
+    }
+
+    /// Describe the error for machine-readable output
+    fn error_info(&self) -> ErrorInfo {
+        let code = self.error_code();
+        ErrorInfo { code, category: code.category(), message: self.error_message(), fields: self.error_fields() }
+    }
+}
+
+impl HasErrorCode for ConfigError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            ConfigError::IoError(_) => ErrorCode::ConfigUnreadable,
+            ConfigError::ParseError(_) => ErrorCode::ConfigParse,
+            ConfigError::ValidationError(_) => ErrorCode::ConfigInvalid,
+        }
+    }
+
+    fn error_fields(&self) -> BTreeMap<&'static str, String> {
+        let mut fields = BTreeMap::new();
+        if let ConfigError::ParseError(err) = self {
+            if let Some(location) = err.location() {
+                fields.insert("line", location.line().to_string());
+                fields.insert("column", location.column().to_string());
+            }
+        }
+        fields
+    }
+}
+
+impl HasErrorCode for ToolError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            ToolError::ExecutionError(_) => ErrorCode::ProcessFailed,
+            ToolError::ToolNotFound(_) => ErrorCode::ToolNotFound,
+            ToolError::InstallationError(_) => ErrorCode::ToolInstallFailed,
+            ToolError::IoError(_) => ErrorCode::ToolIo,
+        }
+    }
+}
+
+impl HasErrorCode for HookError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            HookError::IoError(_) => ErrorCode::HookIo,
+            HookError::Utf8Error(_) => ErrorCode::HookEncoding,
+            HookError::Other(_) => ErrorCode::HookFailed,
+        }
+    }
+}
+
+impl HasErrorCode for HookResolverError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            HookResolverError::FileMatcherError(_) => ErrorCode::InvalidFilePattern,
+            HookResolverError::ToolError(err) => err.error_code(),
+            HookResolverError::HookError(err) => err.error_code(),
+            HookResolverError::HookNotFound(_) => ErrorCode::HookNotFound,
+            HookResolverError::UnsupportedLanguage(_) => ErrorCode::UnsupportedLanguage,
+            HookResolverError::ProcessError(_) => ErrorCode::ProcessFailed,
+            HookResolverError::IoError(_) => ErrorCode::Io,
+            HookResolverError::FileNotFound { .. } => ErrorCode::CommandNotFound,
+            HookResolverError::ProvisioningError { .. } => ErrorCode::ProvisioningFailed,
+            HookResolverError::UncleanFiles { .. } => ErrorCode::UncleanFiles,
+        }
+    }
+
+    fn error_message(&self) -> String {
+        self.to_string()
+    }
+
+    fn error_fields(&self) -> BTreeMap<&'static str, String> {
+        let mut fields = BTreeMap::new();
+        match self {
+            HookResolverError::HookNotFound(hook_id) => {
+                fields.insert("hook", hook_id.clone());
+            }
+            HookResolverError::UnsupportedLanguage(language) => {
+                fields.insert("language", language.clone());
+            }
+            HookResolverError::FileNotFound { path, .. } => {
+                fields.insert("path", path.display().to_string());
+            }
+            HookResolverError::ProvisioningError { hook_id, command, .. } => {
+                fields.insert("hook", hook_id.clone());
+                fields.insert("command", command.clone());
+            }
+            HookResolverError::UncleanFiles { hook_id, files } => {
+                fields.insert("hook", hook_id.clone());
+                fields.insert("files", files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>().join("\n"));
+            }
+            HookResolverError::IoError(err) | HookResolverError::ToolError(ToolError::IoError(err)) => {
+                fields.insert("io_kind", format!("{:?}", err.kind()));
+            }
+            _ => {}
+        }
+        fields
+    }
+}
+
+impl HasErrorCode for ParallelExecutionError {
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            ParallelExecutionError::HookResolverError(err) => err.error_code(),
+            ParallelExecutionError::TokioError(_) => ErrorCode::TaskFailed,
+            ParallelExecutionError::MissingCommands(_) => ErrorCode::MissingCommands,
+        }
+    }
+
+    fn error_message(&self) -> String {
+        self.to_string()
+    }
+
+    fn error_fields(&self) -> BTreeMap<&'static str, String> {
+        match self {
+            ParallelExecutionError::HookResolverError(err) => err.error_fields(),
+            ParallelExecutionError::MissingCommands(missing) => {
+                let commands: Vec<&str> = missing.iter().map(|missing| missing.command.as_str()).collect();
+                BTreeMap::from([("commands", commands.join(","))])
+            }
+            ParallelExecutionError::TokioError(_) => BTreeMap::new(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Implementation of the branch-name-lint hook
+
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::hooks::common::{Hook, HookError};
+
+/// Branch names allowed by default
+const DEFAULT_PATTERN: &str = r"^(main|master|develop|(feature|feat|fix|bugfix|hotfix|release|chore|docs|refactor|test|ci)/[a-z0-9._-]+)$";
+
+/// Check that the current branch name matches a policy
+///
+/// The hook is meant for the pre-push stage. The files passed to it are
+/// ignored; the branch is read from the repository in the working directory.
+/// A detached HEAD is always accepted.
+///
+/// Arguments:
+/// - `--pattern=REGEX`: regex branch names must match
+/// - `--allow=NAME`: branch exempt from the pattern (repeatable)
+pub struct BranchNameLint {
+    /// Regex branch names must match
+    pattern: Regex,
+    /// Branches exempt from the pattern
+    allowed: Vec<String>,
+}
+
+impl Default for BranchNameLint {
+    fn default() -> Self {
+        BranchNameLint {
+            pattern: Regex::new(DEFAULT_PATTERN).expect("default branch pattern is valid"),
+            allowed: Vec::new(),
+        }
+    }
+}
+
+impl BranchNameLint {
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut hook = BranchNameLint::default();
+        for arg in args {
+            if let Some(pattern) = arg.strip_prefix("--pattern=") {
+                hook.pattern = Regex::new(pattern)
+                    .map_err(|e| HookError::Other(format!("Invalid branch pattern '{}': {}", pattern, e)))?;
+            } else if let Some(name) = arg.strip_prefix("--allow=") {
+                hook.allowed.push(name.to_string());
+            } else {
+                return Err(HookError::Other(format!("Unknown branch-name-lint argument: {}", arg)));
+            }
+        }
+        Ok(hook)
+    }
+
+    /// Check a branch name against the policy
+    pub fn check_branch(&self, branch: &str) -> Result<(), HookError> {
+        if self.allowed.iter().any(|allowed| allowed == branch) || self.pattern.is_match(branch) {
+            return Ok(());
+        }
+        Err(HookError::Other(format!(
+            "Branch name '{}' does not match the pattern {}",
+            branch,
+            self.pattern.as_str()
+        )))
+    }
+
+    /// Get the name of the current branch, or None for a detached HEAD
+    fn current_branch() -> Result<Option<String>, HookError> {
+        let repo = git2::Repository::discover(".")
+            .map_err(|e| HookError::Other(format!("Not in a Git repository: {}", e)))?;
+        let head = match repo.head() {
+            Ok(head) => head,
+            // An unborn branch has no commits yet, so read its name from HEAD itself
+            Err(_) => {
+                let head = repo.find_reference("HEAD").map_err(|e| HookError::Other(e.to_string()))?;
+                return Ok(head
+                    .symbolic_target()
+                    .map(|target| target.trim_start_matches("refs/heads/").to_string()));
+            }
+        };
+
+        if head.is_branch() {
+            Ok(head.shorthand().map(str::to_string))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Hook for BranchNameLint {
+    fn run(&self, _files: &[PathBuf]) -> Result<(), HookError> {
+        match Self::current_branch()? {
+            Some(branch) => self.check_branch(&branch),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,33 +1,75 @@
 //! Implementation of the check-case-conflict hook
 
-use std::path::PathBuf;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use crate::hooks::common::{Hook, HookError};
+use crate::runner::file_matcher::{repo_relative_path, to_slash_path};
 
 /// Check for files with names that would conflict on a case-insensitive filesystem
+///
+/// The files are compared with each other and with the paths tracked in the
+/// Git index, including the directories leading to them, so adding
+/// `docs/guide.md` conflicts with a tracked `Docs/` directory.
 pub struct CheckCaseConflict;
 
+/// Get a path and every directory leading to it, like `a`, `a/b` and `a/b/c.txt`
+fn with_parents(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/')
+        .map(move |(index, _)| &path[..index])
+        .filter(|parent| !parent.is_empty())
+        .chain(std::iter::once(path))
+}
+
+impl CheckCaseConflict {
+    /// Get the root and the tracked paths of the repository containing a file
+    fn tracked_paths(file: &Path) -> Option<(PathBuf, Vec<String>)> {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let repo = git2::Repository::discover(dir).ok()?;
+        let root = repo.workdir()?.canonicalize().ok()?;
+        let index = repo.index().ok()?;
+        let paths = index.iter().map(|entry| String::from_utf8_lossy(&entry.path).into_owned()).collect();
+        Some((root, paths))
+    }
+
+    /// Find the paths that differ only in case, where at least one of them is new
+    ///
+    /// Each conflict lists the paths involved, sorted.
+    fn conflicts(new: &[String], tracked: &[String]) -> Vec<Vec<String>> {
+        let new: BTreeSet<&str> = new.iter().flat_map(|path| with_parents(path)).collect();
+        let mut by_lowercase: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+        for path in new.iter().copied().chain(tracked.iter().flat_map(|path| with_parents(path))) {
+            by_lowercase.entry(path.to_lowercase()).or_default().insert(path);
+        }
+
+        by_lowercase
+            .into_values()
+            .filter(|paths| paths.len() > 1 && paths.iter().any(|path| new.contains(path)))
+            .map(|paths| paths.into_iter().map(String::from).collect())
+            .collect()
+    }
+}
+
 impl Hook for CheckCaseConflict {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
-        let mut lowercase_names = HashSet::new();
-        let mut conflicts = Vec::new();
-
         for file in files {
-            let filename = file.file_name()
-                .ok_or_else(|| HookError::Other(format!("Invalid file name: {}", file.display())))?
-                .to_string_lossy()
-                .to_lowercase();
-
-            if lowercase_names.contains(&filename) {
-                conflicts.push(file.clone());
-            } else {
-                lowercase_names.insert(filename);
-            }
+            file.file_name().ok_or_else(|| HookError::Other(format!("Invalid file name: {}", file.display())))?;
         }
+        let Some(first) = files.first() else { return Ok(()) };
+
+        // Compare repository-relative paths with the index, or the files with each other outside a repository
+        let (new, tracked): (Vec<String>, Vec<String>) = match Self::tracked_paths(first) {
+            Some((root, tracked)) => {
+                let cwd = std::env::current_dir()?;
+                let new = files.iter().map(|file| repo_relative_path(&cwd.join(file), &root)).collect();
+                (new, tracked)
+            }
+            None => (files.iter().map(|file| to_slash_path(file)).collect(), Vec::new()),
+        };
 
+        let conflicts = Self::conflicts(&new, &tracked);
         if !conflicts.is_empty() {
             let conflict_list = conflicts.iter()
-                .map(|f| f.display().to_string())
+                .map(|paths| paths.join(" and "))
                 .collect::<Vec<_>>()
                 .join(", ");
 
@@ -36,4 +78,4 @@ impl Hook for CheckCaseConflict {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,205 @@
+//! Implementation of the check-frontmatter hook
+
+use std::fs;
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde_yaml::Value;
+
+use crate::hooks::common::{Hook, HookError};
+use crate::hooks::text::read_text;
+
+/// Validate the YAML or JSON front matter of content files against a schema
+///
+/// Front matter is either YAML between `---` lines or a JSON object at the
+/// very start of the file, closed by a `}` line and followed by the body. Schemas are YAML or JSON files using a subset of
+/// JSON Schema: `required`, `properties` with `type`, `enum` and `pattern`,
+/// `items` for arrays, and `additionalProperties: false`.
+///
+/// Arguments:
+/// - `--schema=FILE`: schema to validate against (default: only check the front matter parses)
+/// - `--require`: fail for files without front matter
+pub struct CheckFrontmatter {
+    /// Schema to validate against
+    schema: Option<Value>,
+    /// Whether files must have front matter
+    require: bool,
+}
+
+impl CheckFrontmatter {
+    /// Create a new instance with an optional schema
+    pub fn new(schema: Option<Value>, require: bool) -> Self {
+        CheckFrontmatter { schema, require }
+    }
+
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut hook = CheckFrontmatter::new(None, false);
+        for arg in args {
+            if let Some(path) = arg.strip_prefix("--schema=") {
+                let content = fs::read_to_string(path)?;
+                let schema = serde_yaml::from_str(&content)
+                    .map_err(|e| HookError::Other(format!("Invalid front matter schema {}: {}", path, e)))?;
+                hook.schema = Some(schema);
+            } else if arg == "--require" {
+                hook.require = true;
+            } else {
+                return Err(HookError::Other(format!("Unknown check-frontmatter argument: {}", arg)));
+            }
+        }
+        Ok(hook)
+    }
+
+    /// Check the content of a file, returning the problems found
+    pub fn check_content(&self, content: &str) -> Vec<String> {
+        let front_matter = match extract_front_matter(content) {
+            Some(front_matter) => front_matter,
+            None if self.require => return vec!["missing front matter".to_string()],
+            None => return Vec::new(),
+        };
+
+        let value: Value = match serde_yaml::from_str(front_matter) {
+            Ok(value) => value,
+            Err(e) => return vec![format!("invalid front matter: {}", e)],
+        };
+
+        let mut problems = Vec::new();
+        if let Some(schema) = &self.schema {
+            validate(&value, schema, "front matter", &mut problems);
+        }
+        problems
+    }
+}
+
+/// Extract the front matter block from the start of a file
+fn extract_front_matter(content: &str) -> Option<&str> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    if let Some(rest) = content.strip_prefix("---\n").or_else(|| content.strip_prefix("---\r\n")) {
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            if line.trim_end() == "---" || line.trim_end() == "..." {
+                return Some(&rest[..offset]);
+            }
+            offset += line.len();
+        }
+        return None;
+    }
+
+    if content.starts_with('{') {
+        // A JSON object ends at the first line that closes it, and front
+        // matter has a body after it, unlike a JSON file
+        let mut offset = 0;
+        for line in content.split_inclusive('\n') {
+            offset += line.len();
+            if line.trim_end() == "}" {
+                return (!content[offset..].trim().is_empty()).then(|| &content[..offset]);
+            }
+        }
+    }
+
+    None
+}
+
+/// Get the name of a value's type as used by JSON Schema
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Sequence(_) => "array",
+        Value::Mapping(_) => "object",
+        Value::Tagged(_) => "tagged",
+    }
+}
+
+/// Check whether a value matches a schema type
+fn matches_type(value: &Value, expected: &str) -> bool {
+    let actual = type_name(value);
+    actual == expected || (expected == "number" && actual == "integer")
+}
+
+/// Validate a value against a schema, collecting problems
+fn validate(value: &Value, schema: &Value, path: &str, problems: &mut Vec<String>) {
+    if let Some(expected) = schema.get("type") {
+        let allowed: Vec<&str> = match expected {
+            Value::String(t) => vec![t.as_str()],
+            Value::Sequence(types) => types.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|t| matches_type(value, t)) {
+            problems.push(format!("{} should be {}, found {}", path, allowed.join(" or "), type_name(value)));
+            return;
+        }
+    }
+
+    if let Some(Value::Sequence(options)) = schema.get("enum") {
+        if !options.contains(value) {
+            let options: Vec<String> = options.iter().map(|o| serde_yaml::to_string(o).unwrap_or_default().trim().to_string()).collect();
+            problems.push(format!("{} should be one of: {}", path, options.join(", ")));
+        }
+    }
+
+    if let (Some(pattern), Some(text)) = (schema.get("pattern").and_then(Value::as_str), value.as_str()) {
+        match Regex::new(pattern) {
+            Ok(regex) if !regex.is_match(text) => problems.push(format!("{} does not match {}", path, pattern)),
+            Err(e) => problems.push(format!("invalid pattern {} in schema: {}", pattern, e)),
+            _ => {}
+        }
+    }
+
+    if let (Some(items), Value::Sequence(values)) = (schema.get("items"), value) {
+        for (index, item) in values.iter().enumerate() {
+            validate(item, items, &format!("{}[{}]", path, index), problems);
+        }
+    }
+
+    if let Value::Mapping(mapping) = value {
+        if let Some(Value::Sequence(required)) = schema.get("required") {
+            for key in required.iter().filter_map(Value::as_str) {
+                if !mapping.contains_key(key) {
+                    problems.push(format!("{} is missing required field '{}'", path, key));
+                }
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_mapping);
+        for (key, field) in mapping {
+            let name = key.as_str().unwrap_or_default();
+            match properties.and_then(|p| p.get(key)) {
+                Some(field_schema) => validate(field, field_schema, &format!("'{}'", name), problems),
+                None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                    problems.push(format!("{} has unexpected field '{}'", path, name));
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+impl Hook for CheckFrontmatter {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let mut reports = Vec::new();
+
+        for file in files {
+            let content = match read_text(file)? {
+                Ok(text) => text.content,
+                Err(err) => {
+                    reports.push(format!("{}: {}", file.display(), err));
+                    continue;
+                }
+            };
+            for problem in self.check_content(&content) {
+                reports.push(format!("{}: {}", file.display(), problem));
+            }
+        }
+
+        if !reports.is_empty() {
+            return Err(HookError::Other(format!("Front matter problems found:\n{}", reports.join("\n"))));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Implementation of the check-lockfile-sync hook family
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use crate::hooks::common::{Hook, HookError};
+use crate::runner::{ChangeKind, FileChange};
+
+/// A package ecosystem with a manifest and the lockfiles that pin it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ecosystem {
+    /// Cargo.toml and Cargo.lock
+    Cargo,
+    /// package.json and the npm, pnpm or yarn lockfile
+    Node,
+    /// pyproject.toml and the uv, poetry or pdm lockfile
+    Python,
+}
+
+impl Ecosystem {
+    /// Every supported ecosystem
+    pub const ALL: [Ecosystem; 3] = [Ecosystem::Cargo, Ecosystem::Node, Ecosystem::Python];
+
+    /// The manifest file name
+    fn manifest(&self) -> &'static str {
+        match self {
+            Ecosystem::Cargo => "Cargo.toml",
+            Ecosystem::Node => "package.json",
+            Ecosystem::Python => "pyproject.toml",
+        }
+    }
+
+    /// The lockfile names, any of which pins the manifest
+    fn lockfiles(&self) -> &'static [&'static str] {
+        match self {
+            Ecosystem::Cargo => &["Cargo.lock"],
+            Ecosystem::Node => &["package-lock.json", "pnpm-lock.yaml", "yarn.lock"],
+            Ecosystem::Python => &["uv.lock", "poetry.lock", "pdm.lock"],
+        }
+    }
+}
+
+/// Check that changed manifests come with a lockfile update in the same changeset
+///
+/// The changeset is the one of the run (see [`Hook::set_changes`]), so a
+/// manifest among the files is only checked when it changed. Without one, the
+/// files are taken as the changeset. The lockfile is looked up from the
+/// manifest's directory towards the repository root, so workspace members
+/// share their workspace's lockfile. Manifests without any lockfile are ignored.
+pub struct CheckLockfileSync {
+    /// The ecosystems to check
+    ecosystems: Vec<Ecosystem>,
+    /// The files changed in the changeset, when the run has one
+    changed: Option<HashSet<PathBuf>>,
+}
+
+impl CheckLockfileSync {
+    /// Create a new instance checking the given ecosystems
+    pub fn new(ecosystems: Vec<Ecosystem>) -> Self {
+        CheckLockfileSync { ecosystems, changed: None }
+    }
+
+    /// Find the lockfile pinning a manifest, searching up to the repository root
+    fn find_lockfile(manifest: &Path, ecosystem: Ecosystem) -> Option<PathBuf> {
+        let mut dir = manifest.parent();
+        while let Some(current) = dir {
+            for name in ecosystem.lockfiles() {
+                let lockfile = current.join(name);
+                if lockfile.exists() {
+                    return Some(lockfile);
+                }
+            }
+
+            // Don't look past the root of the repository
+            let root = if current.as_os_str().is_empty() { Path::new(".") } else { current };
+            if root.join(".git").exists() {
+                break;
+            }
+            dir = current.parent();
+        }
+        None
+    }
+}
+
+/// Normalize a path so files given in different forms compare equal
+fn normalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Hook for CheckLockfileSync {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let changed = match &self.changed {
+            Some(changed) => changed.clone(),
+            None => files.iter().map(|f| normalize(f)).collect(),
+        };
+        let mut out_of_sync = Vec::new();
+
+        for file in files {
+            if !changed.contains(&normalize(file)) {
+                continue;
+            }
+            let file_name = match file.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => continue,
+            };
+
+            for ecosystem in &self.ecosystems {
+                if file_name != ecosystem.manifest() {
+                    continue;
+                }
+
+                if let Some(lockfile) = Self::find_lockfile(file, *ecosystem) {
+                    if !changed.contains(&normalize(&lockfile)) {
+                        out_of_sync.push(format!("{} changed without {}", file.display(), lockfile.display()));
+                    }
+                }
+            }
+        }
+
+        if !out_of_sync.is_empty() {
+            return Err(HookError::Other(format!("Lockfiles out of sync with their manifests: {}", out_of_sync.join(", "))));
+        }
+
+        Ok(())
+    }
+
+    fn set_changes(&mut self, changes: &[FileChange]) {
+        let mut changed = HashSet::new();
+        for change in changes {
+            changed.insert(normalize(&change.path));
+            if let ChangeKind::Renamed { from } = &change.kind {
+                changed.insert(normalize(from));
+            }
+        }
+        self.changed = Some(changed);
+    }
+}
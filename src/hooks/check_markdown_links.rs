@@ -0,0 +1,166 @@
+//! Implementation of the check-markdown-links hook
+
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::hooks::common::{Hook, HookError};
+use crate::hooks::text::read_text;
+
+/// Lint Markdown files for broken relative links and common structure problems
+///
+/// Checks, outside of code blocks:
+/// - relative links and images point to existing files (`/` is the repository root)
+/// - headings don't skip levels (`#` followed by `###`)
+/// - table rows have no trailing whitespace
+///
+/// Arguments:
+/// - `--skip=CHECKS`: comma-separated checks to disable (`links`, `headings`, `tables`)
+pub struct CheckMarkdownLinks {
+    /// Whether to check relative links
+    links: bool,
+    /// Whether to check heading levels
+    headings: bool,
+    /// Whether to check table rows
+    tables: bool,
+}
+
+impl Default for CheckMarkdownLinks {
+    fn default() -> Self {
+        CheckMarkdownLinks { links: true, headings: true, tables: true }
+    }
+}
+
+impl CheckMarkdownLinks {
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut hook = CheckMarkdownLinks::default();
+        for arg in args {
+            let skipped = arg
+                .strip_prefix("--skip=")
+                .ok_or_else(|| HookError::Other(format!("Unknown check-markdown-links argument: {}", arg)))?;
+            for check in skipped.split(',') {
+                match check.trim() {
+                    "links" => hook.links = false,
+                    "headings" => hook.headings = false,
+                    "tables" => hook.tables = false,
+                    other => return Err(HookError::Other(format!("Unknown markdown check: {}", other))),
+                }
+            }
+        }
+        Ok(hook)
+    }
+
+    /// Check the content of a Markdown file, returning the problems found
+    ///
+    /// `dir` is the directory relative links are resolved against and `root`
+    /// the directory absolute (`/`) links are resolved against.
+    pub fn check_content(&self, content: &str, dir: &Path, root: &Path) -> Vec<String> {
+        let link_regex = Regex::new(r"!?\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+[^)]*)?\)").expect("link regex is valid");
+        let reference_regex = Regex::new(r"^\s{0,3}\[[^\]]+\]:\s*<?([^\s>]+)>?").expect("reference regex is valid");
+        let code_regex = Regex::new(r"`[^`]*`").expect("code regex is valid");
+
+        let mut problems = Vec::new();
+        let mut in_fence: Option<&str> = None;
+        let mut previous_level = 0;
+
+        for (index, line) in content.lines().enumerate() {
+            let number = index + 1;
+            let trimmed = line.trim_start();
+
+            // Skip fenced code blocks
+            let fence = if trimmed.starts_with("```") { Some("```") } else if trimmed.starts_with("~~~") { Some("~~~") } else { None };
+            match (in_fence, fence) {
+                (None, Some(marker)) => {
+                    in_fence = Some(marker);
+                    continue;
+                }
+                (Some(open), Some(marker)) if open == marker => {
+                    in_fence = None;
+                    continue;
+                }
+                (Some(_), _) => continue,
+                _ => {}
+            }
+
+            if self.headings {
+                let level = trimmed.chars().take_while(|c| *c == '#').count();
+                if (1..=6).contains(&level) && trimmed[level..].starts_with([' ', '\t']) {
+                    if previous_level > 0 && level > previous_level + 1 {
+                        problems.push(format!("line {}: heading level {} skips level {}", number, level, previous_level + 1));
+                    }
+                    previous_level = level;
+                }
+            }
+
+            if self.tables && trimmed.starts_with('|') && line.ends_with([' ', '\t']) {
+                problems.push(format!("line {}: trailing whitespace in table row", number));
+            }
+
+            if self.links {
+                let without_code = code_regex.replace_all(line, "");
+                let targets = link_regex
+                    .captures_iter(&without_code)
+                    .chain(reference_regex.captures_iter(&without_code))
+                    .map(|captures| captures[1].to_string())
+                    .collect::<Vec<_>>();
+
+                for target in targets {
+                    if let Some(path) = resolve_link(&target, dir, root) {
+                        if !path.exists() {
+                            problems.push(format!("line {}: broken link to {}", number, target));
+                        }
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// Resolve a link target to a local path, or None for external links and anchors
+fn resolve_link(target: &str, dir: &Path, root: &Path) -> Option<PathBuf> {
+    let is_external = target.contains("://") || target.starts_with("mailto:") || target.starts_with("tel:") || target.starts_with("//");
+    if is_external || target.starts_with('#') {
+        return None;
+    }
+
+    let path = target.split(['#', '?']).next().unwrap_or(target);
+    if path.is_empty() {
+        return None;
+    }
+    let path = path.replace("%20", " ");
+
+    Some(match path.strip_prefix('/') {
+        Some(absolute) => root.join(absolute),
+        None => dir.join(path),
+    })
+}
+
+impl Hook for CheckMarkdownLinks {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let root = std::env::current_dir()?;
+        let mut reports = Vec::new();
+
+        for file in files {
+            let content = match read_text(file)? {
+                Ok(text) => text.content,
+                Err(err) => {
+                    reports.push(format!("{}: {}", file.display(), err));
+                    continue;
+                }
+            };
+            let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+            for problem in self.check_content(&content, &dir, &root) {
+                reports.push(format!("{}: {}", file.display(), problem));
+            }
+        }
+
+        if !reports.is_empty() {
+            return Err(HookError::Other(format!("Markdown problems found:\n{}", reports.join("\n"))));
+        }
+
+        Ok(())
+    }
+}
@@ -1,25 +1,37 @@
 //! Implementation of the check-merge-conflict hook
 
-use std::path::PathBuf;
-use std::fs;
-use crate::hooks::common::{Hook, HookError};
+use std::path::{Path, PathBuf};
+use crate::hooks::common::{file_contains, Hook, HookError};
+use crate::hooks::text::{is_utf16_file, read_utf16};
+
+/// Merge conflict markers
+const MARKERS: [&str; 3] = ["<<<<<<<", "=======", ">>>>>>>"];
 
 /// Check for merge conflicts
+///
+/// Files are read in chunks, stopping at the first marker found. UTF-16
+/// files are decoded first, so their markers are found too.
 pub struct CheckMergeConflict;
 
+impl CheckMergeConflict {
+    /// Check whether a file has a merge conflict marker
+    fn has_markers(file: &Path) -> std::io::Result<bool> {
+        if let Some(text) = is_utf16_file(file)?.then(|| read_utf16(file)).transpose()?.flatten() {
+            return Ok(MARKERS.iter().any(|marker| text.content.contains(marker)));
+        }
+        file_contains(file, &MARKERS.map(str::as_bytes))
+    }
+}
+
 impl Hook for CheckMergeConflict {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
         for file in files {
-            // Read the file
-            let content = fs::read(file)?;
-            let content_str = String::from_utf8_lossy(&content);
-
             // Check for merge conflict markers
-            if content_str.contains("<<<<<<<") || content_str.contains("=======") || content_str.contains(">>>>>>>") {
+            if Self::has_markers(file)? {
                 return Err(HookError::Other(format!("Merge conflict markers found in {}", file.display())));
             }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,8 +1,8 @@
 //! Implementation of the check-toml hook
 
 use std::path::PathBuf;
-use std::fs;
 use crate::hooks::common::{Hook, HookError};
+use crate::hooks::text::read_text;
 
 /// Check TOML files for parseable syntax
 pub struct CheckToml;
@@ -11,8 +11,10 @@ impl Hook for CheckToml {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
         for file in files {
             // Read the file
-            let content = fs::read(file)?;
-            let content_str = String::from_utf8_lossy(&content);
+            let content_str = match read_text(file)? {
+                Ok(text) => text.content,
+                Err(err) => return Err(HookError::Other(format!("Invalid TOML in {}: {}", file.display(), err))),
+            };
 
             // This is a simple check that looks for basic TOML syntax errors
             // A more robust solution would use a proper TOML parser
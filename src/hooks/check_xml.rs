@@ -1,8 +1,8 @@
 //! Implementation of the check-xml hook
 
 use std::path::PathBuf;
-use std::fs;
 use crate::hooks::common::{Hook, HookError};
+use crate::hooks::text::read_text;
 
 /// Check XML files for parseable syntax
 pub struct CheckXml;
@@ -11,8 +11,10 @@ impl Hook for CheckXml {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
         for file in files {
             // Read the file
-            let content = fs::read(file)?;
-            let content_str = String::from_utf8_lossy(&content);
+            let content_str = match read_text(file)? {
+                Ok(text) => text.content,
+                Err(err) => return Err(HookError::Other(format!("Invalid XML in {}: {}", file.display(), err))),
+            };
 
             // Try to parse the XML
             // This is a simple check that looks for basic XML syntax errors
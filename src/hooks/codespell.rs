@@ -0,0 +1,281 @@
+//! Implementation of the codespell hook
+//!
+//! A native typo checker with an embedded misspelling dictionary, accepting
+//! codespell's most common arguments so existing configurations keep working.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::hooks::common::{rewrite_file, Hook, HookError};
+use crate::hooks::text::read_text;
+
+/// The embedded misspelling dictionary
+const BUILTIN_DICTIONARY: &str = include_str!("data/misspellings.txt");
+
+/// Ignore file read from the working directory when present
+const DEFAULT_IGNORE_FILE: &str = ".codespellignore";
+
+/// Check files for common misspellings
+///
+/// Arguments (codespell compatible):
+/// - `-L WORDS`/`--ignore-words-list=WORDS`: comma-separated words to ignore
+/// - `-I FILE`/`--ignore-words=FILE`: file with one word to ignore per line
+/// - `-D FILE`/`--dictionary=FILE`: extra dictionary, in codespell's format
+/// - `-w`/`--write-changes`: fix misspellings that have a single correction
+pub struct Codespell {
+    /// Misspellings and their corrections, keyed by lowercase word
+    dictionary: HashMap<String, Entry>,
+    /// Lowercase words that are never reported
+    ignored: HashSet<String>,
+    /// Whether to fix misspellings in place
+    write_changes: bool,
+}
+
+/// A dictionary entry
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    /// The suggested corrections
+    corrections: Vec<String>,
+    /// Why the misspelling isn't fixed automatically, when it isn't
+    reason: Option<String>,
+}
+
+/// A misspelling found in a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Misspelling {
+    /// Line number, starting at 1
+    pub line: usize,
+    /// The misspelled word as written
+    pub word: String,
+    /// The suggested corrections
+    pub corrections: Vec<String>,
+    /// Whether `--write-changes` fixes it
+    pub fixable: bool,
+    /// Why it isn't fixed automatically, when the dictionary says
+    pub reason: Option<String>,
+}
+
+impl fmt::Display for Misspelling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ==> {}", self.word, self.corrections.join(", "))?;
+        if let Some(reason) = &self.reason {
+            write!(f, " | {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Codespell {
+    fn default() -> Self {
+        let mut codespell = Codespell {
+            dictionary: HashMap::new(),
+            ignored: HashSet::new(),
+            write_changes: false,
+        };
+        codespell.add_dictionary(BUILTIN_DICTIONARY);
+        codespell
+    }
+}
+
+impl Codespell {
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut codespell = Codespell::default();
+
+        if Path::new(DEFAULT_IGNORE_FILE).exists() {
+            codespell.add_ignore_file(Path::new(DEFAULT_IGNORE_FILE))?;
+        }
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            // Support both `--flag=value` and `--flag value`
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| iter.next().cloned())
+                    .ok_or_else(|| HookError::Other(format!("Missing value for codespell argument {}", flag)))
+            };
+
+            match flag {
+                "-L" | "--ignore-words-list" => {
+                    for word in value()?.split(',') {
+                        codespell.ignore_word(word);
+                    }
+                }
+                "-I" | "--ignore-words" => codespell.add_ignore_file(Path::new(&value()?))?,
+                "-D" | "--dictionary" => {
+                    let path = value()?;
+                    // codespell uses "-" for its built-in dictionary
+                    if path != "-" {
+                        codespell.add_dictionary(&fs::read_to_string(path)?);
+                    }
+                }
+                "-w" | "--write-changes" => codespell.write_changes = true,
+                other => log::warn!("Ignoring unsupported codespell argument: {}", other),
+            }
+        }
+
+        Ok(codespell)
+    }
+
+    /// Add entries in codespell's dictionary format
+    ///
+    /// An entry is `misspelling->correction`, fixed by `--write-changes`, or
+    /// `misspelling->correction, correction, reason` with any number of
+    /// corrections, which is only reported. The reason after the last comma
+    /// may be empty.
+    pub fn add_dictionary(&mut self, content: &str) {
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((wrong, right)) = line.split_once("->") else { continue };
+            let entry = match right.rsplit_once(',') {
+                Some((corrections, reason)) => Entry {
+                    corrections: corrections.split(',').map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect(),
+                    reason: Some(reason.trim().to_string()),
+                },
+                None => Entry { corrections: vec![right.trim().to_string()], reason: None },
+            };
+            self.dictionary.insert(wrong.trim().to_lowercase(), entry);
+        }
+    }
+
+    /// Never report the given word
+    pub fn ignore_word(&mut self, word: &str) {
+        let word = word.trim();
+        if !word.is_empty() {
+            self.ignored.insert(word.to_lowercase());
+        }
+    }
+
+    /// Never report the words listed one per line in a file
+    pub fn add_ignore_file(&mut self, path: &Path) -> Result<(), HookError> {
+        for line in fs::read_to_string(path)?.lines() {
+            self.ignore_word(line);
+        }
+        Ok(())
+    }
+
+    /// Set whether to fix misspellings in place
+    pub fn with_write_changes(mut self, write_changes: bool) -> Self {
+        self.write_changes = write_changes;
+        self
+    }
+
+    /// Look up the entry of a word
+    fn entry(&self, word: &str) -> Option<&Entry> {
+        let lower = word.to_lowercase();
+        if self.ignored.contains(&lower) {
+            return None;
+        }
+        self.dictionary.get(&lower)
+    }
+
+    /// Find the misspellings in some text
+    pub fn check(&self, content: &str) -> Vec<Misspelling> {
+        let word_regex = word_regex();
+        let mut misspellings = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            for m in word_regex.find_iter(line) {
+                if let Some(entry) = self.entry(m.as_str()) {
+                    misspellings.push(Misspelling {
+                        line: index + 1,
+                        word: m.as_str().to_string(),
+                        corrections: entry.corrections.clone(),
+                        fixable: entry.reason.is_none(),
+                        reason: entry.reason.clone().filter(|reason| !reason.is_empty()),
+                    });
+                }
+            }
+        }
+        misspellings
+    }
+
+    /// Replace misspellings codespell would fix, keeping the word's case
+    pub fn fix(&self, content: &str) -> String {
+        word_regex()
+            .replace_all(content, |caps: &regex::Captures| {
+                let word = &caps[0];
+                match self.entry(word) {
+                    Some(Entry { corrections, reason: None }) => match_case(word, &corrections[0]),
+                    _ => word.to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Regex matching the words checked for misspellings
+fn word_regex() -> Regex {
+    Regex::new(r"[A-Za-z]+(?:'[A-Za-z]+)?").expect("word regex is valid")
+}
+
+/// Apply the case of `original` to `correction`
+fn match_case(original: &str, correction: &str) -> String {
+    if original.len() > 1 && original.chars().all(|c| !c.is_lowercase()) {
+        correction.to_uppercase()
+    } else if original.chars().next().is_some_and(char::is_uppercase) {
+        let mut chars = correction.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => String::new(),
+        }
+    } else {
+        correction.to_string()
+    }
+}
+
+impl Hook for Codespell {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let mut reports = Vec::new();
+
+        for file in files {
+            // Binary files can't contain prose worth checking
+            let text = match read_text(file)? {
+                Ok(text) => text,
+                Err(err) => {
+                    log::info!("Skipping {}: {}", file.display(), err);
+                    continue;
+                }
+            };
+            let content = &text.content;
+
+            let misspellings = self.check(content);
+            if misspellings.is_empty() {
+                continue;
+            }
+
+            if self.write_changes {
+                let fixed = self.fix(content);
+                if fixed != *content {
+                    log::info!("Fixed misspellings in {}", file.display());
+                    rewrite_file(file, &text.encode(&fixed))?;
+                }
+                // Misspellings the dictionary doesn't fix automatically still need a human
+                for m in misspellings.iter().filter(|m| !m.fixable) {
+                    reports.push(format!("{}:{}: {}", file.display(), m.line, m));
+                }
+            } else {
+                for m in &misspellings {
+                    reports.push(format!("{}:{}: {}", file.display(), m.line, m));
+                }
+            }
+        }
+
+        if !reports.is_empty() {
+            return Err(HookError::Other(format!("Misspellings found:\n{}", reports.join("\n"))));
+        }
+
+        Ok(())
+    }
+}
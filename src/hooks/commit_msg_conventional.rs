@@ -0,0 +1,153 @@
+//! Implementation of the commit-msg-conventional hook
+
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::hooks::common::{Hook, HookError};
+use crate::hooks::text::read_text;
+
+/// Commit types allowed by default, as in the Conventional Commits convention
+const DEFAULT_TYPES: &[&str] = &["feat", "fix", "docs", "style", "refactor", "perf", "test", "build", "ci", "chore", "revert"];
+
+/// Default maximum length of the header line
+const DEFAULT_MAX_HEADER_LENGTH: usize = 72;
+
+/// Check that commit messages follow the Conventional Commits format
+///
+/// The hook is meant for the commit-msg stage, where the file passed to it is
+/// the commit message. Merge, revert, fixup and squash commits are accepted
+/// as-is.
+///
+/// Arguments:
+/// - `--types=TYPES`: comma-separated allowed types
+/// - `--scopes=SCOPES`: comma-separated allowed scopes (default: any)
+/// - `--require-scope`: reject headers without a scope
+/// - `--max-header-length=N`: maximum header length (default: 72)
+pub struct CommitMsgConventional {
+    /// Allowed commit types
+    types: Vec<String>,
+    /// Allowed scopes, or any scope when empty
+    scopes: Vec<String>,
+    /// Whether a scope is required
+    require_scope: bool,
+    /// Maximum length of the header line
+    max_header_length: usize,
+}
+
+impl Default for CommitMsgConventional {
+    fn default() -> Self {
+        CommitMsgConventional {
+            types: DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+            scopes: Vec::new(),
+            require_scope: false,
+            max_header_length: DEFAULT_MAX_HEADER_LENGTH,
+        }
+    }
+}
+
+/// Split a comma-separated list argument
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+impl CommitMsgConventional {
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut hook = CommitMsgConventional::default();
+        for arg in args {
+            if let Some(types) = arg.strip_prefix("--types=") {
+                hook.types = split_list(types);
+            } else if let Some(scopes) = arg.strip_prefix("--scopes=") {
+                hook.scopes = split_list(scopes);
+            } else if arg == "--require-scope" {
+                hook.require_scope = true;
+            } else if let Some(length) = arg.strip_prefix("--max-header-length=") {
+                hook.max_header_length = length
+                    .parse()
+                    .map_err(|_| HookError::Other(format!("Invalid --max-header-length: {}", length)))?;
+            } else {
+                return Err(HookError::Other(format!("Unknown commit-msg-conventional argument: {}", arg)));
+            }
+        }
+        Ok(hook)
+    }
+
+    /// Check a commit message, returning the problems found
+    pub fn check_message(&self, message: &str) -> Vec<String> {
+        // Git strips comment lines from the final message
+        let lines: Vec<&str> = message.lines().filter(|line| !line.starts_with('#')).collect();
+        let header = match lines.iter().find(|line| !line.trim().is_empty()) {
+            Some(header) => header.trim_end(),
+            None => return vec!["Commit message is empty".to_string()],
+        };
+
+        // Commits generated by git itself are not expected to follow the convention
+        if ["Merge ", "Revert \"", "fixup! ", "squash! ", "amend! "].iter().any(|prefix| header.starts_with(prefix)) {
+            return Vec::new();
+        }
+
+        let mut problems = Vec::new();
+        if header.chars().count() > self.max_header_length {
+            problems.push(format!("Header is {} characters long, the maximum is {}", header.chars().count(), self.max_header_length));
+        }
+
+        let regex = Regex::new(r"^(?P<type>[A-Za-z]+)(?:\((?P<scope>[^()]*)\))?!?: (?P<subject>\S.*)$").expect("header regex is valid");
+        let captures = match regex.captures(header) {
+            Some(captures) => captures,
+            None => {
+                problems.push(format!("Header '{}' does not match 'type(scope): subject'", header));
+                return problems;
+            }
+        };
+
+        let commit_type = &captures["type"];
+        if !self.types.iter().any(|t| t == commit_type) {
+            problems.push(format!("Type '{}' is not one of: {}", commit_type, self.types.join(", ")));
+        }
+
+        match captures.name("scope").map(|m| m.as_str()) {
+            Some(scope) if !self.scopes.is_empty() && !self.scopes.iter().any(|s| s == scope) => {
+                problems.push(format!("Scope '{}' is not one of: {}", scope, self.scopes.join(", ")));
+            }
+            None if self.require_scope => problems.push("A scope is required".to_string()),
+            _ => {}
+        }
+
+        // The body must be separated from the header by a blank line
+        let header_index = lines.iter().position(|line| line.trim_end() == header).unwrap_or(0);
+        if let Some(next) = lines.get(header_index + 1) {
+            if !next.trim().is_empty() {
+                problems.push("The header must be followed by a blank line".to_string());
+            }
+        }
+
+        problems
+    }
+}
+
+impl Hook for CommitMsgConventional {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let mut problems = Vec::new();
+        for file in files {
+            let message = match read_text(file)? {
+                Ok(text) => text.content,
+                Err(err) => {
+                    problems.push(format!("{}: {}", file.display(), err));
+                    continue;
+                }
+            };
+            problems.extend(self.check_message(&message));
+        }
+
+        if !problems.is_empty() {
+            return Err(HookError::Other(format!("Commit message is not a conventional commit:\n{}", problems.join("\n"))));
+        }
+
+        Ok(())
+    }
+}
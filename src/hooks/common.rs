@@ -1,7 +1,20 @@
 //! Common types and traits for hooks
 
-use std::path::PathBuf;
-use std::io;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::runner::FileChange;
+
+/// Size of the chunks files are streamed in
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Longest line read from a file, so a file without newlines isn't buffered whole
+pub const MAX_LINE_LENGTH: usize = 1024 * 1024;
 
 /// Error type for hook operations
 #[derive(Debug)]
@@ -26,8 +39,132 @@ impl From<std::string::FromUtf8Error> for HookError {
     }
 }
 
+/// How serious a problem is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// A problem that fails the hook
+    #[default]
+    Error,
+    /// A problem worth fixing that may not fail the hook
+    Warning,
+    /// A remark
+    Info,
+}
+
+/// A problem a hook found on a line of a file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// File the problem is in
+    pub path: PathBuf,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// How serious the problem is
+    pub severity: Severity,
+    /// What found the problem, like the pattern that matched
+    pub rule: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "{}:{}:{}: {}", self.path.display(), self.line, column, self.message),
+            None => write!(f, "{}:{}: {}", self.path.display(), self.line, self.message),
+        }
+    }
+}
+
 /// Trait for hooks
 pub trait Hook {
     /// Run the hook on files
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError>;
-}
\ No newline at end of file
+
+    /// Find the problems in files without changing them, for reporters
+    ///
+    /// Hooks without structured findings report none.
+    fn diagnostics(&self, _files: &[PathBuf]) -> Result<Vec<Diagnostic>, HookError> {
+        Ok(Vec::new())
+    }
+
+    /// Give the hook the changes of the changeset the run checks
+    ///
+    /// Hooks that don't compare files with the changeset ignore them.
+    fn set_changes(&mut self, _changes: &[FileChange]) {}
+}
+/// Check whether content is text that fixers can rewrite safely
+///
+/// Text is valid UTF-8 without NUL bytes, which rules out binary files and
+/// UTF-16.
+pub fn is_text(content: &[u8]) -> bool {
+    !content.contains(&0) && std::str::from_utf8(content).is_ok()
+}
+
+/// Get the line ending most lines of the content use, `\n` if it has none
+pub fn dominant_line_ending(content: &[u8]) -> &'static [u8] {
+    let crlf = content.windows(2).filter(|pair| pair == b"\r\n").count();
+    let lf = content.iter().filter(|byte| **byte == b'\n').count() - crlf;
+    if crlf > lf {
+        b"\r\n"
+    } else {
+        b"\n"
+    }
+}
+
+/// Replace the content of an existing file
+///
+/// The file is truncated and rewritten in place rather than replaced, so its
+/// mode bits, owner and hard links are kept.
+pub fn rewrite_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(content)?;
+    file.flush()
+}
+
+/// Check whether a file contains any of the patterns, reading it in chunks
+///
+/// Reading stops at the first match, and matches spanning two chunks are
+/// found, so large files are never held in memory.
+pub fn file_contains(path: &Path, patterns: &[&[u8]]) -> io::Result<bool> {
+    let overlap = patterns.iter().map(|pattern| pattern.len()).max().unwrap_or(0).saturating_sub(1);
+    let mut file = fs::File::open(path)?;
+    let mut window = Vec::with_capacity(CHUNK_SIZE + overlap);
+    let mut chunk = vec![0; CHUNK_SIZE];
+    loop {
+        let read = file.read(&mut chunk)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        window.extend_from_slice(&chunk[..read]);
+        if patterns.iter().any(|pattern| !pattern.is_empty() && window.windows(pattern.len()).any(|bytes| bytes == *pattern)) {
+            return Ok(true);
+        }
+        // Keep the end of the window, where a match may continue into the next chunk
+        let keep = window.len().min(overlap);
+        window.drain(..window.len() - keep);
+    }
+}
+
+/// Call a function with every line of a file, including its line ending
+///
+/// Lines are read one at a time, and reading stops when the function breaks.
+/// A line longer than [`MAX_LINE_LENGTH`], as in minified files, stops the
+/// reading with an [`io::ErrorKind::InvalidData`] error.
+pub fn for_each_line(path: &Path, mut f: impl FnMut(&[u8]) -> ControlFlow<()>) -> io::Result<()> {
+    let mut reader = BufReader::with_capacity(CHUNK_SIZE, fs::File::open(path)?);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = (&mut reader).take(MAX_LINE_LENGTH as u64 + 1).read_until(b'\n', &mut line)?;
+        if line.len() > MAX_LINE_LENGTH && !line.ends_with(b"\n") {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("a line is longer than {} bytes", MAX_LINE_LENGTH)));
+        }
+        if read == 0 || f(&line).is_break() {
+            return Ok(());
+        }
+    }
+}
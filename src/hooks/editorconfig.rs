@@ -0,0 +1,401 @@
+//! Implementation of the editorconfig hook
+//!
+//! Enforces the `.editorconfig` rules that apply to each file: indent style
+//! and size, charset, end of line, trailing whitespace and final newline.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::hooks::common::{Hook, HookError};
+
+/// UTF-8 byte order mark
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// A section of an `.editorconfig` file
+struct Section {
+    /// Matcher for the section's glob
+    matcher: GlobMatcher,
+    /// The section's properties, with lowercase keys and values
+    properties: HashMap<String, String>,
+}
+
+/// A parsed `.editorconfig` file
+struct EditorConfigFile {
+    /// The directory containing the file
+    dir: PathBuf,
+    /// Whether the file stops the search for parent configurations
+    root: bool,
+    /// The file's sections, in order
+    sections: Vec<Section>,
+}
+
+impl EditorConfigFile {
+    /// Parse an `.editorconfig` file
+    fn parse(path: &Path) -> Result<Self, HookError> {
+        let content = fs::read_to_string(path)?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut config = EditorConfigFile { dir, root: false, sections: Vec::new() };
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                let pattern = &line[1..line.len() - 1];
+                config.sections.push(Section {
+                    matcher: section_matcher(pattern)?,
+                    properties: HashMap::new(),
+                });
+            } else if let Some((key, value)) = line.split_once('=') {
+                let key = key.trim().to_lowercase();
+                let value = value.trim().to_lowercase();
+                match config.sections.last_mut() {
+                    Some(section) => {
+                        section.properties.insert(key, value);
+                    }
+                    None if key == "root" => config.root = value == "true",
+                    None => {}
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Build a matcher for a section glob, following EditorConfig's rules
+///
+/// Globs without a `/` match file names in any directory; others are relative
+/// to the directory of the `.editorconfig` file.
+fn section_matcher(pattern: &str) -> Result<GlobMatcher, HookError> {
+    let pattern = if pattern.contains('/') {
+        pattern.trim_start_matches('/').to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+
+    GlobBuilder::new(&pattern)
+        .literal_separator(true)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| HookError::Other(format!("Invalid .editorconfig section '{}': {}", pattern, e)))
+}
+
+/// The EditorConfig properties that apply to a file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EditorConfigRules {
+    /// `space` or `tab`
+    pub indent_style: Option<String>,
+    /// Width of one indentation level
+    pub indent_size: Option<usize>,
+    /// `utf-8`, `utf-8-bom`, `latin1`, ...
+    pub charset: Option<String>,
+    /// `lf`, `crlf` or `cr`
+    pub end_of_line: Option<String>,
+    /// Whether trailing whitespace must be removed
+    pub trim_trailing_whitespace: Option<bool>,
+    /// Whether the file must end with a newline
+    pub insert_final_newline: Option<bool>,
+}
+
+impl EditorConfigRules {
+    /// Build rules from resolved properties
+    fn from_properties(properties: &HashMap<String, String>) -> Self {
+        let flag = |key: &str| properties.get(key).and_then(|v| v.parse::<bool>().ok());
+        let tab_width = properties.get("tab_width").and_then(|v| v.parse::<usize>().ok());
+        let indent_size = match properties.get("indent_size").map(String::as_str) {
+            Some("tab") => tab_width,
+            Some(size) => size.parse().ok().or(tab_width),
+            None => tab_width,
+        };
+
+        EditorConfigRules {
+            indent_style: properties.get("indent_style").cloned(),
+            indent_size,
+            charset: properties.get("charset").cloned(),
+            end_of_line: properties.get("end_of_line").cloned(),
+            trim_trailing_whitespace: flag("trim_trailing_whitespace"),
+            insert_final_newline: flag("insert_final_newline"),
+        }
+    }
+
+    /// The line ending required by the rules
+    fn line_ending(&self) -> Option<&'static str> {
+        match self.end_of_line.as_deref() {
+            Some("lf") => Some("\n"),
+            Some("crlf") => Some("\r\n"),
+            Some("cr") => Some("\r"),
+            _ => None,
+        }
+    }
+
+    /// Check file content against the rules, returning the problems found
+    pub fn check(&self, content: &[u8]) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        match self.charset.as_deref() {
+            Some("utf-8-bom") if !content.starts_with(UTF8_BOM) => problems.push("missing UTF-8 byte order mark".to_string()),
+            Some("utf-8") if content.starts_with(UTF8_BOM) => problems.push("unexpected UTF-8 byte order mark".to_string()),
+            _ => {}
+        }
+        if matches!(self.charset.as_deref(), Some("utf-8") | Some("utf-8-bom")) && std::str::from_utf8(content).is_err() {
+            problems.push("content is not valid UTF-8".to_string());
+        }
+
+        let text = String::from_utf8_lossy(content.strip_prefix(UTF8_BOM).unwrap_or(content));
+
+        if let Some(ending) = self.line_ending() {
+            let crlf = text.matches("\r\n").count();
+            let bare = text.replace("\r\n", "");
+            let (lf, cr) = (bare.matches('\n').count(), bare.matches('\r').count());
+            let consistent = match ending {
+                "\n" => crlf == 0 && cr == 0,
+                "\r\n" => lf == 0 && cr == 0,
+                _ => crlf == 0 && lf == 0,
+            };
+            if !consistent {
+                problems.push(format!("line endings are not {}", self.end_of_line.as_deref().unwrap_or_default()));
+            }
+        }
+
+        for (index, line) in split_lines(&text).iter().enumerate() {
+            let number = index + 1;
+            if self.trim_trailing_whitespace == Some(true) && line.ends_with([' ', '\t']) {
+                problems.push(format!("line {}: trailing whitespace", number));
+            }
+
+            let indent: String = line.chars().take_while(|c| *c == ' ' || *c == '\t').collect();
+            match self.indent_style.as_deref() {
+                Some("space") if indent.contains('\t') => {
+                    problems.push(format!("line {}: indented with tabs instead of spaces", number));
+                }
+                Some("space") => {
+                    // Lines of only whitespace are left to trim_trailing_whitespace
+                    if let Some(size) = self.indent_size.filter(|size| *size > 0 && !indent.len().is_multiple_of(*size) && indent.len() < line.len()) {
+                        problems.push(format!("line {}: indented with {} spaces, not a multiple of {}", number, indent.len(), size));
+                    }
+                }
+                Some("tab") if indent.contains(&" ".repeat(self.indent_size.unwrap_or(4).max(1))) => {
+                    problems.push(format!("line {}: indented with spaces instead of tabs", number));
+                }
+                _ => {}
+            }
+        }
+
+        match self.insert_final_newline {
+            Some(true) if !text.is_empty() && !text.ends_with(['\n', '\r']) => problems.push("missing final newline".to_string()),
+            Some(false) if text.ends_with(['\n', '\r']) => problems.push("unexpected final newline".to_string()),
+            _ => {}
+        }
+
+        problems
+    }
+
+    /// Rewrite file content to follow the rules
+    pub fn fix(&self, content: &[u8]) -> Vec<u8> {
+        // Content in other encodings can't be rewritten safely as text
+        let body = content.strip_prefix(UTF8_BOM).unwrap_or(content);
+        let text = match std::str::from_utf8(body) {
+            Ok(text) => text,
+            Err(_) => return content.to_vec(),
+        };
+
+        let had_final_newline = text.ends_with(['\n', '\r']);
+        let ending = self.line_ending().unwrap_or_else(|| detect_line_ending(text));
+        let tab_spaces = " ".repeat(self.indent_size.unwrap_or(4).max(1));
+
+        let mut lines: Vec<String> = split_lines(text)
+            .into_iter()
+            .map(|line| {
+                let indent_len = line.len() - line.trim_start_matches([' ', '\t']).len();
+                let (indent, rest) = line.split_at(indent_len);
+                let indent = match self.indent_style.as_deref() {
+                    Some("space") => indent.replace('\t', &tab_spaces),
+                    Some("tab") => indent.replace(&tab_spaces, "\t"),
+                    _ => indent.to_string(),
+                };
+                let line = format!("{}{}", indent, rest);
+                if self.trim_trailing_whitespace == Some(true) {
+                    line.trim_end_matches([' ', '\t']).to_string()
+                } else {
+                    line
+                }
+            })
+            .collect();
+
+        // split_lines yields an empty last line for content ending in a newline
+        if had_final_newline {
+            lines.pop();
+        }
+
+        let mut fixed = lines.join(ending);
+        let final_newline = self.insert_final_newline.unwrap_or(had_final_newline);
+        if final_newline && !fixed.is_empty() {
+            fixed.push_str(ending);
+        }
+
+        let mut bytes = Vec::new();
+        let bom = match self.charset.as_deref() {
+            Some("utf-8-bom") => true,
+            Some("utf-8") => false,
+            _ => content.starts_with(UTF8_BOM),
+        };
+        if bom {
+            bytes.extend_from_slice(UTF8_BOM);
+        }
+        bytes.extend_from_slice(fixed.as_bytes());
+        bytes
+    }
+}
+
+/// Split text into lines on any line ending
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\r' if bytes.get(i + 1) == Some(&b'\n') => {
+                lines.push(&text[start..i]);
+                i += 2;
+                start = i;
+                continue;
+            }
+            b'\r' | b'\n' => {
+                lines.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    lines.push(&text[start..]);
+    lines
+}
+
+/// Detect the line ending a text mostly uses
+fn detect_line_ending(text: &str) -> &'static str {
+    if text.contains("\r\n") {
+        "\r\n"
+    } else if text.contains('\r') && !text.contains('\n') {
+        "\r"
+    } else {
+        "\n"
+    }
+}
+
+/// Enforce `.editorconfig` rules on files
+///
+/// Arguments:
+/// - `--fix`: rewrite files to follow the rules instead of failing
+pub struct EditorConfig {
+    /// Whether to rewrite files
+    fix: bool,
+}
+
+impl EditorConfig {
+    /// Create a new instance
+    pub fn new(fix: bool) -> Self {
+        EditorConfig { fix }
+    }
+
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut fix = false;
+        for arg in args {
+            match arg.as_str() {
+                "--fix" => fix = true,
+                other => return Err(HookError::Other(format!("Unknown editorconfig argument: {}", other))),
+            }
+        }
+        Ok(EditorConfig::new(fix))
+    }
+
+    /// Resolve the rules that apply to a file
+    pub fn rules_for(file: &Path) -> Result<EditorConfigRules, HookError> {
+        let file = file.canonicalize()?;
+
+        // Collect the .editorconfig files from the file's directory up to the root
+        let mut configs = Vec::new();
+        let mut dir = file.parent();
+        while let Some(current) = dir {
+            let path = current.join(".editorconfig");
+            if path.is_file() {
+                let config = EditorConfigFile::parse(&path)?;
+                let root = config.root;
+                configs.push(config);
+                if root {
+                    break;
+                }
+            }
+            dir = current.parent();
+        }
+
+        // Closer files and later sections take precedence
+        let mut properties = HashMap::new();
+        for config in configs.iter().rev() {
+            let relative = match file.strip_prefix(&config.dir) {
+                Ok(relative) => relative,
+                Err(_) => continue,
+            };
+            for section in &config.sections {
+                if section.matcher.is_match(relative) {
+                    for (key, value) in &section.properties {
+                        properties.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+
+        // "unset" removes a property set by an earlier section
+        properties.retain(|_, value| value != "unset");
+        Ok(EditorConfigRules::from_properties(&properties))
+    }
+}
+
+impl Hook for EditorConfig {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let mut reports = Vec::new();
+
+        for file in files {
+            let rules = Self::rules_for(file)?;
+            if rules == EditorConfigRules::default() {
+                continue;
+            }
+
+            let content = fs::read(file)?;
+            let problems = rules.check(&content);
+            if problems.is_empty() {
+                continue;
+            }
+
+            if self.fix {
+                let fixed = rules.fix(&content);
+                if fixed != content {
+                    log::info!("Fixed .editorconfig violations in {}", file.display());
+                    fs::write(file, &fixed)?;
+                }
+                // Whatever the fix couldn't address is still reported
+                for problem in rules.check(&fixed) {
+                    reports.push(format!("{}: {}", file.display(), problem));
+                }
+            } else {
+                for problem in problems {
+                    reports.push(format!("{}: {}", file.display(), problem));
+                }
+            }
+        }
+
+        if !reports.is_empty() {
+            return Err(HookError::Other(format!(".editorconfig violations:\n{}", reports.join("\n"))));
+        }
+
+        Ok(())
+    }
+}
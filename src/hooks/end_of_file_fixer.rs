@@ -1,18 +1,78 @@
 //! Implementation of the end-of-file-fixer hook
 
-use std::path::PathBuf;
 use std::fs;
-use crate::hooks::common::{Hook, HookError};
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use crate::hooks::common::{dominant_line_ending, for_each_line, is_text, rewrite_file, Hook, HookError};
+use crate::hooks::text::read_utf16;
+use crate::runner::GitAttributes;
 
 /// Fix end of files
+///
+/// A missing final newline is added using the file's `eol` attribute, or the
+/// line ending most lines of the file use. Files that aren't text are
+/// skipped. UTF-8 files are streamed line by line and the line ending is
+/// appended, so large files are never held in memory. UTF-16 files are
+/// decoded, fixed and written back in UTF-16 with their byte order mark.
 pub struct EndOfFileFixer;
 
+/// What a file's lines look like
+#[derive(Default)]
+struct Scan {
+    /// Whether every line is UTF-8 text
+    text: bool,
+    /// Whether the file has content and its last line has no line ending
+    missing_newline: bool,
+    /// Number of lines ending with CRLF
+    crlf: usize,
+    /// Number of lines ending with a lone LF
+    lf: usize,
+}
+
+impl EndOfFileFixer {
+    /// Add a final line ending to content that lacks one
+    pub fn fix(content: &[u8], line_ending: &[u8]) -> Vec<u8> {
+        let mut fixed = content.to_vec();
+        if !content.is_empty() && !content.ends_with(b"\n") {
+            fixed.extend_from_slice(line_ending);
+        }
+        fixed
+    }
+
+    /// Read the lines of a file, stopping at the first line that isn't text
+    fn scan(file: &Path) -> io::Result<Scan> {
+        let mut scan = Scan { text: true, ..Scan::default() };
+        for_each_line(file, |line| {
+            if !is_text(line) {
+                scan.text = false;
+                return ControlFlow::Break(());
+            }
+            if line.ends_with(b"\r\n") {
+                scan.crlf += 1;
+            } else if line.ends_with(b"\n") {
+                scan.lf += 1;
+            }
+            scan.missing_newline = !line.ends_with(b"\n");
+            ControlFlow::Continue(())
+        })?;
+        Ok(scan)
+    }
+}
+
 impl Hook for EndOfFileFixer {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let Some(first) = files.first() else { return Ok(()) };
+        let attributes = GitAttributes::discover(first);
+
         for file in files {
             // Read the file
-            let content = match fs::read(file) {
-                Ok(content) => content,
+            let scan = match Self::scan(file) {
+                Ok(scan) => scan,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    log::info!("Skipping {}: {}", file.display(), e);
+                    continue;
+                }
                 Err(e) => {
                     if e.kind() == std::io::ErrorKind::PermissionDenied {
                         // Skip files that can't be accessed due to permission issues
@@ -23,17 +83,51 @@ impl Hook for EndOfFileFixer {
                     }
                 }
             };
-            let content_str = String::from_utf8_lossy(&content);
+
+            // Rewriting binary files as text would corrupt them
+            if !scan.text {
+                let fixed = read_utf16(file).and_then(|text| match text {
+                    Some(text) if !text.content.is_empty() && !text.content.ends_with('\n') => {
+                        let line_ending = match attributes.get(file).eol {
+                            Some(eol) => eol.as_bytes(),
+                            None => dominant_line_ending(text.content.as_bytes()),
+                        };
+                        let fixed = format!("{}{}", text.content, String::from_utf8_lossy(line_ending));
+                        rewrite_file(file, &text.encode(&fixed))
+                    }
+                    Some(_) => Ok(()),
+                    None => {
+                        log::info!("Skipping {}: not a text file", file.display());
+                        Ok(())
+                    }
+                });
+                if let Err(e) = fixed {
+                    if e.kind() == std::io::ErrorKind::PermissionDenied {
+                        // Skip files that can't be written to due to permission issues
+                        log::warn!("Skipping file write due to permission denied: {}", file.display());
+                    } else {
+                        return Err(HookError::IoError(e));
+                    }
+                }
+                continue;
+            }
 
             // Check if the file is empty or ends with a newline
-            if content_str.is_empty() || content_str.ends_with('\n') {
+            if !scan.missing_newline {
                 continue;
             }
+            let line_ending: &[u8] = match attributes.get(file).eol {
+                Some(eol) => eol.as_bytes(),
+                None if scan.crlf > scan.lf => b"\r\n",
+                None => b"\n",
+            };
 
             // Fix the file
-            let mut new_content = content_str.to_string();
-            new_content.push('\n');
-            if let Err(e) = fs::write(file, new_content) {
+            let appended = fs::OpenOptions::new().append(true).open(file).and_then(|mut target| {
+                target.write_all(line_ending)?;
+                target.flush()
+            });
+            if let Err(e) = appended {
                 if e.kind() == std::io::ErrorKind::PermissionDenied {
                     // Skip files that can't be written to due to permission issues
                     log::warn!("Skipping file write due to permission denied: {}", file.display());
@@ -46,4 +140,4 @@ impl Hook for EndOfFileFixer {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
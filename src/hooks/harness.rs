@@ -0,0 +1,205 @@
+//! Property and corpus checks for native hooks
+//!
+//! Native hooks run on whatever is staged, so they must never corrupt a file.
+//! This module runs hooks on a corpus of awkward files (CRLF, UTF-16, no
+//! trailing newline, binary, ...) and on generated inputs, and checks that:
+//!
+//! - fixers are idempotent: running a fixer twice equals running it once
+//! - hooks in check mode never modify files
+//! - fixers leave files that aren't text alone, keep the encoding and byte
+//!   order mark of text files, and don't mix lone LF line endings into CRLF
+//!   files
+//!
+//! The crate's tests run these checks on every hook in
+//! [`HookFactory::HOOK_IDS`](super::HookFactory::HOOK_IDS).
+
+use std::fs;
+use std::path::Path;
+
+use super::{decode, HookError, HookFactory};
+
+/// A file to run hooks on
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusFile {
+    /// File name, whose extension decides which rules apply
+    pub name: String,
+    /// Content of the file
+    pub content: Vec<u8>,
+}
+
+impl CorpusFile {
+    /// Create a corpus file
+    pub fn new(name: &str, content: impl Into<Vec<u8>>) -> Self {
+        CorpusFile { name: name.to_string(), content: content.into() }
+    }
+}
+
+/// Encode text as UTF-16 LE with a byte order mark
+fn utf16le(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    bytes
+}
+
+/// Encode text as UTF-16 BE with a byte order mark
+fn utf16be(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xfe, 0xff];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    bytes
+}
+
+/// Get the files every native hook is checked against
+pub fn corpus() -> Vec<CorpusFile> {
+    vec![
+        CorpusFile::new("empty.txt", ""),
+        CorpusFile::new("newline-only.txt", "\n"),
+        CorpusFile::new("blank-lines.txt", "\n\n\n"),
+        CorpusFile::new("no-final-newline.txt", "no newline at the end"),
+        CorpusFile::new("trailing-whitespace.txt", "one  \ntwo\t\nthree\n"),
+        CorpusFile::new("whitespace-only.txt", "   \n\t\n"),
+        CorpusFile::new("crlf.txt", "one\r\ntwo  \r\nthree\r\n"),
+        CorpusFile::new("crlf-no-final-newline.txt", "one\r\ntwo"),
+        CorpusFile::new("mixed-endings.txt", "one\r\ntwo\nthree\r\n"),
+        CorpusFile::new("cr-only.txt", "one\rtwo\r"),
+        CorpusFile::new("utf8-bom.txt", "\u{feff}one  \ntwo\n"),
+        CorpusFile::new("unicode.txt", "caf\u{e9} \u{3000}\nna\u{ef}ve\u{a0}\n\u{1f980}\n"),
+        CorpusFile::new("utf16.txt", utf16le("one  \r\ntwo\r\n")),
+        CorpusFile::new("utf16be-no-final-newline.txt", utf16be("caf\u{e9}  \ntwo")),
+        CorpusFile::new("invalid-utf8.txt", b"valid \xff\xfe invalid  \nnext\n".to_vec()),
+        CorpusFile::new("binary.bin", b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR  \n\x00".to_vec()),
+        CorpusFile::new("long-line.txt", format!("{}  \n", "x".repeat(100_000))),
+        CorpusFile::new("code.rs", "fn main() {  \n    println!(\"hi\");\r\n}"),
+        CorpusFile::new("config.yaml", "key: value  \nlist:\n  - a\n"),
+        CorpusFile::new("data.json", "{\"key\": \"value\"}  "),
+        CorpusFile::new("notes.md", "# Title\n\nText  \n"),
+    ]
+}
+
+/// Generate pseudo-random inputs made of awkward fragments
+///
+/// The same seed always gives the same inputs, so failures are reproducible.
+pub fn generated_inputs(seed: u64, count: usize) -> Vec<CorpusFile> {
+    const FRAGMENTS: &[&[u8]] = &[
+        b"a", b"word", b" ", b"  ", b"\t", b"\n", b"\r\n", b"\r", b"\x0b", b"\x0c",
+        "\u{e9}".as_bytes(), "\u{3000}".as_bytes(), "\u{a0}".as_bytes(), "\u{feff}".as_bytes(),
+        b"\x00", b"\xff", b"#", b"---", b"{", b"}",
+    ];
+
+    // xorshift64*, which is plenty for picking fragments
+    let mut state = seed.max(1);
+    let mut next = move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    };
+
+    (0..count)
+        .map(|index| {
+            let length = (next() % 40) as usize;
+            let content: Vec<u8> = (0..length)
+                .flat_map(|_| FRAGMENTS[(next() % FRAGMENTS.len() as u64) as usize].iter().copied())
+                .collect();
+            CorpusFile::new(&format!("generated-{}.txt", index), content)
+        })
+        .collect()
+}
+
+/// Run a native hook on a copy of a file in `dir`, and return the new content
+fn run_on_copy(hook_id: &str, args: &[String], file: &CorpusFile, dir: &Path) -> Result<Vec<u8>, HookError> {
+    let path = dir.join(&file.name);
+    fs::write(&path, &file.content)?;
+    run_again(hook_id, args, &path)
+}
+
+/// Run a native hook on a file, and return the new content
+fn run_again(hook_id: &str, args: &[String], path: &Path) -> Result<Vec<u8>, HookError> {
+    let hook = HookFactory::create_hook(hook_id, args)?;
+    // Failures are expected, only the effect on the file matters
+    let _ = hook.run(&[path.to_path_buf()]);
+    Ok(fs::read(path)?)
+}
+
+/// Check that a fixer gives the same result when run a second time
+///
+/// Returns a description of each file the fixer isn't idempotent on.
+pub fn check_idempotent(hook_id: &str, args: &[String], files: &[CorpusFile]) -> Result<Vec<String>, HookError> {
+    let dir = tempfile::tempdir()?;
+    let mut violations = Vec::new();
+    for file in files {
+        let once = run_on_copy(hook_id, args, file, dir.path())?;
+        let twice = run_again(hook_id, args, &dir.path().join(&file.name))?;
+        if once != twice {
+            violations.push(format!(
+                "{} is not idempotent on {}: {:?} became {:?}, then {:?}",
+                hook_id,
+                file.name,
+                String::from_utf8_lossy(&file.content),
+                String::from_utf8_lossy(&once),
+                String::from_utf8_lossy(&twice)
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Check that a hook never modifies files
+///
+/// Returns a description of each file the hook modified.
+pub fn check_read_only(hook_id: &str, args: &[String], files: &[CorpusFile]) -> Result<Vec<String>, HookError> {
+    let dir = tempfile::tempdir()?;
+    let mut violations = Vec::new();
+    for file in files {
+        let after = run_on_copy(hook_id, args, file, dir.path())?;
+        if after != file.content {
+            violations.push(format!(
+                "{} modified {} in check mode: {:?} became {:?}",
+                hook_id,
+                file.name,
+                String::from_utf8_lossy(&file.content),
+                String::from_utf8_lossy(&after)
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Check that a fixer keeps non-text files, encodings and CRLF line endings intact
+///
+/// Returns a description of each file the fixer damaged.
+pub fn check_preserves_text(hook_id: &str, args: &[String], files: &[CorpusFile]) -> Result<Vec<String>, HookError> {
+    let dir = tempfile::tempdir()?;
+    let mut violations = Vec::new();
+    for file in files {
+        let after = run_on_copy(hook_id, args, file, dir.path())?;
+        let Ok(before) = decode(&file.content) else {
+            if after != file.content {
+                violations.push(format!("{} modified non-text file {}", hook_id, file.name));
+            }
+            continue;
+        };
+        let after = match decode(&after) {
+            Ok(after) if after.encoding == before.encoding => after,
+            _ => {
+                violations.push(format!("{} changed the encoding of {}", hook_id, file.name));
+                continue;
+            }
+        };
+        if is_crlf_only(before.content.as_bytes()) && !is_crlf_only(after.content.as_bytes()) {
+            violations.push(format!(
+                "{} broke the CRLF line endings of {}: {:?} became {:?}",
+                hook_id,
+                file.name,
+                before.content,
+                after.content
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Check whether content has line endings, all of them CRLF
+fn is_crlf_only(content: &[u8]) -> bool {
+    let lf = content.iter().filter(|byte| **byte == b'\n').count();
+    lf > 0 && content.windows(2).filter(|pair| pair == b"\r\n").count() == lf
+}
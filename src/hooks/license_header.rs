@@ -0,0 +1,297 @@
+//! Implementation of the license-header hook
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::Datelike;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use regex::Regex;
+
+use crate::hooks::common::{rewrite_file, Hook, HookError};
+use crate::hooks::text::read_text;
+use crate::runner::file_matcher::{repo_relative_path, to_slash_path};
+
+/// Default header template, whose `{company}` is dropped when none is set
+const DEFAULT_TEMPLATE: &str = "Copyright (c) {year} {company}. All rights reserved.";
+
+/// How many lines at the top of a file are searched for the header
+const HEADER_SEARCH_LINES: usize = 30;
+
+/// How a header is commented out in a file type
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommentStyle {
+    /// Line opening a block comment, if any
+    pub start: Option<String>,
+    /// Prefix of every header line
+    pub prefix: String,
+    /// Line closing a block comment, if any
+    pub end: Option<String>,
+}
+
+impl CommentStyle {
+    /// A style prefixing every line, like `//` or `#`
+    pub fn line(prefix: &str) -> Self {
+        CommentStyle {
+            start: None,
+            prefix: prefix.to_string(),
+            end: None,
+        }
+    }
+
+    /// A block comment style, like `/*`, ` *`, ` */`
+    pub fn block(start: &str, prefix: &str, end: &str) -> Self {
+        CommentStyle {
+            start: Some(start.to_string()),
+            prefix: prefix.to_string(),
+            end: Some(end.to_string()),
+        }
+    }
+
+    /// Parse a style from `PREFIX` or `START|PREFIX|END`
+    fn parse(spec: &str) -> Result<Self, HookError> {
+        let parts: Vec<&str> = spec.split('|').collect();
+        match parts.as_slice() {
+            [prefix] => Ok(CommentStyle::line(prefix)),
+            [start, prefix, end] => Ok(CommentStyle::block(start, prefix, end)),
+            _ => Err(HookError::Other(format!("Invalid comment style '{}', expected PREFIX or START|PREFIX|END", spec))),
+        }
+    }
+
+    /// Comment out a header
+    fn render(&self, header: &str) -> String {
+        let mut lines = Vec::new();
+        if let Some(start) = &self.start {
+            lines.push(start.clone());
+        }
+        for line in header.lines() {
+            lines.push(format!("{} {}", self.prefix, line).trim_end().to_string());
+        }
+        if let Some(end) = &self.end {
+            lines.push(end.clone());
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Default comment styles by file extension
+fn default_comment_styles() -> HashMap<String, CommentStyle> {
+    let mut styles = HashMap::new();
+    for ext in ["rs", "go", "java", "kt", "swift", "c", "h", "cc", "cpp", "hpp", "cs", "js", "jsx", "ts", "tsx", "scala", "dart"] {
+        styles.insert(ext.to_string(), CommentStyle::line("//"));
+    }
+    for ext in ["py", "rb", "sh", "bash", "zsh", "pl", "r", "yaml", "yml", "toml", "tf", "cmake"] {
+        styles.insert(ext.to_string(), CommentStyle::line("#"));
+    }
+    for ext in ["sql", "lua", "hs"] {
+        styles.insert(ext.to_string(), CommentStyle::line("--"));
+    }
+    styles.insert("css".to_string(), CommentStyle::block("/*", " *", " */"));
+    styles.insert("scss".to_string(), CommentStyle::block("/*", " *", " */"));
+    for ext in ["html", "xml", "vue", "svg"] {
+        styles.insert(ext.to_string(), CommentStyle::block("<!--", "  ", "-->"));
+    }
+    styles
+}
+
+/// Check for, and optionally insert, a license header
+///
+/// Arguments:
+/// - `--template=FILE`: read the header from a file (supports `{year}` and `{company}`)
+/// - `--company=NAME`: value of `{company}`
+/// - `--year=YEAR`: value of `{year}` for inserted headers (default: the current year)
+/// - `--comment-style=EXT:STYLE`: comment style for an extension, `PREFIX` or `START|PREFIX|END`
+/// - `--exclude=GLOB`: skip files whose path relative to the repository root matches (repeatable)
+/// - `--fix`: insert missing headers instead of failing
+pub struct LicenseHeader {
+    /// Header template
+    template: String,
+    /// Company substituted for `{company}`
+    company: String,
+    /// Year substituted for `{year}` in inserted headers
+    year: String,
+    /// Comment styles by file extension
+    styles: HashMap<String, CommentStyle>,
+    /// Files to skip
+    excludes: GlobSet,
+    /// Whether to insert missing headers
+    fix: bool,
+}
+
+impl LicenseHeader {
+    /// Create a new instance with the given template and company
+    pub fn new(template: &str, company: &str) -> Self {
+        LicenseHeader {
+            template: template.trim_end().to_string(),
+            company: company.to_string(),
+            year: chrono::Local::now().year().to_string(),
+            styles: default_comment_styles(),
+            excludes: GlobSet::empty(),
+            fix: false,
+        }
+    }
+
+    /// Create a new instance from hook arguments
+    pub fn from_args(args: &[String]) -> Result<Self, HookError> {
+        let mut hook = LicenseHeader::new(DEFAULT_TEMPLATE, "");
+        let mut excludes = GlobSetBuilder::new();
+
+        for arg in args {
+            if let Some(path) = arg.strip_prefix("--template=") {
+                hook.template = fs::read_to_string(path)?.trim_end().to_string();
+            } else if let Some(company) = arg.strip_prefix("--company=") {
+                hook.company = company.to_string();
+            } else if let Some(year) = arg.strip_prefix("--year=") {
+                hook.year = year.to_string();
+            } else if let Some(spec) = arg.strip_prefix("--comment-style=") {
+                let (ext, style) = spec.split_once(':').ok_or_else(|| {
+                    HookError::Other(format!("Invalid comment style '{}', expected EXT:STYLE", spec))
+                })?;
+                hook.styles.insert(ext.trim_start_matches('.').to_string(), CommentStyle::parse(style)?);
+            } else if let Some(pattern) = arg.strip_prefix("--exclude=") {
+                let glob = Glob::new(pattern)
+                    .map_err(|e| HookError::Other(format!("Invalid exclude pattern '{}': {}", pattern, e)))?;
+                excludes.add(glob);
+            } else if arg == "--fix" {
+                hook.fix = true;
+            } else {
+                return Err(HookError::Other(format!("Unknown license-header argument: {}", arg)));
+            }
+        }
+
+        hook.excludes = excludes
+            .build()
+            .map_err(|e| HookError::Other(format!("Invalid exclude patterns: {}", e)))?;
+        Ok(hook)
+    }
+
+    /// Set whether to insert missing headers
+    pub fn with_fix(mut self, fix: bool) -> Self {
+        self.fix = fix;
+        self
+    }
+
+    /// Set the year substituted into inserted headers
+    pub fn with_year(mut self, year: &str) -> Self {
+        self.year = year.to_string();
+        self
+    }
+
+    /// Get the comment style for a file, if its type is known
+    fn style_for(&self, file: &Path) -> Option<&CommentStyle> {
+        let ext = file.extension()?.to_string_lossy().to_lowercase();
+        self.styles.get(&ext)
+    }
+
+    /// Get the template, without the `{company}` placeholder when no company is set
+    fn template(&self) -> String {
+        match self.company.is_empty() {
+            true => self.template.replace(" {company}", "").replace("{company}", ""),
+            false => self.template.clone(),
+        }
+    }
+
+    /// Get the path of a file that excludes are matched against
+    ///
+    /// Paths are relative to the root of the repository containing the files,
+    /// or as given outside of a repository.
+    fn exclude_path(file: &Path, root: Option<&Path>) -> Result<String, HookError> {
+        match root {
+            Some(root) => Ok(repo_relative_path(&std::env::current_dir()?.join(file), root)),
+            None => Ok(to_slash_path(file)),
+        }
+    }
+
+    /// Get the root of the repository containing a file
+    fn repo_root(file: &Path) -> Option<PathBuf> {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let repo = git2::Repository::discover(dir).ok()?;
+        repo.workdir()?.canonicalize().ok()
+    }
+
+    /// Build a regex matching the header with any year or year range
+    fn header_regex(&self) -> Result<Regex, HookError> {
+        let pattern = regex::escape(&self.template())
+            .replace(r"\{year\}", r"\d{4}(?:\s*-\s*\d{4})?")
+            .replace(r"\{company\}", &regex::escape(&self.company));
+        Regex::new(&pattern).map_err(|e| HookError::Other(format!("Invalid license header template: {}", e)))
+    }
+
+    /// Check whether the top of a file already contains the header
+    fn has_header(&self, regex: &Regex, style: &CommentStyle, content: &str) -> bool {
+        // Strip the comment markers so the template can be matched as plain text
+        let top: Vec<&str> = content
+            .lines()
+            .take(HEADER_SEARCH_LINES)
+            .map(|line| {
+                let line = line.trim();
+                let line = line.strip_prefix(style.prefix.trim()).unwrap_or(line);
+                line.trim()
+            })
+            .collect();
+        regex.is_match(&top.join("\n"))
+    }
+
+    /// Insert the header, keeping shebangs and XML declarations first
+    fn insert_header(&self, style: &CommentStyle, content: &str) -> String {
+        let header = style.render(
+            &self
+                .template()
+                .replace("{year}", &self.year)
+                .replace("{company}", &self.company),
+        );
+
+        let keep_first = content.starts_with("#!") || content.starts_with("<?xml");
+        match content.split_once('\n') {
+            Some((first, rest)) if keep_first => format!("{}\n{}\n{}", first, header, rest),
+            _ if keep_first => format!("{}\n{}", content, header),
+            _ => format!("{}\n{}", header, content),
+        }
+    }
+}
+
+impl Hook for LicenseHeader {
+    fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
+        let regex = self.header_regex()?;
+        let root = match self.excludes.is_empty() {
+            true => None,
+            false => files.first().and_then(|file| Self::repo_root(file)),
+        };
+        let mut missing = Vec::new();
+
+        for file in files {
+            if !self.excludes.is_empty() && self.excludes.is_match(Self::exclude_path(file, root.as_deref())?) {
+                continue;
+            }
+            let style = match self.style_for(file) {
+                Some(style) => style,
+                None => continue,
+            };
+
+            let text = match read_text(file)? {
+                Ok(text) => text,
+                Err(err) => {
+                    log::warn!("Skipping {}: {}", file.display(), err);
+                    continue;
+                }
+            };
+            let content = &text.content;
+            if self.has_header(&regex, style, content) {
+                continue;
+            }
+
+            if self.fix {
+                log::info!("Inserting license header into {}", file.display());
+                rewrite_file(file, &text.encode(&self.insert_header(style, content)))?;
+            } else {
+                missing.push(file.display().to_string());
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(HookError::Other(format!("Missing license header: {}", missing.join(", "))));
+        }
+
+        Ok(())
+    }
+}
@@ -5,7 +5,7 @@
 
 // Re-export common types
 mod common;
-pub use common::{Hook, HookError};
+pub use common::{dominant_line_ending, file_contains, for_each_line, is_text, rewrite_file, Diagnostic, Hook, HookError, Severity, CHUNK_SIZE, MAX_LINE_LENGTH};
 
 // Import individual hook implementations
 mod trailing_whitespace;
@@ -18,6 +18,16 @@ mod check_toml;
 mod check_xml;
 mod check_case_conflict;
 mod detect_private_key;
+mod check_lockfile_sync;
+mod license_header;
+mod codespell;
+mod commit_msg_conventional;
+mod branch_name_lint;
+mod editorconfig;
+mod check_markdown_links;
+mod check_frontmatter;
+pub mod harness;
+pub mod text;
 
 // Re-export hook implementations
 pub use trailing_whitespace::TrailingWhitespace;
@@ -29,35 +39,115 @@ pub use check_json::CheckJson;
 pub use check_toml::CheckToml;
 pub use check_xml::CheckXml;
 pub use check_case_conflict::CheckCaseConflict;
-pub use detect_private_key::DetectPrivateKey;
+pub use detect_private_key::{shannon_entropy, DetectPrivateKey, ALLOW_SECRET};
+pub use check_lockfile_sync::{CheckLockfileSync, Ecosystem};
+pub use license_header::{CommentStyle, LicenseHeader};
+pub use codespell::{Codespell, Misspelling};
+pub use commit_msg_conventional::CommitMsgConventional;
+pub use branch_name_lint::BranchNameLint;
+pub use editorconfig::{EditorConfig, EditorConfigRules};
+pub use check_markdown_links::CheckMarkdownLinks;
+pub use check_frontmatter::CheckFrontmatter;
+pub use text::{decode, read_text, DecodeError, Encoding, Text};
+
+/// Create a native hook from its arguments
+type Constructor = fn(&[String]) -> Result<Box<dyn Hook>, HookError>;
 
 /// Factory for creating hooks
 pub struct HookFactory;
 
 impl HookFactory {
+    /// Every native hook, with how to create it from its arguments
+    ///
+    /// New hooks are only added here: [`HOOK_IDS`](Self::HOOK_IDS), shell
+    /// completion, multi-repository runs and the corpus and property tests
+    /// of [`harness`] all come from this table.
+    const HOOKS: &'static [(&'static str, Constructor)] = &[
+        ("trailing-whitespace", |_| Ok(Box::new(TrailingWhitespace))),
+        ("end-of-file-fixer", |_| Ok(Box::new(EndOfFileFixer))),
+        ("check-yaml", |_| Ok(Box::new(CheckYaml))),
+        ("check-added-large-files", |args| {
+            // Parse the max size argument
+            let max_size_kb = if let Some(arg) = args.iter().find(|a| a.starts_with("--maxkb=")) {
+                arg.trim_start_matches("--maxkb=").parse::<usize>().unwrap_or(500)
+            } else {
+                500 // Default to 500 KB
+            };
+
+            Ok(Box::new(CheckAddedLargeFiles::new(max_size_kb)))
+        }),
+        ("check-merge-conflict", |_| Ok(Box::new(CheckMergeConflict))),
+        ("check-json", |_| Ok(Box::new(CheckJson))),
+        ("check-toml", |_| Ok(Box::new(CheckToml))),
+        ("check-xml", |_| Ok(Box::new(CheckXml))),
+        ("check-case-conflict", |_| Ok(Box::new(CheckCaseConflict))),
+        ("detect-private-key", |args| Ok(Box::new(DetectPrivateKey::from_args(args)?))),
+        ("check-lockfile-sync", |_| Ok(Box::new(CheckLockfileSync::new(Ecosystem::ALL.to_vec())))),
+        ("check-cargo-lockfile-sync", |_| Ok(Box::new(CheckLockfileSync::new(vec![Ecosystem::Cargo])))),
+        ("check-node-lockfile-sync", |_| Ok(Box::new(CheckLockfileSync::new(vec![Ecosystem::Node])))),
+        ("check-python-lockfile-sync", |_| Ok(Box::new(CheckLockfileSync::new(vec![Ecosystem::Python])))),
+        ("license-header", |args| Ok(Box::new(LicenseHeader::from_args(args)?))),
+        ("codespell", |args| Ok(Box::new(Codespell::from_args(args)?))),
+        ("commit-msg-conventional", |args| Ok(Box::new(CommitMsgConventional::from_args(args)?))),
+        ("branch-name-lint", |args| Ok(Box::new(BranchNameLint::from_args(args)?))),
+        ("editorconfig", |args| Ok(Box::new(EditorConfig::from_args(args)?))),
+        ("check-markdown-links", |args| Ok(Box::new(CheckMarkdownLinks::from_args(args)?))),
+        ("check-frontmatter", |args| Ok(Box::new(CheckFrontmatter::from_args(args)?))),
+    ];
+
+    /// IDs of every native hook, in the order of their table
+    pub const HOOK_IDS: &'static [&'static str] = &Self::hook_ids::<{ HookFactory::HOOKS.len() }>();
+
+    /// Get the IDs of the hooks of the table
+    const fn hook_ids<const N: usize>() -> [&'static str; N] {
+        let mut ids = [""; N];
+        let mut index = 0;
+        while index < N {
+            ids[index] = Self::HOOKS[index].0;
+            index += 1;
+        }
+        ids
+    }
+
+    /// Native hooks that check files whatever their content
+    ///
+    /// Other native hooks read files as text, so they skip files
+    /// `.gitattributes` marks as binary or generated.
+    pub const ANY_CONTENT_HOOKS: &'static [&'static str] = &[
+        "check-added-large-files",
+        "check-case-conflict",
+        "check-lockfile-sync",
+        "check-cargo-lockfile-sync",
+        "check-node-lockfile-sync",
+        "check-python-lockfile-sync",
+        "commit-msg-conventional",
+        "branch-name-lint",
+    ];
+
+    /// Native hooks that modify files, with the arguments that make them do so
+    ///
+    /// Hooks with several spellings of their arguments are listed once per spelling.
+    pub const FIXERS: &'static [(&'static str, &'static [&'static str])] = &[
+        ("trailing-whitespace", &[]),
+        ("end-of-file-fixer", &[]),
+        ("license-header", &["--fix"]),
+        ("codespell", &["--write-changes"]),
+        ("codespell", &["-w"]),
+        ("editorconfig", &["--fix"]),
+    ];
+
+    /// Check whether a native hook modifies files when run with these arguments
+    pub fn is_fixer(id: &str, args: &[String]) -> bool {
+        Self::FIXERS
+            .iter()
+            .any(|(fixer, fix_args)| *fixer == id && fix_args.iter().all(|fix_arg| args.iter().any(|arg| arg == fix_arg)))
+    }
+
     /// Create a hook by ID
     pub fn create_hook(id: &str, args: &[String]) -> Result<Box<dyn Hook>, HookError> {
-        match id {
-            "trailing-whitespace" => Ok(Box::new(TrailingWhitespace)),
-            "end-of-file-fixer" => Ok(Box::new(EndOfFileFixer)),
-            "check-yaml" => Ok(Box::new(CheckYaml)),
-            "check-added-large-files" => {
-                // Parse the max size argument
-                let max_size_kb = if let Some(arg) = args.iter().find(|a| a.starts_with("--maxkb=")) {
-                    arg.trim_start_matches("--maxkb=").parse::<usize>().unwrap_or(500)
-                } else {
-                    500 // Default to 500 KB
-                };
-
-                Ok(Box::new(CheckAddedLargeFiles::new(max_size_kb)))
-            },
-            "check-merge-conflict" => Ok(Box::new(CheckMergeConflict)),
-            "check-json" => Ok(Box::new(CheckJson)),
-            "check-toml" => Ok(Box::new(CheckToml)),
-            "check-xml" => Ok(Box::new(CheckXml)),
-            "check-case-conflict" => Ok(Box::new(CheckCaseConflict)),
-            "detect-private-key" => Ok(Box::new(DetectPrivateKey)),
-            _ => Err(HookError::Other(format!("Unknown hook ID: {}", id))),
+        match Self::HOOKS.iter().find(|(hook_id, _)| *hook_id == id) {
+            Some((_, create)) => create(args),
+            None => Err(HookError::Other(format!("Unknown hook ID: {}", id))),
         }
     }
 }
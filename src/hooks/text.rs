@@ -0,0 +1,168 @@
+//! Text decoding for native hooks
+//!
+//! Native hooks that read files as text decode them here, so files in UTF-16
+//! or with a byte order mark are checked and fixed like UTF-8 files, instead
+//! of failing to read or being mangled. The encoding is taken from the byte
+//! order mark:
+//!
+//! - `EF BB BF`: UTF-8
+//! - `FF FE`: UTF-16 little endian
+//! - `FE FF`: UTF-16 big endian
+//! - none: UTF-8
+//!
+//! The byte order mark isn't part of the decoded [`Text`], and
+//! [`Text::encode`] writes text back in the file's encoding with its byte
+//! order mark, so unchanged text round-trips to the same bytes.
+//!
+//! Files that can't be decoded, like binary files or files in other
+//! encodings, give a [`DecodeError`] saying where decoding failed, instead of
+//! an IO error failing the whole hook. Checkers report it as a problem of the
+//! file, and fixers leave the file alone.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// UTF-8 byte order mark
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+/// UTF-16 little endian byte order mark
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+
+/// UTF-16 big endian byte order mark
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Encoding of a text file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// UTF-8
+    Utf8,
+    /// UTF-16 little endian
+    Utf16Le,
+    /// UTF-16 big endian
+    Utf16Be,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Encoding::Utf8 => write!(f, "UTF-8"),
+            Encoding::Utf16Le => write!(f, "UTF-16LE"),
+            Encoding::Utf16Be => write!(f, "UTF-16BE"),
+        }
+    }
+}
+
+/// The decoded content of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    /// Content, without the byte order mark
+    pub content: String,
+    /// Encoding of the file
+    pub encoding: Encoding,
+    /// Whether the file starts with a byte order mark
+    pub bom: bool,
+}
+
+/// Why a file couldn't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Encoding the file was decoded as
+    pub encoding: Encoding,
+    /// Offset of the first byte that couldn't be decoded
+    pub offset: usize,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "not {} text (undecodable byte at offset {})", self.encoding, self.offset)
+    }
+}
+
+/// Decode the content of a file
+///
+/// UTF-8 content with NUL bytes is binary, not text.
+pub fn decode(bytes: &[u8]) -> Result<Text, DecodeError> {
+    if let Some(rest) = bytes.strip_prefix(UTF16LE_BOM) {
+        return decode_utf16(rest, Encoding::Utf16Le, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(UTF16BE_BOM) {
+        return decode_utf16(rest, Encoding::Utf16Be, u16::from_be_bytes);
+    }
+    let (rest, bom) = match bytes.strip_prefix(UTF8_BOM) {
+        Some(rest) => (rest, true),
+        None => (bytes, false),
+    };
+    let error = |offset: usize| DecodeError { encoding: Encoding::Utf8, offset: offset + bom as usize * UTF8_BOM.len() };
+    if let Some(offset) = rest.iter().position(|byte| *byte == 0) {
+        return Err(error(offset));
+    }
+    match std::str::from_utf8(rest) {
+        Ok(content) => Ok(Text { content: content.to_string(), encoding: Encoding::Utf8, bom }),
+        Err(e) => Err(error(e.valid_up_to())),
+    }
+}
+
+/// Decode UTF-16 content following its byte order mark
+fn decode_utf16(bytes: &[u8], encoding: Encoding, unit: fn([u8; 2]) -> u16) -> Result<Text, DecodeError> {
+    let error = |index: usize| DecodeError { encoding, offset: UTF16LE_BOM.len() + index * 2 };
+    if !bytes.len().is_multiple_of(2) {
+        return Err(error(bytes.len() / 2));
+    }
+    let units: Vec<u16> = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]])).collect();
+    let mut content = String::with_capacity(units.len());
+    let mut index = 0;
+    for decoded in char::decode_utf16(units.iter().copied()) {
+        match decoded {
+            Ok(c) => {
+                content.push(c);
+                index += c.len_utf16();
+            }
+            Err(_) => return Err(error(index)),
+        }
+    }
+    Ok(Text { content, encoding, bom: true })
+}
+
+impl Text {
+    /// Encode content in the encoding of this text, with its byte order mark
+    pub fn encode(&self, content: &str) -> Vec<u8> {
+        match self.encoding {
+            Encoding::Utf8 => {
+                let bom = if self.bom { UTF8_BOM } else { &[] };
+                [bom, content.as_bytes()].concat()
+            }
+            Encoding::Utf16Le => UTF16LE_BOM.iter().copied().chain(content.encode_utf16().flat_map(u16::to_le_bytes)).collect(),
+            Encoding::Utf16Be => UTF16BE_BOM.iter().copied().chain(content.encode_utf16().flat_map(u16::to_be_bytes)).collect(),
+        }
+    }
+}
+
+/// Read and decode a file
+///
+/// The outer error is a failure to read the file, the inner one a file that
+/// isn't text, which hooks report as a problem of the file or skip.
+pub fn read_text(path: &Path) -> io::Result<Result<Text, DecodeError>> {
+    Ok(decode(&fs::read(path)?))
+}
+
+/// Check whether content starts with a UTF-16 byte order mark
+pub fn is_utf16(bytes: &[u8]) -> bool {
+    bytes.starts_with(UTF16LE_BOM) || bytes.starts_with(UTF16BE_BOM)
+}
+
+/// Check whether a file starts with a UTF-16 byte order mark, reading only that
+pub fn is_utf16_file(path: &Path) -> io::Result<bool> {
+    let mut bom = [0; 2];
+    let read = fs::File::open(path)?.read(&mut bom)?;
+    Ok(is_utf16(&bom[..read]))
+}
+
+/// Read a UTF-16 file, for fixers that stream UTF-8 files as bytes
+///
+/// Returns `None` for files that aren't UTF-16 text.
+pub fn read_utf16(path: &Path) -> io::Result<Option<Text>> {
+    let bytes = fs::read(path)?;
+    Ok(if is_utf16(&bytes) { decode(&bytes).ok() } else { None })
+}
@@ -1,18 +1,113 @@
 //! Implementation of the trailing-whitespace hook
 
-use std::path::PathBuf;
 use std::fs;
-use crate::hooks::common::{Hook, HookError};
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use crate::hooks::common::{for_each_line, is_text, rewrite_file, Hook, HookError};
+use crate::hooks::text::read_utf16;
 
 /// Trim trailing whitespace
+///
+/// Files are processed as bytes: each line keeps its own line ending, a
+/// missing final newline stays missing, and files that aren't text are
+/// skipped. UTF-8 files are streamed line by line, so large files are never
+/// held in memory. UTF-16 files are decoded, fixed and written back in
+/// UTF-16 with their byte order mark.
 pub struct TrailingWhitespace;
 
+/// What a file needs
+enum Scan {
+    /// The file isn't UTF-8 text
+    NotText,
+    /// The file has no trailing whitespace
+    Clean,
+    /// Some lines have trailing whitespace
+    NeedsFix,
+}
+
+impl TrailingWhitespace {
+    /// Remove the trailing whitespace of every line, keeping line endings
+    pub fn fix(content: &[u8]) -> Vec<u8> {
+        let mut fixed = Vec::with_capacity(content.len());
+        for line in content.split_inclusive(|byte| *byte == b'\n') {
+            let ending: &[u8] = if line.ends_with(b"\r\n") {
+                b"\r\n"
+            } else if line.ends_with(b"\n") {
+                b"\n"
+            } else {
+                b""
+            };
+            fixed.extend_from_slice(line[..line.len() - ending.len()].trim_ascii_end());
+            fixed.extend_from_slice(ending);
+        }
+        fixed
+    }
+
+    /// Check whether a file is text with trailing whitespace, stopping at the first line that isn't text
+    fn scan(file: &Path) -> io::Result<Scan> {
+        let mut scan = Scan::Clean;
+        for_each_line(file, |line| {
+            if !is_text(line) {
+                scan = Scan::NotText;
+                return ControlFlow::Break(());
+            }
+            if Self::fix(line) != line {
+                scan = Scan::NeedsFix;
+            }
+            ControlFlow::Continue(())
+        })?;
+        Ok(scan)
+    }
+
+    /// Fix a file that isn't UTF-8, if it is UTF-16 text
+    fn fix_utf16(file: &Path) -> io::Result<()> {
+        let Some(text) = read_utf16(file)? else {
+            log::info!("Skipping {}: not a text file", file.display());
+            return Ok(());
+        };
+        let fixed = Self::fix(text.content.as_bytes());
+        if fixed != text.content.as_bytes() {
+            // Only ASCII whitespace is removed, so the content stays UTF-8
+            rewrite_file(file, &text.encode(&String::from_utf8_lossy(&fixed)))?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite a file with its trailing whitespace removed
+    ///
+    /// The fixed lines are written to a temporary file next to it, then copied
+    /// over the file in place, so its mode bits, owner and hard links are kept.
+    fn rewrite(file: &Path) -> io::Result<()> {
+        let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut fixed = io::BufWriter::new(tempfile::NamedTempFile::new_in(dir)?);
+        let mut result = Ok(());
+        for_each_line(file, |line| match fixed.write_all(&Self::fix(line)) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => {
+                result = Err(e);
+                ControlFlow::Break(())
+            }
+        })?;
+        result?;
+        let fixed = fixed.into_inner().map_err(|e| e.into_error())?;
+
+        let mut target = fs::OpenOptions::new().write(true).truncate(true).open(file)?;
+        io::copy(&mut fixed.reopen()?, &mut target)?;
+        target.flush()
+    }
+}
+
 impl Hook for TrailingWhitespace {
     fn run(&self, files: &[PathBuf]) -> Result<(), HookError> {
         for file in files {
             // Read the file
-            let content = match fs::read(file) {
-                Ok(content) => content,
+            let scan = match Self::scan(file) {
+                Ok(scan) => scan,
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    log::info!("Skipping {}: {}", file.display(), e);
+                    continue;
+                }
                 Err(e) => {
                     if e.kind() == std::io::ErrorKind::PermissionDenied {
                         // Skip files that can't be accessed due to permission issues
@@ -23,35 +118,24 @@ impl Hook for TrailingWhitespace {
                     }
                 }
             };
-            let content = String::from_utf8_lossy(&content);
-
-            // Check if the file has trailing whitespace
-            let mut has_trailing_whitespace = false;
-            let mut new_content = String::new();
-
-            for line in content.lines() {
-                let trimmed = line.trim_end();
-                if trimmed.len() != line.len() {
-                    has_trailing_whitespace = true;
-                }
-                new_content.push_str(trimmed);
-                new_content.push('\n');
-            }
 
-            // If the file has trailing whitespace, fix it
-            if has_trailing_whitespace {
-                if let Err(e) = fs::write(file, new_content) {
-                    if e.kind() == std::io::ErrorKind::PermissionDenied {
-                        // Skip files that can't be written to due to permission issues
-                        log::warn!("Skipping file write due to permission denied: {}", file.display());
-                        continue;
-                    } else {
-                        return Err(HookError::IoError(e));
-                    }
+            let fixed = match scan {
+                // Rewriting binary files as text would corrupt them
+                Scan::NotText => Self::fix_utf16(file),
+                Scan::Clean => Ok(()),
+                // If the file has trailing whitespace, fix it
+                Scan::NeedsFix => Self::rewrite(file),
+            };
+            if let Err(e) = fixed {
+                if e.kind() == std::io::ErrorKind::PermissionDenied {
+                    // Skip files that can't be written to due to permission issues
+                    log::warn!("Skipping file write due to permission denied: {}", file.display());
+                } else {
+                    return Err(HookError::IoError(e));
                 }
             }
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
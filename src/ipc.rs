@@ -0,0 +1,427 @@
+//! Local IPC transport for RustyHook
+//!
+//! A long-running hook server and its clients talk over one of these
+//! transports, picked per platform:
+//!
+//! - Unix domain sockets on Unix
+//! - Named pipes on Windows
+//! - TCP on the loopback interface, where neither is available (such as a
+//!   socket path too long for the platform)
+//!
+//! The server writes its endpoint next to a per-user token file in the cache
+//! root. Every client must send the token before anything else, so other
+//! local users can't drive the server even if they can reach the endpoint.
+//!
+//! The handshake is a single line each way: the client sends `AUTH <token>`
+//! and the server answers `OK`, or `DENIED` before closing the connection.
+//! Handshakes run in their own tasks, so a client that sends nothing only
+//! holds up itself.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+
+/// Directory of the cache root holding the endpoint and token files
+pub const IPC_DIR: &str = "ipc";
+
+/// File holding the server's endpoint
+pub const ENDPOINT_FILE: &str = "endpoint";
+
+/// File holding the token clients authenticate with
+pub const TOKEN_FILE: &str = "token";
+
+/// Longest socket path that is safe on every Unix (macOS allows 104 bytes)
+const MAX_SOCKET_PATH: usize = 100;
+
+/// Longest handshake line a server reads
+const MAX_HANDSHAKE: usize = 256;
+
+/// How long a server waits for a client's handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Most handshakes a server runs at once, and authenticated connections it keeps waiting
+const MAX_PENDING_HANDSHAKES: usize = 64;
+
+/// A bidirectional connection between a server and a client
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+/// An authenticated connection
+pub type Connection = Box<dyn Stream>;
+
+/// Where a server listens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A Unix domain socket
+    Unix(PathBuf),
+    /// A Windows named pipe, such as `\\.\pipe\rustyhook-1234`
+    NamedPipe(String),
+    /// A TCP port on the loopback interface
+    Tcp(SocketAddr),
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::NamedPipe(name) => write!(f, "pipe:{}", name),
+            Endpoint::Tcp(addr) => write!(f, "tcp:{}", addr),
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid IPC endpoint: {}", s));
+        let (transport, address) = s.trim().split_once(':').ok_or_else(invalid)?;
+        match transport {
+            "unix" => Ok(Endpoint::Unix(PathBuf::from(address))),
+            "pipe" => Ok(Endpoint::NamedPipe(address.to_string())),
+            "tcp" => {
+                let addr: SocketAddr = address.parse().map_err(|_| invalid())?;
+                // Never talk to another machine
+                if !addr.ip().is_loopback() {
+                    return Err(invalid());
+                }
+                Ok(Endpoint::Tcp(addr))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Pick the endpoint a server for a cache root listens on
+///
+/// TCP endpoints use port 0 until they are bound.
+pub fn default_endpoint(cache_root: &Path) -> Endpoint {
+    if cfg!(windows) {
+        // Pipes live in a global namespace, so name them after the cache root
+        let hash: String = Sha256::digest(cache_root.to_string_lossy().as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        return Endpoint::NamedPipe(format!(r"\\.\pipe\rustyhook-{}", hash));
+    }
+
+    let socket = cache_root.join(IPC_DIR).join("rustyhook.sock");
+    if cfg!(unix) && socket.as_os_str().len() <= MAX_SOCKET_PATH {
+        Endpoint::Unix(socket)
+    } else {
+        Endpoint::Tcp(SocketAddr::from(([127, 0, 0, 1], 0)))
+    }
+}
+
+/// Generate a random token
+fn generate_token() -> String {
+    let mut hasher = Sha256::new();
+
+    // The OS random source where there is one, and process-seeded hashers everywhere
+    #[cfg(unix)]
+    if let Ok(mut urandom) = fs::File::open("/dev/urandom") {
+        let mut bytes = [0u8; 32];
+        if io::Read::read_exact(&mut urandom, &mut bytes).is_ok() {
+            hasher.update(bytes);
+        }
+    }
+    for _ in 0..4 {
+        let mut random = RandomState::new().build_hasher();
+        random.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos());
+        random.write_u32(std::process::id());
+        hasher.update(random.finish().to_le_bytes());
+    }
+
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Write a file only the current user can read
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        use std::io::Write;
+        let _ = fs::remove_file(path);
+        let mut file = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+        file.write_all(content.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        fs::write(path, content)
+    }
+}
+
+/// Check that a file can only be read by the current user
+fn is_private(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Other users reading the token could connect as us
+        fs::metadata(path).is_ok_and(|metadata| metadata.mode() & 0o077 == 0)
+    }
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Get the token of a cache root, creating it if needed
+///
+/// A token other users could read is replaced.
+pub fn load_or_create_token(cache_root: &Path) -> io::Result<String> {
+    let path = cache_root.join(IPC_DIR).join(TOKEN_FILE);
+    if is_private(&path) {
+        let token = fs::read_to_string(&path)?.trim().to_string();
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    } else if path.exists() {
+        log::warn!("Replacing {}: other users can read it", path.display());
+    }
+
+    let token = generate_token();
+    write_private(&path, &token)?;
+    Ok(token)
+}
+
+/// Compare tokens without leaking how much of them matched through timing
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// The listening side of a transport
+enum Transport {
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+    #[cfg(windows)]
+    NamedPipe {
+        name: String,
+        next: tokio::net::windows::named_pipe::NamedPipeServer,
+    },
+    Tcp(TcpListener),
+}
+
+impl Transport {
+    /// Accept the next raw connection
+    async fn accept(&mut self) -> io::Result<Connection> {
+        match self {
+            #[cfg(unix)]
+            Transport::Unix(listener) => Ok(Box::new(listener.accept().await?.0)),
+            #[cfg(windows)]
+            Transport::NamedPipe { name, next } => {
+                next.connect().await?;
+                // Create the next instance before handing this one out, so clients never find no pipe
+                let following = tokio::net::windows::named_pipe::ServerOptions::new()
+                    .reject_remote_clients(true)
+                    .create(name.as_str())?;
+                Ok(Box::new(std::mem::replace(next, following)))
+            }
+            Transport::Tcp(listener) => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+/// A server accepting authenticated connections
+///
+/// A background task accepts connections and runs each handshake in a task
+/// of its own, handing out the authenticated connections through a channel.
+pub struct Listener {
+    /// Where the server listens, with the actual port for TCP
+    endpoint: Endpoint,
+    /// Authenticated connections, and the errors accepting connections
+    accepted: mpsc::Receiver<io::Result<Connection>>,
+    /// The task accepting connections
+    acceptor: JoinHandle<()>,
+}
+
+impl Listener {
+    /// Listen on the default endpoint of a cache root, falling back to TCP
+    ///
+    /// The endpoint and token are written to the cache root's `ipc` directory
+    /// so clients can find the server.
+    pub async fn bind(cache_root: &Path) -> io::Result<Self> {
+        let token = load_or_create_token(cache_root)?;
+        let listener = match Self::bind_endpoint(&default_endpoint(cache_root), token.clone()).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::warn!("Could not listen on {}, using TCP on the loopback interface: {}", default_endpoint(cache_root), e);
+                Self::bind_endpoint(&Endpoint::Tcp(SocketAddr::from(([127, 0, 0, 1], 0))), token).await?
+            }
+        };
+        write_private(&cache_root.join(IPC_DIR).join(ENDPOINT_FILE), &listener.endpoint.to_string())?;
+        Ok(listener)
+    }
+
+    /// Listen on an endpoint, accepting clients that send `token`
+    pub async fn bind_endpoint(endpoint: &Endpoint, token: String) -> io::Result<Self> {
+        let (endpoint, transport) = match endpoint {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                use std::os::unix::fs::PermissionsExt;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                // A socket left behind by a server that crashed blocks binding
+                let _ = fs::remove_file(path);
+                let listener = tokio::net::UnixListener::bind(path)?;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+                (endpoint.clone(), Transport::Unix(listener))
+            }
+            #[cfg(windows)]
+            Endpoint::NamedPipe(name) => {
+                let next = tokio::net::windows::named_pipe::ServerOptions::new()
+                    .first_pipe_instance(true)
+                    .reject_remote_clients(true)
+                    .create(name)?;
+                (endpoint.clone(), Transport::NamedPipe { name: name.clone(), next })
+            }
+            Endpoint::Tcp(addr) => {
+                if !addr.ip().is_loopback() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "IPC only listens on the loopback interface"));
+                }
+                let listener = TcpListener::bind(addr).await?;
+                (Endpoint::Tcp(listener.local_addr()?), Transport::Tcp(listener))
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} is not supported on this platform", endpoint),
+                ))
+            }
+        };
+        let (sender, accepted) = mpsc::channel(MAX_PENDING_HANDSHAKES);
+        let acceptor = tokio::spawn(run_acceptor(transport, endpoint.clone(), Arc::from(token), sender));
+        Ok(Listener { endpoint, accepted, acceptor })
+    }
+
+    /// Get where the server listens
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    /// Accept the next client that authenticates
+    ///
+    /// Clients that send a wrong token, or nothing in time, are turned away.
+    pub async fn accept(&mut self) -> io::Result<Connection> {
+        match self.accepted.recv().await {
+            Some(result) => result,
+            None => Err(io::Error::new(io::ErrorKind::BrokenPipe, format!("The listener on {} stopped", self.endpoint))),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.acceptor.abort();
+    }
+}
+
+/// Accept connections, authenticating each in its own task
+///
+/// At most [`MAX_PENDING_HANDSHAKES`] handshakes run at once; further
+/// connections wait in the transport's backlog. Stops once the listener is gone.
+async fn run_acceptor(mut transport: Transport, endpoint: Endpoint, token: Arc<str>, accepted: mpsc::Sender<io::Result<Connection>>) {
+    let handshakes = Arc::new(Semaphore::new(MAX_PENDING_HANDSHAKES));
+    loop {
+        let Ok(permit) = handshakes.clone().acquire_owned().await else {
+            return;
+        };
+        let connection = match transport.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                if accepted.send(Err(e)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+        let (endpoint, token, accepted) = (endpoint.clone(), token.clone(), accepted.clone());
+        tokio::spawn(async move {
+            let connection = handshake(connection, &token, &endpoint).await;
+            drop(permit);
+            if let Some(connection) = connection {
+                let _ = accepted.send(Ok(connection)).await;
+            }
+        });
+    }
+}
+
+/// Authenticate a client, returning its connection when it sent the token in time
+async fn handshake(mut connection: Connection, token: &str, endpoint: &Endpoint) -> Option<Connection> {
+    match tokio::time::timeout(HANDSHAKE_TIMEOUT, read_line(&mut connection)).await {
+        Ok(Ok(line)) if line.strip_prefix("AUTH ").is_some_and(|given| tokens_match(token, given)) => {
+            connection.write_all(b"OK\n").await.ok()?;
+            Some(connection)
+        }
+        _ => {
+            log::warn!("Rejected an IPC client on {} that did not authenticate", endpoint);
+            let _ = connection.write_all(b"DENIED\n").await;
+            None
+        }
+    }
+}
+
+/// Read one handshake line, without its line ending
+///
+/// Reads a byte at a time, so nothing sent after the line is consumed.
+async fn read_line(connection: &mut Connection) -> io::Result<String> {
+    let mut line = Vec::new();
+    while line.len() < MAX_HANDSHAKE {
+        match connection.read_u8().await? {
+            b'\n' => break,
+            byte => line.push(byte),
+        }
+    }
+    Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+}
+
+/// Connect to an endpoint and authenticate with `token`
+pub async fn connect_endpoint(endpoint: &Endpoint, token: &str) -> io::Result<Connection> {
+    let mut connection: Connection = match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => Box::new(tokio::net::UnixStream::connect(path).await?),
+        #[cfg(windows)]
+        Endpoint::NamedPipe(name) => Box::new(tokio::net::windows::named_pipe::ClientOptions::new().open(name)?),
+        Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).await?),
+        #[allow(unreachable_patterns)]
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} is not supported on this platform", endpoint),
+            ))
+        }
+    };
+
+    connection.write_all(format!("AUTH {}\n", token).as_bytes()).await?;
+    match read_line(&mut connection).await?.as_str() {
+        "OK" => Ok(connection),
+        _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("The server on {} rejected the token", endpoint))),
+    }
+}
+
+/// Connect to the server of a cache root
+pub async fn connect(cache_root: &Path) -> io::Result<Connection> {
+    let dir = cache_root.join(IPC_DIR);
+    let endpoint: Endpoint = fs::read_to_string(dir.join(ENDPOINT_FILE))?.parse()?;
+    let token = fs::read_to_string(dir.join(TOKEN_FILE))?;
+    connect_endpoint(&endpoint, token.trim()).await
+}
@@ -6,15 +6,26 @@ pub mod config;
 pub mod toolchains;
 pub mod runner;
 pub mod cache;
+pub mod completion;
+pub mod error_codes;
 pub mod hooks;
+pub mod ipc;
 pub mod logging;
+pub mod messages;
+pub mod shim;
+pub mod testing;
 
-use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell as ClapShell};
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use log::{debug, info, warn, error};
-use tokio;
+use error_codes::HasErrorCode;
+use messages::MessageId;
+
+/// Names the CLI is installed under: the full name and the short alias
+pub const BIN_NAMES: &[&str] = &["rustyhook", "rh"];
 
 /// Supported shells for completion script generation
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
@@ -29,6 +40,15 @@ pub enum Shell {
     PowerShell,
 }
 
+/// Graph formats for the execution plan
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
 #[derive(Parser)]
 #[command(
     name = "rustyhook",
@@ -40,9 +60,17 @@ pub enum Shell {
 #[command(propagate_version = true)]
 pub struct Cli {
     /// Maximum number of hooks to run in parallel (0 means unlimited)
-    #[arg(short, long, default_value_t = 0)]
+    #[arg(short, long, visible_alias = "jobs", default_value_t = 0)]
     pub parallelism: usize,
 
+    /// Maximum number of hook environments to set up in parallel (0 means unlimited)
+    #[arg(long)]
+    pub setup_parallelism: Option<usize>,
+
+    /// Maximum number of hooks to run in parallel once set up (0 means unlimited)
+    #[arg(long)]
+    pub run_parallelism: Option<usize>,
+
     /// Path to the log file (if not specified, logs will only go to stdout)
     #[arg(long)]
     pub log_file: Option<PathBuf>,
@@ -51,21 +79,143 @@ pub struct Cli {
     #[arg(long, default_value = "info")]
     pub log_level: String,
 
+    /// Path to the config file (overrides RUSTYHOOK_CONFIG and .rustyhook/config.yaml)
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     /// Comma-separated list of hook IDs to skip
     #[arg(long)]
     pub skip: Option<String>,
 
+    /// Only check the files changed between the merge base of this ref and HEAD, like `git diff REF...HEAD`
+    #[arg(long, value_name = "REF")]
+    pub changed_since: Option<String>,
+
+    /// Directory for cached environments and tool installs (overrides RUSTYHOOK_CACHE_DIR and the config)
+    #[arg(long)]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Fail instead of warning when a repo is pinned to a mutable ref
+    #[arg(long)]
+    pub frozen: bool,
+
+    /// Print the diff of files changed by hooks when the run fails or hooks modify files
+    #[arg(long)]
+    pub show_diff_on_failure: bool,
+
+    /// Print the argv, working directory and environment changes of failing hook processes
+    #[arg(long)]
+    pub verbose_errors: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
 
+/// Commands reading the audit log
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum AuditCommand {
+    /// Print the entries of the audit log
+    Show {
+        /// Only print this many of the latest entries
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Print the entries as JSON lines, as they are stored
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Check that no entry of the audit log was changed, removed or reordered
+    Verify,
+}
+
+/// Options choosing the files a run checks
+#[derive(Args, Debug, Clone, Default, PartialEq)]
+pub struct FileArgs {
+    /// Check every file Git tracks in the current directory, even with --changed-since or in Git hooks
+    #[arg(long, conflicts_with_all = ["files", "from_ref"])]
+    pub all_files: bool,
+
+    /// Check only these files, separated by spaces or commas
+    #[arg(long, num_args = 1.., value_delimiter = ',')]
+    pub files: Vec<PathBuf>,
+
+    /// Check the files changed between the merge base of this ref and --to-ref, like `git diff FROM...TO`
+    #[arg(long, value_name = "REF", requires = "to_ref", conflicts_with = "files")]
+    pub from_ref: Option<String>,
+
+    /// The ref --from-ref compares with
+    #[arg(long, value_name = "REF", requires = "from_ref")]
+    pub to_ref: Option<String>,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Run hooks using native config if present
-    Run,
+    Run {
+        /// Also run the .pre-commit-config.yaml hooks the native config doesn't define
+        #[arg(long)]
+        with_compat: bool,
+
+        /// Record the files, environment, commands and results of the run into this directory
+        #[arg(long)]
+        record: Option<PathBuf>,
+
+        /// Fail instead of downloading anything, and cut hook processes off from the network on Linux
+        #[arg(long)]
+        forbid_network: bool,
+
+        /// Write durations, failures and environment cache hits of the run to this JSON file
+        #[arg(long)]
+        metrics_file: Option<PathBuf>,
+
+        /// Warn about hooks declared `access_mode: Read` that write files (runs them one at a time)
+        #[arg(long)]
+        verify_access_modes: bool,
+
+        /// Run hooks one at a time in plan order, on files in sorted order, for reproducible runs
+        #[arg(long)]
+        deterministic: bool,
+
+        /// Print how long each hook took, setting up its environment apart from running
+        #[arg(long)]
+        timings: bool,
+
+        /// Run on the files of this commit, checked out into a temporary worktree, instead of the working tree
+        #[arg(long, conflicts_with_all = ["all_files", "files", "from_ref"])]
+        rev: Option<String>,
+
+        /// Only run the hooks of this stage, like pre-commit or pre-push (installed Git hooks pass their own)
+        #[arg(long, value_name = "STAGE")]
+        hook_stage: Option<String>,
+
+        /// Arguments Git passed to the hook, which installed hook scripts forward
+        #[arg(last = true, hide = true, requires = "hook_stage")]
+        hook_args: Vec<String>,
+
+        #[command(flatten)]
+        file_args: FileArgs,
+    },
+
+    /// Run the hooks of a recorded run again, on the recorded files
+    Replay {
+        /// Directory written by `run --record`
+        dir: PathBuf,
+    },
 
     /// Run hooks using .pre-commit-config.yaml
-    Compat,
+    Compat {
+        /// Only run the hooks of this stage, like pre-commit or pre-push
+        #[arg(long, value_name = "STAGE")]
+        hook_stage: Option<String>,
+
+        /// Arguments Git passed to the hook, as for `run`
+        #[arg(last = true, hide = true, requires = "hook_stage")]
+        hook_args: Vec<String>,
+
+        #[command(flatten)]
+        file_args: FileArgs,
+    },
 
     /// Convert pre-commit config to .rustyhook/config.yaml
     Convert {
@@ -82,17 +232,114 @@ pub enum Commands {
         config_path: Option<PathBuf>,
     },
 
+    /// Pin every repo rev in .pre-commit-config.yaml to a commit SHA
+    Freeze {
+        /// Path to the pre-commit config file
+        #[arg(long)]
+        config_path: Option<PathBuf>,
+    },
+
+    /// Show the order hooks would run in without running them
+    Plan {
+        /// Plan the hooks in .pre-commit-config.yaml instead of the native config
+        #[arg(long)]
+        compat: bool,
+
+        /// Print the plan as a graph instead of text
+        #[arg(long, value_enum)]
+        graph: Option<GraphFormat>,
+    },
+
     /// Create a starter .rustyhook/config.yaml
-    Init,
+    Init {
+        /// Rediscover workspace members and update the `projects` of the existing config
+        #[arg(long)]
+        refresh_projects: bool,
+
+        /// Create the config from a template: python-service, node-app, rust-crate, terraform-module, a path, or an https:// URL ending in #sha256=<checksum>
+        #[arg(long, conflicts_with = "refresh_projects")]
+        template: Option<String>,
+
+        /// Set a variable of the template, as name=value
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = config::templates::parse_var, requires = "template")]
+        vars: Vec<(String, String)>,
+    },
 
     /// List all available hooks and their status
     List,
 
+    /// Report whether the Git hook is installed, the config is valid and environments are ready
+    Status {
+        /// Print stable `key value` lines for shell prompts
+        #[arg(long)]
+        porcelain: bool,
+    },
+
+    /// Check that the config file is valid
+    Validate {
+        /// Also flag config patterns that slow runs down, with suggested edits
+        #[arg(long)]
+        perf: bool,
+    },
+
+    /// Run one hook in many repositories, with a report of each
+    MultiRun {
+        /// File listing the repositories, one path per line
+        #[arg(long, value_name = "FILE")]
+        repos_file: PathBuf,
+
+        /// ID of the hook to run, from each repository's config or a native hook
+        #[arg(long, value_name = "ID")]
+        hook: String,
+
+        /// Maximum number of repositories checked at once (default: number of CPUs)
+        #[arg(short, long, default_value_t = 0)]
+        jobs: usize,
+
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show or verify the audit log of runs
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+
     /// Diagnose issues with setup or environments
-    Doctor,
+    Doctor {
+        /// Check that installed Git hooks and converted configs match the config
+        #[arg(long)]
+        check_config_drift: bool,
+
+        /// Print the findings as JSON, grouped by category
+        #[arg(long)]
+        json: bool,
+
+        /// Exit with an error when warnings are found, not only errors
+        #[arg(long)]
+        strict: bool,
+    },
 
     /// Remove cached environments and tool installs
-    Clean,
+    Clean {
+        /// Only remove the environments of hooks that are no longer configured
+        #[arg(long)]
+        prune: bool,
+
+        /// With --prune, keep environments used within this many days
+        #[arg(long, default_value_t = 7, requires = "prune")]
+        grace_days: u64,
+
+        /// Remove leftover hook containers and the container images RustyHook pulled, instead of environments
+        #[arg(long, conflicts_with = "prune")]
+        containers: bool,
+
+        /// Remove runtimes earlier versions left in the repository without asking
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
 
     /// Generate shell completion scripts
     Completions {
@@ -127,9 +374,214 @@ pub enum Commands {
     },
 }
 
+/// Which files a run checks
+#[derive(Debug, Default, Clone, PartialEq)]
+enum FileSelection {
+    /// Every file Git tracks in the current directory
+    #[default]
+    AllFiles,
+    /// The files staged in Git
+    Staged,
+    /// The given files
+    Files(Vec<PathBuf>),
+    /// The files changed between the merge base of a ref and HEAD
+    ChangedSince(String),
+    /// The files changed between the merge base of two refs and the second one
+    Range {
+        /// Ref to compare from
+        from: String,
+        /// Ref to compare to
+        to: String,
+    },
+}
+
+impl FileSelection {
+    /// Get the selection of the run options of a command, and `--changed-since`
+    ///
+    /// Explicit files win over a range, and `--all-files` over `--changed-since`.
+    fn from_args(file_args: Option<&FileArgs>, changed_since: Option<&str>) -> Self {
+        let file_args = file_args.cloned().unwrap_or_default();
+        if !file_args.files.is_empty() {
+            let current_dir = std::env::current_dir().unwrap_or_default();
+            return FileSelection::Files(file_args.files.iter().map(|file| current_dir.join(file)).collect());
+        }
+        if let (Some(from), Some(to)) = (file_args.from_ref, file_args.to_ref) {
+            return FileSelection::Range { from, to };
+        }
+        match changed_since {
+            Some(rev) if !file_args.all_files => FileSelection::ChangedSince(rev.to_string()),
+            _ => FileSelection::AllFiles,
+        }
+    }
+}
+
+/// Options for a hook run
+#[derive(Debug, Default, Clone)]
+struct RunOptions {
+    /// Path to the config file
+    config: Option<PathBuf>,
+    /// Maximum number of hooks to run in parallel (0 means the config value)
+    parallelism: usize,
+    /// Maximum number of environments to set up in parallel, instead of the config value
+    setup_parallelism: Option<usize>,
+    /// Maximum number of hooks to run in parallel once set up, instead of the config value
+    run_parallelism: Option<usize>,
+    /// Comma-separated list of hook IDs to skip
+    skip: Option<String>,
+    /// Fail when a repo is pinned to a mutable ref
+    frozen: bool,
+    /// Directory for cached environments and tool installs
+    cache_dir: Option<PathBuf>,
+    /// Print the diff of files changed by hooks
+    show_diff_on_failure: bool,
+    /// Files to check
+    selection: FileSelection,
+    /// Only run the hook with this ID
+    hook_id: Option<String>,
+    /// Only run the hooks of this stage
+    hook_stage: Option<String>,
+    /// What Git passed to the hook of the stage
+    stage_input: runner::StageInput,
+    /// Directory to record the run into
+    record: Option<PathBuf>,
+    /// File to write the metrics of the run to
+    metrics_file: Option<PathBuf>,
+    /// Check that read-only hooks don't write files
+    verify_access_modes: bool,
+    /// Run hooks one at a time on sorted files
+    deterministic: bool,
+    /// Print the setup and execution time of each hook
+    timings: bool,
+}
+
+impl RunOptions {
+    /// Get the run options from the global CLI options
+    fn from_cli(cli: &Cli) -> Self {
+        RunOptions {
+            config: cli.config.clone(),
+            parallelism: cli.parallelism,
+            setup_parallelism: cli.setup_parallelism,
+            run_parallelism: cli.run_parallelism,
+            skip: cli.skip.clone(),
+            selection: FileSelection::from_args(
+                match &cli.command {
+                    Commands::Run { file_args, .. } | Commands::Compat { file_args, .. } => Some(file_args),
+                    _ => None,
+                },
+                cli.changed_since.as_deref(),
+            ),
+            frozen: cli.frozen,
+            cache_dir: cli.cache_dir.clone(),
+            show_diff_on_failure: cli.show_diff_on_failure,
+            record: match &cli.command {
+                Commands::Run { record, .. } => record.clone(),
+                _ => None,
+            },
+            metrics_file: match &cli.command {
+                Commands::Run { metrics_file, .. } => metrics_file.clone(),
+                _ => None,
+            },
+            verify_access_modes: matches!(cli.command, Commands::Run { verify_access_modes: true, .. }),
+            deterministic: matches!(cli.command, Commands::Run { deterministic: true, .. }),
+            timings: matches!(cli.command, Commands::Run { timings: true, .. }),
+            hook_stage: match &cli.command {
+                Commands::Run { hook_stage: Some(stage), .. } | Commands::Compat { hook_stage: Some(stage), .. } => {
+                    Some(config::parser::normalize_stage(stage).to_string())
+                }
+                _ => None,
+            },
+            ..Default::default()
+        }
+        .with_stage_input(cli)
+    }
+
+    /// Read what Git passed to the hook of the stage, and check the files it designates
+    ///
+    /// Without such files, the staged files are checked, as pre-commit does,
+    /// except for pushes, which check every file of a new history.
+    /// Files or refs given on the command line, and `--all-files`, win over
+    /// the input.
+    fn with_stage_input(mut self, cli: &Cli) -> Self {
+        let (
+            Some(stage),
+            Commands::Run { hook_args, file_args, .. } | Commands::Compat { hook_args, file_args, .. },
+        ) = (&self.hook_stage, &cli.command)
+        else {
+            return self;
+        };
+        let current_dir = std::env::current_dir().unwrap_or_default();
+        if !hook_args.is_empty() {
+            self.stage_input = match runner::StageInput::read(stage, hook_args, io::stdin().lock(), &current_dir) {
+                Ok(input) => input,
+                Err(e) => {
+                    error!("Could not read the input of the {} hook: {:?}", stage, e);
+                    std::process::exit(1);
+                }
+            };
+        }
+        if self.selection == FileSelection::AllFiles && !file_args.all_files {
+            self.selection = if let Some(files) = self.stage_input.files() {
+                FileSelection::Files(files.iter().map(|file| current_dir.join(file)).collect())
+            } else if let Some((from, to)) = self.stage_input.range() {
+                FileSelection::Range { from: from.to_string(), to: to.to_string() }
+            } else if matches!(self.stage_input, runner::StageInput::PrePush { .. }) {
+                // A push of a new history checks every file
+                FileSelection::AllFiles
+            } else {
+                FileSelection::Staged
+            };
+        }
+        self
+    }
+}
+
+/// Get the name the binary was invoked as, `rustyhook` unless it was `rh`
+fn invoked_name() -> &'static str {
+    let arg0 = std::env::args_os().next().unwrap_or_default();
+    match Path::new(&arg0).file_stem().and_then(|stem| stem.to_str()) {
+        Some("rh") => "rh",
+        _ => "rustyhook",
+    }
+}
+
+/// Get the command line of the CLI under one of its names
+///
+/// Both names accept the same arguments; only usage differs.
+pub fn cli_command(bin_name: &'static str) -> clap::Command {
+    Cli::command().bin_name(bin_name)
+}
+
+/// Get the path hook scripts should run, as the user invoked it
+///
+/// `current_exe` resolves symlinks, so a script installed through an `rh`
+/// symlink would otherwise run the binary it points to.
+fn invoked_binary() -> io::Result<PathBuf> {
+    let arg0 = PathBuf::from(std::env::args_os().next().unwrap_or_default());
+    if arg0.components().count() > 1 {
+        return std::env::current_dir().map(|dir| dir.join(arg0));
+    }
+    match which::which(&arg0) {
+        Ok(path) => Ok(path),
+        Err(_) => std::env::current_exe(),
+    }
+}
+
 /// Main entry point for the RustyHook CLI
 pub fn main() {
-    let cli = Cli::parse();
+    // Behave like pre-commit when invoked through a `pre-commit` symlink
+    if shim::invoked_as_precommit() {
+        return shim::main();
+    }
+
+    // Completion scripts read candidates from stdout, so nothing else may be printed
+    if let Some(words) = completion::complete_args() {
+        for candidate in completion::candidates(&words) {
+            println!("{}", candidate);
+        }
+        return;
+    }
+
+    let cli = Cli::from_arg_matches(&cli_command(invoked_name()).get_matches()).unwrap_or_else(|e| e.exit());
 
     // Initialize the logger
     let log_file = cli.log_file.clone().or_else(|| {
@@ -152,15 +604,27 @@ pub fn main() {
         info!("Logging to file: {}", log_path.display());
     }
     debug!("Log level set to: {}", cli.log_level);
+    toolchains::diagnostics::set_verbose_errors(cli.verbose_errors);
 
     match cli.command {
-        Commands::Run => {
+        Commands::Run { rev: Some(ref rev), .. } => {
+            info!("Running hooks on revision {}...", rev);
+            run_hooks_at_revision(rev, &cli);
+        }
+        Commands::Run { with_compat, forbid_network, .. } => {
             info!("Running hooks using native config...");
-            run_hooks_with_native_config();
+            if forbid_network {
+                toolchains::network::set_forbidden(true);
+            }
+            run_hooks_with_native_config(with_compat, &RunOptions::from_cli(&cli));
         }
-        Commands::Compat => {
+        Commands::Compat { .. } => {
             info!("Running hooks using .pre-commit-config.yaml...");
-            run_hooks_with_compat_config();
+            run_hooks_with_compat_config(&RunOptions::from_cli(&cli));
+        }
+        Commands::Replay { dir } => {
+            info!("Replaying the run recorded in {}...", dir.display());
+            replay_run(&dir, cli.cache_dir.as_deref());
         }
         Commands::Convert { from_precommit, delete_original, config_path } => {
             if from_precommit {
@@ -184,7 +648,29 @@ pub fn main() {
                 warn!("Please specify --from-precommit to convert from pre-commit config");
             }
         }
-        Commands::Init => {
+        Commands::Freeze { config_path } => {
+            info!("Pinning repo revs to commit SHAs...");
+            freeze_revs(config_path);
+        }
+        Commands::Plan { compat, graph } => {
+            debug!("Computing the execution plan...");
+            plan_hooks(compat, graph, &cli);
+        }
+        Commands::Init { refresh_projects: true, .. } => {
+            info!("Refreshing the projects of the configuration...");
+            refresh_config_projects(cli.config.as_deref());
+        }
+        Commands::Init { template: Some(template), vars, .. } => {
+            info!("Creating .rustyhook/config.yaml from the {} template...", template);
+            match config::create_config_from_template::<&str>(&template, &vars, None) {
+                Ok(path) => info!("Created {} from the {} template.", path.display(), template),
+                Err(e) => {
+                    error!("Error creating configuration from template: {:?}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Init { .. } => {
             info!("Creating starter .rustyhook/config.yaml...");
             match config::create_starter_config::<&str>(None) {
                 Ok(_) => info!("Starter configuration created successfully!"),
@@ -193,18 +679,45 @@ pub fn main() {
         }
         Commands::List => {
             info!("Listing all available hooks and their status...");
-            list_hooks();
+            list_hooks(cli.config.as_deref(), cli.cache_dir.as_deref());
+        }
+        Commands::Status { porcelain } => {
+            show_status(porcelain, &cli);
+        }
+        Commands::Validate { perf } => {
+            debug!("Validating the configuration...");
+            validate_config(cli.config.as_deref(), perf);
         }
-        Commands::Doctor => {
-            info!("Diagnosing issues with setup or environments...");
-            diagnose_issues();
+        Commands::MultiRun { ref repos_file, ref hook, jobs, json } => {
+            multi_run(repos_file, hook, jobs, json, cli.cache_dir.as_deref());
         }
-        Commands::Clean => {
-            info!("Removing cached environments and tool installs...");
-            clean_environments();
+        Commands::Audit { ref command } => {
+            audit(command, cli.config.as_deref());
+        }
+        Commands::Doctor { check_config_drift, json, strict } => {
+            if !json {
+                info!("Diagnosing issues with setup or environments...");
+            }
+            diagnose_issues(&cli, check_config_drift, json, strict);
+        }
+        Commands::Clean { prune, grace_days, containers, yes } => {
+            let cache_root = configured_cache_dir(cli.cache_dir.as_deref(), cli.config.as_deref());
+            if containers {
+                info!("Removing hook containers and the images RustyHook pulled...");
+                clean_containers(&cache_root);
+                return;
+            }
+            if prune {
+                info!("Removing environments of hooks that are no longer configured...");
+                prune_environments(&cache_root, cli.config.as_deref(), Duration::from_secs(grace_days * 24 * 60 * 60));
+            } else {
+                info!("Removing cached environments and tool installs...");
+                clean_environments(&cache_root);
+            }
+            clean_legacy_runtime(yes);
         }
         Commands::Completions { shell } => {
-            info!("Generating completion script for {:?}...", shell);
+            debug!("Generating completion script for {:?}...", shell);
             generate_completion_script(shell);
         }
         Commands::Install { hook_type, force } => {
@@ -218,78 +731,143 @@ pub fn main() {
     }
 }
 
+/// Check that hooks may run, exiting when they may not
+///
+/// Running as root is handled as `run_as_root` says, and a config found by
+/// searching the parent directories must belong to the repository being hooked.
+fn guard_run(run_as_root: config::parser::RunAsRoot, searched_config: Option<&Path>, cache_root: &Path) {
+    if let Err(e) = runner::guard::check_root(run_as_root, runner::guard::is_root()) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    if let Some(runtime_dir) = cache::legacy_runtime::find_legacy_runtime(&cwd) {
+        warn!(
+            "{} holds runtimes installed by an earlier version of RustyHook ({}); they now live in {}. Run `rh clean` to remove it.",
+            runtime_dir.display(),
+            toolchains::downloads::format_size(toolchains::downloads::dir_size(&runtime_dir)),
+            cache::runtimes_dir(cache_root).display()
+        );
+    }
+    let Some(repo_root) = runner::guard::repo_root(&cwd) else { return };
+    debug!("Repository root: {}", repo_root.display());
+    if let Some(config_path) = searched_config {
+        if let Err(e) = runner::guard::check_config_repo(config_path, &repo_root) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
 /// Run hooks using native config
-fn run_hooks_with_native_config() {
+fn run_hooks_with_native_config(with_compat: bool, options: &RunOptions) {
     // Find the native config
-    match config::find_config() {
+    match config::find_config_with(options.config.as_deref()) {
         Ok(mut config) => {
-            // Get the CLI options
-            let cli = Cli::parse();
-            if cli.parallelism > 0 {
-                // Override the parallelism limit from the config with the one from the CLI
-                config.parallelism = cli.parallelism;
-                debug!("Overriding parallelism limit to: {}", cli.parallelism);
+            cache::set_ttls(config.cache_ttl);
+            toolchains::downloads::set_keep_downloads(config.keep_downloads);
+            toolchains::ruby::set_reuse_installed_rubies(config.reuse_installed_rubies);
+
+            // Refuse to run as root or with the config of another repository
+            let searched = options.config.is_none() && std::env::var_os(config::parser::CONFIG_ENV).filter(|value| !value.is_empty()).is_none();
+            let cache_root = cache::resolve_cache_dir(options.cache_dir.as_deref(), &config);
+            guard_run(config.run_as_root, if searched { config::find_config_path(None).ok() } else { None }.as_deref(), &cache_root);
+
+            // Add the pre-commit hooks that haven't been migrated yet
+            if with_compat || config.compat_merge {
+                match config::find_precommit_config() {
+                    Ok(precommit_config) => {
+                        if let Err(e) = config::check_mutable_revs(&precommit_config, options.frozen).and_then(|_| config::check_minimum_revs(&precommit_config, options.frozen)) {
+                            error!("Error checking repo revisions: {:?}", e);
+                            std::process::exit(1);
+                        }
+                        let compat_config = config::convert_to_rustyhook_config(&precommit_config);
+                        if let Err(e) = config::check_minimum_versions(&compat_config) {
+                            error!("Error loading pre-commit configuration: {:?}", e);
+                            std::process::exit(1);
+                        }
+                        config = config::merge_compat_config(&config, &compat_config);
+                        debug!("Merged pre-commit configuration into native configuration");
+                    }
+                    Err(e) => warn!("Not merging pre-commit configuration: {:?}", e),
+                }
             }
+            override_parallelism(&mut config, options);
+
+            // Add the hooks the policy makes mandatory
+            let config = enforce_policy(&config, options.cache_dir.as_deref(), options.skip.as_deref());
+
+            // Expand matrix hooks into one hook per matched value
+            let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+            let config = match config::expand_matrix(&config, &root) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Error expanding hook matrix: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            // Keep only the selected hook and stage
+            let deselected = deselected_hooks(&config, options);
+            let config = config.select_hooks(options.hook_id.as_deref(), options.hook_stage.as_deref());
 
             // Create a cache directory
-            let cache_dir = std::env::temp_dir().join(".rustyhook");
+            let cache_dir = cache::resolve_cache_dir(options.cache_dir.as_deref(), &config);
             std::fs::create_dir_all(&cache_dir).unwrap_or_else(|e| {
                 error!("Error creating cache directory: {}", e);
                 std::process::exit(1);
             });
             debug!("Using cache directory: {}", cache_dir.display());
+            nudge_install(&cache_dir);
+
+            // Pin hooks asking for the latest version to the version in the lockfile
+            let config = runner::pin_latest_versions(&config, &config::Lockfile::path(&root), &cache_dir);
+
+            // Keep what the end of the run needs before the executor takes the config
+            let mut tail = RunTail::new(&config, options, root, &cache_dir, config::find_config_path(options.config.as_deref()).ok());
 
             // Create a parallel executor
             let executor = runner::ParallelExecutor::new(config, cache_dir);
+            executor.progress().subscribe(runner::progress::print_progress);
+            executor.set_verify_access_modes(options.verify_access_modes);
             debug!("Parallel executor created");
 
             // Create a tokio runtime for async execution
             let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(executor.record_skipped(deselected));
 
             // Set hooks to skip if specified
-            let mut hooks_to_skip = Vec::new();
-            if let Some(skip) = &cli.skip {
-                let cli_hooks_to_skip: Vec<String> = skip.split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect();
-                if !cli_hooks_to_skip.is_empty() {
-                    debug!("Skipping hooks from CLI: {}", cli_hooks_to_skip.join(", "));
-                    hooks_to_skip.extend(cli_hooks_to_skip);
-                }
-            }
-
-            // Check for environment variable to skip hooks
-            if let Ok(skip_env) = std::env::var("RUSTYHOOK_SKIP") {
-                if !skip_env.is_empty() {
-                    let env_hooks_to_skip: Vec<String> = skip_env.split(',')
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    if !env_hooks_to_skip.is_empty() {
-                        debug!("Skipping hooks from environment: {}", env_hooks_to_skip.join(", "));
-                        hooks_to_skip.extend(env_hooks_to_skip);
-                    }
-                }
-            }
-
-            // Set hooks to skip on the executor
+            let hooks_to_skip = get_hooks_to_skip(options.skip.as_deref());
             if !hooks_to_skip.is_empty() {
                 debug!("Skipping hooks: {}", hooks_to_skip.join(", "));
                 rt.block_on(executor.set_hooks_to_skip(hooks_to_skip));
             }
 
+            // Classify the changes so hooks can see deletions and renames
+            let changes = get_changes(&options.selection);
+            debug!("Found {} changes", changes.len());
+
             // Get the list of files to check
-            // For now, we'll just use all files in the current directory
-            let files = get_files_to_check();
+            // Unless files or a ref were given, we'll just use all files in the current directory
+            let mut files = get_files_to_run(options, &changes);
+            if options.deterministic {
+                files.sort();
+            }
             debug!("Found {} files to check", files.len());
+            rt.block_on(executor.set_changes(changes));
+            if let Some(stage) = &options.hook_stage {
+                rt.block_on(executor.set_stage(stage));
+            }
+            rt.block_on(executor.set_stage_input(&options.stage_input));
+
+            // Copy the files before any hook changes them
+            tail.recorded_files = options.record.as_deref().map(|dir| start_recording(dir, &files));
 
             // Run all hooks in parallel
-            match rt.block_on(executor.run_all_hooks(files)) {
-                Ok(_) => info!("All hooks passed!"),
-                Err(e) => {
-                    error!("Error running hooks using native config: {}", e);
-                    std::process::exit(1);
-                }
-            }
+            let started = std::time::Instant::now();
+            let result = rt.block_on(executor.run_all_hooks(files));
+            finish_run(&rt, &executor, options, tail, started, result);
         }
         Err(e) => {
             error!("Error finding configuration: {:?}", e);
@@ -299,83 +877,106 @@ fn run_hooks_with_native_config() {
 }
 
 /// Run hooks using .pre-commit-config.yaml
-fn run_hooks_with_compat_config() {
+fn run_hooks_with_compat_config(options: &RunOptions) {
     // Find the pre-commit config
     match config::find_precommit_config() {
         Ok(precommit_config) => {
             debug!("Found pre-commit configuration");
 
+            // Warn about (or reject) repos pinned to branches and moving tags, or older than their minimum_rev
+            if let Err(e) = config::check_mutable_revs(&precommit_config, options.frozen).and_then(|_| config::check_minimum_revs(&precommit_config, options.frozen)) {
+                error!("Error checking repo revisions: {:?}", e);
+                std::process::exit(1);
+            }
+
             // Convert to native config
             let mut config = config::convert_to_rustyhook_config(&precommit_config);
             debug!("Converted pre-commit configuration to rustyhook configuration");
-
-            // Get the parallelism limit from the CLI
-            let cli = Cli::parse();
-            if cli.parallelism > 0 {
-                // Override the parallelism limit from the config with the one from the CLI
-                config.parallelism = cli.parallelism;
-                debug!("Overriding parallelism limit to: {}", cli.parallelism);
+            if let Err(e) = config::check_minimum_versions(&config) {
+                error!("Error loading pre-commit configuration: {:?}", e);
+                std::process::exit(1);
             }
+            guard_run(config.run_as_root, None, &cache::resolve_cache_dir(options.cache_dir.as_deref(), &config));
+
+            // Get the parallelism limits from the CLI
+            override_parallelism(&mut config, options);
+
+            // Add the hooks the policy makes mandatory
+            let config = enforce_policy(&config, options.cache_dir.as_deref(), options.skip.as_deref());
+
+            // Expand matrix hooks into one hook per matched value
+            let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+            let config = match config::expand_matrix(&config, &root) {
+                Ok(config) => config,
+                Err(e) => {
+                    error!("Error expanding hook matrix: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            // Keep only the selected hook and stage
+            let deselected = deselected_hooks(&config, options);
+            let config = config.select_hooks(options.hook_id.as_deref(), options.hook_stage.as_deref());
 
             // Create a cache directory
-            let cache_dir = std::env::temp_dir().join(".rustyhook");
+            let cache_dir = cache::resolve_cache_dir(options.cache_dir.as_deref(), &config);
             std::fs::create_dir_all(&cache_dir).unwrap_or_else(|e| {
                 error!("Error creating cache directory: {}", e);
                 std::process::exit(1);
             });
             debug!("Using cache directory: {}", cache_dir.display());
+            nudge_install(&cache_dir);
+
+            // Pin hooks asking for the latest version to the version in the lockfile
+            let mut config = runner::pin_latest_versions(&config, &config::Lockfile::path(&root), &cache_dir);
+
+            // Runs of the pre-commit config go to the audit log of the native config, if any
+            config.audit_log = config::find_config_with(options.config.as_deref()).ok().and_then(|native| native.audit_log);
+
+            // Keep what the end of the run needs before the executor takes the config
+            let mut tail = RunTail::new(&config, options, root, &cache_dir, config::find_precommit_config_path().ok());
 
             // Create a parallel executor
             let executor = runner::ParallelExecutor::new(config, cache_dir);
+            executor.progress().subscribe(runner::progress::print_progress);
+            executor.set_verify_access_modes(options.verify_access_modes);
             debug!("Parallel executor created");
 
             // Create a tokio runtime for async execution
             let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(executor.record_skipped(deselected));
 
             // Set hooks to skip if specified
-            let mut hooks_to_skip = Vec::new();
-            if let Some(skip) = &cli.skip {
-                let cli_hooks_to_skip: Vec<String> = skip.split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect();
-                if !cli_hooks_to_skip.is_empty() {
-                    debug!("Skipping hooks from CLI: {}", cli_hooks_to_skip.join(", "));
-                    hooks_to_skip.extend(cli_hooks_to_skip);
-                }
-            }
-
-            // Check for environment variable to skip hooks
-            if let Ok(skip_env) = std::env::var("RUSTYHOOK_SKIP") {
-                if !skip_env.is_empty() {
-                    let env_hooks_to_skip: Vec<String> = skip_env.split(',')
-                        .map(|s| s.trim().to_string())
-                        .collect();
-                    if !env_hooks_to_skip.is_empty() {
-                        debug!("Skipping hooks from environment: {}", env_hooks_to_skip.join(", "));
-                        hooks_to_skip.extend(env_hooks_to_skip);
-                    }
-                }
-            }
-
-            // Set hooks to skip on the executor
+            let hooks_to_skip = get_hooks_to_skip(options.skip.as_deref());
             if !hooks_to_skip.is_empty() {
                 debug!("Skipping hooks: {}", hooks_to_skip.join(", "));
                 rt.block_on(executor.set_hooks_to_skip(hooks_to_skip));
             }
 
+            // Classify the changes so hooks can see deletions and renames
+            let changes = get_changes(&options.selection);
+            debug!("Found {} changes", changes.len());
+
             // Get the list of files to check
-            // For now, we'll just use all files in the current directory
-            let files = get_files_to_check();
+            // Unless files or a ref were given, we'll just use all files in the current directory
+            let mut files = get_files_to_run(options, &changes);
+            if options.deterministic {
+                files.sort();
+            }
             debug!("Found {} files to check", files.len());
+            rt.block_on(executor.set_changes(changes));
+            if let Some(stage) = &options.hook_stage {
+                rt.block_on(executor.set_stage(stage));
+            }
+            rt.block_on(executor.set_stage_input(&options.stage_input));
+
+            // Copy the files before any hook changes them
+            tail.recorded_files = options.record.as_deref().map(|dir| start_recording(dir, &files));
 
             // Run all hooks in parallel
-            match rt.block_on(executor.run_all_hooks(files)) {
-                Ok(_) => info!("All hooks passed!"),
-                Err(e) => {
-                    error!("Error running hooks in compatibility mode: {}", e);
-                    std::process::exit(1);
-                }
-            }
+            let started = std::time::Instant::now();
+            let result = rt.block_on(executor.run_all_hooks(files));
+            finish_run(&rt, &executor, options, tail, started, result);
         }
         Err(e) => {
             error!("Error finding pre-commit configuration: {:?}", e);
@@ -384,219 +985,1054 @@ fn run_hooks_with_compat_config() {
     }
 }
 
-/// List all available hooks and their status
-fn list_hooks() {
-    // Find the native config
-    match config::find_config() {
-        Ok(config) => {
-            info!("Available hooks:");
-            for repo in &config.repos {
-                info!("Repository: {}", repo.repo);
-                for hook in &repo.hooks {
-                    info!("  - {}: {}", hook.id, hook.name);
-                    info!("    Language: {}", hook.language);
-                    info!("    Files: {}", hook.files);
-                    info!("    Stages: {}", hook.stages.join(", "));
-                }
-            }
-            debug!("Found {} repositories with a total of {} hooks", 
-                  config.repos.len(), 
-                  config.repos.iter().map(|r| r.hooks.len()).sum::<usize>());
+/// What the end of a run needs from its config, kept before the executor takes it
+struct RunTail {
+    /// Directory the run started in
+    root: PathBuf,
+    /// Cache root, checked for environments no hook uses anymore
+    cache_dir: PathBuf,
+    /// Notifies when a long run finishes
+    notifier: Option<runner::Notifier>,
+    /// Config to save in the recording, when recording
+    recorded_config: Option<config::Config>,
+    /// Files copied into the recording, when recording
+    recorded_files: Option<Vec<PathBuf>>,
+    /// Audit log to append the run to, relative to the repository root
+    audit_log: Option<PathBuf>,
+    /// Config file whose hash the audit entry records
+    config_file: Option<PathBuf>,
+    /// Message printed when a run of a Git hook fails
+    blocked_message: Option<String>,
+}
+
+impl RunTail {
+    fn new(config: &config::Config, options: &RunOptions, root: PathBuf, cache_dir: &Path, config_file: Option<PathBuf>) -> Self {
+        let audit_log = config.audit_log.as_ref().map(|path| {
+            let repo_root = find_git_directory().and_then(|git_dir| git_dir.parent().map(PathBuf::from));
+            repo_root.unwrap_or_else(|| root.clone()).join(path)
+        });
+        RunTail {
+            root,
+            cache_dir: cache_dir.to_path_buf(),
+            notifier: runner::Notifier::from_config(config),
+            recorded_config: options.record.as_ref().map(|_| config.clone()),
+            recorded_files: None,
+            audit_log,
+            config_file,
+            blocked_message: config.blocked_message.clone(),
         }
+    }
+}
+
+/// Report the outcome of a run, exiting with 1 when it failed
+///
+/// Runs of the native and pre-commit configs end here, so both write the
+/// same reports and audit entry.
+fn finish_run(
+    rt: &tokio::runtime::Runtime,
+    executor: &runner::ParallelExecutor,
+    options: &RunOptions,
+    tail: RunTail,
+    started: std::time::Instant,
+    result: Result<(), runner::ParallelExecutionError>,
+) {
+    if let Some(notifier) = &tail.notifier {
+        notifier.finish(started.elapsed(), result.is_ok());
+    }
+    let report = rt.block_on(executor.report());
+    if let (Some(dir), Some(config), Some(files)) = (&options.record, tail.recorded_config, tail.recorded_files) {
+        finish_recording(dir, config, files, &report);
+    }
+    report_skipped(&report);
+    if options.timings {
+        print!("{}", report.format_timings());
+    }
+    report_cache_stats();
+    report_orphaned_environments(&tail.cache_dir, options.config.as_deref());
+    if let Some(path) = &options.metrics_file {
+        write_metrics(path, &report, started.elapsed(), result.as_ref().err());
+    }
+    if let Some(path) = &tail.audit_log {
+        write_audit_entry(path, &report, &tail.root, tail.config_file.as_deref());
+    }
+    report_fixes(&rt.block_on(executor.fixer_changes()), options.show_diff_on_failure, result.is_err());
+    print_summary(&report);
+    match result {
+        Ok(_) => info!("{}", messages::message(MessageId::AllHooksPassed)),
         Err(e) => {
-            error!("Error finding configuration: {:?}", e);
+            match options.hook_stage.as_deref().filter(|_| report.with_status(runner::HookStatus::Failed).next().is_some()) {
+                Some(stage) => report_blocked(stage, &report, tail.blocked_message.as_deref(), &e),
+                None => report_failure(&report, &e),
+            }
             std::process::exit(1);
         }
     }
 }
 
-/// Diagnose issues with setup or environments
-fn diagnose_issues() {
-    debug!("Starting diagnosis of setup and environments");
+/// Copy the files of a run into a recording, and return their repository-relative paths
+fn start_recording(dir: &std::path::Path, files: &[PathBuf]) -> Vec<PathBuf> {
+    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    runner::record::snapshot_files(dir, &root, files).unwrap_or_else(|e| {
+        error!("Error recording files into {}: {:?}", dir.display(), e);
+        std::process::exit(1);
+    })
+}
 
-    // Check if the .rustyhook directory exists
-    let rustyhook_dir = std::env::current_dir().unwrap().join(".rustyhook");
-    if !rustyhook_dir.exists() {
-        info!("The .rustyhook directory does not exist. Run 'rustyhook init' to create it.");
-    } else {
-        info!("The .rustyhook directory exists.");
+/// Get the arguments of this process for a run in another directory
+///
+/// `--rev` is dropped, and the config and cache directory are passed as
+/// absolute paths so the run uses the current ones.
+fn revision_run_args(config_path: &std::path::Path, cache_dir: &std::path::Path) -> Vec<std::ffi::OsString> {
+    let replaced = ["--rev", "--config", "--cache-dir"];
+    let mut args = Vec::new();
+    let mut skip_value = false;
+    for arg in std::env::args_os().skip(1) {
+        if std::mem::take(&mut skip_value) {
+            continue;
+        }
+        let text = arg.to_string_lossy();
+        if replaced.contains(&text.as_ref()) {
+            skip_value = true;
+        } else if !replaced.iter().any(|option| text.starts_with(&format!("{}=", option))) {
+            args.push(arg);
+        }
     }
+    let mut run_args = vec!["--config".into(), config_path.as_os_str().to_owned(), "--cache-dir".into(), cache_dir.as_os_str().to_owned()];
+    run_args.extend(args);
+    run_args
+}
 
-    // Check if the config file exists
-    let config_file = rustyhook_dir.join("config.yaml");
-    if !config_file.exists() {
-        info!("The .rustyhook/config.yaml file does not exist. Run 'rustyhook init' to create it.");
-    } else {
-        info!("The .rustyhook/config.yaml file exists.");
-    }
+/// Run the hooks on a commit, in a temporary worktree
+///
+/// The run happens in a child process, so the worktree is removed however
+/// the run ends. The hooks come from the current config, and environments
+/// are shared with runs in the working tree.
+fn run_hooks_at_revision(rev: &str, cli: &Cli) {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let config_path = match config::find_config_path(cli.config.as_deref()) {
+        Ok(path) => cwd.join(path),
+        Err(e) => {
+            error!("Error finding configuration: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let cache_dir = cwd.join(configured_cache_dir(cli.cache_dir.as_deref(), Some(&config_path)));
 
-    // Check if the cache directory exists
-    let cache_dir = rustyhook_dir.join("cache");
-    if !cache_dir.exists() {
-        info!("The .rustyhook/cache directory does not exist. It will be created when needed.");
-    } else {
-        info!("The .rustyhook/cache directory exists.");
+    let worktree = runner::RevisionWorktree::create(&cwd, rev).unwrap_or_else(|e| {
+        error!("Error checking out {}: {}", rev, e);
+        std::process::exit(1);
+    });
+    info!("Checked out {} into {}", worktree.commit(), worktree.path().display());
+
+    let exe = std::env::current_exe().unwrap_or_else(|_| PathBuf::from(invoked_name()));
+    let status = std::process::Command::new(exe).args(revision_run_args(&config_path, &cache_dir)).current_dir(worktree.path()).status();
+    drop(worktree);
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            error!("Error running hooks in the worktree: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Write the description of a finished run into its recording
+fn finish_recording(dir: &std::path::Path, config: config::Config, files: Vec<PathBuf>, report: &runner::RunReport) {
+    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    match runner::Bundle::new(config, &root, files, report).write(dir) {
+        Ok(()) => info!("Recorded the run into {}. Replay it with: rustyhook replay {}", dir.display(), dir.display()),
+        Err(e) => error!("Error recording the run into {}: {:?}", dir.display(), e),
+    }
+}
+
+/// Run the hooks of a recorded run again, in a scratch repository holding the recorded files
+fn replay_run(dir: &std::path::Path, cli_cache_dir: Option<&std::path::Path>) {
+    let bundle = runner::Bundle::read(dir).unwrap_or_else(|e| {
+        error!("Error reading the recording in {}: {:?}", dir.display(), e);
+        std::process::exit(1);
+    });
+    if bundle.rustyhook_version != env!("CARGO_PKG_VERSION") {
+        warn!("The run was recorded with rustyhook {}, this is {}", bundle.rustyhook_version, env!("CARGO_PKG_VERSION"));
+    }
+
+    // Resolve the cache directory before leaving the current directory, so environments are reused
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let cache_dir = cwd.join(cache::resolve_cache_dir(cli_cache_dir, &bundle.config));
+
+    let scratch = tempfile::tempdir().unwrap_or_else(|e| {
+        error!("Error creating a scratch directory: {}", e);
+        std::process::exit(1);
+    });
+    let files = bundle.restore(dir, scratch.path()).unwrap_or_else(|e| {
+        error!("Error restoring the recorded files: {:?}", e);
+        std::process::exit(1);
+    });
+    for (name, value) in bundle.replay_env() {
+        std::env::set_var(name, value);
+    }
+    if let Err(e) = std::env::set_current_dir(scratch.path()) {
+        error!("Error entering the scratch directory: {}", e);
+        std::process::exit(1);
     }
+    info!("Replaying {} hooks on {} files in {}", bundle.hooks.len(), files.len(), scratch.path().display());
 
-    // Check if the venvs directory exists
-    let venvs_dir = rustyhook_dir.join("venvs");
-    if !venvs_dir.exists() {
-        info!("The .rustyhook/venvs directory does not exist. It will be created when needed.");
+    let executor = runner::ParallelExecutor::new(bundle.config.clone(), cache_dir);
+    executor.progress().subscribe(runner::progress::print_progress);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(executor.set_changes(runner::staged_changes(scratch.path()).unwrap_or_default()));
+    let result = rt.block_on(executor.run_all_hooks(files));
+    let report = rt.block_on(executor.report());
+
+    for hook in &report.hooks {
+        info!("{}: {:?}{}", hook.hook_id, hook.status, hook.message.as_deref().map(|m| format!("\n{}", m)).unwrap_or_default());
+    }
+    let differences = bundle.differences(&report);
+    if differences.is_empty() {
+        info!("The replay reproduced the recorded results.");
     } else {
-        info!("The .rustyhook/venvs directory exists.");
+        for difference in &differences {
+            warn!("{}", difference);
+        }
+    }
+    if result.is_err() {
+        std::process::exit(1);
     }
+}
 
-    // Check if Python is installed
-    match which::which("python3") {
-        Ok(path) => {
-            info!("Python 3 is installed at: {}", path.display());
-            debug!("Python 3 found at path: {}", path.display());
-        },
-        Err(_) => {
-            warn!("Python 3 is not installed. Some hooks may not work.");
-            debug!("Failed to find Python 3 in PATH");
-        },
+/// Print a summary of the files changed by hooks, and their diff if the run failed and that was asked for
+fn report_fixes(changes: &[runner::HookChanges], show_diff_on_failure: bool, failed: bool) {
+    use std::io::IsTerminal;
+
+    if changes.is_empty() {
+        return;
     }
 
-    // Check for Node.js installation
-    let runtime_dir = std::env::current_dir().unwrap().join(".runtime").join("node");
-    if runtime_dir.exists() {
-        info!("Node.js runtime directory exists at: {}", runtime_dir.display());
-        debug!("Node.js runtime directory found at path: {}", runtime_dir.display());
+    let color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    print!("{}", runner::diff::format_summary(changes, color));
 
-        // Check for installed Node.js versions
-        if let Ok(entries) = std::fs::read_dir(&runtime_dir) {
-            let mut versions = Vec::new();
-            for entry in entries.filter_map(Result::ok) {
-                if entry.path().is_dir() {
-                    if let Some(version) = entry.file_name().to_str() {
-                        versions.push(version.to_string());
-                    }
-                }
+    if show_diff_on_failure && failed {
+        println!("All changes made by hooks:");
+        print!("{}", runner::diff::format_diffs(changes, color));
+    }
+}
+
+/// Pin the repo revs of the pre-commit config to commit SHAs
+fn freeze_revs(config_path: Option<PathBuf>) {
+    let path = match config_path {
+        Some(path) => path,
+        None => match config::find_precommit_config_path() {
+            Ok(path) => path,
+            Err(e) => {
+                error!("Error finding pre-commit configuration: {:?}", e);
+                std::process::exit(1);
             }
+        },
+    };
+    debug!("Freezing pre-commit config at: {}", path.display());
+
+    match config::freeze_precommit_config(&path) {
+        Ok(0) => info!("All repos are already pinned to commit SHAs."),
+        Ok(count) => info!("Pinned {} repo(s) in {}", count, path.display()),
+        Err(e) => {
+            error!("Error freezing repo revisions: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
 
-            if !versions.is_empty() {
-                info!("Node.js is installed. Available versions: {}", versions.join(", "));
+/// Get the hooks `--hook-id` and `--hook-stage` leave out of a run, and why
+fn deselected_hooks(config: &config::Config, options: &RunOptions) -> Vec<runner::SkippedHook> {
+    let mut deselected = Vec::new();
+    for repo in &config.repos {
+        for hook in &repo.hooks {
+            let reason = if options.hook_id.as_deref().is_some_and(|id| hook.id != id && hook.base_id() != id) {
+                runner::SkipReason::NotSelected
+            } else if let Some(stage) = options.hook_stage.as_deref().filter(|stage| !hook.runs_in_stage(&config.default_stages, stage)) {
+                runner::SkipReason::StageMismatch { stage: stage.to_string() }
             } else {
-                warn!("No Node.js versions found in runtime directory. Some hooks may not work.");
-                debug!("No subdirectories found in Node.js runtime directory");
+                continue;
+            };
+            deselected.push(runner::SkippedHook { repo: repo.repo.clone(), hook_id: hook.id.clone(), reason });
+        }
+    }
+    deselected
+}
+
+/// Merge the configured policy into a config, exiting when it can't be loaded
+///
+/// Warns about hooks the skip list names that the policy doesn't let skip.
+fn enforce_policy(config: &config::Config, cli_cache_dir: Option<&std::path::Path>, skip: Option<&str>) -> config::Config {
+    let cache_root = cache::resolve_cache_dir(cli_cache_dir, config);
+    let config = config::apply_policy(config, &cache_root).unwrap_or_else(|e| {
+        error!("Error loading the policy: {:?}", e);
+        std::process::exit(1);
+    });
+    let hooks_to_skip = get_hooks_to_skip(skip);
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        if let Some(policy) = hook.policy.as_deref().filter(|_| hooks_to_skip.iter().any(|skip| skip == &hook.id)) {
+            warn!("Not skipping {}: the policy {} requires it", hook.id, policy);
+        }
+    }
+    config
+}
+
+/// Run a hook in every repository of a list, and print the consolidated report
+fn multi_run(repos_file: &std::path::Path, hook_id: &str, jobs: usize, json: bool, cli_cache_dir: Option<&std::path::Path>) {
+    let repos = runner::multi::read_repos_file(repos_file).unwrap_or_else(|e| {
+        error!("Error reading the repositories in {}: {:?}", repos_file.display(), e);
+        std::process::exit(1);
+    });
+    let binary = std::env::current_exe().unwrap_or_else(|e| {
+        error!("Error getting path to rustyhook executable: {}", e);
+        std::process::exit(1);
+    });
+    let options = runner::multi::MultiRunOptions {
+        hook_id: hook_id.to_string(),
+        jobs: if jobs == 0 { std::thread::available_parallelism().map(usize::from).unwrap_or(1) } else { jobs },
+        cache_dir: cache::resolve_cache_dir(cli_cache_dir, &config::Config::default()),
+        binary,
+    };
+    if !json {
+        info!("Running {} in {} repositories, {} at a time", hook_id, repos.len(), options.jobs);
+    }
+    let report = runner::multi::run(&repos, &options);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("multi-run reports serialize"));
+    } else {
+        for outcome in report.repos.iter().filter(|outcome| !outcome.output.is_empty()) {
+            println!("--- {} ---\n{}", outcome.repo.display(), outcome.output.trim_end());
+        }
+        print!("{}", report.format_summary());
+    }
+    if !report.is_success() {
+        std::process::exit(1);
+    }
+}
+
+/// Print the outcome of each hook that ran
+fn print_summary(report: &runner::RunReport) {
+    if !report.hooks.is_empty() {
+        print!("{}", report.format_summary());
+    }
+}
+
+/// Report why a run failed
+///
+/// Failed hooks are listed by ID, since the summary shows their errors.
+/// Errors stopping the run before hooks failed, like a tool that can't be
+/// set up, are printed in full.
+fn report_failure(report: &runner::RunReport, error: &runner::ParallelExecutionError) {
+    let failed: Vec<&str> = report.with_status(runner::HookStatus::Failed).map(|result| result.hook_id.as_str()).collect();
+    if failed.is_empty() {
+        error!("{}", messages::format(MessageId::HooksFailed, &[("details", error)]));
+        return;
+    }
+    debug!("{}", messages::format(MessageId::HooksFailed, &[("details", error)]));
+    error!(
+        "{}",
+        messages::format(MessageId::SomeHooksFailed, &[("failed", &failed.len()), ("total", &report.hooks.len()), ("hooks", &failed.join(", "))])
+    );
+}
+
+/// Tell how to get past a Git hook that failed hooks blocked
+///
+/// The failed hooks already printed their output, so the error itself is
+/// only logged at debug level. The message comes from the config or policy,
+/// then the user's global config, then the message catalog.
+fn report_blocked(stage: &str, report: &runner::RunReport, template: Option<&str>, error: &runner::ParallelExecutionError) {
+    debug!("{}", messages::format(MessageId::HooksFailed, &[("details", error)]));
+    let template = template.map(str::to_string).or_else(|| {
+        config::GlobalConfig::load()
+            .map_err(|e| warn!("Error reading the global config: {:?}", e))
+            .ok()
+            .and_then(|global| global.blocked_message)
+    });
+    let hooks = report.with_status(runner::HookStatus::Failed).map(|result| result.hook_id.as_str()).collect::<Vec<_>>().join(", ");
+    let args: [(&str, &dyn std::fmt::Display); 3] = [("stage", &stage), ("hooks", &hooks), ("bin", &invoked_name())];
+    match template {
+        Some(template) => error!("{}", messages::fill(&template, &args)),
+        None => error!("{}", messages::format(MessageId::blocked(stage), &args)),
+    }
+}
+
+/// Write the metrics of a run, without failing the run when that isn't possible
+fn write_metrics(path: &std::path::Path, report: &runner::RunReport, duration: Duration, error: Option<&runner::ParallelExecutionError>) {
+    let mut metrics = runner::metrics::RunMetrics::from_report(report, duration);
+    metrics.error = error.map(|error| error.error_info());
+    match metrics.write(path) {
+        Ok(()) => debug!("Wrote run metrics to {}", path.display()),
+        Err(e) => warn!("Error writing run metrics to {}: {}", path.display(), e),
+    }
+}
+
+/// Append the entry of a run to the audit log
+///
+/// The entry records the hash of the config file, which is empty when there
+/// is none to read.
+fn write_audit_entry(path: &Path, report: &runner::RunReport, root: &Path, config_file: Option<&Path>) {
+    let config_hash = config_file
+        .and_then(|config_file| std::fs::read(config_file).ok())
+        .map(|content| config::drift::fingerprint(&content))
+        .unwrap_or_default();
+    let entry = runner::audit::AuditEntry::new(report, root, config_hash);
+    match runner::audit::append(path, entry) {
+        Ok(entry) => debug!("Appended entry {} to the audit log {}", entry.hash, path.display()),
+        Err(e) => warn!("Error appending to the audit log {}: {:?}", path.display(), e),
+    }
+}
+
+/// Log why each skipped hook didn't run, and the files hooks skipped for their size
+fn report_skipped(report: &runner::RunReport) {
+    for skipped in &report.skipped {
+        debug!("Skipped {}: {}", skipped.hook_id, skipped.reason);
+    }
+    for skipped in &report.skipped_files {
+        warn!("{}", skipped);
+    }
+    if !report.access_mode_violations.is_empty() {
+        let hooks: Vec<&str> = report.access_mode_violations.iter().map(|violation| violation.hook_id.as_str()).collect();
+        warn!(
+            "The results of this run may be wrong: read-only hooks modified files while other hooks may have read them ({}). Set their access_mode to ReadWrite.",
+            hooks.join(", ")
+        );
+    }
+}
+
+/// Log how often the metadata caches were used
+fn report_cache_stats() {
+    for stats in cache::stats() {
+        debug!("Metadata cache {}: {} hits, {} misses", stats.category.name(), stats.hits, stats.misses);
+    }
+}
+
+/// Get the hooks to skip from the --skip option and the RUSTYHOOK_SKIP environment variable
+fn get_hooks_to_skip(skip: Option<&str>) -> Vec<String> {
+    let mut hooks_to_skip = Vec::new();
+    if let Some(skip) = skip {
+        let cli_hooks_to_skip: Vec<String> = skip.split(',')
+            .map(|s| s.trim().to_string())
+            .collect();
+        if !cli_hooks_to_skip.is_empty() {
+            debug!("Skipping hooks from CLI: {}", cli_hooks_to_skip.join(", "));
+            hooks_to_skip.extend(cli_hooks_to_skip);
+        }
+    }
+
+    // Check for environment variable to skip hooks
+    if let Ok(skip_env) = std::env::var("RUSTYHOOK_SKIP") {
+        if !skip_env.is_empty() {
+            let env_hooks_to_skip: Vec<String> = skip_env.split(',')
+                .map(|s| s.trim().to_string())
+                .collect();
+            if !env_hooks_to_skip.is_empty() {
+                debug!("Skipping hooks from environment: {}", env_hooks_to_skip.join(", "));
+                hooks_to_skip.extend(env_hooks_to_skip);
             }
-        } else {
-            warn!("Failed to read Node.js runtime directory. Some hooks may not work.");
-            debug!("Failed to read Node.js runtime directory");
         }
+    }
+
+    hooks_to_skip
+}
+
+/// Override the parallelism limits of the config with the ones from the CLI
+fn override_parallelism(config: &mut config::Config, options: &RunOptions) {
+    if options.parallelism > 0 {
+        config.parallelism = options.parallelism;
+        debug!("Overriding parallelism limit to: {}", options.parallelism);
+    }
+    if let Some(limit) = options.setup_parallelism {
+        config.setup_parallelism = Some(limit);
+    }
+    if let Some(limit) = options.run_parallelism {
+        config.run_parallelism = Some(limit);
+    }
+    if options.deterministic {
+        // One environment setup and one hook at a time, in plan order
+        config.parallelism = 1;
+        config.setup_parallelism = Some(1);
+        config.run_parallelism = Some(1);
+    }
+}
+
+/// Print the execution plan for the configured hooks
+fn plan_hooks(compat: bool, graph: Option<GraphFormat>, cli: &Cli) {
+    // Load the native config, or the pre-commit config in compatibility mode
+    let config = if compat {
+        config::find_precommit_config().map(|precommit_config| config::convert_to_rustyhook_config(&precommit_config))
     } else {
-        // If runtime directory doesn't exist, check for Node.js directly
-        match which::which("node") {
-            Ok(path) => {
-                info!("System Node.js is installed at: {}", path.display());
-                debug!("System Node.js found at path: {}", path.display());
-                info!("RustyHook will download and use its own Node.js runtime when needed.");
-            },
-            Err(_) => {
-                warn!("Node.js is not installed. Some hooks may not work.");
-                debug!("Failed to find Node.js in PATH");
-                info!("RustyHook will download and install Node.js when needed.");
-            },
+        config::find_config_with(cli.config.as_deref())
+    };
+    let mut config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Error finding configuration: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    override_parallelism(&mut config, &RunOptions::from_cli(cli));
+    cache::set_ttls(config.cache_ttl);
+    toolchains::downloads::set_keep_downloads(config.keep_downloads);
+    toolchains::ruby::set_reuse_installed_rubies(config.reuse_installed_rubies);
+
+    // Include the pre-commit hooks a merged run would add
+    if !compat && config.compat_merge {
+        if let Ok(precommit_config) = config::find_precommit_config() {
+            config = config::merge_compat_config(&config, &config::convert_to_rustyhook_config(&precommit_config));
         }
     }
 
-    // Check if Ruby is installed
-    match which::which("ruby") {
-        Ok(path) => {
-            info!("Ruby is installed at: {}", path.display());
-            debug!("Ruby found at path: {}", path.display());
-        },
-        Err(_) => {
-            warn!("Ruby is not installed. Some hooks may not work.");
-            debug!("Failed to find Ruby in PATH");
+    // Add the hooks the policy makes mandatory
+    let config = enforce_policy(&config, cli.cache_dir.as_deref(), cli.skip.as_deref());
+
+    // Expand matrix hooks into one hook per matched value
+    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let config = match config::expand_matrix(&config, &root) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Error expanding hook matrix: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let cache_dir = cache::resolve_cache_dir(cli.cache_dir.as_deref(), &config);
+    let executor = runner::ParallelExecutor::new(config, cache_dir);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(executor.set_hooks_to_skip(get_hooks_to_skip(cli.skip.as_deref())));
+    let options = RunOptions::from_cli(cli);
+    let changes = get_changes(&options.selection);
+    let files = get_files_to_run(&options, &changes);
+    rt.block_on(executor.set_changes(changes));
+    match rt.block_on(executor.plan(&files)) {
+        Ok(plan) => match graph {
+            Some(GraphFormat::Dot) => print!("{}", plan.to_dot()),
+            Some(GraphFormat::Mermaid) => print!("{}", plan.to_mermaid()),
+            None => print!("{}", plan.to_text()),
         },
+        Err(e) => {
+            error!("Error computing the execution plan: {}", e);
+            std::process::exit(1);
+        }
     }
+}
 
-    debug!("Diagnosis completed");
+/// List all available hooks and their status
+fn list_hooks(config_path: Option<&std::path::Path>, cli_cache_dir: Option<&std::path::Path>) {
+    // Find the native config
+    match config::find_config_with(config_path) {
+        Ok(config) => {
+            let config = enforce_policy(&config, cli_cache_dir, None);
+            info!("Available hooks:");
+            for repo in &config.repos {
+                info!("Repository: {}", repo.repo);
+                for hook in &repo.hooks {
+                    info!("  - {}: {}", hook.id, hook.name);
+                    if let Some(description) = &hook.description {
+                        info!("    Description: {}", description);
+                    }
+                    info!("    Language: {}", hook.language);
+                    info!("    Files: {}", hook.files);
+                    match hook.stages_or(&config.default_stages) {
+                        [] => info!("    Stages: all"),
+                        stages => info!("    Stages: {}", stages.join(", ")),
+                    }
+                    if let Some(policy) = &hook.policy {
+                        info!("    Required by policy: {}", policy);
+                    }
+                }
+            }
+            debug!("Found {} repositories with a total of {} hooks", 
+                  config.repos.len(), 
+                  config.repos.iter().map(|r| r.hooks.len()).sum::<usize>());
+
+            // Container images take far more disk space than environments
+            let cache_root = cache::resolve_cache_dir(cli_cache_dir, &config);
+            let usage = runner::container::image_usage(&runner::container::runtime(), &cache_root);
+            if !usage.images.is_empty() {
+                info!("Container images:");
+                for (image, size) in &usage.images {
+                    info!("  - {}: {}", image, toolchains::downloads::format_size(*size));
+                }
+                info!("  Pulled by RustyHook: {}", toolchains::downloads::format_size(usage.pulled_bytes));
+            }
+        }
+        Err(e) => {
+            error!("Error finding configuration: {:?}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
-/// Remove cached environments and tool installs
-fn clean_environments() {
-    debug!("Starting cleanup of cached environments and tool installs");
+/// Check that the config file exists and parses
+fn validate_config(config_path: Option<&std::path::Path>, perf: bool) {
+    let path = match config::find_config_path(config_path) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("Error finding configuration: {:?}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Remove the .rustyhook/cache directory
-    let cache_dir = std::env::current_dir().unwrap().join(".rustyhook").join("cache");
-    if cache_dir.exists() {
-        debug!("Found cache directory at: {}", cache_dir.display());
-        match std::fs::remove_dir_all(&cache_dir) {
-            Ok(_) => {
-                info!("Removed .rustyhook/cache directory.");
-                debug!("Successfully removed directory: {}", cache_dir.display());
-            },
-            Err(e) => {
-                error!("Error removing .rustyhook/cache directory: {}", e);
-                debug!("Failed to remove directory: {}, error: {}", cache_dir.display(), e);
-            },
+    match config::parse_config(&path) {
+        Ok(config) => {
+            info!(
+                "{} is valid ({} repositories, {} hooks)",
+                path.display(),
+                config.repos.len(),
+                config.repos.iter().map(|r| r.hooks.len()).sum::<usize>()
+            );
+            if perf {
+                let issues = config::lint_performance(&config);
+                for issue in &issues {
+                    warn!("{}", issue);
+                }
+                info!("{} performance issue(s) found", issues.len());
+            }
         }
-    } else {
-        info!("The .rustyhook/cache directory does not exist.");
-        debug!("Cache directory not found at: {}", cache_dir.display());
-    }
-
-    // Remove the .rustyhook/venvs directory
-    let venvs_dir = std::env::current_dir().unwrap().join(".rustyhook").join("venvs");
-    if venvs_dir.exists() {
-        debug!("Found venvs directory at: {}", venvs_dir.display());
-        match std::fs::remove_dir_all(&venvs_dir) {
-            Ok(_) => {
-                info!("Removed .rustyhook/venvs directory.");
-                debug!("Successfully removed directory: {}", venvs_dir.display());
-            },
+        Err(e) => {
+            error!("Invalid configuration in {}: {:?}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Show or verify the audit log the config sets
+fn audit(command: &AuditCommand, config_path: Option<&Path>) {
+    let config = match config::find_config_with(config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Error finding configuration: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let Some(path) = &config.audit_log else {
+        error!("No audit log is configured, set audit_log in the config");
+        std::process::exit(1);
+    };
+    let root = find_git_directory()
+        .and_then(|git_dir| git_dir.parent().map(PathBuf::from))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    let path = root.join(path);
+
+    match command {
+        AuditCommand::Show { limit, json } => {
+            let entries = runner::audit::read(&path).unwrap_or_else(|e| {
+                error!("Error reading the audit log {}: {:?}", path.display(), e);
+                std::process::exit(1);
+            });
+            let skip = limit.map_or(0, |limit| entries.len().saturating_sub(limit));
+            for entry in &entries[skip..] {
+                if *json {
+                    println!("{}", serde_json::to_string(entry).expect("audit entries serialize"));
+                    continue;
+                }
+                let failed: Vec<&str> = entry
+                    .hooks
+                    .iter()
+                    .filter(|hook| hook.status == runner::HookStatus::Failed)
+                    .map(|hook| hook.id.as_str())
+                    .collect();
+                println!(
+                    "{} {} head {} tree {}: {} hooks, {}",
+                    entry.timestamp,
+                    if entry.success { "passed" } else { "failed" },
+                    entry.head.as_deref().map_or("-", |head| &head[..head.len().min(12)]),
+                    entry.tree.as_deref().map_or("-", |tree| &tree[..tree.len().min(12)]),
+                    entry.hooks.len(),
+                    if failed.is_empty() { format!("{} skipped", entry.skipped.len()) } else { format!("failed: {}", failed.join(", ")) }
+                );
+            }
+        }
+        AuditCommand::Verify => match runner::audit::verify(&path) {
+            Ok(count) => info!("The audit log {} is intact ({} entries)", path.display(), count),
             Err(e) => {
-                error!("Error removing .rustyhook/venvs directory: {}", e);
-                debug!("Failed to remove directory: {}, error: {}", venvs_dir.display(), e);
-            },
+                error!("The audit log {} failed verification: {:?}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Diagnose issues with setup or environments
+///
+/// Exits with 1 when errors were found, and with `--strict` with 2 when
+/// warnings were.
+fn diagnose_issues(cli: &Cli, check_config_drift: bool, json: bool, strict: bool) {
+    let config_path = config::find_config_path(cli.config.as_deref()).ok();
+    let git_dir = find_git_directory();
+    let root = git_dir
+        .as_deref()
+        .and_then(|git_dir| git_dir.parent().map(PathBuf::from))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    let cache_root = configured_cache_dir(cli.cache_dir.as_deref(), cli.config.as_deref());
+    let current_exe = std::env::current_exe().ok();
+
+    let mut report = runner::doctor::diagnose(&runner::doctor::Inputs {
+        root: &root,
+        config_path: config_path.as_deref(),
+        cache_root: &cache_root,
+        cli_cache_dir: cli.cache_dir.as_deref(),
+        git_dir: git_dir.as_deref(),
+        current_exe: current_exe.as_deref(),
+    });
+    if check_config_drift {
+        check_drift(&mut report, cli.config.as_deref(), git_dir.as_deref(), current_exe.as_deref());
+    }
+
+    if json {
+        println!("{}", report.to_json(strict));
+    } else {
+        report.log();
+        info!(
+            "Found {} error(s) and {} warning(s).",
+            report.count(runner::doctor::Severity::Error),
+            report.count(runner::doctor::Severity::Warning)
+        );
+    }
+    let code = report.exit_code(strict);
+    if code != 0 {
+        std::process::exit(code);
+    }
+}
+
+/// Report where installed Git hooks or the converted config drifted from the config
+///
+/// Every drift is an error, so CI can enforce a synced setup.
+fn check_drift(
+    report: &mut runner::doctor::Report,
+    config_path: Option<&std::path::Path>,
+    git_dir: Option<&std::path::Path>,
+    current_exe: Option<&std::path::Path>,
+) {
+    use runner::doctor::{Category, Severity};
+
+    let mut drifts = Vec::new();
+    let native_path = config::find_config_path(config_path).ok();
+    if let (Some(native_path), Ok(precommit_path)) = (&native_path, config::find_precommit_config_path()) {
+        drifts.extend(config::check_converted_config(native_path, &precommit_path).into_iter().map(|drift| (Category::Config, drift)));
+    }
+    match (git_dir, current_exe) {
+        (Some(git_dir), Some(current_exe)) => {
+            let hooks = all_configured_hooks(config_path);
+            drifts.extend(config::check_hook_scripts(git_dir, &hooks, current_exe).into_iter().map(|drift| (Category::Git, drift)));
         }
+        _ => debug!("Not checking installed Git hooks: no Git repository or rustyhook binary found"),
+    }
+
+    for (category, drift) in &drifts {
+        report.add(*category, Severity::Error, MessageId::DoctorConfigDrift, &[("drift", drift), ("remedy", &drift.remedy())]);
+    }
+}
+
+/// Print the setup status, exiting with an error when anything isn't set up
+fn show_status(porcelain: bool, cli: &Cli) {
+    let root = find_git_directory()
+        .and_then(|git_dir| git_dir.parent().map(PathBuf::from))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    let config_path = config::find_config_path(cli.config.as_deref())
+        .unwrap_or_else(|_| root.join(".rustyhook").join("config.yaml"));
+    let cache_root = match &cli.cache_dir {
+        Some(dir) => cache::resolve_cache_dir(Some(dir), &config::Config::default()),
+        None => cache::default_cache_dir(),
+    };
+
+    let facts = runner::status::cached_config_facts(&config_path, &root, &cache_root, cli.cache_dir.as_deref());
+    let current_exe = std::env::current_exe().ok();
+    let status = runner::status::status(find_git_directory().as_deref(), current_exe.as_deref(), facts);
+    if porcelain {
+        print!("{}", status.to_porcelain());
     } else {
-        info!("The .rustyhook/venvs directory does not exist.");
-        debug!("Venvs directory not found at: {}", venvs_dir.display());
+        print!("{}", status);
+    }
+    if !status.is_ok() {
+        std::process::exit(1);
+    }
+}
+
+/// Update the `projects` of the config from the workspaces of the repository
+fn refresh_config_projects(config_path: Option<&std::path::Path>) {
+    let path = match config::find_config_path(config_path) {
+        Ok(path) => path,
+        Err(e) => {
+            error!("No configuration to refresh, run 'rustyhook init' first: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let root = find_git_directory()
+        .and_then(|git_dir| git_dir.parent().map(PathBuf::from))
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+
+    match config::refresh_projects(&path, &root) {
+        Ok(projects) => {
+            for project in &projects {
+                info!("{} project {} at {}", project.kind, project.name, project.path);
+            }
+            info!("{} now lists {} projects", path.display(), projects.len());
+        }
+        Err(e) => {
+            error!("Error refreshing projects: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get the cache directory for commands that don't run hooks
+fn configured_cache_dir(cli_cache_dir: Option<&std::path::Path>, config_path: Option<&std::path::Path>) -> PathBuf {
+    let config = config::find_config_with(config_path).unwrap_or_default();
+    cache::resolve_cache_dir(cli_cache_dir, &config)
+}
+
+/// Get every hook the native and pre-commit configs define
+///
+/// This is what decides whether an environment is still in use, so both
+/// configs count, whichever one the runs use. Hooks asking for the latest
+/// version get the version of the lockfile, like runs install.
+fn all_configured_hooks(config_path: Option<&std::path::Path>) -> config::Config {
+    let mut all = config::Config::default();
+    if let Ok(native) = config::find_config_with(config_path) {
+        all.repos.extend(native.repos);
+    }
+    if let Ok(precommit_config) = config::find_precommit_config() {
+        all.repos.extend(config::convert_to_rustyhook_config(&precommit_config).repos);
+    }
+    let root = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let all = config::expand_matrix(&all, &root).unwrap_or(all);
+    runner::pin_locked_versions(&all, &config::Lockfile::path(&root))
+}
+
+/// Log the environments of hooks that are no longer configured
+fn report_orphaned_environments(cache_root: &std::path::Path, config_path: Option<&std::path::Path>) {
+    let orphans = cache::orphans::find_orphaned_environments(cache_root, &all_configured_hooks(config_path));
+    for orphan in &orphans {
+        debug!("Unused environment {} (last used {} days ago)", orphan.path.display(), orphan.unused_for.as_secs() / 86400);
+    }
+    if !orphans.is_empty() {
+        info!("Found {} environment(s) of hooks that are no longer configured. Run 'rustyhook clean --prune' to remove them.", orphans.len());
+    }
+}
+
+/// Remove the environments of hooks that are no longer configured
+fn prune_environments(cache_root: &std::path::Path, config_path: Option<&std::path::Path>, grace_period: Duration) {
+    let orphans = cache::orphans::find_orphaned_environments(cache_root, &all_configured_hooks(config_path));
+    match cache::orphans::prune_orphaned_environments(&orphans, grace_period) {
+        Ok(removed) => {
+            for path in &removed {
+                info!("Removed {}", path.display());
+            }
+            let kept = orphans.len() - removed.len();
+            if kept > 0 {
+                info!("Kept {} unused environment(s) used within the last {} days.", kept, grace_period.as_secs() / 86400);
+            }
+            if removed.is_empty() && kept == 0 {
+                info!("No unused environments found.");
+            }
+        }
+        Err(e) => {
+            error!("Error removing unused environments: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remove cached environments and tool installs
+fn clean_environments(cache_root: &std::path::Path) {
+    debug!("Starting cleanup of cached environments and tool installs");
+
+    // Remove the cache, venvs, runtimes, tool home, hook cache, status cache and hook log directories
+    for name in ["cache", "venvs", cache::RUNTIMES_DIR, cache::TOOL_HOME_DIR, cache::HOOK_CACHE_DIR, runner::status::STATUS_CACHE_DIR, toolchains::output::LOGS_DIR] {
+        let dir = cache_root.join(name);
+        if dir.exists() {
+            debug!("Found {} directory at: {}", name, dir.display());
+            let size = toolchains::downloads::dir_size(&dir);
+            // Downloaded and extracted runtime archives live in the environment caches
+            let archives = (name == "cache").then(|| toolchains::downloads::leftover_sizes(&dir));
+            match std::fs::remove_dir_all(&dir) {
+                Ok(_) => {
+                    info!("Removed {} directory ({}).", dir.display(), toolchains::downloads::format_size(size));
+                    if let Some((downloaded, extracted)) = archives {
+                        info!(
+                            "  including {} of downloaded archives and {} of extracted archives",
+                            toolchains::downloads::format_size(downloaded),
+                            toolchains::downloads::format_size(extracted)
+                        );
+                    }
+                    debug!("Successfully removed directory: {}", dir.display());
+                },
+                Err(e) => {
+                    error!("Error removing {} directory: {}", dir.display(), e);
+                    debug!("Failed to remove directory: {}, error: {}", dir.display(), e);
+                },
+            }
+        } else {
+            info!("The {} directory does not exist.", dir.display());
+            debug!("{} directory not found at: {}", name, dir.display());
+        }
     }
 
     debug!("Cleanup completed");
 }
 
+/// Remove leftover hook containers and the container images RustyHook pulled
+fn clean_containers(cache_root: &std::path::Path) {
+    let runtime = runner::container::runtime();
+    match runner::container::clean(&runtime, cache_root) {
+        Ok(cleaned) => {
+            info!("Removed {} container(s) and {} image(s).", cleaned.containers, cleaned.images.len());
+            for image in &cleaned.images {
+                info!("  {}", image);
+            }
+            for image in &cleaned.kept {
+                warn!("Could not remove image {}, it may still be in use", image);
+            }
+        }
+        Err(e) => {
+            error!("Could not run {}: {}", runtime, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Remove the `.runtime` directory earlier versions installed runtimes into
+///
+/// Asks first on a terminal, unless `yes` is set. Without a terminal to ask
+/// on, the directory is only removed with `yes`.
+fn clean_legacy_runtime(yes: bool) {
+    use std::io::IsTerminal;
+
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let Some(runtime_dir) = cache::legacy_runtime::find_legacy_runtime(&cwd) else { return };
+    let size = toolchains::downloads::format_size(toolchains::downloads::dir_size(&runtime_dir));
+
+    if !yes {
+        if !io::stdin().is_terminal() {
+            warn!("{} holds runtimes installed by an earlier version of RustyHook ({}). Run `rh clean --yes` to remove it.", runtime_dir.display(), size);
+            return;
+        }
+        print!("Remove {} ({}), left by an earlier version of RustyHook? [y/N] ", runtime_dir.display(), size);
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).is_err() || !matches!(answer.trim(), "y" | "Y" | "yes") {
+            info!("Kept {}.", runtime_dir.display());
+            return;
+        }
+    }
+
+    match cache::legacy_runtime::remove_legacy_runtime(&runtime_dir) {
+        Ok(_) => info!("Removed {} ({}).", runtime_dir.display(), size),
+        Err(e) => error!("Error removing {}: {}", runtime_dir.display(), e),
+    }
+}
+
 /// Get the list of files to check
+///
+/// These are the files Git tracks in the current directory, like `git
+/// ls-files`. Outside a Git repository, every file is checked, except those
+/// of `.git` and `.rustyhook` directories.
 fn get_files_to_check() -> Vec<std::path::PathBuf> {
-    // For now, we'll just use all files in the current directory
-    let mut files = Vec::new();
     let current_dir = std::env::current_dir().unwrap();
+    match runner::tracked_files(&current_dir) {
+        Ok(files) => return files,
+        Err(e) => debug!("Could not list tracked files, checking every file: {}", e),
+    }
 
     // Walk the directory tree
-    for entry in walkdir::WalkDir::new(&current_dir)
+    walkdir::WalkDir::new(&current_dir)
         .into_iter()
+        .filter_entry(|e| !(e.file_type().is_dir() && matches!(e.file_name().to_str(), Some(".git" | ".rustyhook"))))
         .filter_map(Result::ok)
         .filter(|e| e.file_type().is_file())
-    {
-        files.push(entry.path().to_path_buf());
+        .map(|e| e.path().to_path_buf())
+        .collect()
+}
+
+/// Get the files a run checks
+///
+/// These are the files given, the staged files, the files changed since a
+/// ref or between two refs, or every tracked file in the current directory.
+fn get_files_to_run(options: &RunOptions, changes: &[runner::FileChange]) -> Vec<PathBuf> {
+    match &options.selection {
+        FileSelection::Files(files) => files.clone(),
+        FileSelection::Staged => {
+            let files = runner::changed_files(changes);
+            info!("Checking {} staged files", files.len());
+            files
+        }
+        FileSelection::ChangedSince(rev) => {
+            let files = runner::changed_files(changes);
+            info!("Checking {} files changed since {}", files.len(), rev);
+            files
+        }
+        FileSelection::Range { from, to } => {
+            let files = runner::changed_files(changes);
+            info!("Checking {} files changed between {} and {}", files.len(), from, to);
+            files
+        }
+        FileSelection::AllFiles => get_files_to_check(),
     }
+}
 
-    files
+/// Get the changes hooks see: the staged changes, or the changes since a ref or between two refs
+fn get_changes(selection: &FileSelection) -> Vec<runner::FileChange> {
+    let current_dir = std::env::current_dir().unwrap();
+    let (changes, range) = match selection {
+        FileSelection::ChangedSince(rev) => (runner::changes_since(&current_dir, rev), format!("since {}", rev)),
+        FileSelection::Range { from, to } => (runner::changes_between(&current_dir, from, to), format!("between {} and {}", from, to)),
+        FileSelection::AllFiles | FileSelection::Staged | FileSelection::Files(_) => return get_staged_changes(),
+    };
+    match changes {
+        Ok(changes) => changes,
+        Err(e) => {
+            error!("Could not find the files changed {}: {}", range, e.message());
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Get the changes staged in the current Git repository
+fn get_staged_changes() -> Vec<runner::FileChange> {
+    let current_dir = std::env::current_dir().unwrap();
+    match runner::staged_changes(&current_dir) {
+        Ok(changes) => changes,
+        Err(e) => {
+            debug!("Could not classify staged changes: {}", e);
+            Vec::new()
+        }
+    }
 }
 
 /// Generate shell completion script for the specified shell
 fn generate_completion_script(shell: Shell) {
-    let mut cmd = Cli::command();
-    let bin_name = cmd.get_name().to_string();
+    // A closed pipe, such as `| head`, isn't an error worth reporting
+    let _ = io::stdout().write_all(completion_script(shell).as_bytes());
+}
+
+/// Get the completion script of a shell, completing both `rustyhook` and `rh`
+///
+/// Hook ids and stages are completed from the config by `__complete`.
+pub fn completion_script(shell: Shell) -> String {
+    let generate_for = |shell: ClapShell, bin_name: &'static str| {
+        let mut script = Vec::new();
+        generate(shell, &mut cli_command(bin_name), bin_name, &mut script);
+        String::from_utf8_lossy(&script).into_owned()
+    };
 
     match shell {
-        Shell::Bash => {
-            generate(ClapShell::Bash, &mut cmd, bin_name, &mut io::stdout());
-        }
+        Shell::Bash => BIN_NAMES.iter().map(|name| generate_for(ClapShell::Bash, name) + &completion::dynamic_script(shell, name)).collect(),
+        // A zsh completion file defines one function, so register it for every name
         Shell::Zsh => {
-            generate(ClapShell::Zsh, &mut cmd, bin_name, &mut io::stdout());
-        }
-        Shell::Fish => {
-            generate(ClapShell::Fish, &mut cmd, bin_name, &mut io::stdout());
-        }
-        Shell::PowerShell => {
-            generate(ClapShell::PowerShell, &mut cmd, bin_name, &mut io::stdout());
+            let names = BIN_NAMES.join(" ");
+            let script = generate_for(ClapShell::Zsh, BIN_NAMES[0])
+                .replacen("#compdef rustyhook\n", &format!("#compdef {}\n", names), 1)
+                .replacen("    _rustyhook \"$@\"\n", "    _rustyhook_dynamic \"$@\"\n", 1)
+                .replacen("compdef _rustyhook rustyhook\n", &format!("compdef _rustyhook_dynamic {}\n", names), 1);
+            // The dynamic function has to be defined before the file's last lines call it
+            let last_lines = script.rfind("\nif [ \"$funcstack[1]\"").unwrap_or(script.len());
+            format!("{}{}{}", &script[..last_lines], completion::dynamic_script(shell, BIN_NAMES[0]), &script[last_lines..])
         }
+        Shell::Fish => BIN_NAMES.iter().map(|name| generate_for(ClapShell::Fish, name) + &completion::dynamic_script(shell, name)).collect(),
+        Shell::PowerShell => BIN_NAMES.iter().map(|name| generate_for(ClapShell::PowerShell, name)).collect(),
     }
 }
 
@@ -633,21 +2069,23 @@ fn install_git_hook(hook_type: &str, force: bool) {
         std::process::exit(1);
     }
 
-    // Get the path to the rustyhook executable
-    let rustyhook_path = std::env::current_exe().unwrap_or_else(|e| {
+    // Get the path to the rustyhook executable, as it was invoked
+    let rustyhook_path = invoked_binary().unwrap_or_else(|e| {
         error!("Error getting path to rustyhook executable: {}", e);
         std::process::exit(1);
     });
     debug!("Using rustyhook executable at: {}", rustyhook_path.display());
 
-    // Create the hook script
+    // Create the hook script, running the hooks of its stage
     let hook_script = format!(
         "#!/bin/sh\n\
-         # RustyHook Git hook\n\
+         {}\n\
          # Generated by rustyhook\n\
          \n\
-         {} run\n",
-        rustyhook_path.display()
+         {} run --hook-stage {} -- \"$@\"\n",
+        config::drift::HOOK_SCRIPT_MARKER,
+        rustyhook_path.display(),
+        config::parser::normalize_stage(hook_type)
     );
 
     // Write the hook script
@@ -678,6 +2116,16 @@ fn install_git_hook(hook_type: &str, force: bool) {
     info!("Successfully installed rustyhook as a {} Git hook", hook_type);
 }
 
+/// Nudge once to install the Git hook, unless it is installed or this is CI
+fn nudge_install(cache_dir: &Path) {
+    if runner::notify::is_ci() {
+        return;
+    }
+    if let Some(nudge) = runner::status::install_nudge(find_git_directory().as_deref(), cache_dir) {
+        warn!("{}", nudge);
+    }
+}
+
 /// Find the .git directory
 fn find_git_directory() -> Option<std::path::PathBuf> {
     let mut current_dir = std::env::current_dir().ok()?;
@@ -25,7 +25,7 @@
 //!
 //! For example:
 //!
-//! ```
+//! ```sh
 //! # Set the default log level to info
 //! export RUSTYHOOK_LOG_LEVEL=info
 //!
@@ -69,9 +69,9 @@ pub fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
 /// # Arguments
 ///
 /// * `log_file` - Optional path to a log file. If provided, logs will be written to this file
-///                in addition to stdout.
+///   in addition to stdout.
 /// * `log_level` - The log level to use. If not provided, defaults to "info".
-///                 Valid values are: error, warn, info, debug, trace, off
+///   Valid values are: error, warn, info, debug, trace, off
 ///
 /// # Returns
 ///
@@ -79,7 +79,7 @@ pub fn parse_log_level(level: &str) -> Result<LevelFilter, String> {
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
 /// use rustyhook::logging;
 /// use std::path::PathBuf;
 ///
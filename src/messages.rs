@@ -0,0 +1,331 @@
+//! Message catalog for RustyHook
+//!
+//! User-facing strings (errors, summaries, doctor advice) are looked up by a
+//! [`MessageId`] in the catalog of the current locale, so they can be
+//! translated. Each id has a stable [`key`](MessageId::key) that programmatic
+//! consumers can branch on instead of matching prose.
+//!
+//! ## Locale
+//!
+//! The locale is taken from the first of these that is set:
+//!
+//! - The `RUSTYHOOK_LANG` environment variable
+//! - The `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables
+//!
+//! Only the language part is used (`de_DE.UTF-8` selects `de`). Messages
+//! missing from a catalog, and locales without a catalog, fall back to
+//! English.
+//!
+//! ## Adding a Language
+//!
+//! Write a function mapping ids to translated templates, like [`en`], and
+//! add it to [`CATALOGS`]. Templates use `{name}` placeholders, which must
+//! match the English ones.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Environment variable selecting the language of messages
+pub const LANG_ENV: &str = "RUSTYHOOK_LANG";
+
+/// Identifier of a user-facing message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageId {
+    /// A hook's file pattern is invalid
+    FileMatcherError,
+    /// A tool couldn't be set up or run
+    ToolError,
+    /// A native hook failed
+    HookError,
+    /// A hook isn't defined
+    HookNotFound,
+    /// A hook uses an unknown language
+    UnsupportedLanguage,
+    /// A hook's process failed
+    ProcessError,
+    /// A hook's environment failed its preflight command, even after a rebuild
+    ProvisioningError,
+    /// A hook with `require_clean` has files with unstaged changes
+    UncleanFiles,
+    /// A specific file is missing
+    FileNotFound,
+    /// Some file or directory is missing
+    NotFound,
+    /// Access to a file was denied
+    PermissionDenied,
+    /// Another IO operation failed
+    IoError,
+    /// Every hook passed
+    AllHooksPassed,
+    /// Hooks failed
+    HooksFailed,
+    /// Some of the hooks that ran failed
+    SomeHooksFailed,
+    /// Hooks failed in a run started by a Git hook, which blocks the commit
+    CommitBlocked,
+    /// Hooks failed in a run started by the pre-push Git hook, which blocks the push
+    PushBlocked,
+    /// Hooks failed in a run started by the commit-msg Git hook, which rejects the commit message
+    CommitMsgBlocked,
+    /// The .rustyhook directory is missing
+    DoctorRustyhookDirMissing,
+    /// The .rustyhook directory exists
+    DoctorRustyhookDirFound,
+    /// The native config is missing
+    DoctorConfigMissing,
+    /// The native config exists
+    DoctorConfigFound,
+    /// A cache directory is missing
+    DoctorCacheDirMissing,
+    /// A cache directory exists
+    DoctorCacheDirFound,
+    /// A tool was found
+    DoctorToolFound,
+    /// A tool is missing
+    DoctorToolMissing,
+    /// RustyHook installs a missing tool itself
+    DoctorToolManaged,
+    /// Runtimes RustyHook installed were found
+    DoctorRuntimeVersions,
+    /// The native config can't be used
+    DoctorConfigInvalid,
+    /// The installed Git hooks or converted config drifted from the config
+    DoctorConfigDrift,
+    /// The working directory isn't in a Git repository
+    DoctorGitRepositoryMissing,
+    /// The pre-commit Git hook runs RustyHook
+    DoctorGitHookInstalled,
+    /// The pre-commit Git hook runs another RustyHook binary
+    DoctorGitHookStale,
+    /// No pre-commit Git hook runs RustyHook
+    DoctorGitHookMissing,
+    /// A command hooks need is missing
+    DoctorCommandMissing,
+    /// Environments of hooks aren't set up yet
+    DoctorEnvironmentsMissing,
+    /// Every hook's environment is set up
+    DoctorEnvironmentsReady,
+    /// Earlier versions left runtimes in the repository
+    DoctorLegacyRuntime,
+    /// Container images hooks ran in take disk space
+    DoctorContainerImages,
+}
+
+impl MessageId {
+    /// Every message, so catalogs can be checked for completeness
+    pub const ALL: &'static [MessageId] = &[
+        MessageId::FileMatcherError,
+        MessageId::ToolError,
+        MessageId::HookError,
+        MessageId::HookNotFound,
+        MessageId::UnsupportedLanguage,
+        MessageId::ProcessError,
+        MessageId::ProvisioningError,
+        MessageId::UncleanFiles,
+        MessageId::FileNotFound,
+        MessageId::NotFound,
+        MessageId::PermissionDenied,
+        MessageId::IoError,
+        MessageId::AllHooksPassed,
+        MessageId::HooksFailed,
+        MessageId::SomeHooksFailed,
+        MessageId::CommitBlocked,
+        MessageId::PushBlocked,
+        MessageId::CommitMsgBlocked,
+        MessageId::DoctorRustyhookDirMissing,
+        MessageId::DoctorRustyhookDirFound,
+        MessageId::DoctorConfigMissing,
+        MessageId::DoctorConfigFound,
+        MessageId::DoctorCacheDirMissing,
+        MessageId::DoctorCacheDirFound,
+        MessageId::DoctorToolFound,
+        MessageId::DoctorToolMissing,
+        MessageId::DoctorToolManaged,
+        MessageId::DoctorRuntimeVersions,
+        MessageId::DoctorConfigInvalid,
+        MessageId::DoctorConfigDrift,
+        MessageId::DoctorGitRepositoryMissing,
+        MessageId::DoctorGitHookInstalled,
+        MessageId::DoctorGitHookStale,
+        MessageId::DoctorGitHookMissing,
+        MessageId::DoctorCommandMissing,
+        MessageId::DoctorEnvironmentsMissing,
+        MessageId::DoctorEnvironmentsReady,
+        MessageId::DoctorLegacyRuntime,
+        MessageId::DoctorContainerImages,
+    ];
+
+    /// Get the stable key of the message
+    ///
+    /// Keys never change once released, whatever the wording of the message.
+    pub fn key(&self) -> &'static str {
+        match self {
+            MessageId::FileMatcherError => "error.file-matcher",
+            MessageId::ToolError => "error.tool",
+            MessageId::HookError => "error.hook",
+            MessageId::HookNotFound => "error.hook-not-found",
+            MessageId::UnsupportedLanguage => "error.unsupported-language",
+            MessageId::ProcessError => "error.process",
+            MessageId::ProvisioningError => "error.provisioning",
+            MessageId::UncleanFiles => "error.unclean-files",
+            MessageId::FileNotFound => "error.file-not-found",
+            MessageId::NotFound => "error.not-found",
+            MessageId::PermissionDenied => "error.permission-denied",
+            MessageId::IoError => "error.io",
+            MessageId::AllHooksPassed => "run.passed",
+            MessageId::HooksFailed => "run.failed",
+            MessageId::SomeHooksFailed => "run.hooks-failed",
+            MessageId::CommitBlocked => "run.blocked",
+            MessageId::PushBlocked => "run.push-blocked",
+            MessageId::CommitMsgBlocked => "run.commit-msg-blocked",
+            MessageId::DoctorRustyhookDirMissing => "doctor.rustyhook-dir-missing",
+            MessageId::DoctorRustyhookDirFound => "doctor.rustyhook-dir-found",
+            MessageId::DoctorConfigMissing => "doctor.config-missing",
+            MessageId::DoctorConfigFound => "doctor.config-found",
+            MessageId::DoctorCacheDirMissing => "doctor.cache-dir-missing",
+            MessageId::DoctorCacheDirFound => "doctor.cache-dir-found",
+            MessageId::DoctorToolFound => "doctor.tool-found",
+            MessageId::DoctorToolMissing => "doctor.tool-missing",
+            MessageId::DoctorToolManaged => "doctor.tool-managed",
+            MessageId::DoctorRuntimeVersions => "doctor.runtime-versions",
+            MessageId::DoctorConfigInvalid => "doctor.config-invalid",
+            MessageId::DoctorConfigDrift => "doctor.config-drift",
+            MessageId::DoctorGitRepositoryMissing => "doctor.git-repository-missing",
+            MessageId::DoctorGitHookInstalled => "doctor.git-hook-installed",
+            MessageId::DoctorGitHookStale => "doctor.git-hook-stale",
+            MessageId::DoctorGitHookMissing => "doctor.git-hook-missing",
+            MessageId::DoctorCommandMissing => "doctor.command-missing",
+            MessageId::DoctorEnvironmentsMissing => "doctor.environments-missing",
+            MessageId::DoctorEnvironmentsReady => "doctor.environments-ready",
+            MessageId::DoctorLegacyRuntime => "doctor.legacy-runtime",
+            MessageId::DoctorContainerImages => "doctor.container-images",
+        }
+    }
+
+    /// Get the message telling how to get past a Git hook of a stage that failed hooks blocked
+    pub fn blocked(stage: &str) -> MessageId {
+        match stage {
+            "pre-push" | "push" => MessageId::PushBlocked,
+            "commit-msg" => MessageId::CommitMsgBlocked,
+            _ => MessageId::CommitBlocked,
+        }
+    }
+}
+
+/// The messages of a language
+pub struct Catalog {
+    /// Language code, such as `en`
+    pub language: &'static str,
+    /// Get the template of a message, if it is translated
+    pub messages: fn(MessageId) -> Option<&'static str>,
+}
+
+/// Catalogs of every supported language
+pub const CATALOGS: &[Catalog] = &[Catalog { language: "en", messages: en }];
+
+/// English messages, which every other catalog falls back to
+pub fn en(id: MessageId) -> Option<&'static str> {
+    let template = match id {
+        MessageId::FileMatcherError => "ERROR: File matching error.\n\nDetails: {details}\n\nSOLUTION: Check the file pattern in your configuration and ensure it's a valid regex pattern.",
+        MessageId::ToolError => "ERROR: Tool setup or execution failed.\n\nDetails: {details}\n\nSOLUTION: Ensure the required tools are installed and properly configured. Run 'rustyhook doctor' for diagnostics.",
+        MessageId::HookError => "ERROR: Hook execution failed.\n\nDetails: {details}\n\nSOLUTION: Check the hook configuration and ensure all dependencies are installed.",
+        MessageId::HookNotFound => "ERROR: Hook not found.\n\nDetails: {details}\n\nSOLUTION: Verify that the hook ID is correct and defined in your configuration file.",
+        MessageId::UnsupportedLanguage => "ERROR: Unsupported language: {language}\n\nSOLUTION: Use one of the supported languages: python, node, javascript, typescript, ruby, system, fail, pygrep, or native.",
+        MessageId::ProcessError => "ERROR: Process execution failed.\n\nDetails: {details}\n\nSOLUTION: Check that the command exists and has the correct permissions.",
+        MessageId::ProvisioningError => "ERROR: The environment of hook {hook} doesn't work.\n\nPreflight command: {command}\nDetails: {details}\n\nSOLUTION: Check that the hook's tool installs and runs. Run 'rustyhook clean' to rebuild every environment, or 'rustyhook doctor' for diagnostics.",
+        MessageId::UncleanFiles => "ERROR: Hook {hook} requires a clean working tree, but these files have unstaged changes: {files}\n\nSOLUTION: Stage the changes with 'git add {files}', or stash them with 'git stash --keep-index', and run again.",
+        MessageId::FileNotFound => "ERROR: Specific file not found: {path}\n\nContext: {context}\n\nSOLUTION: Please check that this file exists and that the path is correct. If this is a configuration file, ensure it's properly formatted.",
+        MessageId::NotFound => "ERROR: File or directory not found.\n\nThis could be due to one of the following issues:\n\
+            - Missing configuration file (check for .rustyhook/config.yaml or .pre-commit-config.yaml)\n\
+            - Missing hook script or executable (verify the 'entry' path in your config)\n\
+            - Missing dependencies required by a hook\n\
+            - Incorrect working directory (ensure you're running from the repository root)\n\n\
+            SOLUTION: Try running 'rustyhook doctor' for more detailed diagnostics, or check the paths in your configuration.",
+        MessageId::PermissionDenied => "ERROR: Permission denied.\n\nDetails: {details}\n\nSOLUTION: Check file permissions and ensure you have the necessary access rights. You may need to run with elevated privileges.",
+        MessageId::IoError => "ERROR: IO operation failed.\n\nDetails: {details}\n\nSOLUTION: Check system resources, disk space, and file access. If the issue persists, try running 'rustyhook doctor' for diagnostics.",
+        MessageId::AllHooksPassed => "All hooks passed!",
+        MessageId::HooksFailed => "Error running hooks: {details}",
+        MessageId::SomeHooksFailed => "{failed} of {total} hooks failed: {hooks}",
+        MessageId::CommitBlocked => "The {stage} hook blocked Git: {hooks} failed.\n\nTo fix it:\n  1. Fix the problems reported above (fixers may already have changed files)\n  2. Stage the changes with 'git add -u'\n  3. Try again\n\nRun '{bin} run' to check the files again without Git.",
+        MessageId::PushBlocked => "The {stage} hook blocked the push: {hooks} failed.\n\nTo fix it:\n  1. Fix the problems reported above (fixers may already have changed files)\n  2. Commit the fixes, or amend the commit with 'git commit --amend'\n  3. Push again\n\nRun '{bin} run --hook-stage {stage}' to check the files again without Git.",
+        MessageId::CommitMsgBlocked => "The {stage} hook rejected the commit message: {hooks} failed.\n\nTo fix it, commit again with a message that passes them. Git kept the rejected message, so 'git commit -e -F .git/COMMIT_EDITMSG' starts from it.",
+        MessageId::DoctorRustyhookDirMissing => "The .rustyhook directory does not exist. Run 'rustyhook init' to create it.",
+        MessageId::DoctorRustyhookDirFound => "The .rustyhook directory exists.",
+        MessageId::DoctorConfigMissing => "The .rustyhook/config.yaml file does not exist. Run 'rustyhook init' to create it.",
+        MessageId::DoctorConfigFound => "The .rustyhook/config.yaml file exists.",
+        MessageId::DoctorCacheDirMissing => "The {name} directory {path} does not exist. It will be created when needed.",
+        MessageId::DoctorCacheDirFound => "The {name} directory {path} exists.",
+        MessageId::DoctorToolFound => "{tool} is installed at: {path}",
+        MessageId::DoctorToolMissing => "{tool} is not installed. Some hooks may not work.",
+        MessageId::DoctorToolManaged => "RustyHook will download and install {tool} when needed.",
+        MessageId::DoctorRuntimeVersions => "{tool} runtimes are installed: {versions}",
+        MessageId::DoctorConfigInvalid => "The configuration in {path} can't be used: {error}",
+        MessageId::DoctorConfigDrift => "{drift}. To resync, run: {remedy}",
+        MessageId::DoctorGitRepositoryMissing => "The current directory is not in a Git repository. Hooks only run on commits in one.",
+        MessageId::DoctorGitHookInstalled => "The pre-commit Git hook runs RustyHook.",
+        MessageId::DoctorGitHookStale => "The pre-commit Git hook runs another rustyhook binary. Run 'rustyhook install --force' to update it.",
+        MessageId::DoctorGitHookMissing => "No pre-commit Git hook runs RustyHook. Run 'rustyhook install' to install it.",
+        MessageId::DoctorCommandMissing => "{missing}",
+        MessageId::DoctorEnvironmentsMissing => "The environments of {hooks} are not set up yet. They will be set up on the next run.",
+        MessageId::DoctorEnvironmentsReady => "The environments of every hook are set up.",
+        MessageId::DoctorLegacyRuntime => "{path} holds runtimes installed by an earlier version of RustyHook. Run 'rustyhook clean' to remove it.",
+        MessageId::DoctorContainerImages => "Container images of hooks use {size}: {images}. Run 'rustyhook clean --containers' to remove the {pulled} RustyHook pulled.",
+    };
+    Some(template)
+}
+
+/// Get the language code of a locale such as `de_DE.UTF-8`
+fn language_of(locale: &str) -> &str {
+    locale.split(['_', '-', '.', '@']).next().unwrap_or(locale)
+}
+
+/// Get the catalog of a locale, or the English one if there is none
+pub fn catalog_for(locale: &str) -> &'static Catalog {
+    let language = language_of(locale).to_ascii_lowercase();
+    CATALOGS
+        .iter()
+        .find(|catalog| catalog.language == language)
+        .unwrap_or(&CATALOGS[0])
+}
+
+/// Get the locale set in the environment
+pub fn current_locale() -> String {
+    [LANG_ENV, "LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "en".to_string())
+}
+
+/// Get the catalog of the current locale
+pub fn current_catalog() -> &'static Catalog {
+    static CATALOG: OnceLock<&'static Catalog> = OnceLock::new();
+    CATALOG.get_or_init(|| catalog_for(&current_locale()))
+}
+
+/// Format a message from a catalog, filling in its placeholders
+pub fn format_with(catalog: &Catalog, id: MessageId, args: &[(&str, &dyn Display)]) -> String {
+    let template = (catalog.messages)(id)
+        .or_else(|| en(id))
+        .unwrap_or_else(|| id.key());
+    fill(template, args)
+}
+
+/// Fill in the `{name}` placeholders of a template, such as one set in a config
+pub fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut message = template.to_string();
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    message
+}
+
+/// Format a message in the current locale, filling in its placeholders
+pub fn format(id: MessageId, args: &[(&str, &dyn Display)]) -> String {
+    format_with(current_catalog(), id, args)
+}
+
+/// Get a message without placeholders in the current locale
+pub fn message(id: MessageId) -> String {
+    format(id, &[])
+}
@@ -0,0 +1,215 @@
+//! Git attributes for RustyHook
+//!
+//! This module reads the `.gitattributes` of a repository, so hooks can skip
+//! files Git treats as binary or generated, and fixers keep the line endings
+//! Git checks files out with.
+//!
+//! The attributes of files can be cached in the file-attributes category of
+//! the metadata cache, see [`GitAttributes::with_cache`]. Entries are keyed by
+//! the repository and the content of its attribute files, so they are only
+//! reused while those are unchanged.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use git2::{AttrCheckFlags, AttrValue, Repository};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cache::{self, CacheCategory, CacheManager};
+
+/// Line ending set with the `eol` attribute
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LineEnding {
+    /// `eol=lf`
+    Lf,
+    /// `eol=crlf`
+    Crlf,
+}
+
+impl LineEnding {
+    /// Get the bytes of the line ending
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+        }
+    }
+}
+
+/// Attributes of a file that matter to hooks
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileAttributes {
+    /// Marked `binary` or `-text`
+    pub binary: bool,
+    /// Marked `linguist-generated`
+    pub generated: bool,
+    /// Line ending set with `eol`
+    pub eol: Option<LineEnding>,
+}
+
+impl FileAttributes {
+    /// Check whether text hooks should run on the file
+    pub fn is_text(&self) -> bool {
+        !self.binary && !self.generated
+    }
+}
+
+/// The attributes of the files of a repository
+///
+/// Outside a Git repository every file has default attributes.
+pub struct GitAttributes {
+    /// The repository and its working directory
+    repo: Option<(Repository, PathBuf)>,
+    /// Cached attributes of files, by path relative to the working directory
+    cache: Option<AttributeCache>,
+}
+
+/// The cached attributes of the files of a repository
+struct AttributeCache {
+    /// Cache of the file-attributes category
+    manager: CacheManager,
+    /// Entry of the repository and the content of its attribute files
+    key: String,
+    /// Attributes by path relative to the working directory
+    files: Mutex<HashMap<String, FileAttributes>>,
+    /// Whether attributes were looked up since the entry was read
+    changed: AtomicBool,
+}
+
+impl GitAttributes {
+    /// Read the attributes of the repository containing `path`
+    pub fn discover(path: &Path) -> Self {
+        let dir = if path.is_dir() { path } else { path.parent().unwrap_or(path) };
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let repo = Repository::discover(dir).ok().and_then(|repo| {
+            let workdir = repo.workdir()?.canonicalize().ok()?;
+            Some((repo, workdir))
+        });
+        GitAttributes { repo, cache: None }
+    }
+
+    /// Cache the attributes of files in a cache root
+    ///
+    /// The entry is written back when the attributes are dropped. Outside a
+    /// Git repository nothing is cached.
+    pub fn with_cache(mut self, cache_root: &Path) -> Self {
+        let Some((repo, workdir)) = &self.repo else { return self };
+        let manager = CacheManager::for_category(cache_root, CacheCategory::FileAttributes, &cache::ttls());
+        let key = CacheManager::entry_name(&Self::fingerprint(repo, workdir));
+        let files = match manager.get(&key) {
+            Ok(files) => files.unwrap_or_default(),
+            Err(e) => {
+                log::debug!("Ignoring the cached file attributes: {:?}", e);
+                HashMap::new()
+            }
+        };
+        self.cache = Some(AttributeCache { manager, key, files: Mutex::new(files), changed: AtomicBool::new(false) });
+        self
+    }
+
+    /// Hash the working directory and the content of the repository's attribute files
+    ///
+    /// The attribute files are the tracked `.gitattributes` files, in the
+    /// working directory and the index, and `info/attributes` of the Git
+    /// directory.
+    fn fingerprint(repo: &Repository, workdir: &Path) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(workdir.to_string_lossy().as_bytes());
+        let mut sources = vec![repo.path().join("info").join("attributes")];
+        if let Ok(index) = repo.index() {
+            for entry in index.iter() {
+                let path = String::from_utf8_lossy(&entry.path).into_owned();
+                if path == ".gitattributes" || path.ends_with("/.gitattributes") {
+                    hasher.update(entry.id.as_bytes());
+                    sources.push(workdir.join(path));
+                }
+            }
+        }
+        for source in sources {
+            hasher.update([0]);
+            hasher.update(source.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(std::fs::read(&source).unwrap_or_default());
+        }
+        hasher.finalize().iter().take(16).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Get the working directory of the repository, if there is one
+    pub fn root(&self) -> Option<&Path> {
+        self.repo.as_ref().map(|(_, workdir)| workdir.as_path())
+    }
+
+    /// Get the attributes of a file
+    pub fn get(&self, path: &Path) -> FileAttributes {
+        let Some((repo, workdir)) = &self.repo else { return FileAttributes::default() };
+
+        // Attributes are looked up by path relative to the working directory
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            std::env::current_dir().map(|dir| dir.join(path)).unwrap_or_else(|_| path.to_path_buf())
+        };
+        let absolute = absolute.canonicalize().unwrap_or(absolute);
+        let Ok(relative) = absolute.strip_prefix(workdir) else { return FileAttributes::default() };
+        let Some(cache) = &self.cache else { return Self::lookup(repo, relative) };
+
+        let key = super::file_matcher::to_slash_path(relative);
+        if let Some(attributes) = cache.files.lock().unwrap().get(&key) {
+            return attributes.clone();
+        }
+        let attributes = Self::lookup(repo, relative);
+        cache.files.lock().unwrap().insert(key, attributes.clone());
+        cache.changed.store(true, Ordering::Relaxed);
+        attributes
+    }
+
+    /// Look up the attributes of a file, by path relative to the working directory
+    fn lookup(repo: &Repository, relative: &Path) -> FileAttributes {
+        let attr = |name: &str| {
+            repo.get_attr_bytes(relative, name, AttrCheckFlags::FILE_THEN_INDEX)
+                .map(AttrValue::from_bytes)
+                .unwrap_or(AttrValue::Unspecified)
+        };
+        let is_set = |value: AttrValue| match value {
+            AttrValue::True => true,
+            AttrValue::String(value) => value != "false",
+            _ => false,
+        };
+
+        FileAttributes {
+            binary: is_set(attr("binary")) || attr("text") == AttrValue::False,
+            generated: is_set(attr("linguist-generated")),
+            eol: match attr("eol") {
+                AttrValue::String("lf") => Some(LineEnding::Lf),
+                AttrValue::String("crlf") => Some(LineEnding::Crlf),
+                _ => None,
+            },
+        }
+    }
+
+    /// Keep the files text hooks should run on
+    pub fn retain_text(&self, files: &mut Vec<PathBuf>) {
+        files.retain(|file| {
+            let text = self.get(file).is_text();
+            if !text {
+                log::debug!("Skipping {}: marked binary or generated in .gitattributes", file.display());
+            }
+            text
+        });
+    }
+}
+
+impl Drop for GitAttributes {
+    fn drop(&mut self) {
+        let Some(cache) = &self.cache else { return };
+        if !cache.changed.load(Ordering::Relaxed) {
+            return;
+        }
+        if let Err(e) = cache.manager.set(&cache.key, &*cache.files.lock().unwrap()) {
+            log::debug!("Could not cache the file attributes: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Audit log of hook runs
+//!
+//! With `audit_log` set in the config, every run appends one JSON line to the
+//! log: when it ran, the commit it checked, the hash of the config, and what
+//! each hook did. Regulated teams can show from it that checks ran before a
+//! commit.
+//!
+//! The log is tamper-evident: each entry records the hash of the entry
+//! before it, and its own hash covers all of its fields. Editing, removing or
+//! reordering entries breaks the chain, which `rustyhook audit verify`
+//! checks. Removing entries from the end can't be seen from the log alone, so
+//! keep the last hash somewhere else when that matters.
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::report::{HookStatus, RunReport, SkippedHook};
+
+/// Version of the audit entry format, bumped on incompatible changes
+pub const AUDIT_VERSION: u32 = 1;
+
+/// Error type for audit log operations
+#[derive(Debug)]
+pub enum AuditError {
+    /// Error reading or writing the log
+    IoError(std::io::Error),
+    /// A line of the log isn't an entry
+    InvalidEntry {
+        /// Line of the entry, from 1
+        line: usize,
+        /// Why it can't be read
+        error: serde_json::Error,
+    },
+    /// An entry was changed, or entries were removed or reordered
+    BrokenChain {
+        /// Line of the first entry that doesn't match, from 1
+        line: usize,
+        /// What doesn't match
+        reason: String,
+    },
+}
+
+impl From<std::io::Error> for AuditError {
+    fn from(err: std::io::Error) -> Self {
+        AuditError::IoError(err)
+    }
+}
+
+/// A hook that ran, in an audit entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditHook {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub id: String,
+    /// Outcome of the hook
+    pub status: HookStatus,
+}
+
+/// An entry of the audit log, for one run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Version of the entry format
+    pub version: u32,
+    /// When the run finished, in RFC 3339
+    pub timestamp: String,
+    /// Version of RustyHook that ran the hooks
+    pub rustyhook_version: String,
+    /// Commit checked out when the hooks ran, the parent of the commit being prepared
+    pub head: Option<String>,
+    /// Tree of the staged files, which the commit being prepared will have
+    pub tree: Option<String>,
+    /// SHA-256 of the config file
+    pub config_hash: String,
+    /// Whether every hook passed
+    pub success: bool,
+    /// Hooks that ran
+    pub hooks: Vec<AuditHook>,
+    /// Hooks that didn't run
+    pub skipped: Vec<SkippedHook>,
+    /// Hash of the previous entry, `None` for the first one
+    pub prev_hash: Option<String>,
+    /// Hash of this entry, over all its other fields
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Create the entry of a run in the repository containing `dir`
+    ///
+    /// The entry isn't chained yet, [`append`] does that.
+    pub fn new(report: &RunReport, dir: &Path, config_hash: String) -> Self {
+        let repo = git2::Repository::discover(dir).ok();
+        let head = repo.as_ref().and_then(|repo| repo.head().ok()?.peel_to_commit().ok()).map(|commit| commit.id().to_string());
+        let tree = repo.as_ref().and_then(|repo| repo.index().ok()?.write_tree().ok()).map(|tree| tree.to_string());
+        AuditEntry {
+            version: AUDIT_VERSION,
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            rustyhook_version: env!("CARGO_PKG_VERSION").to_string(),
+            head,
+            tree,
+            config_hash,
+            success: report.is_success(),
+            hooks: report
+                .hooks
+                .iter()
+                .map(|result| AuditHook { repo: result.repo.clone(), id: result.hook_id.clone(), status: result.status.clone() })
+                .collect(),
+            skipped: report.skipped.clone(),
+            prev_hash: None,
+            hash: String::new(),
+        }
+    }
+
+    /// Compute the hash of the entry, over every field but `hash`
+    ///
+    /// The fields are hashed as JSON with sorted keys, so the hash doesn't
+    /// depend on how the line was formatted.
+    pub fn compute_hash(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("audit entries serialize");
+        if let Some(fields) = value.as_object_mut() {
+            fields.remove("hash");
+        }
+        Sha256::digest(value.to_string().as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// Read the entries of an audit log, without verifying them
+///
+/// A missing log has no entries.
+pub fn read(path: &Path) -> Result<Vec<AuditEntry>, AuditError> {
+    match fs::read_to_string(path) {
+        Ok(content) => parse(&content),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Parse the entries of an audit log's content
+fn parse(content: &str) -> Result<Vec<AuditEntry>, AuditError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| serde_json::from_str(line).map_err(|error| AuditError::InvalidEntry { line: index + 1, error }))
+        .collect()
+}
+
+/// Chain an entry to the end of an audit log and append it
+///
+/// The entry is written with a single append, so a run interrupted midway
+/// leaves no partial entry. The log stays locked from reading its last entry
+/// until the new one is written, so concurrent runs, like commits in two
+/// worktrees, can't chain to the same entry. The last entry is read through
+/// the locked handle, as Windows locks keep other handles from reading.
+/// Returns the entry as written.
+pub fn append(path: &Path, mut entry: AuditEntry) -> Result<AuditEntry, AuditError> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+    file.lock()?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    entry.prev_hash = parse(&content)?.last().map(|last| last.hash.clone());
+    entry.hash = entry.compute_hash();
+    let line = serde_json::to_string(&entry).expect("audit entries serialize") + "\n";
+    file.write_all(line.as_bytes())?;
+    file.sync_all()?;
+    Ok(entry)
+}
+
+/// Verify the hash chain of an audit log, returning its number of entries
+pub fn verify(path: &Path) -> Result<usize, AuditError> {
+    let entries = read(path)?;
+    let mut prev_hash: Option<&str> = None;
+    for (index, entry) in entries.iter().enumerate() {
+        let line = index + 1;
+        if entry.prev_hash.as_deref() != prev_hash {
+            let reason = match prev_hash {
+                Some(_) => "it doesn't follow the entry before it, entries were removed or reordered".to_string(),
+                None => "the first entry follows another one, entries were removed".to_string(),
+            };
+            return Err(AuditError::BrokenChain { line, reason });
+        }
+        if entry.compute_hash() != entry.hash {
+            return Err(AuditError::BrokenChain { line, reason: "its content doesn't match its hash, it was changed".to_string() });
+        }
+        prev_hash = Some(&entry.hash);
+    }
+    Ok(entries.len())
+}
@@ -0,0 +1,152 @@
+//! Capability probe for RustyHook
+//!
+//! Minimal CI containers may lack commands RustyHook or the hooks rely on,
+//! like `tar` to extract a downloaded Node.js or the program a system hook
+//! runs. Setup then fails halfway with an unhelpful error. Before a run
+//! starts, the commands its hooks need are looked up in PATH, and every
+//! missing one is reported with the hooks it affects. A hook's entry is
+//! looked up in the PATH it runs with, its `env` override and the
+//! repository's `node_modules/.bin` included, and setup commands in the
+//! PATH of RustyHook.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::Hook;
+use crate::toolchains::ready::READY_MARKER;
+use super::container;
+use super::hook_context::HookContext;
+use super::hook_resolver::HookResolver;
+
+/// A command a hook needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    /// Name or path of the command
+    pub command: String,
+    /// What the command is needed for
+    pub purpose: &'static str,
+    /// Whether the hook runs the command, with its own PATH, rather than its setup
+    pub runs_in_hook: bool,
+}
+
+impl Requirement {
+    fn new(command: impl Into<String>, purpose: &'static str) -> Self {
+        Requirement { command: command.into(), purpose, runs_in_hook: false }
+    }
+
+    fn entry(command: impl Into<String>, purpose: &'static str) -> Self {
+        Requirement { runs_in_hook: true, ..Requirement::new(command, purpose) }
+    }
+}
+
+/// A command that is needed but can't be found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingCommand {
+    /// Name or path of the command
+    pub command: String,
+    /// What the command is needed for
+    pub purpose: &'static str,
+    /// IDs of the hooks that need it
+    pub hooks: Vec<String>,
+}
+
+impl fmt::Display for MissingCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' is not installed or not in PATH ({}), needed by: {}", self.command, self.purpose, self.hooks.join(", "))
+    }
+}
+
+/// Check whether the environment of a hook was set up by an earlier run
+fn environment_ready(hook: &Hook, cache_root: &Path) -> bool {
+    cache_root.join("venvs").join(HookResolver::tool_key(hook)).join(READY_MARKER).is_file()
+}
+
+/// Get the commands a hook needs to be set up and run
+///
+/// Setup commands are only needed while the hook's environment isn't ready.
+pub fn requirements(hook: &Hook, cache_root: &Path) -> Vec<Requirement> {
+    if hook.image.is_some() {
+        return vec![Requirement::new(container::runtime(), "to run the hook's container")];
+    }
+
+    // The repository's node_modules has the hook's commands
+    if hook.uses_repo_node_modules() {
+        return hook.npm_script.iter().map(|_| Requirement::entry("npm", "to run the hook's npm script")).collect();
+    }
+
+    let copy = if cfg!(windows) { "xcopy" } else { "cp" };
+    match hook.language.as_str() {
+        "system" | "script" => hook
+            .commands()
+            .iter()
+            .filter_map(|command| command.split_whitespace().next())
+            // Paths in the entry are relative to where the hook runs
+            .map(|program| match hook.working_dir.as_deref().filter(|_| program.contains(['/', '\\'])) {
+                Some(dir) => Requirement::entry(Path::new(dir).join(program).to_string_lossy(), "the hook's entry"),
+                None => Requirement::entry(program, "the hook's entry"),
+            })
+            .collect(),
+        _ if environment_ready(hook, cache_root) => Vec::new(),
+        "python" => vec![Requirement::new(copy, "to install the Python runtime")],
+        "node" | "javascript" | "typescript" => {
+            let mut requirements = vec![Requirement::new("npm", "to install the hook's packages")];
+            // Node.js is downloaded once, into the shared runtimes directory
+            if !crate::cache::runtimes_dir(cache_root).join("node").is_dir() {
+                requirements.push(Requirement::new("curl", "to download Node.js"));
+                if cfg!(windows) {
+                    requirements.push(Requirement::new("powershell", "to extract Node.js"));
+                } else {
+                    requirements.push(Requirement::new("tar", "to extract Node.js"));
+                    requirements.push(Requirement::new("chmod", "to make Node.js executable"));
+                }
+            }
+            requirements
+        }
+        "ruby" => {
+            let mut requirements = vec![Requirement::new(copy, "to install the Ruby runtime")];
+            if !cfg!(windows) {
+                requirements.push(Requirement::new("chmod", "to make Ruby executable"));
+            }
+            requirements
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Find a command in a PATH, with relative paths relative to `cwd`
+fn is_found(command: &str, path: Option<&OsString>, cwd: &Path) -> bool {
+    match path {
+        Some(path) => which::which_in(command, Some(path), cwd).is_ok(),
+        None => which::which(command).is_ok(),
+    }
+}
+
+/// Find the commands hooks need that can't be found in PATH
+///
+/// Each missing command is reported once, with every hook that needs it.
+pub fn probe<'a>(hooks: impl IntoIterator<Item = &'a Hook>, cache_root: &Path) -> Vec<MissingCommand> {
+    let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    let mut found: HashMap<(String, Option<OsString>), bool> = HashMap::new();
+    let mut missing: BTreeMap<String, MissingCommand> = BTreeMap::new();
+    for hook in hooks {
+        let context = HookContext::from_hook(hook, cwd.clone(), Vec::new());
+        for requirement in requirements(hook, cache_root) {
+            let path = requirement.runs_in_hook.then(|| context.host_path());
+            let key = (requirement.command.clone(), path.clone());
+            if *found.entry(key).or_insert_with(|| is_found(&requirement.command, path.as_ref(), &cwd)) {
+                continue;
+            }
+            let entry = missing.entry(requirement.command.clone()).or_insert_with(|| MissingCommand {
+                command: requirement.command,
+                purpose: requirement.purpose,
+                hooks: Vec::new(),
+            });
+            if !entry.hooks.contains(&hook.id) {
+                entry.hooks.push(hook.id.clone());
+            }
+        }
+    }
+    missing.into_values().collect()
+}
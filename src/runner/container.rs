@@ -0,0 +1,284 @@
+//! Container execution for RustyHook
+//!
+//! This module runs hook entries inside a container image declared with
+//! `image:`, with the repository mounted, instead of a managed toolchain.
+//!
+//! Containers are labelled with [`CONTAINER_LABEL`]. Pulled images can't be
+//! labelled, so the images hooks run in are recorded in the cache instead,
+//! with whether RustyHook pulled them. `rustyhook clean --containers`
+//! removes the pulled ones, and leaves images that were already there.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::toolchains::network;
+
+/// Environment variable selecting the container runtime
+pub const CONTAINER_RUNTIME_ENV: &str = "RUSTYHOOK_CONTAINER_RUNTIME";
+
+/// Path the repository is mounted at inside the container
+pub const CONTAINER_ROOT: &str = "/src";
+
+/// Label added to the containers RustyHook starts
+pub const CONTAINER_LABEL: &str = "dev.rustyhook.hook";
+
+/// Directory of the cache root holding the record of container images
+pub const CONTAINERS_CACHE_DIR: &str = "containers";
+
+/// File of the containers cache directory recording the images
+const IMAGES_FILE: &str = "images.yaml";
+
+/// Serializes updates of the image records between hooks running in parallel
+static RECORDS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Get the container runtime to use
+///
+/// `RUSTYHOOK_CONTAINER_RUNTIME` wins, then `docker` or `podman`, whichever
+/// is found first in PATH.
+pub fn runtime() -> String {
+    if let Ok(runtime) = std::env::var(CONTAINER_RUNTIME_ENV) {
+        if !runtime.is_empty() {
+            return runtime;
+        }
+    }
+    ["docker", "podman"]
+        .into_iter()
+        .find(|runtime| which::which(runtime).is_ok())
+        .unwrap_or("docker")
+        .to_string()
+}
+
+/// How the repository is mounted into a container
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mount {
+    /// Root of the repository on the host
+    pub host_root: PathBuf,
+    /// Directory the hook runs in on the host
+    pub working_dir: PathBuf,
+}
+
+impl Mount {
+    /// Mount the Git repository containing `working_dir`, or the directory itself
+    pub fn for_dir(working_dir: &Path) -> Self {
+        let host_root = git2::Repository::discover(working_dir)
+            .ok()
+            .and_then(|repo| repo.workdir().map(Path::to_path_buf))
+            .unwrap_or_else(|| working_dir.to_path_buf());
+        Mount { host_root, working_dir: working_dir.to_path_buf() }
+    }
+
+    /// Map a host path to its path inside the container
+    ///
+    /// Relative paths are relative to the working directory, which is the
+    /// container's working directory too, so they are kept.
+    pub fn container_path(&self, path: &Path) -> String {
+        if path.is_relative() {
+            return path.to_string_lossy().into_owned();
+        }
+        let host_root = self.host_root.canonicalize().unwrap_or_else(|_| self.host_root.clone());
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        match path.strip_prefix(&host_root) {
+            Ok(relative) if relative.as_os_str().is_empty() => CONTAINER_ROOT.to_string(),
+            Ok(relative) => format!("{}/{}", CONTAINER_ROOT, relative.to_string_lossy().replace('\\', "/")),
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Build the command running `argv` inside `image`
+///
+/// The first element of `argv` replaces the image's entrypoint. The container
+/// is removed afterwards and runs as the owner of the repository, so files
+/// written by the hook keep their owner.
+pub fn command(runtime: &str, image: &str, hook_id: &str, mount: &Mount, env: &HashMap<String, String>, argv: &[String]) -> Command {
+    let mut command = Command::new(runtime);
+    command
+        .arg("run")
+        .arg("--rm")
+        .arg("--label")
+        .arg(format!("{}={}", CONTAINER_LABEL, hook_id))
+        .arg("--volume")
+        .arg(format!("{}:{}", mount.host_root.display(), CONTAINER_ROOT))
+        .arg("--workdir")
+        .arg(mount.container_path(&mount.working_dir));
+
+    // Neither pull the image nor give the container a network
+    if network::is_forbidden() {
+        command.arg("--pull").arg("never").arg("--network").arg("none");
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let Ok(metadata) = std::fs::metadata(&mount.host_root) {
+            command.arg("--user").arg(format!("{}:{}", metadata.uid(), metadata.gid()));
+        }
+    }
+
+    // Sort the variables so the command line is stable
+    let mut env: Vec<_> = env.iter().collect();
+    env.sort();
+    for (key, value) in env {
+        command.arg("--env").arg(format!("{}={}", key, value));
+    }
+
+    if let Some((program, args)) = argv.split_first() {
+        command.arg("--entrypoint").arg(program).arg(image).args(args);
+    } else {
+        command.arg(image);
+    }
+    command
+}
+
+/// A container image hooks ran in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRecord {
+    /// Whether RustyHook pulled the image, rather than finding it already there
+    pub pulled: bool,
+    /// When a hook last ran in the image, in RFC 3339
+    pub last_used: String,
+}
+
+/// The container images hooks ran in, recorded in the cache
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImageRecords {
+    /// Records by image name
+    #[serde(default)]
+    pub images: BTreeMap<String, ImageRecord>,
+}
+
+impl ImageRecords {
+    /// Get the path of the records in a cache root
+    pub fn path(cache_root: &Path) -> PathBuf {
+        cache_root.join(CONTAINERS_CACHE_DIR).join(IMAGES_FILE)
+    }
+
+    /// Load the records of a cache root, empty if there are none
+    pub fn load(cache_root: &Path) -> Self {
+        fs::read_to_string(Self::path(cache_root))
+            .ok()
+            .and_then(|content| serde_yaml::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the records into a cache root
+    pub fn save(&self, cache_root: &Path) -> io::Result<()> {
+        let path = Self::path(cache_root);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_yaml::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, content)
+    }
+}
+
+/// Run a runtime command quietly, getting its stdout if it succeeds
+///
+/// Returns `Err` when the runtime can't be started.
+fn query(runtime: &str, args: &[&str]) -> io::Result<Option<String>> {
+    let output = Command::new(runtime).args(args).stdin(Stdio::null()).stderr(Stdio::null()).output()?;
+    Ok(output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+}
+
+/// Record that a hook is about to run in an image
+///
+/// An image seen for the first time counts as pulled by RustyHook when the
+/// runtime doesn't have it yet, since running the hook pulls it.
+pub fn record_image(runtime: &str, image: &str, cache_root: &Path) {
+    let _guard = RECORDS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut records = ImageRecords::load(cache_root);
+    let pulled = match records.images.get(image) {
+        Some(record) => record.pulled,
+        None => match query(runtime, &["image", "inspect", "--format", "{{.Id}}", image]) {
+            Ok(found) => found.is_none(),
+            Err(e) => {
+                log::debug!("Not recording image {}, {} can't run: {}", image, runtime, e);
+                return;
+            }
+        },
+    };
+    records.images.insert(image.to_string(), ImageRecord { pulled, last_used: chrono::Utc::now().to_rfc3339() });
+    if let Err(e) = records.save(cache_root) {
+        log::debug!("Could not record image {}: {}", image, e);
+    }
+}
+
+/// Get the size of an image in bytes, `None` if the runtime doesn't have it
+pub fn image_size(runtime: &str, image: &str) -> Option<u64> {
+    query(runtime, &["image", "inspect", "--format", "{{.Size}}", image]).ok().flatten()?.parse().ok()
+}
+
+/// Disk usage of the recorded images
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageUsage {
+    /// Recorded images the runtime still has, with their size in bytes
+    pub images: Vec<(String, u64)>,
+    /// Total size of the images RustyHook pulled, in bytes
+    pub pulled_bytes: u64,
+}
+
+/// Get the disk usage of the images recorded in a cache root
+pub fn image_usage(runtime: &str, cache_root: &Path) -> ImageUsage {
+    let mut usage = ImageUsage::default();
+    for (image, record) in ImageRecords::load(cache_root).images {
+        let Some(size) = image_size(runtime, &image) else { continue };
+        if record.pulled {
+            usage.pulled_bytes += size;
+        }
+        usage.images.push((image, size));
+    }
+    usage
+}
+
+/// What cleaning the containers removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanedContainers {
+    /// Leftover containers removed
+    pub containers: usize,
+    /// Images removed
+    pub images: Vec<String>,
+    /// Images that couldn't be removed, like images other containers use
+    pub kept: Vec<String>,
+}
+
+/// Remove leftover containers RustyHook started and the images it pulled
+///
+/// Images found already there when first recorded are forgotten but not
+/// removed.
+pub fn clean(runtime: &str, cache_root: &Path) -> io::Result<CleanedContainers> {
+    let _guard = RECORDS_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut cleaned = CleanedContainers::default();
+
+    // Containers are removed when their hook finishes, unless it was killed
+    let filter = format!("label={}", CONTAINER_LABEL);
+    let ids = query(runtime, &["ps", "--all", "--quiet", "--filter", &filter])?.unwrap_or_default();
+    let ids: Vec<&str> = ids.split_whitespace().collect();
+    if !ids.is_empty() {
+        let mut args = vec!["rm", "--force"];
+        args.extend(&ids);
+        if query(runtime, &args)?.is_some() {
+            cleaned.containers = ids.len();
+        }
+    }
+
+    let mut records = ImageRecords::load(cache_root);
+    for (image, record) in std::mem::take(&mut records.images) {
+        if !record.pulled || image_size(runtime, &image).is_none() {
+            continue;
+        }
+        if query(runtime, &["rmi", &image])?.is_some() {
+            cleaned.images.push(image);
+        } else {
+            cleaned.kept.push(image.clone());
+            records.images.insert(image, record);
+        }
+    }
+    records.save(cache_root)?;
+    Ok(cleaned)
+}
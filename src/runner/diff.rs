@@ -0,0 +1,259 @@
+//! File change tracking for RustyHook
+//!
+//! This module records what read-write hooks change, so runs can show a
+//! summary or a unified diff of the fixes.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Files larger than this are not tracked
+pub const MAX_SNAPSHOT_SIZE: u64 = 1024 * 1024;
+
+/// Lines of context around each hunk
+const CONTEXT_LINES: usize = 3;
+
+/// Line diffs larger than this (in compared line pairs) are shown as a full rewrite
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Changes made to a single file
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Number of added lines
+    pub added: usize,
+    /// Number of removed lines
+    pub removed: usize,
+    /// Unified diff of the change (empty for binary files)
+    pub unified: String,
+}
+
+/// Changes made by a single hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookChanges {
+    /// ID of the hook
+    pub hook_id: String,
+    /// Files the hook changed
+    pub files: Vec<FileDiff>,
+}
+
+/// Content of files before a hook runs
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    /// File contents by path
+    contents: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl Snapshot {
+    /// Record the content of the files, skipping large and unreadable ones
+    pub fn capture(files: &[PathBuf]) -> Self {
+        let contents = files
+            .iter()
+            .filter(|file| fs::metadata(file).map(|m| m.is_file() && m.len() <= MAX_SNAPSHOT_SIZE).unwrap_or(false))
+            .filter_map(|file| fs::read(file).ok().map(|content| (file.clone(), content)))
+            .collect();
+        Snapshot { contents }
+    }
+
+    /// Compare the recorded files with their current content
+    pub fn changes(&self) -> Vec<FileDiff> {
+        let mut diffs: Vec<FileDiff> = self
+            .contents
+            .iter()
+            .filter_map(|(path, before)| {
+                let after = fs::read(path).unwrap_or_default();
+                if &after == before {
+                    return None;
+                }
+                Some(match (std::str::from_utf8(before), std::str::from_utf8(&after)) {
+                    (Ok(before), Ok(after)) => diff_text(path, before, after),
+                    _ => FileDiff { path: path.clone(), added: 0, removed: 0, unified: String::new() },
+                })
+            })
+            .collect();
+        diffs.sort_by(|a, b| a.path.cmp(&b.path));
+        diffs
+    }
+}
+
+/// Modification times and sizes of files before a hook runs
+///
+/// Much cheaper than a [`Snapshot`], so it can be taken of every read-only
+/// hook's files to notice hooks that write anyway.
+#[derive(Debug, Default)]
+pub struct FileStamps {
+    /// Modification time and size by path
+    stamps: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+}
+
+impl FileStamps {
+    /// Record the modification time and size of the files
+    pub fn capture(files: &[PathBuf]) -> Self {
+        let stamps = files.iter().filter_map(|file| stamp(file).map(|stamp| (file.clone(), stamp))).collect();
+        FileStamps { stamps }
+    }
+
+    /// Get the recorded files whose modification time or size changed, or that were removed
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = self
+            .stamps
+            .iter()
+            .filter(|(path, before)| stamp(path).as_ref() != Some(before))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changed.sort();
+        changed
+    }
+}
+
+/// Get the modification time and size of a file
+fn stamp(file: &Path) -> Option<(Option<SystemTime>, u64)> {
+    let metadata = fs::metadata(file).ok().filter(|metadata| metadata.is_file())?;
+    Some((metadata.modified().ok(), metadata.len()))
+}
+
+/// A line of a diff
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Line<'a> {
+    /// Line present in both versions
+    Equal(&'a str),
+    /// Line only in the old version
+    Removed(&'a str),
+    /// Line only in the new version
+    Added(&'a str),
+}
+
+/// Compute the line diff between two versions using a longest common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Line<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<Line> = old[..prefix].iter().map(|line| Line::Equal(line)).collect();
+
+    if old_middle.len() * new_middle.len() > MAX_DIFF_CELLS {
+        lines.extend(old_middle.iter().map(|line| Line::Removed(line)));
+        lines.extend(new_middle.iter().map(|line| Line::Added(line)));
+    } else {
+        // lengths[i][j] is the LCS length of old_middle[i..] and new_middle[j..]
+        let (n, m) = (old_middle.len(), new_middle.len());
+        let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i * (m + 1) + j] = if old_middle[i] == new_middle[j] {
+                    lengths[(i + 1) * (m + 1) + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * (m + 1) + j].max(lengths[i * (m + 1) + j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                lines.push(Line::Equal(old_middle[i]));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lengths[(i + 1) * (m + 1) + j] >= lengths[i * (m + 1) + j + 1]) {
+                lines.push(Line::Removed(old_middle[i]));
+                i += 1;
+            } else {
+                lines.push(Line::Added(new_middle[j]));
+                j += 1;
+            }
+        }
+    }
+
+    lines.extend(old[old.len() - suffix..].iter().map(|line| Line::Equal(line)));
+    lines
+}
+
+/// Compute the diff between two versions of a text file
+pub fn diff_text(path: &Path, before: &str, after: &str) -> FileDiff {
+    let old: Vec<&str> = before.split_inclusive('\n').collect();
+    let new: Vec<&str> = after.split_inclusive('\n').collect();
+    let lines = diff_lines(&old, &new);
+
+    let added = lines.iter().filter(|line| matches!(line, Line::Added(_))).count();
+    let removed = lines.iter().filter(|line| matches!(line, Line::Removed(_))).count();
+
+    // Group changed lines into hunks, merging hunks whose context would overlap
+    let changed: Vec<usize> = lines.iter().enumerate().filter(|(_, line)| !matches!(line, Line::Equal(_))).map(|(i, _)| i).collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for index in changed {
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let end = (index + CONTEXT_LINES + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let display = path.strip_prefix(std::env::current_dir().unwrap_or_default()).unwrap_or(path).display().to_string();
+    let mut unified = format!("--- a/{}\n+++ b/{}\n", display, display);
+    for (start, end) in hunks {
+        // Line numbers of the hunk start in each version
+        let old_start = 1 + lines[..start].iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let new_start = 1 + lines[..start].iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+        let old_len = lines[start..end].iter().filter(|line| !matches!(line, Line::Added(_))).count();
+        let new_len = lines[start..end].iter().filter(|line| !matches!(line, Line::Removed(_))).count();
+        unified.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+
+        for line in &lines[start..end] {
+            let (marker, text) = match line {
+                Line::Equal(text) => (' ', text),
+                Line::Removed(text) => ('-', text),
+                Line::Added(text) => ('+', text),
+            };
+            unified.push(marker);
+            unified.push_str(text);
+            if !text.ends_with('\n') {
+                unified.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    FileDiff { path: path.to_path_buf(), added, removed, unified }
+}
+
+/// Format a per-hook summary of changed files
+pub fn format_summary(changes: &[HookChanges], color: bool) -> String {
+    let (green, red, reset) = if color { ("\x1b[32m", "\x1b[31m", "\x1b[0m") } else { ("", "", "") };
+    let mut output = String::new();
+    for hook in changes.iter().filter(|hook| !hook.files.is_empty()) {
+        output.push_str(&format!("{} modified {} file(s):\n", hook.hook_id, hook.files.len()));
+        for file in &hook.files {
+            let display = file.path.strip_prefix(std::env::current_dir().unwrap_or_default()).unwrap_or(&file.path);
+            if file.unified.is_empty() {
+                output.push_str(&format!("  {} (binary)\n", display.display()));
+            } else {
+                output.push_str(&format!("  {} {}+{}{} {}-{}{}\n", display.display(), green, file.added, reset, red, file.removed, reset));
+            }
+        }
+    }
+    output
+}
+
+/// Format the unified diffs of all changed files
+pub fn format_diffs(changes: &[HookChanges], color: bool) -> String {
+    let mut output = String::new();
+    for file in changes.iter().flat_map(|hook| &hook.files) {
+        for line in file.unified.lines() {
+            let code = match line.chars().next() {
+                _ if !color || line.starts_with("---") || line.starts_with("+++") => None,
+                Some('+') => Some("32"),
+                Some('-') => Some("31"),
+                Some('@') => Some("36"),
+                _ => None,
+            };
+            match code {
+                Some(code) => output.push_str(&format!("\x1b[{}m{}\x1b[0m\n", code, line)),
+                None => output.push_str(&format!("{}\n", line)),
+            }
+        }
+    }
+    output
+}
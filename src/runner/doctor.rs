@@ -0,0 +1,298 @@
+//! Health checks of `rustyhook doctor`
+//!
+//! Every check adds a [`Finding`] to a [`Report`], in one of four categories
+//! and with a severity. Errors are problems that make hooks fail, warnings
+//! are problems that may, and infos describe what was found. The report
+//! decides the exit code of `doctor`, so CI can fail on broken hook
+//! infrastructure:
+//!
+//! - `0`: no errors, and no warnings with `--strict`
+//! - `1`: errors were found
+//! - `2`: warnings were found with `--strict`
+//!
+//! With `--json`, the report is printed as a JSON object with the findings
+//! grouped by category. Each finding has the stable [`key`] of its message,
+//! which consumers can branch on.
+//!
+//! [`key`]: crate::messages::MessageId::key
+
+use std::fmt::Display;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::cache::legacy_runtime::find_legacy_runtime;
+use crate::config::{expand_matrix, parse_config, Hook};
+use crate::messages::{self, MessageId};
+use crate::toolchains::downloads::format_size;
+use crate::toolchains::ready;
+use super::{capabilities, container};
+use super::status::{self, ConfigState, HookInstall};
+
+/// What a finding is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// The RustyHook config
+    Config,
+    /// The Git repository and its installed hooks
+    Git,
+    /// Runtimes and commands hooks need
+    Toolchains,
+    /// Cache directories and hook environments
+    Environments,
+}
+
+impl Category {
+    /// Every category, in the order they are reported
+    pub const ALL: &'static [Category] = &[Category::Config, Category::Git, Category::Toolchains, Category::Environments];
+
+    /// Get the name of the category in the JSON report
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Config => "config",
+            Category::Git => "git",
+            Category::Toolchains => "toolchains",
+            Category::Environments => "environments",
+        }
+    }
+}
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something that was found
+    Info,
+    /// A problem that may make hooks fail
+    Warning,
+    /// A problem that makes hooks fail
+    Error,
+}
+
+/// The result of a check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    /// What the finding is about
+    #[serde(skip)]
+    pub category: Category,
+    /// How bad the finding is
+    pub severity: Severity,
+    /// Stable key of the message
+    pub key: &'static str,
+    /// The message, in the current locale
+    pub message: String,
+}
+
+/// The findings of every check
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Findings, in the order they were made
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Add a finding
+    pub fn add(&mut self, category: Category, severity: Severity, id: MessageId, args: &[(&str, &dyn Display)]) {
+        self.findings.push(Finding { category, severity, key: id.key(), message: messages::format(id, args) });
+    }
+
+    /// Count the findings of a severity
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings.iter().filter(|finding| finding.severity == severity).count()
+    }
+
+    /// Get the exit code of `doctor`
+    ///
+    /// With `strict`, warnings fail it too.
+    pub fn exit_code(&self, strict: bool) -> i32 {
+        if self.count(Severity::Error) > 0 {
+            1
+        } else if strict && self.count(Severity::Warning) > 0 {
+            2
+        } else {
+            0
+        }
+    }
+
+    /// Render the report as JSON, with the findings grouped by category
+    pub fn to_json(&self, strict: bool) -> String {
+        let categories: serde_json::Map<String, serde_json::Value> = Category::ALL
+            .iter()
+            .map(|category| {
+                let findings: Vec<&Finding> = self.findings.iter().filter(|finding| finding.category == *category).collect();
+                (category.name().to_string(), serde_json::json!(findings))
+            })
+            .collect();
+        let report = serde_json::json!({
+            "exit_code": self.exit_code(strict),
+            "errors": self.count(Severity::Error),
+            "warnings": self.count(Severity::Warning),
+            "categories": categories,
+        });
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    }
+
+    /// Log every finding at the level of its severity
+    pub fn log(&self) {
+        for category in Category::ALL {
+            for finding in self.findings.iter().filter(|finding| finding.category == *category) {
+                match finding.severity {
+                    Severity::Info => log::info!("{}", finding.message),
+                    Severity::Warning => log::warn!("{}", finding.message),
+                    Severity::Error => log::error!("{}", finding.message),
+                }
+            }
+        }
+    }
+}
+
+/// Where `doctor` looks
+#[derive(Debug, Clone, Copy)]
+pub struct Inputs<'a> {
+    /// Root of the repository, or the working directory outside one
+    pub root: &'a Path,
+    /// The native config, if one was found
+    pub config_path: Option<&'a Path>,
+    /// The cache directory
+    pub cache_root: &'a Path,
+    /// The `--cache-dir` option
+    pub cli_cache_dir: Option<&'a Path>,
+    /// The Git directory of the repository
+    pub git_dir: Option<&'a Path>,
+    /// The running rustyhook binary
+    pub current_exe: Option<&'a Path>,
+}
+
+/// Run every check
+pub fn diagnose(inputs: &Inputs) -> Report {
+    let mut report = Report::default();
+    let hooks = check_config(&mut report, inputs);
+    check_git(&mut report, inputs);
+    check_toolchains(&mut report, &hooks, inputs.cache_root);
+    check_environments(&mut report, inputs);
+    report
+}
+
+/// Check the config, returning its hooks
+fn check_config(report: &mut Report, inputs: &Inputs) -> Vec<Hook> {
+    let rustyhook_dir = inputs.root.join(".rustyhook");
+    let id = if rustyhook_dir.exists() { MessageId::DoctorRustyhookDirFound } else { MessageId::DoctorRustyhookDirMissing };
+    report.add(Category::Config, Severity::Info, id, &[]);
+
+    let Some(config_path) = inputs.config_path else {
+        report.add(Category::Config, Severity::Warning, MessageId::DoctorConfigMissing, &[]);
+        return Vec::new();
+    };
+    match parse_config(config_path).and_then(|config| expand_matrix(&config, inputs.root)) {
+        Ok(config) => {
+            report.add(Category::Config, Severity::Info, MessageId::DoctorConfigFound, &[]);
+            config.repos.into_iter().flat_map(|repo| repo.hooks).collect()
+        }
+        Err(e) => {
+            let error = format!("{:?}", e);
+            report.add(Category::Config, Severity::Error, MessageId::DoctorConfigInvalid, &[("path", &config_path.display()), ("error", &error)]);
+            Vec::new()
+        }
+    }
+}
+
+/// Check that the repository runs RustyHook on commits
+fn check_git(report: &mut Report, inputs: &Inputs) {
+    let Some(git_dir) = inputs.git_dir else {
+        report.add(Category::Git, Severity::Warning, MessageId::DoctorGitRepositoryMissing, &[]);
+        return;
+    };
+    let (severity, id) = match status::hook_install(Some(git_dir), inputs.current_exe) {
+        HookInstall::Installed => (Severity::Info, MessageId::DoctorGitHookInstalled),
+        HookInstall::Stale => (Severity::Warning, MessageId::DoctorGitHookStale),
+        HookInstall::Missing => (Severity::Warning, MessageId::DoctorGitHookMissing),
+    };
+    report.add(Category::Git, severity, id, &[]);
+}
+
+/// Check the runtimes and the commands the hooks need
+///
+/// Commands only hooks with `continue_on_error` need can't fail a run, so
+/// they are warnings.
+fn check_toolchains(report: &mut Report, hooks: &[Hook], cache_root: &Path) {
+    for (tool, command) in [("Python 3", "python3"), ("Ruby", "ruby")] {
+        match which::which(command) {
+            Ok(path) => report.add(Category::Toolchains, Severity::Info, MessageId::DoctorToolFound, &[("tool", &tool), ("path", &path.display())]),
+            Err(_) => report.add(Category::Toolchains, Severity::Warning, MessageId::DoctorToolMissing, &[("tool", &tool)]),
+        }
+    }
+
+    let mut node_versions: Vec<String> = std::fs::read_dir(crate::cache::runtimes_dir(cache_root).join("node"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().to_str().map(String::from))
+        .collect();
+    node_versions.sort();
+    if !node_versions.is_empty() {
+        report.add(Category::Toolchains, Severity::Info, MessageId::DoctorRuntimeVersions, &[("tool", &"Node.js"), ("versions", &node_versions.join(", "))]);
+    } else {
+        match which::which("node") {
+            Ok(path) => report.add(Category::Toolchains, Severity::Info, MessageId::DoctorToolFound, &[("tool", &"System Node.js"), ("path", &path.display())]),
+            Err(_) => report.add(Category::Toolchains, Severity::Info, MessageId::DoctorToolManaged, &[("tool", &"Node.js")]),
+        }
+    }
+
+    let (required, optional): (Vec<&Hook>, Vec<&Hook>) = hooks.iter().partition(|hook| !hook.continue_on_error);
+    for (hooks, severity) in [(optional, Severity::Warning), (required, Severity::Error)] {
+        for missing in capabilities::probe(hooks, cache_root) {
+            report.add(Category::Toolchains, severity, MessageId::DoctorCommandMissing, &[("missing", &missing)]);
+        }
+    }
+}
+
+/// Check the cache directories and the environments of the hooks
+fn check_environments(report: &mut Report, inputs: &Inputs) {
+    for name in ["cache", "venvs"] {
+        let dir = inputs.cache_root.join(name);
+        let id = if dir.exists() { MessageId::DoctorCacheDirFound } else { MessageId::DoctorCacheDirMissing };
+        report.add(Category::Environments, Severity::Info, id, &[("name", &name), ("path", &dir.display())]);
+    }
+
+    if let Some(config_path) = inputs.config_path {
+        let facts = status::config_facts(config_path, inputs.root, inputs.cli_cache_dir);
+        if facts.state == ConfigState::Valid {
+            let missing: Vec<String> = facts
+                .environments
+                .iter()
+                .filter(|environment| !ready::is_ready(&environment.install_dir, &environment.manifest))
+                .map(|environment| environment.hook_id.clone())
+                .collect();
+            if missing.is_empty() {
+                report.add(Category::Environments, Severity::Info, MessageId::DoctorEnvironmentsReady, &[]);
+            } else {
+                report.add(Category::Environments, Severity::Info, MessageId::DoctorEnvironmentsMissing, &[("hooks", &missing.join(", "))]);
+            }
+        }
+    }
+
+    // Asking the runtime for image sizes is only worth it once hooks ran in containers
+    if !container::ImageRecords::load(inputs.cache_root).images.is_empty() {
+        let usage = container::image_usage(&container::runtime(), inputs.cache_root);
+        if !usage.images.is_empty() {
+            let size = format_size(usage.images.iter().map(|(_, size)| size).sum());
+            let images: Vec<String> = usage.images.iter().map(|(image, size)| format!("{} ({})", image, format_size(*size))).collect();
+            report.add(
+                Category::Environments,
+                Severity::Info,
+                MessageId::DoctorContainerImages,
+                &[("size", &size), ("images", &images.join(", ")), ("pulled", &format_size(usage.pulled_bytes))],
+            );
+        }
+    }
+
+    let cwd = std::env::current_dir().ok();
+    for dir in [Some(inputs.root), cwd.as_deref()].into_iter().flatten() {
+        if let Some(runtime_dir) = find_legacy_runtime(dir) {
+            report.add(Category::Environments, Severity::Warning, MessageId::DoctorLegacyRuntime, &[("path", &runtime_dir.display())]);
+            break;
+        }
+    }
+}
@@ -1,11 +1,34 @@
 //! File matcher for RustyHook
 //!
 //! This module provides functionality for matching files against patterns.
+//!
+//! Patterns are matched against paths relative to the repository root, with
+//! forward slashes on every platform, so `^src/.*\.rs$` works on Windows too.
 
-use std::path::{Path, PathBuf};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 use regex::Regex;
 use globset::{Glob, GlobSet, GlobSetBuilder};
 
+use crate::config::Hook;
+use crate::hooks::HookFactory;
+use super::attributes::GitAttributes;
+
+/// Default `max_file_size` of native hooks that read files as text, in bytes
+pub const DEFAULT_NATIVE_MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
+/// Vendored dependencies, build output and minified JavaScript, which hooks skip by default
+pub const DEFAULT_EXCLUDES: &str = r"(^|/)(vendor|node_modules|dist|target)/|\.min\.js$";
+
+/// Lockfiles, which native fixers also skip by default
+pub const DEFAULT_FIXER_EXCLUDES: &str = r"(^|/)(Cargo\.lock|package-lock\.json|npm-shrinkwrap\.json|yarn\.lock|pnpm-lock\.yaml|poetry\.lock|uv\.lock|Pipfile\.lock|Gemfile\.lock|composer\.lock)$";
+
+/// Marker in the header of generated files, which native fixers skip by default
+pub const GENERATED_MARKER: &str = "@generated";
+
+/// Bytes at the start of a file searched for [`GENERATED_MARKER`]
+const GENERATED_HEADER_SIZE: u64 = 1024;
+
 /// Error type for file matcher operations
 #[derive(Debug)]
 pub enum FileMatcherError {
@@ -62,10 +85,21 @@ impl FileMatcher {
     }
     
     /// Check if a file matches the pattern
+    ///
+    /// The path is matched with forward slashes as separators.
     pub fn matches(&self, path: &Path) -> bool {
-        let path_str = path.to_string_lossy();
+        self.matches_normalized(&to_slash_path(path))
+    }
+
+    /// Check if a file matches the pattern, relative to the repository root
+    pub fn matches_in(&self, path: &Path, root: &Path) -> bool {
+        self.matches_normalized(&repo_relative_path(path, root))
+    }
+
+    /// Check if a normalized path matches the pattern
+    fn matches_normalized(&self, path: &str) -> bool {
         match self {
-            FileMatcher::Regex(regex) => regex.is_match(&path_str),
+            FileMatcher::Regex(regex) => regex.is_match(path),
             FileMatcher::Glob(globset) => globset.is_match(path),
         }
     }
@@ -77,6 +111,165 @@ impl FileMatcher {
             .cloned()
             .collect()
     }
+
+    /// Filter a list of files to those that match the pattern relative to `root`
+    pub fn filter_files_in(&self, files: &[PathBuf], root: &Path) -> Vec<PathBuf> {
+        files.iter()
+            .filter(|path| self.matches_in(path, root))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Convert a path to a string with forward slashes as separators
+///
+/// Verbatim Windows paths (`\\?\C:\...`) don't accept forward slashes, so
+/// they are kept as they are.
+pub fn to_slash_path(path: &Path) -> String {
+    let mut normalized = String::new();
+    // Whether the next component needs a separator before it
+    let mut separate = false;
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) if prefix.kind().is_verbatim() => return path.to_string_lossy().into_owned(),
+            Component::Prefix(prefix) => normalized.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => normalized.push('/'),
+            component => {
+                if separate {
+                    normalized.push('/');
+                }
+                normalized.push_str(&component.as_os_str().to_string_lossy());
+                separate = true;
+            }
+        }
+    }
+    normalized
+}
+
+/// Get a path relative to the repository root, with forward slashes
+///
+/// Paths outside the root are only converted to forward slashes.
+pub fn repo_relative_path(path: &Path, root: &Path) -> String {
+    if let Ok(relative) = path.strip_prefix(root) {
+        return to_slash_path(relative);
+    }
+    // The root is canonical, so the path may only match once canonicalized
+    match path.canonicalize() {
+        Ok(canonical) => match canonical.strip_prefix(root) {
+            Ok(relative) => to_slash_path(relative),
+            Err(_) => to_slash_path(path),
+        },
+        Err(_) => to_slash_path(path),
+    }
+}
+
+/// Get a path relative to the directory a hook runs in
+///
+/// Paths outside the directory are kept as they are, since `..` paths break
+/// tools that resolve them against symlinked directories.
+pub fn hook_relative_path(path: &Path, dir: &Path) -> PathBuf {
+    if !path.is_absolute() {
+        return path.to_path_buf();
+    }
+    if let Ok(relative) = path.strip_prefix(dir) {
+        return relative.to_path_buf();
+    }
+    // Either side may be canonical while the other isn't, such as /tmp on macOS
+    match (path.canonicalize(), dir.canonicalize()) {
+        (Ok(canonical), Ok(dir)) => match canonical.strip_prefix(&dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Check whether a file says it is generated in its header
+pub fn is_generated(path: &Path) -> bool {
+    let mut header = Vec::new();
+    let read = std::fs::File::open(path).and_then(|file| file.take(GENERATED_HEADER_SIZE).read_to_end(&mut header));
+    read.is_ok() && String::from_utf8_lossy(&header).contains(GENERATED_MARKER)
+}
+
+/// Remove the files a hook skips by default
+///
+/// Every hook skips [`DEFAULT_EXCLUDES`], and native fixers also skip
+/// lockfiles and files marked [`GENERATED_MARKER`].
+fn remove_default_excludes(hook: &Hook, files: &mut Vec<PathBuf>, root: &Path) -> Result<(), FileMatcherError> {
+    let excludes = FileMatcher::from_regex(DEFAULT_EXCLUDES)?;
+    files.retain(|file| !excludes.matches_in(file, root));
+
+    if hook.language == "native" && HookFactory::is_fixer(&hook.entry, &hook.args) {
+        let lockfiles = FileMatcher::from_regex(DEFAULT_FIXER_EXCLUDES)?;
+        files.retain(|file| {
+            let generated = is_generated(file);
+            if generated {
+                log::debug!("Skipping {} for {}: marked {}", file.display(), hook.id, GENERATED_MARKER);
+            }
+            !generated && !lockfiles.matches_in(file, root)
+        });
+    }
+    Ok(())
+}
+
+/// Filter files to those a hook should run on
+///
+/// Files must match the hook's `files` pattern, relative to the repository
+/// root (the current directory outside a repository). Unless
+/// `default_excludes` is false or the hook sets `no_default_excludes`,
+/// vendored, built and generated files are left out. Text hooks also skip
+/// the files `.gitattributes` marks as binary or generated. The files are
+/// returned with forward slashes unless the hook sets
+/// `native_path_separators`.
+pub fn filter_hook_files(hook: &Hook, files: &[PathBuf], attributes: &GitAttributes, default_excludes: bool) -> Result<Vec<PathBuf>, FileMatcherError> {
+    let root = match attributes.root() {
+        Some(root) => root.to_path_buf(),
+        None => std::env::current_dir().unwrap_or_default(),
+    };
+    let mut filtered = if !hook.files.is_empty() {
+        FileMatcher::from_regex(&hook.files)?.filter_files_in(files, &root)
+    } else {
+        files.to_vec()
+    };
+
+    if default_excludes && !hook.no_default_excludes {
+        remove_default_excludes(hook, &mut filtered, &root)?;
+    }
+
+    if hook.text_only.unwrap_or(is_native_text_hook(hook)) {
+        attributes.retain_text(&mut filtered);
+    }
+
+    if !hook.native_path_separators && std::path::MAIN_SEPARATOR != '/' {
+        filtered = filtered.iter().map(|file| PathBuf::from(to_slash_path(file))).collect();
+    }
+    Ok(filtered)
+}
+
+/// Check whether a hook is a native hook that reads files as text
+fn is_native_text_hook(hook: &Hook) -> bool {
+    hook.language == "native" && !HookFactory::ANY_CONTENT_HOOKS.contains(&hook.entry.as_str())
+}
+
+/// Get the largest file a hook runs on, if it has a limit
+pub fn max_file_size(hook: &Hook) -> Option<u64> {
+    match hook.max_file_size {
+        Some(0) => None,
+        Some(limit) => Some(limit),
+        None => is_native_text_hook(hook).then_some(DEFAULT_NATIVE_MAX_FILE_SIZE),
+    }
+}
+
+/// Get the files over a hook's `max_file_size`, with their sizes
+///
+/// Files that can't be read, like deleted ones, are left to the hook.
+pub fn oversized_files(hook: &Hook, files: &[PathBuf]) -> Vec<(PathBuf, u64)> {
+    let Some(limit) = max_file_size(hook) else { return Vec::new() };
+    files
+        .iter()
+        .filter_map(|file| std::fs::metadata(file).ok().map(|metadata| (file.clone(), metadata.len())))
+        .filter(|(_, size)| *size > limit)
+        .collect()
 }
 
 #[cfg(test)]
@@ -111,4 +304,30 @@ mod tests {
         assert!(filtered.contains(&PathBuf::from("src/lib.rs")));
         assert!(!filtered.contains(&PathBuf::from("src/main.py")));
     }
+
+    #[test]
+    fn test_matches_relative_to_root() {
+        let matcher = FileMatcher::from_regex(r"^src/.*\.rs$").unwrap();
+        let root = Path::new("/repo");
+        assert!(matcher.matches_in(Path::new("/repo/src/main.rs"), root));
+        assert!(!matcher.matches_in(Path::new("/repo/tests/src/main.rs"), root));
+        assert!(!matcher.matches(Path::new("/repo/src/main.rs")));
+        assert_eq!(repo_relative_path(Path::new("/elsewhere/main.rs"), root), "/elsewhere/main.rs");
+        assert_eq!(to_slash_path(Path::new("src/./main.rs")), "src/main.rs");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_paths_use_forward_slashes() {
+        let matcher = FileMatcher::from_regex(r"^src/.*\.rs$").unwrap();
+        assert!(matcher.matches(Path::new(r"src\main.rs")));
+        assert!(matcher.matches_in(Path::new(r"C:\repo\src\nested\lib.rs"), Path::new(r"C:\repo")));
+        assert!(!matcher.matches_in(Path::new(r"C:\repo\tests\lib.rs"), Path::new(r"C:\repo")));
+
+        let glob = FileMatcher::from_glob("src/**/*.rs").unwrap();
+        assert!(glob.matches_in(Path::new(r"C:\repo\src\nested\lib.rs"), Path::new(r"C:\repo")));
+
+        assert_eq!(to_slash_path(Path::new(r"C:\repo\src\main.rs")), "C:/repo/src/main.rs");
+        assert_eq!(to_slash_path(Path::new(r"\\?\C:\repo\main.rs")), r"\\?\C:\repo\main.rs");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,206 @@
+//! Git change classification for RustyHook
+//!
+//! This module classifies the files staged in the current changeset as added,
+//! modified, deleted or renamed, so hooks that care about deletions and renames
+//! (stale references, migration numbering) can see them instead of having them
+//! silently filtered out.
+//!
+//! With `--changed-since <ref>`, the changes are those between the merge base
+//! of the ref and `HEAD`, and `HEAD`, like `git diff <ref>...HEAD`. CI jobs on
+//! merge queues use it to check everything a branch changed.
+
+use std::path::{Path, PathBuf};
+
+use git2::{Delta, DiffFindOptions, Repository};
+
+/// Environment variable listing the files deleted in the changeset
+pub const DELETED_FILES_ENV: &str = "RH_DELETED_FILES";
+
+/// Environment variable listing the files renamed in the changeset
+pub const RENAMED_FILES_ENV: &str = "RH_RENAMED_FILES";
+
+/// How a file changed in the changeset
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeKind {
+    /// The file is new
+    Added,
+    /// The file's content or mode changed
+    Modified,
+    /// The file was removed
+    Deleted,
+    /// The file was moved from another path
+    Renamed {
+        /// The previous path of the file
+        from: PathBuf,
+    },
+}
+
+/// A file changed in the changeset
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileChange {
+    /// The path of the file (the old path for deletions)
+    pub path: PathBuf,
+    /// How the file changed
+    pub kind: ChangeKind,
+}
+
+/// Classify the changes staged in the repository containing `dir`
+///
+/// Paths are absolute, matching the files handed to hooks. A repository
+/// without any commits treats every staged file as added.
+pub fn staged_changes(dir: &Path) -> Result<Vec<FileChange>, git2::Error> {
+    let repo = Repository::discover(dir)?;
+    let workdir = repo
+        .workdir()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| dir.to_path_buf());
+
+    let head_tree = match repo.head() {
+        Ok(head) => Some(head.peel_to_tree()?),
+        Err(_) => None,
+    };
+
+    let diff = repo.diff_tree_to_index(head_tree.as_ref(), None, None)?;
+    classify(diff, &workdir)
+}
+
+/// Classify the changes between the merge base of `rev` and `HEAD`, and `HEAD`
+///
+/// Paths are absolute, as for [`staged_changes`].
+pub fn changes_since(dir: &Path, rev: &str) -> Result<Vec<FileChange>, git2::Error> {
+    changes_between(dir, rev, "HEAD")
+}
+
+/// Classify the changes between the merge base of `from` and `to`, and `to`
+///
+/// This is `git diff FROM...TO`. Paths are absolute, as for [`staged_changes`].
+pub fn changes_between(dir: &Path, from: &str, to: &str) -> Result<Vec<FileChange>, git2::Error> {
+    let repo = Repository::discover(dir)?;
+    let workdir = repo
+        .workdir()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| dir.to_path_buf());
+
+    let to = repo.revparse_single(to)?.peel_to_commit()?;
+    let from = repo.revparse_single(from)?.peel_to_commit()?;
+    let base = repo.find_commit(repo.merge_base(from.id(), to.id())?)?;
+
+    let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&to.tree()?), None)?;
+    classify(diff, &workdir)
+}
+
+/// Get the files in the index of the repository containing `dir` that are under `dir`
+///
+/// This is `git ls-files`, so untracked files and the `.git` directory are
+/// left out. Paths are joined to `dir`, and files missing from the working
+/// tree are skipped.
+pub fn tracked_files(dir: &Path) -> Result<Vec<PathBuf>, git2::Error> {
+    let repo = Repository::discover(dir)?;
+    let workdir = repo
+        .workdir()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| dir.to_path_buf());
+    let prefix = match dir.strip_prefix(&workdir) {
+        Ok(prefix) => prefix.to_path_buf(),
+        Err(_) => {
+            let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+            let workdir = workdir.canonicalize().unwrap_or(workdir);
+            dir.strip_prefix(&workdir).map(Path::to_path_buf).unwrap_or_default()
+        }
+    };
+
+    let mut files: Vec<PathBuf> = repo
+        .index()?
+        .iter()
+        .filter_map(|entry| {
+            let path = PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned());
+            path.strip_prefix(&prefix).ok().map(|relative| dir.join(relative))
+        })
+        .filter(|path| path.is_file())
+        .collect();
+    // Conflicted files have an entry per side
+    files.dedup();
+    Ok(files)
+}
+
+/// Get the tracked files of the repository containing `dir` with unstaged changes
+///
+/// Paths are absolute, as for [`staged_changes`]. Untracked files aren't
+/// included, since they have nothing staged to mix changes with.
+pub fn unstaged_files(dir: &Path) -> Result<Vec<PathBuf>, git2::Error> {
+    let repo = Repository::discover(dir)?;
+    let workdir = repo
+        .workdir()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| dir.to_path_buf());
+
+    let diff = repo.diff_index_to_workdir(None, None)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()).map(|p| workdir.join(p)))
+        .collect())
+}
+
+/// Get the files to check from changes, leaving out deleted files
+pub fn changed_files(changes: &[FileChange]) -> Vec<PathBuf> {
+    changes
+        .iter()
+        .filter(|change| change.kind != ChangeKind::Deleted)
+        .map(|change| change.path.clone())
+        .collect()
+}
+
+/// Classify the files of a diff, with paths joined to the working directory
+fn classify(mut diff: git2::Diff, workdir: &Path) -> Result<Vec<FileChange>, git2::Error> {
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut changes = Vec::new();
+    for delta in diff.deltas() {
+        let new_path = delta.new_file().path().map(|p| workdir.join(p));
+        let old_path = delta.old_file().path().map(|p| workdir.join(p));
+
+        let change = match (delta.status(), new_path, old_path) {
+            (Delta::Added | Delta::Copied, Some(path), _) => FileChange { path, kind: ChangeKind::Added },
+            (Delta::Modified | Delta::Typechange, Some(path), _) => FileChange { path, kind: ChangeKind::Modified },
+            (Delta::Deleted, _, Some(path)) => FileChange { path, kind: ChangeKind::Deleted },
+            (Delta::Renamed, Some(path), Some(from)) => FileChange { path, kind: ChangeKind::Renamed { from } },
+            _ => continue,
+        };
+        changes.push(change);
+    }
+
+    Ok(changes)
+}
+
+/// Get the paths deleted in a changeset
+pub fn deleted_files(changes: &[FileChange]) -> Vec<PathBuf> {
+    changes
+        .iter()
+        .filter(|change| change.kind == ChangeKind::Deleted)
+        .map(|change| change.path.clone())
+        .collect()
+}
+
+/// Build the environment variables describing deletions and renames
+///
+/// `RH_DELETED_FILES` holds one path per line. `RH_RENAMED_FILES` holds one
+/// `old<TAB>new` pair per line.
+pub fn change_env(changes: &[FileChange]) -> Vec<(String, String)> {
+    let deleted: Vec<String> = deleted_files(changes)
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    let renamed: Vec<String> = changes
+        .iter()
+        .filter_map(|change| match &change.kind {
+            ChangeKind::Renamed { from } => Some(format!("{}\t{}", from.display(), change.path.display())),
+            _ => None,
+        })
+        .collect();
+
+    vec![
+        (DELETED_FILES_ENV.to_string(), deleted.join("\n")),
+        (RENAMED_FILES_ENV.to_string(), renamed.join("\n")),
+    ]
+}
@@ -0,0 +1,97 @@
+//! Safety checks before hooks run
+//!
+//! Hooks run as root, like with `sudo git commit`, leave root-owned files and
+//! environments behind, so they are warned about or refused, as the config's
+//! `run_as_root` says. A config found by searching the parent directories
+//! must belong to the repository being hooked: a stale config above a nested
+//! repository would otherwise run the wrong hooks.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::config::parser::RunAsRoot;
+
+/// Error type for the checks before a run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardError {
+    /// Hooks were run as root and `run_as_root` is `fail`
+    RunningAsRoot,
+    /// The config is outside the repository being hooked
+    ConfigOutsideRepo {
+        /// The config file
+        config: PathBuf,
+        /// Root of the repository being hooked
+        repo_root: PathBuf,
+    },
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::RunningAsRoot => write!(f, "Refusing to run hooks as root (run_as_root: fail)"),
+            GuardError::ConfigOutsideRepo { config, repo_root } => write!(
+                f,
+                "Config {} is not part of the repository at {}; add a .rustyhook/config.yaml to the repository or pass --config",
+                config.display(),
+                repo_root.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+/// Check whether the process runs as root
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Check whether the process runs as root
+#[cfg(not(unix))]
+pub fn is_root() -> bool {
+    false
+}
+
+/// Apply the `run_as_root` policy
+///
+/// With `warn`, the warning is logged and the run goes on.
+pub fn check_root(policy: RunAsRoot, root: bool) -> Result<(), GuardError> {
+    if !root {
+        return Ok(());
+    }
+    match policy {
+        RunAsRoot::Allow => Ok(()),
+        RunAsRoot::Warn => {
+            log::warn!("Running hooks as root, files and environments they create will be owned by root. Set run_as_root to allow or fail to change this");
+            Ok(())
+        }
+        RunAsRoot::Fail => Err(GuardError::RunningAsRoot),
+    }
+}
+
+/// Get the root of the repository being hooked from a directory
+///
+/// `GIT_DIR` and `GIT_WORK_TREE`, which Git sets for hooks, are honored.
+pub fn repo_root(dir: &Path) -> Option<PathBuf> {
+    let repo = if std::env::var_os("GIT_DIR").is_some() {
+        git2::Repository::open_from_env().ok()?
+    } else {
+        git2::Repository::discover(dir).ok()?
+    };
+    repo.workdir()?.canonicalize().ok()
+}
+
+/// Check that a config found by searching belongs to the repository at `repo_root`
+///
+/// A config in a parent directory of the repository, or in the repository's
+/// own parent repository, doesn't.
+pub fn check_config_repo(config: &Path, repo_root: &Path) -> Result<(), GuardError> {
+    let config = config.canonicalize().unwrap_or_else(|_| config.to_path_buf());
+    if config.starts_with(repo_root) {
+        Ok(())
+    } else {
+        Err(GuardError::ConfigOutsideRepo { config, repo_root: repo_root.to_path_buf() })
+    }
+}
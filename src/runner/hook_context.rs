@@ -3,10 +3,15 @@
 //! This module provides the context for running hooks.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use crate::config::parser::HookType;
+use crate::config::parser::{command_line, entry_commands, HookType};
+use super::file_matcher::hook_relative_path;
 use crate::hooks::HookError;
+use crate::toolchains::{diagnostics, network, uv_script};
+use crate::toolchains::exit_status::{self, SuccessCodes};
+use crate::toolchains::output::{self, OutputLimit, DEFAULT_MAX_OUTPUT};
 
 /// Error type for hook context operations
 #[derive(Debug)]
@@ -72,6 +77,9 @@ pub struct HookContext {
     /// Additional arguments to pass to the hook
     pub args: Vec<String>,
 
+    /// Arguments to pass to the hook after the files
+    pub args_after_files: Vec<String>,
+
     /// Additional environment variables
     pub env: HashMap<String, String>,
 
@@ -89,10 +97,32 @@ pub struct HookContext {
 
     /// Files to process
     pub files_to_process: Vec<PathBuf>,
+
+    /// Container image to run the entry in
+    pub image: Option<String>,
+
+    /// Cache root to record the container image in, see [`super::container::record_image`]
+    pub image_records: Option<PathBuf>,
+
+    /// Whether to pass absolute paths instead of paths relative to where the hook runs
+    pub absolute_paths: bool,
+
+    /// Limit of the output shown from the hook
+    pub output_limit: OutputLimit,
+
+    /// Exit codes the hook passes with
+    pub success_codes: SuccessCodes,
+
+    /// Script of the repository's package.json to run instead of the entry
+    pub npm_script: Option<String>,
+
+    /// Whether the hook runs with the repository's `node_modules`
+    pub repo_node_modules: bool,
 }
 
 impl HookContext {
     /// Create a new hook context
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: String,
         name: String,
@@ -108,6 +138,8 @@ impl HookContext {
         working_dir: PathBuf,
         files_to_process: Vec<PathBuf>,
     ) -> Self {
+        let output_limit = OutputLimit { hook_id: id.clone(), ..Default::default() };
+        let success_codes = SuccessCodes::new(&id, &[]);
         HookContext {
             id,
             name,
@@ -116,21 +148,36 @@ impl HookContext {
             files,
             stages,
             args,
+            args_after_files: Vec::new(),
             env,
             version,
             hook_type,
             separate_process,
             working_dir,
             files_to_process,
+            image: None,
+            image_records: None,
+            absolute_paths: false,
+            output_limit,
+            success_codes,
+            npm_script: None,
+            repo_node_modules: false,
         }
     }
 
     /// Create a hook context from a hook configuration
+    ///
+    /// If the hook declares a `working_dir`, it is resolved relative to `working_dir`.
     pub fn from_hook(
         hook: &crate::config::Hook,
         working_dir: PathBuf,
         files_to_process: Vec<PathBuf>,
     ) -> Self {
+        let working_dir = match &hook.working_dir {
+            Some(dir) => working_dir.join(dir),
+            None => working_dir,
+        };
+
         HookContext {
             id: hook.id.clone(),
             name: hook.name.clone(),
@@ -139,68 +186,124 @@ impl HookContext {
             files: hook.files.clone(),
             stages: hook.stages.clone(),
             args: hook.args.clone(),
+            args_after_files: hook.args_after_files.clone(),
             env: hook.env.clone(),
             version: hook.version.clone(),
             hook_type: hook.hook_type.clone(),
             separate_process: hook.separate_process,
             working_dir,
             files_to_process,
+            image: hook.image.clone(),
+            image_records: None,
+            absolute_paths: hook.absolute_paths,
+            output_limit: OutputLimit {
+                hook_id: hook.id.clone(),
+                max_bytes: hook.max_output.unwrap_or(DEFAULT_MAX_OUTPUT),
+                truncation: hook.output_truncation,
+                log_dir: None,
+            },
+            success_codes: SuccessCodes::new(&hook.id, &hook.success_exit_codes),
+            npm_script: hook.npm_script.clone(),
+            repo_node_modules: hook.uses_repo_node_modules(),
+        }
+    }
+
+    /// Expose the deletions and renames of the changeset through the environment
+    pub fn set_changes(&mut self, changes: &[super::git_changes::FileChange]) {
+        self.env.extend(super::git_changes::change_env(changes));
+    }
+
+    /// Get the files to pass to a hook process running in `dir`
+    ///
+    /// Files are relative to `dir`, unless the hook asks for absolute paths.
+    pub fn hook_files(&self, dir: &Path) -> Vec<PathBuf> {
+        if self.absolute_paths {
+            return self.files_to_process.clone();
         }
+        self.files_to_process.iter().map(|file| hook_relative_path(file, dir)).collect()
     }
 
     /// Determine if the hook should be run in a separate process
     pub fn should_run_in_separate_process(&self) -> bool {
+        // Hooks with an image always run their entry in a container
+        if self.image.is_some() {
+            return true;
+        }
+
+        // Hooks using the repository's node_modules have no environment of their own
+        if self.repo_node_modules {
+            return true;
+        }
+
+        // Fail, pygrep and native hooks have a message, pattern or hook ID rather than a command as their entry
+        if matches!(self.language.as_str(), "fail" | "pygrep" | "native") {
+            return false;
+        }
         self.separate_process || self.hook_type == HookType::External
     }
 
     /// Run the hook in a separate process
+    ///
+    /// An entry of several commands runs them one after another, with the
+    /// same environment and working directory, stopping at the first failure.
+    /// The hook's arguments and files go to the last command, see
+    /// [`command_line`].
     pub fn run_in_separate_process(&self) -> Result<(), HookContextError> {
         println!("Running hook {} in separate process", self.id);
 
-        // Parse the entry to separate the command from any arguments
-        let parts: Vec<&str> = self.entry.split_whitespace().collect();
-        if parts.is_empty() {
+        // An npm script gets the arguments and files after `--`
+        let script = self.npm_script.as_ref().map(|script| format!("npm run {} --", script));
+        let commands = match &script {
+            Some(script) => vec![script.as_str()],
+            None => entry_commands(&self.language, &self.entry),
+        };
+        if commands.is_empty() {
             return Err(HookContextError::ProcessError(format!(
                 "Empty entry for hook {}", self.id
             )));
         }
-
-        // The first part is the command, the rest are arguments
-        let command_name = parts[0];
-        let command_args = &parts[1..];
-
-        // Create a command to run the hook
-        let mut command = Command::new(command_name);
-
-        // Add any arguments from the entry
-        for arg in command_args {
-            command.arg(arg);
-        }
-
-        // Add arguments from the hook configuration
-        for arg in &self.args {
-            command.arg(arg);
-        }
-
-        // Add files to process
-        for file in &self.files_to_process {
-            command.arg(file);
+        for (index, command) in commands.iter().enumerate() {
+            self.run_command(command, index + 1 == commands.len())?;
         }
+        Ok(())
+    }
 
-        // Set environment variables
-        for (key, value) in &self.env {
-            command.env(key, value);
-        }
+    /// Run one command of the entry in a separate process
+    fn run_command(&self, entry_command: &str, last: bool) -> Result<(), HookContextError> {
+        // Parse the command to separate the program from any arguments
+        let parts: Vec<&str> = entry_command.split_whitespace().collect();
+
+        // Hooks with an image run the whole command line in a container
+        let (command_name, mut command) = match &self.image {
+            Some(image) => {
+                let runtime = super::container::runtime();
+                let mount = super::container::Mount::for_dir(&self.working_dir);
+                let files: Vec<String> = self.files_to_process.iter().map(|file| mount.container_path(file)).collect();
+                let argv = command_line(entry_command, last, &self.args, &files, &self.args_after_files);
+                if let Some(cache_root) = &self.image_records {
+                    super::container::record_image(&runtime, image, cache_root);
+                }
+                let command = super::container::command(&runtime, image, &self.id, &mount, &self.env, &argv);
+                (runtime, command)
+            }
+            // Python scripts with inline metadata run with uv, which sets up their dependencies
+            None if self.language == "python" && uv_script::is_script(&self.working_dir.join(parts[0])) => {
+                let uv_command = format!("uv run --quiet --script {}", entry_command);
+                ("uv".to_string(), self.host_command(&uv_command, last))
+            }
+            None => (parts[0].to_string(), self.host_command(entry_command, last)),
+        };
 
         // Set working directory
         command.current_dir(&self.working_dir);
 
         // Run the command
         let output = command.output().map_err(|err| {
+            diagnostics::report_failure(&format!("hook {}", self.id), &command);
             if err.kind() == std::io::ErrorKind::NotFound {
                 // Command not found error
                 HookContextError::CommandNotFound {
-                    command: command_name.to_string(),
+                    command: command_name.clone(),
                     hook_id: self.id.clone(),
                     error: err,
                 }
@@ -214,8 +317,10 @@ impl HookContext {
         })?;
 
         // Check if the command was successful
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
+        output::record_stdout(&output.stdout);
+        if !self.success_codes.accepts(&output.status) {
+            diagnostics::report_failure(&format!("hook {}", self.id), &command);
+            let stderr = output::truncate(&self.output_limit, "stderr", &output.stderr);
             return Err(HookContextError::ProcessError(format!(
                 "Hook {} failed: {}", self.id, stderr
             )));
@@ -224,6 +329,40 @@ impl HookContext {
         Ok(())
     }
 
+    /// Build the command running a command of the entry on the host
+    fn host_command(&self, entry_command: &str, last: bool) -> Command {
+        // Files to process are relative to the directory the command runs in
+        let files: Vec<OsString> = self.hook_files(&self.working_dir).into_iter().map(PathBuf::into_os_string).collect();
+        let argv = command_line(entry_command, last, &self.args, &files, &self.args_after_files);
+
+        // The first part is the command, the rest are arguments
+        let path = self.host_path();
+        let mut command = network::hook_command_in(&argv[0], &path, &self.working_dir);
+        command.args(&argv[1..]);
+
+        // Set environment variables
+        for (key, value) in self.env.iter().filter(|(key, _)| *key != "PATH") {
+            command.env(key, value);
+        }
+        command.env("PATH", path);
+
+        command
+    }
+
+    /// Get the PATH the entry runs with on the host
+    ///
+    /// These commands come from the developer's environment, so the hook
+    /// gets the inherited PATH, or its PATH override, see
+    /// [`hook_path`](crate::toolchains::hermetic::hook_path). The commands
+    /// installed by the repository's packages come first.
+    pub fn host_path(&self) -> OsString {
+        let bin_dirs: Vec<PathBuf> = match self.repo_node_modules {
+            true => crate::toolchains::node::find_package_dir(&self.working_dir).map(|dir| dir.join("node_modules").join(".bin")).into_iter().collect(),
+            false => Vec::new(),
+        };
+        crate::toolchains::hermetic::hook_path(&bin_dirs, &self.env, std::env::var_os("PATH").unwrap_or_default())
+    }
+
     /// Execute the hook using the appropriate method
     pub fn execute(&self, tool: Option<&dyn crate::toolchains::Tool>) -> Result<(), HookContextError> {
         // If there are no files to process, we're done
@@ -238,7 +377,7 @@ impl HookContext {
         } else {
             // Run the hook in the same process using the tool
             if let Some(tool) = tool {
-                tool.run(&self.files_to_process).map_err(HookContextError::ToolError)
+                self.run_tool(tool, true)
             } else {
                 Err(HookContextError::ProcessError(format!(
                     "No tool provided for hook {}", self.id
@@ -246,4 +385,17 @@ impl HookContext {
             }
         }
     }
+
+    /// Run a command of the entry in the same process with its tool
+    ///
+    /// Commands of an entry that don't take the files, see
+    /// [`Hook::command_hooks`](crate::config::Hook::command_hooks), run
+    /// without any.
+    pub fn run_tool(&self, tool: &dyn crate::toolchains::Tool, takes_files: bool) -> Result<(), HookContextError> {
+        // Tools run in the current directory
+        let dir = std::env::current_dir()?;
+        let files = if takes_files { self.hook_files(&dir) } else { Vec::new() };
+        let run = || exit_status::with_success_codes(&self.success_codes, || tool.run(&files));
+        output::with_limit(&self.output_limit, run).map_err(HookContextError::ToolError)
+    }
 }
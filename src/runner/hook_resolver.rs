@@ -2,15 +2,32 @@
 //!
 //! This module provides functionality for resolving and running hooks.
 
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::env;
 
+use sha2::{Digest, Sha256};
+
+use crate::cache::{self, orphans};
 use crate::config::{Config, Hook};
-use crate::toolchains::{Tool, ToolError, SetupContext, PythonTool, NodeTool, RubyTool, SystemTool};
+use crate::config::parser::NodeModules;
+use crate::config::hook_repo::resolve_entry;
+use crate::toolchains::{diagnostics, network, node, output, ready, uv_script};
+use crate::toolchains::node::NodeModulesState;
+use crate::error_codes::HasErrorCode;
+use crate::toolchains::hermetic::{apply_hook_env, home_vars, hook_path};
+use crate::toolchains::{Tool, ToolError, SetupContext, PythonTool, NodeTool, RubyTool, SystemTool, FailTool, PygrepTool, PygrepOptions, NativeTool, UvScriptTool};
 use crate::hooks::HookError;
-use super::file_matcher::{FileMatcher, FileMatcherError};
+use crate::messages::{self, MessageId};
+use super::attributes::GitAttributes;
+use super::file_matcher::{filter_hook_files, FileMatcherError};
 use super::hook_context::HookContext;
+use super::git_changes::FileChange;
+use super::report::EnvironmentSetup;
+use super::run_env::RunEnv;
+use super::stage_input::StageInput;
+use super::latest::Registry;
+use super::progress::{Progress, ProgressEvent};
 
 /// Error type for hook resolver operations
 #[derive(Debug)]
@@ -36,6 +53,22 @@ pub enum HookResolverError {
         /// Additional context about the error
         context: String,
     },
+    /// A hook's preflight command failed, even after rebuilding its environment
+    ProvisioningError {
+        /// The hook
+        hook_id: String,
+        /// The preflight command
+        command: String,
+        /// Why the command failed
+        details: String,
+    },
+    /// A hook with `require_clean` has files with unstaged changes
+    UncleanFiles {
+        /// The hook
+        hook_id: String,
+        /// The files with unstaged changes
+        files: Vec<std::path::PathBuf>,
+    },
 }
 
 impl From<FileMatcherError> for HookResolverError {
@@ -62,35 +95,73 @@ impl From<std::io::Error> for HookResolverError {
     }
 }
 
-impl std::fmt::Display for HookResolverError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl HookResolverError {
+    /// Get the catalog message describing this error
+    ///
+    /// The message is the one of the error's code, see
+    /// [`ErrorCode::message_id`](crate::error_codes::ErrorCode::message_id),
+    /// and IO errors are told apart by their kind.
+    pub fn message_id(&self) -> MessageId {
         match self {
-            HookResolverError::FileMatcherError(err) => write!(f, "ERROR: File matching error.\n\nDetails: {:?}\n\nSOLUTION: Check the file pattern in your configuration and ensure it's a valid regex pattern.", err),
-            HookResolverError::ToolError(err) => write!(f, "ERROR: Tool setup or execution failed.\n\nDetails: {:?}\n\nSOLUTION: Ensure the required tools are installed and properly configured. Run 'rustyhook doctor' for diagnostics.", err),
-            HookResolverError::HookError(err) => write!(f, "ERROR: Hook execution failed.\n\nDetails: {:?}\n\nSOLUTION: Check the hook configuration and ensure all dependencies are installed.", err),
-            HookResolverError::HookNotFound(msg) => write!(f, "ERROR: Hook not found.\n\nDetails: {}\n\nSOLUTION: Verify that the hook ID is correct and defined in your configuration file.", msg),
-            HookResolverError::UnsupportedLanguage(lang) => write!(f, "ERROR: Unsupported language: {}\n\nSOLUTION: Use one of the supported languages: python, node, javascript, typescript, ruby, or system.", lang),
-            HookResolverError::ProcessError(msg) => write!(f, "ERROR: Process execution failed.\n\nDetails: {}\n\nSOLUTION: Check that the command exists and has the correct permissions.", msg),
-            HookResolverError::FileNotFound { path, context } => {
-                write!(f, "ERROR: Specific file not found: {}\n\nContext: {}\n\nSOLUTION: Please check that this file exists and that the path is correct. If this is a configuration file, ensure it's properly formatted.", 
-                       path.display(), context)
+            HookResolverError::IoError(err) => match err.kind() {
+                std::io::ErrorKind::NotFound => MessageId::NotFound,
+                std::io::ErrorKind::PermissionDenied => MessageId::PermissionDenied,
+                _ => MessageId::IoError,
             },
-            HookResolverError::IoError(err) => {
-                match err.kind() {
-                    std::io::ErrorKind::NotFound => write!(f, "ERROR: File or directory not found.\n\nThis could be due to one of the following issues:\n\
-                       - Missing configuration file (check for .rustyhook/config.yaml or .pre-commit-config.yaml)\n\
-                       - Missing hook script or executable (verify the 'entry' path in your config)\n\
-                       - Missing dependencies required by a hook\n\
-                       - Incorrect working directory (ensure you're running from the repository root)\n\n\
-                       SOLUTION: Try running 'rustyhook doctor' for more detailed diagnostics, or check the paths in your configuration."),
-                    std::io::ErrorKind::PermissionDenied => write!(f, "ERROR: Permission denied.\n\nDetails: {}\n\nSOLUTION: Check file permissions and ensure you have the necessary access rights. You may need to run with elevated privileges.", err),
-                    _ => write!(f, "ERROR: IO operation failed.\n\nDetails: {}\n\nSOLUTION: Check system resources, disk space, and file access. If the issue persists, try running 'rustyhook doctor' for diagnostics.", err),
-                }
+            _ => self.error_code().message_id().expect("every hook resolver error code has a message"),
+        }
+    }
+
+    /// Get what went wrong, without the advice of the catalog message
+    pub fn details(&self) -> String {
+        match self {
+            HookResolverError::ToolError(ToolError::ExecutionError(details))
+            | HookResolverError::ToolError(ToolError::ToolNotFound(details))
+            | HookResolverError::ToolError(ToolError::InstallationError(details))
+            | HookResolverError::HookError(HookError::Other(details))
+            | HookResolverError::HookNotFound(details)
+            | HookResolverError::ProcessError(details)
+            | HookResolverError::ProvisioningError { details, .. } => details.clone(),
+            HookResolverError::ToolError(ToolError::IoError(err)) | HookResolverError::HookError(HookError::IoError(err)) | HookResolverError::IoError(err) => {
+                err.to_string()
+            }
+            HookResolverError::HookError(HookError::Utf8Error(err)) => err.to_string(),
+            HookResolverError::FileMatcherError(err) => format!("{:?}", err),
+            HookResolverError::UnsupportedLanguage(language) => format!("Unsupported language: {}", language),
+            HookResolverError::FileNotFound { path, context } => format!("{} not found: {}", path.display(), context),
+            HookResolverError::UncleanFiles { files, .. } => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                format!("Unstaged changes in {}", files.join(" "))
             }
         }
     }
 }
 
+impl std::fmt::Display for HookResolverError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.message_id();
+        let message = match self {
+            HookResolverError::FileMatcherError(err) => messages::format(id, &[("details", &format!("{:?}", err))]),
+            HookResolverError::ToolError(err) => messages::format(id, &[("details", &format!("{:?}", err))]),
+            HookResolverError::HookError(err) => messages::format(id, &[("details", &format!("{:?}", err))]),
+            HookResolverError::HookNotFound(msg) | HookResolverError::ProcessError(msg) => messages::format(id, &[("details", msg)]),
+            HookResolverError::UnsupportedLanguage(lang) => messages::format(id, &[("language", lang)]),
+            HookResolverError::FileNotFound { path, context } => {
+                messages::format(id, &[("path", &path.display()), ("context", context)])
+            },
+            HookResolverError::IoError(err) => messages::format(id, &[("details", err)]),
+            HookResolverError::ProvisioningError { hook_id, command, details } => {
+                messages::format(id, &[("hook", hook_id), ("command", command), ("details", details)])
+            },
+            HookResolverError::UncleanFiles { hook_id, files } => {
+                let files: Vec<String> = files.iter().map(|file| file.display().to_string()).collect();
+                messages::format(id, &[("hook", hook_id), ("files", &files.join(" "))])
+            },
+        };
+        f.write_str(&message)
+    }
+}
+
 impl std::error::Error for HookResolverError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
@@ -110,6 +181,55 @@ pub struct HookResolver {
     tool_cache: HashMap<String, Box<dyn Tool>>,
     /// Hooks to skip
     hooks_to_skip: Vec<String>,
+    /// Changes in the current changeset
+    changes: Vec<FileChange>,
+    /// Environments set up so far
+    setups: Vec<EnvironmentSetup>,
+    /// Where environment setups are reported
+    progress: Progress,
+    /// Preflight commands that passed in this session, with the environment they ran in
+    preflights: HashSet<String>,
+    /// Errors of the tool setups that failed in this session, by tool cache key
+    failed_setups: HashMap<String, ToolError>,
+    /// What hooks are told about the run
+    run_env: RunEnv,
+}
+
+/// Copy a tool error, which can't be cloned because of its I/O errors
+fn copy_tool_error(error: &ToolError) -> ToolError {
+    match error {
+        ToolError::ExecutionError(message) => ToolError::ExecutionError(message.clone()),
+        ToolError::ToolNotFound(message) => ToolError::ToolNotFound(message.clone()),
+        ToolError::InstallationError(message) => ToolError::InstallationError(message.clone()),
+        ToolError::IoError(e) => ToolError::IoError(std::io::Error::new(e.kind(), e.to_string())),
+    }
+}
+
+/// The setup of a hook's tool, which runs without holding the resolver
+pub struct PendingSetup {
+    /// ID of the hook the tool is set up for
+    hook_id: String,
+    /// Key of the tool in the tool cache
+    cache_key: String,
+    /// Key of the tool's environment
+    tool_key: String,
+    /// The tool to set up
+    tool: Box<dyn Tool>,
+    /// Where the tool is set up
+    ctx: SetupContext,
+    /// Manifest the environment is marked ready with
+    manifest: Option<String>,
+    /// Whether the environment was installed already
+    reused: bool,
+    /// When the setup started
+    started: std::time::Instant,
+}
+
+impl PendingSetup {
+    /// Set up the tool
+    pub fn run(&self) -> Result<(), ToolError> {
+        self.tool.setup(&self.ctx)
+    }
 }
 
 impl HookResolver {
@@ -120,9 +240,20 @@ impl HookResolver {
             cache_dir,
             tool_cache: HashMap::new(),
             hooks_to_skip: Vec::new(),
+            changes: Vec::new(),
+            setups: Vec::new(),
+            progress: Progress::default(),
+            preflights: HashSet::new(),
+            failed_setups: HashMap::new(),
+            run_env: RunEnv::detect(&env::current_dir().unwrap_or_else(|_| PathBuf::from("."))),
         }
     }
 
+    /// Get the cache directory
+    pub fn cache_dir(&self) -> &PathBuf {
+        &self.cache_dir
+    }
+
     /// Set hooks to skip
     pub fn set_hooks_to_skip(&mut self, hooks: Vec<String>) {
         self.hooks_to_skip = hooks;
@@ -133,6 +264,41 @@ impl HookResolver {
         &self.hooks_to_skip
     }
 
+    /// Set the changes in the current changeset
+    pub fn set_changes(&mut self, changes: Vec<FileChange>) {
+        self.changes = changes;
+    }
+
+    /// Set the stage of the run, which hooks see as `RH_STAGE`
+    pub fn set_stage(&mut self, stage: &str) {
+        self.run_env.stage = stage.to_string();
+    }
+
+    /// Set what Git passed to the stage, which hooks see in their environment
+    pub fn set_stage_input(&mut self, input: &StageInput) {
+        self.run_env.stage_input = input.env();
+    }
+
+    /// Get what hooks are told about the run
+    pub fn run_env(&self) -> &RunEnv {
+        &self.run_env
+    }
+
+    /// Get the changes in the current changeset
+    pub fn changes(&self) -> &Vec<FileChange> {
+        &self.changes
+    }
+
+    /// Report environment setups to a run's progress
+    pub fn set_progress(&mut self, progress: Progress) {
+        self.progress = progress;
+    }
+
+    /// Get the environments set up so far
+    pub fn setups(&self) -> &[EnvironmentSetup] {
+        &self.setups
+    }
+
     /// Get the configuration
     pub fn config(&self) -> &Config {
         &self.config
@@ -148,21 +314,22 @@ impl HookResolver {
             }
         })?;
 
-        // Create a file matcher if the hook has a file pattern
-        let filtered_files = if !hook.files.is_empty() {
-            let matcher = FileMatcher::from_regex(&hook.files)?;
-            matcher.filter_files(files)
-        } else {
-            files.to_vec()
-        };
+        // Filter files based on the hook's file pattern and attributes
+        let attributes = GitAttributes::discover(files.first().unwrap_or(&working_dir)).with_cache(&self.cache_dir);
+        let filtered_files = filter_hook_files(hook, files, &attributes, !self.config.no_default_excludes)?;
 
         // Create the context
-        let context = HookContext::from_hook(hook, working_dir, filtered_files);
+        let mut context = HookContext::from_hook(hook, working_dir, filtered_files);
+        context.set_changes(&self.changes);
+        context.output_limit.log_dir = Some(self.cache_dir.join(output::LOGS_DIR));
+        context.image_records = Some(self.cache_dir.clone());
 
         Ok(context)
     }
 
     /// Resolve a hook by ID
+    ///
+    /// Entries running a file of the hook's repo point at its checkout, see [`resolve_entry`].
     pub fn resolve_hook(&self, repo_id: &str, hook_id: &str) -> Result<Hook, HookResolverError> {
         // Find the repository
         let repo = self.config.repos.iter()
@@ -174,8 +341,170 @@ impl HookResolver {
             .find(|h| h.id == hook_id)
             .ok_or_else(|| HookResolverError::HookNotFound(format!("Hook {} not found in repository {}", hook_id, repo_id)))?;
 
-        // Return a clone of the hook to avoid borrowing issues
-        Ok(hook.clone())
+        Ok(resolve_entry(repo_id, hook))
+    }
+
+    /// Give a hook its persistent cache directory and the variables describing the run
+    ///
+    /// The directory is exposed as `RH_HOOK_CACHE_DIR` and replaces
+    /// `{hook_cache_dir}` in the hook's `args` and `env` values, so tools like
+    /// mypy or eslint can keep their caches out of the repository. The run's
+    /// variables (see [`run_env`](super::run_env)) don't override the hook's `env`.
+    pub fn with_hook_cache(&self, repo_id: &str, hook: &Hook) -> Hook {
+        let project = env::current_dir().unwrap_or_default();
+        let dir = cache::hook_cache_dir(&self.cache_dir, &project, repo_id, &hook.id);
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            log::warn!("Could not create the cache directory of hook {}: {}", hook.id, e);
+        }
+        orphans::touch_last_used(&dir);
+        orphans::add_reference(&dir, &project);
+
+        let dir = dir.to_string_lossy();
+        let mut hook = hook.clone();
+        for arg in hook.args.iter_mut().chain(hook.args_after_files.iter_mut()) {
+            *arg = arg.replace(cache::HOOK_CACHE_PLACEHOLDER, &dir);
+        }
+        for value in hook.env.values_mut() {
+            *value = value.replace(cache::HOOK_CACHE_PLACEHOLDER, &dir);
+        }
+        hook.env.insert(cache::HOOK_CACHE_ENV.to_string(), dir.into_owned());
+        for (key, value) in self.run_env.vars(&hook) {
+            hook.env.entry(key).or_insert(value);
+        }
+        hook
+    }
+
+    /// Get the environment for a hook's tool, including the changeset description
+    fn tool_env(&self, hook: &Hook) -> HashMap<String, String> {
+        let mut env = hook.env.clone();
+        env.extend(super::git_changes::change_env(&self.changes));
+        env
+    }
+
+    /// Get the home directory of a hook's tool environment
+    ///
+    /// Relative `tool_home` directories are resolved from the working directory.
+    fn tool_home(&self, hook: &Hook) -> PathBuf {
+        let root = match &self.config.tool_home {
+            Some(dir) => std::path::absolute(dir).unwrap_or_else(|_| dir.clone()),
+            None => self.cache_dir.join(cache::TOOL_HOME_DIR),
+        };
+        root.join(Self::tool_key(hook))
+    }
+
+    /// Get the environment of a hook's tool, with the tool environment's home directory
+    ///
+    /// The hook's `env` can still set `HOME` and `XDG_CACHE_HOME` itself.
+    fn isolated_env(&self, hook: &Hook) -> HashMap<String, String> {
+        let mut env = self.tool_env(hook);
+        for (key, value) in home_vars(&self.tool_home(hook)) {
+            env.entry(key).or_insert(value);
+        }
+        env
+    }
+
+    /// Hash the parts a tool key depends on
+    fn key_hash(parts: &[&str]) -> String {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        hasher.finalize().iter().take(8).map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Get the key identifying a hook's environment in the cache
+    ///
+    /// The key is made of what the environment holds rather than of the hook
+    /// using it: it ends with a hash of the language, the package (or the
+    /// entry, for hooks without one), the requested version and the platform.
+    /// Hooks installing the same package share an environment, like the
+    /// expansions of a `per_project` hook, while changing any of these gets a
+    /// fresh environment and platforms sharing a cache don't collide.
+    pub fn tool_key(hook: &Hook) -> String {
+        let version = hook.version.as_deref().unwrap_or("latest");
+        let platform = format!("{}-{}", env::consts::ARCH, env::consts::OS);
+
+        // Packages are installed from the first word of the entry, the rest doesn't change the environment
+        let (name, content) = match Self::package(hook) {
+            Some(package) => (package.clone(), package),
+            None => {
+                let program = hook.entry.split_whitespace().next().unwrap_or_default();
+                let name = Path::new(program).file_name().map_or(program.into(), |name| name.to_string_lossy());
+                (name.into_owned(), hook.entry.clone())
+            }
+        };
+
+        let hash = Self::key_hash(&[&hook.language, &content, version, &platform]);
+        format!("{}-{}-{}", hook.language, cache::file_name_key(&name), hash)
+    }
+
+    /// Get the keys earlier versions gave a hook's environment
+    ///
+    /// Environments used to be keyed by hook, with a hash of the entry,
+    /// version and platform. Only those keys are migrated: the hash matches
+    /// the hook as configured now, so the environment holds what it needs.
+    /// The first `{language}-{id}` keys say nothing of the version installed
+    /// in them, so their environments are left to be pruned as orphans.
+    pub fn legacy_tool_keys(hook: &Hook) -> Vec<String> {
+        let version = hook.version.as_deref().unwrap_or("latest");
+        let platform = format!("{}-{}", env::consts::ARCH, env::consts::OS);
+        let hash = Self::key_hash(&[&hook.language, &hook.entry, version, &platform]);
+        vec![format!("{}-{}-{}", hook.language, hook.id, hash)]
+    }
+
+    /// Get the key of a hook's tool in the tool cache of this resolver
+    ///
+    /// Tools carry the hook's ID and environment variables, so each hook has
+    /// its own even when they share an environment.
+    fn tool_cache_key(hook: &Hook) -> String {
+        format!("{}\0{}", hook.id, Self::tool_key(hook))
+    }
+
+    /// Move directories created with a legacy key to the current key
+    ///
+    /// Legacy environments predate readiness markers, and were used as they
+    /// were, so a migrated environment without a marker is marked ready for
+    /// the tool's `manifest` rather than rebuilt.
+    fn migrate_legacy_tool_dirs(&self, hook: &Hook, tool_key: &str, manifest: Option<&str>) -> std::io::Result<()> {
+        for legacy_key in Self::legacy_tool_keys(hook) {
+            for dir in ["venvs", "cache"] {
+                let legacy_dir = self.cache_dir.join(dir).join(&legacy_key);
+                let new_dir = self.cache_dir.join(dir).join(tool_key);
+                if !legacy_dir.is_dir() || new_dir.exists() {
+                    continue;
+                }
+                match std::fs::rename(&legacy_dir, &new_dir) {
+                    Ok(()) => log::info!("Migrated {} to {}", legacy_dir.display(), new_dir.display()),
+                    Err(e) => {
+                        log::warn!("Could not migrate {}: {}", legacy_dir.display(), e);
+                        continue;
+                    }
+                }
+                if let Some(manifest) = manifest.filter(|_| dir == "venvs" && !new_dir.join(ready::READY_MARKER).exists()) {
+                    ready::mark_ready(&new_dir, manifest)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the directory a hook's tool is installed in
+    fn tool_install_dir(&self, hook: &Hook) -> PathBuf {
+        self.cache_dir.join("venvs").join(Self::tool_key(hook))
+    }
+
+    /// Get the package a hook's tool installs, for languages with a package registry
+    pub fn package(hook: &Hook) -> Option<String> {
+        let name = hook.entry.split_whitespace().next().unwrap_or(&hook.entry);
+        match hook.language.as_str() {
+            // Scripts with inline metadata are set up by uv
+            "python" if uv_script::is_script(Path::new(name)) => None,
+            "python" if name == "shellcheck" => Some("shellcheck-py".to_string()),
+            "node" | "javascript" | "typescript" if name == "biome" => Some("@biomejs/biome".to_string()),
+            "python" | "node" | "javascript" | "typescript" | "ruby" => Some(name.to_string()),
+            _ => None,
+        }
     }
 
     /// Create a tool for a hook
@@ -185,58 +514,66 @@ impl HookResolver {
 
         match hook.language.as_str() {
             "python" => {
-                // Create a Python tool
-                // Extract the package name from the entry (first part before space)
-                let package_name = hook.entry.split_whitespace().next().unwrap_or(&hook.entry).to_string();
-
-                // For pre-commit-hooks, we need to install the pre-commit-hooks package
-                let package = if package_name == "pre-commit-hooks" {
-                    "pre-commit-hooks".to_string()
-                } else if package_name == "ruff" {
-                    "ruff".to_string()
-                } else if package_name == "shellcheck" {
-                    "shellcheck-py".to_string()
-                } else if package_name == "codespell" {
-                    "codespell".to_string()
-                } else if package_name == "djhtml" {
-                    "djhtml".to_string()
-                } else {
-                    package_name
-                };
+                // Scripts with inline metadata run in an environment uv sets up for them
+                if let Some(script) = uv_script::entry_script(&hook.entry) {
+                    let args = hook.entry.split_whitespace().skip(1).map(String::from).chain(hook.args.iter().cloned()).collect();
+                    let tool = UvScriptTool::new(hook.id.clone(), version, script, args)
+                        .with_args_after_files(hook.args_after_files.clone())
+                        .with_env(self.isolated_env(hook))
+                        .with_install_dir(self.cache_dir.join(uv_script::UV_CACHE_DIR));
+                    return Ok(Box::new(tool));
+                }
 
-                let packages = vec![package];
-                let tool = PythonTool::new(hook.id.clone(), version, packages);
+                // Create a Python tool
+                let package = Self::package(hook).unwrap_or_default();
+                let packages = vec![Registry::PyPI.pin(&package, &version)];
+                let tool = PythonTool::new(hook.id.clone(), version, packages)
+                    .with_env(self.isolated_env(hook))
+                    .with_install_dir(self.tool_install_dir(hook));
                 Ok(Box::new(tool))
             },
             "node" | "javascript" | "typescript" => {
                 // Create a Node.js tool
-                // Extract the package name from the entry (first part before space)
-                let package_name = hook.entry.split_whitespace().next().unwrap_or(&hook.entry).to_string();
-
-                // For biome, we need to install the @biomejs/biome package
-                let package = if package_name == "biome" {
-                    "@biomejs/biome".to_string()
-                } else {
-                    package_name
-                };
-
-                let packages = vec![package];
-                let tool = NodeTool::new(hook.id.clone(), version, packages, true, None);
+                let package = Self::package(hook).unwrap_or_default();
+                let packages = vec![Registry::Npm.pin(&package, &version)];
+                let tool = NodeTool::new(hook.id.clone(), version, packages, true, None)
+                    .with_env(self.isolated_env(hook))
+                    .with_install_dir(self.tool_install_dir(hook))
+                    .with_runtimes_dir(cache::runtimes_dir(&self.cache_dir));
                 Ok(Box::new(tool))
             },
             "ruby" => {
                 // Create a Ruby tool
-                // Extract the package name from the entry (first part before space)
-                let package_name = hook.entry.split_whitespace().next().unwrap_or(&hook.entry).to_string();
-                let gems = vec![package_name];
-                let tool = RubyTool::new(hook.id.clone(), version, gems);
+                let gem = Self::package(hook).unwrap_or_default();
+                let gems = vec![Registry::RubyGems.pin(&gem, &version)];
+                let tool = RubyTool::new(hook.id.clone(), version, gems)
+                    .with_env(self.isolated_env(hook))
+                    .with_install_dir(self.tool_install_dir(hook))
+                    .with_runtimes_dir(cache::runtimes_dir(&self.cache_dir));
                 Ok(Box::new(tool))
             },
-            "system" => {
-                // For system hooks, we create a SystemTool
+            "system" | "script" => {
+                // For system and script hooks, we create a SystemTool
                 let tool = SystemTool::new(hook.id.clone(), version, hook.entry.clone());
                 Ok(Box::new(tool))
             },
+            "fail" => {
+                // For fail hooks, the entry is the message to report
+                let tool = FailTool::new(hook.id.clone(), version, hook.entry.clone());
+                Ok(Box::new(tool))
+            },
+            "pygrep" => {
+                // For pygrep hooks, the entry is the regex and the args are search flags
+                let options = PygrepOptions::from_args(&hook.args);
+                let tool = PygrepTool::new(hook.id.clone(), version, hook.entry.clone(), options);
+                Ok(Box::new(tool))
+            },
+            "native" => {
+                // For native hooks, the entry is the ID of the built-in hook
+                let tool = NativeTool::new(hook.id.clone(), version, hook.entry.clone(), hook.args.clone())
+                    .with_changes(self.changes.clone());
+                Ok(Box::new(tool))
+            },
             _ => {
                 // Unsupported language
                 Err(HookResolverError::UnsupportedLanguage(hook.language.clone()))
@@ -244,40 +581,248 @@ impl HookResolver {
         }
     }
 
+    /// Get the environment a hook's tool is set up in, and its setup manifest
+    ///
+    /// Returns `None` for hooks whose tool needs no environment.
+    pub fn environment(&self, hook: &Hook) -> Result<Option<(PathBuf, String)>, HookResolverError> {
+        let tool = self.create_tool(hook)?;
+        Ok(tool.setup_manifest().map(|manifest| (self.tool_install_dir(hook), manifest)))
+    }
+
     /// Set up a tool for a hook
-    fn setup_tool(&mut self, hook: &Hook) -> Result<&Box<dyn Tool>, HookResolverError> {
-        // Check if the tool is already in the cache
-        let tool_key = format!("{}-{}", hook.language, hook.id);
-        if !self.tool_cache.contains_key(&tool_key) {
-            // Create the tool
-            let tool = self.create_tool(hook)?;
+    fn setup_tool(&mut self, hook: &Hook) -> Result<&dyn Tool, HookResolverError> {
+        let cache_key = Self::tool_cache_key(hook);
+        if let Some(error) = self.failed_setups.get(&cache_key) {
+            return Err(HookResolverError::ToolError(copy_tool_error(error)));
+        }
+        if !self.tool_cache.contains_key(&cache_key) {
+            let pending = self.begin_setup(hook)?;
+            let result = pending.run();
+            self.finish_setup(pending, result)?;
+        }
 
-            // Set up the tool
-            let ctx = SetupContext {
-                install_dir: self.cache_dir.join("venvs").join(&tool_key),
-                cache_dir: self.cache_dir.join("cache").join(&tool_key),
-                force: false,
-                version: Some(hook.version.clone().unwrap_or_else(|| "latest".to_string())),
-            };
+        // Return the tool from the cache
+        Ok(self.tool_cache.get(&cache_key).unwrap().as_ref())
+    }
 
-            // Set up the tool
-            tool.setup(&ctx)?;
+    /// Start setting up the tools of a hook that runs in this process, before it runs
+    ///
+    /// Returns the setups still to run, skipping the tools that are set up
+    /// already or whose environment's key is in `started`, which they are
+    /// added to. Hooks sharing an environment thus set it up once, rather
+    /// than in setups racing on its directory, and the others get their tool
+    /// from the finished environment when they run. Hooks without files to
+    /// check need no setup.
+    pub fn begin_setups(&mut self, repo_id: &str, hook_id: &str, files: &[PathBuf], started: &mut HashSet<String>) -> Result<Vec<PendingSetup>, HookResolverError> {
+        let hook = {
+            let hook = self.resolve_hook(repo_id, hook_id)?;
+            self.with_hook_cache(repo_id, &hook)
+        };
+        let context = self.create_context(&hook, files)?;
+        if context.files_to_process.is_empty() || context.should_run_in_separate_process() {
+            return Ok(Vec::new());
+        }
 
-            // Add the tool to the cache
-            self.tool_cache.insert(tool_key.clone(), tool);
+        let mut pending = Vec::new();
+        for (hook, _) in &hook.command_hooks() {
+            if self.tool_cache.contains_key(&Self::tool_cache_key(hook)) || !started.insert(Self::tool_key(hook)) {
+                continue;
+            }
+            pending.push(self.begin_setup(hook)?);
         }
+        Ok(pending)
+    }
 
-        // Return the tool from the cache
-        Ok(self.tool_cache.get(&tool_key).unwrap())
+    /// Prepare the setup of a hook's tool
+    fn begin_setup(&mut self, hook: &Hook) -> Result<PendingSetup, HookResolverError> {
+        let tool_key = Self::tool_key(hook);
+        let started = std::time::Instant::now();
+
+        // Create the tool, move its environment from a legacy key, and create the home directory of its environment
+        let tool = self.create_tool(hook)?;
+        let manifest = tool.setup_manifest();
+        self.migrate_legacy_tool_dirs(hook, &tool_key, manifest.as_deref())?;
+        std::fs::create_dir_all(self.tool_home(hook))?;
+
+        // Rebuild environments whose setup didn't complete or was for a different manifest
+        let install_dir = self.tool_install_dir(hook);
+        let mut force = false;
+        if let Some(manifest) = &manifest {
+            if install_dir.exists() && !ready::is_ready(&install_dir, manifest) {
+                log::warn!("Environment for hook '{}' at {} is incomplete, rebuilding it", hook.id, install_dir.display());
+                std::fs::remove_dir_all(&install_dir)?;
+                force = true;
+            }
+        }
+
+        let ctx = SetupContext {
+            install_dir: install_dir.clone(),
+            cache_dir: self.cache_dir.join("cache").join(&tool_key),
+            force,
+            // The version of a package's hook pins the package, the runtime follows the project's version files
+            version: Self::package(hook).is_none().then(|| hook.version.clone().unwrap_or_else(|| "latest".to_string())),
+        };
+
+        let reused = manifest.as_ref().is_some_and(|manifest| ready::is_ready(&install_dir, manifest));
+        if manifest.is_some() {
+            self.progress.emit(ProgressEvent::SetupStarted { hook_id: hook.id.clone(), tool: tool_key.clone() });
+        }
+        Ok(PendingSetup { hook_id: hook.id.clone(), cache_key: Self::tool_cache_key(hook), tool_key, tool, ctx, manifest, reused, started })
+    }
+
+    /// Record the result of a setup, adding its tool to the cache when it succeeded
+    ///
+    /// A failed setup isn't retried in this session, hooks using its tool
+    /// fail with its error.
+    pub fn finish_setup(&mut self, pending: PendingSetup, result: Result<(), ToolError>) -> Result<(), HookResolverError> {
+        let PendingSetup { hook_id, cache_key, tool_key, tool, ctx, manifest, reused, started } = pending;
+        if let Err(e) = result {
+            self.failed_setups.insert(cache_key, copy_tool_error(&e));
+            return Err(e.into());
+        }
+
+        // Only a completed setup marks the environment as installed
+        if let Some(manifest) = &manifest {
+            ready::mark_ready(&ctx.install_dir, manifest)?;
+            let setup = EnvironmentSetup { hook_id, tool: tool_key, reused, duration: started.elapsed() };
+            self.progress.emit(ProgressEvent::SetupFinished(setup.clone()));
+            self.setups.push(setup);
+        }
+
+        // Keep used environments from being pruned as orphans, and count this project as using it
+        orphans::touch_last_used(&ctx.install_dir);
+        orphans::touch_last_used(&ctx.cache_dir);
+        if let Ok(project) = env::current_dir() {
+            orphans::add_reference(&ctx.install_dir, &project);
+        }
+
+        // Add the tool to the cache
+        self.tool_cache.insert(cache_key, tool);
+        Ok(())
     }
 
+    /// Run a preflight command in a hook's environment
+    ///
+    /// Hooks with a tool run it with the tool's environment on their PATH,
+    /// other hooks run it on the host.
+    fn run_preflight_command(&mut self, hook: &Hook, command: &str, in_tool: bool) -> Result<(), String> {
+        let parts: Vec<&str> = command.split_whitespace().collect();
+        let Some((program, args)) = parts.split_first() else {
+            return Err("empty preflight command".to_string());
+        };
+        let working_dir = HookContext::from_hook(hook, env::current_dir().map_err(|e| e.to_string())?, Vec::new()).working_dir;
+        let env = if in_tool { self.isolated_env(hook) } else { self.tool_env(hook) };
+
+        let mut process = network::hook_command(program);
+        process.args(args).current_dir(&working_dir);
+        if in_tool {
+            let tool = self.setup_tool(hook).map_err(|e| e.to_string())?;
+            apply_hook_env(&mut process, &tool.bin_dirs(), &env);
+        } else {
+            for (key, value) in &env {
+                match key.as_str() {
+                    "PATH" => process.env(key, hook_path(&[], &env, env::var_os("PATH").unwrap_or_default())),
+                    _ => process.env(key, value),
+                };
+            }
+        }
+
+        let output = process.output().map_err(|e| format!("could not run {}: {}", program, e))?;
+        if output.status.success() {
+            return Ok(());
+        }
+        diagnostics::report_failure(&format!("the preflight of hook {}", hook.id), &process);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(format!("exited with {}: {}", output.status, stderr.trim()))
+    }
+
+    /// Check that the repository's `node_modules` is installed for hooks using it
+    ///
+    /// With `node_modules: ci`, a missing or stale `node_modules` is installed
+    /// from the lockfile. Each package is checked once per session.
+    fn check_node_modules(&mut self, hook: &Hook) -> Result<(), HookResolverError> {
+        if !hook.uses_repo_node_modules() {
+            return Ok(());
+        }
+        let working_dir = HookContext::from_hook(hook, env::current_dir()?, Vec::new()).working_dir;
+        let Some(package_dir) = node::find_package_dir(&working_dir) else {
+            return Err(HookResolverError::ToolError(ToolError::InstallationError(format!(
+                "Hook '{}' uses the repository's node_modules, but no package.json was found in {} or its parents",
+                hook.id,
+                working_dir.display()
+            ))));
+        };
+        let key = format!("node_modules\0{}", package_dir.display());
+        if self.preflights.contains(&key) {
+            return Ok(());
+        }
+
+        let state = node::node_modules_state(&package_dir);
+        log::debug!("node_modules of {} for hook '{}': {:?}", package_dir.display(), hook.id, state);
+        match state {
+            NodeModulesState::Installed => {}
+            _ if hook.node_modules == NodeModules::Ci => node::install_node_modules(&package_dir)?,
+            state => {
+                let problem = if state == NodeModulesState::Stale { "is older than the lockfile" } else { "is not installed" };
+                let fix = node::lockfile_install_command(&package_dir).unwrap_or_else(|| "npm install".to_string());
+                return Err(HookResolverError::ToolError(ToolError::InstallationError(format!(
+                    "The node_modules of {} {}, which hook '{}' needs. Run `{}` there, or set `node_modules: ci` on the hook to have it run",
+                    package_dir.display(),
+                    problem,
+                    hook.id,
+                    fix
+                ))));
+            }
+        }
+        self.preflights.insert(key);
+        Ok(())
+    }
+
+    /// Check a hook's environment with its `preflight` command
+    ///
+    /// Each command runs once per session and environment. When it fails in a
+    /// tool's environment, the environment is rebuilt and the command retried
+    /// once before the hook fails with a provisioning error.
+    pub fn preflight(&mut self, hook: &Hook) -> Result<(), HookResolverError> {
+        self.check_node_modules(hook)?;
+        let Some(command) = hook.preflight.clone() else { return Ok(()) };
+        if hook.image.is_some() {
+            log::debug!("Not running the preflight command of hook '{}', which runs in a container", hook.id);
+            return Ok(());
+        }
+        let in_tool = !HookContext::from_hook(hook, PathBuf::new(), Vec::new()).should_run_in_separate_process();
+        let key = if in_tool { format!("{}\0{}", Self::tool_key(hook), command) } else { command.clone() };
+        if self.preflights.contains(&key) {
+            return Ok(());
+        }
+
+        let mut result = self.run_preflight_command(hook, &command, in_tool);
+        if let (Err(details), true) = (&result, in_tool) {
+            log::warn!("Preflight `{}` of hook '{}' failed ({}), rebuilding its environment", command, hook.id, details);
+            self.tool_cache.remove(&Self::tool_cache_key(hook));
+            let install_dir = self.tool_install_dir(hook);
+            if install_dir.exists() {
+                std::fs::remove_dir_all(&install_dir)?;
+            }
+            result = self.run_preflight_command(hook, &command, in_tool);
+        }
+
+        match result {
+            Ok(()) => {
+                log::debug!("Preflight `{}` of hook '{}' passed", command, hook.id);
+                self.preflights.insert(key);
+                Ok(())
+            }
+            Err(details) => Err(HookResolverError::ProvisioningError { hook_id: hook.id.clone(), command, details }),
+        }
+    }
 
     /// Run a hook on files
     pub fn run_hook(&mut self, repo_id: &str, hook_id: &str, files: &[PathBuf]) -> Result<(), HookResolverError> {
         // First, get all the information we need from immutable borrows
         let hook_clone = {
             let hook = self.resolve_hook(repo_id, hook_id)?;
-            hook.clone()
+            self.with_hook_cache(repo_id, &hook)
         };
 
         // Create the context for running the hook
@@ -287,6 +832,7 @@ impl HookResolver {
         if context.files_to_process.is_empty() {
             return Ok(());
         }
+        self.preflight(&hook_clone)?;
 
         // Use the context to decide how to run the hook
         if context.should_run_in_separate_process() {
@@ -304,34 +850,41 @@ impl HookResolver {
                 }
             })
         } else {
-            // Run the hook in the same process using the tool
-            // Now we can do the mutable borrow since the immutable borrow is no longer active
-            let tool = self.setup_tool(&hook_clone)?;
+            // Each command of an entry with several runs with its own tool, stopping at the first failure
+            for (hook, takes_files) in &hook_clone.command_hooks() {
+                self.run_with_tool(hook, &context, *takes_files)?;
+            }
+            Ok(())
+        }
+    }
 
-            // Execute the hook using the context
-            context.execute(Some(tool.as_ref())).map_err(|err| match err {
-                super::hook_context::HookContextError::ProcessError(msg) => HookResolverError::ProcessError(msg),
-                super::hook_context::HookContextError::IoError(err) => HookResolverError::IoError(err),
-                super::hook_context::HookContextError::HookError(err) => HookResolverError::HookError(err),
-                super::hook_context::HookContextError::ToolError(err) => HookResolverError::ToolError(err),
-                super::hook_context::HookContextError::CommandNotFound { command, hook_id, error: _ } => {
-                    HookResolverError::FileNotFound {
-                        path: PathBuf::from(command),
-                        context: format!("Command not found when running hook '{}'. Make sure the command is installed and available in your PATH.", hook_id)
-                    }
+    /// Run a hook in the same process with its tool
+    fn run_with_tool(&mut self, hook: &Hook, context: &HookContext, takes_files: bool) -> Result<(), HookResolverError> {
+        let tool = self.setup_tool(hook)?;
+
+        // Execute the hook using the context
+        context.run_tool(tool, takes_files).map_err(|err| match err {
+            super::hook_context::HookContextError::ProcessError(msg) => HookResolverError::ProcessError(msg),
+            super::hook_context::HookContextError::IoError(err) => HookResolverError::IoError(err),
+            super::hook_context::HookContextError::HookError(err) => HookResolverError::HookError(err),
+            super::hook_context::HookContextError::ToolError(err) => HookResolverError::ToolError(err),
+            super::hook_context::HookContextError::CommandNotFound { command, hook_id, error: _ } => {
+                HookResolverError::FileNotFound {
+                    path: PathBuf::from(command),
+                    context: format!("Command not found when running hook '{}'. Make sure the command is installed and available in your PATH.", hook_id)
                 }
-            })
-        }
+            }
+        })
     }
 
     /// Run all hooks on files
     pub fn run_all_hooks(&mut self, files: &[PathBuf]) -> Result<(), HookResolverError> {
         // Collect all hooks first to avoid borrowing issues
-        let hooks_to_run: Vec<(String, String)> = self.config.repos.iter()
+        let hooks_to_run: Vec<(String, String, bool)> = self.config.repos.iter()
             .flat_map(|repo| {
                 repo.hooks.iter()
-                    .filter(|hook| !self.hooks_to_skip.contains(&hook.id))
-                    .map(move |hook| (repo.repo.clone(), hook.id.clone()))
+                    .filter(|hook| !hook.is_skipped_by(&self.hooks_to_skip))
+                    .map(move |hook| (repo.repo.clone(), hook.id.clone(), hook.continue_on_error))
             })
             .collect();
 
@@ -340,9 +893,14 @@ impl HookResolver {
             log::info!("Skipping hooks: {}", self.hooks_to_skip.join(", "));
         }
 
-        // Run each hook
-        for (repo_id, hook_id) in hooks_to_run {
-            self.run_hook(&repo_id, &hook_id, files)?;
+        // Run each hook, only reporting failures of hooks with continue_on_error
+        for (repo_id, hook_id, continue_on_error) in hooks_to_run {
+            match self.run_hook(&repo_id, &hook_id, files) {
+                Err(err) if continue_on_error => {
+                    log::warn!("Hook '{}' failed, continuing because it has continue_on_error set: {}", hook_id, err);
+                }
+                result => result?,
+            }
         }
 
         Ok(())
@@ -0,0 +1,276 @@
+//! Resolution of `version: latest`
+//!
+//! A hook asking for `version: latest` gets the newest release of its package
+//! from the package's registry: PyPI for Python hooks, npm for Node.js hooks
+//! and RubyGems for Ruby hooks. Hooks of other languages from a GitHub repo
+//! get the repo's latest release. Registry answers are cached in the cache
+//! directory for `cache_ttl.versions` (a day by default), and resolved
+//! versions are recorded in the lockfile, so later runs install the same
+//! version until its entry is removed.
+//!
+//! Registries are asked through the [query layer](crate::toolchains::queries),
+//! which asks for the missing versions in parallel, up to its concurrency
+//! limit, and revalidates expired answers with conditional requests.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::cache::{CacheCategory, CacheManager};
+use crate::config::lockfile::Lockfile;
+use crate::config::parser::CacheTtl;
+use crate::config::{Config, Hook};
+use crate::toolchains::{network, queries, ToolError};
+use super::hook_resolver::HookResolver;
+
+/// The version asking for the newest release
+pub const LATEST: &str = "latest";
+
+/// Where the releases of a package are published
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Registry {
+    /// The Python Package Index
+    PyPI,
+    /// The npm registry
+    Npm,
+    /// RubyGems.org
+    RubyGems,
+    /// Releases of a GitHub repository
+    GitHub,
+}
+
+impl Registry {
+    /// Get the registry of a hook language's packages
+    pub fn for_language(language: &str) -> Option<Self> {
+        match language {
+            "python" => Some(Registry::PyPI),
+            "node" | "javascript" | "typescript" => Some(Registry::Npm),
+            "ruby" => Some(Registry::RubyGems),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the registry in lockfile keys
+    pub fn name(&self) -> &'static str {
+        match self {
+            Registry::PyPI => "pypi",
+            Registry::Npm => "npm",
+            Registry::RubyGems => "rubygems",
+            Registry::GitHub => "github",
+        }
+    }
+
+    /// Get the URL describing the latest release of a package
+    fn url(&self, package: &str) -> String {
+        match self {
+            Registry::PyPI => format!("https://pypi.org/pypi/{}/json", package),
+            // Scoped packages keep their slash encoded
+            Registry::Npm => format!("https://registry.npmjs.org/{}/latest", package.replace('/', "%2F")),
+            Registry::RubyGems => format!("https://rubygems.org/api/v1/versions/{}/latest.json", package),
+            Registry::GitHub => format!("https://api.github.com/repos/{}/releases/latest", package),
+        }
+    }
+
+    /// Get the version from the registry's description of the latest release
+    fn parse(&self, body: &serde_json::Value) -> Option<String> {
+        let version = match self {
+            Registry::PyPI => &body["info"]["version"],
+            Registry::Npm | Registry::RubyGems => &body["version"],
+            Registry::GitHub => &body["tag_name"],
+        };
+        version.as_str().filter(|version| !version.is_empty() && *version != "unknown").map(String::from)
+    }
+
+    /// Pin a package to an exact version in the installer's syntax
+    ///
+    /// Packages are left alone for other versions, such as ranges.
+    pub fn pin(&self, package: &str, version: &str) -> String {
+        if !is_exact(version) {
+            return package.to_string();
+        }
+        match self {
+            Registry::PyPI => format!("{}=={}", package, version),
+            Registry::Npm => format!("{}@{}", package, version),
+            Registry::RubyGems => format!("{}:{}", package, version),
+            Registry::GitHub => package.to_string(),
+        }
+    }
+}
+
+/// Check whether a version asks for the newest release
+pub fn is_latest(version: &str) -> bool {
+    version.eq_ignore_ascii_case(LATEST)
+}
+
+/// Check whether a version names exactly one release, like `1.2.3`
+pub fn is_exact(version: &str) -> bool {
+    version.starts_with(|c: char| c.is_ascii_digit()) && version.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+}
+
+/// A package whose versions a hook installs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Package {
+    /// Where the package is published
+    pub registry: Registry,
+    /// Name of the package, or `owner/repo` on GitHub
+    pub name: String,
+}
+
+impl Package {
+    /// Get the package of a hook from a repo
+    pub fn for_hook(repo: &str, hook: &Hook) -> Option<Self> {
+        if let Some(registry) = Registry::for_language(&hook.language) {
+            return HookResolver::package(hook).map(|name| Package { registry, name });
+        }
+        github_repo(repo).map(|name| Package { registry: Registry::GitHub, name })
+    }
+
+    /// Get the key of the package in the lockfile and the query cache
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.registry.name(), self.name)
+    }
+}
+
+/// Get the `owner/repo` of a GitHub repository URL
+fn github_repo(url: &str) -> Option<String> {
+    let path = url.strip_prefix("https://github.com/")?.trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    let (owner, repo) = path.split_once('/')?;
+    (!owner.is_empty() && !repo.is_empty() && !repo.contains('/')).then(|| path.to_string())
+}
+
+/// Ask a package's registry for its latest version
+///
+/// Responses are kept in the cache root to be revalidated by later queries.
+pub fn query(package: &Package, cache_root: &Path) -> Result<String, ToolError> {
+    network::check_allowed(&format!("the latest version of {}", package.key()))?;
+    let url = package.registry.url(&package.name);
+    let failed = |details: String| ToolError::ExecutionError(format!("Failed to get the latest version of {} from {}: {}", package.key(), url, details));
+
+    let body = queries::get(&url, Some(cache_root)).map_err(failed)?;
+    let body: serde_json::Value = serde_json::from_str(&body).map_err(|e| failed(e.to_string()))?;
+    package.registry.parse(&body).ok_or_else(|| failed("no version in the response".to_string()))
+}
+
+/// Get the latest version of a package, asking its registry at most once per `cache_ttl.versions`
+///
+/// When the registry can't be asked, an expired answer is still used.
+pub fn resolve<F>(package: &Package, cache_root: &Path, ttl: &CacheTtl, query: F) -> Result<String, ToolError>
+where
+    F: FnOnce(&Package) -> Result<String, ToolError>,
+{
+    let cache = CacheManager::for_category(cache_root, CacheCategory::Versions, ttl);
+    let key = CacheManager::entry_name(&package.key());
+    if let Ok(Some(version)) = cache.get::<String>(&key) {
+        return Ok(version);
+    }
+
+    match query(package) {
+        Ok(version) => {
+            if let Err(e) = cache.set(&key, &version) {
+                log::debug!("Could not cache the latest version of {}: {:?}", package.key(), e);
+            }
+            Ok(version)
+        }
+        Err(e) => match cache.get_stale::<String>(&key).ok().flatten() {
+            Some(version) => {
+                log::warn!("{:?}, using version {} found earlier", e, version);
+                Ok(version)
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Replace `version: latest` with the version recorded in the lockfile
+///
+/// Packages missing from the lockfile are resolved with [`query`] and
+/// recorded, asking for the ones missing from the query cache in parallel.
+/// Hooks whose version can't be resolved keep `latest`.
+pub fn pin_latest_versions(config: &Config, lockfile_path: &Path, cache_root: &Path) -> Config {
+    let lockfile = Lockfile::load(lockfile_path).unwrap_or_default();
+    let cache = CacheManager::for_category(cache_root, CacheCategory::Versions, &config.cache_ttl);
+    let mut missing: Vec<Package> = Vec::new();
+    for repo in &config.repos {
+        for hook in repo.hooks.iter().filter(|hook| hook.version.as_deref().is_some_and(is_latest)) {
+            let Some(package) = Package::for_hook(&repo.repo, hook) else { continue };
+            let key = package.key();
+            if !lockfile.versions.contains_key(&key) && !cache.is_valid(&CacheManager::entry_name(&key)) && !missing.contains(&package) {
+                missing.push(package);
+            }
+        }
+    }
+
+    let answers = queries::batch(&missing, |package| query(package, cache_root));
+    let mut answers: HashMap<String, Result<String, ToolError>> = missing.iter().map(Package::key).zip(answers).collect();
+    pin_latest_versions_with(config, lockfile_path, cache_root, |package| {
+        answers.remove(&package.key()).unwrap_or_else(|| query(package, cache_root))
+    })
+}
+
+/// Replace `version: latest` with the version recorded in the lockfile, without resolving any
+///
+/// For commands that only need the environments hooks use, like finding
+/// orphaned environments: hooks missing from the lockfile keep `latest`, as
+/// they have no environment of a resolved version yet.
+pub fn pin_locked_versions(config: &Config, lockfile_path: &Path) -> Config {
+    let lockfile = Lockfile::load(lockfile_path).unwrap_or_default();
+    let mut pinned = config.clone();
+    for repo in &mut pinned.repos {
+        for hook in repo.hooks.iter_mut().filter(|hook| hook.version.as_deref().is_some_and(is_latest)) {
+            let Some(package) = Package::for_hook(&repo.repo, hook) else { continue };
+            if let Some(version) = lockfile.versions.get(&package.key()) {
+                hook.version = Some(version.clone());
+            }
+        }
+    }
+    pinned
+}
+
+/// Replace `version: latest` with the version recorded in the lockfile
+///
+/// `query` is called with every package missing from the lockfile and the
+/// query cache, and returns its latest version.
+pub fn pin_latest_versions_with<F>(config: &Config, lockfile_path: &Path, cache_root: &Path, mut query: F) -> Config
+where
+    F: FnMut(&Package) -> Result<String, ToolError>,
+{
+    let mut lockfile = Lockfile::load(lockfile_path).unwrap_or_else(|e| {
+        log::warn!("Could not read the lockfile {}: {:?}", lockfile_path.display(), e);
+        Lockfile::default()
+    });
+    let mut changed = false;
+
+    let mut pinned = config.clone();
+    for repo in &mut pinned.repos {
+        for hook in &mut repo.hooks {
+            if !hook.version.as_deref().is_some_and(is_latest) {
+                continue;
+            }
+            let Some(package) = Package::for_hook(&repo.repo, hook) else { continue };
+            let key = package.key();
+            let version = match lockfile.versions.get(&key) {
+                Some(version) => version.clone(),
+                None => match resolve(&package, cache_root, &config.cache_ttl, &mut query) {
+                    Ok(version) => {
+                        log::info!("Resolved the latest version of {} to {}", key, version);
+                        lockfile.versions.insert(key, version.clone());
+                        changed = true;
+                        version
+                    }
+                    Err(e) => {
+                        log::warn!("Could not resolve the latest version of hook '{}': {:?}", hook.id, e);
+                        continue;
+                    }
+                },
+            };
+            hook.version = Some(version);
+        }
+    }
+
+    if changed {
+        if let Err(e) = lockfile.save(lockfile_path) {
+            log::warn!("Could not update the lockfile {}: {:?}", lockfile_path.display(), e);
+        }
+    }
+    pinned
+}
@@ -0,0 +1,153 @@
+//! Run metrics for RustyHook
+//!
+//! `rustyhook run --metrics-file <path>` writes a JSON summary of the run:
+//! how long it and each hook took, which hooks failed and which environments
+//! were reused or had to be set up. CI jobs can ship the file to their own
+//! observability stack. Nothing is ever sent anywhere by RustyHook itself.
+//!
+//! Failures carry the stable code of their [`ErrorInfo`], so consumers can
+//! tell a failed download from a failing hook.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::error_codes::ErrorInfo;
+use crate::hooks::Diagnostic;
+use super::report::{CacheState, HookStatus, RunReport};
+
+/// Version of the metrics format, bumped on incompatible changes
+pub const METRICS_VERSION: u32 = 1;
+
+/// Metrics of a hook that ran
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HookMetrics {
+    /// ID of the hook
+    pub id: String,
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// Outcome of the hook
+    pub status: HookStatus,
+    /// How long the hook itself ran, in milliseconds
+    pub duration_ms: u128,
+    /// How long setting up the hook's environment took, in milliseconds, for the first hook needing it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setup_ms: Option<u128>,
+    /// Whether the hook's environment was ready before the run, for hooks with one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheState>,
+    /// Number of files passed to the hook
+    pub files: usize,
+    /// Code and details of the error of a failed hook
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorInfo>,
+    /// Problems the hook's output parser found in its output
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Metrics of an environment setup
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnvironmentMetrics {
+    /// ID of the first hook that needed the environment
+    pub hook_id: String,
+    /// Key of the tool the environment is for
+    pub tool: String,
+    /// Whether an environment set up by an earlier run was reused
+    pub cache_hit: bool,
+    /// How long the setup took, in milliseconds
+    pub setup_ms: u128,
+}
+
+/// Metrics of a run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunMetrics {
+    /// Version of the metrics format
+    pub version: u32,
+    /// Version of RustyHook that ran
+    pub rustyhook_version: String,
+    /// When the run finished, in RFC 3339 format
+    pub finished_at: String,
+    /// How long the whole run took, in milliseconds
+    pub duration_ms: u128,
+    /// Whether no hook failed the run
+    pub success: bool,
+    /// Code and details of the error that failed the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorInfo>,
+    /// Number of hooks that passed
+    pub passed: usize,
+    /// Number of hooks that failed the run
+    pub failed: usize,
+    /// Number of hooks that failed with `continue_on_error`
+    pub allowed_failures: usize,
+    /// Number of configured hooks that didn't run
+    pub skipped: usize,
+    /// Number of environments reused from earlier runs
+    pub cache_hits: usize,
+    /// Number of environments that had to be set up
+    pub cache_misses: usize,
+    /// Each hook that ran
+    pub hooks: Vec<HookMetrics>,
+    /// Each environment the hooks were set up in
+    pub environments: Vec<EnvironmentMetrics>,
+}
+
+impl RunMetrics {
+    /// Summarize the report of a run that took `duration`
+    pub fn from_report(report: &RunReport, duration: Duration) -> Self {
+        let count = |status: HookStatus| report.with_status(status).count();
+        let cache_hits = report.setups.iter().filter(|setup| setup.reused).count();
+        RunMetrics {
+            version: METRICS_VERSION,
+            rustyhook_version: env!("CARGO_PKG_VERSION").to_string(),
+            finished_at: chrono::Utc::now().to_rfc3339(),
+            duration_ms: duration.as_millis(),
+            success: report.is_success(),
+            error: None,
+            passed: count(HookStatus::Passed),
+            failed: count(HookStatus::Failed),
+            allowed_failures: count(HookStatus::AllowedFailure),
+            skipped: report.skipped.len(),
+            cache_hits,
+            cache_misses: report.setups.len() - cache_hits,
+            hooks: report
+                .hooks
+                .iter()
+                .map(|result| (result, report.timing(result)))
+                .map(|(result, timing)| HookMetrics {
+                    id: result.hook_id.clone(),
+                    repo: result.repo.clone(),
+                    status: result.status.clone(),
+                    duration_ms: result.duration.as_millis(),
+                    setup_ms: (!timing.setup.is_zero()).then_some(timing.setup.as_millis()),
+                    cache: timing.cache,
+                    files: result.file_count,
+                    error: result.error.clone(),
+                    diagnostics: result.diagnostics.clone(),
+                })
+                .collect(),
+            environments: report
+                .setups
+                .iter()
+                .map(|setup| EnvironmentMetrics {
+                    hook_id: setup.hook_id.clone(),
+                    tool: setup.tool.clone(),
+                    cache_hit: setup.reused,
+                    setup_ms: setup.duration.as_millis(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Write the metrics to a file as JSON
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        fs::write(path, json + "\n")
+    }
+}
@@ -6,8 +6,41 @@ pub mod file_matcher;
 pub mod hook_resolver;
 pub mod parallel;
 pub mod hook_context;
+pub mod git_changes;
+pub mod plan;
+pub mod notify;
+pub mod diff;
+pub mod container;
+pub mod report;
+pub mod attributes;
+pub mod audit;
+pub mod multi;
+pub mod record;
+pub mod status;
+pub mod metrics;
+pub mod capabilities;
+pub mod latest;
+pub mod progress;
+pub mod revision;
+pub mod guard;
+pub mod doctor;
+pub mod run_env;
+pub mod stage_input;
 
-pub use file_matcher::{FileMatcher, FileMatcherError};
+pub use file_matcher::{filter_hook_files, is_generated, max_file_size, oversized_files, FileMatcher, FileMatcherError, DEFAULT_EXCLUDES, DEFAULT_NATIVE_MAX_FILE_SIZE};
 pub use hook_resolver::{HookResolver, HookResolverError};
 pub use parallel::{ParallelExecutor, ParallelExecutionError};
 pub use hook_context::HookContext;
+pub use git_changes::{changed_files, changes_between, changes_since, ChangeKind, FileChange, staged_changes, tracked_files, unstaged_files};
+pub use plan::{Conflict, ConflictReason, ExecutionPlan, PlanStage, PreparedHook, StageLimit};
+pub use notify::Notifier;
+pub use capabilities::MissingCommand;
+pub use latest::{pin_latest_versions, pin_locked_versions};
+pub use progress::{Progress, ProgressEvent};
+pub use revision::{RevisionError, RevisionWorktree};
+pub use guard::GuardError;
+pub use diff::{FileDiff, HookChanges};
+pub use report::{AccessModeViolation, CacheState, EnvironmentSetup, HookResult, HookStatus, HookTiming, RunReport, SkipReason, SkippedFile, SkippedHook};
+pub use record::{Bundle, RecordError, RecordedHook};
+pub use stage_input::{PushedRef, StageInput, StageInputError};
+pub use attributes::{FileAttributes, GitAttributes, LineEnding};
@@ -0,0 +1,311 @@
+//! Running one hook across many repositories
+//!
+//! `rustyhook multi-run` runs a hook, like a secret scan, in every checkout
+//! listed in a file and reports the outcome of each. A repository runs the
+//! hook as its config defines it, or as the native hook of that ID when its
+//! config doesn't have it.
+//!
+//! Each repository runs in its own `rustyhook run` process, since runs
+//! depend on their working directory, and at most `jobs` of them run at
+//! once. They share one cache directory, so environments are set up once:
+//! each repository whose hook uses an environment no repository before it
+//! uses runs alone, in order, to set it up, and the others then run in
+//! parallel and reuse them. Two processes never set up one environment at
+//! the same time.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, Lockfile};
+use crate::hooks::HookFactory;
+use super::hook_resolver::HookResolver;
+use super::latest::pin_locked_versions;
+use super::report::HookStatus;
+
+/// Error type for multi-repository runs
+#[derive(Debug)]
+pub enum MultiRunError {
+    /// Error reading the list of repositories
+    IoError(std::io::Error),
+    /// The list of repositories is empty
+    NoRepositories(PathBuf),
+}
+
+impl From<std::io::Error> for MultiRunError {
+    fn from(err: std::io::Error) -> Self {
+        MultiRunError::IoError(err)
+    }
+}
+
+/// Options of a multi-repository run
+#[derive(Debug, Clone)]
+pub struct MultiRunOptions {
+    /// ID of the hook to run
+    pub hook_id: String,
+    /// Maximum number of repositories checked at once
+    pub jobs: usize,
+    /// Cache directory shared by every repository
+    pub cache_dir: PathBuf,
+    /// RustyHook binary running each repository
+    pub binary: PathBuf,
+}
+
+/// Outcome of the hook in a repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepoStatus {
+    /// The hook passed
+    Passed,
+    /// The hook failed
+    Failed,
+    /// The repository's config doesn't define the hook, and it isn't a native hook
+    MissingHook,
+    /// The hook couldn't run, like when the repository doesn't exist
+    Error,
+}
+
+/// A hook that ran in a repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoHook {
+    /// ID of the hook
+    pub id: String,
+    /// Outcome of the hook
+    pub status: HookStatus,
+    /// Number of files passed to the hook
+    pub files: usize,
+}
+
+/// Outcome of a multi-repository run in one repository
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoOutcome {
+    /// Path of the repository
+    pub repo: PathBuf,
+    /// Outcome of the hook
+    pub status: RepoStatus,
+    /// How long the repository took, in milliseconds
+    pub duration_ms: u128,
+    /// Hooks that ran, more than one for matrix hooks
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<RepoHook>,
+    /// Output of the run, kept when the hook didn't pass
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub output: String,
+}
+
+/// Consolidated report of a multi-repository run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiRunReport {
+    /// ID of the hook that ran
+    pub hook_id: String,
+    /// Outcome in each repository, in the order of the list
+    pub repos: Vec<RepoOutcome>,
+}
+
+impl MultiRunReport {
+    /// Count the repositories with a status
+    pub fn count(&self, status: RepoStatus) -> usize {
+        self.repos.iter().filter(|outcome| outcome.status == status).count()
+    }
+
+    /// Whether the hook passed in every repository
+    pub fn is_success(&self) -> bool {
+        self.count(RepoStatus::Passed) == self.repos.len()
+    }
+
+    /// Describe the outcome of every repository, then the totals
+    pub fn format_summary(&self) -> String {
+        let mut summary = String::new();
+        for outcome in &self.repos {
+            let status = match outcome.status {
+                RepoStatus::Passed => "passed",
+                RepoStatus::Failed => "FAILED",
+                RepoStatus::MissingHook => "no such hook",
+                RepoStatus::Error => "ERROR",
+            };
+            summary.push_str(&format!("{:<12} {} ({} ms)\n", status, outcome.repo.display(), outcome.duration_ms));
+        }
+        summary.push_str(&format!(
+            "{} in {} repositories: {} passed, {} failed, {} without the hook, {} errors\n",
+            self.hook_id,
+            self.repos.len(),
+            self.count(RepoStatus::Passed),
+            self.count(RepoStatus::Failed),
+            self.count(RepoStatus::MissingHook),
+            self.count(RepoStatus::Error)
+        ));
+        summary
+    }
+}
+
+/// Read a list of repositories, one path per line
+///
+/// Blank lines and lines starting with `#` are ignored. Relative paths are
+/// relative to the directory of the list.
+pub fn read_repos_file(path: &Path) -> Result<Vec<PathBuf>, MultiRunError> {
+    let base = path.parent().unwrap_or(Path::new("."));
+    let repos: Vec<PathBuf> = fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect();
+    if repos.is_empty() {
+        return Err(MultiRunError::NoRepositories(path.to_path_buf()));
+    }
+    Ok(repos)
+}
+
+/// Get the config running only a hook in a repository
+///
+/// The hook comes from the repository's `.rustyhook/config.yaml`, or is the
+/// native hook of that ID. `None` means neither has it.
+pub fn hook_config(repo: &Path, hook_id: &str) -> Result<Option<Config>, config::ConfigError> {
+    let config_path = repo.join(".rustyhook").join("config.yaml");
+    if config_path.is_file() {
+        let config = config::parse_config(&config_path)?.select_hooks(Some(hook_id), None);
+        if !config.repos.is_empty() {
+            return Ok(Some(config));
+        }
+    }
+    if !HookFactory::HOOK_IDS.contains(&hook_id) {
+        return Ok(None);
+    }
+    let hook = config::Hook {
+        id: hook_id.to_string(),
+        name: hook_id.to_string(),
+        entry: hook_id.to_string(),
+        language: "native".to_string(),
+        ..Default::default()
+    };
+    Ok(Some(Config { repos: vec![config::Repo { repo: "local".to_string(), hooks: vec![hook] }], ..Default::default() }))
+}
+
+/// Get the keys of the environments the hook sets up in a repository
+///
+/// Hooks are expanded and pinned like the run does, and hooks without an
+/// environment, like native ones, have none.
+fn environment_keys(repo: &Path, options: &MultiRunOptions) -> Vec<String> {
+    let Ok(Some(config)) = hook_config(repo, &options.hook_id) else {
+        return Vec::new();
+    };
+    let config = config::expand_matrix(&config, repo).unwrap_or(config);
+    let config = pin_locked_versions(&config, &Lockfile::path(repo));
+    let hooks: Vec<config::Hook> = config.repos.iter().flat_map(|repo| repo.hooks.iter().cloned()).collect();
+    let resolver = HookResolver::new(config, options.cache_dir.clone());
+    hooks
+        .iter()
+        .filter(|hook| resolver.environment(hook).is_ok_and(|environment| environment.is_some()))
+        .map(HookResolver::tool_key)
+        .collect()
+}
+
+/// Metrics of a run, as far as the report needs them
+#[derive(Deserialize)]
+struct RunHooks {
+    /// Each hook that ran
+    hooks: Vec<RepoHook>,
+}
+
+/// Run the hook in a repository
+fn run_repo(repo: &Path, options: &MultiRunOptions) -> RepoOutcome {
+    let started = Instant::now();
+    let outcome = |status: RepoStatus, hooks: Vec<RepoHook>, output: String| RepoOutcome {
+        repo: repo.to_path_buf(),
+        status,
+        duration_ms: started.elapsed().as_millis(),
+        hooks,
+        output,
+    };
+    if !repo.is_dir() {
+        return outcome(RepoStatus::Error, Vec::new(), format!("{} is not a directory", repo.display()));
+    }
+    let config = match hook_config(repo, &options.hook_id) {
+        Ok(Some(config)) => config,
+        Ok(None) => return outcome(RepoStatus::MissingHook, Vec::new(), String::new()),
+        Err(e) => return outcome(RepoStatus::Error, Vec::new(), format!("Error reading the config: {:?}", e)),
+    };
+
+    // The run reads the selected hook from a config of its own, and reports through metrics
+    let scratch = match tempfile::tempdir() {
+        Ok(scratch) => scratch,
+        Err(e) => return outcome(RepoStatus::Error, Vec::new(), e.to_string()),
+    };
+    let config_path = scratch.path().join("config.yaml");
+    let metrics_path = scratch.path().join("metrics.json");
+    let written = serde_yaml::to_string(&config).map_err(|e| e.to_string()).and_then(|yaml| fs::write(&config_path, yaml).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        return outcome(RepoStatus::Error, Vec::new(), e);
+    }
+    let output = Command::new(&options.binary)
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--cache-dir")
+        .arg(&options.cache_dir)
+        .arg("run")
+        .arg("--metrics-file")
+        .arg(&metrics_path)
+        .env_remove(config::parser::CONFIG_ENV)
+        .current_dir(repo)
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => return outcome(RepoStatus::Error, Vec::new(), format!("Error running {}: {}", options.binary.display(), e)),
+    };
+    let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+    let hooks = fs::read_to_string(&metrics_path)
+        .ok()
+        .and_then(|metrics| serde_json::from_str::<RunHooks>(&metrics).ok())
+        .map(|metrics| metrics.hooks)
+        .unwrap_or_default();
+    match (output.status.success(), hooks.iter().any(|hook| hook.status == HookStatus::Failed)) {
+        (true, _) => outcome(RepoStatus::Passed, hooks, String::new()),
+        (false, true) => outcome(RepoStatus::Failed, hooks, text),
+        (false, false) => outcome(RepoStatus::Error, hooks, text),
+    }
+}
+
+/// Run a hook in every repository
+///
+/// Repositories using an environment no repository before them uses run
+/// alone first, so every environment is set up before the others run in
+/// parallel. Outcomes are in the order of `repos`.
+pub fn run(repos: &[PathBuf], options: &MultiRunOptions) -> MultiRunReport {
+    let mut seen = HashSet::new();
+    let (setups, rest): (Vec<_>, Vec<_>) = repos.iter().enumerate().partition(|(_, repo)| {
+        let keys = environment_keys(repo, options);
+        let sets_up = keys.iter().any(|key| !seen.contains(key));
+        seen.extend(keys);
+        sets_up
+    });
+
+    let mut done = Vec::with_capacity(repos.len());
+    for (index, repo) in setups {
+        let outcome = run_repo(repo, options);
+        log::debug!("{}: {:?}", repo.display(), outcome.status);
+        done.push((index, outcome));
+    }
+
+    let next = Mutex::new(rest.into_iter());
+    let done = Mutex::new(done);
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.max(1) {
+            scope.spawn(|| loop {
+                let Some((index, repo)) = next.lock().unwrap().next() else {
+                    break;
+                };
+                let outcome = run_repo(repo, options);
+                log::debug!("{}: {:?}", repo.display(), outcome.status);
+                done.lock().unwrap().push((index, outcome));
+            });
+        }
+    });
+    let mut done = done.into_inner().unwrap();
+    done.sort_by_key(|(index, _)| *index);
+    MultiRunReport { hook_id: options.hook_id.clone(), repos: done.into_iter().map(|(_, outcome)| outcome).collect() }
+}
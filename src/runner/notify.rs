@@ -0,0 +1,125 @@
+//! Completion notifications for RustyHook
+//!
+//! This module notifies the user when a long run finishes, so a failure isn't
+//! missed after switching to another window.
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::config::parser::NotifyMethod;
+use crate::config::Config;
+
+/// Environment variables set by common CI providers
+const CI_ENV_VARS: &[&str] = &["CI", "GITHUB_ACTIONS", "GITLAB_CI", "BUILDKITE", "CIRCLECI", "TF_BUILD", "JENKINS_URL", "TEAMCITY_VERSION"];
+
+/// Check whether we're running in a CI environment
+pub fn is_ci() -> bool {
+    CI_ENV_VARS.iter().any(|var| match std::env::var(var) {
+        Ok(value) => !value.is_empty() && value != "false" && value != "0",
+        Err(_) => false,
+    })
+}
+
+/// Sends a notification when a run takes longer than a threshold
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    /// Minimum run duration to notify for
+    threshold: Duration,
+    /// How to notify
+    methods: Vec<NotifyMethod>,
+}
+
+impl Notifier {
+    /// Create a notifier from the config, or None when notifications are off
+    ///
+    /// Notifications are always off in CI.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let seconds = config.notify_after_seconds?;
+        if is_ci() {
+            debug!("Notifications are disabled in CI");
+            return None;
+        }
+
+        let methods = if config.notify.is_empty() {
+            vec![NotifyMethod::Bell, NotifyMethod::Desktop]
+        } else {
+            config.notify.clone()
+        };
+        Some(Notifier { threshold: Duration::from_secs(seconds), methods })
+    }
+
+    /// Check whether a run of the given duration should be notified
+    pub fn should_notify(&self, elapsed: Duration) -> bool {
+        elapsed >= self.threshold
+    }
+
+    /// Get the notification message for a finished run
+    pub fn message(elapsed: Duration, success: bool) -> String {
+        let outcome = if success { "Hooks passed" } else { "Hooks failed" };
+        format!("{} after {}s", outcome, elapsed.as_secs())
+    }
+
+    /// Notify that a run finished, if it took long enough
+    pub fn finish(&self, elapsed: Duration, success: bool) {
+        if !self.should_notify(elapsed) {
+            return;
+        }
+
+        let message = Self::message(elapsed, success);
+        for method in &self.methods {
+            match method {
+                NotifyMethod::Bell => ring_bell(),
+                NotifyMethod::Desktop => desktop_notification("RustyHook", &message),
+            }
+        }
+    }
+}
+
+/// Ring the terminal bell, if stderr is a terminal
+fn ring_bell() {
+    let mut stderr = std::io::stderr();
+    if stderr.is_terminal() {
+        let _ = stderr.write_all(b"\x07");
+        let _ = stderr.flush();
+    }
+}
+
+/// Show a desktop notification, ignoring failures
+fn desktop_notification(title: &str, message: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!("display notification \"{}\" with title \"{}\"", message, title);
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(windows) {
+        let script = format!(
+            "[reflection.assembly]::loadwithpartialname('System.Windows.Forms') | Out-Null; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; $n.Icon = [System.Drawing.SystemIcons]::Information; \
+             $n.Visible = $true; $n.ShowBalloonTip(5000, '{}', '{}', 'None'); Start-Sleep -Seconds 5; $n.Dispose()",
+            title, message
+        );
+        // The balloon disappears with the process, so it stays up on its own, detached from the run
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-Command", &script]);
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            const DETACHED_PROCESS: u32 = 0x0000_0008;
+            command.creation_flags(DETACHED_PROCESS);
+        }
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg(title).arg(message);
+        command
+    };
+
+    // The run doesn't wait for the notification to go away
+    let result = command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null()).spawn();
+    if let Err(e) = result {
+        debug!("Could not show desktop notification: {}", e);
+    }
+}
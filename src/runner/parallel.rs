@@ -2,19 +2,32 @@
 //!
 //! This module provides functionality for running hooks in parallel.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 use tokio::task::JoinSet;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::config::parser::AccessMode;
+use crate::config::parser::{AccessMode, OversizedFiles};
 
 use crate::config::{Config, Hook};
-use crate::toolchains::Tool;
+use crate::config::hook_repo::resolve_entry;
+use crate::error_codes::HasErrorCode;
+use crate::hooks::{Diagnostic, HookError};
+use crate::toolchains::parsers::{OutputFormat, OutputParser};
+use crate::toolchains::{output, Tool};
 use super::hook_resolver::{HookResolver, HookResolverError};
-use super::file_matcher::FileMatcher;
+use super::attributes::GitAttributes;
+use super::file_matcher::{filter_hook_files, max_file_size, oversized_files};
 use super::hook_context::HookContext;
+use super::git_changes::{deleted_files, unstaged_files, FileChange};
+use super::plan::{ExecutionPlan, PreparedHook};
+use super::diff::{FileStamps, HookChanges, Snapshot};
+use super::report::{AccessModeViolation, HookResult, HookStatus, RunReport, SkipReason, SkippedFile, SkippedHook};
+use super::capabilities::{self, MissingCommand};
+use super::progress::{Progress, ProgressEvent};
+use super::stage_input::StageInput;
 
 /// Error type for parallel execution operations
 #[derive(Debug)]
@@ -23,6 +36,8 @@ pub enum ParallelExecutionError {
     HookResolverError(HookResolverError),
     /// Error with tokio
     TokioError(tokio::task::JoinError),
+    /// Commands the hooks need are missing
+    MissingCommands(Vec<MissingCommand>),
 }
 
 impl From<HookResolverError> for ParallelExecutionError {
@@ -42,6 +57,10 @@ impl std::fmt::Display for ParallelExecutionError {
         match self {
             ParallelExecutionError::HookResolverError(err) => write!(f, "{}", err),
             ParallelExecutionError::TokioError(err) => write!(f, "Task execution error: {}", err),
+            ParallelExecutionError::MissingCommands(missing) => {
+                let commands: Vec<&str> = missing.iter().map(|missing| missing.command.as_str()).collect();
+                write!(f, "Required commands not found: {}", commands.join(", "))
+            }
         }
     }
 }
@@ -51,36 +70,148 @@ impl std::error::Error for ParallelExecutionError {
         match self {
             ParallelExecutionError::HookResolverError(err) => Some(err),
             ParallelExecutionError::TokioError(err) => Some(err),
+            ParallelExecutionError::MissingCommands(_) => None,
         }
     }
 }
 
+/// Thread-safe cache of tools shared between hook tasks
+type SharedToolCache = Arc<RwLock<HashMap<String, Arc<Box<dyn Tool + Send + Sync>>>>>;
+
+/// Result of a hook task: its position in the batch, whether its failure stops the run, and how it went
+///
+/// `None` means the run was stopped before the hook started.
+type HookTaskResult = (usize, bool, Option<Result<(), ParallelExecutionError>>);
+
 /// Represents a parallel executor
 pub struct ParallelExecutor {
     /// Hook resolver
     resolver: Arc<Mutex<HookResolver>>,
     /// Thread-safe tool cache
-    tool_cache: Arc<RwLock<HashMap<String, Arc<Box<dyn Tool + Send + Sync>>>>>,
+    tool_cache: SharedToolCache,
+    /// Changes made by read-write hooks
+    fixes: Arc<Mutex<Vec<HookChanges>>>,
+    /// Results of the hooks that ran
+    results: Arc<Mutex<Vec<HookResult>>>,
+    /// Configured hooks that didn't run
+    skipped: Arc<Mutex<Vec<SkippedHook>>>,
+    /// Files hooks skipped for their size
+    skipped_files: Arc<Mutex<Vec<SkippedFile>>>,
+    /// Read-only hooks that modified files
+    access_mode_violations: Arc<Mutex<Vec<AccessModeViolation>>>,
+    /// Whether to check that read-only hooks don't write files
+    verify_access_modes: AtomicBool,
+    /// Where the progress of runs is reported
+    progress: Progress,
 }
 
 impl ParallelExecutor {
     /// Create a new parallel executor
     pub fn new(config: Config, cache_dir: PathBuf) -> Self {
-        let resolver = HookResolver::new(config, cache_dir);
+        let progress = Progress::default();
+        let mut resolver = HookResolver::new(config, cache_dir);
+        resolver.set_progress(progress.clone());
         ParallelExecutor {
             resolver: Arc::new(Mutex::new(resolver)),
             tool_cache: Arc::new(RwLock::new(HashMap::new())),
+            fixes: Arc::new(Mutex::new(Vec::new())),
+            results: Arc::new(Mutex::new(Vec::new())),
+            skipped: Arc::new(Mutex::new(Vec::new())),
+            skipped_files: Arc::new(Mutex::new(Vec::new())),
+            access_mode_violations: Arc::new(Mutex::new(Vec::new())),
+            verify_access_modes: AtomicBool::new(false),
+            progress,
         }
     }
 
+    /// Get where the progress of runs is reported, to subscribe to it
+    pub fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    /// Check that hooks declared `access_mode: Read` don't write files
+    ///
+    /// Read-only hooks then run one at a time, so a change to any of the
+    /// run's files can be attributed to the hook that made it. Such hooks are
+    /// reported with a warning and the files they modified.
+    pub fn set_verify_access_modes(&self, verify: bool) {
+        self.verify_access_modes.store(verify, Ordering::SeqCst);
+    }
+
     /// Set hooks to skip
     pub async fn set_hooks_to_skip(&self, hooks: Vec<String>) {
         let mut resolver = self.resolver.lock().await;
         resolver.set_hooks_to_skip(hooks);
     }
 
+    /// Set the stage of the run, which hooks see as `RH_STAGE`
+    pub async fn set_stage(&self, stage: &str) {
+        self.resolver.lock().await.set_stage(stage);
+    }
+
+    /// Set what Git passed to the stage, which hooks see in their environment
+    pub async fn set_stage_input(&self, input: &StageInput) {
+        self.resolver.lock().await.set_stage_input(input);
+    }
+
+    /// Set the changes in the current changeset
+    pub async fn set_changes(&self, changes: Vec<FileChange>) {
+        let mut resolver = self.resolver.lock().await;
+        resolver.set_changes(changes);
+    }
+
+    /// Record hooks that were left out of the config before it reached the executor
+    pub async fn record_skipped(&self, hooks: Vec<SkippedHook>) {
+        self.skipped.lock().await.extend(hooks);
+    }
+
+    /// Get the position of each hook ID in the config
+    ///
+    /// Reports list hooks in this order whatever order they finished in, so
+    /// the output of parallel runs can be compared.
+    async fn declaration_order(&self) -> HashMap<String, usize> {
+        let resolver = self.resolver.lock().await;
+        let mut order = HashMap::new();
+        for hook in resolver.config().repos.iter().flat_map(|repo| repo.hooks.iter()) {
+            let position = order.len();
+            order.entry(hook.id.clone()).or_insert(position);
+        }
+        order
+    }
+
+    /// Get the changes made by read-write hooks, in config order
+    pub async fn fixer_changes(&self) -> Vec<HookChanges> {
+        let order = self.declaration_order().await;
+        let mut fixes = self.fixes.lock().await.clone();
+        fixes.sort_by_key(|fix| (order.get(&fix.hook_id).copied().unwrap_or(usize::MAX), fix.hook_id.clone()));
+        fixes
+    }
+
+    /// Get the results of the hooks that ran, in config order
+    ///
+    /// Hooks left out of the config before it reached the executor are
+    /// listed last among the skipped hooks.
+    pub async fn report(&self) -> RunReport {
+        let order = self.declaration_order().await;
+        let position = |hook_id: &str| order.get(hook_id).copied().unwrap_or(usize::MAX);
+        let mut hooks = self.results.lock().await.clone();
+        hooks.sort_by_key(|hook| (position(&hook.hook_id), hook.hook_id.clone(), hook.repo.clone()));
+        let mut skipped = self.skipped.lock().await.clone();
+        skipped.sort_by_key(|hook| (position(&hook.hook_id), hook.hook_id.clone(), hook.repo.clone()));
+        let mut setups = self.resolver.lock().await.setups().to_vec();
+        setups.sort_by_key(|setup| (position(&setup.hook_id), setup.tool.clone()));
+        let mut skipped_files = self.skipped_files.lock().await.clone();
+        skipped_files.sort_by_key(|file| (position(&file.hook_id), file.hook_id.clone(), file.path.clone()));
+        let mut access_mode_violations = self.access_mode_violations.lock().await.clone();
+        access_mode_violations.sort_by_key(|violation| (position(&violation.hook_id), violation.hook_id.clone(), violation.repo.clone()));
+        RunReport { hooks, skipped, skipped_files, setups, access_mode_violations }
+    }
+
     /// Prepare hook contexts for parallel execution
-    async fn prepare_hook_contexts(&self, files: &[PathBuf]) -> Result<Vec<(String, String, Hook, Vec<PathBuf>)>, ParallelExecutionError> {
+    ///
+    /// Also returns the hooks that won't run, and why, and the files hooks
+    /// skip for being over their `max_file_size`.
+    async fn prepare_hook_contexts(&self, files: &[PathBuf]) -> Result<(Vec<PreparedHook>, Vec<SkippedHook>, Vec<SkippedFile>), ParallelExecutionError> {
         // Acquire the lock and get a reference to the resolver
         let resolver_guard = self.resolver.lock().await;
 
@@ -90,49 +221,91 @@ impl ParallelExecutor {
         // Get the hooks to skip
         let hooks_to_skip = resolver_guard.hooks_to_skip().clone();
 
+        // Get the deleted files for hooks that ask for them
+        let deleted = deleted_files(resolver_guard.changes());
+        let cache_dir = resolver_guard.cache_dir().clone();
+
         // Release the lock
         drop(resolver_guard);
 
+        // Text hooks skip files .gitattributes marks as binary or generated
+        let attributes = GitAttributes::discover(files.first().map(PathBuf::as_path).unwrap_or(Path::new("."))).with_cache(&cache_dir);
+
         // Collect all hooks to run, excluding those that should be skipped
         let mut hook_contexts = Vec::new();
+        let mut skipped = Vec::new();
+        let mut skipped_files = Vec::new();
         for repo in &config.repos {
             for hook in &repo.hooks {
-                if !hooks_to_skip.contains(&hook.id) {
-                    // Filter files based on the hook's file pattern
-                    let filtered_files = if !hook.files.is_empty() {
-                        match FileMatcher::from_regex(&hook.files) {
-                            Ok(matcher) => matcher.filter_files(files),
-                            Err(err) => return Err(ParallelExecutionError::HookResolverError(err.into())),
+                let skip = |reason| SkippedHook { repo: repo.repo.clone(), hook_id: hook.id.clone(), reason };
+                if hook.is_skipped_by(&hooks_to_skip) {
+                    skipped.push(skip(SkipReason::SkipList));
+                } else {
+                    // Deleted files are only passed to hooks that opt in
+                    let mut hook_files = files.to_vec();
+                    if hook.include_deleted {
+                        hook_files.extend(deleted.iter().cloned());
+                    }
+
+                    // Filter files based on the hook's file pattern and attributes
+                    let mut filtered_files = filter_hook_files(hook, &hook_files, &attributes, !config.no_default_excludes)
+                        .map_err(|err| ParallelExecutionError::HookResolverError(err.into()))?;
+
+                    // Leave out files over the hook's size limit, unless they should fail it when it runs
+                    let matched = !filtered_files.is_empty();
+                    if hook.oversized_files == OversizedFiles::Skip {
+                        let limit = max_file_size(hook).unwrap_or(u64::MAX);
+                        for (path, size) in oversized_files(hook, &filtered_files) {
+                            filtered_files.retain(|file| *file != path);
+                            skipped_files.push(SkippedFile { repo: repo.repo.clone(), hook_id: hook.id.clone(), path, size, limit });
                         }
-                    } else {
-                        files.to_vec()
-                    };
+                    }
 
                     // Skip hooks with no matching files
-                    if !filtered_files.is_empty() {
-                        hook_contexts.push((repo.repo.clone(), hook.id.clone(), hook.clone(), filtered_files));
+                    if !matched {
+                        skipped.push(skip(SkipReason::NoMatchingFiles));
+                    } else if filtered_files.is_empty() {
+                        skipped.push(skip(SkipReason::FilesTooLarge));
+                    } else {
+                        hook_contexts.push((repo.repo.clone(), hook.id.clone(), resolve_entry(&repo.repo, hook), filtered_files));
                     }
                 }
             }
         }
 
-        Ok(hook_contexts)
+        Ok((hook_contexts, skipped, skipped_files))
     }
 
     /// Run a hook with the prepared context
+    ///
+    /// The problems its output parser finds in its output go to `diagnostics`,
+    /// whether the hook passes or fails.
     async fn run_hook_with_context(
         resolver: Arc<Mutex<HookResolver>>,
-        _tool_cache: Arc<RwLock<HashMap<String, Arc<Box<dyn Tool + Send + Sync>>>>>,
+        _tool_cache: SharedToolCache,
         repo_id: &str,
         hook_id: &str,
         hook: &Hook,
-        files: &[PathBuf]
+        files: &[PathBuf],
+        diagnostics: &mut Vec<Diagnostic>
     ) -> Result<(), HookResolverError> {
         // If there are no files to process, we're done
         if files.is_empty() {
             return Ok(());
         }
 
+        // Files over the size limit fail hooks that don't skip them
+        let oversized = oversized_files(hook, files);
+        if !oversized.is_empty() {
+            let limit = max_file_size(hook).unwrap_or(u64::MAX);
+            let files: Vec<String> = oversized.iter().map(|(path, size)| format!("{} ({} bytes)", path.display(), size)).collect();
+            return Err(HookResolverError::HookError(HookError::Other(format!(
+                "Files over max_file_size of {} bytes: {}",
+                limit,
+                files.join(", ")
+            ))));
+        }
+
         // Get the current working directory
         let working_dir = std::env::current_dir().map_err(|err| {
             HookResolverError::FileNotFound {
@@ -141,13 +314,29 @@ impl ParallelExecutor {
             }
         })?;
 
+        // Hooks requiring a clean working tree don't run on files with unstaged changes
+        if hook.require_clean {
+            check_clean(hook, &working_dir, files)?;
+        }
+
         // Create the context for running the hook
-        let context = HookContext::from_hook(hook, working_dir, files.to_vec());
+        let resolver_guard = resolver.lock().await;
+        let hook = resolver_guard.with_hook_cache(repo_id, hook);
+        let mut context = HookContext::from_hook(&hook, working_dir, files.to_vec());
+        context.set_changes(resolver_guard.changes());
+        context.output_limit.log_dir = Some(resolver_guard.cache_dir().join(output::LOGS_DIR));
+        context.image_records = Some(resolver_guard.cache_dir().to_path_buf());
+        drop(resolver_guard);
 
         // Use the context to decide how to run the hook
         if context.should_run_in_separate_process() {
+            // Check the environment first, hooks with a tool do so when they run
+            resolver.lock().await.preflight(&hook)?;
+
             // Run the hook in a separate process using the context
-            context.run_in_separate_process().map_err(|err| match err {
+            let (result, stdout) = output::capturing_when(parses_output(&hook), || context.run_in_separate_process());
+            diagnostics.extend(parse_output(&hook, &stdout));
+            result.map_err(|err| match err {
                 super::hook_context::HookContextError::ProcessError(msg) => HookResolverError::ProcessError(msg),
                 super::hook_context::HookContextError::IoError(err) => HookResolverError::IoError(err),
                 super::hook_context::HookContextError::HookError(err) => HookResolverError::HookError(err),
@@ -163,139 +352,194 @@ impl ParallelExecutor {
             // Instead of using the tool cache or setup_tool, use run_hook directly
             // This avoids the trait bound error and the private method issue
             let mut resolver_guard = resolver.lock().await;
-            resolver_guard.run_hook(repo_id, hook_id, files)
+            let (result, stdout) = output::capturing_when(parses_output(&hook), || resolver_guard.run_hook(repo_id, hook_id, files));
+            diagnostics.extend(parse_output(&hook, &stdout));
+            result
         }
     }
 
-    /// Run all hooks on files in parallel
-    pub async fn run_all_hooks(&self, files: Vec<PathBuf>) -> Result<(), ParallelExecutionError> {
-        // Prepare all hook contexts upfront to minimize mutex contention
-        let hook_contexts = self.prepare_hook_contexts(&files).await?;
+    /// Compute the execution plan for the hooks matching the files
+    pub async fn plan(&self, files: &[PathBuf]) -> Result<ExecutionPlan, ParallelExecutionError> {
+        let (hook_contexts, skipped, skipped_files) = self.prepare_hook_contexts(files).await?;
 
-        // Get the parallelism limit from the config
+        // Get the parallelism limit of hook runs from the config
         let parallelism = {
             let resolver_guard = self.resolver.lock().await;
-            resolver_guard.config().parallelism
+            resolver_guard.config().run_limit()
         };
 
+        let mut plan = ExecutionPlan::build(hook_contexts, parallelism);
+        plan.skipped.extend(skipped);
+        plan.skipped_files = skipped_files;
+        Ok(plan)
+    }
+
+    /// Run all hooks on files in parallel
+    ///
+    /// A failing hook fails the run once every hook has run, unless it has
+    /// `continue_on_error` set. When a hook with `fail_fast` fails, or any
+    /// hook with the config's `fail_fast`, the hooks still running are
+    /// cancelled and the rest are not started. They are reported as skipped.
+    pub async fn run_all_hooks(&self, files: Vec<PathBuf>) -> Result<(), ParallelExecutionError> {
+        // Prepare all hook contexts upfront to minimize mutex contention
+        let plan = self.plan(&files).await?;
+        let verify = self.verify_access_modes.load(Ordering::SeqCst);
+        let fail_fast = self.resolver.lock().await.config().fail_fast;
+        self.skipped.lock().await.extend(plan.skipped.iter().cloned());
+        self.skipped_files.lock().await.extend(plan.skipped_files.iter().cloned());
+        self.probe_capabilities(&plan).await?;
+        self.set_up_environments(&plan).await?;
+
         // Create a JoinSet to collect all tasks
         let mut tasks = JoinSet::new();
+        let mut failure = None;
+
+        // Read-only hooks run first, then groups of non-overlapping read-write hooks
+        self.progress.emit(ProgressEvent::PhaseStarted { access_mode: AccessMode::Read, hooks: plan.hook_count(AccessMode::Read) });
+        let mut announced_write_hooks = false;
+        let mut write_group = 0;
+
+        'stages: for (index, stage) in plan.stages.iter().enumerate() {
+            if stage.access_mode == AccessMode::ReadWrite {
+                if !announced_write_hooks {
+                    self.progress.emit(ProgressEvent::PhaseStarted { access_mode: AccessMode::ReadWrite, hooks: plan.hook_count(AccessMode::ReadWrite) });
+                    announced_write_hooks = true;
+                }
+                write_group += 1;
+                self.progress.emit(ProgressEvent::GroupStarted { group: write_group, hooks: stage.hooks.len() });
+            }
 
-        // Separate hooks into read-only and read-write groups
-        let mut read_hooks = Vec::new();
-        let mut write_hooks = Vec::new();
-
-        for context in hook_contexts {
-            if context.2.access_mode == AccessMode::Read {
-                read_hooks.push(context);
-            } else {
-                write_hooks.push(context);
+            // Apply parallelism limit if configured, and audit read-only hooks one at a time
+            let audited_files = (verify && stage.access_mode == AccessMode::Read).then_some(files.as_slice());
+            let limit = plan.stage_limit(index, audited_files.is_some());
+            log::debug!("Stage {} runs {}", index + 1, limit);
+            for (batch, chunk) in stage.hooks.chunks(limit.hooks).enumerate() {
+                if let Some(stopped_by) = self.run_hook_batch(chunk, fail_fast, audited_files, &mut tasks, &mut failure).await? {
+                    log::error!("Stopping because hook '{}' failed with fail_fast", stopped_by);
+                    let unstarted = stage.hooks.iter().skip((batch + 1) * limit.hooks).chain(plan.stages[index + 1..].iter().flat_map(|stage| &stage.hooks));
+                    self.record_cancelled(unstarted, &stopped_by).await;
+                    break 'stages;
+                }
             }
         }
 
-        // Run read-only hooks first (they can all run in parallel)
-        println!("Running {} read-only hooks", read_hooks.len());
-
-        // Apply parallelism limit if configured
-        if parallelism > 0 {
-            // Process read hooks in batches
-            for chunk in read_hooks.chunks(parallelism) {
-                self.run_hook_batch(chunk, &mut tasks).await?;
-            }
-        } else {
-            // Run all read hooks in parallel
-            self.run_hook_batch(&read_hooks, &mut tasks).await?;
+        if !announced_write_hooks && failure.is_none() {
+            self.progress.emit(ProgressEvent::PhaseStarted { access_mode: AccessMode::ReadWrite, hooks: 0 });
         }
+        self.progress.emit(ProgressEvent::RunFinished { success: failure.is_none() });
 
-        // Group read-write hooks by their file globs to avoid conflicts
-        println!("Running {} read-write hooks", write_hooks.len());
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
 
-        if write_hooks.is_empty() {
-            // Make sure all tasks are completed before returning
-            while tasks.len() > 0 {
-                let result = tasks.join_next().await.unwrap();
-                result??;
-            }
+    /// Check that the commands the planned hooks need can be found
+    ///
+    /// Hooks with `continue_on_error` can't fail the run, so commands only
+    /// they need are warned about.
+    async fn probe_capabilities(&self, plan: &ExecutionPlan) -> Result<(), ParallelExecutionError> {
+        let cache_dir = self.resolver.lock().await.cache_dir().clone();
+        let hooks: Vec<&Hook> = plan.stages.iter().flat_map(|stage| stage.hooks.iter().map(|prepared| &prepared.2)).collect();
+        let (required, optional): (Vec<&Hook>, Vec<&Hook>) = hooks.into_iter().partition(|hook| !hook.continue_on_error);
+
+        for missing in capabilities::probe(optional, &cache_dir) {
+            log::warn!("{}", missing);
+        }
+        let missing = capabilities::probe(required, &cache_dir);
+        if missing.is_empty() {
             return Ok(());
         }
+        for missing in &missing {
+            log::error!("{}", missing);
+        }
+        Err(ParallelExecutionError::MissingCommands(missing))
+    }
 
-        // Create groups of non-overlapping hooks
-        let mut hook_groups: Vec<Vec<(String, String, Hook, Vec<PathBuf>)>> = Vec::new();
-
-        // Helper function to check if two hooks have overlapping file patterns
-        let hooks_overlap = |hook1: &Hook, hook2: &Hook| -> bool {
-            // If either hook has an empty files pattern, assume they overlap
-            if hook1.files.is_empty() || hook2.files.is_empty() {
-                return true;
-            }
-
-            // If the file patterns are different, assume they don't overlap
-            // This is a simplification - in a real implementation, we would need to check
-            // if the regex patterns could match the same files
-            hook1.files == hook2.files
-        };
-
-        // Group hooks that don't overlap
-        for (repo_id, hook_id, hook, filtered_files) in write_hooks {
-            // Try to find a group where this hook doesn't overlap with any hook
-            let mut found_group = false;
-
-            for group in &mut hook_groups {
-                let mut can_add_to_group = true;
-
-                // Check if this hook overlaps with any hook in the group
-                for (_, _, existing_hook, _) in group.iter() {
-                    if hooks_overlap(existing_hook, &hook) {
-                        can_add_to_group = false;
-                        break;
-                    }
+    /// Set up the environments of the planned hooks before any of them runs
+    ///
+    /// Setups run without holding the resolver, at most `setup_parallelism`
+    /// at a time, so they don't compete with hooks for CPU and bandwidth. A
+    /// failed setup fails the hooks using its tool when they run.
+    async fn set_up_environments(&self, plan: &ExecutionPlan) -> Result<(), ParallelExecutionError> {
+        let (pending, limit) = {
+            let mut resolver = self.resolver.lock().await;
+            let mut started = HashSet::new();
+            let mut pending = Vec::new();
+            for (repo_id, hook_id, hook, files) in plan.stages.iter().flat_map(|stage| &stage.hooks) {
+                if files.is_empty() || !oversized_files(hook, files).is_empty() {
+                    continue;
                 }
-
-                if can_add_to_group {
-                    group.push((repo_id.clone(), hook_id.clone(), hook.clone(), filtered_files.clone()));
-                    found_group = true;
-                    break;
+                match resolver.begin_setups(repo_id, hook_id, files, &mut started) {
+                    Ok(setups) => pending.extend(setups),
+                    Err(e) => log::debug!("Not setting up hook '{}' ahead of its run: {:?}", hook_id, e),
                 }
             }
+            (pending, resolver.config().setup_limit())
+        };
 
-            // If no suitable group was found, create a new group
-            if !found_group {
-                hook_groups.push(vec![(repo_id, hook_id, hook, filtered_files)]);
+        let batch_size = if limit > 0 { limit } else { pending.len().max(1) };
+        let mut pending = pending.into_iter().peekable();
+        while pending.peek().is_some() {
+            let mut tasks = JoinSet::new();
+            for setup in pending.by_ref().take(batch_size) {
+                tasks.spawn_blocking(move || {
+                    let result = setup.run();
+                    (setup, result)
+                });
             }
-        }
-
-        // Run each group of non-overlapping hooks in parallel
-        for (i, group) in hook_groups.iter().enumerate() {
-            println!("Running group {} of {} non-overlapping read-write hooks", i + 1, group.len());
-
-            if parallelism > 0 {
-                // Process hooks in batches
-                for chunk in group.chunks(parallelism) {
-                    self.run_hook_batch(chunk, &mut tasks).await?;
+            while let Some(joined) = tasks.join_next().await {
+                let (setup, result) = joined?;
+                if let Err(e) = self.resolver.lock().await.finish_setup(setup, result) {
+                    log::debug!("Environment setup failed, its hooks report it when they run: {:?}", e);
                 }
-            } else {
-                // Run all hooks in this group in parallel
-                self.run_hook_batch(group, &mut tasks).await?;
             }
         }
+        Ok(())
+    }
 
-        // Make sure all tasks are completed before returning
-        while tasks.len() > 0 {
-            let result = tasks.join_next().await.unwrap();
-            result??;
+    /// Record hooks that didn't run because a hook failed with `fail_fast`
+    async fn record_cancelled<'a>(&self, hooks: impl Iterator<Item = &'a PreparedHook>, stopped_by: &str) {
+        let mut skipped = self.skipped.lock().await;
+        for (repo_id, hook_id, _, _) in hooks {
+            skipped.push(SkippedHook { repo: repo_id.clone(), hook_id: hook_id.clone(), reason: SkipReason::Cancelled { by: stopped_by.to_string() } });
         }
-
-        Ok(())
     }
 
     /// Run a batch of hooks in parallel
+    ///
+    /// The failure of the first failing hook in plan order is stored in `failure`. When a hook with
+    /// `fail_fast`, or any hook with `fail_fast` set for the run, fails, the
+    /// hooks of the batch that haven't started are cancelled and the ID of
+    /// the failed hook returned. A hook's command can't be interrupted, so
+    /// hooks already running finish and are reported as finished after it. With `audited_files`, read-only hooks that
+    /// change any of them are reported.
     async fn run_hook_batch(
         &self,
-        hooks: &[(String, String, Hook, Vec<PathBuf>)],
-        tasks: &mut JoinSet<Result<(), ParallelExecutionError>>
-    ) -> Result<(), ParallelExecutionError> {
+        hooks: &[PreparedHook],
+        fail_fast: bool,
+        audited_files: Option<&[PathBuf]>,
+        tasks: &mut JoinSet<HookTaskResult>,
+        failure: &mut Option<ParallelExecutionError>,
+    ) -> Result<Option<String>, ParallelExecutionError> {
+        // Hooks of the batch run at the same time, so a change to a file several of them run on can't be pinned on one
+        let mut file_hooks: HashMap<&PathBuf, Vec<&str>> = HashMap::new();
+        for (_, hook_id, _, files) in hooks {
+            for file in files {
+                file_hooks.entry(file).or_default().push(hook_id);
+            }
+        }
+
         // Spawn tasks for this batch
-        for (repo_id, hook_id, hook, filtered_files) in hooks {
+        let stopping = Arc::new(AtomicBool::new(false));
+        for (index, (repo_id, hook_id, hook, filtered_files)) in hooks.iter().enumerate() {
+            let sharers: HashMap<PathBuf, Vec<String>> = filtered_files
+                .iter()
+                .filter_map(|file| {
+                    let others: Vec<String> = file_hooks.get(file)?.iter().filter(|other| **other != hook_id).map(|other| other.to_string()).collect();
+                    (!others.is_empty()).then(|| (file.clone(), others))
+                })
+                .collect();
             // Clone the necessary data for the task
             let resolver = Arc::clone(&self.resolver);
             let tool_cache = Arc::clone(&self.tool_cache);
@@ -303,26 +547,176 @@ impl ParallelExecutor {
             let hook_id = hook_id.clone();
             let hook = hook.clone();
             let filtered_files = filtered_files.clone();
+            let fixes = Arc::clone(&self.fixes);
+            let results = Arc::clone(&self.results);
+            let violations = Arc::clone(&self.access_mode_violations);
+            let progress = self.progress.clone();
+            let audited_files = audited_files.filter(|_| hook.access_mode == AccessMode::Read).map(<[PathBuf]>::to_vec);
+            let stopping = Arc::clone(&stopping);
 
             // Spawn a task to run the hook
             tasks.spawn(async move {
-                Self::run_hook_with_context(
+                if stopping.load(Ordering::SeqCst) {
+                    return (index, false, None);
+                }
+                // Record the files read-write hooks may change
+                let snapshot = (hook.access_mode == AccessMode::ReadWrite).then(|| Snapshot::capture(&filtered_files));
+                let audit = audited_files.map(|files| Snapshot::capture(&files));
+                // Without an audit, notice cheaply when read-only hooks write their own files anyway
+                let stamps = (hook.access_mode == AccessMode::Read && audit.is_none()).then(|| FileStamps::capture(&filtered_files));
+                progress.emit(ProgressEvent::HookStarted { repo: repo_id.clone(), hook_id: hook_id.clone(), file_count: filtered_files.len() });
+                let started = std::time::Instant::now();
+
+                let mut diagnostics = Vec::new();
+                let result = Self::run_hook_with_context(
                     resolver,
                     tool_cache,
                     &repo_id,
                     &hook_id,
                     &hook,
-                    &filtered_files
-                ).await.map_err(ParallelExecutionError::from)
+                    &filtered_files,
+                    &mut diagnostics
+                ).await;
+
+                let duration = started.elapsed();
+
+                let mut files_modified = Vec::new();
+                if let Some(snapshot) = snapshot {
+                    let files = snapshot.changes();
+                    if !files.is_empty() {
+                        files_modified = files.iter().map(|file| file.path.clone()).collect();
+                        fixes.lock().await.push(HookChanges { hook_id: hook_id.clone(), files });
+                    }
+                }
+                if let Some(audit) = audit {
+                    files_modified = audit.changes().into_iter().map(|file| file.path).collect();
+                }
+                if let Some(stamps) = stamps {
+                    files_modified = stamps.changed();
+                }
+                if hook.access_mode == AccessMode::Read && !files_modified.is_empty() {
+                    let mut shared_with: Vec<String> = files_modified.iter().filter_map(|file| sharers.get(file)).flatten().cloned().collect();
+                    shared_with.sort();
+                    shared_with.dedup();
+                    let violation = AccessModeViolation { repo: repo_id.clone(), hook_id: hook_id.clone(), files: files_modified.clone(), shared_with };
+                    log::warn!("{}", violation);
+                    violations.lock().await.push(violation);
+                }
+
+                let status = match &result {
+                    Ok(()) => HookStatus::Passed,
+                    Err(_) if hook.continue_on_error => HookStatus::AllowedFailure,
+                    Err(_) => HookStatus::Failed,
+                };
+                let hook_result = HookResult {
+                    repo: repo_id.clone(),
+                    hook_id: hook_id.clone(),
+                    status,
+                    duration,
+                    file_count: filtered_files.len(),
+                    files_modified,
+                    message: result.as_ref().err().map(|err| err.to_string()),
+                    details: result.as_ref().err().map(|err| err.details()),
+                    error: result.as_ref().err().map(|err| err.error_info()),
+                    diagnostics,
+                    tool: HookResolver::tool_key(&hook),
+                    finished_after: None,
+                };
+                progress.emit(ProgressEvent::HookFinished(Box::new(hook_result.clone())));
+                results.lock().await.push(hook_result);
+
+                let result = match result {
+                    Err(err) if hook.continue_on_error => {
+                        log::warn!("Hook '{}' failed, continuing because it has continue_on_error set: {}", hook_id, err);
+                        Ok(())
+                    }
+                    result => result.map_err(ParallelExecutionError::from),
+                };
+                (index, hook.fail_fast || fail_fast, Some(result))
             });
         }
 
-        // Wait for all tasks in this batch to complete
-        while tasks.len() > 0 {
-            let result = tasks.join_next().await.unwrap();
-            result??;
+        // Wait for all tasks in this batch to complete, keeping the failure of the first hook in plan order
+        let mut stopped_by = None;
+        let mut running: BTreeSet<usize> = (0..hooks.len()).collect();
+        let mut finished_after = Vec::new();
+        let mut first_failure: Option<(usize, ParallelExecutionError)> = None;
+        while let Some(joined) = tasks.join_next().await {
+            let (index, stops, result) = match joined {
+                Ok(joined) => joined,
+                Err(err) => return Err(err.into()),
+            };
+            let Some(result) = result else {
+                continue;
+            };
+            running.remove(&index);
+            if stopped_by.is_some() {
+                finished_after.push(hooks[index].1.clone());
+            }
+            if let Err(err) = result {
+                if stops && stopped_by.is_none() {
+                    stopped_by = Some(hooks[index].1.clone());
+                    stopping.store(true, Ordering::SeqCst);
+                }
+                if first_failure.as_ref().is_none_or(|(first, _)| index < *first) {
+                    first_failure = Some((index, err));
+                }
+            }
+        }
+        if let Some((_, err)) = first_failure {
+            failure.get_or_insert(err);
+        }
+        if let Some(stopped_by) = &stopped_by {
+            self.record_cancelled(running.iter().map(|index| &hooks[*index]), stopped_by).await;
+            for result in self.results.lock().await.iter_mut().filter(|result| finished_after.contains(&result.hook_id)) {
+                result.finished_after = Some(stopped_by.clone());
+            }
+        }
+
+        Ok(stopped_by)
+    }
+}
+
+/// Whether a hook's output is parsed into diagnostics, which needs its standard output collected
+fn parses_output(hook: &Hook) -> bool {
+    hook.output_format != OutputFormat::None
+}
+
+/// Parse the standard output of a hook with its output parser
+///
+/// Config validation rejects bad parsers, so a hook whose parser can't be
+/// created only gets no diagnostics.
+fn parse_output(hook: &Hook, stdout: &[u8]) -> Vec<Diagnostic> {
+    if !parses_output(hook) || stdout.is_empty() {
+        return Vec::new();
+    }
+    match OutputParser::new(hook.output_format, hook.output_pattern.as_deref()) {
+        Ok(parser) => parser.parse(&String::from_utf8_lossy(stdout)),
+        Err(e) => {
+            log::warn!("Hook '{}' output isn't parsed: {}", hook.id, e);
+            Vec::new()
         }
+    }
+}
 
-        Ok(())
+/// Check that none of a hook's files have unstaged changes
+///
+/// The repository is the one holding the files. Outside of a Git repository
+/// nothing is staged, so every file is clean.
+fn check_clean(hook: &Hook, working_dir: &Path, files: &[PathBuf]) -> Result<(), HookResolverError> {
+    let Some(first) = files.first() else { return Ok(()) };
+    let first = working_dir.join(first);
+    let repo_dir = first.parent().unwrap_or(working_dir);
+    let unstaged: HashSet<PathBuf> = match unstaged_files(repo_dir) {
+        Ok(unstaged) => unstaged.into_iter().collect(),
+        Err(e) => {
+            log::warn!("Hook '{}' requires a clean working tree, but its status is unknown: {}", hook.id, e.message());
+            return Ok(());
+        }
+    };
+    let unclean: Vec<PathBuf> = files.iter().filter(|file| unstaged.contains(&working_dir.join(file))).cloned().collect();
+    if unclean.is_empty() {
+        return Ok(());
     }
+    Err(HookResolverError::UncleanFiles { hook_id: hook.id.clone(), files: unclean })
 }
@@ -0,0 +1,403 @@
+//! Execution plans for RustyHook
+//!
+//! This module computes the order in which hooks run and renders it as text,
+//! Graphviz DOT or Mermaid.
+//!
+//! The decisions behind the plan are kept with it, so users can tell why
+//! hooks run one after the other: the conflicts that kept read-write hooks
+//! out of a group, and how many hooks of each stage run at a time. They are
+//! logged at debug level and listed by `rustyhook plan`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::config::parser::AccessMode;
+use crate::config::Hook;
+use super::hook_context::HookContext;
+use super::report::{SkipReason, SkippedFile, SkippedHook};
+
+/// A hook scheduled for execution: (repo id, hook id, hook, matched files)
+pub type PreparedHook = (String, String, Hook, Vec<PathBuf>);
+
+/// A group of hooks that run in parallel
+#[derive(Debug, Clone)]
+pub struct PlanStage {
+    /// Access mode shared by the hooks in the stage
+    pub access_mode: AccessMode,
+    /// Hooks in the stage
+    pub hooks: Vec<PreparedHook>,
+}
+
+/// The stages hooks run in, one after the other
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+    /// Stages in execution order
+    pub stages: Vec<PlanStage>,
+    /// Maximum number of hooks to run at once (0 means unlimited)
+    pub parallelism: usize,
+    /// Configured hooks that won't run
+    pub skipped: Vec<SkippedHook>,
+    /// Files hooks that run skip for their size
+    pub skipped_files: Vec<SkippedFile>,
+    /// Conflicts that kept read-write hooks out of earlier groups
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Most shared files listed in a conflict's description
+const LISTED_FILES: usize = 5;
+
+/// Why two read-write hooks may touch the same files
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictReason {
+    /// A hook has no `files` pattern, so it may touch any file
+    AnyFile {
+        /// ID of the hook without a pattern
+        hook_id: String,
+    },
+    /// The hooks have the same `files` pattern
+    SamePattern {
+        /// The pattern
+        pattern: String,
+    },
+}
+
+/// Two read-write hooks that can't run at the same time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// ID of the hook that waits
+    pub hook_id: String,
+    /// ID of the earlier hook it waits for
+    pub other: String,
+    /// Why the hooks may touch the same files
+    pub reason: ConflictReason,
+    /// Files both hooks run on
+    pub files: Vec<PathBuf>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} waits for {}: ", self.hook_id, self.other)?;
+        match &self.reason {
+            ConflictReason::AnyFile { hook_id } => write!(f, "{} has no files pattern, so it may touch any file", hook_id)?,
+            ConflictReason::SamePattern { pattern } => write!(f, "both have the files pattern `{}`", pattern)?,
+        }
+        if self.files.is_empty() {
+            return write!(f, ", no shared files in this run");
+        }
+        let listed: Vec<String> = self.files.iter().take(LISTED_FILES).map(|file| file.display().to_string()).collect();
+        write!(f, ", shared files: {}", listed.join(", "))?;
+        if self.files.len() > LISTED_FILES {
+            write!(f, " and {} more", self.files.len() - LISTED_FILES)?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the conflict between a read-write hook and an earlier one, if they may touch the same files
+///
+/// Hooks without a pattern may touch any file. Otherwise, only hooks with
+/// the same pattern conflict: patterns aren't compared for the files they
+/// could both match.
+fn conflict(earlier: &PreparedHook, prepared: &PreparedHook) -> Option<Conflict> {
+    let (_, other, earlier_hook, earlier_files) = earlier;
+    let (_, hook_id, hook, files) = prepared;
+    let reason = if hook.files.is_empty() {
+        ConflictReason::AnyFile { hook_id: hook_id.clone() }
+    } else if earlier_hook.files.is_empty() {
+        ConflictReason::AnyFile { hook_id: other.clone() }
+    } else if hook.files == earlier_hook.files {
+        ConflictReason::SamePattern { pattern: hook.files.clone() }
+    } else {
+        return None;
+    };
+    let earlier_files: HashSet<&PathBuf> = earlier_files.iter().collect();
+    let files = files.iter().filter(|file| earlier_files.contains(file)).cloned().collect();
+    Some(Conflict { hook_id: hook_id.clone(), other: other.clone(), reason, files })
+}
+
+/// How many hooks of a stage run at a time, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageLimit {
+    /// Most hooks running at a time
+    pub hooks: usize,
+    /// Why the limit applies
+    pub reason: &'static str,
+}
+
+impl fmt::Display for StageLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.hooks == 1 { "" } else { "s" };
+        write!(f, "up to {} hook{} at a time ({})", self.hooks, plural, self.reason)
+    }
+}
+
+/// Hash what a hook runs: its repo, every setting changing its invocation, and its files
+///
+/// The hook's name doesn't change what runs, and neither do its `files`
+/// pattern and stages once its files are matched. Neither does its ID,
+/// unless its tool runs it by its ID, see [`runs_by_id`]. The settings deciding what
+/// happens with its outcome, like `fail_fast`, are merged into the hook that
+/// runs instead, see [`dedupe`].
+fn invocation_key(repo: &str, hook: &Hook, files: &[PathBuf]) -> String {
+    let invocation = Hook {
+        id: if runs_by_id(hook) { hook.id.clone() } else { String::new() },
+        name: String::new(),
+        description: None,
+        files: String::new(),
+        stages: Vec::new(),
+        policy: None,
+        fail_fast: false,
+        continue_on_error: false,
+        require_clean: false,
+        access_mode: AccessMode::Read,
+        ..hook.clone()
+    };
+    // Maps of JSON values are sorted, so the env hashes the same whatever its order
+    let invocation = serde_json::to_value(&invocation).map(|value| value.to_string()).unwrap_or_default();
+    let mut files: Vec<_> = files.iter().map(|file| file.to_str().unwrap_or_default()).collect();
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for part in [repo, invocation.as_str()].into_iter().chain(files) {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Whether a hook's tool runs it by its ID
+///
+/// Python hooks from `pre-commit-hooks` run `pre_commit_hooks.<id>`, and
+/// Node and Ruby hooks run the executable named after their ID. Hooks
+/// running their entry as a command don't depend on it.
+fn runs_by_id(hook: &Hook) -> bool {
+    matches!(hook.language.as_str(), "python" | "node" | "javascript" | "typescript" | "ruby")
+        && !HookContext::from_hook(hook, PathBuf::new(), Vec::new()).should_run_in_separate_process()
+}
+
+/// Drop hooks that repeat the invocation of an earlier hook
+///
+/// This happens in workspaces where a root hook and a project hook run the
+/// same tool on the same files. The hook that stays is as strict as the
+/// hooks it replaces.
+fn dedupe(hooks: Vec<PreparedHook>) -> (Vec<PreparedHook>, Vec<SkippedHook>) {
+    let mut kept: Vec<PreparedHook> = Vec::new();
+    let mut seen = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (repo, hook_id, hook, files) in hooks {
+        let key = invocation_key(&repo, &hook, &files);
+        match seen.get(&key) {
+            Some(&index) => {
+                let (_, first_id, first, _): &mut PreparedHook = &mut kept[index];
+                log::debug!("Hook '{}' runs the same invocation as '{}', running it once", hook_id, first_id);
+                first.fail_fast |= hook.fail_fast;
+                first.continue_on_error &= hook.continue_on_error;
+                first.require_clean |= hook.require_clean;
+                if hook.access_mode == AccessMode::ReadWrite {
+                    first.access_mode = AccessMode::ReadWrite;
+                }
+                duplicates.push(SkippedHook { repo, hook_id, reason: SkipReason::Duplicate { of: first_id.clone() } });
+            }
+            None => {
+                seen.insert(key, kept.len());
+                kept.push((repo, hook_id, hook, files));
+            }
+        }
+    }
+    (kept, duplicates)
+}
+
+impl ExecutionPlan {
+    /// Build a plan: all read-only hooks first, then groups of non-overlapping read-write hooks
+    ///
+    /// Hooks repeating the invocation of an earlier hook are skipped.
+    pub fn build(hooks: Vec<PreparedHook>, parallelism: usize) -> Self {
+        let (hooks, duplicates) = dedupe(hooks);
+        let (read_hooks, write_hooks): (Vec<_>, Vec<_>) =
+            hooks.into_iter().partition(|prepared| prepared.2.access_mode == AccessMode::Read);
+
+        let mut stages = Vec::new();
+        if !read_hooks.is_empty() {
+            let ids: Vec<&str> = read_hooks.iter().map(|prepared| prepared.1.as_str()).collect();
+            log::debug!("Read-only hooks run together: {}", ids.join(", "));
+            stages.push(PlanStage { access_mode: AccessMode::Read, hooks: read_hooks });
+        }
+
+        // Put each read-write hook in the first group it doesn't conflict with
+        let mut write_groups: Vec<Vec<PreparedHook>> = Vec::new();
+        let mut conflicts = Vec::new();
+        for prepared in write_hooks {
+            let mut placed = None;
+            for (index, group) in write_groups.iter().enumerate() {
+                let found: Vec<Conflict> = group.iter().filter_map(|earlier| conflict(earlier, &prepared)).collect();
+                if found.is_empty() {
+                    placed = Some(index);
+                    break;
+                }
+                for conflict in &found {
+                    log::debug!("{}", conflict);
+                }
+                conflicts.extend(found);
+            }
+            let index = match placed {
+                Some(index) => index,
+                None => {
+                    write_groups.push(Vec::new());
+                    write_groups.len() - 1
+                }
+            };
+            log::debug!("Hook '{}' runs in read-write group {}", prepared.1, index + 1);
+            write_groups[index].push(prepared);
+        }
+        stages.extend(write_groups.into_iter().map(|hooks| PlanStage { access_mode: AccessMode::ReadWrite, hooks }));
+
+        ExecutionPlan { stages, parallelism, skipped: duplicates, skipped_files: Vec::new(), conflicts }
+    }
+
+    /// Get how many hooks of a stage run at a time
+    ///
+    /// `audited` is whether the stage's hooks are audited for writes, which
+    /// runs them one at a time.
+    pub fn stage_limit(&self, index: usize, audited: bool) -> StageLimit {
+        if audited {
+            return StageLimit { hooks: 1, reason: "read-only hooks are audited for writes one at a time" };
+        }
+        match self.parallelism {
+            0 => StageLimit { hooks: self.stages[index].hooks.len().max(1), reason: "parallelism is unlimited" },
+            limit => StageLimit { hooks: limit, reason: "the parallelism limit" },
+        }
+    }
+
+    /// Count the hooks with the given access mode
+    pub fn hook_count(&self, access_mode: AccessMode) -> usize {
+        self.stages
+            .iter()
+            .filter(|stage| stage.access_mode == access_mode)
+            .map(|stage| stage.hooks.len())
+            .sum()
+    }
+
+    /// Describe a stage for display
+    fn stage_label(&self, index: usize) -> String {
+        let stage = &self.stages[index];
+        let kind = match stage.access_mode {
+            AccessMode::Read => "read-only",
+            AccessMode::ReadWrite => "read-write",
+        };
+        let plural = if stage.hooks.len() == 1 { "" } else { "s" };
+        format!("Stage {}: {} ({} hook{})", index + 1, kind, stage.hooks.len(), plural)
+    }
+
+    /// Describe a hook for display
+    fn hook_label(prepared: &PreparedHook) -> String {
+        let (repo, hook_id, _, files) = prepared;
+        format!("{} [{}] - {} files", hook_id, repo, files.len())
+    }
+
+    /// Render the plan as indented text
+    pub fn to_text(&self) -> String {
+        let mut output = match self.parallelism {
+            0 => "Execution plan (parallelism: unlimited)\n".to_string(),
+            n => format!("Execution plan (parallelism: {})\n", n),
+        };
+        if self.stages.is_empty() {
+            output.push_str("No hooks to run\n");
+        }
+        for (index, stage) in self.stages.iter().enumerate() {
+            output.push_str(&format!("{}\n", self.stage_label(index)));
+            for prepared in &stage.hooks {
+                output.push_str(&format!("  - {}\n", Self::hook_label(prepared)));
+                if let Some(description) = &prepared.2.description {
+                    output.push_str(&format!("    {}\n", description));
+                }
+            }
+        }
+        if !self.conflicts.is_empty() {
+            output.push_str("Conflicts\n");
+            for conflict in &self.conflicts {
+                output.push_str(&format!("  - {}\n", conflict));
+            }
+        }
+        if !self.stages.is_empty() {
+            output.push_str("Concurrency\n");
+            for index in 0..self.stages.len() {
+                output.push_str(&format!("  - Stage {}: {}\n", index + 1, self.stage_limit(index, false)));
+            }
+        }
+        if !self.skipped.is_empty() {
+            output.push_str("Skipped\n");
+            for skipped in &self.skipped {
+                output.push_str(&format!("  - {} [{}] - {}\n", skipped.hook_id, skipped.repo, skipped.reason));
+            }
+        }
+        if !self.skipped_files.is_empty() {
+            output.push_str("Skipped files\n");
+            for skipped in &self.skipped_files {
+                output.push_str(&format!("  - {}\n", skipped));
+            }
+        }
+        output
+    }
+
+    /// Render the plan as a Graphviz DOT digraph
+    ///
+    /// Each stage is a cluster, and an edge between clusters means the later
+    /// stage waits for the earlier one.
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph plan {\n  rankdir=LR;\n  compound=true;\n  node [shape=box];\n");
+        let mut first_nodes = Vec::new();
+        let mut node = 0;
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            output.push_str(&format!("  subgraph cluster_{} {{\n    label=\"{}\";\n", index, escape(&self.stage_label(index))));
+            first_nodes.push(node);
+            for prepared in &stage.hooks {
+                output.push_str(&format!("    n{} [label=\"{}\"];\n", node, escape(&Self::hook_label(prepared))));
+                node += 1;
+            }
+            output.push_str("  }\n");
+        }
+
+        for index in 1..first_nodes.len() {
+            output.push_str(&format!(
+                "  n{} -> n{} [ltail=cluster_{}, lhead=cluster_{}];\n",
+                first_nodes[index - 1],
+                first_nodes[index],
+                index - 1,
+                index
+            ));
+        }
+
+        output.push_str("}\n");
+        output
+    }
+
+    /// Render the plan as a Mermaid flowchart
+    pub fn to_mermaid(&self) -> String {
+        let mut output = String::from("flowchart LR\n");
+        let mut node = 0;
+
+        for (index, stage) in self.stages.iter().enumerate() {
+            output.push_str(&format!("  subgraph stage{}[\"{}\"]\n", index + 1, escape(&self.stage_label(index))));
+            for prepared in &stage.hooks {
+                output.push_str(&format!("    n{}[\"{}\"]\n", node, escape(&Self::hook_label(prepared))));
+                node += 1;
+            }
+            output.push_str("  end\n");
+        }
+
+        for index in 1..self.stages.len() {
+            output.push_str(&format!("  stage{} --> stage{}\n", index, index + 1));
+        }
+
+        output
+    }
+}
+
+/// Escape double quotes in a graph label
+fn escape(label: &str) -> String {
+    label.replace('"', "'")
+}
@@ -0,0 +1,117 @@
+//! Progress events for RustyHook
+//!
+//! A run reports its progress as [`ProgressEvent`]s: when hooks start, when
+//! their environments are set up and how each hook finished. Callers
+//! subscribe to the [`Progress`] of a `ParallelExecutor` with a callback or a
+//! channel. The CLI's terminal output is itself a subscriber,
+//! [`print_progress`], so every consumer sees the same events.
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+
+use crate::config::parser::AccessMode;
+use super::report::{EnvironmentSetup, HookResult};
+
+/// Something that happened during a run
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// The hooks of an access mode start, read-only hooks first
+    PhaseStarted {
+        /// Access mode of the hooks
+        access_mode: AccessMode,
+        /// Number of hooks with the access mode
+        hooks: usize,
+    },
+    /// A group of non-overlapping read-write hooks starts
+    GroupStarted {
+        /// Number of the group, from 1
+        group: usize,
+        /// Number of hooks in the group
+        hooks: usize,
+    },
+    /// A hook starts
+    HookStarted {
+        /// Repository of the hook
+        repo: String,
+        /// ID of the hook
+        hook_id: String,
+        /// Number of files the hook runs on
+        file_count: usize,
+    },
+    /// The environment of a hook's tool is being set up or checked
+    SetupStarted {
+        /// ID of the hook that needs the environment
+        hook_id: String,
+        /// Key of the tool the environment is for
+        tool: String,
+    },
+    /// The environment of a hook's tool is ready
+    SetupFinished(EnvironmentSetup),
+    /// A hook finished
+    HookFinished(Box<HookResult>),
+    /// Every hook that was started finished
+    RunFinished {
+        /// Whether the run succeeded
+        success: bool,
+    },
+}
+
+/// A function called with every progress event
+type Listener = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// Where the progress events of a run go
+///
+/// Clones share their subscribers.
+#[derive(Clone, Default)]
+pub struct Progress {
+    /// Subscribers, called in the order they subscribed
+    listeners: Arc<Mutex<Vec<Listener>>>,
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let count = self.listeners.lock().map_or(0, |listeners| listeners.len());
+        f.debug_struct("Progress").field("listeners", &count).finish()
+    }
+}
+
+impl Progress {
+    /// Call a function with every later event
+    ///
+    /// Events of parallel hooks are emitted from their threads, so the
+    /// function should return quickly.
+    pub fn subscribe(&self, listener: impl Fn(&ProgressEvent) + Send + Sync + 'static) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(Box::new(listener));
+        }
+    }
+
+    /// Receive every later event on a channel
+    pub fn channel(&self) -> Receiver<ProgressEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribe(move |event| {
+            // A dropped receiver just stops listening
+            let _ = sender.send(event.clone());
+        });
+        receiver
+    }
+
+    /// Send an event to every subscriber
+    pub fn emit(&self, event: ProgressEvent) {
+        if let Ok(listeners) = self.listeners.lock() {
+            for listener in listeners.iter() {
+                listener(&event);
+            }
+        }
+    }
+}
+
+/// Print the progress of a run to the terminal
+pub fn print_progress(event: &ProgressEvent) {
+    match event {
+        ProgressEvent::PhaseStarted { access_mode: AccessMode::Read, hooks } => println!("Running {} read-only hooks", hooks),
+        ProgressEvent::PhaseStarted { access_mode: AccessMode::ReadWrite, hooks } => println!("Running {} read-write hooks", hooks),
+        ProgressEvent::GroupStarted { group, hooks } => println!("Running group {} of {} non-overlapping read-write hooks", group, hooks),
+        _ => {}
+    }
+}
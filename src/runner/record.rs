@@ -0,0 +1,263 @@
+//! Recording and replaying runs
+//!
+//! `rustyhook run --record <dir>` writes a bundle with everything a run
+//! depended on: the resolved config, the files it checked (with their content
+//! before any hook ran), the environment and the command and outcome of each
+//! hook. `rustyhook replay <dir>` restores the files into a scratch
+//! repository and runs the same hooks again, so a maintainer can reproduce a
+//! failure from someone else's machine.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::config::parser::command_line;
+use super::attributes::GitAttributes;
+use super::file_matcher::{filter_hook_files, repo_relative_path};
+use super::report::{HookStatus, RunReport, SkippedHook};
+
+/// Name of the bundle's metadata file
+pub const BUNDLE_FILE: &str = "bundle.yaml";
+
+/// Directory of the bundle holding the recorded files
+pub const FILES_DIR: &str = "files";
+
+/// Value replacing secrets in the recorded environment
+pub const REDACTED: &str = "<redacted>";
+
+/// Parts of variable names whose values are never recorded
+const SECRET_NAME_PARTS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "PASSWD", "KEY", "CREDENTIAL", "AUTH"];
+
+/// Variables that describe the recording machine, which replays keep their own value of
+pub const MACHINE_ENV: &[&str] = &["PATH", "HOME", "PWD", "OLDPWD", "TMPDIR", "USER", "LOGNAME", "SHELL"];
+
+/// Error type for recording operations
+#[derive(Debug)]
+pub enum RecordError {
+    /// Error reading or writing the bundle
+    IoError(std::io::Error),
+    /// Error parsing or serializing the bundle
+    YamlError(serde_yaml::Error),
+    /// Error creating the replay repository
+    GitError(git2::Error),
+}
+
+impl From<std::io::Error> for RecordError {
+    fn from(err: std::io::Error) -> Self {
+        RecordError::IoError(err)
+    }
+}
+
+impl From<serde_yaml::Error> for RecordError {
+    fn from(err: serde_yaml::Error) -> Self {
+        RecordError::YamlError(err)
+    }
+}
+
+impl From<git2::Error> for RecordError {
+    fn from(err: git2::Error) -> Self {
+        RecordError::GitError(err)
+    }
+}
+
+/// A hook of a recorded run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedHook {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub hook_id: String,
+    /// Command line the hook ran, with file paths relative to the repository
+    ///
+    /// The commands of an entry with several are joined with `&&`.
+    pub command: Vec<String>,
+    /// Outcome of the hook
+    pub status: HookStatus,
+    /// How long the hook took, in milliseconds
+    pub duration_ms: u64,
+    /// Error output of a failed hook
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Everything a run depended on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    /// Version of RustyHook that recorded the run
+    pub rustyhook_version: String,
+    /// The config the run used, after merging and matrix expansion
+    pub config: Config,
+    /// Files the run checked, relative to the repository root
+    pub files: Vec<PathBuf>,
+    /// Environment of the run, with secrets redacted
+    pub env: BTreeMap<String, String>,
+    /// Each hook that ran
+    pub hooks: Vec<RecordedHook>,
+    /// Each configured hook that didn't run, and why
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedHook>,
+}
+
+/// Check whether a variable's value must not be recorded
+fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// Get the environment of the current process, with secrets redacted
+pub fn recorded_env() -> BTreeMap<String, String> {
+    std::env::vars()
+        .map(|(name, value)| {
+            let value = if is_secret(&name) { REDACTED.to_string() } else { value };
+            (name, value)
+        })
+        .collect()
+}
+
+/// Copy the files of a run into a bundle directory, before any hook changes them
+///
+/// Returns the paths of the files relative to `root`. Files that can't be
+/// read, such as deletions, are listed but not copied.
+pub fn snapshot_files(bundle_dir: &Path, root: &Path, files: &[PathBuf]) -> Result<Vec<PathBuf>, RecordError> {
+    let files_dir = bundle_dir.join(FILES_DIR);
+    fs::create_dir_all(&files_dir)?;
+
+    let mut relative_files = Vec::new();
+    for file in files {
+        let relative = PathBuf::from(repo_relative_path(&root.join(file), root));
+        if relative.is_absolute() {
+            log::warn!("Not recording {}: it is outside the repository", file.display());
+            continue;
+        }
+        if let Ok(content) = fs::read(root.join(file)) {
+            let target = files_dir.join(&relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(target, content)?;
+        }
+        relative_files.push(relative);
+    }
+    Ok(relative_files)
+}
+
+impl Bundle {
+    /// Describe a finished run on `files`, relative to `root`
+    pub fn new(config: Config, root: &Path, files: Vec<PathBuf>, report: &RunReport) -> Self {
+        let attributes = GitAttributes::discover(root);
+        let absolute_files: Vec<PathBuf> = files.iter().map(|file| root.join(file)).collect();
+        let hooks = report
+            .hooks
+            .iter()
+            .map(|result| {
+                let hook = config
+                    .repos
+                    .iter()
+                    .filter(|repo| repo.repo == result.repo)
+                    .flat_map(|repo| &repo.hooks)
+                    .find(|hook| hook.id == result.hook_id);
+                let mut command = Vec::new();
+                if let Some(hook) = hook {
+                    // The commands of an entry with several are joined with `&&`
+                    let hook_files = filter_hook_files(hook, &absolute_files, &attributes, !config.no_default_excludes).unwrap_or_default();
+                    let hook_files: Vec<String> = hook_files.iter().map(|file| repo_relative_path(file, root)).collect();
+                    let commands = hook.commands();
+                    for (index, entry) in commands.iter().enumerate() {
+                        if index > 0 {
+                            command.push("&&".to_string());
+                        }
+                        command.extend(command_line(entry, index + 1 == commands.len(), &hook.args, &hook_files, &hook.args_after_files));
+                    }
+                }
+                RecordedHook {
+                    repo: result.repo.clone(),
+                    hook_id: result.hook_id.clone(),
+                    command,
+                    status: result.status.clone(),
+                    duration_ms: result.duration.as_millis() as u64,
+                    message: result.message.clone(),
+                }
+            })
+            .collect();
+
+        Bundle {
+            rustyhook_version: env!("CARGO_PKG_VERSION").to_string(),
+            config,
+            files,
+            env: recorded_env(),
+            hooks,
+            skipped: report.skipped.clone(),
+        }
+    }
+
+    /// Write the bundle's metadata next to its recorded files
+    pub fn write(&self, bundle_dir: &Path) -> Result<(), RecordError> {
+        fs::create_dir_all(bundle_dir)?;
+        fs::write(bundle_dir.join(BUNDLE_FILE), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Read a bundle
+    pub fn read(bundle_dir: &Path) -> Result<Self, RecordError> {
+        let content = fs::read_to_string(bundle_dir.join(BUNDLE_FILE))?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    /// Restore the recorded files into a new Git repository at `target`, staged
+    ///
+    /// Returns the absolute paths of the restored files.
+    pub fn restore(&self, bundle_dir: &Path, target: &Path) -> Result<Vec<PathBuf>, RecordError> {
+        let repo = git2::Repository::init(target)?;
+        let mut index = repo.index()?;
+
+        let mut restored = Vec::new();
+        for file in &self.files {
+            // Never write outside the target, whatever the bundle says
+            if file.is_absolute() || file.components().any(|component| component == std::path::Component::ParentDir) {
+                log::warn!("Not restoring {}: it is outside the repository", file.display());
+                continue;
+            }
+            let source = bundle_dir.join(FILES_DIR).join(file);
+            if !source.exists() {
+                continue;
+            }
+            let destination = target.join(file);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&source, &destination)?;
+            index.add_path(file)?;
+            restored.push(destination);
+        }
+        index.write()?;
+        Ok(restored)
+    }
+
+    /// Get the recorded variables a replay should set
+    pub fn replay_env(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.env
+            .iter()
+            .filter(|(name, value)| *value != REDACTED && !MACHINE_ENV.contains(&name.as_str()))
+    }
+
+    /// Compare a replay with the recording
+    ///
+    /// Returns a description of each hook whose outcome differs.
+    pub fn differences(&self, replay: &RunReport) -> Vec<String> {
+        let mut differences = Vec::new();
+        for recorded in &self.hooks {
+            match replay.result(&recorded.hook_id) {
+                Some(result) if result.status == recorded.status => {}
+                Some(result) => differences.push(format!(
+                    "{}: recorded {:?}, replayed {:?}",
+                    recorded.hook_id, recorded.status, result.status
+                )),
+                None => differences.push(format!("{}: recorded {:?}, did not run in the replay", recorded.hook_id, recorded.status)),
+            }
+        }
+        differences
+    }
+}
@@ -0,0 +1,333 @@
+//! Run reports for RustyHook
+//!
+//! This module records the outcome of every hook of a run, so callers can
+//! inspect results instead of parsing output.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error_codes::ErrorInfo;
+use crate::hooks::Diagnostic;
+
+/// Outcome of a hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookStatus {
+    /// The hook succeeded
+    Passed,
+    /// The hook failed
+    Failed,
+    /// The hook failed, but has `continue_on_error` set
+    AllowedFailure,
+}
+
+impl fmt::Display for HookStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookStatus::Passed => write!(f, "passed"),
+            HookStatus::Failed => write!(f, "failed"),
+            HookStatus::AllowedFailure => write!(f, "allowed failure"),
+        }
+    }
+}
+
+/// Why a configured hook didn't run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", tag = "reason")]
+pub enum SkipReason {
+    /// None of the files matched the hook's `files` pattern and attributes
+    NoMatchingFiles,
+    /// The hook doesn't run in the stage of the run
+    StageMismatch {
+        /// Stage of the run
+        stage: String,
+    },
+    /// The hook is listed in `--skip` or `RUSTYHOOK_SKIP`
+    SkipList,
+    /// The run was limited to another hook
+    NotSelected,
+    /// Another hook runs the same tool with the same arguments on the same files
+    Duplicate {
+        /// ID of the hook that runs instead
+        of: String,
+    },
+    /// Every matching file is over the hook's `max_file_size`
+    FilesTooLarge,
+    /// A hook failed with `fail_fast` before this one finished
+    Cancelled {
+        /// ID of the hook whose failure stopped the run
+        by: String,
+    },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NoMatchingFiles => write!(f, "no files matched"),
+            SkipReason::StageMismatch { stage } => write!(f, "does not run in the {} stage", stage),
+            SkipReason::SkipList => write!(f, "skipped by --skip or RUSTYHOOK_SKIP"),
+            SkipReason::NotSelected => write!(f, "not the selected hook"),
+            SkipReason::Duplicate { of } => write!(f, "same invocation as {}", of),
+            SkipReason::FilesTooLarge => write!(f, "every matching file is over max_file_size"),
+            SkipReason::Cancelled { by } => write!(f, "cancelled after {} failed", by),
+        }
+    }
+}
+
+/// A configured hook that didn't run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedHook {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub hook_id: String,
+    /// Why the hook didn't run
+    #[serde(flatten)]
+    pub reason: SkipReason,
+}
+
+/// A file a hook didn't run on because it is over the hook's `max_file_size`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedFile {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub hook_id: String,
+    /// Path of the file
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+    /// The hook's `max_file_size`
+    pub limit: u64,
+}
+
+impl fmt::Display for SkippedFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} skipped {}: {} bytes is over max_file_size of {} bytes", self.hook_id, self.path.display(), self.size, self.limit)
+    }
+}
+
+/// Result of a single hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookResult {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub hook_id: String,
+    /// Outcome of the hook
+    pub status: HookStatus,
+    /// How long the hook took
+    pub duration: Duration,
+    /// Number of files passed to the hook
+    pub file_count: usize,
+    /// Files the hook modified
+    pub files_modified: Vec<PathBuf>,
+    /// Error message of a failed hook
+    pub message: Option<String>,
+    /// What went wrong in a failed hook, without advice on fixing it
+    pub details: Option<String>,
+    /// Code and details of the error of a failed hook
+    pub error: Option<ErrorInfo>,
+    /// Problems the hook's output parser found in its output
+    pub diagnostics: Vec<Diagnostic>,
+    /// Key of the hook's tool, naming the environment it runs in
+    pub tool: String,
+    /// ID of the `fail_fast` hook whose failure stopped the run while this hook was running
+    pub finished_after: Option<String>,
+}
+
+/// A hook declared `access_mode: Read` whose files changed while it ran
+///
+/// Read-only hooks run at the same time, so when others ran on the changed
+/// files too, any of them may have written them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessModeViolation {
+    /// ID of the repository the hook comes from
+    pub repo: String,
+    /// ID of the hook
+    pub hook_id: String,
+    /// Files that changed
+    pub files: Vec<PathBuf>,
+    /// IDs of the other hooks that ran on the changed files at the same time
+    pub shared_with: Vec<String>,
+}
+
+impl fmt::Display for AccessModeViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let files: Vec<String> = self.files.iter().map(|file| file.display().to_string()).collect();
+        if !self.shared_with.is_empty() {
+            return write!(
+                f,
+                "{} changed while hook '{}', declared access_mode: Read, ran alongside {} on them; one of them modified it, declare that one ReadWrite so it doesn't run alongside other hooks",
+                files.join(", "),
+                self.hook_id,
+                self.shared_with.iter().map(|id| format!("'{}'", id)).collect::<Vec<_>>().join(", ")
+            );
+        }
+        write!(
+            f,
+            "Hook '{}' is declared access_mode: Read but modified {}; declare it ReadWrite so it doesn't run alongside other hooks",
+            self.hook_id,
+            files.join(", ")
+        )
+    }
+}
+
+/// Setup of a hook's environment during a run
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentSetup {
+    /// ID of the first hook that needed the environment
+    pub hook_id: String,
+    /// Key of the tool the environment is for
+    pub tool: String,
+    /// Whether an environment set up by an earlier run was reused
+    pub reused: bool,
+    /// How long the setup took
+    pub duration: Duration,
+}
+
+/// Whether a hook's environment was ready before the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheState {
+    /// The environment was set up by an earlier run
+    Warm,
+    /// The environment was set up by this run
+    Cold,
+}
+
+impl fmt::Display for CacheState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheState::Warm => write!(f, "warm"),
+            CacheState::Cold => write!(f, "cold"),
+        }
+    }
+}
+
+/// How the time of a hook splits between setting up its environment and running it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookTiming {
+    /// ID of the hook
+    pub hook_id: String,
+    /// How long setting up the hook's environment took, for the first hook needing it
+    pub setup: Duration,
+    /// How long the hook itself ran
+    pub exec: Duration,
+    /// Whether the hook's environment was ready before the run, for hooks with one
+    pub cache: Option<CacheState>,
+}
+
+/// Results of the hooks of a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunReport {
+    /// Result of each hook that ran
+    pub hooks: Vec<HookResult>,
+    /// Each configured hook that didn't run
+    pub skipped: Vec<SkippedHook>,
+    /// Each file a hook that ran skipped for its size
+    pub skipped_files: Vec<SkippedFile>,
+    /// Each environment the hooks that ran were set up in
+    pub setups: Vec<EnvironmentSetup>,
+    /// Each read-only hook that modified files, which makes the run's results unreliable
+    pub access_mode_violations: Vec<AccessModeViolation>,
+}
+
+impl RunReport {
+    /// Get the result of a hook
+    pub fn result(&self, hook_id: &str) -> Option<&HookResult> {
+        self.hooks.iter().find(|result| result.hook_id == hook_id)
+    }
+
+    /// Get why a hook didn't run, if it was skipped
+    pub fn skip_reason(&self, hook_id: &str) -> Option<&SkipReason> {
+        self.skipped.iter().find(|skipped| skipped.hook_id == hook_id).map(|skipped| &skipped.reason)
+    }
+
+    /// Get the results with the given status
+    pub fn with_status(&self, status: HookStatus) -> impl Iterator<Item = &HookResult> {
+        self.hooks.iter().filter(move |result| result.status == status)
+    }
+
+    /// Check whether no hook failed the run
+    pub fn is_success(&self) -> bool {
+        self.with_status(HookStatus::Failed).next().is_none()
+    }
+
+    /// Get how the time of a hook splits between setup and execution
+    ///
+    /// Hooks sharing an environment share its cache state, but only the
+    /// first hook needing it is charged its setup.
+    pub fn timing(&self, result: &HookResult) -> HookTiming {
+        let setup = self.setups.iter().find(|setup| setup.tool == result.tool);
+        HookTiming {
+            hook_id: result.hook_id.clone(),
+            setup: setup.filter(|setup| setup.hook_id == result.hook_id).map_or(Duration::ZERO, |setup| setup.duration),
+            exec: result.duration,
+            cache: setup.map(|setup| if setup.reused { CacheState::Warm } else { CacheState::Cold }),
+        }
+    }
+
+    /// Format the timing of each hook as a table with setup and execution columns
+    pub fn format_timings(&self) -> String {
+        let timings: Vec<HookTiming> = self.hooks.iter().map(|result| self.timing(result)).collect();
+        let width = timings.iter().map(|timing| timing.hook_id.len()).chain(["Hook".len()]).max().unwrap_or_default();
+        let seconds = |duration: Duration| format!("{:.2}s", duration.as_secs_f64());
+        let mut output = format!("{:<width$}  {:>8}  {:>8}  Cache\n", "Hook", "Setup", "Exec");
+        for timing in &timings {
+            let setup = if timing.setup.is_zero() { "-".to_string() } else { seconds(timing.setup) };
+            let cache = timing.cache.map_or("-".to_string(), |cache| cache.to_string());
+            output.push_str(&format!("{:<width$}  {:>8}  {:>8}  {}\n", timing.hook_id, setup, seconds(timing.exec), cache));
+        }
+        let setup: Duration = timings.iter().map(|timing| timing.setup).sum();
+        let exec: Duration = timings.iter().map(|timing| timing.exec).sum();
+        output.push_str(&format!("{:<width$}  {:>8}  {:>8}\n", "Total", seconds(setup), seconds(exec)));
+        output
+    }
+
+    /// Format the outcome of each hook as a table, then the errors of the hooks that failed
+    pub fn format_summary(&self) -> String {
+        let width = self.hooks.iter().map(|result| result.hook_id.len()).chain(["Hook".len()]).max().unwrap_or_default();
+        let mut output = format!("{:<width$}  {:<15}  {:>8}  {:>8}\n", "Hook", "Status", "Duration", "Modified");
+        for result in &self.hooks {
+            output.push_str(&format!(
+                "{:<width$}  {:<15}  {:>8}  {:>8}\n",
+                result.hook_id,
+                result.status.to_string(),
+                format!("{:.2}s", result.duration.as_secs_f64()),
+                result.files_modified.len()
+            ));
+        }
+        let count = |status: HookStatus| self.with_status(status).count();
+        output.push_str(&format!(
+            "{} hooks: {} passed, {} failed, {} allowed failures, {} skipped\n",
+            self.hooks.len(),
+            count(HookStatus::Passed),
+            count(HookStatus::Failed),
+            count(HookStatus::AllowedFailure),
+            self.skipped.len()
+        ));
+        let cancelled: Vec<&SkippedHook> = self.skipped.iter().filter(|skipped| matches!(skipped.reason, SkipReason::Cancelled { .. })).collect();
+        if let Some(SkipReason::Cancelled { by }) = cancelled.first().map(|skipped| &skipped.reason) {
+            let hooks: Vec<&str> = cancelled.iter().map(|skipped| skipped.hook_id.as_str()).collect();
+            output.push_str(&format!("Cancelled after {} failed: {}\n", by, hooks.join(", ")));
+        }
+        let finished: Vec<&HookResult> = self.hooks.iter().filter(|result| result.finished_after.is_some()).collect();
+        if let Some(by) = finished.first().and_then(|result| result.finished_after.as_ref()) {
+            let hooks: Vec<&str> = finished.iter().map(|result| result.hook_id.as_str()).collect();
+            output.push_str(&format!("Finished after {} failed: {}\n", by, hooks.join(", ")));
+        }
+        for result in self.hooks.iter().filter(|result| result.status != HookStatus::Passed) {
+            if let Some(details) = &result.details {
+                output.push_str(&format!("\n{} ({}):\n", result.hook_id, result.status));
+                for line in details.lines() {
+                    output.push_str(&if line.is_empty() { "\n".to_string() } else { format!("  {}\n", line) });
+                }
+            }
+        }
+        output
+    }
+}
@@ -0,0 +1,110 @@
+//! Runs against a revision
+//!
+//! `rustyhook run --rev <rev>` checks the commit out into a temporary
+//! worktree (`git worktree add --detach`) and runs the hooks there, so a
+//! commit can be audited or bisected without touching the user's checkout.
+//! The worktree is removed again when it is dropped.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tempfile::TempDir;
+
+/// Error type for revision worktrees
+#[derive(Debug)]
+pub enum RevisionError {
+    /// A git command failed
+    GitError(String),
+    /// IO error
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for RevisionError {
+    fn from(err: std::io::Error) -> Self {
+        RevisionError::IoError(err)
+    }
+}
+
+impl fmt::Display for RevisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RevisionError::GitError(msg) => write!(f, "{}", msg),
+            RevisionError::IoError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RevisionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RevisionError::IoError(err) => Some(err),
+            RevisionError::GitError(_) => None,
+        }
+    }
+}
+
+/// Run a git command in a directory, returning its trimmed output
+fn git(dir: &Path, args: &[&str]) -> Result<String, RevisionError> {
+    let output = Command::new("git").current_dir(dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(RevisionError::GitError(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A commit checked out into a temporary worktree
+#[derive(Debug)]
+pub struct RevisionWorktree {
+    /// Root of the repository the worktree belongs to
+    repo_root: PathBuf,
+    /// SHA of the checked out commit
+    commit: String,
+    /// Directory of the worktree
+    path: PathBuf,
+    /// Temporary directory holding the worktree
+    _dir: TempDir,
+}
+
+impl RevisionWorktree {
+    /// Check a revision of the repository containing `dir` out into a temporary worktree
+    pub fn create(dir: &Path, rev: &str) -> Result<Self, RevisionError> {
+        let repo_root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?);
+        let commit = git(&repo_root, &["rev-parse", "--verify", "--quiet", &format!("{}^{{commit}}", rev)])
+            .map_err(|_| RevisionError::GitError(format!("'{}' is not a commit of {}", rev, repo_root.display())))?;
+
+        let dir = tempfile::Builder::new().prefix("rustyhook-rev-").tempdir()?;
+        let path = dir.path().join("tree");
+        let target = path.to_string_lossy().into_owned();
+        git(&repo_root, &["worktree", "add", "--detach", "--quiet", &target, &commit])?;
+        log::debug!("Checked out {} ({}) into {}", rev, commit, path.display());
+
+        Ok(RevisionWorktree { repo_root, commit, path, _dir: dir })
+    }
+
+    /// Get the SHA of the checked out commit
+    pub fn commit(&self) -> &str {
+        &self.commit
+    }
+
+    /// Get the directory of the worktree
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for RevisionWorktree {
+    fn drop(&mut self) {
+        let target = self.path.to_string_lossy().into_owned();
+        if let Err(e) = git(&self.repo_root, &["worktree", "remove", "--force", &target]) {
+            log::warn!("Could not remove the worktree at {}: {}", self.path.display(), e);
+            // Forget the worktree once its directory is deleted with the temporary directory
+            let _ = std::fs::remove_dir_all(&self.path);
+            let _ = git(&self.repo_root, &["worktree", "prune"]);
+        }
+    }
+}
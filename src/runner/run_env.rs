@@ -0,0 +1,108 @@
+//! Variables describing the run to hook processes
+//!
+//! Every hook gets these in its environment, so hook scripts can adapt to
+//! the run, like disabling interactive prompts in CI, without configuring
+//! each hook:
+//!
+//! - `RH_STAGE`: the stage of the run, `pre-commit` unless `--hook-stage` is given
+//! - `RH_RUN_ID`: an ID shared by the hooks of a run, and unique to it
+//! - `RH_REPO_ROOT`: the root of the repository
+//! - `RH_PROJECT_DIR`: the directory the hook runs in, which is its `working_dir`
+//! - `RH_IS_CI`: `1` when running in CI, `0` otherwise
+//! - `RH_COLOR`: `1` when RustyHook's output is colored, `0` otherwise
+//!
+//! Hooks of `commit-msg`, `prepare-commit-msg` and `pre-push` also get what
+//! Git passed to the stage (see [`stage_input`](super::stage_input)).
+//!
+//! A hook's own `env` takes precedence over them.
+
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::Hook;
+use super::notify::is_ci;
+
+/// Environment variable holding the stage of the run
+pub const STAGE_ENV: &str = "RH_STAGE";
+
+/// Environment variable holding the ID of the run
+pub const RUN_ID_ENV: &str = "RH_RUN_ID";
+
+/// Environment variable holding the root of the repository
+pub const REPO_ROOT_ENV: &str = "RH_REPO_ROOT";
+
+/// Environment variable holding the directory the hook runs in
+pub const PROJECT_DIR_ENV: &str = "RH_PROJECT_DIR";
+
+/// Environment variable telling whether the run is in CI
+pub const IS_CI_ENV: &str = "RH_IS_CI";
+
+/// Environment variable telling whether output is colored
+pub const COLOR_ENV: &str = "RH_COLOR";
+
+/// Stage of runs that don't select one
+pub const DEFAULT_STAGE: &str = "pre-commit";
+
+/// What hooks are told about the run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunEnv {
+    /// Stage of the run
+    pub stage: String,
+    /// ID of the run
+    pub run_id: String,
+    /// Root of the repository, or the directory of the run outside one
+    pub repo_root: PathBuf,
+    /// Directory the run started in, which hooks' `working_dir` is relative to
+    pub run_dir: PathBuf,
+    /// Whether the run is in CI
+    pub is_ci: bool,
+    /// Whether output is colored
+    pub color: bool,
+    /// Variables exposing what Git passed to the stage
+    pub stage_input: Vec<(String, String)>,
+}
+
+impl RunEnv {
+    /// Describe a run starting in `dir`
+    pub fn detect(dir: &Path) -> Self {
+        let repo_root = git2::Repository::discover(dir)
+            .ok()
+            .and_then(|repo| repo.workdir().map(Path::to_path_buf))
+            .unwrap_or_else(|| dir.to_path_buf());
+        RunEnv {
+            stage: DEFAULT_STAGE.to_string(),
+            run_id: new_run_id(),
+            repo_root,
+            run_dir: dir.to_path_buf(),
+            is_ci: is_ci(),
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            stage_input: Vec::new(),
+        }
+    }
+
+    /// Get the variables of a hook
+    pub fn vars(&self, hook: &Hook) -> Vec<(String, String)> {
+        let project_dir = match &hook.working_dir {
+            Some(dir) => self.run_dir.join(dir),
+            None => self.run_dir.clone(),
+        };
+        let flag = |value: bool| if value { "1" } else { "0" }.to_string();
+        let mut vars = vec![
+            (STAGE_ENV.to_string(), self.stage.clone()),
+            (RUN_ID_ENV.to_string(), self.run_id.clone()),
+            (REPO_ROOT_ENV.to_string(), self.repo_root.display().to_string()),
+            (PROJECT_DIR_ENV.to_string(), project_dir.display().to_string()),
+            (IS_CI_ENV.to_string(), flag(self.is_ci)),
+            (COLOR_ENV.to_string(), flag(self.color)),
+        ];
+        vars.extend(self.stage_input.iter().cloned());
+        vars
+    }
+}
+
+/// Create an ID for a run from the time and the process ID
+fn new_run_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_nanos()).unwrap_or_default();
+    format!("{:x}-{:x}", nanos, std::process::id())
+}
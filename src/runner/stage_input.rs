@@ -0,0 +1,263 @@
+//! Inputs Git hands to hooks of some stages
+//!
+//! Git runs `commit-msg` and `prepare-commit-msg` with the path of the
+//! commit message as the first argument, and `pre-push` with the remote as
+//! arguments and one line per pushed ref on stdin. Installed hook scripts
+//! pass these on to `run`, which exposes them to hooks:
+//!
+//! - `RH_COMMIT_MSG_FILE`: the commit message file, which is also the only
+//!   file `commit-msg` and `prepare-commit-msg` hooks get
+//! - `RH_COMMIT_MSG_SOURCE`: the source of the message for
+//!   `prepare-commit-msg`, like `message` or `merge`, empty when Git gives none
+//! - `RH_COMMIT_OBJECT`: the commit being amended for `prepare-commit-msg`
+//! - `RH_REMOTE_NAME` and `RH_REMOTE_URL`: the remote of a `pre-push`
+//! - `RH_FROM_REF` and `RH_TO_REF`: the commits bounding what a `pre-push`
+//!   sends, whose changed files the hooks get
+//!
+//! Like `pre-commit`, only the first ref of a push that isn't a deletion is
+//! checked.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use git2::{Oid, Repository, Sort};
+
+/// Environment variable holding the commit message file
+pub const COMMIT_MSG_FILE_ENV: &str = "RH_COMMIT_MSG_FILE";
+
+/// Environment variable holding the source of the commit message
+pub const COMMIT_MSG_SOURCE_ENV: &str = "RH_COMMIT_MSG_SOURCE";
+
+/// Environment variable holding the commit being amended
+pub const COMMIT_OBJECT_ENV: &str = "RH_COMMIT_OBJECT";
+
+/// Environment variable holding the name of the remote pushed to
+pub const REMOTE_NAME_ENV: &str = "RH_REMOTE_NAME";
+
+/// Environment variable holding the URL of the remote pushed to
+pub const REMOTE_URL_ENV: &str = "RH_REMOTE_URL";
+
+/// Environment variable holding the commit a push starts from
+pub const FROM_REF_ENV: &str = "RH_FROM_REF";
+
+/// Environment variable holding the commit a push sends
+pub const TO_REF_ENV: &str = "RH_TO_REF";
+
+/// Error type for reading stage inputs
+#[derive(Debug)]
+pub enum StageInputError {
+    /// Git didn't pass the arguments the stage takes
+    MissingArgument {
+        /// The stage of the run
+        stage: String,
+        /// The argument that is missing
+        argument: &'static str,
+    },
+    /// A line of `pre-push` input isn't a pushed ref
+    InvalidRefLine(String),
+    /// Error reading stdin
+    IoError(std::io::Error),
+    /// Error finding the commits of a push
+    GitError(git2::Error),
+}
+
+impl From<std::io::Error> for StageInputError {
+    fn from(err: std::io::Error) -> Self {
+        StageInputError::IoError(err)
+    }
+}
+
+impl From<git2::Error> for StageInputError {
+    fn from(err: git2::Error) -> Self {
+        StageInputError::GitError(err)
+    }
+}
+
+/// A ref being pushed, from a line of `pre-push` input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushedRef {
+    /// Local ref pushed, `(delete)` for deletions
+    pub local_ref: String,
+    /// Commit pushed, all zeros for deletions
+    pub local_sha: String,
+    /// Remote ref updated
+    pub remote_ref: String,
+    /// Commit of the remote ref, all zeros when it doesn't exist yet
+    pub remote_sha: String,
+}
+
+impl PushedRef {
+    /// Parse the lines Git passes to `pre-push` on stdin
+    pub fn parse_lines(input: &str) -> Result<Vec<PushedRef>, StageInputError> {
+        input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [local_ref, local_sha, remote_ref, remote_sha] => Ok(PushedRef {
+                    local_ref: local_ref.to_string(),
+                    local_sha: local_sha.to_string(),
+                    remote_ref: remote_ref.to_string(),
+                    remote_sha: remote_sha.to_string(),
+                }),
+                _ => Err(StageInputError::InvalidRefLine(line.to_string())),
+            })
+            .collect()
+    }
+
+    /// Whether the push deletes the remote ref
+    pub fn is_deletion(&self) -> bool {
+        is_zero(&self.local_sha)
+    }
+
+    /// Whether the remote ref doesn't exist yet
+    pub fn is_new(&self) -> bool {
+        is_zero(&self.remote_sha)
+    }
+}
+
+/// Check whether a commit ID is Git's all-zeros placeholder
+fn is_zero(sha: &str) -> bool {
+    sha.chars().all(|c| c == '0')
+}
+
+/// What Git passed to the hook of a stage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum StageInput {
+    /// The stage takes no input
+    #[default]
+    None,
+    /// Input of `commit-msg`
+    CommitMsg {
+        /// The commit message file
+        message_file: PathBuf,
+    },
+    /// Input of `prepare-commit-msg`
+    PrepareCommitMsg {
+        /// The commit message file
+        message_file: PathBuf,
+        /// Where the message comes from, like `message`, `template` or `merge`
+        source: Option<String>,
+        /// The commit being amended, with `commit` as source
+        commit: Option<String>,
+    },
+    /// Input of `pre-push`
+    PrePush {
+        /// Name of the remote
+        remote_name: String,
+        /// URL of the remote
+        remote_url: String,
+        /// Refs being pushed
+        refs: Vec<PushedRef>,
+        /// Commits whose changes are checked, from and to, when something is pushed
+        range: Option<(String, String)>,
+    },
+}
+
+impl StageInput {
+    /// Read the input of a stage from the hook's arguments and stdin
+    ///
+    /// Stdin is only read for `pre-push`. `dir` is in the repository pushed
+    /// from, to find the commits the push sends.
+    pub fn read(stage: &str, args: &[String], stdin: impl Read, dir: &Path) -> Result<Self, StageInputError> {
+        let arg = |index: usize, argument: &'static str| {
+            args.get(index).cloned().ok_or_else(|| StageInputError::MissingArgument { stage: stage.to_string(), argument })
+        };
+        match stage {
+            "commit-msg" => Ok(StageInput::CommitMsg { message_file: PathBuf::from(arg(0, "the commit message file")?) }),
+            "prepare-commit-msg" => Ok(StageInput::PrepareCommitMsg {
+                message_file: PathBuf::from(arg(0, "the commit message file")?),
+                source: args.get(1).cloned(),
+                commit: args.get(2).cloned(),
+            }),
+            "pre-push" => {
+                let mut input = String::new();
+                let mut stdin = stdin;
+                stdin.read_to_string(&mut input)?;
+                let refs = PushedRef::parse_lines(&input)?;
+                let remote_name = arg(0, "the remote name")?;
+                let range = match refs.iter().find(|pushed| !pushed.is_deletion()) {
+                    Some(pushed) => push_range(dir, &remote_name, pushed)?,
+                    None => None,
+                };
+                Ok(StageInput::PrePush { remote_url: args.get(1).cloned().unwrap_or_else(|| remote_name.clone()), remote_name, refs, range })
+            }
+            _ => Ok(StageInput::None),
+        }
+    }
+
+    /// Get the files hooks of the stage check instead of the selected files
+    ///
+    /// `None` keeps the selected files. A push of commits without a parent
+    /// checks every file.
+    pub fn files(&self) -> Option<Vec<PathBuf>> {
+        match self {
+            StageInput::CommitMsg { message_file } | StageInput::PrepareCommitMsg { message_file, .. } => {
+                Some(vec![message_file.clone()])
+            }
+            StageInput::PrePush { range: None, refs, .. } if refs.iter().all(PushedRef::is_deletion) => Some(Vec::new()),
+            _ => None,
+        }
+    }
+
+    /// Get the commits bounding the changes a push sends
+    pub fn range(&self) -> Option<(&str, &str)> {
+        match self {
+            StageInput::PrePush { range: Some((from, to)), .. } => Some((from, to)),
+            _ => None,
+        }
+    }
+
+    /// Get the environment variables exposing the input to hooks
+    pub fn env(&self) -> Vec<(String, String)> {
+        let var = |key: &str, value: &str| (key.to_string(), value.to_string());
+        match self {
+            StageInput::None => Vec::new(),
+            StageInput::CommitMsg { message_file } => vec![var(COMMIT_MSG_FILE_ENV, &message_file.display().to_string())],
+            StageInput::PrepareCommitMsg { message_file, source, commit } => vec![
+                var(COMMIT_MSG_FILE_ENV, &message_file.display().to_string()),
+                var(COMMIT_MSG_SOURCE_ENV, source.as_deref().unwrap_or_default()),
+                var(COMMIT_OBJECT_ENV, commit.as_deref().unwrap_or_default()),
+            ],
+            StageInput::PrePush { remote_name, remote_url, range, .. } => {
+                let mut env = vec![var(REMOTE_NAME_ENV, remote_name), var(REMOTE_URL_ENV, remote_url)];
+                if let Some((from, to)) = range {
+                    env.push(var(FROM_REF_ENV, from));
+                    env.push(var(TO_REF_ENV, to));
+                }
+                env
+            }
+        }
+    }
+}
+
+/// Find the commits bounding the changes a pushed ref sends
+///
+/// An existing remote ref known locally is the start. Otherwise the push
+/// starts at the parent of the oldest pushed commit the remote's branches
+/// don't have, and `None` means that commit has no parent.
+fn push_range(dir: &Path, remote_name: &str, pushed: &PushedRef) -> Result<Option<(String, String)>, StageInputError> {
+    let repo = Repository::discover(dir)?;
+    let to = Oid::from_str(&pushed.local_sha)?;
+    if !pushed.is_new() {
+        if let Ok(from) = Oid::from_str(&pushed.remote_sha) {
+            if repo.find_commit(from).is_ok() {
+                return Ok(Some((from.to_string(), to.to_string())));
+            }
+        }
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+    walk.push(to)?;
+    for reference in repo.references_glob(&format!("refs/remotes/{}/*", remote_name))?.flatten() {
+        if let Some(oid) = reference.target() {
+            walk.hide(oid)?;
+        }
+    }
+    let Some(oldest) = walk.last().transpose()? else {
+        // The remote has every commit already
+        return Ok(Some((to.to_string(), to.to_string())));
+    };
+    let oldest = repo.find_commit(oldest)?;
+    Ok(oldest.parent_id(0).ok().map(|from| (from.to_string(), to.to_string())))
+}
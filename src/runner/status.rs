@@ -0,0 +1,236 @@
+//! Setup status for shell prompts
+//!
+//! `rustyhook status` reports whether the Git hook is installed, the config
+//! is valid and every hook's environment is ready. Prompts run it on every
+//! command line, so it must be fast: what it learns from parsing the config
+//! is cached under the config's fingerprint, and later calls only read the
+//! hook script and the environments' ready markers.
+//!
+//! Runs in a repository without the Git hook nudge once to install it,
+//! see [`install_nudge`].
+//!
+//! `--porcelain` prints one `key value` line per check, in a fixed order:
+//!
+//! ```text
+//! hooks installed
+//! config valid
+//! environments ready
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::CacheManager;
+use crate::config::drift::{fingerprint, installed_binary, is_same_binary};
+use crate::config::{expand_matrix, parse_config};
+use crate::toolchains::ready;
+use super::hook_resolver::HookResolver;
+
+/// Directory of the cache root holding cached config facts
+pub const STATUS_CACHE_DIR: &str = "status";
+
+/// Directory of the cache root holding the markers of repositories nudged to install the Git hook
+pub const NUDGE_CACHE_DIR: &str = "nudged";
+
+/// How long cached config facts are kept without the config changing
+const STATUS_CACHE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Whether the pre-commit Git hook runs RustyHook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookInstall {
+    /// The hook runs the current binary
+    Installed,
+    /// The hook runs another binary
+    Stale,
+    /// No RustyHook hook is installed
+    Missing,
+}
+
+/// Whether the config can be used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigState {
+    /// The config parses and its matrices expand
+    Valid,
+    /// The config exists but can't be used
+    Invalid {
+        /// Why the config can't be used
+        error: String,
+    },
+    /// There is no config
+    Missing,
+}
+
+/// An environment a configured hook needs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequiredEnvironment {
+    /// ID of the hook
+    pub hook_id: String,
+    /// Directory of the environment
+    pub install_dir: PathBuf,
+    /// Setup manifest the environment must be ready for
+    pub manifest: String,
+}
+
+/// What the status learns from parsing the config, cached by its fingerprint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigFacts {
+    /// Whether the config can be used
+    pub state: ConfigState,
+    /// Environments the hooks need
+    pub environments: Vec<RequiredEnvironment>,
+}
+
+/// The setup status of a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Status {
+    /// Whether the pre-commit Git hook runs RustyHook
+    pub hooks: HookInstall,
+    /// Whether the config can be used
+    pub config: ConfigState,
+    /// Hooks whose environment isn't set up yet
+    pub missing_environments: Vec<String>,
+}
+
+impl Status {
+    /// Check whether everything is set up
+    pub fn is_ok(&self) -> bool {
+        self.hooks == HookInstall::Installed && self.config == ConfigState::Valid && self.missing_environments.is_empty()
+    }
+
+    /// Render the status as stable `key value` lines
+    pub fn to_porcelain(&self) -> String {
+        let hooks = match self.hooks {
+            HookInstall::Installed => "installed",
+            HookInstall::Stale => "stale",
+            HookInstall::Missing => "missing",
+        };
+        let config = match self.config {
+            ConfigState::Valid => "valid",
+            ConfigState::Invalid { .. } => "invalid",
+            ConfigState::Missing => "missing",
+        };
+        let environments = match self.missing_environments.len() {
+            0 => "ready".to_string(),
+            count => format!("missing {}", count),
+        };
+        format!("hooks {}\nconfig {}\nenvironments {}\n", hooks, config, environments)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.hooks {
+            HookInstall::Installed => writeln!(f, "Git hook: installed")?,
+            HookInstall::Stale => writeln!(f, "Git hook: runs another rustyhook binary, run 'rustyhook install --force'")?,
+            HookInstall::Missing => writeln!(f, "Git hook: not installed, run 'rustyhook install'")?,
+        }
+        match &self.config {
+            ConfigState::Valid => writeln!(f, "Config: valid")?,
+            ConfigState::Invalid { error } => writeln!(f, "Config: invalid ({})", error)?,
+            ConfigState::Missing => writeln!(f, "Config: missing, run 'rustyhook init'")?,
+        }
+        if self.missing_environments.is_empty() {
+            writeln!(f, "Environments: ready")
+        } else {
+            writeln!(f, "Environments: not set up for {}", self.missing_environments.join(", "))
+        }
+    }
+}
+
+/// Check whether the pre-commit Git hook runs the current binary
+pub fn hook_install(git_dir: Option<&Path>, current_exe: Option<&Path>) -> HookInstall {
+    let script = git_dir.and_then(|git_dir| fs::read_to_string(git_dir.join("hooks").join("pre-commit")).ok());
+    match script.as_deref().and_then(installed_binary) {
+        None => HookInstall::Missing,
+        Some(binary) if current_exe.is_some_and(|exe| !is_same_binary(&binary, exe)) => HookInstall::Stale,
+        Some(_) => HookInstall::Installed,
+    }
+}
+
+/// Parse a config and find the environments its hooks need
+pub fn config_facts(config_path: &Path, root: &Path, cli_cache_dir: Option<&Path>) -> ConfigFacts {
+    let invalid = |error: String| ConfigFacts { state: ConfigState::Invalid { error }, environments: Vec::new() };
+    let config = match parse_config(config_path).and_then(|config| expand_matrix(&config, root)) {
+        Ok(config) => config,
+        Err(e) => return invalid(format!("{:?}", e)),
+    };
+
+    let cache_dir = crate::cache::resolve_cache_dir(cli_cache_dir, &config);
+    let resolver = HookResolver::new(config.clone(), cache_dir);
+    let mut environments = Vec::new();
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        match resolver.environment(hook) {
+            Ok(Some((install_dir, manifest))) => {
+                environments.push(RequiredEnvironment { hook_id: hook.id.clone(), install_dir, manifest });
+            }
+            Ok(None) => {}
+            Err(e) => return invalid(e.to_string()),
+        }
+    }
+    ConfigFacts { state: ConfigState::Valid, environments }
+}
+
+/// Get the facts of a config, from the cache in `cache_root` when it didn't change
+pub fn cached_config_facts(config_path: &Path, root: &Path, cache_root: &Path, cli_cache_dir: Option<&Path>) -> ConfigFacts {
+    let Ok(content) = fs::read(config_path) else {
+        return ConfigFacts { state: ConfigState::Missing, environments: Vec::new() };
+    };
+
+    // Cached facts are only valid for this exact config and rustyhook version
+    let key = format!("{}-{}.yaml", env!("CARGO_PKG_VERSION"), fingerprint(&content));
+    let cache = CacheManager::new(cache_root.join(STATUS_CACHE_DIR), STATUS_CACHE_MAX_AGE);
+    if let Ok(Some(facts)) = cache.get::<ConfigFacts>(&key) {
+        return facts;
+    }
+
+    let facts = config_facts(config_path, root, cli_cache_dir);
+    // Replace the facts of older versions of the config
+    let _ = cache.clear();
+    if let Err(e) = cache.set(&key, &facts) {
+        log::debug!("Could not cache the config status: {:?}", e);
+    }
+    facts
+}
+
+/// Get the setup status of a repository
+pub fn status(git_dir: Option<&Path>, current_exe: Option<&Path>, facts: ConfigFacts) -> Status {
+    let missing_environments = facts
+        .environments
+        .iter()
+        .filter(|environment| !ready::is_ready(&environment.install_dir, &environment.manifest))
+        .map(|environment| environment.hook_id.clone())
+        .collect();
+    Status { hooks: hook_install(git_dir, current_exe), config: facts.state, missing_environments }
+}
+
+/// Get the nudge to install the Git hook, once per repository
+///
+/// Hooks configured in a repository without the pre-commit Git hook only
+/// run when invoked by hand, and commits go unchecked. The first call for
+/// such a repository returns the command that installs the hook, and leaves
+/// a marker named after the repository in `cache_root` so later calls
+/// return `None`.
+pub fn install_nudge(git_dir: Option<&Path>, cache_root: &Path) -> Option<String> {
+    let git_dir = git_dir?;
+    if hook_install(Some(git_dir), None) != HookInstall::Missing {
+        return None;
+    }
+
+    let root = git_dir.parent().unwrap_or(git_dir);
+    let marker = cache_root.join(NUDGE_CACHE_DIR).join(fingerprint(root.to_string_lossy().as_bytes()));
+    if marker.exists() {
+        return None;
+    }
+    if let Err(e) = fs::create_dir_all(cache_root.join(NUDGE_CACHE_DIR)).and_then(|_| fs::write(&marker, root.to_string_lossy().as_bytes())) {
+        log::debug!("Could not record the install nudge for {}: {}", root.display(), e);
+    }
+    Some(format!(
+        "The Git hook isn't installed in {}, so hooks only run when invoked by hand. Run 'rustyhook install' to run them on every commit (this is only shown once)",
+        root.display()
+    ))
+}
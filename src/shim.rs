@@ -0,0 +1,163 @@
+//! pre-commit compatibility shim for RustyHook
+//!
+//! When the binary is invoked as `pre-commit` (for example through a symlink),
+//! it accepts the most common pre-commit commands and maps them to the
+//! equivalent RustyHook behavior, so existing scripts keep working.
+
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand};
+use log::{debug, error, info};
+
+use crate::{config, logging, runner, FileSelection, RunOptions};
+
+/// The pre-commit command line accepted by the shim
+#[derive(Parser, Debug)]
+#[command(name = "pre-commit", about = "pre-commit compatible interface to RustyHook")]
+pub struct PreCommitCli {
+    #[command(subcommand)]
+    pub command: PreCommitCommand,
+}
+
+/// pre-commit commands supported by the shim
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum PreCommitCommand {
+    /// Run hooks
+    Run {
+        /// Only run the hook with this ID
+        hook: Option<String>,
+
+        /// Run on all the files in the repo
+        #[arg(short, long)]
+        all_files: bool,
+
+        /// Specific filenames to run hooks on
+        #[arg(long, num_args = 1..)]
+        files: Vec<PathBuf>,
+
+        /// The stage during which the hooks are run
+        #[arg(long)]
+        hook_stage: Option<String>,
+
+        /// Show the diff of files changed by hooks
+        #[arg(long)]
+        show_diff_on_failure: bool,
+    },
+    /// Install the Git hook script
+    Install {
+        /// Type of hook to install (can be given several times)
+        #[arg(short = 't', long = "hook-type")]
+        hook_types: Vec<String>,
+
+        /// Replace any existing hook script
+        #[arg(short = 'f', long)]
+        overwrite: bool,
+    },
+    /// Update repo revs to their latest tags
+    Autoupdate {
+        /// Pin the updated revs to commit SHAs
+        #[arg(long)]
+        freeze: bool,
+
+        /// Only update this repo (can be given several times)
+        #[arg(long = "repo")]
+        repos: Vec<String>,
+    },
+}
+
+/// Check whether the binary was invoked as `pre-commit`
+pub fn invoked_as_precommit() -> bool {
+    std::env::args_os()
+        .next()
+        .map(|arg0| is_precommit_name(Path::new(&arg0)))
+        .unwrap_or(false)
+}
+
+/// Check whether a program path names `pre-commit`
+pub fn is_precommit_name(program: &Path) -> bool {
+    program.file_stem().and_then(|stem| stem.to_str()) == Some("pre-commit")
+}
+
+/// Map a pre-commit `run` invocation to RustyHook run options
+///
+/// Without `--all-files` or `--files`, only the staged files are checked, as
+/// pre-commit does.
+fn run_options(command: &PreCommitCommand, staged: impl FnOnce() -> Vec<PathBuf>) -> Option<RunOptions> {
+    let PreCommitCommand::Run { hook, all_files, files, hook_stage, show_diff_on_failure } = command else {
+        return None;
+    };
+
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let selection = if *all_files {
+        FileSelection::AllFiles
+    } else if !files.is_empty() {
+        FileSelection::Files(files.iter().map(|file| current_dir.join(file)).collect())
+    } else {
+        FileSelection::Files(staged())
+    };
+
+    Some(RunOptions {
+        selection,
+        hook_id: hook.clone(),
+        hook_stage: hook_stage.as_deref().map(|stage| config::parser::normalize_stage(stage).to_string()),
+        show_diff_on_failure: *show_diff_on_failure,
+        ..Default::default()
+    })
+}
+
+/// Get the staged files that still exist
+fn staged_files() -> Vec<PathBuf> {
+    crate::get_staged_changes()
+        .into_iter()
+        .filter(|change| change.kind != runner::ChangeKind::Deleted)
+        .map(|change| change.path)
+        .collect()
+}
+
+/// Entry point when invoked as `pre-commit`
+pub fn main() {
+    let cli = PreCommitCli::parse();
+
+    if let Err(e) = logging::init(None, None) {
+        eprintln!("Failed to initialize logger: {}", e);
+        return;
+    }
+    debug!("Running as a pre-commit drop-in: {:?}", cli.command);
+
+    match &cli.command {
+        PreCommitCommand::Run { .. } => {
+            let options = run_options(&cli.command, staged_files).unwrap_or_default();
+            // Prefer the native config, falling back to the pre-commit one
+            if config::find_config().is_ok() {
+                crate::run_hooks_with_native_config(false, &options);
+            } else {
+                crate::run_hooks_with_compat_config(&options);
+            }
+        }
+        PreCommitCommand::Install { hook_types, overwrite } => {
+            if hook_types.is_empty() {
+                crate::install_git_hook("pre-commit", *overwrite);
+            }
+            for hook_type in hook_types {
+                crate::install_git_hook(hook_type, *overwrite);
+            }
+        }
+        PreCommitCommand::Autoupdate { freeze, repos } => {
+            let path = match config::find_precommit_config_path() {
+                Ok(path) => path,
+                Err(e) => {
+                    error!("Error finding pre-commit configuration: {:?}", e);
+                    std::process::exit(1);
+                }
+            };
+            match config::autoupdate_precommit_config(&path, repos, *freeze) {
+                Ok(0) => info!("All repos are already up to date."),
+                Ok(count) => info!("Updated {} repo(s) in {}", count, path.display()),
+                Err(e) => {
+                    error!("Error updating repo revisions: {:?}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Test helpers for RustyHook
+//!
+//! This module helps test hooks and configurations: it creates temporary Git
+//! repositories with staged files, runs a configuration through the executor
+//! in-process and returns a [`RunReport`] to assert on. The helpers panic on
+//! failure, as tests do.
+//!
+//! ```no_run
+//! use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+//!
+//! let repo = TestRepo::new();
+//! repo.write_staged("notes.txt", "trailing  \n");
+//! let report = repo.run(local_config(vec![hook("trailing-whitespace", "native", "trailing-whitespace")]));
+//! assert_passed(&report, "trailing-whitespace");
+//! assert_eq!(repo.read("notes.txt"), "trailing\n");
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use crate::config::{Config, Hook, Repo};
+use crate::runner::{ChangeKind, FileChange, HookResult, HookStatus, ParallelExecutor, RunReport};
+
+/// A temporary Git repository, removed when dropped
+pub struct TestRepo {
+    /// Working directory of the repository
+    dir: TempDir,
+    /// Cache directory for tool environments
+    cache_dir: TempDir,
+    /// The repository
+    repo: git2::Repository,
+}
+
+impl Default for TestRepo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TestRepo {
+    /// Create an empty repository
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().expect("create temporary directory");
+        let cache_dir = tempfile::tempdir().expect("create temporary cache directory");
+        let repo = git2::Repository::init(dir.path()).expect("initialize repository");
+
+        let mut config = repo.config().expect("open repository config");
+        config.set_str("user.name", "RustyHook Tests").expect("set user.name");
+        config.set_str("user.email", "tests@rustyhook.invalid").expect("set user.email");
+
+        TestRepo { dir, cache_dir, repo }
+    }
+
+    /// Get the working directory of the repository
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Get the cache directory runs use
+    pub fn cache_dir(&self) -> &Path {
+        self.cache_dir.path()
+    }
+
+    /// Write a file, creating its parent directories, and return its absolute path
+    pub fn write(&self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+        let full_path = self.path().join(path);
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent).expect("create parent directories");
+        }
+        std::fs::write(&full_path, content).expect("write file");
+        full_path
+    }
+
+    /// Read a file as text
+    pub fn read(&self, path: impl AsRef<Path>) -> String {
+        std::fs::read_to_string(self.path().join(path)).expect("read file")
+    }
+
+    /// Stage a file, or its removal if it doesn't exist
+    pub fn stage(&self, path: impl AsRef<Path>) {
+        let mut index = self.repo.index().expect("open index");
+        if self.path().join(path.as_ref()).exists() {
+            index.add_path(path.as_ref()).expect("stage file");
+        } else {
+            index.remove_path(path.as_ref()).expect("stage removal");
+        }
+        index.write().expect("write index");
+    }
+
+    /// Write and stage a file, and return its absolute path
+    pub fn write_staged(&self, path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> PathBuf {
+        let full_path = self.write(path.as_ref(), content);
+        self.stage(path);
+        full_path
+    }
+
+    /// Delete a file and stage its removal
+    pub fn remove_staged(&self, path: impl AsRef<Path>) {
+        std::fs::remove_file(self.path().join(path.as_ref())).expect("remove file");
+        self.stage(path);
+    }
+
+    /// Commit the staged changes
+    pub fn commit(&self, message: &str) {
+        let mut index = self.repo.index().expect("open index");
+        let tree = self.repo.find_tree(index.write_tree().expect("write tree")).expect("find tree");
+        let signature = self.repo.signature().expect("create signature");
+        let parent = self.repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .expect("commit");
+    }
+
+    /// Tag the current commit with a lightweight tag
+    pub fn tag(&self, name: &str) {
+        let head = self.repo.head().and_then(|head| head.peel(git2::ObjectType::Commit)).expect("find HEAD");
+        self.repo.tag_lightweight(name, &head, false).expect("tag");
+    }
+
+    /// Get the staged changes, as runs see them
+    pub fn staged_changes(&self) -> Vec<FileChange> {
+        crate::runner::staged_changes(self.path()).expect("classify staged changes")
+    }
+
+    /// Get the absolute paths of the staged files that weren't deleted
+    pub fn staged_files(&self) -> Vec<PathBuf> {
+        self.staged_changes()
+            .into_iter()
+            .filter(|change| change.kind != ChangeKind::Deleted)
+            .map(|change| change.path)
+            .collect()
+    }
+
+    /// Run a configuration on the staged files
+    pub fn run(&self, config: Config) -> RunReport {
+        self.run_on(config, self.staged_files())
+    }
+
+    /// Run a configuration on the given files
+    ///
+    /// Relative paths are relative to the repository. Hooks that run a
+    /// command still run it in the current directory of the test process.
+    /// This creates its own async runtime, so it must not be called from
+    /// async code.
+    pub fn run_on(&self, config: Config, files: Vec<PathBuf>) -> RunReport {
+        let files = files.into_iter().map(|file| self.path().join(file)).collect();
+        let executor = ParallelExecutor::new(config, self.cache_dir().to_path_buf());
+        let rt = tokio::runtime::Runtime::new().expect("create async runtime");
+
+        rt.block_on(executor.set_changes(self.staged_changes()));
+        let result = rt.block_on(executor.run_all_hooks(files));
+        let report = rt.block_on(executor.report());
+
+        // Hook failures are in the report, anything else is a broken test setup
+        if let Err(e) = result {
+            if report.is_success() {
+                panic!("Run failed before its hooks ran: {}", e);
+            }
+        }
+        report
+    }
+}
+
+/// Create a hook with the given ID, language and entry
+pub fn hook(id: &str, language: &str, entry: &str) -> Hook {
+    Hook {
+        id: id.to_string(),
+        name: id.to_string(),
+        entry: entry.to_string(),
+        language: language.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Create a configuration with a single local repository
+pub fn local_config(hooks: Vec<Hook>) -> Config {
+    Config {
+        repos: vec![Repo { repo: "local".to_string(), hooks }],
+        ..Default::default()
+    }
+}
+
+/// Get the result of a hook, panicking if it didn't run
+pub fn hook_result<'a>(report: &'a RunReport, hook_id: &str) -> &'a HookResult {
+    report.result(hook_id).unwrap_or_else(|| panic!("hook '{}' did not run: {:#?}", hook_id, report))
+}
+
+/// Assert that a hook ran and passed
+pub fn assert_passed(report: &RunReport, hook_id: &str) {
+    let result = hook_result(report, hook_id);
+    assert_eq!(result.status, HookStatus::Passed, "hook '{}' did not pass: {:?}", hook_id, result.message);
+}
+
+/// Assert that a hook ran and failed
+pub fn assert_failed(report: &RunReport, hook_id: &str) {
+    let result = hook_result(report, hook_id);
+    assert_ne!(result.status, HookStatus::Passed, "hook '{}' did not fail", hook_id);
+}
@@ -0,0 +1,97 @@
+//! Details of failing hook processes
+//!
+//! With `--verbose-errors`, a failing hook process is reported with the
+//! argv it was spawned with, its working directory, and the environment
+//! variables it got on top of the ones inherited from RustyHook's own
+//! environment. That is what it takes to reproduce the failure in a shell.
+//! Values of variables that look like credentials are redacted.
+
+use std::ffi::OsStr;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether failing hook processes are reported in detail
+static VERBOSE_ERRORS: AtomicBool = AtomicBool::new(false);
+
+/// Parts of variable names whose values are redacted
+const SECRET_NAME_PARTS: &[&str] = &["TOKEN", "SECRET", "PASSWORD", "PASSWD", "KEY", "AUTH", "CREDENTIAL", "PRIVATE"];
+
+/// Set whether failing hook processes are reported in detail
+pub fn set_verbose_errors(verbose: bool) {
+    VERBOSE_ERRORS.store(verbose, Ordering::SeqCst);
+}
+
+/// Check whether failing hook processes are reported in detail
+pub fn is_verbose_errors() -> bool {
+    VERBOSE_ERRORS.load(Ordering::SeqCst)
+}
+
+/// Check whether the value of an environment variable is redacted
+pub fn is_secret(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// Quote an argument the way a POSIX shell would need it
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Describe a value in the environment diff
+fn value(name: &str, value: &OsStr) -> String {
+    if is_secret(name) {
+        "<redacted>".to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Describe how a command is spawned
+///
+/// Gives one line each for the argv and working directory, then one line per
+/// variable the command sets or removes compared to the current environment.
+pub fn describe(command: &Command) -> String {
+    let argv: Vec<String> = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(quote)
+        .collect();
+    let cwd = command
+        .get_current_dir()
+        .map(|dir| dir.to_path_buf())
+        .or_else(|| std::env::current_dir().ok())
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_else(|| "?".to_string());
+
+    let mut lines = vec![format!("argv: {}", argv.join(" ")), format!("cwd: {}", cwd)];
+    let mut envs: Vec<_> = command.get_envs().collect();
+    envs.sort();
+    for (key, new) in envs {
+        let name = key.to_string_lossy();
+        let old = std::env::var_os(key);
+        let line = match (old, new) {
+            (None, None) => continue,
+            (Some(_), None) => format!("env: -{}", name),
+            (None, Some(new)) => format!("env: +{}={}", name, value(&name, new)),
+            (Some(old), Some(new)) if old == new => continue,
+            (Some(old), Some(new)) => {
+                format!("env: ~{}={} (was {})", name, value(&name, new), value(&name, &old))
+            }
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+/// Report a failing hook process when `--verbose-errors` is on
+pub fn report_failure(name: &str, command: &Command) {
+    if is_verbose_errors() {
+        log::error!("Command of {} failed:\n{}", name, describe(command));
+    }
+}
@@ -0,0 +1,101 @@
+//! Downloaded runtime archives and their extraction directories
+//!
+//! Runtimes are downloaded into `downloads/` and unpacked into `extracted/`
+//! before being copied or built into their install directory. Both can take
+//! hundreds of megabytes per runtime, so once an install has been verified,
+//! the extracted tree is removed, and so is the archive when the config sets
+//! `keep_downloads: false`. Keeping archives (the default) saves a download
+//! the next time the runtime is installed.
+
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Name of the directories archives are downloaded into
+pub const DOWNLOADS_DIR: &str = "downloads";
+
+/// Name of the directories archives are extracted into
+pub const EXTRACTED_DIR: &str = "extracted";
+
+/// Whether downloaded archives are kept after a successful install
+static KEEP_DOWNLOADS: AtomicBool = AtomicBool::new(true);
+
+/// Set whether downloaded archives are kept after a successful install
+pub fn set_keep_downloads(keep: bool) {
+    KEEP_DOWNLOADS.store(keep, Ordering::SeqCst);
+}
+
+/// Check whether downloaded archives are kept after a successful install
+pub fn keep_downloads() -> bool {
+    KEEP_DOWNLOADS.load(Ordering::SeqCst)
+}
+
+/// Remove a directory, and its parent once that is empty
+fn remove_with_empty_parent(path: &Path) {
+    let removed = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+    match removed {
+        Ok(()) => log::debug!("Removed {}", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => {
+            log::warn!("Could not remove {}: {}", path.display(), e);
+            return;
+        }
+    }
+    if let Some(parent) = path.parent() {
+        // Only succeeds when nothing else is left in it
+        let _ = fs::remove_dir(parent);
+    }
+}
+
+/// Clean up after a verified install from `archive`
+///
+/// `extracted` is the directory the archive was unpacked into, when the
+/// install copied or built from it rather than using it in place.
+pub fn clean_up_install(archive: &Path, extracted: Option<&Path>) {
+    if let Some(extracted) = extracted {
+        remove_with_empty_parent(extracted);
+    }
+    if !keep_downloads() {
+        remove_with_empty_parent(archive);
+    }
+}
+
+/// Get the total size of the files under a path
+pub fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+/// Get the size of the downloaded archives and extracted trees under a cache directory
+///
+/// Returns the sizes of all `downloads` and all `extracted` directories,
+/// which sit in the cache directory of each environment.
+pub fn leftover_sizes(cache_dir: &Path) -> (u64, u64) {
+    let mut sizes = (0, 0);
+    let Ok(entries) = fs::read_dir(cache_dir) else { return sizes };
+    for entry in entries.filter_map(Result::ok) {
+        sizes.0 += dir_size(&entry.path().join(DOWNLOADS_DIR));
+        sizes.1 += dir_size(&entry.path().join(EXTRACTED_DIR));
+    }
+    sizes
+}
+
+/// Format a size in bytes for people
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
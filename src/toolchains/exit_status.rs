@@ -0,0 +1,68 @@
+//! Exit codes hook processes pass with
+//!
+//! Some tools exit with 1 for findings that aren't fatal and with 2 for real
+//! errors, like `grep`. A hook's `success_exit_codes` lists the exit codes it
+//! passes with, `[0]` by default. A hook passing with a code other than 0 is
+//! logged as a warning, so the findings aren't lost.
+//!
+//! Like output limits, the codes of the current hook are kept in a
+//! thread-local set by [`with_success_codes`], since tools check the status
+//! of their processes in their `run`.
+
+use std::cell::RefCell;
+use std::process::ExitStatus;
+
+/// Exit codes of hooks that don't set `success_exit_codes`
+pub const DEFAULT_SUCCESS_CODES: &[i32] = &[0];
+
+/// The exit codes a hook passes with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuccessCodes {
+    /// ID of the hook, for warnings
+    pub hook_id: String,
+    /// Exit codes the hook passes with
+    pub codes: Vec<i32>,
+}
+
+impl SuccessCodes {
+    /// Get the exit codes of a hook, `[0]` when it sets none
+    pub fn new(hook_id: &str, codes: &[i32]) -> Self {
+        let codes = if codes.is_empty() { DEFAULT_SUCCESS_CODES } else { codes };
+        SuccessCodes { hook_id: hook_id.to_string(), codes: codes.to_vec() }
+    }
+
+    /// Check whether a process of the hook passed
+    ///
+    /// Processes killed by a signal have no exit code and never pass.
+    pub fn accepts(&self, status: &ExitStatus) -> bool {
+        let Some(code) = status.code() else { return false };
+        let accepted = self.codes.contains(&code);
+        if accepted && code != 0 {
+            log::warn!("Hook '{}' exited with code {}, which its success_exit_codes accept", self.hook_id, code);
+        }
+        accepted
+    }
+}
+
+thread_local! {
+    /// Exit codes of the hook running on this thread
+    static CURRENT: RefCell<Option<SuccessCodes>> = const { RefCell::new(None) };
+}
+
+/// Run `f` with the exit codes of a hook
+pub fn with_success_codes<T>(codes: &SuccessCodes, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|current| current.replace(Some(codes.clone())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Check whether a process of the hook running on this thread passed
+///
+/// Outside of a hook, only exit code 0 passes.
+pub fn passed(status: &ExitStatus) -> bool {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(codes) => codes.accepts(status),
+        None => status.success(),
+    })
+}
@@ -0,0 +1,72 @@
+//! Fail tool implementation for RustyHook
+//!
+//! This module provides a tool for pre-commit's `fail` language, which simply
+//! fails with the hook's entry as the message whenever any files match. It is
+//! typically used to forbid files such as `.env` or `*.orig` from being committed.
+
+use std::path::PathBuf;
+
+use super::r#trait::{SetupContext, Tool, ToolError};
+
+/// A tool that always fails when given files
+pub struct FailTool {
+    /// The name of the tool
+    name: String,
+
+    /// The version of the tool
+    version: String,
+
+    /// The message to report
+    message: String,
+
+    /// The installation directory
+    install_dir: PathBuf,
+}
+
+impl FailTool {
+    /// Create a new fail tool
+    pub fn new(name: String, version: String, message: String) -> Self {
+        FailTool {
+            name,
+            version,
+            message,
+            install_dir: PathBuf::new(),
+        }
+    }
+}
+
+impl Tool for FailTool {
+    fn setup(&self, _ctx: &SetupContext) -> Result<(), ToolError> {
+        // Nothing to install
+        Ok(())
+    }
+
+    fn run(&self, files: &[PathBuf]) -> Result<(), ToolError> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let file_list = files.iter()
+            .map(|f| f.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Err(ToolError::ExecutionError(format!("{}\n\n{}", self.message.trim_end(), file_list)))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn is_installed(&self) -> bool {
+        true
+    }
+
+    fn install_dir(&self) -> &PathBuf {
+        &self.install_dir
+    }
+}
@@ -0,0 +1,94 @@
+//! Hermetic PATH construction for RustyHook
+//!
+//! Hook processes get an explicit PATH made of the tool's environment bin
+//! directory, the managed runtime bin directory and a minimal system fallback,
+//! so binaries resolve against the provisioned environment rather than whatever
+//! happens to be installed globally on the developer's machine.
+//!
+//! A hook can override the PATH through its `env`. The override replaces the
+//! system fallback, and any `$PATH` or `${PATH}` in it expands to the PATH
+//! RustyHook was started with, for every hook, see [`hook_path`]. The bin
+//! directories always come first.
+//!
+//! Tool environments also get a home directory of their own, through `HOME`
+//! and `XDG_CACHE_HOME`, when they are set up and when their hooks run.
+//! Tools like npm and pip write dotfiles and caches there instead of in the
+//! developer's home, which may be read-only in CI.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Get the minimal system directories appended to every hermetic PATH
+pub fn system_fallback_dirs() -> Vec<PathBuf> {
+    if cfg!(windows) {
+        let system_root = std::env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+        vec![PathBuf::from(&system_root).join("System32"), PathBuf::from(system_root)]
+    } else {
+        ["/usr/local/bin", "/usr/bin", "/bin", "/usr/sbin", "/sbin"]
+            .iter()
+            .map(PathBuf::from)
+            .collect()
+    }
+}
+
+/// Build a hermetic PATH from the given bin directories and the system fallback
+pub fn hermetic_path(bin_dirs: &[PathBuf]) -> OsString {
+    let fallback = std::env::join_paths(system_fallback_dirs()).unwrap_or_default();
+    hook_path(bin_dirs, &HashMap::new(), fallback)
+}
+
+/// Get the PATH of a hook's process
+///
+/// The bin directories come first, made absolute since hooks may run in
+/// another directory. The PATH override of the hook's `env` follows, with
+/// `$PATH` expanded to the inherited PATH, or `rest` when it has none.
+pub fn hook_path(bin_dirs: &[PathBuf], env: &HashMap<String, String>, rest: OsString) -> OsString {
+    let rest = match env.get("PATH") {
+        Some(value) => expand_path_override(value, &std::env::var_os("PATH").unwrap_or_default()),
+        None => rest,
+    };
+    let bin_dirs = bin_dirs.iter().map(|dir| std::path::absolute(dir).unwrap_or_else(|_| dir.clone()));
+    let dirs: Vec<PathBuf> = bin_dirs.chain(std::env::split_paths(&rest)).filter(|dir| !dir.as_os_str().is_empty()).collect();
+    // Bin directories never contain the path separator, so joining cannot fail
+    std::env::join_paths(dirs).unwrap_or(rest)
+}
+
+/// Expand `$PATH` and `${PATH}` in a PATH override to the given base PATH
+pub fn expand_path_override(value: &str, base: &OsStr) -> OsString {
+    let base = base.to_string_lossy();
+    OsString::from(value.replace("${PATH}", &base).replace("$PATH", &base))
+}
+
+/// Variables pointing tools at the home directory of their environment
+pub const HOME_VARS: [&str; 2] = ["HOME", "XDG_CACHE_HOME"];
+
+/// Get the variables giving a tool environment `home` as its home directory
+pub fn home_vars(home: &Path) -> Vec<(String, String)> {
+    vec![
+        ("HOME".to_string(), home.to_string_lossy().into_owned()),
+        ("XDG_CACHE_HOME".to_string(), home.join(".cache").to_string_lossy().into_owned()),
+    ]
+}
+
+/// Get the home directory variables of a tool's environment, for its setup commands
+///
+/// Setup commands don't get the rest of the hook's `env`.
+pub fn home_env(env: &HashMap<String, String>) -> impl Iterator<Item = (&String, &String)> {
+    env.iter().filter(|(key, _)| HOME_VARS.contains(&key.as_str()))
+}
+
+/// Set the hermetic PATH and the hook's environment on a command
+pub fn apply_hook_env(command: &mut Command, bin_dirs: &[PathBuf], env: &HashMap<String, String>) {
+    let fallback = std::env::join_paths(system_fallback_dirs()).unwrap_or_default();
+    let path = hook_path(bin_dirs, env, fallback);
+    log::debug!("Using hermetic PATH: {}", path.to_string_lossy());
+
+    for (key, value) in env {
+        if key != "PATH" {
+            command.env(key, value);
+        }
+    }
+    command.env("PATH", path);
+}
@@ -7,9 +7,26 @@ pub mod python;
 pub mod node;
 pub mod ruby;
 pub mod system;
+pub mod fail;
+pub mod pygrep;
+pub mod hermetic;
+pub mod network;
+pub mod diagnostics;
+pub mod downloads;
+pub mod exit_status;
+pub mod queries;
+pub mod output;
+pub mod parsers;
+pub mod native;
+pub mod ready;
+pub mod uv_script;
 
 pub use r#trait::{SetupContext, Tool, ToolError};
 pub use python::PythonTool;
 pub use node::NodeTool;
 pub use ruby::RubyTool;
 pub use system::SystemTool;
+pub use fail::FailTool;
+pub use pygrep::{PygrepOptions, PygrepTool};
+pub use native::NativeTool;
+pub use uv_script::UvScriptTool;
@@ -0,0 +1,88 @@
+//! Native hook tool implementation for RustyHook
+//!
+//! This module adapts RustyHook's built-in Rust hooks to the Tool trait, so
+//! configurations can use them with `language: native` (the entry is the hook
+//! ID) and well-known tools can be served natively without an environment.
+
+use std::path::PathBuf;
+
+use super::r#trait::{SetupContext, Tool, ToolError};
+use crate::hooks::HookFactory;
+use crate::runner::FileChange;
+
+/// A tool that runs one of RustyHook's native hooks
+pub struct NativeTool {
+    /// The name of the tool
+    name: String,
+
+    /// The version of the tool
+    version: String,
+
+    /// The ID of the native hook to run
+    hook_id: String,
+
+    /// Arguments passed to the native hook
+    args: Vec<String>,
+
+    /// The installation directory
+    install_dir: PathBuf,
+
+    /// The changes of the changeset the run checks
+    changes: Vec<FileChange>,
+}
+
+impl NativeTool {
+    /// Create a new native tool
+    pub fn new(name: String, version: String, hook_id: String, args: Vec<String>) -> Self {
+        NativeTool {
+            name,
+            version,
+            hook_id,
+            args,
+            install_dir: PathBuf::new(),
+            changes: Vec::new(),
+        }
+    }
+
+    /// Set the changes of the changeset, for hooks comparing files with it
+    pub fn with_changes(mut self, changes: Vec<FileChange>) -> Self {
+        self.changes = changes;
+        self
+    }
+}
+
+impl Tool for NativeTool {
+    fn setup(&self, _ctx: &SetupContext) -> Result<(), ToolError> {
+        // Nothing to install, but make sure the hook exists and its arguments are valid
+        HookFactory::create_hook(&self.hook_id, &self.args)
+            .map(|_| ())
+            .map_err(|e| ToolError::ToolNotFound(format!("Native hook {}: {:?}", self.hook_id, e)))
+    }
+
+    fn run(&self, files: &[PathBuf]) -> Result<(), ToolError> {
+        let mut hook = HookFactory::create_hook(&self.hook_id, &self.args)
+            .map_err(|e| ToolError::ToolNotFound(format!("Native hook {}: {:?}", self.hook_id, e)))?;
+        hook.set_changes(&self.changes);
+
+        hook.run(files).map_err(|e| match e {
+            crate::hooks::HookError::Other(message) => ToolError::ExecutionError(message),
+            other => ToolError::ExecutionError(format!("{:?}", other)),
+        })
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn is_installed(&self) -> bool {
+        true
+    }
+
+    fn install_dir(&self) -> &PathBuf {
+        &self.install_dir
+    }
+}
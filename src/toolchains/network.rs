@@ -0,0 +1,99 @@
+//! Network policy for RustyHook
+//!
+//! `rustyhook run --forbid-network` guarantees that a run downloads nothing:
+//! setting up a tool that needs a download fails loudly instead of fetching
+//! it. On Linux, hook processes additionally run in a new network namespace
+//! (through `unshare`), so hooks that reach for the network fail too. Where
+//! that isn't possible, only RustyHook's own downloads are blocked, and the
+//! run says so.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use super::r#trait::ToolError;
+
+/// Whether network access is forbidden for the rest of the process
+static FORBIDDEN: AtomicBool = AtomicBool::new(false);
+
+/// `unshare` arguments to try, most specific first
+const UNSHARE_ARGS: &[&[&str]] = &[
+    &["--net", "--map-current-user"],
+    &["--net", "--map-root-user"],
+    &["--net"],
+];
+
+/// Set whether network access is forbidden
+pub fn set_forbidden(forbidden: bool) {
+    FORBIDDEN.store(forbidden, Ordering::SeqCst);
+    if forbidden && isolation_args().is_none() {
+        log::warn!(
+            "Hook processes can't be cut off from the network here (this needs Linux with unshare and user namespaces); only downloads by RustyHook are blocked"
+        );
+    }
+}
+
+/// Check whether network access is forbidden
+pub fn is_forbidden() -> bool {
+    FORBIDDEN.load(Ordering::SeqCst)
+}
+
+/// Fail if network access is forbidden
+///
+/// `what` describes the download, such as "Python 3.12".
+pub fn check_allowed(what: &str) -> Result<(), ToolError> {
+    if is_forbidden() {
+        return Err(ToolError::InstallationError(format!(
+            "Network access is forbidden by --forbid-network, but {} must be downloaded. Run once without --forbid-network to populate the cache.",
+            what
+        )));
+    }
+    Ok(())
+}
+
+/// Get the `unshare` arguments that work on this system, if any
+fn isolation_args() -> Option<&'static [&'static str]> {
+    static ARGS: OnceLock<Option<&'static [&'static str]>> = OnceLock::new();
+    *ARGS.get_or_init(|| {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        UNSHARE_ARGS.iter().copied().find(|args| {
+            Command::new("unshare")
+                .args(*args)
+                .arg("true")
+                .output()
+                .is_ok_and(|output| output.status.success())
+        })
+    })
+}
+
+/// Create the command for a hook process
+///
+/// When network access is forbidden and the system supports it, the process
+/// runs in its own network namespace, which only has a loopback device. The
+/// program is looked up on RustyHook's own PATH, see [`hook_command_in`].
+pub fn hook_command(program: impl AsRef<OsStr>) -> Command {
+    hook_command_in(program, std::env::var_os("PATH").unwrap_or_default(), std::env::current_dir().unwrap_or_default())
+}
+
+/// Create the command for a hook process running with its own PATH and working directory
+///
+/// A program that isn't found there isn't run through `unshare`, so running
+/// it fails with a missing command rather than an error of `unshare`.
+pub fn hook_command_in(program: impl AsRef<OsStr>, path: impl AsRef<OsStr>, dir: impl AsRef<Path>) -> Command {
+    let program = program.as_ref();
+    let isolated = isolation_args()
+        .filter(|_| is_forbidden())
+        .filter(|_| which::which_in(program, Some(path), dir).is_ok());
+    match isolated {
+        Some(args) => {
+            let mut command = Command::new("unshare");
+            command.args(args).arg("--").arg(program);
+            command
+        }
+        None => Command::new(program),
+    }
+}
@@ -3,6 +3,7 @@
 //! This module provides functionality for managing Node.js environments and packages.
 //! It downloads precompiled Node.js binaries directly from nodejs.org.
 
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -11,6 +12,9 @@ use serde::{Serialize, Deserialize};
 use log::{debug, info};
 use std::env;
 
+use super::hermetic::{apply_hook_env, home_env};
+use super::{diagnostics, downloads, exit_status, network};
+use super::output::{capped, is_capturing, record_stdout};
 use super::r#trait::{SetupContext, Tool, ToolError};
 
 /// Represents a Node.js package.json file
@@ -26,6 +30,87 @@ struct PackageJson {
     dev_dependencies: Option<serde_json::Value>,
 }
 
+/// Lockfiles of Node.js package managers, with the file each writes into
+/// `node_modules` when it installs, and the command installing exactly the lockfile
+const LOCKFILES: &[(&str, &str, &[&str])] = &[
+    ("package-lock.json", ".package-lock.json", &["npm", "ci"]),
+    ("npm-shrinkwrap.json", ".package-lock.json", &["npm", "ci"]),
+    ("pnpm-lock.yaml", ".modules.yaml", &["pnpm", "install", "--frozen-lockfile"]),
+    ("yarn.lock", ".yarn-integrity", &["yarn", "install", "--frozen-lockfile"]),
+];
+
+/// State of a repository's `node_modules` compared to its lockfile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeModulesState {
+    /// Installed from the current lockfile, or present in a package without a lockfile
+    Installed,
+    /// Not installed
+    Missing,
+    /// Installed from an older lockfile
+    Stale,
+}
+
+/// Find the package a directory belongs to: the nearest directory with a package.json
+pub fn find_package_dir(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find(|dir| dir.join("package.json").is_file()).map(Path::to_path_buf)
+}
+
+/// Get the lockfile of a package and the command installing it
+fn package_lockfile(package_dir: &Path) -> Option<(PathBuf, PathBuf, &'static [&'static str])> {
+    LOCKFILES.iter().find_map(|(lockfile, marker, command)| {
+        let lockfile = package_dir.join(lockfile);
+        lockfile.is_file().then(|| (lockfile, package_dir.join("node_modules").join(marker), *command))
+    })
+}
+
+/// Check whether a package's `node_modules` is installed from its current lockfile
+///
+/// Package managers write a file into `node_modules` when they install, so an
+/// install older than the lockfile is stale.
+pub fn node_modules_state(package_dir: &Path) -> NodeModulesState {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    match package_lockfile(package_dir) {
+        None if package_dir.join("node_modules").is_dir() => NodeModulesState::Installed,
+        None => NodeModulesState::Missing,
+        Some((lockfile, marker, _)) => match (modified(&lockfile), modified(&marker)) {
+            (_, None) => NodeModulesState::Missing,
+            (Some(locked), Some(installed)) if installed < locked => NodeModulesState::Stale,
+            _ => NodeModulesState::Installed,
+        },
+    }
+}
+
+/// Get the command installing a package's `node_modules` from its lockfile, like `npm ci`
+pub fn lockfile_install_command(package_dir: &Path) -> Option<String> {
+    package_lockfile(package_dir).map(|(_, _, command)| command.join(" "))
+}
+
+/// Install a package's `node_modules` from its lockfile
+pub fn install_node_modules(package_dir: &Path) -> Result<(), ToolError> {
+    let Some((_, _, command)) = package_lockfile(package_dir) else {
+        return Err(ToolError::InstallationError(format!(
+            "{} has no lockfile to install node_modules from",
+            package_dir.display()
+        )));
+    };
+    network::check_allowed(&format!("the node_modules of {}", package_dir.display()))?;
+    info!("Running {} in {}", command.join(" "), package_dir.display());
+    let status = Command::new(command[0])
+        .args(&command[1..])
+        .current_dir(package_dir)
+        .status()
+        .map_err(|e| ToolError::InstallationError(format!("Failed to run {}: {}", command.join(" "), e)))?;
+    if !status.success() {
+        return Err(ToolError::InstallationError(format!(
+            "{} failed in {} with {}",
+            command.join(" "),
+            package_dir.display(),
+            status
+        )));
+    }
+    Ok(())
+}
+
 /// Represents a Node.js tool
 pub struct NodeTool {
     /// Name of the tool
@@ -45,6 +130,12 @@ pub struct NodeTool {
 
     /// Installation directory
     install_dir: PathBuf,
+
+    /// Directory Node.js runtimes are shared in
+    runtimes_dir: PathBuf,
+
+    /// Additional environment variables for the tool's processes
+    env: HashMap<String, String>,
 }
 
 impl NodeTool {
@@ -63,10 +154,7 @@ impl NodeTool {
             .unwrap_or_else(|| "npm".to_string());
 
         // Default installation directory
-        let mut install_dir = std::env::temp_dir();
-        install_dir.push(".rustyhook");
-        install_dir.push("venvs");
-        install_dir.push(format!("node-{}-{}", name_str, version_str));
+        let install_dir = crate::cache::default_cache_dir().join("venvs").join(format!("node-{}-{}", name_str, version_str));
 
         NodeTool {
             name: name_str,
@@ -75,9 +163,29 @@ impl NodeTool {
             dev_dependencies,
             package_manager: package_manager_str,
             install_dir,
+            runtimes_dir: crate::cache::runtimes_dir(&crate::cache::default_cache_dir()),
+            env: HashMap::new(),
         }
     }
 
+    /// Set additional environment variables for the tool's processes
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set the directory the tool is installed in
+    pub fn with_install_dir(mut self, install_dir: PathBuf) -> Self {
+        self.install_dir = install_dir;
+        self
+    }
+
+    /// Set the directory Node.js runtimes are shared in
+    pub fn with_runtimes_dir(mut self, runtimes_dir: PathBuf) -> Self {
+        self.runtimes_dir = runtimes_dir;
+        self
+    }
+
     /// Determine the platform triple for Node.js download
     fn get_platform_triple(&self) -> Result<String, ToolError> {
         let os = env::consts::OS;
@@ -167,7 +275,7 @@ impl NodeTool {
 
     /// Get the Node.js binary path for the installed version
     fn get_node_binary_path(&self, version: &str) -> PathBuf {
-        let runtime_dir = PathBuf::from(".runtime");
+        let runtime_dir = &self.runtimes_dir;
         let platform = self.get_platform_triple().unwrap_or_else(|_| "unknown".to_string());
         let node_dir = runtime_dir.join("node").join(version);
 
@@ -193,7 +301,7 @@ impl NodeTool {
     /// Download and extract Node.js
     fn download_and_extract_node(&self, version: &str) -> Result<PathBuf, ToolError> {
         let platform = self.get_platform_triple()?;
-        let runtime_dir = PathBuf::from(".runtime");
+        let runtime_dir = &self.runtimes_dir;
         let node_dir = runtime_dir.join("node").join(version);
 
         // Create directories
@@ -213,6 +321,7 @@ impl NodeTool {
         // Download the archive
         let archive_path = node_dir.join(format!("node-v{}-{}.{}", version, platform, file_ext));
 
+        network::check_allowed(&format!("Node.js {}", version))?;
         let curl_output = Command::new("curl")
             .arg("-fsSL")
             .arg("--output")
@@ -296,6 +405,7 @@ impl NodeTool {
         let installed_version = String::from_utf8_lossy(&version_output.stdout).trim().to_string();
         info!("Node.js {} installed successfully. Version: {}", version, installed_version);
 
+        downloads::clean_up_install(&archive_path, None);
         Ok(node_binary)
     }
 
@@ -350,10 +460,11 @@ impl NodeTool {
     fn install_packages(&self, ctx: &SetupContext) -> Result<(), ToolError> {
         // Find the package manager
         let package_manager = self.find_package_manager()?;
+        network::check_allowed(&format!("the packages of {}", self.name))?;
 
         // Build the install command
         let mut command = Command::new(package_manager);
-        command.current_dir(&ctx.install_dir);
+        command.current_dir(&ctx.install_dir).envs(home_env(&self.env));
 
         // Add the install command
         command.arg("install");
@@ -412,21 +523,46 @@ impl Tool for NodeTool {
         let tool_path = self.install_dir.join("node_modules").join(".bin").join(&self.name);
 
         // Run the tool on the files
-        let mut command = Command::new(&tool_path);
+        let mut command = network::hook_command(&tool_path);
+        apply_hook_env(&mut command, &self.bin_dirs(), &self.env);
 
         // Add files as arguments
         for file in files {
             command.arg(file);
         }
 
-        // Execute the command
-        let status = command
-            .status()
+        // Capture the output only when it is parsed, so the hook's output shows otherwise
+        if !is_capturing() {
+            let status = command
+                .status()
+                .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.name, e)))?;
+            if !exit_status::passed(&status) {
+                diagnostics::report_failure(&self.name, &command);
+                return Err(ToolError::ExecutionError(
+                    format!("{} failed with exit code {:?}", self.name, status.code()),
+                ));
+            }
+            return Ok(());
+        }
+
+        // Execute the command with output capture
+        let output = command
+            .output()
             .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.name, e)))?;
 
-        if !status.success() {
+        record_stdout(&output.stdout);
+        if !exit_status::passed(&output.status) {
+            diagnostics::report_failure(&self.name, &command);
+            let stdout = capped("stdout", &output.stdout);
+            let stderr = capped("stderr", &output.stderr);
+            if !stdout.is_empty() {
+                log::error!("Command stdout: {}", stdout);
+            }
+            if !stderr.is_empty() {
+                log::error!("Command stderr: {}", stderr);
+            }
             return Err(ToolError::ExecutionError(
-                format!("{} failed with exit code {:?}", self.name, status.code()),
+                format!("{} failed with exit code {:?}", self.name, output.status.code()),
             ));
         }
 
@@ -450,4 +586,28 @@ impl Tool for NodeTool {
     fn install_dir(&self) -> &PathBuf {
         &self.install_dir
     }
+
+    fn setup_manifest(&self) -> Option<String> {
+        Some(format!(
+            "node\n{}\n{}\n{}\n{}\n{}",
+            self.name,
+            self.version,
+            self.package_manager,
+            self.dev_dependencies,
+            self.packages.join("\n")
+        ))
+    }
+
+    fn bin_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.install_dir.join("node_modules").join(".bin")];
+
+        // Scripts with a `#!/usr/bin/env node` shebang must find the managed runtime
+        if let Ok(node_version) = self.determine_node_version(Some(&self.version)) {
+            if let Some(runtime_bin) = self.get_node_binary_path(&node_version).parent() {
+                dirs.push(runtime_bin.to_path_buf());
+            }
+        }
+
+        dirs
+    }
 }
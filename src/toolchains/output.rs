@@ -0,0 +1,173 @@
+//! Output limits for hook processes
+//!
+//! A linter dumping megabytes of output can freeze a terminal and bloat CI
+//! logs, so the output captured from hook processes is cut to the hook's
+//! `max_output` before it is logged or reported. The full output is saved to
+//! a log file in the cache directory, and the truncated output says where.
+//!
+//! Tools capture output in their `run`, which executes synchronously on the
+//! thread running the hook, so the limit of the current hook is kept in a
+//! thread-local set by [`with_limit`]. The same way, [`capturing`] collects
+//! the standard output of a hook's processes for its output parser.
+
+use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+/// Default `max_output` of hooks, in bytes
+pub const DEFAULT_MAX_OUTPUT: usize = 1024 * 1024;
+
+/// Directory of the cache root holding the full output of truncated hooks
+pub const LOGS_DIR: &str = "logs";
+
+/// Which part of output over the limit is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Truncation {
+    /// Keep the start, where tools usually report the first problem
+    Head,
+    /// Keep the end, where tools usually print a summary
+    Tail,
+    /// Keep half of the limit from each end
+    #[default]
+    HeadTail,
+}
+
+/// The output limit of a hook
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// ID of the hook, naming its log files
+    pub hook_id: String,
+    /// Most bytes of each stream to keep, or 0 for no limit
+    pub max_bytes: usize,
+    /// Which part of the output to keep
+    pub truncation: Truncation,
+    /// Directory to save the full output of truncated streams in
+    pub log_dir: Option<PathBuf>,
+}
+
+impl Default for OutputLimit {
+    fn default() -> Self {
+        OutputLimit { hook_id: String::new(), max_bytes: DEFAULT_MAX_OUTPUT, truncation: Truncation::default(), log_dir: None }
+    }
+}
+
+thread_local! {
+    /// Output limit of the hook running on this thread
+    static CURRENT: RefCell<Option<OutputLimit>> = const { RefCell::new(None) };
+
+    /// Standard output of the processes of the hook running on this thread, when collected
+    static CAPTURED: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+}
+
+/// Run `f`, collecting the standard output its hook processes record
+pub fn capturing<T>(f: impl FnOnce() -> T) -> (T, Vec<u8>) {
+    let previous = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let stdout = CAPTURED.with(|captured| captured.replace(previous)).unwrap_or_default();
+    (result, stdout)
+}
+
+/// Run `f`, collecting the standard output its hook processes record only when `collect` is set
+pub fn capturing_when<T>(collect: bool, f: impl FnOnce() -> T) -> (T, Vec<u8>) {
+    if collect {
+        capturing(f)
+    } else {
+        (f(), Vec::new())
+    }
+}
+
+/// Whether the standard output of the hook running on this thread is collected
+pub fn is_capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+/// Record the standard output of a hook process, when it is collected
+pub fn record_stdout(stdout: &[u8]) {
+    CAPTURED.with(|captured| {
+        if let Some(captured) = captured.borrow_mut().as_mut() {
+            captured.extend_from_slice(stdout);
+        }
+    });
+}
+
+/// Run `f` with the output limit of a hook
+pub fn with_limit<T>(limit: &OutputLimit, f: impl FnOnce() -> T) -> T {
+    let previous = CURRENT.with(|current| current.replace(Some(limit.clone())));
+    let result = f();
+    CURRENT.with(|current| *current.borrow_mut() = previous);
+    result
+}
+
+/// Move an index to the nearest character boundary at or before it
+fn floor_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// Move an index to the nearest character boundary at or after it
+fn ceil_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index += 1;
+    }
+    index
+}
+
+/// Save the full output of a stream, returning the log file
+fn save_full_output(limit: &OutputLimit, stream: &str, text: &str) -> Option<PathBuf> {
+    let dir = limit.log_dir.as_ref()?;
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let id: String = limit.hook_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}-{}.{}.log", id, timestamp, stream));
+    match fs::create_dir_all(dir).and_then(|_| fs::write(&path, text)) {
+        Ok(()) => Some(path),
+        Err(e) => {
+            log::debug!("Could not save the full output of {}: {}", limit.hook_id, e);
+            None
+        }
+    }
+}
+
+/// Cut output to a limit
+///
+/// `stream` names the output, such as `stdout`, in the log file name.
+pub fn truncate(limit: &OutputLimit, stream: &str, output: &[u8]) -> String {
+    let text = String::from_utf8_lossy(output);
+    if limit.max_bytes == 0 || text.len() <= limit.max_bytes {
+        return text.into_owned();
+    }
+
+    // Keep text[..head] and text[tail..], on character boundaries
+    let (head, tail) = match limit.truncation {
+        Truncation::Head => (floor_boundary(&text, limit.max_bytes), text.len()),
+        Truncation::Tail => (0, ceil_boundary(&text, text.len() - limit.max_bytes)),
+        Truncation::HeadTail => (
+            floor_boundary(&text, limit.max_bytes / 2),
+            ceil_boundary(&text, text.len() - (limit.max_bytes - limit.max_bytes / 2)),
+        ),
+    };
+    let note = match save_full_output(limit, stream, &text) {
+        Some(path) => format!("[... {} bytes truncated, full output in {} ...]", tail - head, path.display()),
+        None => format!("[... {} bytes truncated ...]", tail - head),
+    };
+    [&text[..head], note.as_str(), &text[tail..]]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cut captured output to the limit of the hook running on this thread
+///
+/// Output is only converted to text outside of a hook.
+pub fn capped(stream: &str, output: &[u8]) -> String {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(limit) => truncate(limit, stream, output),
+        None => String::from_utf8_lossy(output).into_owned(),
+    })
+}
@@ -0,0 +1,173 @@
+//! Parsers of hook output
+//!
+//! A hook's `output_format` names the format its tool prints problems in,
+//! and the tool's standard output is parsed into [`Diagnostic`]s, which run
+//! reports and the metrics file carry for reporters. The formats are:
+//!
+//! - `none`: no parsing, the default
+//! - `gcc`: `file:line:column: severity: message`, the column being optional
+//! - `flake8`: `file:line:column: CODE message`
+//! - `eslint-json`: the output of `eslint --format json`
+//! - `ruff-json`: the output of `ruff check --output-format json`
+//! - `regex`: lines matching the hook's `output_pattern`, whose named groups
+//!   `file`, `line` and `message` are required, and `column`, `code` and
+//!   `severity` optional
+//!
+//! Output that doesn't match the format gives no diagnostics rather than an
+//! error, since tools print other things too.
+
+use std::path::PathBuf;
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::{Diagnostic, Severity};
+
+/// Format of the problems a hook's tool prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// The output isn't parsed
+    #[default]
+    None,
+    /// `file:line:column: severity: message`, as printed by GCC and many other tools
+    Gcc,
+    /// `file:line:column: CODE message`, as printed by flake8 and pycodestyle
+    Flake8,
+    /// The JSON output of ESLint
+    EslintJson,
+    /// The JSON output of Ruff
+    RuffJson,
+    /// Lines matching the hook's `output_pattern`
+    Regex,
+}
+
+/// Pattern of `gcc` lines
+const GCC_PATTERN: &str = r"^(?P<file>[^:\s][^:]*):(?P<line>\d+):(?:(?P<column>\d+):)?\s*(?P<severity>fatal error|error|warning|note|info):\s*(?P<message>.+)$";
+
+/// Pattern of `flake8` lines
+const FLAKE8_PATTERN: &str = r"^(?P<file>[^:\s][^:]*):(?P<line>\d+):(?P<column>\d+):\s*(?P<code>[A-Z]+\d+)\s+(?P<message>.+)$";
+
+/// Named groups `output_pattern` must have
+const REQUIRED_GROUPS: &[&str] = &["file", "line", "message"];
+
+/// A parser of a hook's output
+#[derive(Debug, Clone)]
+pub struct OutputParser {
+    /// Format of the output
+    format: OutputFormat,
+    /// Pattern of lines with a problem, for line-based formats
+    pattern: Option<Regex>,
+}
+
+impl OutputParser {
+    /// Create the parser of a format
+    ///
+    /// `pattern` is the hook's `output_pattern`, which `regex` requires.
+    pub fn new(format: OutputFormat, pattern: Option<&str>) -> Result<Self, String> {
+        let pattern = match format {
+            OutputFormat::Gcc => Some(GCC_PATTERN),
+            OutputFormat::Flake8 => Some(FLAKE8_PATTERN),
+            OutputFormat::Regex => Some(pattern.ok_or("output_format: regex requires an output_pattern")?),
+            OutputFormat::None | OutputFormat::EslintJson | OutputFormat::RuffJson => None,
+        };
+        let pattern = match pattern {
+            Some(pattern) => {
+                let regex = Regex::new(pattern).map_err(|e| format!("Invalid output_pattern: {}", e))?;
+                let missing: Vec<&str> = REQUIRED_GROUPS.iter().copied().filter(|group| !regex.capture_names().any(|name| name == Some(group))).collect();
+                if !missing.is_empty() {
+                    return Err(format!("output_pattern must have the named groups {}, but lacks {}", REQUIRED_GROUPS.join(", "), missing.join(", ")));
+                }
+                Some(regex)
+            }
+            None => None,
+        };
+        Ok(OutputParser { format, pattern })
+    }
+
+    /// Parse the standard output of a hook into diagnostics
+    pub fn parse(&self, output: &str) -> Vec<Diagnostic> {
+        match (self.format, &self.pattern) {
+            (OutputFormat::EslintJson, _) => parse_eslint(output),
+            (OutputFormat::RuffJson, _) => parse_ruff(output),
+            (_, Some(pattern)) => output.lines().filter_map(|line| parse_line(pattern, line.trim_end())).collect(),
+            (_, None) => Vec::new(),
+        }
+    }
+}
+
+/// Get the severity of a level printed by a tool
+fn severity(level: &str) -> Severity {
+    match level.to_ascii_lowercase().as_str() {
+        "warning" | "warn" => Severity::Warning,
+        "note" | "info" | "information" | "hint" => Severity::Info,
+        _ => Severity::Error,
+    }
+}
+
+/// Parse a line with a problem
+fn parse_line(pattern: &Regex, line: &str) -> Option<Diagnostic> {
+    let captures = pattern.captures(line)?;
+    let group = |name: &str| captures.name(name).map(|value| value.as_str());
+    let code = group("code").unwrap_or_default();
+    // Without a severity, codes of warnings start with W, as in flake8
+    let severity = match group("severity") {
+        Some(level) => severity(level),
+        None if code.starts_with('W') => Severity::Warning,
+        None => Severity::Error,
+    };
+    Some(Diagnostic {
+        path: PathBuf::from(group("file")?),
+        line: group("line")?.parse().ok()?,
+        column: group("column").and_then(|column| column.parse().ok()),
+        severity,
+        rule: code.to_string(),
+        message: group("message")?.trim().to_string(),
+    })
+}
+
+/// Parse the JSON output of ESLint
+fn parse_eslint(output: &str) -> Vec<Diagnostic> {
+    let Ok(serde_json::Value::Array(files)) = serde_json::from_str(output.trim()) else { return Vec::new() };
+    let mut diagnostics = Vec::new();
+    for file in &files {
+        let Some(path) = file["filePath"].as_str() else { continue };
+        for message in file["messages"].as_array().into_iter().flatten() {
+            diagnostics.push(Diagnostic {
+                path: PathBuf::from(path),
+                line: message["line"].as_u64().unwrap_or(1) as usize,
+                column: message["column"].as_u64().map(|column| column as usize),
+                severity: if message["severity"].as_u64() == Some(1) { Severity::Warning } else { Severity::Error },
+                rule: message["ruleId"].as_str().unwrap_or_default().to_string(),
+                message: message["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+    }
+    diagnostics
+}
+
+/// Parse the JSON output of Ruff
+fn parse_ruff(output: &str) -> Vec<Diagnostic> {
+    let Ok(serde_json::Value::Array(problems)) = serde_json::from_str(output.trim()) else { return Vec::new() };
+    problems
+        .iter()
+        .filter_map(|problem| {
+            Some(Diagnostic {
+                path: PathBuf::from(problem["filename"].as_str()?),
+                line: problem["location"]["row"].as_u64().unwrap_or(1) as usize,
+                column: problem["location"]["column"].as_u64().map(|column| column as usize),
+                severity: Severity::Error,
+                rule: problem["code"].as_str().unwrap_or_default().to_string(),
+                message: problem["message"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Check the output parsers of a config's hooks
+pub fn check_output_parsers(config: &crate::config::Config) -> Result<(), String> {
+    for hook in config.repos.iter().flat_map(|repo| &repo.hooks) {
+        OutputParser::new(hook.output_format, hook.output_pattern.as_deref()).map_err(|e| format!("Hook '{}': {}", hook.id, e))?;
+    }
+    Ok(())
+}
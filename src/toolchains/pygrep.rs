@@ -0,0 +1,177 @@
+//! Pygrep tool implementation for RustyHook
+//!
+//! This module provides a native implementation of pre-commit's `pygrep`
+//! language, where the hook's entry is a regex searched for in every file.
+//! It supports the same `--ignore-case`, `--multiline` and `--negate` flags,
+//! so hooks like those from pygrep-hooks run without a Python environment.
+//! Patterns are compiled with a backtracking engine, so the look-arounds and
+//! backreferences of Python's `re` work too.
+
+use std::fs;
+use std::path::PathBuf;
+
+use fancy_regex::{Regex, RegexBuilder};
+
+use super::r#trait::{SetupContext, Tool, ToolError};
+
+/// Options controlling how the pattern is applied
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PygrepOptions {
+    /// Match case-insensitively (`-i`/`--ignore-case`)
+    pub ignore_case: bool,
+    /// Search the whole file at once with `^`/`$` matching at line boundaries
+    /// and `.` matching newlines (`--multiline`)
+    pub multiline: bool,
+    /// Fail for files that do *not* contain a match (`--negate`)
+    pub negate: bool,
+}
+
+impl PygrepOptions {
+    /// Parse pygrep options from hook arguments, ignoring unknown arguments
+    pub fn from_args(args: &[String]) -> Self {
+        let mut options = PygrepOptions::default();
+        for arg in args {
+            match arg.as_str() {
+                "-i" | "--ignore-case" => options.ignore_case = true,
+                "--multiline" => options.multiline = true,
+                "--negate" => options.negate = true,
+                other => log::warn!("Ignoring unknown pygrep argument: {}", other),
+            }
+        }
+        options
+    }
+}
+
+/// A tool that searches files for a regex, like pre-commit's pygrep
+pub struct PygrepTool {
+    /// The name of the tool
+    name: String,
+
+    /// The version of the tool
+    version: String,
+
+    /// The regex pattern to search for
+    pattern: String,
+
+    /// Options controlling the search
+    options: PygrepOptions,
+
+    /// The installation directory
+    install_dir: PathBuf,
+}
+
+impl PygrepTool {
+    /// Create a new pygrep tool
+    pub fn new(name: String, version: String, pattern: String, options: PygrepOptions) -> Self {
+        PygrepTool {
+            name,
+            version,
+            pattern,
+            options,
+            install_dir: PathBuf::new(),
+        }
+    }
+
+    /// Compile the pattern with the configured flags
+    fn compile(&self) -> Result<Regex, ToolError> {
+        RegexBuilder::new(&self.pattern)
+            .case_insensitive(self.options.ignore_case)
+            .multi_line(self.options.multiline)
+            .dot_matches_new_line(self.options.multiline)
+            .build()
+            .map_err(|e| ToolError::ExecutionError(format!("Invalid pygrep pattern '{}': {}", self.pattern, e)))
+    }
+
+    /// Search a file's content, returning the findings to report
+    ///
+    /// Findings use pygrep's `filename:line:content` format, or just the
+    /// filename for negated searches. Lines are matched without their line
+    /// ending, so `$` matches at the end of CRLF lines too. Fails when
+    /// matching backtracks too much.
+    pub fn search(&self, regex: &Regex, file: &str, content: &[u8]) -> Result<Vec<String>, ToolError> {
+        let mut findings = Vec::new();
+        let match_error = |e: fancy_regex::Error| {
+            ToolError::ExecutionError(format!("Could not match pygrep pattern '{}' in {}: {}", self.pattern, file, e))
+        };
+        let is_match = |line: &[u8]| regex.is_match(line).map_err(match_error);
+
+        if self.options.multiline {
+            let found = regex.find(content).map_err(match_error)?;
+            match (found, self.options.negate) {
+                (Some(m), false) => {
+                    // Report every line touched by the match
+                    let line_no = content[..m.start()].iter().filter(|&&b| b == b'\n').count() + 1;
+                    let line_start = content[..m.start()].iter().rposition(|&b| b == b'\n').map_or(0, |p| p + 1);
+                    let line_end = content[m.end()..].iter().position(|&b| b == b'\n').map_or(content.len(), |p| m.end() + p);
+                    let lines = String::from_utf8_lossy(&content[line_start..line_end]);
+                    findings.push(format!("{}:{}:{}", file, line_no, lines));
+                }
+                (None, true) => findings.push(file.to_string()),
+                _ => {}
+            }
+        } else if self.options.negate {
+            let mut found = false;
+            for line in lines(content) {
+                if is_match(line)? {
+                    found = true;
+                    break;
+                }
+            }
+            if !found {
+                findings.push(file.to_string());
+            }
+        } else {
+            for (index, line) in lines(content).enumerate() {
+                if is_match(line)? {
+                    findings.push(format!("{}:{}:{}", file, index + 1, String::from_utf8_lossy(line)));
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+}
+
+/// Split content into lines, without their `\n` or `\r\n` ending
+fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content.split(|&b| b == b'\n').map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+impl Tool for PygrepTool {
+    fn setup(&self, _ctx: &SetupContext) -> Result<(), ToolError> {
+        // Nothing to install, but make sure the pattern is valid up front
+        self.compile().map(|_| ())
+    }
+
+    fn run(&self, files: &[PathBuf]) -> Result<(), ToolError> {
+        let regex = self.compile()?;
+
+        let mut findings = Vec::new();
+        for file in files {
+            let content = fs::read(file)?;
+            findings.extend(self.search(&regex, &file.display().to_string(), &content)?);
+        }
+
+        if findings.is_empty() {
+            Ok(())
+        } else {
+            Err(ToolError::ExecutionError(findings.join("\n")))
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn is_installed(&self) -> bool {
+        true
+    }
+
+    fn install_dir(&self) -> &PathBuf {
+        &self.install_dir
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! This module provides functionality for managing Python environments and packages.
 
+use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
@@ -14,8 +15,180 @@ use tar::Archive;
 use zip::ZipArchive;
 use zstd::stream::Decoder as ZstdDecoder;
 
+use super::hermetic::{apply_hook_env, home_env};
+use super::{diagnostics, downloads, exit_status, network};
+use super::output::{capped, record_stdout};
 use super::r#trait::{SetupContext, Tool, ToolError};
 
+/// Release of python-build-standalone that CPython builds are downloaded from
+const PBS_RELEASE: &str = "20240224";
+
+/// Release of python-build-standalone that free-threaded CPython builds are downloaded from
+const PBS_FREETHREADED_RELEASE: &str = "20241016";
+
+/// Latest CPython version of each minor version in the python-build-standalone release
+const PBS_VERSIONS: &[&str] = &["3.8.18", "3.9.18", "3.10.13", "3.11.8", "3.12.2"];
+
+/// Latest free-threaded CPython version of each minor version in its python-build-standalone release
+const PBS_FREETHREADED_VERSIONS: &[&str] = &["3.13.0"];
+
+/// PyPy release that PyPy interpreters are downloaded from
+const PYPY_RELEASE: &str = "7.3.16";
+
+/// Python versions the PyPy release provides
+const PYPY_VERSIONS: &[&str] = &["3.9", "3.10"];
+
+/// Python version used when neither the hook nor `.python-version` asks for one
+const DEFAULT_PYTHON_VERSION: &str = "3.9.18";
+
+/// Forms of Python versions, for error messages
+const PYTHON_VERSION_FORMS: &str = "3.12.1 or 3.12 (CPython), cpython-3.12.1, 3.13.0t or cpython-3.13.0t (free-threaded CPython, 3.13 and later), pypy3.10 or pypy-3.10 (PyPy 3.9 or 3.10)";
+
+/// Python implementations RustyHook can install
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PythonImplementation {
+    /// CPython, from python-build-standalone
+    CPython,
+    /// PyPy, from pypy.org
+    PyPy,
+}
+
+/// A Python interpreter asked for by a version like `3.12.1`, `3.13t` or `pypy3.10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonRequest {
+    /// Implementation of the interpreter
+    pub implementation: PythonImplementation,
+    /// Version of Python, such as `3.12.1`
+    pub version: String,
+    /// Whether the interpreter is a free-threaded (no GIL) build
+    pub free_threaded: bool,
+}
+
+impl PythonRequest {
+    /// Parse a Python version
+    ///
+    /// CPython versions may start with `cpython-` and end with `t` for a
+    /// free-threaded build. PyPy versions start with `pypy`. A CPython minor
+    /// version like `3.12` is the latest patch version python-build-standalone
+    /// has a build of.
+    pub fn parse(request: &str) -> Result<Self, ToolError> {
+        let invalid = |reason: &str| {
+            ToolError::InstallationError(format!(
+                "Unsupported Python version '{}': {}. Supported forms: {}",
+                request, reason, PYTHON_VERSION_FORMS
+            ))
+        };
+
+        let lower = request.trim().to_ascii_lowercase();
+        let (implementation, rest) = if let Some(rest) = lower.strip_prefix("pypy") {
+            (PythonImplementation::PyPy, rest.strip_prefix('-').unwrap_or(rest))
+        } else if let Some(rest) = lower.strip_prefix("cpython") {
+            (PythonImplementation::CPython, rest.strip_prefix('-').unwrap_or(rest))
+        } else {
+            (PythonImplementation::CPython, lower.as_str())
+        };
+        let (version, free_threaded) = match rest.strip_suffix('t') {
+            Some(version) => (version, true),
+            None => (rest, false),
+        };
+
+        let parts: Vec<&str> = version.split('.').collect();
+        if !(2..=3).contains(&parts.len()) || parts.iter().any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit())) {
+            return Err(invalid("expected a version like 3.12 or 3.12.1"));
+        }
+        if parts[0] != "3" {
+            return Err(invalid("only Python 3 is supported"));
+        }
+        let minor: u32 = parts[1].parse().map_err(|_| invalid("the minor version is too large"))?;
+
+        match implementation {
+            PythonImplementation::PyPy if free_threaded => return Err(invalid("PyPy has no free-threaded builds")),
+            PythonImplementation::PyPy if parts.len() != 2 || !PYPY_VERSIONS.contains(&version) => {
+                return Err(invalid(&format!("PyPy {} provides Python {}", PYPY_RELEASE, PYPY_VERSIONS.join(" and "))))
+            }
+            PythonImplementation::CPython if free_threaded && minor < 13 => {
+                return Err(invalid("free-threaded builds start with Python 3.13"))
+            }
+            PythonImplementation::CPython if parts.len() == 2 => {
+                let (release, versions) = if free_threaded {
+                    (PBS_FREETHREADED_RELEASE, PBS_FREETHREADED_VERSIONS)
+                } else {
+                    (PBS_RELEASE, PBS_VERSIONS)
+                };
+                let prefix = format!("{}.", version);
+                let Some(patch) = versions.iter().find(|patch| patch.starts_with(&prefix)) else {
+                    let minors: Vec<&str> = versions.iter().filter_map(|patch| patch.rsplit_once('.').map(|(minor, _)| minor)).collect();
+                    return Err(invalid(&format!("python-build-standalone {} has builds of Python {}", release, minors.join(", "))));
+                };
+                return Ok(PythonRequest { implementation, version: patch.to_string(), free_threaded });
+            }
+            _ => {}
+        }
+
+        Ok(PythonRequest { implementation, version: version.to_string(), free_threaded })
+    }
+
+    /// Get the URL of the interpreter's archive for an OS and architecture
+    pub fn download_url(&self, os: &str, arch: &str) -> Result<String, ToolError> {
+        let unsupported = || ToolError::ExecutionError(format!("Unsupported OS/architecture for {}: {}/{}", self, os, arch));
+        match self.implementation {
+            PythonImplementation::PyPy => {
+                let (platform, extension) = match (os, arch) {
+                    ("windows", "x86_64") => ("win64", "zip"),
+                    ("macos", "x86_64") => ("macos_x86_64", "tar.bz2"),
+                    ("macos", "aarch64") => ("macos_arm64", "tar.bz2"),
+                    ("linux", "x86_64") => ("linux64", "tar.bz2"),
+                    ("linux", "aarch64") => ("aarch64", "tar.bz2"),
+                    _ => return Err(unsupported()),
+                };
+                Ok(format!(
+                    "https://downloads.python.org/pypy/pypy{}-v{}-{}.{}",
+                    self.version, PYPY_RELEASE, platform, extension
+                ))
+            }
+            PythonImplementation::CPython if self.free_threaded => {
+                let (triple, flavor) = match (os, arch) {
+                    ("windows", "x86_64") => ("x86_64-pc-windows-msvc", "pgo"),
+                    ("macos", "x86_64") => ("x86_64-apple-darwin", "pgo+lto"),
+                    ("macos", "aarch64") => ("aarch64-apple-darwin", "pgo+lto"),
+                    ("linux", "x86_64") => ("x86_64-unknown-linux-gnu", "pgo+lto"),
+                    ("linux", "aarch64") => ("aarch64-unknown-linux-gnu", "lto"),
+                    _ => return Err(unsupported()),
+                };
+                Ok(format!(
+                    "https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}+{}-{}-freethreaded+{}-full.tar.zst",
+                    PBS_FREETHREADED_RELEASE, self.version, PBS_FREETHREADED_RELEASE, triple, flavor
+                ))
+            }
+            PythonImplementation::CPython => {
+                let platform = match (os, arch) {
+                    ("windows", "x86_64") => "windows-amd64-shared-pgo",
+                    ("windows", "aarch64") => "windows-arm64-shared-pgo",
+                    ("macos", "x86_64") => "macos-x86_64-shared-install_only",
+                    ("macos", "aarch64") => "macos-arm64-shared-install_only",
+                    ("linux", "x86_64") => "linux-x86_64-shared-install_only",
+                    ("linux", "aarch64") => "linux-aarch64-shared-install_only",
+                    _ => return Err(unsupported()),
+                };
+                Ok(format!(
+                    "https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-{}.tar.zst",
+                    PBS_RELEASE, self.version, PBS_RELEASE, platform
+                ))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for PythonRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.implementation {
+            PythonImplementation::PyPy => write!(f, "PyPy {}", self.version),
+            PythonImplementation::CPython if self.free_threaded => write!(f, "Python {} (free-threaded)", self.version),
+            PythonImplementation::CPython => write!(f, "Python {}", self.version),
+        }
+    }
+}
+
 /// Represents a Python tool
 pub struct PythonTool {
     /// Name of the tool
@@ -29,6 +202,9 @@ pub struct PythonTool {
 
     /// Installation directory
     install_dir: PathBuf,
+
+    /// Additional environment variables for the tool's processes
+    env: HashMap<String, String>,
 }
 
 impl PythonTool {
@@ -38,19 +214,29 @@ impl PythonTool {
         let version_str = version.into();
 
         // Default installation directory
-        let mut install_dir = std::env::temp_dir();
-        install_dir.push(".rustyhook");
-        install_dir.push("venvs");
-        install_dir.push(format!("python-{}-{}", name_str, version_str));
+        let install_dir = crate::cache::default_cache_dir().join("venvs").join(format!("python-{}-{}", name_str, version_str));
 
         PythonTool {
             name: name_str,
             version: version_str,
             packages,
             install_dir,
+            env: HashMap::new(),
         }
     }
 
+    /// Set additional environment variables for the tool's processes
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set the directory the tool is installed in
+    pub fn with_install_dir(mut self, install_dir: PathBuf) -> Self {
+        self.install_dir = install_dir;
+        self
+    }
+
     /// Read Python version from .python-version file
     fn read_python_version_file(dir: &Path) -> Option<String> {
         // Start from the given directory and look for .python-version file
@@ -99,10 +285,10 @@ impl PythonTool {
     }
 
     /// Get the Python download URL based on the operating system and architecture
-    /// Uses python-build-standalone from Gregory Szorc's project
+    /// Uses python-build-standalone from Gregory Szorc's project, or pypy.org for PyPy
     fn get_python_download_url(ctx: Option<&SetupContext>) -> Result<String, ToolError> {
         // Default to Python 3.9.18 as it's stable and widely compatible
-        let mut version = "3.9.18".to_string();
+        let mut version = DEFAULT_PYTHON_VERSION.to_string();
 
         // Check for .python-version file if context is provided
         if let Some(_context) = ctx {
@@ -115,30 +301,7 @@ impl PythonTool {
             }
         }
 
-        // python-build-standalone version
-        let pbs_version = "20240224";
-
-        // Determine the OS and architecture
-        let os = std::env::consts::OS;
-        let arch = std::env::consts::ARCH;
-
-        let url = match (os, arch) {
-            ("windows", "x86_64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-windows-amd64-shared-pgo.tar.zst", 
-                pbs_version, version, pbs_version),
-            ("windows", "aarch64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-windows-arm64-shared-pgo.tar.zst", 
-                pbs_version, version, pbs_version),
-            ("macos", "x86_64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-macos-x86_64-shared-install_only.tar.zst", 
-                pbs_version, version, pbs_version),
-            ("macos", "aarch64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-macos-arm64-shared-install_only.tar.zst", 
-                pbs_version, version, pbs_version),
-            ("linux", "x86_64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-linux-x86_64-shared-install_only.tar.zst", 
-                pbs_version, version, pbs_version),
-            ("linux", "aarch64") => format!("https://github.com/indygreg/python-build-standalone/releases/download/{}/cpython-{}-{}-linux-aarch64-shared-install_only.tar.zst", 
-                pbs_version, version, pbs_version),
-            _ => return Err(ToolError::ExecutionError(format!("Unsupported OS/architecture: {}/{}", os, arch))),
-        };
-
-        Ok(url)
+        PythonRequest::parse(&version)?.download_url(std::env::consts::OS, std::env::consts::ARCH)
     }
 
     /// Download Python from the official website
@@ -150,7 +313,7 @@ impl PythonTool {
         let url = Self::get_python_download_url(ctx)?;
 
         // Extract the filename from the URL
-        let filename = url.split('/').last().unwrap_or("python.tgz");
+        let filename = url.split('/').next_back().unwrap_or("python.tgz");
         let download_path = download_dir.join(filename);
 
         // Skip download if the file already exists
@@ -160,6 +323,7 @@ impl PythonTool {
         }
 
         // Download the file
+        network::check_allowed("Python")?;
         log::info!("Downloading Python from {}", url);
         let client = Client::new();
         let mut response = client.get(&url)
@@ -284,7 +448,24 @@ impl PythonTool {
                 }
             }
 
-            Ok(extract_dir.clone())
+            // PyPy archives hold a single pypy* directory
+            Ok(Self::find_pypy_dir(extract_dir).unwrap_or_else(|| extract_dir.clone()))
+        } else if filename.ends_with(".tar.bz2") {
+            // Extract .tar.bz2 archive (used by PyPy) with tar, which has bzip2 support
+            log::info!("Extracting Python from {:?} to {:?}", archive_path, extract_dir);
+            let status = Command::new("tar")
+                .arg("-xjf")
+                .arg(archive_path)
+                .arg("-C")
+                .arg(extract_dir)
+                .status()
+                .map_err(|e| ToolError::ExecutionError(format!("Failed to extract archive: {}", e)))?;
+            if !status.success() {
+                return Err(ToolError::ExecutionError(format!("Failed to extract archive {:?}", archive_path)));
+            }
+
+            Self::find_pypy_dir(extract_dir)
+                .ok_or_else(|| ToolError::ExecutionError("Failed to find PyPy directory after extraction".to_string()))
         } else {
             // For Windows .exe and macOS .pkg installers, we can't extract them directly
             // We would need to run the installer, which is more complex
@@ -292,8 +473,28 @@ impl PythonTool {
         }
     }
 
+    /// Find the Python executable in the bin directory of a prebuilt interpreter
+    ///
+    /// Free-threaded builds may only have `python3t`, and PyPy `pypy3`.
+    fn find_python_executable(bin_dir: &Path) -> PathBuf {
+        ["python3", "python3t", "pypy3"]
+            .iter()
+            .map(|name| bin_dir.join(name))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| bin_dir.join("python3"))
+    }
+
+    /// Find the directory a PyPy archive was extracted into
+    fn find_pypy_dir(extract_dir: &Path) -> Option<PathBuf> {
+        fs::read_dir(extract_dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .find(|path| path.is_dir() && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("pypy")))
+    }
+
     /// Build Python from source (for Linux)
-    fn build_python(python_dir: &PathBuf, install_dir: &PathBuf) -> Result<PathBuf, ToolError> {
+    fn build_python(python_dir: &PathBuf, install_dir: &Path) -> Result<PathBuf, ToolError> {
         log::info!("Building Python from source at {:?}", python_dir);
 
         // Configure
@@ -347,8 +548,8 @@ impl PythonTool {
     /// Install Python locally
     fn install_python(ctx: &SetupContext) -> Result<PathBuf, ToolError> {
         // Create directories
-        let download_dir = ctx.cache_dir.join("downloads");
-        let extract_dir = ctx.cache_dir.join("extracted");
+        let download_dir = ctx.cache_dir.join(downloads::DOWNLOADS_DIR);
+        let extract_dir = ctx.cache_dir.join(downloads::EXTRACTED_DIR);
         let install_dir = ctx.install_dir.join("python");
 
         // Download Python, passing the context to use .python-version if available
@@ -360,14 +561,20 @@ impl PythonTool {
         // Get the filename to determine if we're using python-build-standalone
         let filename = archive_path.file_name().unwrap().to_string_lossy();
 
-        let python_path = if filename.ends_with(".tar.zst") {
-            // For python-build-standalone, we don't need to build from source
-            // The Python executable is already in the bin directory
-            let bin_dir = python_dir.join("bin");
+        let python_path = if filename.ends_with(".tar.zst") || filename.starts_with("pypy") {
+            // For python-build-standalone and PyPy, we don't need to build from source
+            // The Python executable is already in the bin directory, which full
+            // builds (like the free-threaded ones) keep under install/
+            let prebuilt_dir = if python_dir.join("install").join("bin").is_dir() {
+                python_dir.join("install")
+            } else {
+                python_dir.clone()
+            };
+            let bin_dir = prebuilt_dir.join("bin");
             let python_exe = if cfg!(windows) {
                 bin_dir.join("python.exe")
             } else {
-                bin_dir.join("python3")
+                Self::find_python_executable(&bin_dir)
             };
 
             if !python_exe.exists() {
@@ -392,7 +599,7 @@ impl PythonTool {
                     .arg("/E")
                     .arg("/I")
                     .arg("/Y")
-                    .arg(python_dir.to_str().unwrap())
+                    .arg(prebuilt_dir.to_str().unwrap())
                     .arg(install_dir.to_str().unwrap())
                     .status()
                     .map_err(|e| ToolError::ExecutionError(format!("Failed to copy Python: {}", e)))?;
@@ -404,7 +611,7 @@ impl PythonTool {
                 // On Unix-like systems, use cp
                 let status = Command::new("cp")
                     .arg("-R")
-                    .arg(python_dir.to_str().unwrap())
+                    .arg(prebuilt_dir.to_str().unwrap())
                     .arg(install_dir.to_str().unwrap())
                     .status()
                     .map_err(|e| ToolError::ExecutionError(format!("Failed to copy Python: {}", e)))?;
@@ -415,16 +622,13 @@ impl PythonTool {
             }
 
             // Return the path to the Python executable in the install directory
-            if cfg!(windows) {
-                install_dir.join("bin").join("python.exe")
-            } else {
-                install_dir.join("bin").join("python3")
-            }
+            install_dir.join("bin").join(python_exe.file_name().unwrap())
         } else {
             // For traditional Python source, build from source
             Self::build_python(&python_dir, &install_dir)?
         };
 
+        downloads::clean_up_install(&archive_path, Some(&python_dir));
         Ok(python_path)
     }
 
@@ -474,9 +678,12 @@ impl PythonTool {
 
         // Install all packages at once for better performance
         if !self.packages.is_empty() {
+            network::check_allowed(&self.packages.join(", "))?;
+
             // First, try to install uv directly using python -m pip
             log::info!("Installing uv package manager...");
             let status = Command::new(&python)
+                .envs(home_env(&self.env))
                 .arg("-m")
                 .arg("pip")
                 .arg("install")
@@ -513,7 +720,8 @@ impl PythonTool {
 
                 // Use uv with the specified Python version
                 let mut cmd = Command::new(&uv);
-                cmd.arg("pip")
+                cmd.envs(home_env(&self.env))
+                    .arg("pip")
                     .arg("--python")
                     .arg(version)
                     .arg("install");
@@ -551,9 +759,10 @@ impl PythonTool {
     }
 
     /// Install packages using uv
-    fn install_packages_with_uv(&self, uv: &PathBuf, ctx: &SetupContext) -> Result<(), ToolError> {
+    fn install_packages_with_uv(&self, uv: &Path, ctx: &SetupContext) -> Result<(), ToolError> {
         let mut cmd = Command::new(uv);
-        cmd.arg("pip")
+        cmd.envs(home_env(&self.env))
+            .arg("pip")
             .arg("install");
 
         // Add all packages as arguments
@@ -590,7 +799,8 @@ impl PythonTool {
     /// Install packages using pip
     fn install_packages_with_pip(&self, python: &PathBuf, _ctx: &SetupContext) -> Result<(), ToolError> {
         let mut cmd = Command::new(python);
-        cmd.arg("-m")
+        cmd.envs(home_env(&self.env))
+            .arg("-m")
             .arg("pip")
             .arg("install");
 
@@ -646,7 +856,8 @@ impl Tool for PythonTool {
             };
 
             // Run the pre-commit-hooks module with the hook ID
-            let mut command = Command::new(&python_path);
+            let mut command = network::hook_command(&python_path);
+            apply_hook_env(&mut command, &self.bin_dirs(), &self.env);
             command.arg("-m")
                    .arg(format!("pre_commit_hooks.{}", self.name.replace('-', "_")));
 
@@ -661,12 +872,14 @@ impl Tool for PythonTool {
                 .map_err(|e| ToolError::ExecutionError(format!("Failed to run pre-commit-hooks module {}: {}", self.name, e)))?;
 
             // Check the status
-            if output.status.success() {
+            record_stdout(&output.stdout);
+            if exit_status::passed(&output.status) {
                 return Ok(());
             } else {
+                diagnostics::report_failure(&self.name, &command);
                 // Try to convert stdout and stderr to strings, but handle non-UTF-8 data
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stdout = capped("stdout", &output.stdout);
+                let stderr = capped("stderr", &output.stderr);
 
                 // Log the command and its output
                 log::error!("Command failed: {} -m pre_commit_hooks.{} {}", 
@@ -694,7 +907,8 @@ impl Tool for PythonTool {
         };
 
         // Run the tool on the files
-        let mut command = Command::new(&tool_path);
+        let mut command = network::hook_command(&tool_path);
+        apply_hook_env(&mut command, &self.bin_dirs(), &self.env);
 
         // Add files as arguments
         for file in files {
@@ -707,12 +921,14 @@ impl Tool for PythonTool {
             .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.name, e)))?;
 
         // Check the status
-        if output.status.success() {
+        record_stdout(&output.stdout);
+        if exit_status::passed(&output.status) {
             Ok(())
         } else {
+            diagnostics::report_failure(&self.name, &command);
             // Try to convert stdout and stderr to strings, but handle non-UTF-8 data
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = capped("stdout", &output.stdout);
+            let stderr = capped("stderr", &output.stderr);
 
             // Log the command and its output
             log::error!("Command failed: {} {}", tool_path.display(), files.iter().map(|f| f.display().to_string()).collect::<Vec<_>>().join(" "));
@@ -765,4 +981,17 @@ impl Tool for PythonTool {
     fn install_dir(&self) -> &PathBuf {
         &self.install_dir
     }
+
+    fn setup_manifest(&self) -> Option<String> {
+        Some(format!("python\n{}\n{}\n{}", self.name, self.version, self.packages.join("\n")))
+    }
+
+    fn bin_dirs(&self) -> Vec<PathBuf> {
+        // The virtualenv's bin directory also holds its python interpreter
+        if cfg!(windows) {
+            vec![self.install_dir.join("Scripts")]
+        } else {
+            vec![self.install_dir.join("bin")]
+        }
+    }
 }
@@ -0,0 +1,240 @@
+//! Queries to package registries, the GitHub API and remote git repos
+//!
+//! Resolving `version: latest` and `autoupdate` ask a remote service about
+//! every repo or package of the config, which hits rate limits in big
+//! configs, most of all GitHub's 60 unauthenticated API requests an hour.
+//! Every such query goes through this module, which:
+//!
+//! - runs at most [`concurrency`] queries at a time, 4 unless
+//!   `RUSTYHOOK_QUERY_CONCURRENCY` sets another limit, and runs batches of
+//!   queries in parallel up to that limit with [`batch`]
+//! - keeps HTTP responses with their `ETag` and `Last-Modified` in the cache
+//!   directory, and revalidates them with conditional requests, which GitHub
+//!   doesn't count against the rate limit when they're unchanged
+//! - authenticates GitHub API requests with `GITHUB_TOKEN` when it is set,
+//!   raising the limit to 5000 requests an hour
+//! - lists the refs of each remote git repo at most once per process
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{CacheManager, METADATA_DIR};
+
+/// Environment variable setting how many queries run at a time
+pub const CONCURRENCY_ENV: &str = "RUSTYHOOK_QUERY_CONCURRENCY";
+
+/// Environment variable holding the token GitHub API requests are authenticated with
+pub const GITHUB_TOKEN_ENV: &str = "GITHUB_TOKEN";
+
+/// Number of queries running at a time by default
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Directory of the metadata cache holding HTTP responses
+pub const RESPONSES_DIR: &str = "responses";
+
+/// Host of the GitHub API
+const GITHUB_API: &str = "https://api.github.com/";
+
+/// Number of queries running
+static RUNNING: Mutex<usize> = Mutex::new(0);
+
+/// Signaled when a query finishes
+static FINISHED: Condvar = Condvar::new();
+
+/// Refs of the remote git repos listed in this process, by URL
+static REFS: Mutex<Option<HashMap<String, Vec<RemoteRef>>>> = Mutex::new(None);
+
+/// Get how many queries run at a time
+pub fn concurrency() -> usize {
+    std::env::var(CONCURRENCY_ENV)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|limit| *limit > 0)
+        .unwrap_or(DEFAULT_CONCURRENCY)
+}
+
+/// A slot of the queries running, freed when dropped
+struct Slot;
+
+impl Slot {
+    /// Wait until fewer than [`concurrency`] queries are running, and take a slot
+    fn acquire() -> Self {
+        let limit = concurrency();
+        let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        while *running >= limit {
+            running = FINISHED.wait(running).unwrap_or_else(|e| e.into_inner());
+        }
+        *running += 1;
+        Slot
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        *running -= 1;
+        FINISHED.notify_one();
+    }
+}
+
+/// Run a query for each item, up to [`concurrency`] at a time
+///
+/// Results are in the order of the items.
+pub fn batch<T, R, F>(items: &[T], query: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = concurrency().min(items.len());
+    if threads <= 1 {
+        return items.iter().map(&query).collect();
+    }
+    let next = Mutex::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let index = {
+                    let mut next = next.lock().unwrap_or_else(|e| e.into_inner());
+                    *next += 1;
+                    *next - 1
+                };
+                let Some(item) = items.get(index) else { break };
+                let result = query(item);
+                results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap_or_else(|e| e.into_inner()).into_iter().flatten().collect()
+}
+
+/// An HTTP response kept for conditional requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    /// `ETag` of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    /// `Last-Modified` date of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    /// Body of the response
+    body: String,
+}
+
+/// Get the token GitHub API requests are authenticated with
+fn github_token() -> Option<String> {
+    std::env::var(GITHUB_TOKEN_ENV).ok().filter(|token| !token.trim().is_empty())
+}
+
+/// Get a URL, revalidating the response kept in the cache root if any
+///
+/// Returns the body of the response.
+pub fn get(url: &str, cache_root: Option<&Path>) -> Result<String, String> {
+    let cache = cache_root.map(|root| CacheManager::new(root.join(METADATA_DIR).join(RESPONSES_DIR), Duration::ZERO));
+    let key = CacheManager::entry_name(url);
+    let cached = cache.as_ref().and_then(|cache| cache.get_stale::<CachedResponse>(&key).ok().flatten());
+
+    // GitHub rejects requests without a user agent
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!("rustyhook/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut request = client.get(url);
+    if url.starts_with(GITHUB_API) {
+        request = request.header("Accept", "application/vnd.github+json");
+        if let Some(token) = github_token() {
+            request = request.bearer_auth(token);
+        }
+    }
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.header("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.header("If-Modified-Since", last_modified);
+        }
+    }
+
+    let response = {
+        let _slot = Slot::acquire();
+        request.send().map_err(|e| e.to_string())?
+    };
+    let status = response.status();
+    if status == reqwest::StatusCode::NOT_MODIFIED {
+        if let Some(cached) = cached {
+            log::debug!("{} is unchanged, using the cached response", url);
+            return Ok(cached.body);
+        }
+    }
+    if let Some(error) = rate_limit_error(url, &response) {
+        return Err(error);
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+
+    let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(String::from);
+    let (etag, last_modified) = (header("etag"), header("last-modified"));
+    let body = response.text().map_err(|e| e.to_string())?;
+    if let Some(cache) = cache.filter(|_| etag.is_some() || last_modified.is_some()) {
+        let response = CachedResponse { etag, last_modified, body: body.clone() };
+        if let Err(e) = cache.set(&key, &response) {
+            log::debug!("Could not cache the response of {}: {:?}", url, e);
+        }
+    }
+    Ok(body)
+}
+
+/// Describe a response refused because the rate limit is exhausted
+fn rate_limit_error(url: &str, response: &reqwest::blocking::Response) -> Option<String> {
+    let status = response.status();
+    if status != reqwest::StatusCode::FORBIDDEN && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok());
+    if status == reqwest::StatusCode::FORBIDDEN && header("x-ratelimit-remaining") != Some("0") {
+        return None;
+    }
+    let reset = header("x-ratelimit-reset")
+        .and_then(|reset| reset.parse::<i64>().ok())
+        .and_then(|reset| chrono::DateTime::from_timestamp(reset, 0))
+        .map(|reset| format!(", it resets at {}", reset.with_timezone(&chrono::Local).format("%H:%M:%S")))
+        .unwrap_or_default();
+    let hint = if url.starts_with(GITHUB_API) && github_token().is_none() {
+        format!(". Set {} to raise the limit", GITHUB_TOKEN_ENV)
+    } else {
+        String::new()
+    };
+    Some(format!("HTTP {}: rate limit exceeded{}{}", status, reset, hint))
+}
+
+/// A ref of a remote git repo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRef {
+    /// Full name of the ref, like `refs/tags/v1.0.0^{}`
+    pub name: String,
+    /// Commit SHA the ref points to
+    pub oid: String,
+}
+
+/// List the refs of a remote git repo, at most once per process
+pub fn list_refs(repo_url: &str) -> Result<Vec<RemoteRef>, git2::Error> {
+    if let Some(refs) = REFS.lock().unwrap_or_else(|e| e.into_inner()).as_ref().and_then(|listed| listed.get(repo_url)) {
+        return Ok(refs.clone());
+    }
+
+    let refs: Vec<RemoteRef> = {
+        let _slot = Slot::acquire();
+        let mut remote = git2::Remote::create_detached(repo_url)?;
+        remote.connect(git2::Direction::Fetch)?;
+        remote.list()?.iter().map(|head| RemoteRef { name: head.name().to_string(), oid: head.oid().to_string() }).collect()
+    };
+    REFS.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert_with(HashMap::new).insert(repo_url.to_string(), refs.clone());
+    Ok(refs)
+}
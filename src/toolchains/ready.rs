@@ -0,0 +1,33 @@
+//! Environment readiness markers for RustyHook
+//!
+//! A `.ready` marker is written to an environment's directory once setup has
+//! completed. It holds a hash of the setup manifest, so environments whose
+//! setup was interrupted, or that were built for a different manifest, are
+//! treated as not installed.
+
+use std::fs;
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+/// Name of the marker file written after a successful setup
+pub const READY_MARKER: &str = ".ready";
+
+/// Hash a setup manifest
+pub fn manifest_hash(manifest: &str) -> String {
+    Sha256::digest(manifest.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Check whether the environment in a directory was set up for the manifest
+pub fn is_ready(install_dir: &Path, manifest: &str) -> bool {
+    match fs::read_to_string(install_dir.join(READY_MARKER)) {
+        Ok(content) => content.trim() == manifest_hash(manifest),
+        Err(_) => false,
+    }
+}
+
+/// Mark the environment in a directory as set up for the manifest
+pub fn mark_ready(install_dir: &Path, manifest: &str) -> std::io::Result<()> {
+    fs::create_dir_all(install_dir)?;
+    fs::write(install_dir.join(READY_MARKER), format!("{}\n", manifest_hash(manifest)))
+}
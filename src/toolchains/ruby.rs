@@ -2,19 +2,81 @@
 //!
 //! This module provides functionality for managing Ruby environments and gems.
 
+use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use flate2::read::GzDecoder;
 use reqwest::blocking::Client;
 use tar::Archive;
 use zip::ZipArchive;
 
+use super::hermetic::{apply_hook_env, home_env};
+use super::{diagnostics, downloads, exit_status, network};
+use super::output::{capped, is_capturing, record_stdout};
 use super::r#trait::{SetupContext, Tool, ToolError};
 
+/// Whether Rubies installed by rbenv, rvm or asdf are used instead of installing one
+static REUSE_INSTALLED_RUBIES: AtomicBool = AtomicBool::new(true);
+
+/// Set whether Rubies installed by rbenv, rvm or asdf are used instead of installing one
+pub fn set_reuse_installed_rubies(reuse: bool) {
+    REUSE_INSTALLED_RUBIES.store(reuse, Ordering::SeqCst);
+}
+
+/// Check whether Rubies installed by rbenv, rvm or asdf are used instead of installing one
+pub fn reuse_installed_rubies() -> bool {
+    REUSE_INSTALLED_RUBIES.load(Ordering::SeqCst)
+}
+
+/// Get the directories rbenv, rvm and asdf would install a Ruby version in
+///
+/// Their root directories come from `RBENV_ROOT`, `rvm_path` and
+/// `ASDF_DATA_DIR`, or their defaults in `home`.
+pub fn version_manager_dirs(version: &str, home: Option<&Path>) -> Vec<PathBuf> {
+    let root = |env_var: &str, default: &str| {
+        env::var_os(env_var)
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(default)))
+    };
+    [
+        root("RBENV_ROOT", ".rbenv").map(|root| root.join("versions").join(version)),
+        root("rvm_path", ".rvm").map(|root| root.join("rubies").join(format!("ruby-{}", version))),
+        root("ASDF_DATA_DIR", ".asdf").map(|root| root.join("installs").join("ruby").join(version)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Find a Ruby of the given version installed by rbenv, rvm or asdf
+///
+/// Returns the directory with the Ruby's `bin` directory.
+pub fn find_installed_ruby(version: &str, home: Option<&Path>) -> Option<PathBuf> {
+    let exe = if cfg!(windows) { "ruby.exe" } else { "ruby" };
+    version_manager_dirs(version, home).into_iter().find(|dir| dir.join("bin").join(exe).is_file())
+}
+
+/// Find a Ruby of a version manager to reuse, unless reusing them is turned off
+fn reusable_ruby(version: &str) -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    reuse_installed_rubies().then(|| find_installed_ruby(version, home.as_deref())).flatten()
+}
+
+/// Get the directory Ruby is installed in for a version
+///
+/// This is a Ruby installed by a version manager when one matches and
+/// reusing them is allowed, and otherwise the Ruby RustyHook downloads or
+/// builds into the shared runtimes directory.
+fn ruby_root(runtimes_dir: &Path, version: &str) -> PathBuf {
+    reusable_ruby(version).unwrap_or_else(|| runtimes_dir.join("ruby").join(version))
+}
+
 /// Represents a Ruby tool
 pub struct RubyTool {
     /// Name of the tool
@@ -28,6 +90,12 @@ pub struct RubyTool {
 
     /// Installation directory
     install_dir: PathBuf,
+
+    /// Directory Ruby builds are shared in
+    runtimes_dir: PathBuf,
+
+    /// Additional environment variables for the tool's processes
+    env: HashMap<String, String>,
 }
 
 impl RubyTool {
@@ -36,8 +104,9 @@ impl RubyTool {
         let name_str = name.into();
         let version_str = version.into();
 
-        // Default installation directory - use .runtime/ruby/{version} as per docs/ruby.md
-        let mut install_dir = PathBuf::from(".runtime");
+        // Default installation directory, in the shared runtimes directory
+        let runtimes_dir = crate::cache::runtimes_dir(&crate::cache::default_cache_dir());
+        let mut install_dir = runtimes_dir.clone();
         install_dir.push("ruby");
         install_dir.push(&version_str);
         install_dir.push(format!("tool-{}", name_str));
@@ -47,9 +116,29 @@ impl RubyTool {
             version: version_str,
             gems,
             install_dir,
+            runtimes_dir,
+            env: HashMap::new(),
         }
     }
 
+    /// Set additional environment variables for the tool's processes
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set the directory the tool is installed in
+    pub fn with_install_dir(mut self, install_dir: PathBuf) -> Self {
+        self.install_dir = install_dir;
+        self
+    }
+
+    /// Set the directory Ruby builds are shared in
+    pub fn with_runtimes_dir(mut self, runtimes_dir: PathBuf) -> Self {
+        self.runtimes_dir = runtimes_dir;
+        self
+    }
+
     /// Read Ruby version from .ruby-version file
     fn read_ruby_version_file(dir: &Path) -> Option<String> {
         // Start from the given directory and look for .ruby-version file
@@ -158,7 +247,7 @@ impl RubyTool {
         let url = Self::get_ruby_download_url(version)?;
 
         // Extract the filename from the URL
-        let filename = url.split('/').last().unwrap_or("ruby.tgz");
+        let filename = url.split('/').next_back().unwrap_or("ruby.tgz");
         let download_path = download_dir.join(filename);
 
         // Skip download if the file already exists and force is not set
@@ -180,6 +269,7 @@ impl RubyTool {
         }
 
         // Download the file
+        network::check_allowed(&format!("Ruby {}", version))?;
         log::info!("Downloading Ruby from {}", url);
         let client = Client::new();
         let mut response = client.get(&url)
@@ -350,18 +440,22 @@ impl RubyTool {
     }
 
     /// Install Ruby locally using prebuilt binaries
-    fn install_ruby(ctx: &SetupContext) -> Result<PathBuf, ToolError> {
+    fn install_ruby(&self, ctx: &SetupContext) -> Result<PathBuf, ToolError> {
         // Create directories
-        let download_dir = ctx.cache_dir.join("downloads");
-        let extract_dir = ctx.cache_dir.join("extracted");
-        let runtime_dir = PathBuf::from(".runtime").join("ruby");
+        let download_dir = ctx.cache_dir.join(downloads::DOWNLOADS_DIR);
+        let extract_dir = ctx.cache_dir.join(downloads::EXTRACTED_DIR);
 
         // Determine Ruby version to use
-        let ruby_tool = RubyTool::new("bundler", "2.4.10", vec![]);
-        let version = ruby_tool.determine_ruby_version(ctx.version.as_deref())?;
+        let version = self.determine_ruby_version(ctx.version.as_deref())?;
+
+        // Use a Ruby of rbenv, rvm or asdf when one matches
+        if let Some(dir) = reusable_ruby(&version) {
+            log::info!("Using Ruby {} installed at {}", version, dir.display());
+            return Ok(dir.join("bin").join(if cfg!(windows) { "ruby.exe" } else { "ruby" }));
+        }
 
-        // Create the version-specific runtime directory
-        let version_dir = runtime_dir.join(&version);
+        // Create the version-specific runtime directory, shared by every repository
+        let version_dir = ruby_root(&self.runtimes_dir, &version);
         fs::create_dir_all(&version_dir)?;
 
         // Check if Ruby is already installed
@@ -382,14 +476,21 @@ impl RubyTool {
         // Extract Ruby
         let ruby_dir = Self::extract_ruby(&archive_path, &extract_dir)?;
 
+        let ruby_path = Self::install_extracted_ruby(&ruby_dir, &version_dir, &ruby_exe)?;
+        downloads::clean_up_install(&archive_path, Some(&ruby_dir));
+        Ok(ruby_path)
+    }
+
+    /// Install Ruby from its extracted archive into the runtime directory
+    fn install_extracted_ruby(ruby_dir: &PathBuf, version_dir: &PathBuf, ruby_exe: &PathBuf) -> Result<PathBuf, ToolError> {
         // For Windows, we can use the extracted Ruby directly
         if cfg!(windows) {
             // Find the Ruby executable in the extracted directory
             let extracted_ruby_exe = ruby_dir.join("bin").join("ruby.exe");
             if extracted_ruby_exe.exists() {
                 // Copy the extracted Ruby to the runtime directory
-                Self::copy_directory(&ruby_dir, &version_dir)?;
-                return Ok(ruby_exe);
+                Self::copy_directory(ruby_dir, version_dir)?;
+                return Ok(ruby_exe.clone());
             }
 
             // Try alternative paths for Windows RubyInstaller
@@ -400,21 +501,21 @@ impl RubyTool {
                 let bin_dir = version_dir.join("bin");
                 fs::create_dir_all(&bin_dir)?;
                 fs::copy(&alt_ruby_exe, bin_dir.join("ruby.exe"))?;
-                return Ok(ruby_exe);
+                return Ok(ruby_exe.clone());
             }
 
-            return Err(ToolError::ExecutionError(format!("Ruby executable not found in extracted directory")));
+            Err(ToolError::ExecutionError("Ruby executable not found in extracted directory".to_string()))
         } else {
             // For Unix systems, check if we have a prebuilt binary
             let extracted_ruby_exe = ruby_dir.join("bin").join("ruby");
             if extracted_ruby_exe.exists() {
                 // Copy the extracted Ruby to the runtime directory
-                Self::copy_directory(&ruby_dir, &version_dir)?;
+                Self::copy_directory(ruby_dir, version_dir)?;
 
                 // Make the Ruby executable executable
                 let chmod_status = Command::new("chmod")
                     .arg("+x")
-                    .arg(&ruby_exe)
+                    .arg(ruby_exe)
                     .status()
                     .map_err(|e| ToolError::ExecutionError(format!("Failed to make Ruby executable: {}", e)))?;
 
@@ -422,11 +523,11 @@ impl RubyTool {
                     return Err(ToolError::ExecutionError("Failed to make Ruby executable".to_string()));
                 }
 
-                return Ok(ruby_exe);
+                Ok(ruby_exe.clone())
             } else {
                 // If we don't have a prebuilt binary, build from source
                 log::info!("No prebuilt binary found, building Ruby from source...");
-                Self::build_ruby(&ruby_dir, &version_dir)
+                Self::build_ruby(ruby_dir, version_dir)
             }
         }
     }
@@ -473,7 +574,7 @@ impl RubyTool {
     /// Install gems directly using the Ruby executable
     fn install_gems(&self, ctx: &SetupContext) -> Result<(), ToolError> {
         // Install Ruby locally
-        let ruby_path = Self::install_ruby(ctx)?;
+        let ruby_path = self.install_ruby(ctx)?;
 
         // Create the bin directory
         let bin_dir = ctx.install_dir.join("bin");
@@ -481,10 +582,12 @@ impl RubyTool {
 
         // Install each gem directly using the Ruby executable
         for gem in &self.gems {
+            network::check_allowed(&format!("the {} gem", gem))?;
             log::info!("Installing gem: {}", gem);
 
             // Use the Ruby executable to install the gem
             let status = Command::new(&ruby_path)
+                .envs(home_env(&self.env))
                 .arg("-e")
                 .arg(format!("require 'rubygems'; require 'rubygems/gem_runner'; Gem::GemRunner.new.run(['install', '{}', '--install-dir', '{}', '--bindir', '{}'])", 
                     gem, ctx.install_dir.display(), bin_dir.display()))
@@ -549,21 +652,46 @@ impl Tool for RubyTool {
         let tool_path = self.install_dir.join("bin").join(&self.name);
 
         // Run the tool on the files
-        let mut command = Command::new(&tool_path);
+        let mut command = network::hook_command(&tool_path);
+        apply_hook_env(&mut command, &self.bin_dirs(), &self.env);
 
         // Add files as arguments
         for file in files {
             command.arg(file);
         }
 
-        // Execute the command
-        let status = command
-            .status()
+        // Capture the output only when it is parsed, so the hook's output shows otherwise
+        if !is_capturing() {
+            let status = command
+                .status()
+                .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.name, e)))?;
+            if !exit_status::passed(&status) {
+                diagnostics::report_failure(&self.name, &command);
+                return Err(ToolError::ExecutionError(
+                    format!("{} failed with exit code {:?}", self.name, status.code()),
+                ));
+            }
+            return Ok(());
+        }
+
+        // Execute the command with output capture
+        let output = command
+            .output()
             .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.name, e)))?;
 
-        if !status.success() {
+        record_stdout(&output.stdout);
+        if !exit_status::passed(&output.status) {
+            diagnostics::report_failure(&self.name, &command);
+            let stdout = capped("stdout", &output.stdout);
+            let stderr = capped("stderr", &output.stderr);
+            if !stdout.is_empty() {
+                log::error!("Command stdout: {}", stdout);
+            }
+            if !stderr.is_empty() {
+                log::error!("Command stderr: {}", stderr);
+            }
             return Err(ToolError::ExecutionError(
-                format!("{} failed with exit code {:?}", self.name, status.code()),
+                format!("{} failed with exit code {:?}", self.name, output.status.code()),
             ));
         }
 
@@ -587,4 +715,19 @@ impl Tool for RubyTool {
     fn install_dir(&self) -> &PathBuf {
         &self.install_dir
     }
+
+    fn setup_manifest(&self) -> Option<String> {
+        Some(format!("ruby\n{}\n{}\n{}", self.name, self.version, self.gems.join("\n")))
+    }
+
+    fn bin_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = vec![self.install_dir.join("bin")];
+
+        // Gem binstubs need the ruby interpreter they were installed with
+        if let Ok(ruby_version) = self.determine_ruby_version(None) {
+            dirs.push(ruby_root(&self.runtimes_dir, &ruby_version).join("bin"));
+        }
+
+        dirs
+    }
 }
@@ -3,8 +3,9 @@
 //! This module provides a tool implementation for system commands.
 
 use std::path::PathBuf;
-use std::process::Command;
 
+use super::{diagnostics, exit_status, network};
+use super::output::{capped, record_stdout};
 use super::r#trait::{SetupContext, Tool, ToolError};
 
 /// A tool that runs system commands
@@ -40,7 +41,7 @@ impl Tool for SystemTool {
         // Just check if the command exists
         let parts: Vec<&str> = self.command.split_whitespace().collect();
         if parts.is_empty() {
-            return Err(ToolError::ToolNotFound(format!("Empty command")));
+            return Err(ToolError::ToolNotFound("Empty command".to_string()));
         }
 
         let cmd = parts[0];
@@ -54,7 +55,7 @@ impl Tool for SystemTool {
         // Split the command into parts
         let parts: Vec<&str> = self.command.split_whitespace().collect();
         if parts.is_empty() {
-            return Err(ToolError::ExecutionError(format!("Empty command")));
+            return Err(ToolError::ExecutionError("Empty command".to_string()));
         }
 
         let cmd = parts[0];
@@ -70,19 +71,23 @@ impl Tool for SystemTool {
         let file_args_str = file_args.join(" ");
 
         // Run the command with output capture
-        let output = Command::new(cmd)
+        let mut command = network::hook_command(cmd);
+        command
             .args(&args)  // Use reference to avoid moving
-            .args(&file_args)  // Use reference to avoid moving
+            .args(&file_args);  // Use reference to avoid moving
+        let output = command
             .output()
             .map_err(|e| ToolError::ExecutionError(format!("Failed to execute command: {}", e)))?;
 
         // Check the status
-        if output.status.success() {
+        record_stdout(&output.stdout);
+        if exit_status::passed(&output.status) {
             Ok(())
         } else {
+            diagnostics::report_failure(&self.name, &command);
             // Try to convert stdout and stderr to strings, but handle non-UTF-8 data
-            let stdout = String::from_utf8_lossy(&output.stdout);
-            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = capped("stdout", &output.stdout);
+            let stderr = capped("stderr", &output.stderr);
 
             // Log the command and its output
             log::error!("Command failed: {} {} {}", cmd, args_str, file_args_str);
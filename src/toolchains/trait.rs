@@ -60,4 +60,17 @@ pub trait Tool: Send {
 
     /// Get the installation directory of the tool
     fn install_dir(&self) -> &PathBuf;
+
+    /// Get the directories that lead the PATH of processes run by this tool
+    fn bin_dirs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Describe what setup installs, for tools that provision an environment
+    ///
+    /// The environment is only considered installed once setup has completed
+    /// for this exact manifest.
+    fn setup_manifest(&self) -> Option<String> {
+        None
+    }
 }
@@ -0,0 +1,248 @@
+//! Python scripts with inline metadata, run with uv
+//!
+//! A Python hook whose entry is a `.py` file with an inline `script`
+//! metadata block (PEP 723) declares its own dependencies:
+//!
+//! ```python
+//! # /// script
+//! # requires-python = ">=3.11"
+//! # dependencies = ["tomli-w"]
+//! # ///
+//! ```
+//!
+//! Such hooks get no virtualenv of their own. They run with `uv run
+//! --script`, which sets up an ephemeral environment for the script's
+//! dependencies and keeps it in its cache, `<cache_dir>/uv`, so later runs
+//! reuse it. `uv` must be installed. With `--forbid-network`, uv only uses
+//! what its cache already has.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use super::hermetic::{apply_hook_env, home_env};
+use super::output::{capped, record_stdout};
+use super::r#trait::{SetupContext, Tool, ToolError};
+use super::{diagnostics, exit_status, network};
+
+/// Directory of the cache root holding uv's cache
+pub const UV_CACHE_DIR: &str = "uv";
+
+/// Pattern of inline metadata blocks, from PEP 723
+const METADATA_PATTERN: &str = r"(?m)^# /// (?P<type>[a-zA-Z0-9-]+)$\s(?P<content>(^#(| .*)$\s)+)^# ///$";
+
+/// Get the `script` metadata of a Python script, as TOML
+///
+/// Returns `None` for scripts without one.
+pub fn metadata(source: &str) -> Option<String> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| Regex::new(METADATA_PATTERN).expect("valid metadata pattern"));
+    let block = pattern.captures_iter(source).find(|captures| &captures["type"] == "script")?;
+    let toml = block["content"]
+        .lines()
+        .map(|line| line.strip_prefix("# ").unwrap_or(line.trim_start_matches('#')))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some(toml)
+}
+
+/// Check whether a file is a Python script with inline metadata
+pub fn is_script(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "py")
+        && fs::read_to_string(path).is_ok_and(|source| metadata(&source).is_some())
+}
+
+/// Get the script a Python hook's entry runs, if it has inline metadata
+///
+/// The script is the first word of the entry, relative to the current
+/// directory. Scripts of hook repos are resolved to their checkout first,
+/// see [`crate::config::hook_repo::resolve_entry`].
+pub fn entry_script(entry: &str) -> Option<PathBuf> {
+    let program = entry.split_whitespace().next()?;
+    is_script(Path::new(program)).then(|| PathBuf::from(program))
+}
+
+/// Keep uv to its cache when network access is forbidden
+fn set_offline(command: &mut Command) {
+    if network::is_forbidden() {
+        command.env("UV_OFFLINE", "1");
+    }
+}
+
+/// Check whether uv failed because it has no `sync --script`
+fn is_unsupported_sync(stderr: &str) -> bool {
+    stderr.contains("unexpected argument '--script'") || stderr.contains("unrecognized subcommand")
+}
+
+/// Find the uv executable
+pub fn find_uv() -> Result<PathBuf, ToolError> {
+    which::which("uv").map_err(|_| {
+        ToolError::ToolNotFound(
+            "uv is required to run Python scripts with inline metadata. Install it from https://docs.astral.sh/uv/".to_string(),
+        )
+    })
+}
+
+/// A tool that runs a Python script with inline metadata with `uv run`
+pub struct UvScriptTool {
+    /// The name of the tool
+    name: String,
+
+    /// The version of the tool
+    version: String,
+
+    /// The script to run
+    script: PathBuf,
+
+    /// Arguments passed to the script before the files
+    args: Vec<String>,
+
+    /// Arguments passed to the script after the files
+    args_after_files: Vec<String>,
+
+    /// Environment variables of the script
+    env: HashMap<String, String>,
+
+    /// The uv executable, found on the PATH by default
+    uv: Option<PathBuf>,
+
+    /// The cache uv keeps script environments in
+    install_dir: PathBuf,
+}
+
+impl UvScriptTool {
+    /// Create a new tool for a script
+    pub fn new(name: String, version: String, script: PathBuf, args: Vec<String>) -> Self {
+        UvScriptTool {
+            name,
+            version,
+            script,
+            args,
+            args_after_files: Vec::new(),
+            env: HashMap::new(),
+            uv: None,
+            install_dir: PathBuf::new(),
+        }
+    }
+
+    /// Set the arguments passed to the script after the files
+    pub fn with_args_after_files(mut self, args: Vec<String>) -> Self {
+        self.args_after_files = args;
+        self
+    }
+
+    /// Set the environment variables of the script
+    pub fn with_env(mut self, env: HashMap<String, String>) -> Self {
+        self.env = env;
+        self
+    }
+
+    /// Set the cache uv keeps script environments in
+    pub fn with_install_dir(mut self, install_dir: PathBuf) -> Self {
+        self.install_dir = install_dir;
+        self
+    }
+
+    /// Run the script with a given uv executable
+    pub fn with_uv(mut self, uv: PathBuf) -> Self {
+        self.uv = Some(uv);
+        self
+    }
+
+    /// Get the uv executable
+    fn uv(&self) -> Result<PathBuf, ToolError> {
+        match &self.uv {
+            Some(uv) => Ok(uv.clone()),
+            None => find_uv(),
+        }
+    }
+}
+
+impl Tool for UvScriptTool {
+    fn setup(&self, _ctx: &SetupContext) -> Result<(), ToolError> {
+        let uv = self.uv()?;
+
+        // Set up the script's environment now, since the hook may run without network access
+        let mut command = Command::new(&uv);
+        command.args(["sync", "--script"]).arg(&self.script).env("UV_CACHE_DIR", &self.install_dir).envs(home_env(&self.env));
+        set_offline(&mut command);
+        let output = command.output().map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", uv.display(), e)))?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        let stderr = capped("stderr", &output.stderr);
+        // Older versions of uv set the environment up when the script runs instead
+        if is_unsupported_sync(&stderr) {
+            log::debug!("This uv can't set up the environment of {} ahead of its run: {}", self.script.display(), stderr);
+            return Ok(());
+        }
+        network::check_allowed(&format!("the dependencies of {}", self.script.display()))?;
+        Err(ToolError::InstallationError(format!("Failed to set up the environment of {}: {}", self.script.display(), stderr.trim())))
+    }
+
+    fn run(&self, files: &[PathBuf]) -> Result<(), ToolError> {
+        let uv = self.uv()?;
+
+        // Run the script in its cached environment
+        let mut command = network::hook_command(&uv);
+        apply_hook_env(&mut command, &self.bin_dirs(), &self.env);
+        set_offline(&mut command);
+        command
+            .env("UV_CACHE_DIR", &self.install_dir)
+            .args(["run", "--quiet", "--script"])
+            .arg(&self.script)
+            .args(&self.args)
+            .args(files)
+            .args(&self.args_after_files);
+
+        // Execute the command with output capture
+        let output = command
+            .output()
+            .map_err(|e| ToolError::ExecutionError(format!("Failed to run {}: {}", self.script.display(), e)))?;
+
+        record_stdout(&output.stdout);
+        if exit_status::passed(&output.status) {
+            return Ok(());
+        }
+        diagnostics::report_failure(&self.name, &command);
+        let stdout = capped("stdout", &output.stdout);
+        let stderr = capped("stderr", &output.stderr);
+        if !stdout.is_empty() {
+            log::error!("Command stdout: {}", stdout);
+        }
+        if !stderr.is_empty() {
+            log::error!("Command stderr: {}", stderr);
+        }
+        Err(ToolError::ExecutionError(format!(
+            "{} failed with exit code {:?}",
+            self.script.display(),
+            output.status.code()
+        )))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn version(&self) -> &str {
+        &self.version
+    }
+
+    fn is_installed(&self) -> bool {
+        self.uv().is_ok()
+    }
+
+    fn install_dir(&self) -> &PathBuf {
+        &self.install_dir
+    }
+
+    fn bin_dirs(&self) -> Vec<PathBuf> {
+        // uv finds or downloads the Python the script requires
+        self.uv().ok().and_then(|uv| uv.parent().map(Path::to_path_buf)).into_iter().collect()
+    }
+}
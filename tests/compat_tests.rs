@@ -1,13 +1,13 @@
 //! Compatibility tests with pre-commit configs
 
 use std::fs;
-use std::path::PathBuf;
 use rustyhook::config::{
     PreCommitConfig, PreCommitRepo, PreCommitHook,
-    parse_precommit_config, convert_to_rustyhook_config
+    parse_precommit_config, convert_to_rustyhook_config, merge_compat_config
 };
-use rustyhook::config::compat::{PreCommitHooksFile, find_precommit_hooks_for_repo};
-use rustyhook::config::parser::HookType;
+use rustyhook::config::compat::find_precommit_hooks_for_repo;
+use rustyhook::config::freeze::{autoupdate_revs, check_minimum_revs, check_mutable_revs, find_mutable_revs, find_outdated_revs, freeze_revs};
+use rustyhook::config::parser::{AccessMode, Hook, HookType};
 
 #[test]
 fn test_parse_precommit_config() {
@@ -72,16 +72,20 @@ fn test_convert_to_rustyhook_config() {
             PreCommitRepo {
                 repo: "https://github.com/pre-commit/pre-commit-hooks".to_string(),
                 rev: "v4.4.0".to_string(),
+                minimum_rev: None,
+                frozen_rev: None,
                 hooks: vec![
                     PreCommitHook {
                         id: "trailing-whitespace".to_string(),
                         name: Some("trailing-whitespace".to_string()),
+                        description: None,
                         entry: Some("trailing-whitespace".to_string()),
                         language: Some("python".to_string()),
                         files: None,
                         stages: None,
                         args: None,
                         env: None,
+                        fail_fast: None,
                     },
                 ],
             },
@@ -92,8 +96,8 @@ fn test_convert_to_rustyhook_config() {
     let rustyhook_config = convert_to_rustyhook_config(&precommit_config);
 
     // Check the configuration
-    assert_eq!(rustyhook_config.default_stages, vec!["commit".to_string()]);
-    assert_eq!(rustyhook_config.fail_fast, false);
+    assert_eq!(rustyhook_config.default_stages, vec!["pre-commit".to_string()]);
+    assert!(!rustyhook_config.fail_fast);
     assert_eq!(rustyhook_config.repos.len(), 1);
 
     // Check the repository
@@ -120,7 +124,7 @@ fn test_convert_to_rustyhook_config() {
 fn test_fetch_precommit_hooks_file() {
     // Test fetching hooks from a real repository
     // This test requires internet connection and might fail if the repository structure changes
-    let hooks_file = find_precommit_hooks_for_repo("https://github.com/pre-commit/pre-commit-hooks");
+    let hooks_file = find_precommit_hooks_for_repo("https://github.com/pre-commit/pre-commit-hooks", "v4.5.0");
 
     // Verify that we got a hooks file
     assert!(hooks_file.is_some(), "Failed to fetch hooks file from pre-commit-hooks repository");
@@ -148,17 +152,8 @@ fn test_fetch_precommit_hooks_file() {
 fn test_repository_cloned_to_cache_directory() {
     // Clean up any existing cache directory for this test
     let repo_url = "https://github.com/pre-commit/pre-commit-hooks";
-    let cache_dir = std::env::current_dir().unwrap().join(".rustyhook").join("cache").join("repos");
-
-    // Create a hash of the repo URL to find the expected directory
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-
-    let mut hasher = DefaultHasher::new();
-    repo_url.hash(&mut hasher);
-    let repo_hash = hasher.finish();
-
-    let repo_dir = cache_dir.join(format!("{}", repo_hash));
+    let rev = "v4.5.0";
+    let repo_dir = rustyhook::config::hook_repo::checkout_dir(repo_url, rev);
 
     // Remove the directory if it exists
     if repo_dir.exists() {
@@ -166,7 +161,7 @@ fn test_repository_cloned_to_cache_directory() {
     }
 
     // Fetch the hooks file, which should clone the repository to the cache directory
-    let hooks_file = find_precommit_hooks_for_repo(repo_url);
+    let hooks_file = find_precommit_hooks_for_repo(repo_url, rev);
 
     // Verify that we got a hooks file
     assert!(hooks_file.is_some(), "Failed to fetch hooks file from pre-commit-hooks repository");
@@ -177,7 +172,7 @@ fn test_repository_cloned_to_cache_directory() {
     assert!(repo_dir.join(".pre-commit-hooks.yaml").exists(), "Repository directory should contain a .pre-commit-hooks.yaml file");
 
     // Fetch the hooks file again, which should use the cached repository
-    let hooks_file2 = find_precommit_hooks_for_repo(repo_url);
+    let hooks_file2 = find_precommit_hooks_for_repo(repo_url, rev);
 
     // Verify that we got a hooks file
     assert!(hooks_file2.is_some(), "Failed to fetch hooks file from pre-commit-hooks repository (second time)");
@@ -199,48 +194,60 @@ fn test_convert_to_rustyhook_config_with_multiple_repos() {
             PreCommitRepo {
                 repo: "https://github.com/pre-commit/pre-commit-hooks".to_string(),
                 rev: "v4.4.0".to_string(),
+                minimum_rev: None,
+                frozen_rev: None,
                 hooks: vec![
                     PreCommitHook {
                         id: "trailing-whitespace".to_string(),
                         name: Some("trailing-whitespace".to_string()),
+                        description: None,
                         entry: Some("trailing-whitespace".to_string()),
                         language: Some("python".to_string()),
                         files: None,
                         stages: None,
                         args: None,
                         env: None,
+                        fail_fast: None,
                     },
                 ],
             },
             PreCommitRepo {
                 repo: "https://github.com/astral-sh/ruff-pre-commit".to_string(),
                 rev: "v0.0.262".to_string(),
+                minimum_rev: None,
+                frozen_rev: None,
                 hooks: vec![
                     PreCommitHook {
                         id: "ruff".to_string(),
                         name: Some("ruff".to_string()),
+                        description: None,
                         entry: Some("ruff".to_string()),
                         language: Some("python".to_string()),
                         files: None,
                         stages: None,
                         args: None,
                         env: None,
+                        fail_fast: None,
                     },
                 ],
             },
             PreCommitRepo {
                 repo: "https://github.com/biomejs/pre-commit".to_string(),
                 rev: "v1.0.0".to_string(),
+                minimum_rev: None,
+                frozen_rev: None,
                 hooks: vec![
                     PreCommitHook {
                         id: "biome-check".to_string(),
                         name: Some("biome-check".to_string()),
+                        description: None,
                         entry: Some("biome check".to_string()),
                         language: Some("node".to_string()),
                         files: None,
                         stages: None,
                         args: None,
                         env: None,
+                        fail_fast: None,
                     },
                 ],
             },
@@ -251,8 +258,8 @@ fn test_convert_to_rustyhook_config_with_multiple_repos() {
     let rustyhook_config = convert_to_rustyhook_config(&precommit_config);
 
     // Check the configuration
-    assert_eq!(rustyhook_config.default_stages, vec!["commit".to_string()]);
-    assert_eq!(rustyhook_config.fail_fast, false);
+    assert_eq!(rustyhook_config.default_stages, vec!["pre-commit".to_string()]);
+    assert!(!rustyhook_config.fail_fast);
     assert_eq!(rustyhook_config.repos.len(), 3);
 
     // Check the first repository (pre-commit-hooks)
@@ -295,3 +302,292 @@ fn test_convert_to_rustyhook_config_with_multiple_repos() {
     assert_eq!(hook3.entry, "biome check");
     assert_eq!(hook3.hook_type, HookType::External);
 }
+
+#[test]
+fn test_convert_fail_language_hook() {
+    // Create a pre-commit configuration with a local fail hook
+    let precommit_config = PreCommitConfig {
+        default_stages: vec!["commit".to_string()],
+        fail_fast: false,
+        repos: vec![
+            PreCommitRepo {
+                repo: "local".to_string(),
+                rev: String::new(),
+                minimum_rev: None,
+                frozen_rev: None,
+                hooks: vec![
+                    PreCommitHook {
+                        id: "no-orig-files".to_string(),
+                        name: Some("Forbid .orig files".to_string()),
+                        description: Some("Finds files left over by merges".to_string()),
+                        entry: Some("Merge leftovers must not be committed".to_string()),
+                        language: Some("fail".to_string()),
+                        files: Some("\\.orig$".to_string()),
+                        stages: None,
+                        args: None,
+                        env: None,
+                        fail_fast: None,
+                    },
+                ],
+            },
+        ],
+    };
+
+    // Convert to RustyHook configuration
+    let rustyhook_config = convert_to_rustyhook_config(&precommit_config);
+
+    // The language, message and pattern are preserved and the hook is read-only
+    let hook = &rustyhook_config.repos[0].hooks[0];
+    assert_eq!(hook.language, "fail");
+    assert_eq!(hook.entry, "Merge leftovers must not be committed");
+    assert_eq!(hook.files, "\\.orig$");
+    assert_eq!(hook.description.as_deref(), Some("Finds files left over by merges"));
+    assert_eq!(hook.access_mode, AccessMode::Read);
+}
+
+#[test]
+fn test_convert_stages_from_hook_definitions() {
+    use rustyhook::config::hook_repo::checkout_dir;
+    use rustyhook::testing::TestRepo;
+
+    let source = TestRepo::new();
+    source.write_staged(
+        ".pre-commit-hooks.yaml",
+        "- id: commitizen\n  name: Commitizen\n  entry: cz check --commit-msg-file\n  language: python\n  stages: [commit-msg]\n- id: lint\n  name: Lint\n  entry: lint\n  language: python\n",
+    );
+    source.commit("Add hooks");
+    source.tag("v1.0.0");
+    let url = format!("file://{}", source.path().display());
+    let _ = fs::remove_dir_all(checkout_dir(&url, "v1.0.0"));
+
+    let config_str = format!(
+        "default_stages: [pre-commit]\nrepos:\n  - repo: {url}\n    rev: v1.0.0\n    hooks:\n      - id: commitizen\n      - id: lint\n  - repo: {url}\n    rev: v1.0.0\n    hooks:\n      - id: commitizen\n        stages: [manual]\n"
+    );
+    let precommit_config: PreCommitConfig = serde_yaml::from_str(&config_str).unwrap();
+    let rustyhook_config = convert_to_rustyhook_config(&precommit_config);
+    let hooks = &rustyhook_config.repos[0].hooks;
+
+    // The definition's stages apply when the config has none, before default_stages
+    assert_eq!(hooks[0].stages, vec!["commit-msg".to_string()]);
+    assert!(hooks[0].runs_in_stage(&rustyhook_config.default_stages, "commit-msg"));
+    assert!(!hooks[0].runs_in_stage(&rustyhook_config.default_stages, "pre-commit"));
+    assert!(!hooks[0].runs_in_stage(&rustyhook_config.default_stages, "pre-push"));
+    assert!(hooks[1].stages.is_empty());
+    assert!(hooks[1].runs_in_stage(&rustyhook_config.default_stages, "pre-commit"));
+    assert!(!hooks[1].runs_in_stage(&rustyhook_config.default_stages, "pre-push"));
+
+    // The config's stages win over the definition's
+    assert_eq!(rustyhook_config.repos[1].hooks[0].stages, vec!["manual".to_string()]);
+
+    let _ = fs::remove_dir_all(checkout_dir(&url, "v1.0.0"));
+}
+
+#[test]
+fn test_mutable_revs_and_freeze() {
+    let config_str = r#"repos:
+  # Formatting hooks
+  - repo: https://github.com/example/branch-pinned
+    rev: main
+    hooks:
+      - id: format
+  - repo: https://github.com/example/tag-pinned
+    rev: "v1.2.3"
+    hooks:
+      - id: lint
+  - repo: https://github.com/example/sha-pinned
+    rev: 0123456789abcdef0123456789abcdef01234567
+    hooks:
+      - id: check
+  - repo: local
+    hooks:
+      - id: local-check
+        name: Local check
+        entry: ./check.sh
+        language: system
+"#;
+
+    // Only branch-like refs are reported as mutable
+    let precommit_config: PreCommitConfig = serde_yaml::from_str(config_str).unwrap();
+    let mutable = find_mutable_revs(&precommit_config);
+    assert_eq!(mutable.len(), 1);
+    assert_eq!(mutable[0].repo, "https://github.com/example/branch-pinned");
+    assert_eq!(mutable[0].rev, "main");
+    assert!(check_mutable_revs(&precommit_config, false).is_ok());
+    assert!(check_mutable_revs(&precommit_config, true).is_err());
+
+    // Revs older than their minimum_rev are reported, frozen SHAs through the ref they were frozen from
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(".pre-commit-config.yaml");
+    std::fs::write(
+        &path,
+        "repos:\n  - repo: https://github.com/example/old\n    rev: v1.2\n    minimum_rev: v1.2.1\n    hooks:\n      - id: lint\n  - repo: https://github.com/example/frozen\n    rev: 0123456789abcdef0123456789abcdef01234567  # frozen: v2.0.0\n    minimum_rev: \"2.0\"\n    hooks:\n      - id: check\n  - repo: https://github.com/example/branch\n    rev: main\n    minimum_rev: v1.0.0\n    hooks:\n      - id: format\n",
+    )
+    .unwrap();
+    let precommit_config = rustyhook::config::parse_precommit_config(&path).unwrap();
+    assert_eq!(precommit_config.repos[1].frozen_rev.as_deref(), Some("v2.0.0"));
+    let outdated = find_outdated_revs(&precommit_config);
+    assert_eq!(outdated.len(), 1);
+    assert_eq!((outdated[0].repo.as_str(), outdated[0].rev.as_str()), ("https://github.com/example/old", "v1.2"));
+    assert!(check_minimum_revs(&precommit_config, false).is_ok());
+    assert!(check_minimum_revs(&precommit_config, true).is_err());
+
+    // Freezing rewrites branch and tag revs, keeping comments and the original ref
+    let (frozen, count) = freeze_revs(config_str, |repo, rev| {
+        assert_ne!(repo, "local");
+        Ok(format!("{:0>40}", rev.len()))
+    }).unwrap();
+    assert_eq!(count, 2);
+    assert!(frozen.contains("  # Formatting hooks\n"));
+    assert!(frozen.contains("    rev: 0000000000000000000000000000000000000004  # frozen: main\n"));
+    assert!(frozen.contains("    rev: 0000000000000000000000000000000000000006  # frozen: v1.2.3\n"));
+    assert!(frozen.contains("    rev: 0123456789abcdef0123456789abcdef01234567\n"));
+
+    // Revs are matched to their repo whatever the order of the keys, in block and flow items
+    let config_str = "repos:\n- rev: main\n  repo: https://github.com/example/rev-first\n  hooks:\n  - id: format\n- {repo: https://github.com/example/flow, rev: 'dev', hooks: [{id: lint}]}\n- repo: local\n  hooks:\n  - {id: check, name: Check, entry: ./check.sh, language: system}\n";
+    let mut resolved = Vec::new();
+    let (frozen, count) = freeze_revs(config_str, |repo, rev| {
+        resolved.push((repo.to_string(), rev.to_string()));
+        Ok(format!("{:0>40}", resolved.len()))
+    }).unwrap();
+    assert_eq!(resolved, vec![
+        ("https://github.com/example/rev-first".to_string(), "main".to_string()),
+        ("https://github.com/example/flow".to_string(), "dev".to_string()),
+    ]);
+    assert_eq!(count, 2);
+    assert_eq!(frozen, "repos:\n- rev: 0000000000000000000000000000000000000001  # frozen: main\n  repo: https://github.com/example/rev-first\n  hooks:\n  - id: format\n- {repo: https://github.com/example/flow, rev: 0000000000000000000000000000000000000002, hooks: [{id: lint}]}  # frozen: dev\n- repo: local\n  hooks:\n  - {id: check, name: Check, entry: ./check.sh, language: system}\n");
+    let frozen_config: PreCommitConfig = serde_yaml::from_str(&frozen).unwrap();
+    assert_eq!(frozen_config.repos[1].rev, "0000000000000000000000000000000000000002");
+}
+
+#[test]
+fn test_merge_compat_config() {
+    let native: rustyhook::config::Config = serde_yaml::from_str(
+        "compat_merge: true\nparallelism: 2\nrepos:\n  - repo: local\n    hooks:\n      - id: check-yaml\n        name: Native check-yaml\n        entry: check-yaml\n        language: native\n",
+    ).unwrap();
+    assert!(native.compat_merge);
+
+    let precommit_config: PreCommitConfig = serde_yaml::from_str(
+        "repos:\n  - repo: local\n    hooks:\n      - id: check-yaml\n        name: Legacy check-yaml\n        entry: false\n        language: system\n      - id: no-todo\n        name: No TODO\n        entry: TODO\n        language: pygrep\n  - repo: meta\n    hooks:\n      - id: check-yaml\n        name: Duplicate\n        entry: check-yaml\n        language: system\n",
+    ).unwrap();
+    let compat = convert_to_rustyhook_config(&precommit_config);
+
+    // Native hooks win, hooks only in pre-commit are added, empty repos are dropped
+    let merged = merge_compat_config(&native, &compat);
+    let hooks: Vec<(&str, &str)> = merged
+        .repos
+        .iter()
+        .flat_map(|repo| repo.hooks.iter().map(|hook| (hook.id.as_str(), hook.name.as_str())))
+        .collect();
+    assert_eq!(hooks, vec![("check-yaml", "Native check-yaml"), ("no-todo", "No TODO")]);
+    assert_eq!(merged.repos.len(), 2);
+    assert_eq!(merged.parallelism, 2);
+}
+
+#[test]
+fn test_autoupdate_revs() {
+    let config_str = "repos:\n  - repo: https://example.com/a\n    rev: v1.0.0\n    hooks:\n      - id: a\n  - repo: https://example.com/b\n    rev: v2.0.0\n    hooks:\n      - id: b\n  - repo: local\n    hooks:\n      - id: c\n";
+    let latest = |repo: &str| -> Result<Option<(String, String)>, rustyhook::config::ConfigError> {
+        assert_ne!(repo, "local");
+        Ok(Some(("v2.0.0".to_string(), "0123456789abcdef0123456789abcdef01234567".to_string())))
+    };
+
+    // Repos already at the latest tag are left alone
+    let (updated, count) = autoupdate_revs(config_str, &[], false, latest).unwrap();
+    assert_eq!(count, 1);
+    assert!(updated.contains("    rev: v2.0.0\n    hooks:\n      - id: a\n"));
+
+    // Freezing pins to the SHA, and --repo limits the update
+    let (updated, count) = autoupdate_revs(config_str, &["https://example.com/b".to_string()], true, latest).unwrap();
+    assert_eq!(count, 1);
+    assert!(updated.contains("    rev: v1.0.0\n"));
+    assert!(updated.contains("    rev: 0123456789abcdef0123456789abcdef01234567  # frozen: v2.0.0\n"));
+}
+
+#[test]
+fn test_hook_repo_checkouts() {
+    use rustyhook::config::hook_repo::{clone_hook_repo, ensure_sources, is_sparse, Checkout};
+    use rustyhook::testing::TestRepo;
+
+    let source = TestRepo::new();
+    source.write_staged(".pre-commit-hooks.yaml", "- id: lint\n  name: Lint\n  entry: lint\n  language: node\n");
+    source.write_staged("package.json", "{\"name\": \"lint\"}\n");
+    source.write_staged("src/index.js", "module.exports = {};\n");
+    source.commit("First");
+    source.tag("v1");
+    source.write_staged("src/index.js", "module.exports = { lint: true };\n");
+    source.commit("Second");
+    let url = format!("file://{}", source.path().display());
+
+    // Reading the hooks file only needs the hooks file and manifests
+    let target = tempfile::tempdir().unwrap();
+    let sparse_dir = target.path().join("sparse");
+    let checkout = clone_hook_repo(&url, &sparse_dir, "", false).unwrap();
+    assert!(sparse_dir.join(".pre-commit-hooks.yaml").exists());
+    assert!(sparse_dir.join("package.json").exists());
+    if checkout == Checkout::Sparse {
+        assert!(is_sparse(&sparse_dir));
+        assert!(!sparse_dir.join("src").join("index.js").exists());
+    }
+    assert_ne!(checkout, Checkout::Full);
+
+    // Widening a sparse checkout brings the sources back
+    assert!(ensure_sources(&sparse_dir));
+    assert_eq!(fs::read_to_string(sparse_dir.join("src").join("index.js")).unwrap(), "module.exports = { lint: true };\n");
+
+    // Installing from the repo gets every file, still without history when possible
+    let full_dir = target.path().join("full");
+    let checkout = clone_hook_repo(&url, &full_dir, "", true).unwrap();
+    assert_ne!(checkout, Checkout::Sparse);
+    assert!(full_dir.join("src").join("index.js").exists());
+    assert!(!is_sparse(&full_dir));
+
+    // The configured rev is checked out, not the default branch
+    for (name, needs_sources) in [("pinned-sparse", false), ("pinned", true)] {
+        let pinned_dir = target.path().join(name);
+        clone_hook_repo(&url, &pinned_dir, "v1", needs_sources).unwrap();
+        assert!(ensure_sources(&pinned_dir));
+        assert_eq!(fs::read_to_string(pinned_dir.join("src").join("index.js")).unwrap(), "module.exports = {};\n");
+    }
+    assert!(clone_hook_repo(&url, &target.path().join("missing"), "v9", true).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_script_hooks_run_from_hook_repo() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::config::hook_repo::{checkout, checkout_dir, resolve_entry};
+    use rustyhook::testing::{hook, TestRepo};
+
+    let source = TestRepo::new();
+    source.write_staged(".pre-commit-hooks.yaml", "- id: check\n  name: Check\n  entry: bin/check.sh\n  language: script\n");
+    let script_file = source.write("bin/check.sh", "#!/bin/sh\necho checked \"$@\"\n");
+    fs::set_permissions(script_file, fs::Permissions::from_mode(0o755)).unwrap();
+    source.stage("bin/check.sh");
+    source.commit("Add hook");
+    source.tag("v1.0.0");
+    let url = format!("file://{}", source.path().display());
+    let _ = fs::remove_dir_all(checkout_dir(&url, "v1.0.0"));
+
+    // Reading the hooks file makes a checkout without the script
+    let repo_dir = checkout(&url, "v1.0.0", false).unwrap();
+    assert_eq!(repo_dir, checkout_dir(&url, "v1.0.0"));
+    assert_ne!(repo_dir, checkout_dir(&url, "v2.0.0"));
+
+    // The entry points at the script in the checkout of the hook's rev, keeping its arguments
+    let script = Hook { version: Some("v1.0.0".to_string()), ..hook("check", "script", "bin/check.sh --strict") };
+    let resolved = resolve_entry(&url, &script);
+    let expected = repo_dir.join("bin").join("check.sh");
+    assert_eq!(resolved.entry, format!("{} --strict", expected.display()));
+    let output = std::process::Command::new(&expected).arg("a.txt").output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "checked a.txt\n");
+
+    // System hooks only resolve paths the project doesn't have
+    let system = Hook { language: "system".to_string(), entry: "bin/check.sh".to_string(), ..script.clone() };
+    assert_eq!(resolve_entry(&url, &system).entry, expected.display().to_string());
+    assert_eq!(resolve_entry(&url, &hook("echo", "system", "echo hi")).entry, "echo hi");
+
+    // Hooks of the project itself are left alone
+    assert_eq!(resolve_entry("local", &script).entry, "bin/check.sh --strict");
+
+    fs::remove_dir_all(repo_dir).unwrap();
+}
@@ -2,8 +2,7 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use rustyhook::config::{Config, Hook, Repo, parse_config};
+use rustyhook::config::{discover_projects, expand_matrix, parse_config, refresh_projects, ProjectKind};
 
 #[test]
 fn test_parse_config() {
@@ -36,9 +35,9 @@ repos:
     // Parse the configuration
     let config = parse_config(&config_path).unwrap();
 
-    // Check the configuration
-    assert_eq!(config.default_stages, vec!["commit".to_string(), "push".to_string()]);
-    assert_eq!(config.fail_fast, true);
+    // Check the configuration, with legacy stage names normalized
+    assert_eq!(config.default_stages, vec!["pre-commit".to_string(), "pre-push".to_string()]);
+    assert!(config.fail_fast);
     assert_eq!(config.repos.len(), 1);
 
     // Check the repository
@@ -53,7 +52,7 @@ repos:
     assert_eq!(hook.entry, "test-command");
     assert_eq!(hook.language, "system");
     assert_eq!(hook.files, ".*\\.rs$");
-    assert_eq!(hook.stages, vec!["commit".to_string()]);
+    assert_eq!(hook.stages, vec!["pre-commit".to_string()]);
     assert_eq!(hook.args, vec!["--verbose".to_string()]);
 
     // Check the environment variables
@@ -89,14 +88,522 @@ repos:
     let config = parse_config(&config_path).unwrap();
 
     // Check the default values
-    assert_eq!(config.default_stages, vec!["commit".to_string()]);
-    assert_eq!(config.fail_fast, false);
+    assert_eq!(config.default_stages, vec!["pre-commit".to_string()]);
+    assert!(!config.fail_fast);
 
     // Check the hook default values
     let hook = &config.repos[0].hooks[0];
     assert_eq!(hook.files, "");
-    assert_eq!(hook.stages, vec!["commit".to_string()]);
+    assert!(hook.stages.is_empty());
+    assert_eq!(hook.stages_or(&config.default_stages), ["pre-commit".to_string()]);
     assert_eq!(hook.args, Vec::<String>::new());
     assert_eq!(hook.env, HashMap::new());
     assert_eq!(hook.version, None);
 }
+
+#[test]
+fn test_matrix_expansion() {
+    // Create a temporary monorepo with two services
+    let temp_dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(temp_dir.path().join("services").join("api")).unwrap();
+    fs::create_dir_all(temp_dir.path().join("services").join("web")).unwrap();
+    fs::write(temp_dir.path().join("services").join("README.md"), "").unwrap();
+
+    let config_path = temp_dir.path().join("config.yaml");
+    let config_str = r#"
+repos:
+  - repo: local
+    hooks:
+      - id: lint
+        name: Lint
+        entry: make -C {matrix.dir} lint
+        language: system
+        files: "^{matrix.dir}/.*\\.py$"
+        args: ["--package={matrix.dir}"]
+        working_dir: "{matrix.dir}"
+        matrix:
+          dir: "services/*"
+"#;
+    fs::write(&config_path, config_str).unwrap();
+
+    // Expand the matrix relative to the temporary directory
+    let config = parse_config(&config_path).unwrap();
+    let expanded = expand_matrix(&config, temp_dir.path()).unwrap();
+
+    let hooks = &expanded.repos[0].hooks;
+    let ids: Vec<&str> = hooks.iter().map(|h| h.id.as_str()).collect();
+    assert_eq!(ids, vec!["lint[services/api]", "lint[services/web]"]);
+
+    // Check the variables were substituted
+    let api = &hooks[0];
+    assert_eq!(api.entry, "make -C services/api lint");
+    assert_eq!(api.files, "^services/api/.*\\.py$");
+    assert_eq!(api.args, vec!["--package=services/api".to_string()]);
+    assert_eq!(api.working_dir, Some("services/api".to_string()));
+    assert!(api.matrix.is_empty());
+
+    // Skipping the base id skips every expansion
+    assert_eq!(api.base_id(), "lint");
+    assert!(api.is_skipped_by(&["lint".to_string()]));
+    assert!(!api.is_skipped_by(&["lint[services/web]".to_string()]));
+}
+
+#[test]
+fn test_notification_settings() {
+    use rustyhook::config::parser::NotifyMethod;
+    use rustyhook::runner::notify::is_ci;
+    use rustyhook::runner::Notifier;
+    use std::time::Duration;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.yaml");
+    fs::write(&config_path, "notify_after_seconds: 30\nnotify: [bell]\nrepos: []\n").unwrap();
+    let config = parse_config(&config_path).unwrap();
+    assert_eq!(config.notify_after_seconds, Some(30));
+    assert_eq!(config.notify, vec![NotifyMethod::Bell]);
+
+    // Notifications are off unless a threshold is set, and always off in CI
+    assert!(Notifier::from_config(&rustyhook::config::Config::default()).is_none());
+    match Notifier::from_config(&config) {
+        Some(notifier) => {
+            assert!(!is_ci());
+            assert!(!notifier.should_notify(Duration::from_secs(29)));
+            assert!(notifier.should_notify(Duration::from_secs(30)));
+        }
+        None => assert!(is_ci()),
+    }
+
+    assert_eq!(Notifier::message(Duration::from_secs(95), false), "Hooks failed after 95s");
+}
+
+#[test]
+fn test_cache_dir_resolution() {
+    use rustyhook::cache::{resolve_cache_dir, CACHE_DIR_ENV};
+    use std::path::{Path, PathBuf};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.yaml");
+    fs::write(&config_path, "cache_dir: /var/cache/rustyhook\ncache_ttl:\n  versions: 60\nrepos: []\n").unwrap();
+    let config = parse_config(&config_path).unwrap();
+    assert_eq!(config.cache_dir, Some(PathBuf::from("/var/cache/rustyhook")));
+    assert_eq!(config.cache_ttl.versions, 60);
+    assert_eq!(config.cache_ttl.hook_repos, rustyhook::config::parser::CacheTtl::default().hook_repos);
+    assert!(config.keep_downloads);
+    assert!(config.reuse_installed_rubies);
+    fs::write(&config_path, "keep_downloads: false\nreuse_installed_rubies: false\nrepos: []\n").unwrap();
+    let opted_out = parse_config(&config_path).unwrap();
+    assert!(!opted_out.keep_downloads);
+    assert!(!opted_out.reuse_installed_rubies);
+
+    // The command line wins, and relative paths are resolved from the working directory
+    assert_eq!(resolve_cache_dir(Some(Path::new("/tmp/rh-cache")), &config), PathBuf::from("/tmp/rh-cache"));
+    assert_eq!(
+        resolve_cache_dir(Some(Path::new("cache")), &config),
+        std::env::current_dir().unwrap().join("cache")
+    );
+
+    if std::env::var_os(CACHE_DIR_ENV).is_none() {
+        assert_eq!(resolve_cache_dir(None, &config), PathBuf::from("/var/cache/rustyhook"));
+
+        // Without any setting, every project shares the user's cache directory
+        let default = resolve_cache_dir(None, &rustyhook::config::Config::default());
+        assert!(default.is_absolute() && default.ends_with("rustyhook"), "{}", default.display());
+        if cfg!(unix) && std::env::var_os("XDG_CACHE_HOME").is_none() {
+            if let Some(home) = std::env::var_os("HOME") {
+                assert_eq!(default, PathBuf::from(home).join(".cache").join("rustyhook"));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_select_hooks_by_id_and_stage() {
+    let config: rustyhook::config::Config = serde_yaml::from_str(
+        "default_stages: [pre-commit]\nrepos:\n  - repo: local\n    hooks:\n      - id: fmt\n        name: Format\n        entry: fmt\n        language: system\n      - id: test\n        name: Test\n        entry: test\n        language: system\n        stages: [push]\n",
+    ).unwrap();
+
+    let hooks = |config: &rustyhook::config::Config| -> Vec<String> {
+        config.repos.iter().flat_map(|repo| repo.hooks.iter().map(|hook| hook.id.clone())).collect()
+    };
+
+    assert_eq!(hooks(&config.select_hooks(None, None)), vec!["fmt", "test"]);
+    assert_eq!(hooks(&config.select_hooks(None, Some("pre-commit"))), vec!["fmt"]);
+    assert_eq!(hooks(&config.select_hooks(None, Some("pre-push"))), vec!["test"]);
+    assert_eq!(hooks(&config.select_hooks(Some("fmt"), Some("pre-push"))), Vec::<String>::new());
+    assert!(config.select_hooks(Some("missing"), None).repos.is_empty());
+}
+
+#[test]
+fn test_default_stages_and_aliases() {
+    use rustyhook::config::{convert_to_rustyhook_config, PreCommitConfig};
+
+    let hooks = |config: &rustyhook::config::Config| -> Vec<String> {
+        config.repos.iter().flat_map(|repo| repo.hooks.iter().map(|hook| hook.id.clone())).collect()
+    };
+
+    // Hooks without stages follow default_stages, whichever names either uses
+    let config: rustyhook::config::Config = serde_yaml::from_str(
+        "default_stages: [push]\nrepos:\n  - repo: local\n    hooks:\n      - id: test\n        name: Test\n        entry: test\n        language: system\n      - id: fmt\n        name: Format\n        entry: fmt\n        language: system\n        stages: [commit, manual]\n",
+    ).unwrap();
+    assert_eq!(config.repos[0].hooks[1].stages, vec!["pre-commit", "manual"]);
+    assert_eq!(hooks(&config.select_hooks(None, Some("pre-push"))), vec!["test"]);
+    assert_eq!(hooks(&config.select_hooks(None, Some("commit"))), vec!["fmt"]);
+    assert_eq!(hooks(&config.select_hooks(None, Some("manual"))), vec!["fmt"]);
+
+    // Without default_stages in a pre-commit config, hooks run in every stage
+    let precommit: PreCommitConfig = serde_yaml::from_str(
+        "repos:\n  - repo: local\n    rev: ''\n    hooks:\n      - id: lint\n        entry: lint\n        language: system\n      - id: check\n        entry: check\n        language: system\n        stages: [push]\n",
+    ).unwrap();
+    let converted = convert_to_rustyhook_config(&precommit);
+    assert!(converted.default_stages.is_empty());
+    assert_eq!(hooks(&converted.select_hooks(None, Some("post-merge"))), vec!["lint"]);
+    assert_eq!(hooks(&converted.select_hooks(None, Some("pre-push"))), vec!["lint", "check"]);
+
+    // Hooks following default_stages are written without stages
+    let written = serde_yaml::to_string(&converted).unwrap();
+    assert_eq!(written.matches("stages:").count(), 2);
+    assert!(written.contains("- pre-push"));
+}
+
+#[test]
+fn test_config_drift() {
+    use rustyhook::config::{check_converted_config, check_hook_scripts, convert_from_precommit, Drift};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let hooks_dir = temp_dir.path().join(".git").join("hooks");
+    fs::create_dir_all(&hooks_dir).unwrap();
+    let script = |binary: &str| format!("#!/bin/sh\n# RustyHook Git hook\n# Generated by rustyhook\n\n{} run\n", binary);
+    fs::write(hooks_dir.join("pre-commit"), script("/usr/bin/rustyhook").replace(" run\n", " run --hook-stage pre-commit\n")).unwrap();
+    fs::write(hooks_dir.join("commit-msg"), script("/old/rustyhook")).unwrap();
+    fs::write(hooks_dir.join("post-merge"), "#!/bin/sh\necho not ours\n").unwrap();
+
+    let config: rustyhook::config::Config = serde_yaml::from_str(
+        "default_stages: [commit]\nrepos:\n  - repo: local\n    hooks:\n      - id: fmt\n        name: Format\n        entry: fmt\n        language: system\n      - id: test\n        name: Test\n        entry: test\n        language: system\n        stages: [push]\n",
+    ).unwrap();
+
+    let drifts = check_hook_scripts(&temp_dir.path().join(".git"), &config, std::path::Path::new("/usr/bin/rustyhook"));
+    assert_eq!(drifts, vec![
+        Drift::StaleBinary { hook_type: "commit-msg".to_string(), binary: "/old/rustyhook".into() },
+        Drift::UnconfiguredStage { hook_type: "commit-msg".to_string(), script: hooks_dir.join("commit-msg") },
+        Drift::MissingScript { hook_type: "pre-push".to_string() },
+    ]);
+    assert_eq!(drifts[2].remedy(), "rustyhook install --hook-type pre-push");
+
+    // A converted config records the pre-commit config it came from
+    let precommit_path = temp_dir.path().join(".pre-commit-config.yaml");
+    let native_path = temp_dir.path().join("config.yaml");
+    fs::write(&precommit_path, "repos: []\n").unwrap();
+    convert_from_precommit(Some(&precommit_path), Some(native_path.clone()), false).unwrap();
+    assert!(rustyhook::config::parse_config(&native_path).is_ok());
+    assert_eq!(check_converted_config(&native_path, &precommit_path), None);
+
+    fs::write(&precommit_path, "repos: []\nfail_fast: true\n").unwrap();
+    assert_eq!(
+        check_converted_config(&native_path, &precommit_path),
+        Some(Drift::PreCommitConfigChanged { path: precommit_path.clone() })
+    );
+}
+
+#[test]
+fn test_workspace_projects() {
+    // Create a repository with Cargo, pnpm and uv workspaces
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    let write = |path: &str, content: &str| {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    };
+    write("Cargo.toml", "[workspace]\nmembers = [\n    \"crates/*\", # every crate\n]\nexclude = [\"crates/scratch\"]\n");
+    write("crates/api/Cargo.toml", "[package]\nname = \"api-server\"\n");
+    write("crates/scratch/Cargo.toml", "[package]\nname = \"scratch\"\n");
+    write("crates/docs/README.md", "");
+    write("pnpm-workspace.yaml", "packages:\n  - 'packages/*'\n  - '!packages/legacy'\n");
+    write("packages/web/package.json", "{\"name\": \"@acme/web\"}");
+    write("packages/legacy/package.json", "{\"name\": \"legacy\"}");
+    write("pyproject.toml", "[tool.uv.workspace]\nmembers = [\"libs/*\"]\n");
+    write("libs/core/pyproject.toml", "[project]\nname = \"core\"\n");
+
+    let projects = discover_projects(root).unwrap();
+    let found: Vec<(&str, &str, ProjectKind)> =
+        projects.iter().map(|p| (p.name.as_str(), p.path.as_str(), p.kind)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("api-server", "crates/api", ProjectKind::Cargo),
+            ("core", "libs/core", ProjectKind::Python),
+            ("@acme/web", "packages/web", ProjectKind::Node),
+        ]
+    );
+
+    // Refreshing replaces the projects block and keeps everything else
+    let config_path = root.join("config.yaml");
+    fs::write(
+        &config_path,
+        r#"# Keep this comment
+projects:
+- name: old
+  path: crates/old
+  kind: cargo
+repos:
+  - repo: local
+    hooks:
+      - id: clippy
+        name: Clippy
+        entry: cargo clippy -p {project.name}
+        language: system
+        files: "\\.rs$"
+        per_project: cargo
+"#,
+    )
+    .unwrap();
+    refresh_projects(&config_path, root).unwrap();
+    let content = fs::read_to_string(&config_path).unwrap();
+    assert!(content.starts_with("# Keep this comment\n"));
+    assert!(!content.contains("crates/old"));
+
+    // Per-project hooks run in each project of their kind, on its files only
+    let config = parse_config(&config_path).unwrap();
+    assert_eq!(config.projects, projects);
+    let expanded = expand_matrix(&config, root).unwrap();
+    let hooks = &expanded.repos[0].hooks;
+    assert_eq!(hooks.len(), 1);
+    assert_eq!(hooks[0].id, "clippy[crates/api]");
+    assert_eq!(hooks[0].entry, "cargo clippy -p api-server");
+    assert_eq!(hooks[0].files, "^crates/api/.*(?:\\.rs$)");
+    assert_eq!(hooks[0].working_dir, Some("crates/api".to_string()));
+    assert!(hooks[0].is_skipped_by(&["clippy".to_string()]));
+}
+
+#[test]
+fn test_entry_as_list() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.yaml");
+    fs::write(
+        &config_path,
+        "repos:\n  - repo: local\n    hooks:\n      - id: generated\n        name: Generated code is current\n        language: system\n        entry:\n          - go generate ./...\n          - git diff --exit-code\n",
+    )
+    .unwrap();
+    let config = parse_config(&config_path).unwrap();
+    let hook = &config.repos[0].hooks[0];
+    assert_eq!(hook.commands(), ["go generate ./...", "git diff --exit-code"]);
+
+    // A string spanning several lines is one command
+    let string = rustyhook::config::Hook { entry: "sh -c\n  true".to_string(), ..rustyhook::testing::hook("string", "system", "") };
+    assert_eq!(string.commands(), ["sh -c\n  true"]);
+
+    // Lists are written back as lists
+    let yaml = serde_yaml::to_string(hook).unwrap();
+    assert!(yaml.contains("entry:\n- go generate ./...\n- git diff --exit-code\n"), "{}", yaml);
+    assert_eq!(serde_yaml::from_str::<rustyhook::config::Hook>(&yaml).unwrap().commands(), hook.commands());
+
+    // Messages of fail hooks aren't split
+    let fail = rustyhook::config::Hook { entry: "first line\nsecond line".to_string(), ..rustyhook::testing::hook("fail", "fail", "") };
+    assert_eq!(fail.commands(), ["first line\nsecond line"]);
+}
+
+#[test]
+fn test_per_os_values() {
+    use rustyhook::config::{Config, PerOs};
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+repos:
+  - repo: local
+    hooks:
+      - id: lint
+        name: Lint
+        language: system
+        entry:
+          windows: node_modules\.bin\eslint.cmd
+          unix: node_modules/.bin/eslint
+        args:
+          default: [--cache]
+          windows: []
+        env:
+          windows:
+            ESLINT_USE_FLAT_CONFIG: "false"
+          default:
+            NODE_OPTIONS: --max-old-space-size=4096
+      - id: plain
+        name: Plain
+        language: system
+        entry: make lint
+        args: [--quiet]
+        env:
+          default: value
+"#,
+    )
+    .unwrap();
+
+    // Values are picked for the host when the config loads
+    let hook = &config.repos[0].hooks[0];
+    if cfg!(windows) {
+        assert_eq!(hook.entry, "node_modules\\.bin\\eslint.cmd");
+        assert!(hook.args.is_empty());
+        assert_eq!(hook.env["ESLINT_USE_FLAT_CONFIG"], "false");
+    } else {
+        assert_eq!(hook.entry, "node_modules/.bin/eslint");
+        assert_eq!(hook.args, ["--cache"]);
+        assert_eq!(hook.env["NODE_OPTIONS"], "--max-old-space-size=4096");
+    }
+
+    // Plain values are unchanged, even with OS names as keys
+    let hook = &config.repos[0].hooks[1];
+    assert_eq!((hook.entry.as_str(), hook.args.as_slice()), ("make lint", ["--quiet".to_string()].as_slice()));
+    assert_eq!(hook.env["default"], "value");
+
+    // The most specific key wins
+    let per_os = || PerOs { windows: Some("windows"), unix: Some("unix"), linux: Some("linux"), macos: None, default: Some("default") };
+    assert_eq!(per_os().resolve_for("linux", "unix"), Some("linux"));
+    assert_eq!(per_os().resolve_for("macos", "unix"), Some("unix"));
+    assert_eq!(per_os().resolve_for("windows", "windows"), Some("windows"));
+    assert_eq!(per_os().resolve_for("plan9", ""), Some("default"));
+
+    // An entry needs a value for the host
+    let other = if cfg!(windows) { "unix" } else { "windows" };
+    let missing = format!("repos:\n  - repo: local\n    hooks:\n      - id: lint\n        name: Lint\n        language: system\n        entry:\n          {}: lint\n", other);
+    let error = serde_yaml::from_str::<Config>(&missing).unwrap_err().to_string();
+    assert!(error.contains("entry has no value for"), "{}", error);
+}
+
+#[test]
+fn test_minimum_rustyhook_version() {
+    use rustyhook::config::min_version::{check_version, CURRENT_VERSION};
+
+    assert!(check_version("0.1", "0.1.0", "The configuration").is_ok());
+    assert!(check_version("v0.1.0-rc.1", "0.1.0", "The configuration").is_ok());
+    assert!(check_version("0.10.0", "0.9.3", "The configuration").is_err());
+    assert!(check_version("not a version", "0.1.0", "The configuration").is_err());
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config_path = temp_dir.path().join("config.yaml");
+    let hooks = "repos:\n  - repo: local\n    hooks:\n      - id: fmt\n        name: Format\n        entry: fmt\n        language: system\n";
+
+    // The running version loads
+    fs::write(&config_path, format!("minimum_rustyhook_version: {}\n{}", CURRENT_VERSION, hooks)).unwrap();
+    assert_eq!(parse_config(&config_path).unwrap().minimum_rustyhook_version.as_deref(), Some(CURRENT_VERSION));
+    fs::write(&config_path, format!("minimum_rustyhook_version: '0.1'\n{}", hooks)).unwrap();
+    assert!(parse_config(&config_path).is_ok());
+
+    // Versions YAML reads as numbers, losing trailing zeros, must be quoted
+    fs::write(&config_path, format!("minimum_rustyhook_version: 0.10\n{}", hooks)).unwrap();
+    let error = format!("{:?}", parse_config(&config_path).unwrap_err());
+    assert!(error.contains("must be quoted"), "{}", error);
+
+    // Newer versions fail to load with a message to upgrade
+    fs::write(&config_path, format!("minimum_rustyhook_version: '999.0.0'\n{}", hooks)).unwrap();
+    let error = format!("{:?}", parse_config(&config_path).unwrap_err());
+    assert!(error.contains("requires rustyhook 999.0.0 or newer"), "{}", error);
+    assert!(error.contains("Upgrade rustyhook"), "{}", error);
+
+    // And so do hooks
+    fs::write(&config_path, format!("{}        minimum_rustyhook_version: '999.0'\n", hooks)).unwrap();
+    let error = format!("{:?}", parse_config(&config_path).unwrap_err());
+    assert!(error.contains("Hook fmt requires rustyhook 999.0"), "{}", error);
+}
+
+#[test]
+fn test_config_templates() {
+    use rustyhook::config::templates::{create_config_from_template, render, TemplateSource, BUILTIN_TEMPLATES};
+
+    let vars: HashMap<String, String> = [("name".to_string(), "api".to_string())].into_iter().collect();
+    assert_eq!(render("id: {{ name }}-{{ suffix | lint }}", &vars).unwrap(), "id: api-lint");
+    assert!(render("id: {{ missing }}", &vars).unwrap_err().contains("--var missing=<value>"));
+    assert!(render("id: {{ name", &vars).is_err());
+
+    // Every built-in template is a valid config
+    let vars: HashMap<String, String> = [("project_name".to_string(), "demo".to_string())].into_iter().collect();
+    for (name, template) in BUILTIN_TEMPLATES {
+        let rendered = render(template, &vars).unwrap();
+        let config: rustyhook::config::Config = serde_yaml::from_str(&rendered).unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert!(!config.repos[0].hooks.is_empty(), "{}", name);
+        assert_eq!(TemplateSource::parse(name).unwrap(), TemplateSource::BuiltIn(name));
+    }
+
+    // Remote templates are only fetched over HTTPS, with their checksum pinned
+    assert!(TemplateSource::parse("http://example.com/template.yaml").is_err());
+    assert!(TemplateSource::parse("https://example.com/template.yaml#md5=abc").is_err());
+    assert_eq!(
+        TemplateSource::parse("https://example.com/template.yaml#sha256=ABC").unwrap(),
+        TemplateSource::Url { url: "https://example.com/template.yaml".to_string(), sha256: Some("abc".to_string()) }
+    );
+    assert!(TemplateSource::parse("no-such-template").is_err());
+
+    // Templates from files, with variables set on the command line
+    let temp_dir = tempfile::tempdir().unwrap();
+    let template_path = temp_dir.path().join("template.yaml");
+    fs::write(&template_path, "repos:\n  - repo: local\n    hooks:\n      - id: lint\n        name: Lint\n        entry: {{ linter }}\n        language: system\n").unwrap();
+    let output_path = temp_dir.path().join("config.yaml");
+    let vars = [("linter".to_string(), "shellcheck".to_string())];
+    create_config_from_template(template_path.to_str().unwrap(), &vars, Some(&output_path)).unwrap();
+    assert_eq!(parse_config(&output_path).unwrap().repos[0].hooks[0].entry, "shellcheck");
+
+    // Nothing is written when the rendered config is invalid
+    fs::write(&template_path, "repos: [{{ linter }}\n").unwrap();
+    let invalid_output = temp_dir.path().join("invalid.yaml");
+    assert!(create_config_from_template(template_path.to_str().unwrap(), &vars, Some(&invalid_output)).is_err());
+    assert!(!invalid_output.exists());
+}
+
+#[test]
+fn test_performance_lint() {
+    use rustyhook::config::{lint_performance, Config, PerfIssueKind};
+
+    let config: Config = serde_yaml::from_str(
+        r#"
+repos:
+  - repo: local
+    hooks:
+      - id: format-all
+        name: Format everything
+        entry: prettier --write
+        language: system
+      - id: ruff
+        name: Ruff
+        entry: ruff check
+        language: python
+        version: latest
+        files: \.py$
+      - id: ruff-format
+        name: Ruff format
+        entry: ruff format
+        language: python
+        version: latest
+        files: \.py$
+      - id: black
+        name: Black
+        entry: black
+        language: python
+        version: "24.1.0"
+        files: \.py$
+"#,
+    )
+    .unwrap();
+
+    let issues = lint_performance(&config);
+    let kinds: Vec<(PerfIssueKind, Vec<&str>)> =
+        issues.iter().map(|issue| (issue.kind, issue.hook_ids.iter().map(String::as_str).collect())).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            (PerfIssueKind::MatchesEverything, vec!["format-all"]),
+            (PerfIssueKind::SharedEnvironment, vec!["ruff", "ruff-format"]),
+            (PerfIssueKind::UnpinnedVersion, vec!["ruff"]),
+            (PerfIssueKind::UnpinnedVersion, vec!["ruff-format"]),
+            (PerfIssueKind::SerialHook, vec!["format-all"]),
+        ]
+    );
+
+    // Each issue suggests the edit that fixes it
+    assert!(issues[1].suggestion.contains("    - ruff check {files}\n    - ruff format"));
+    assert!(issues[4].message.contains("the 3 read-write hook(s) after it wait for it"));
+    assert!(issues[4].suggestion.contains("access_mode: Read"));
+
+    // A clean config has nothing to report
+    let config: Config = serde_yaml::from_str(
+        "repos:\n  - repo: local\n    hooks:\n      - id: black\n        name: Black\n        entry: black\n        language: python\n        version: \"24.1.0\"\n        files: \\.py$\n",
+    )
+    .unwrap();
+    assert!(lint_performance(&config).is_empty());
+}
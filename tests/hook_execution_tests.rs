@@ -1,6 +1,7 @@
 //! Integration tests for hook execution
 
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use rustyhook::config::{Config, Hook, Repo};
 use rustyhook::config::parser::{HookType, AccessMode};
 use rustyhook::runner::{HookResolver, FileMatcher, HookContext, ParallelExecutor};
@@ -55,10 +56,12 @@ fn test_hook_resolver() {
                         hook_type: HookType::External,
                         separate_process: false,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                 ],
             },
         ],
+        ..Default::default()
     };
 
     // Create a hook resolver
@@ -86,6 +89,7 @@ fn test_hook_context() {
         hook_type: HookType::External,
         separate_process: true,
         access_mode: AccessMode::ReadWrite,
+        ..Default::default()
     };
 
     // Create a working directory and files to process
@@ -109,7 +113,7 @@ fn test_hook_context() {
     assert_eq!(context.env.len(), 0);
     assert_eq!(context.version, None);
     assert_eq!(context.hook_type, HookType::External);
-    assert_eq!(context.separate_process, true);
+    assert!(context.separate_process);
     assert_eq!(context.files_to_process, files_to_process);
 }
 
@@ -141,10 +145,12 @@ fn test_run_hook_in_separate_process() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                 ],
             },
         ],
+        ..Default::default()
     };
 
     // Create a hook resolver
@@ -191,6 +197,7 @@ fn test_skip_hooks() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     Hook {
                         id: "hook2".to_string(),
@@ -205,6 +212,7 @@ fn test_skip_hooks() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     Hook {
                         id: "hook3".to_string(),
@@ -219,10 +227,12 @@ fn test_skip_hooks() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                 ],
             },
         ],
+        ..Default::default()
     };
 
     // Create a hook resolver
@@ -268,6 +278,7 @@ fn test_hook_context_execution() {
         hook_type: HookType::External,
         separate_process: false, // Even though this is false, it should run in a separate process because it's an external hook
         access_mode: AccessMode::ReadWrite,
+        ..Default::default()
     };
 
     // Create a hook that should run in a separate process (separate_process = true)
@@ -284,6 +295,7 @@ fn test_hook_context_execution() {
         hook_type: HookType::BuiltIn,
         separate_process: true, // This should cause the hook to run in a separate process
         access_mode: AccessMode::ReadWrite,
+        ..Default::default()
     };
 
     // Create a hook that should run in the same process
@@ -300,6 +312,7 @@ fn test_hook_context_execution() {
         hook_type: HookType::BuiltIn,
         separate_process: false, // This should cause the hook to run in the same process
         access_mode: AccessMode::ReadWrite,
+        ..Default::default()
     };
 
     // Create a working directory and files to process
@@ -368,6 +381,7 @@ fn test_parallel_execution() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     Hook {
                         id: "hook2".to_string(),
@@ -382,6 +396,7 @@ fn test_parallel_execution() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     Hook {
                         id: "hook3".to_string(),
@@ -396,10 +411,12 @@ fn test_parallel_execution() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                 ],
             },
         ],
+        ..Default::default()
     };
 
     // Create a parallel executor
@@ -459,6 +476,7 @@ fn test_mutex_system() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::Read,
+                        ..Default::default()
                     },
                     Hook {
                         id: "read-hook2".to_string(),
@@ -473,6 +491,7 @@ fn test_mutex_system() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::Read,
+                        ..Default::default()
                     },
                     // Read-write hooks with different file patterns
                     Hook {
@@ -488,6 +507,7 @@ fn test_mutex_system() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     Hook {
                         id: "write-hook2".to_string(),
@@ -502,6 +522,7 @@ fn test_mutex_system() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                     // Another read-write hook with the same file pattern as write-hook1
                     Hook {
@@ -517,10 +538,12 @@ fn test_mutex_system() {
                         hook_type: HookType::External,
                         separate_process: true,
                         access_mode: AccessMode::ReadWrite,
+                        ..Default::default()
                     },
                 ],
             },
         ],
+        ..Default::default()
     };
 
     // Create a parallel executor
@@ -547,4 +570,1898 @@ fn test_mutex_system() {
     // 1. Run all read-only hooks in parallel
     // 2. Group read-write hooks by their file patterns
     // 3. Run read-write hooks in parallel only if their file patterns don't overlap
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_execution_plan() {
+    use rustyhook::runner::{ConflictReason, ExecutionPlan};
+
+    let hook = |id: &str, files: &str, access_mode: AccessMode| Hook {
+        id: id.to_string(),
+        name: id.to_string(),
+        entry: format!("echo {}", id),
+        language: "system".to_string(),
+        files: files.to_string(),
+        access_mode,
+        ..Default::default()
+    };
+    let config = Config {
+        default_stages: vec!["commit".to_string()],
+        fail_fast: false,
+        parallelism: 2,
+        repos: vec![Repo {
+            repo: "local".to_string(),
+            hooks: vec![
+                Hook { description: Some("Lints every file".to_string()), ..hook("lint", "", AccessMode::Read) },
+                hook("fmt-rs", ".*\\.rs$", AccessMode::ReadWrite),
+                hook("fmt-py", ".*\\.py$", AccessMode::ReadWrite),
+                hook("fix-rs", ".*\\.rs$", AccessMode::ReadWrite),
+                hook("unmatched", ".*\\.go$", AccessMode::ReadWrite),
+            ],
+        }],
+        ..Default::default()
+    };
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let executor = ParallelExecutor::new(config, temp_dir.path().to_path_buf());
+    let files = vec![PathBuf::from("src/main.rs"), PathBuf::from("main.py")];
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let plan: ExecutionPlan = rt.block_on(executor.plan(&files)).unwrap();
+
+    // Read-only hooks come first, overlapping read-write hooks are split into groups,
+    // and hooks without matching files are left out
+    let stages: Vec<Vec<&str>> = plan
+        .stages
+        .iter()
+        .map(|stage| stage.hooks.iter().map(|hook| hook.1.as_str()).collect())
+        .collect();
+    assert_eq!(stages, vec![vec!["lint"], vec!["fmt-rs", "fmt-py"], vec!["fix-rs"]]);
+    assert_eq!(plan.hook_count(AccessMode::ReadWrite), 3);
+
+    let text = plan.to_text();
+    assert!(text.starts_with("Execution plan (parallelism: 2)"));
+    assert!(text.contains("  - lint [local] - 2 files\n    Lints every file\n"));
+    assert!(text.contains("Stage 2: read-write (2 hooks)\n  - fmt-rs [local] - 1 files"));
+
+    // The conflict splitting the read-write hooks is explained, with the files both hooks run on
+    assert_eq!(plan.conflicts.len(), 1);
+    assert_eq!((plan.conflicts[0].hook_id.as_str(), plan.conflicts[0].other.as_str()), ("fix-rs", "fmt-rs"));
+    assert_eq!(plan.conflicts[0].reason, ConflictReason::SamePattern { pattern: ".*\\.rs$".to_string() });
+    assert!(text.contains("Conflicts\n  - fix-rs waits for fmt-rs: both have the files pattern `.*\\.rs$`, shared files: src/main.rs\n"), "{}", text);
+    assert!(text.contains("Concurrency\n  - Stage 1: up to 2 hooks at a time (the parallelism limit)\n"), "{}", text);
+    assert_eq!(plan.stage_limit(0, true).hooks, 1);
+
+    let dot = plan.to_dot();
+    assert!(dot.starts_with("digraph plan {"));
+    assert!(dot.contains("subgraph cluster_2"));
+    assert!(dot.contains("n0 -> n1 [ltail=cluster_0, lhead=cluster_1];"));
+    assert!(dot.contains("n1 -> n3 [ltail=cluster_1, lhead=cluster_2];"));
+
+    let mermaid = plan.to_mermaid();
+    assert!(mermaid.starts_with("flowchart LR"));
+    assert!(mermaid.contains("stage2 --> stage3"));
+}
+
+#[test]
+fn test_missing_commands_are_reported_before_running() {
+    use rustyhook::runner::ParallelExecutionError;
+    use rustyhook::testing::{hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one \n");
+
+    let mut optional = hook("optional", "system", "rustyhook-missing-optional");
+    optional.continue_on_error = true;
+    let config = local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("lint", "system", "rustyhook-missing-linter --strict"),
+        hook("lint-again", "system", "rustyhook-missing-linter"),
+        optional,
+    ]);
+
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let result = rt.block_on(executor.run_all_hooks(repo.staged_files()));
+
+    // Each missing command is listed once with the hooks needing it, and nothing runs
+    let Err(ParallelExecutionError::MissingCommands(missing)) = result else {
+        panic!("expected missing commands, got {:?}", result.map_err(|e| e.to_string()));
+    };
+    assert_eq!(missing.len(), 1);
+    assert_eq!(missing[0].command, "rustyhook-missing-linter");
+    assert_eq!(missing[0].hooks, ["lint", "lint-again"]);
+    assert!(rt.block_on(executor.report()).hooks.is_empty());
+    assert_eq!(repo.read("notes.txt"), "one \n");
+
+    // Entries are looked up in the PATH the hook runs with
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        use rustyhook::runner::capabilities::probe;
+
+        let tool = repo.write("tools/rustyhook-extra-linter", "#!/bin/sh\n");
+        fs::set_permissions(&tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let mut extra = hook("extra", "system", "rustyhook-extra-linter");
+        assert_eq!(probe([&extra], repo.cache_dir()).len(), 1);
+        extra.env.insert("PATH".to_string(), format!("{}:$PATH", repo.path().join("tools").display()));
+        assert!(probe([&extra], repo.cache_dir()).is_empty());
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_duplicate_invocations_run_once() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::runner::SkipReason;
+    use rustyhook::testing::{hook, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("app/main.py", "print('hi')\n");
+    repo.write_staged("README.md", "hi\n");
+
+    // The same formatter declared twice, with patterns matching the same files
+    let script = repo.write("format.sh", "#!/bin/sh\necho \"$@\" >> \"$(dirname \"$0\")/invocations.log\"\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    let format = |id: &str, files: &str| {
+        let mut format = hook(id, "system", script.to_str().unwrap());
+        format.files = files.to_string();
+        format.access_mode = AccessMode::ReadWrite;
+        format
+    };
+    let mut other_args = format("format-check", r"\.py$");
+    other_args.args = vec!["--check".to_string()];
+    let mut other_dir = format("format-app", r"\.py$");
+    other_dir.working_dir = Some(repo.path().join("app").to_string_lossy().into_owned());
+    let config = Config {
+        repos: vec![
+            Repo { repo: "local".to_string(), hooks: vec![format("format", r"\.py$"), other_args, format("app-format", r"^app/.*\.py$"), other_dir] },
+            Repo { repo: "other".to_string(), hooks: vec![format("other-format", r"\.py$")] },
+        ],
+        ..Default::default()
+    };
+
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let plan = rt.block_on(executor.plan(&repo.staged_files())).unwrap();
+    let planned: Vec<&str> = plan.stages.iter().flat_map(|stage| stage.hooks.iter().map(|hook| hook.1.as_str())).collect();
+    assert_eq!(planned, ["format", "format-check", "format-app", "other-format"]);
+    assert_eq!(plan.skipped[0].hook_id, "app-format");
+    assert_eq!(plan.skipped[0].reason, SkipReason::Duplicate { of: "format".to_string() });
+
+    // Hooks with other arguments, working directories or repos are separate invocations
+    rt.block_on(executor.run_all_hooks(repo.staged_files())).unwrap();
+    assert_eq!(repo.read("invocations.log").lines().count(), 4);
+
+    // Tools running hooks by their ID run a different hook for each ID
+    let by_id = |id: &str| Hook { hook_type: HookType::BuiltIn, ..hook(id, "python", "pre-commit-hooks") };
+    let config = Config { repos: vec![Repo { repo: "local".to_string(), hooks: vec![by_id("check-json"), by_id("check-yaml")] }], ..Default::default() };
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let plan = rt.block_on(executor.plan(&repo.staged_files())).unwrap();
+    assert!(plan.skipped.is_empty());
+}
+
+#[test]
+fn test_fail_language_hook() {
+    // Create a temporary directory for the test
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = temp_dir.path().to_path_buf();
+
+    // Create a configuration with a fail hook that forbids .env files
+    let config = Config {
+        repos: vec![
+            Repo {
+                repo: "local".to_string(),
+                hooks: vec![
+                    Hook {
+                        id: "no-env-files".to_string(),
+                        name: "Forbid .env files".to_string(),
+                        entry: "Do not commit .env files".to_string(),
+                        language: "fail".to_string(),
+                        files: "\\.env$".to_string(),
+                        hook_type: HookType::External,
+                        ..Default::default()
+                    },
+                ],
+            },
+        ],
+        ..Default::default()
+    };
+
+    let mut resolver = HookResolver::new(config, cache_dir);
+
+    // The hook fails when a matching file is present
+    let result = resolver.run_hook("local", "no-env-files", &[PathBuf::from("config/.env")]);
+    let err = result.expect_err("fail hook should fail on matching files");
+    let message = format!("{:?}", err);
+    assert!(message.contains("Do not commit .env files"));
+    assert!(message.contains("config/.env"));
+
+    // The hook passes when no files match
+    let result = resolver.run_hook("local", "no-env-files", &[PathBuf::from("src/main.rs")]);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_pygrep_language_hook() {
+    // Create a temporary directory for the test
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = temp_dir.path().join("cache");
+
+    let clean = temp_dir.path().join("clean.py");
+    let dirty = temp_dir.path().join("dirty.py");
+    fs::write(&clean, "import logging\nlogging.info('ok')\n").unwrap();
+    fs::write(&dirty, "import pdb\nx = 1\nPDB.set_trace()\n").unwrap();
+
+    // Create a configuration with pygrep hooks, one using flags
+    let config = Config {
+        repos: vec![
+            Repo {
+                repo: "local".to_string(),
+                hooks: vec![
+                    Hook {
+                        id: "no-pdb".to_string(),
+                        name: "No pdb".to_string(),
+                        entry: "pdb\\.set_trace\\(\\)".to_string(),
+                        language: "pygrep".to_string(),
+                        args: vec!["--ignore-case".to_string()],
+                        hook_type: HookType::External,
+                        ..Default::default()
+                    },
+                    Hook {
+                        id: "require-logging".to_string(),
+                        name: "Require logging".to_string(),
+                        entry: "^import logging$".to_string(),
+                        language: "pygrep".to_string(),
+                        args: vec!["--multiline".to_string(), "--negate".to_string()],
+                        hook_type: HookType::External,
+                        ..Default::default()
+                    },
+                    Hook {
+                        id: "python-check-blanket-noqa".to_string(),
+                        name: "Blanket noqa".to_string(),
+                        entry: "(?i)# noqa(?!: )".to_string(),
+                        language: "pygrep".to_string(),
+                        hook_type: HookType::External,
+                        ..Default::default()
+                    },
+                    Hook {
+                        id: "no-bare-pass".to_string(),
+                        name: "No bare pass".to_string(),
+                        entry: "^\\s*pass$".to_string(),
+                        language: "pygrep".to_string(),
+                        hook_type: HookType::External,
+                        ..Default::default()
+                    },
+                ],
+            },
+        ],
+        ..Default::default()
+    };
+
+    let mut resolver = HookResolver::new(config, cache_dir);
+
+    // Matching lines are reported as file:line:content
+    let err = resolver.run_hook("local", "no-pdb", &[clean.clone(), dirty.clone()])
+        .expect_err("pygrep hook should fail on matching lines");
+    let message = format!("{:?}", err);
+    assert!(message.contains(&format!("{}:3:PDB.set_trace()", dirty.display())));
+    assert!(!message.contains("clean.py"));
+    assert!(resolver.run_hook("local", "no-pdb", std::slice::from_ref(&clean)).is_ok());
+
+    // Negated searches report the files without a match
+    let err = resolver.run_hook("local", "require-logging", &[clean.clone(), dirty.clone()])
+        .expect_err("negated pygrep hook should fail on files without a match");
+    let message = format!("{:?}", err);
+    assert!(message.contains("dirty.py"));
+    assert!(!message.contains("clean.py"));
+
+    // Look-arounds work as in Python's re
+    let noqa = temp_dir.path().join("noqa.py");
+    fs::write(&noqa, "a = 1  # noqa: E501\nb = 2  # NOQA\n").unwrap();
+    let err = resolver.run_hook("local", "python-check-blanket-noqa", std::slice::from_ref(&noqa))
+        .expect_err("blanket noqa should be reported");
+    let message = format!("{:?}", err);
+    assert!(message.contains(&format!("{}:2:b = 2  # NOQA", noqa.display())), "{}", message);
+    assert!(!message.contains(":1:"), "{}", message);
+
+    // `$` matches at the end of CRLF lines
+    let crlf = temp_dir.path().join("crlf.py");
+    fs::write(&crlf, "def f():\r\n    pass\r\n").unwrap();
+    let err = resolver.run_hook("local", "no-bare-pass", std::slice::from_ref(&crlf))
+        .expect_err("pass on a CRLF line should match");
+    assert!(format!("{:?}", err).contains(&format!("{}:2:    pass", crlf.display())));
+}
+
+#[test]
+fn test_staged_changes_classification() {
+    use rustyhook::runner::{staged_changes, ChangeKind};
+
+    // Create a repository with an initial commit
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    let repo = git2::Repository::init(&root).unwrap();
+    fs::write(root.join("removed.txt"), "gone soon\n").unwrap();
+    fs::write(root.join("old_name.txt"), "a file that keeps its content\nacross the rename\n").unwrap();
+    fs::write(root.join("edited.txt"), "before\n").unwrap();
+
+    let mut index = repo.index().unwrap();
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "initial", &tree, &[]).unwrap();
+
+    // Stage a deletion, a rename, a modification and an addition
+    fs::remove_file(root.join("removed.txt")).unwrap();
+    fs::rename(root.join("old_name.txt"), root.join("new_name.txt")).unwrap();
+    fs::write(root.join("edited.txt"), "after\n").unwrap();
+    fs::write(root.join("added.txt"), "new\n").unwrap();
+    index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+    index.update_all(["*"], None).unwrap();
+    index.write().unwrap();
+
+    let changes = staged_changes(&root).unwrap();
+    let kind_of = |name: &str| changes.iter().find(|c| c.path == root.join(name)).map(|c| c.kind.clone());
+    assert_eq!(kind_of("removed.txt"), Some(ChangeKind::Deleted));
+    assert_eq!(kind_of("new_name.txt"), Some(ChangeKind::Renamed { from: root.join("old_name.txt") }));
+    assert_eq!(kind_of("edited.txt"), Some(ChangeKind::Modified));
+    assert_eq!(kind_of("added.txt"), Some(ChangeKind::Added));
+
+    // The deletions and renames are exposed to hooks through the environment
+    let hook = Hook {
+        id: "refs".to_string(),
+        name: "Check references".to_string(),
+        entry: "true".to_string(),
+        language: "system".to_string(),
+        include_deleted: true,
+        ..Default::default()
+    };
+    let mut context = HookContext::from_hook(&hook, root.clone(), vec![]);
+    context.set_changes(&changes);
+    assert_eq!(context.env["RH_DELETED_FILES"], root.join("removed.txt").display().to_string());
+    assert_eq!(
+        context.env["RH_RENAMED_FILES"],
+        format!("{}\t{}", root.join("old_name.txt").display(), root.join("new_name.txt").display())
+    );
+}
+
+#[test]
+fn test_native_language_hook() {
+    // Create a temporary directory for the test
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = temp_dir.path().join("cache");
+    let file = temp_dir.path().join("README.md");
+    fs::write(&file, "Teh quick brown fox\n").unwrap();
+
+    // Native hooks name a built-in hook as their entry, and take the checker's arguments
+    let config = Config {
+        repos: vec![
+            Repo {
+                repo: "local".to_string(),
+                hooks: vec![
+                    Hook {
+                        id: "typos".to_string(),
+                        name: "Typos".to_string(),
+                        entry: "codespell".to_string(),
+                        language: "native".to_string(),
+                        ..Default::default()
+                    },
+                    Hook {
+                        id: "typos-ignored".to_string(),
+                        name: "Typos".to_string(),
+                        entry: "codespell".to_string(),
+                        language: "native".to_string(),
+                        args: vec!["--ignore-words-list=teh".to_string()],
+                        ..Default::default()
+                    },
+                ],
+            },
+        ],
+        ..Default::default()
+    };
+
+    let mut resolver = HookResolver::new(config, cache_dir);
+
+    let err = resolver.run_hook("local", "typos", std::slice::from_ref(&file))
+        .expect_err("native codespell should report the typo");
+    assert!(format!("{:?}", err).contains("Teh ==> the"));
+
+    assert!(resolver.run_hook("local", "typos-ignored", std::slice::from_ref(&file)).is_ok());
+}
+
+#[test]
+fn test_fixer_changes_are_recorded() {
+    use rustyhook::runner::diff::{diff_text, format_diffs, format_summary};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = temp_dir.path().join("cache");
+    let file = temp_dir.path().join("notes.txt");
+    fs::write(&file, "one  \ntwo\nthree\n").unwrap();
+    let untouched = temp_dir.path().join("clean.txt");
+    fs::write(&untouched, "clean\n").unwrap();
+
+    let config = Config {
+        repos: vec![Repo {
+            repo: "local".to_string(),
+            hooks: vec![Hook {
+                id: "trailing-whitespace".to_string(),
+                name: "Trim".to_string(),
+                entry: "trailing-whitespace".to_string(),
+                language: "native".to_string(),
+                access_mode: AccessMode::ReadWrite,
+                ..Default::default()
+            }],
+        }],
+        ..Default::default()
+    };
+
+    let executor = ParallelExecutor::new(config, cache_dir);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(executor.run_all_hooks(vec![file.clone(), untouched])).unwrap();
+
+    // Only the file the fixer changed is recorded
+    let changes = rt.block_on(executor.fixer_changes());
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].hook_id, "trailing-whitespace");
+    assert_eq!(changes[0].files.len(), 1);
+    assert_eq!((changes[0].files[0].added, changes[0].files[0].removed), (1, 1));
+    assert!(changes[0].files[0].unified.contains("@@ -1,3 +1,3 @@\n-one  \n+one\n two\n three\n"));
+
+    let summary = format_summary(&changes, false);
+    assert!(summary.starts_with("trailing-whitespace modified 1 file(s):"));
+    assert!(summary.contains("notes.txt +1 -1"));
+    assert!(format_diffs(&changes, true).contains("\x1b[32m+one\x1b[0m"));
+
+    // Hunks keep three lines of context and mark a missing final newline
+    let before = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+    let after = "1\n2\n3\n4\n5\n6\n7\n8\n9\nten";
+    let diff = diff_text(std::path::Path::new("n.txt"), before, after);
+    assert_eq!(diff.unified, "--- a/n.txt\n+++ b/n.txt\n@@ -7,4 +7,4 @@\n 7\n 8\n 9\n-10\n+ten\n\\ No newline at end of file\n");
+}
+
+#[test]
+fn test_tool_key_and_legacy_migration() {
+    let hook = Hook {
+        id: "typos".to_string(),
+        name: "Typos".to_string(),
+        entry: "codespell".to_string(),
+        language: "native".to_string(),
+        version: Some("1.0.0".to_string()),
+        ..Default::default()
+    };
+
+    // The key is stable, readable, and changes with the version and entry
+    let key = HookResolver::tool_key(&hook);
+    assert_eq!(key, HookResolver::tool_key(&hook.clone()));
+    assert!(key.starts_with("native-codespell-"));
+    assert_eq!(key.len(), "native-codespell-".len() + 16);
+    let upgraded = Hook { version: Some("2.0.0".to_string()), ..hook.clone() };
+    assert_ne!(key, HookResolver::tool_key(&upgraded));
+    let other_entry = Hook { entry: "trailing-whitespace".to_string(), ..hook.clone() };
+    assert_ne!(key, HookResolver::tool_key(&other_entry));
+
+    // Hooks installing the same package share an environment, whatever their ID and arguments
+    let ruff = |id: &str, entry: &str| Hook { id: id.to_string(), entry: entry.to_string(), language: "python".to_string(), version: Some("0.4.4".to_string()), ..Default::default() };
+    let shared = HookResolver::tool_key(&ruff("ruff[api]", "ruff check api"));
+    assert_eq!(shared, HookResolver::tool_key(&ruff("ruff[web]", "ruff check web")));
+    assert!(shared.starts_with("python-ruff-"));
+    assert_ne!(shared, HookResolver::tool_key(&Hook { version: Some("0.5.0".to_string()), ..ruff("ruff[web]", "ruff check web") }));
+
+    // Their environment is set up once, so two setups never work in its directory at the same time
+    let setup_dir = tempfile::tempdir().unwrap();
+    let file = setup_dir.path().join("app.py");
+    fs::write(&file, "print()\n").unwrap();
+    let config = Config {
+        repos: vec![Repo { repo: "local".to_string(), hooks: vec![
+            Hook { hook_type: HookType::BuiltIn, ..ruff("ruff", "ruff check") },
+            Hook { hook_type: HookType::BuiltIn, ..ruff("ruff-format", "ruff format") },
+        ] }],
+        ..Default::default()
+    };
+    let mut resolver = HookResolver::new(config, setup_dir.path().join("cache"));
+    let mut started = std::collections::HashSet::new();
+    assert_eq!(resolver.begin_setups("local", "ruff", std::slice::from_ref(&file), &mut started).unwrap().len(), 1);
+    assert!(resolver.begin_setups("local", "ruff-format", std::slice::from_ref(&file), &mut started).unwrap().is_empty());
+
+    // Directories using the old `{language}-{id}-{hash}` key are moved on first use
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_dir = temp_dir.path().join("cache");
+    let legacy_dir = cache_dir.join("venvs").join(&HookResolver::legacy_tool_keys(&hook)[0]);
+    fs::create_dir_all(&legacy_dir).unwrap();
+    fs::write(legacy_dir.join("marker"), "").unwrap();
+    // The first `{language}-{id}` keys don't say which version they hold, so they aren't
+    let unversioned_dir = cache_dir.join("venvs").join("native-typos");
+    fs::create_dir_all(&unversioned_dir).unwrap();
+    let file = temp_dir.path().join("README.md");
+    fs::write(&file, "The quick brown fox\n").unwrap();
+
+    let config = Config {
+        repos: vec![Repo { repo: "local".to_string(), hooks: vec![hook] }],
+        ..Default::default()
+    };
+    let mut resolver = HookResolver::new(config, cache_dir.clone());
+    resolver.run_hook("local", "typos", std::slice::from_ref(&file)).unwrap();
+
+    assert!(!legacy_dir.exists());
+    assert!(cache_dir.join("venvs").join(&key).join("marker").exists());
+    assert!(unversioned_dir.exists());
+
+    // Migrated environments predate readiness markers, and are kept rather than rebuilt
+    let ruff = Hook { hook_type: HookType::BuiltIn, ..ruff("ruff", "ruff check") };
+    let legacy_dir = cache_dir.join("venvs").join(&HookResolver::legacy_tool_keys(&ruff)[0]);
+    fs::create_dir_all(&legacy_dir).unwrap();
+    fs::write(legacy_dir.join("marker"), "").unwrap();
+    let config = Config { repos: vec![Repo { repo: "local".to_string(), hooks: vec![ruff.clone()] }], ..Default::default() };
+    let mut resolver = HookResolver::new(config, cache_dir.clone());
+    let file = temp_dir.path().join("app.py");
+    fs::write(&file, "print()\n").unwrap();
+    resolver.begin_setups("local", "ruff", std::slice::from_ref(&file), &mut std::collections::HashSet::new()).unwrap();
+    let migrated = cache_dir.join("venvs").join(HookResolver::tool_key(&ruff));
+    assert!(migrated.join("marker").exists());
+    assert!(migrated.join(".ready").exists());
+}
+
+#[test]
+fn test_hook_fail_fast_and_continue_on_error() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let file = temp_dir.path().join("notes.txt");
+
+    let config = |fail_fast: bool, continue_on_error: bool| Config {
+        repos: vec![Repo {
+            repo: "local".to_string(),
+            hooks: vec![
+                Hook {
+                    id: "no-commit".to_string(),
+                    name: "Always fails".to_string(),
+                    entry: "Not allowed".to_string(),
+                    language: "fail".to_string(),
+                    access_mode: AccessMode::Read,
+                    fail_fast,
+                    continue_on_error,
+                    ..Default::default()
+                },
+                Hook {
+                    id: "trailing-whitespace".to_string(),
+                    name: "Trim".to_string(),
+                    entry: "trailing-whitespace".to_string(),
+                    language: "native".to_string(),
+                    access_mode: AccessMode::ReadWrite,
+                    ..Default::default()
+                },
+            ],
+        }],
+        ..Default::default()
+    };
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let run = |config: Config| {
+        fs::write(&file, "one  \n").unwrap();
+        let executor = ParallelExecutor::new(config, temp_dir.path().join("cache"));
+        let result = rt.block_on(executor.run_all_hooks(vec![file.clone()]));
+        (result.is_ok(), fs::read_to_string(&file).unwrap())
+    };
+
+    // A failure fails the run, but the later stages still run
+    assert_eq!(run(config(false, false)), (false, "one\n".to_string()));
+
+    // fail_fast stops before the read-write stage
+    assert_eq!(run(config(true, false)), (false, "one  \n".to_string()));
+
+    // continue_on_error only reports the failure
+    assert_eq!(run(config(false, true)), (true, "one\n".to_string()));
+}
+
+#[test]
+fn test_container_image_hooks() {
+    use rustyhook::runner::container::{command, Mount};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path().canonicalize().unwrap();
+    fs::create_dir(root.join("pkg")).unwrap();
+    fs::write(root.join("pkg").join("main.go"), "package main\n").unwrap();
+
+    // Hooks with an image skip the toolchain and run their entry in a container
+    let hook = Hook {
+        id: "golangci-lint".to_string(),
+        entry: "golangci-lint run".to_string(),
+        language: "system".to_string(),
+        image: Some("golangci/golangci-lint:v1.55".to_string()),
+        working_dir: Some("pkg".to_string()),
+        ..Default::default()
+    };
+    let context = HookContext::from_hook(&hook, root.clone(), vec![root.join("pkg").join("main.go")]);
+    assert_eq!(context.image.as_deref(), Some("golangci/golangci-lint:v1.55"));
+    assert!(context.should_run_in_separate_process());
+
+    // Paths are mapped into the mounted repository
+    let mount = Mount { host_root: root.clone(), working_dir: context.working_dir.clone() };
+    assert_eq!(mount.container_path(&root.join("pkg").join("main.go")), "/src/pkg/main.go");
+    assert_eq!(mount.container_path(std::path::Path::new("main.go")), "main.go");
+
+    let env = std::collections::HashMap::from([("GOFLAGS".to_string(), "-mod=mod".to_string())]);
+    let argv = vec!["golangci-lint".to_string(), "run".to_string(), "/src/pkg/main.go".to_string()];
+    let cmd = command("docker", "golangci/golangci-lint:v1.55", "golangci-lint", &mount, &env, &argv);
+    let args: Vec<String> = cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    assert_eq!(cmd.get_program(), "docker");
+    assert_eq!(&args[..6], ["run", "--rm", "--label", "dev.rustyhook.hook=golangci-lint", "--volume", &format!("{}:/src", root.display())]);
+    assert_eq!(&args[6..8], ["--workdir", "/src/pkg"]);
+    assert!(args.windows(2).any(|pair| pair == ["--env", "GOFLAGS=-mod=mod"]));
+    assert!(args.ends_with(&[
+        "--entrypoint".to_string(),
+        "golangci-lint".to_string(),
+        "golangci/golangci-lint:v1.55".to_string(),
+        "run".to_string(),
+        "/src/pkg/main.go".to_string(),
+    ]));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_container_image_cleanup() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::runner::container::{clean, image_usage, record_image, ImageRecords};
+
+    // A fake runtime that already has one image, logging the other commands
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_root = temp_dir.path().join("cache");
+    let log = temp_dir.path().join("runtime.log");
+    let runtime = temp_dir.path().join("runtime");
+    fs::write(
+        &runtime,
+        format!(
+            r#"#!/bin/sh
+case "$1 $2" in
+  "image inspect")
+    case "$5" in
+      local/linter|golangci/golangci-lint:v1.55) [ "$4" = "{{{{.Size}}}}" ] && echo 2000000 || echo sha256:abc ;;
+      *) exit 1 ;;
+    esac ;;
+  "ps --all") echo c1; echo c2 ;;
+  *) echo "$@" >> {} ;;
+esac
+"#,
+            log.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&runtime, fs::Permissions::from_mode(0o755)).unwrap();
+    let runtime = runtime.to_str().unwrap();
+
+    // Images the runtime doesn't have yet are pulled by the hook
+    record_image(runtime, "golangci/golangci-lint:v1.55", &cache_root);
+    record_image(runtime, "local/linter", &cache_root);
+    let records = ImageRecords::load(&cache_root);
+    assert!(!records.images["golangci/golangci-lint:v1.55"].pulled);
+    assert!(!records.images["local/linter"].pulled);
+    record_image(runtime, "hadolint/hadolint:v2", &cache_root);
+    let mut records = ImageRecords::load(&cache_root);
+    assert!(records.images["hadolint/hadolint:v2"].pulled);
+
+    // Once recorded, an image keeps whether it was pulled
+    records.images.get_mut("golangci/golangci-lint:v1.55").unwrap().pulled = true;
+    records.save(&cache_root).unwrap();
+    record_image(runtime, "golangci/golangci-lint:v1.55", &cache_root);
+    assert!(ImageRecords::load(&cache_root).images["golangci/golangci-lint:v1.55"].pulled);
+
+    // Only images the runtime still has count
+    let usage = image_usage(runtime, &cache_root);
+    assert_eq!(usage.images.len(), 2);
+    assert_eq!(usage.pulled_bytes, 2_000_000);
+
+    // Cleaning removes leftover containers and pulled images, and forgets the rest
+    let cleaned = clean(runtime, &cache_root).unwrap();
+    assert_eq!(cleaned.containers, 2);
+    assert_eq!(cleaned.images, ["golangci/golangci-lint:v1.55"]);
+    assert_eq!(fs::read_to_string(&log).unwrap(), "rm --force c1 c2\nrmi golangci/golangci-lint:v1.55\n");
+    assert!(ImageRecords::load(&cache_root).images.is_empty());
+}
+
+#[test]
+fn test_orphaned_environments() {
+    use rustyhook::cache::orphans::{add_reference, find_orphaned_environments, prune_orphaned_environments, references, touch_last_used};
+    use std::time::Duration;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_root = temp_dir.path();
+    let hook = Hook {
+        id: "black".to_string(),
+        entry: "black".to_string(),
+        language: "python".to_string(),
+        ..Default::default()
+    };
+    let config = Config {
+        repos: vec![Repo { repo: "local".to_string(), hooks: vec![hook.clone()] }],
+        ..Default::default()
+    };
+
+    let used = cache_root.join("venvs").join(HookResolver::tool_key(&hook));
+    let legacy = cache_root.join("cache").join(&HookResolver::legacy_tool_keys(&hook)[0]);
+    let removed_hook = cache_root.join("venvs").join("python-flake8-0123456789abcdef");
+    for dir in [&used, &legacy, &removed_hook, &cache_root.join("cache").join("repos")] {
+        fs::create_dir_all(dir).unwrap();
+    }
+    touch_last_used(&removed_hook);
+    assert!(removed_hook.join(".last-used").exists());
+
+    // Only directories of hooks missing from the config are orphans
+    let orphans = find_orphaned_environments(cache_root, &config);
+    assert_eq!(orphans.len(), 1);
+    assert_eq!(orphans[0].path, removed_hook);
+    assert!(orphans[0].unused_for < Duration::from_secs(60));
+
+    // Recently used orphans are kept during the grace period
+    assert!(prune_orphaned_environments(&orphans, Duration::from_secs(3600)).unwrap().is_empty());
+    assert!(removed_hook.exists());
+
+    assert_eq!(prune_orphaned_environments(&orphans, Duration::ZERO).unwrap(), vec![removed_hook.clone()]);
+    assert!(!removed_hook.exists());
+    assert!(used.exists() && legacy.exists());
+
+    // The first `{language}-{id}` keys don't say which version they hold, so their environments are orphans
+    let unversioned = cache_root.join("cache").join("python-black");
+    fs::create_dir_all(&unversioned).unwrap();
+    assert_eq!(find_orphaned_environments(cache_root, &config).iter().map(|orphan| &orphan.path).collect::<Vec<_>>(), [&unversioned]);
+    fs::remove_dir_all(&unversioned).unwrap();
+
+    // Environments stay in use while another project that used them still configures them
+    let other_project = temp_dir.path().join("other");
+    fs::create_dir_all(other_project.join(".rustyhook")).unwrap();
+    let flake8 = HookResolver::tool_key(&Hook { id: "flake8".to_string(), entry: "flake8".to_string(), language: "python".to_string(), ..Default::default() });
+    let shared = cache_root.join("venvs").join(&flake8);
+    fs::create_dir_all(&shared).unwrap();
+    add_reference(&shared, &other_project);
+    add_reference(&shared, &other_project);
+    assert_eq!(references(&shared), [other_project.canonicalize().unwrap()]);
+
+    let other_config = "repos:\n  - repo: local\n    hooks:\n      - id: flake8\n        name: flake8\n        entry: flake8\n        language: python\n";
+    fs::write(other_project.join(".rustyhook").join("config.yaml"), other_config).unwrap();
+    assert!(find_orphaned_environments(cache_root, &config).is_empty());
+    fs::write(other_project.join(".rustyhook").join("config.yaml"), "repos: []\n").unwrap();
+    assert_eq!(find_orphaned_environments(cache_root, &config).iter().map(|orphan| &orphan.path).collect::<Vec<_>>(), [&shared]);
+    fs::remove_dir_all(&shared).unwrap();
+
+    // So do hook caches, which are keyed by project and repo as well as hook
+    let hook_cache = rustyhook::cache::hook_cache_dir(cache_root, &other_project, "local", "flake8");
+    fs::create_dir_all(&hook_cache).unwrap();
+    add_reference(&hook_cache, &other_project);
+    fs::write(other_project.join(".rustyhook").join("config.yaml"), other_config).unwrap();
+    assert!(find_orphaned_environments(cache_root, &config).is_empty());
+    fs::write(other_project.join(".rustyhook").join("config.yaml"), "repos: []\n").unwrap();
+    assert_eq!(find_orphaned_environments(cache_root, &config).iter().map(|orphan| &orphan.path).collect::<Vec<_>>(), [&hook_cache]);
+    fs::remove_dir_all(&hook_cache).unwrap();
+
+    // Hooks asking for the latest version use the environment of the version in the lockfile
+    let pinned = Hook { version: Some("7.0.0".to_string()), id: "flake8".to_string(), entry: "flake8".to_string(), language: "python".to_string(), ..Default::default() };
+    let shared = cache_root.join("venvs").join(HookResolver::tool_key(&pinned));
+    fs::create_dir_all(&shared).unwrap();
+    add_reference(&shared, &other_project);
+    fs::write(other_project.join(".rustyhook").join("config.yaml"), format!("{}        version: latest\n", other_config)).unwrap();
+    let mut lockfile = rustyhook::config::Lockfile::default();
+    lockfile.versions.insert("pypi:flake8".to_string(), "7.0.0".to_string());
+    lockfile.save(&rustyhook::config::Lockfile::path(&other_project)).unwrap();
+    assert!(!find_orphaned_environments(cache_root, &config).iter().any(|orphan| orphan.path == shared));
+}
+
+#[test]
+fn test_run_report_with_test_repo() {
+    use rustyhook::runner::HookStatus;
+    use rustyhook::testing::{assert_failed, assert_passed, hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("committed.txt", "old\n");
+    repo.commit("Initial commit");
+    repo.write_staged("notes.txt", "one  \n");
+    repo.write_staged("config.yaml", "key: [\n");
+    repo.remove_staged("committed.txt");
+    repo.write("unstaged.txt", "two  \n");
+    assert_eq!(repo.staged_files().len(), 2);
+
+    let mut warn_only = hook("no-yaml", "fail", "YAML is not allowed");
+    warn_only.files = r"\.yaml$".to_string();
+    warn_only.continue_on_error = true;
+    let mut check_yaml = hook("check-yaml", "native", "check-yaml");
+    check_yaml.access_mode = AccessMode::Read;
+    let report = repo.run(local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        check_yaml,
+        warn_only,
+    ]));
+
+    // Only staged files are checked
+    assert_passed(&report, "trailing-whitespace");
+    assert_eq!(hook_result(&report, "trailing-whitespace").files_modified, vec![repo.path().join("notes.txt")]);
+    assert_eq!(repo.read("notes.txt"), "one\n");
+    assert_eq!(repo.read("unstaged.txt"), "two  \n");
+
+    assert_failed(&report, "check-yaml");
+    assert_eq!(hook_result(&report, "no-yaml").status, HookStatus::AllowedFailure);
+    assert_eq!(hook_result(&report, "no-yaml").file_count, 1);
+    assert!(!report.is_success());
+}
+
+#[test]
+fn test_gitattributes_in_text_hooks() {
+    use rustyhook::runner::{GitAttributes, LineEnding};
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged(".gitattributes", "*.dat binary\ngen/** linguist-generated\n*.bat eol=crlf\n");
+    repo.write_staged("data.dat", "raw  ");
+    repo.write_staged("gen/api.rs", "fn api() {}  ");
+    repo.write_staged("run.bat", "echo hi");
+    repo.write_staged("notes.txt", "notes  ");
+
+    let attributes = GitAttributes::discover(repo.path());
+    assert!(attributes.get(&repo.path().join("data.dat")).binary);
+    assert!(attributes.get(&repo.path().join("gen/api.rs")).generated);
+    assert_eq!(attributes.get(&repo.path().join("run.bat")).eol, Some(LineEnding::Crlf));
+    assert!(attributes.get(&repo.path().join("notes.txt")).is_text());
+
+    // Cached attributes are reused until the attribute files change
+    let cached = || GitAttributes::discover(repo.path()).with_cache(repo.cache_dir());
+    assert!(cached().get(&repo.path().join("data.dat")).binary);
+    let entries = repo.cache_dir().join("metadata").join("file-attributes");
+    assert_eq!(fs::read_dir(&entries).unwrap().count(), 1);
+    assert!(cached().get(&repo.path().join("data.dat")).binary);
+    assert_eq!(fs::read_dir(&entries).unwrap().count(), 1);
+    repo.write_staged(".gitattributes", "*.bat eol=crlf\n");
+    assert!(!cached().get(&repo.path().join("data.dat")).binary);
+    repo.write_staged(".gitattributes", "*.dat binary\ngen/** linguist-generated\n*.bat eol=crlf\n");
+
+    let mut large_files = hook("check-added-large-files", "native", "check-added-large-files");
+    large_files.access_mode = AccessMode::Read;
+    let mut opted_in = hook("generated-too", "native", "trailing-whitespace");
+    opted_in.files = r"gen/".to_string();
+    opted_in.text_only = Some(false);
+    let report = repo.run(local_config(vec![
+        hook("end-of-file-fixer", "native", "end-of-file-fixer"),
+        large_files,
+        opted_in,
+    ]));
+
+    // Text hooks skip binary and generated files, and use the eol attribute
+    assert_eq!(hook_result(&report, "end-of-file-fixer").file_count, 3);
+    assert_eq!(repo.read("run.bat"), "echo hi\r\n");
+    assert_eq!(repo.read("notes.txt"), "notes  \n");
+    assert_eq!(repo.read("data.dat"), "raw  ");
+
+    // Hooks that don't read content, or that opt in, still see every file
+    assert_eq!(hook_result(&report, "check-added-large-files").file_count, 5);
+    assert_eq!(repo.read("gen/api.rs"), "fn api() {}");
+}
+
+#[test]
+fn test_record_and_replay_bundle() {
+    use rustyhook::runner::record::{snapshot_files, REDACTED};
+    use rustyhook::runner::{Bundle, HookStatus};
+    use rustyhook::testing::{hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("src/notes.txt", "one  \n");
+    repo.write_staged("config.yaml", "key: value\n");
+
+    let mut no_yaml = hook("no-yaml", "fail", "YAML is not allowed");
+    no_yaml.files = r"\.yaml$".to_string();
+    let config = local_config(vec![hook("trailing-whitespace", "native", "trailing-whitespace"), no_yaml]);
+
+    // Files are recorded before the fixer changes them
+    let bundle_dir = tempfile::tempdir().unwrap();
+    let files = snapshot_files(bundle_dir.path(), repo.path(), &repo.staged_files()).unwrap();
+    let report = repo.run(config.clone());
+    assert_eq!(repo.read("src/notes.txt"), "one\n");
+
+    let bundle = Bundle::new(config, repo.path(), files, &report);
+    let no_yaml = bundle.hooks.iter().find(|hook| hook.hook_id == "no-yaml").unwrap();
+    assert_eq!(no_yaml.status, HookStatus::Failed);
+    assert_eq!(no_yaml.command, vec!["YAML", "is", "not", "allowed", "config.yaml"]);
+    assert!(bundle.env.iter().all(|(name, value)| !name.contains("TOKEN") || value == REDACTED));
+    bundle.write(bundle_dir.path()).unwrap();
+
+    // The replay sees the recorded content and gets the recorded results
+    let bundle = Bundle::read(bundle_dir.path()).unwrap();
+    let replay = TestRepo::new();
+    let restored = bundle.restore(bundle_dir.path(), replay.path()).unwrap();
+    assert_eq!(replay.read("src/notes.txt"), "one  \n");
+    assert_eq!(replay.staged_files().len(), 2);
+
+    let replayed = replay.run_on(bundle.config.clone(), restored);
+    assert!(bundle.differences(&replayed).is_empty(), "{:?}", bundle.differences(&replayed));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_cache_directory() {
+    use rustyhook::cache::hook_cache_dir;
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "notes\n");
+    let output = repo.path().join("cache-dir.txt");
+    let script = repo.write(
+        "print-cache.sh",
+        format!("#!/bin/sh\nprintf '%s\\n%s\\n%s\\n' \"$RH_HOOK_CACHE_DIR\" \"$LINT_CACHE\" \"$1\" > {}\n", output.display()),
+    );
+
+    let mut lint = hook("lint[app]", "system", &format!("sh {}", script.display()));
+    lint.args = vec!["{hook_cache_dir}".to_string()];
+    lint.env.insert("LINT_CACHE".to_string(), "{hook_cache_dir}/lint".to_string());
+    lint.files = r"\.txt$".to_string();
+    let report = repo.run(local_config(vec![lint]));
+    assert_passed(&report, "lint[app]");
+
+    // The directory is stable, exists, and is given through the environment and templating
+    let project = std::env::current_dir().unwrap();
+    let dir = hook_cache_dir(repo.cache_dir(), &project, "local", "lint[app]");
+    assert!(dir.is_dir());
+    assert!(dir.file_name().unwrap().to_string_lossy().starts_with("lint_app_-"));
+
+    // Hooks with the same ID in other repos or projects get their own directory
+    assert_ne!(hook_cache_dir(repo.cache_dir(), &project, "https://example.com/lint", "lint[app]"), dir);
+    assert_ne!(hook_cache_dir(repo.cache_dir(), repo.path(), "local", "lint[app]"), dir);
+    let dir = dir.display().to_string();
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), format!("{}\n{}/lint\n{}\n", dir, dir, dir));
+}
+
+#[test]
+fn test_skip_reasons() {
+    use rustyhook::runner::{SkipReason, SkippedHook};
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+
+    let mut unmatched = hook("unmatched", "native", "trailing-whitespace");
+    unmatched.files = r"\.go$".to_string();
+    let config = local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("end-of-file-fixer", "native", "end-of-file-fixer"),
+        unmatched,
+    ]);
+
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(executor.set_hooks_to_skip(vec!["end-of-file-fixer".to_string()]));
+    rt.block_on(executor.record_skipped(vec![SkippedHook {
+        repo: "local".to_string(),
+        hook_id: "pre-push-only".to_string(),
+        reason: SkipReason::StageMismatch { stage: "pre-commit".to_string() },
+    }]));
+    rt.block_on(executor.run_all_hooks(repo.staged_files())).unwrap();
+    let report = rt.block_on(executor.report());
+
+    // Hooks that ran have no skip reason, every other configured hook has one
+    assert_passed(&report, "trailing-whitespace");
+    assert_eq!(report.skip_reason("trailing-whitespace"), None);
+    assert_eq!(report.skip_reason("unmatched"), Some(&SkipReason::NoMatchingFiles));
+    assert_eq!(report.skip_reason("end-of-file-fixer"), Some(&SkipReason::SkipList));
+    assert_eq!(
+        report.skip_reason("pre-push-only").map(ToString::to_string),
+        Some("does not run in the pre-commit stage".to_string())
+    );
+
+    // Skip reasons serialize with a stable tag
+    let json = serde_json::to_string(&report.skipped[0]).unwrap();
+    assert_eq!(json, r#"{"repo":"local","hook_id":"end-of-file-fixer","reason":"skip-list"}"#);
+}
+
+#[test]
+fn test_status_for_prompts() {
+    use rustyhook::runner::status::{cached_config_facts, install_nudge, status, ConfigState, HookInstall};
+    use rustyhook::toolchains::ready::mark_ready;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root = temp_dir.path();
+    let cache_root = root.join(".rustyhook");
+    let config_path = cache_root.join("config.yaml");
+    fs::create_dir_all(root.join(".git").join("hooks")).unwrap();
+    fs::create_dir_all(&cache_root).unwrap();
+    fs::write(
+        &config_path,
+        format!("cache_dir: {}\nrepos:\n  - repo: local\n    hooks:\n      - id: ruff\n        name: Ruff\n        entry: ruff\n        language: python\n      - id: check-yaml\n        name: Check YAML\n        entry: check-yaml\n        language: native\n", root.join("envs").display()),
+    )
+    .unwrap();
+
+    // Only hooks with a managed toolchain need an environment
+    let facts = cached_config_facts(&config_path, root, &cache_root, None);
+    assert_eq!(facts.state, ConfigState::Valid);
+    assert_eq!(facts.environments.len(), 1);
+    let exe = std::path::Path::new("/usr/local/bin/rustyhook");
+    let report = status(Some(&root.join(".git")), Some(exe), facts.clone());
+    assert_eq!(report.hooks, HookInstall::Missing);
+    assert_eq!(report.missing_environments, vec!["ruff".to_string()]);
+    assert_eq!(report.to_porcelain(), "hooks missing\nconfig valid\nenvironments missing 1\n");
+    assert!(!report.is_ok());
+
+    // Runs nudge to install the hook, once per repository
+    let nudge = install_nudge(Some(&root.join(".git")), &cache_root).unwrap();
+    assert!(nudge.contains("Run 'rustyhook install'"));
+    assert_eq!(install_nudge(Some(&root.join(".git")), &cache_root), None);
+    assert_eq!(install_nudge(None, &root.join("other-cache")), None);
+
+    // Installing the hook and setting up the environment makes the status ok
+    fs::write(root.join(".git").join("hooks").join("pre-commit"), "#!/bin/sh\n# RustyHook Git hook\n/usr/local/bin/rustyhook run\n").unwrap();
+    let environment = &facts.environments[0];
+    mark_ready(&environment.install_dir, &environment.manifest).unwrap();
+    let report = status(Some(&root.join(".git")), Some(exe), cached_config_facts(&config_path, root, &cache_root, None));
+    assert_eq!(report.to_porcelain(), "hooks installed\nconfig valid\nenvironments ready\n");
+    assert!(report.is_ok());
+    assert_eq!(install_nudge(Some(&root.join(".git")), &root.join("other-cache")), None);
+
+    // Another binary makes the hook stale
+    let report = status(Some(&root.join(".git")), Some(std::path::Path::new("/opt/rustyhook")), facts);
+    assert_eq!(report.hooks, HookInstall::Stale);
+
+    // Changing the config invalidates the cached facts
+    fs::write(&config_path, "repos: [\n").unwrap();
+    let facts = cached_config_facts(&config_path, root, &cache_root, None);
+    assert!(matches!(facts.state, ConfigState::Invalid { .. }));
+    assert!(status(None, None, facts).to_porcelain().contains("config invalid\n"));
+}
+
+#[test]
+fn test_policy_hooks_are_mandatory() {
+    use rustyhook::config::{load_policy, merge_policy};
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    let policy_path = repo.path().join("policy.yaml");
+    fs::write(
+        &policy_path,
+        r#"
+blocked_message: "Ask #security about {hooks}"
+repos:
+  - repo: security
+    hooks:
+      - id: trailing-whitespace
+        name: Trailing whitespace
+        language: native
+        entry: trailing-whitespace
+"#,
+    )
+    .unwrap();
+
+    // The policy's hook replaces the repository's hook with the same id
+    let mut weakened = hook("trailing-whitespace", "native", "trailing-whitespace");
+    weakened.files = r"\.go$".to_string();
+    let config = local_config(vec![weakened, hook("end-of-file-fixer", "native", "end-of-file-fixer")]);
+    let location = policy_path.to_string_lossy().into_owned();
+    let merged = merge_policy(&config, &load_policy(&location, repo.cache_dir()).unwrap());
+    assert_eq!(merged.blocked_message.as_deref(), Some("Ask #security about {hooks}"));
+    let hooks: Vec<_> = merged.repos.iter().flat_map(|repo| &repo.hooks).collect();
+    assert_eq!(hooks.len(), 2);
+    let mandatory = hooks.iter().find(|hook| hook.id == "trailing-whitespace").unwrap();
+    assert_eq!(mandatory.policy.as_deref(), Some(location.as_str()));
+    assert_eq!(mandatory.files, "");
+
+    // Skipping every hook still runs the policy's
+    let executor = ParallelExecutor::new(merged, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    rt.block_on(executor.set_hooks_to_skip(vec!["trailing-whitespace".to_string(), "end-of-file-fixer".to_string()]));
+    rt.block_on(executor.run_all_hooks(repo.staged_files())).unwrap();
+    let report = rt.block_on(executor.report());
+    assert_passed(&report, "trailing-whitespace");
+    assert!(report.skip_reason("end-of-file-fixer").is_some());
+}
+
+#[test]
+fn test_run_metrics_file() {
+    use rustyhook::runner::metrics::RunMetrics;
+    use rustyhook::runner::EnvironmentSetup;
+    use rustyhook::testing::{hook, local_config, TestRepo};
+    use std::time::Duration;
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    let mut report = repo.run(local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("no-notes", "fail", "Notes are not allowed"),
+    ]));
+    report.setups = vec![
+        EnvironmentSetup { hook_id: "ruff".to_string(), tool: "python-ruff".to_string(), reused: true, duration: Duration::from_millis(3) },
+        EnvironmentSetup { hook_id: "eslint".to_string(), tool: "node-eslint".to_string(), reused: false, duration: Duration::from_secs(2) },
+    ];
+
+    let path = repo.path().join("out").join("metrics.json");
+    RunMetrics::from_report(&report, Duration::from_millis(1500)).write(&path).unwrap();
+    let metrics: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+
+    assert_eq!(metrics["version"], 1);
+    assert_eq!(metrics["duration_ms"], 1500);
+    assert_eq!(metrics["success"], false);
+    assert_eq!(metrics["failed"], 1);
+    assert_eq!(metrics["passed"], 1);
+    assert_eq!(metrics["cache_hits"], 1);
+    assert_eq!(metrics["cache_misses"], 1);
+    assert_eq!(metrics["environments"][1]["setup_ms"], 2000);
+    let hooks = metrics["hooks"].as_array().unwrap();
+    let failed = hooks.iter().find(|hook| hook["id"] == "no-notes").unwrap();
+    assert_eq!(failed["status"], "failed");
+    assert_eq!(failed["files"], 1);
+    assert_eq!(failed["error"]["code"], "RH3002");
+    assert_eq!(failed["error"]["category"], "hook");
+    assert!(hooks.iter().find(|hook| hook["id"] == "trailing-whitespace").unwrap().get("error").is_none());
+}
+
+#[test]
+fn test_hook_timings() {
+    use rustyhook::runner::metrics::RunMetrics;
+    use rustyhook::runner::{CacheState, EnvironmentSetup};
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+    use std::time::Duration;
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    let mut report = repo.run(local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("check-yaml", "native", "check-yaml"),
+    ]));
+
+    // A hook sharing the environment of the hook that set it up
+    let tool = hook_result(&report, "trailing-whitespace").tool.clone();
+    let mut sharing = hook_result(&report, "trailing-whitespace").clone();
+    sharing.hook_id = "trailing-whitespace-again".to_string();
+    report.hooks.push(sharing);
+    report.setups = vec![EnvironmentSetup { hook_id: "trailing-whitespace".to_string(), tool, reused: false, duration: Duration::from_secs(2) }];
+
+    // Setup is charged to the first hook needing the environment, apart from its execution
+    let timing = report.timing(hook_result(&report, "trailing-whitespace"));
+    assert_eq!((timing.setup, timing.cache), (Duration::from_secs(2), Some(CacheState::Cold)));
+    assert_eq!(timing.exec, hook_result(&report, "trailing-whitespace").duration);
+    let timing = report.timing(hook_result(&report, "trailing-whitespace-again"));
+    assert_eq!((timing.setup, timing.cache), (Duration::ZERO, Some(CacheState::Cold)));
+    let timing = report.timing(hook_result(&report, "check-yaml"));
+    assert_eq!((timing.setup, timing.cache), (Duration::ZERO, None));
+
+    let table = report.format_timings();
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("Hook") && lines[0].ends_with("Setup      Exec  Cache"), "{}", table);
+    assert!(lines[1].starts_with("trailing-whitespace ") && lines[1].contains("   2.00s") && lines[1].ends_with("  cold"), "{}", table);
+    assert!(lines[2].starts_with("check-yaml ") && lines[2].ends_with("  -"), "{}", table);
+    assert!(lines[4].starts_with("Total") && lines[4].contains("2.00s"), "{}", table);
+
+    let metrics = serde_json::to_value(RunMetrics::from_report(&report, Duration::from_secs(3))).unwrap();
+    assert_eq!(metrics["hooks"][0]["setup_ms"], 2000);
+    assert_eq!(metrics["hooks"][0]["cache"], "cold");
+    assert!(metrics["hooks"][1].get("setup_ms").is_none() && metrics["hooks"][1].get("cache").is_none());
+}
+
+#[test]
+fn test_run_summary() {
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one \n");
+    repo.write_staged("data.json", "{\"broken\":\n");
+    let mut never = hook("never", "fail", "Never commit this");
+    never.continue_on_error = true;
+    let report = repo.run(local_config(vec![
+        hook("check-json", "native", "check-json"),
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        never,
+    ]));
+
+    // Every hook runs, and failures keep what went wrong without the advice
+    assert!(!report.is_success());
+    let details = hook_result(&report, "check-json").details.clone().unwrap();
+    assert!(details.starts_with("Invalid JSON in"), "{}", details);
+    assert!(hook_result(&report, "trailing-whitespace").details.is_none());
+
+    let summary = report.format_summary();
+    let lines: Vec<&str> = summary.lines().collect();
+    assert!(lines[0].starts_with("Hook") && lines[0].ends_with("Status           Duration  Modified"), "{}", summary);
+    assert!(lines[1].starts_with("check-json ") && lines[1].contains(" failed ") && lines[1].ends_with(" 0"), "{}", summary);
+    assert!(lines[2].starts_with("trailing-whitespace ") && lines[2].contains(" passed ") && lines[2].ends_with(" 1"), "{}", summary);
+    assert!(lines[3].starts_with("never ") && lines[3].contains(" allowed failure "), "{}", summary);
+    assert_eq!(lines[4], "3 hooks: 1 passed, 1 failed, 1 allowed failures, 0 skipped");
+    assert!(summary.contains("\ncheck-json (failed):\n  Invalid JSON in"), "{}", summary);
+    assert!(summary.contains("\nnever (allowed failure):\n  Never commit this\n"), "{}", summary);
+}
+
+#[test]
+fn test_config_fail_fast() {
+    use rustyhook::runner::{HookStatus, SkipReason};
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one \n");
+    let mut stop = hook("stop", "fail", "Stop here");
+    stop.access_mode = AccessMode::Read;
+    let mut check = hook("check-json", "native", "check-json");
+    check.access_mode = AccessMode::Read;
+    let mut config = local_config(vec![stop, check, hook("trailing-whitespace", "native", "trailing-whitespace")]);
+    config.fail_fast = true;
+    config.run_parallelism = Some(1);
+    let report = repo.run(config);
+
+    // The first failure stops the run, and hooks that didn't start are reported as cancelled
+    assert_eq!(hook_result(&report, "stop").status, HookStatus::Failed);
+    let cancelled = SkipReason::Cancelled { by: "stop".to_string() };
+    assert_eq!(report.skip_reason("check-json"), Some(&cancelled));
+    assert_eq!(report.skip_reason("trailing-whitespace"), Some(&cancelled));
+    assert_eq!(repo.read("notes.txt"), "one \n");
+    assert!(report.format_summary().contains("Cancelled after stop failed: check-json, trailing-whitespace\n"), "{}", report.format_summary());
+
+    // Hooks already running when the run stops finish, and are reported as finished after the failure
+    let mut slow = hook("slow", "system", "sh");
+    slow.args = vec!["-c".to_string(), "sleep 1".to_string()];
+    slow.access_mode = AccessMode::Read;
+    let mut stop = hook("stop", "system", "sh");
+    stop.args = vec!["-c".to_string(), "sleep 0.2; exit 1".to_string()];
+    stop.access_mode = AccessMode::Read;
+    let mut config = local_config(vec![stop, slow]);
+    config.fail_fast = true;
+    // Both hooks block a worker thread while they run, so they need one each
+    let rt = tokio::runtime::Builder::new_multi_thread().worker_threads(2).enable_all().build().unwrap();
+    let executor = rustyhook::runner::ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    assert!(rt.block_on(executor.run_all_hooks(vec![repo.path().join("notes.txt")])).is_err());
+    let report = rt.block_on(executor.report());
+    let slow = hook_result(&report, "slow");
+    assert_eq!(slow.status, HookStatus::Passed);
+    assert_eq!(slow.finished_after.as_deref(), Some("stop"));
+    assert!(report.format_summary().contains("Finished after stop failed: slow\n"), "{}", report.format_summary());
+}
+
+#[test]
+fn test_audit_log() {
+    use rustyhook::runner::audit::{append, read, verify, AuditEntry, AuditError};
+    use rustyhook::testing::{hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    repo.commit("Initial commit");
+    repo.write_staged("data.json", "{\"broken\": \n");
+    let report = repo.run(local_config(vec![hook("check-json", "native", "check-json")]));
+
+    // Entries record the commit being prepared and the hooks' results
+    let log = repo.path().join(".rustyhook").join("audit.jsonl");
+    let first = append(&log, AuditEntry::new(&report, repo.path(), "abc".to_string())).unwrap();
+    let head = git2::Repository::open(repo.path()).unwrap().head().unwrap().peel_to_commit().unwrap().id().to_string();
+    assert_eq!(first.head.as_deref(), Some(head.as_str()));
+    assert!(first.tree.is_some());
+    assert!(!first.success);
+    assert_eq!((first.hooks[0].id.as_str(), first.prev_hash.as_deref()), ("check-json", None));
+
+    // Each entry is chained to the one before it
+    let second = append(&log, AuditEntry::new(&report, repo.path(), "abc".to_string())).unwrap();
+    assert_eq!(second.prev_hash, Some(first.hash.clone()));
+    assert_eq!(read(&log).unwrap(), vec![first, second]);
+    assert_eq!(verify(&log).unwrap(), 2);
+
+    // Changing an entry breaks the chain
+    let content = fs::read_to_string(&log).unwrap();
+    fs::write(&log, content.replacen("\"success\":false", "\"success\":true", 1)).unwrap();
+    assert!(matches!(verify(&log), Err(AuditError::BrokenChain { line: 1, .. })));
+
+    // So does removing one
+    fs::write(&log, content.lines().nth(1).unwrap().to_string() + "\n").unwrap();
+    assert!(matches!(verify(&log), Err(AuditError::BrokenChain { line: 1, .. })));
+    fs::write(&log, content).unwrap();
+    assert_eq!(verify(&log).unwrap(), 2);
+
+    // Runs finishing together still chain their entries one after the other
+    let root = repo.path().to_path_buf();
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| append(&log, AuditEntry::new(&report, &root, "abc".to_string())).unwrap());
+        }
+    });
+    assert_eq!(verify(&log).unwrap(), 10);
+}
+
+#[test]
+fn test_verify_access_modes() {
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one  \n");
+    repo.write_staged("config.yaml", "key: value\n");
+
+    // A fixer declared read-only, next to a hook that really only reads
+    let mut mislabeled = hook("trailing-whitespace", "native", "trailing-whitespace");
+    mislabeled.access_mode = AccessMode::Read;
+    let mut check_yaml = hook("check-yaml", "native", "check-yaml");
+    check_yaml.access_mode = AccessMode::Read;
+    let config = local_config(vec![mislabeled, check_yaml]);
+
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    executor.set_verify_access_modes(true);
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let files = repo.staged_files().into_iter().map(|file| repo.path().join(file)).collect();
+    let _ = rt.block_on(executor.run_all_hooks(files));
+    let report = rt.block_on(executor.report());
+
+    assert_eq!(hook_result(&report, "trailing-whitespace").files_modified, vec![repo.path().join("notes.txt")]);
+    assert!(hook_result(&report, "check-yaml").files_modified.is_empty());
+}
+
+#[test]
+fn test_read_hooks_modifying_files() {
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one  \n");
+    repo.write_staged("config.yaml", "key: value\n");
+
+    // Normal runs notice a read-only hook changing its own files
+    let mut mislabeled = hook("trailing-whitespace", "native", "trailing-whitespace");
+    mislabeled.access_mode = AccessMode::Read;
+    mislabeled.files = "\\.txt$".to_string();
+    let mut check_yaml = hook("check-yaml", "native", "check-yaml");
+    check_yaml.access_mode = AccessMode::Read;
+    check_yaml.files = "\\.yaml$".to_string();
+    let report = repo.run(local_config(vec![mislabeled, check_yaml]));
+
+    assert_eq!(hook_result(&report, "trailing-whitespace").files_modified, vec![repo.path().join("notes.txt")]);
+    assert!(hook_result(&report, "check-yaml").files_modified.is_empty());
+    assert_eq!(report.access_mode_violations.len(), 1);
+    assert_eq!(report.access_mode_violations[0].hook_id, "trailing-whitespace");
+    assert_eq!(report.access_mode_violations[0].files, vec![repo.path().join("notes.txt")]);
+    assert!(report.access_mode_violations[0].shared_with.is_empty());
+    assert!(report.access_mode_violations[0].to_string().contains("declare it ReadWrite"));
+
+    // A change to a file other read-only hooks ran on at the same time can't be pinned on one of them
+    repo.write_staged("notes.txt", "three  \n");
+    let mut mislabeled = hook("trailing-whitespace", "native", "trailing-whitespace");
+    mislabeled.access_mode = AccessMode::Read;
+    mislabeled.files = "\\.txt$".to_string();
+    let mut merge_conflict = hook("check-merge-conflict", "native", "check-merge-conflict");
+    merge_conflict.access_mode = AccessMode::Read;
+    merge_conflict.files = "\\.txt$".to_string();
+    let report = repo.run(local_config(vec![mislabeled, merge_conflict]));
+    let violation = report.access_mode_violations.iter().find(|violation| violation.hook_id == "trailing-whitespace").unwrap();
+    assert_eq!(violation.shared_with, vec!["check-merge-conflict".to_string()]);
+    assert!(violation.to_string().contains("ran alongside 'check-merge-conflict'"));
+
+    // Read-write hooks modifying files are fine
+    repo.write_staged("notes.txt", "two  \n");
+    let report = repo.run(local_config(vec![hook("trailing-whitespace", "native", "trailing-whitespace")]));
+    assert!(!hook_result(&report, "trailing-whitespace").files_modified.is_empty());
+    assert!(report.access_mode_violations.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_repo_relative_hook_paths() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("src/main.py", "print('hi')\n");
+    let script = repo.write("print-args.sh", "#!/bin/sh\necho \"$@\" >> \"$(dirname \"$0\")/$ARGS_FILE\"\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let args_hook = |id: &str, args_file: &str| {
+        let mut hook = hook(id, "system", &script.to_string_lossy());
+        hook.files = r"\.py$".to_string();
+        hook.separate_process = true;
+        hook.working_dir = Some(repo.path().to_string_lossy().into_owned());
+        hook.env.insert("ARGS_FILE".to_string(), args_file.to_string());
+        hook
+    };
+    let mut absolute = args_hook("absolute", "absolute.txt");
+    absolute.absolute_paths = true;
+    let report = repo.run(local_config(vec![args_hook("relative", "relative.txt"), absolute]));
+    assert_passed(&report, "relative");
+    assert_passed(&report, "absolute");
+
+    // Files are relative to where the hook runs unless it asks for absolute paths
+    assert_eq!(repo.read("relative.txt"), "src/main.py\n");
+    let absolute_args = repo.read("absolute.txt");
+    assert!(Path::new(absolute_args.trim()).is_absolute());
+    assert!(absolute_args.trim().ends_with("src/main.py"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_args_after_files() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("main.py", "print('hi')\n");
+    let script = repo.write("print-args.sh", "#!/bin/sh\necho \"$@\" > \"$(dirname \"$0\")/args.txt\"\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut args_hook = hook("args", "system", &script.to_string_lossy());
+    args_hook.files = r"\.py$".to_string();
+    args_hook.separate_process = true;
+    args_hook.working_dir = Some(repo.path().to_string_lossy().into_owned());
+    args_hook.args = vec!["--check".to_string(), "--".to_string()];
+    args_hook.args_after_files = vec!["--config".to_string(), "{hook_cache_dir}/tool.toml".to_string()];
+    let report = repo.run(local_config(vec![args_hook]));
+    assert_passed(&report, "args");
+
+    // Args come before the files and args_after_files after them, placeholders replaced
+    let args = repo.read("args.txt");
+    assert!(args.starts_with("--check -- main.py --config "), "{}", args);
+    assert!(args.contains("/hook-cache/args-") && args.trim_end().ends_with("/tool.toml") && !args.contains("{hook_cache_dir}"), "{}", args);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_output_cap() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::testing::{assert_failed, hook, hook_result, local_config, TestRepo};
+    use rustyhook::toolchains::output::{truncate, OutputLimit, Truncation};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    let script = repo.write("noisy.sh", "#!/bin/sh\necho start >&2\nseq 1 5000 >&2\necho end >&2\nexit 1\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let mut noisy = hook("noisy", "system", &script.to_string_lossy());
+    noisy.separate_process = true;
+    noisy.max_output = Some(200);
+    let report = repo.run(local_config(vec![noisy]));
+    assert_failed(&report, "noisy");
+
+    // The start and end are kept, and the full output is saved in the cache
+    let message = hook_result(&report, "noisy").message.clone().unwrap();
+    assert!(message.len() < 600, "{}", message);
+    assert!(message.contains("start"));
+    assert!(message.contains("end"));
+    let log = message.split("full output in ").nth(1).unwrap().split(" ...]").next().unwrap();
+    assert!(log.starts_with(&repo.cache_dir().join("logs").to_string_lossy().into_owned()));
+    assert!(fs::read_to_string(log).unwrap().contains("\n2500\n"));
+
+    // Head and tail truncation keep one end, without splitting characters
+    let limit = |truncation| OutputLimit { hook_id: "lint".to_string(), max_bytes: 5, truncation, log_dir: None };
+    assert_eq!(truncate(&limit(Truncation::Head), "stdout", "ééé-end".as_bytes()), "éé\n[... 6 bytes truncated ...]");
+    assert_eq!(truncate(&limit(Truncation::Tail), "stdout", "start-ééé".as_bytes()), "[... 8 bytes truncated ...]\néé");
+    assert_eq!(truncate(&limit(Truncation::HeadTail), "stdout", b"short"), "short");
+}
+
+#[test]
+fn test_progress_events() {
+    use std::sync::{Arc, Mutex};
+    use rustyhook::runner::{HookStatus, ProgressEvent};
+    use rustyhook::testing::{hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "clean\n");
+    let config = local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("no-notes", "fail", "Notes aren't allowed"),
+    ]);
+
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let events = executor.progress().channel();
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&finished);
+    executor.progress().subscribe(move |event| {
+        if let ProgressEvent::HookFinished(result) = event {
+            recorded.lock().unwrap().push((result.hook_id.clone(), result.status.clone()));
+        }
+    });
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    assert!(rt.block_on(executor.run_all_hooks(repo.staged_files())).is_err());
+
+    // The channel sees the run from the first phase to the end
+    let events: Vec<ProgressEvent> = events.try_iter().collect();
+    assert_eq!(events.first(), Some(&ProgressEvent::PhaseStarted { access_mode: AccessMode::Read, hooks: 0 }));
+    assert!(events.contains(&ProgressEvent::PhaseStarted { access_mode: AccessMode::ReadWrite, hooks: 2 }));
+    assert_eq!(events.last(), Some(&ProgressEvent::RunFinished { success: false }));
+    for id in ["trailing-whitespace", "no-notes"] {
+        let started = events.iter().position(|event| matches!(event, ProgressEvent::HookStarted { hook_id, file_count: 1, .. } if hook_id == id));
+        let finished = events.iter().position(|event| matches!(event, ProgressEvent::HookFinished(result) if result.hook_id == id));
+        assert!(started.unwrap() < finished.unwrap(), "{} should start before it finishes", id);
+    }
+
+    // Callbacks get the same outcomes as the report
+    let mut finished = finished.lock().unwrap().clone();
+    finished.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(finished, [("no-notes".to_string(), HookStatus::Failed), ("trailing-whitespace".to_string(), HookStatus::Passed)]);
+}
+
+#[test]
+fn test_reports_follow_config_order() {
+    use clap::Parser;
+    use rustyhook::{Cli, Commands};
+    use rustyhook::testing::{hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "notes\n");
+    let ids = ["zeta", "beta", "gamma", "alpha"];
+    let config = local_config(ids.iter().map(|id| Hook { access_mode: AccessMode::Read, ..hook(id, "fail", &format!("{} failed", id)) }).collect());
+
+    // Hooks finish in any order, but are reported as declared
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let err = rt.block_on(executor.run_all_hooks(repo.staged_files())).unwrap_err();
+    assert!(err.to_string().contains("zeta failed"), "{}", err);
+    let report = rt.block_on(executor.report());
+    let reported: Vec<&str> = report.hooks.iter().map(|hook| hook.hook_id.as_str()).collect();
+    assert_eq!(reported, ids);
+
+    let cli = Cli::try_parse_from(["rustyhook", "--jobs", "1", "run", "--deterministic"]).unwrap();
+    assert_eq!(cli.parallelism, 1);
+    assert!(matches!(cli.command, Commands::Run { deterministic: true, .. }));
+}
+
+#[test]
+fn test_oversized_files() {
+    use rustyhook::config::parser::OversizedFiles;
+    use rustyhook::runner::{SkipReason, SkippedFile};
+    use rustyhook::testing::{assert_failed, assert_passed, hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("small.txt", "small  \n");
+    repo.write_staged("large.txt", format!("{}  \n", "x".repeat(100)));
+
+    let limited = |id: &str, entry: &str, files: &str| Hook {
+        files: files.to_string(),
+        max_file_size: Some(50),
+        ..hook(id, "native", entry)
+    };
+    let failing = Hook { oversized_files: OversizedFiles::Fail, ..limited("large-fails", "check-merge-conflict", "") };
+    let report = repo.run(local_config(vec![
+        limited("trailing-whitespace", "trailing-whitespace", ""),
+        limited("only-large", "trailing-whitespace", r"^large"),
+        failing,
+    ]));
+
+    // Oversized files are skipped and reported, and the other files still run
+    assert_passed(&report, "trailing-whitespace");
+    assert_eq!(hook_result(&report, "trailing-whitespace").file_count, 1);
+    assert_eq!(repo.read("small.txt"), "small\n");
+    assert!(repo.read("large.txt").ends_with("x  \n"));
+    assert_eq!(report.skip_reason("only-large"), Some(&SkipReason::FilesTooLarge));
+    assert_eq!(
+        report.skipped_files,
+        [
+            SkippedFile { repo: "local".to_string(), hook_id: "trailing-whitespace".to_string(), path: repo.path().join("large.txt"), size: 103, limit: 50 },
+            SkippedFile { repo: "local".to_string(), hook_id: "only-large".to_string(), path: repo.path().join("large.txt"), size: 103, limit: 50 },
+        ]
+    );
+
+    // With oversized_files: fail, the hook fails without running
+    assert_failed(&report, "large-fails");
+    let message = hook_result(&report, "large-fails").message.clone().unwrap();
+    assert!(message.contains("large.txt (103 bytes)"), "{}", message);
+}
+
+#[test]
+fn test_default_excludes() {
+    use rustyhook::testing::{hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("src/main.js", "main();  \n");
+    repo.write_staged("vendor/lib.js", "lib();  \n");
+    repo.write_staged("web/node_modules/dep/index.js", "dep();  \n");
+    repo.write_staged("app.min.js", "app();  \n");
+    repo.write_staged("Cargo.lock", "version = 3  \n");
+    repo.write_staged("src/schema.js", "// @generated by schema-gen\nschema();  \n");
+
+    // Vendored, built and minified files are skipped, and fixers also skip lockfiles and generated files
+    let mut codespell = hook("codespell", "native", "codespell");
+    codespell.args = vec!["-w".to_string()];
+    let report = repo.run(local_config(vec![
+        hook("trailing-whitespace", "native", "trailing-whitespace"),
+        hook("check-merge-conflict", "native", "check-merge-conflict"),
+        codespell,
+    ]));
+    assert_eq!(hook_result(&report, "trailing-whitespace").file_count, 1);
+    assert_eq!(hook_result(&report, "check-merge-conflict").file_count, 3);
+    assert_eq!(hook_result(&report, "codespell").file_count, 1);
+    assert_eq!(repo.read("src/main.js"), "main();\n");
+    assert_eq!(repo.read("vendor/lib.js"), "lib();  \n");
+    assert_eq!(repo.read("Cargo.lock"), "version = 3  \n");
+    assert_eq!(repo.read("src/schema.js"), "// @generated by schema-gen\nschema();  \n");
+
+    // no_default_excludes runs hooks on every file
+    let mut config = local_config(vec![hook("trailing-whitespace", "native", "trailing-whitespace")]);
+    config.no_default_excludes = true;
+    let report = repo.run(config);
+    assert_eq!(hook_result(&report, "trailing-whitespace").file_count, 6);
+    assert_eq!(repo.read("vendor/lib.js"), "lib();\n");
+}
+
+#[test]
+fn test_preflight_commands() {
+    use rustyhook::testing::{assert_failed, assert_passed, hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("file.txt", "content\n");
+    let count = repo.path().join("preflights.log");
+    let script = repo.write("preflight.sh", format!("echo ran >> '{}'\n", count.display()));
+
+    let checked = |id: &str, preflight: &str| Hook { preflight: Some(preflight.to_string()), files: r"\.txt$".to_string(), ..hook(id, "system", &format!("true {}", id)) };
+    let preflight = format!("sh {}", script.display());
+    let report = repo.run(local_config(vec![
+        checked("first", &preflight),
+        checked("second", &preflight),
+        checked("broken", "false"),
+    ]));
+
+    // A preflight command runs once per session, however many hooks share it
+    assert_passed(&report, "first");
+    assert_passed(&report, "second");
+    assert_eq!(std::fs::read_to_string(&count).unwrap(), "ran\n");
+
+    // A failing preflight fails the hook with a provisioning error
+    assert_failed(&report, "broken");
+    let message = hook_result(&report, "broken").message.clone().unwrap();
+    assert!(message.contains("Preflight command: false"), "{}", message);
+}
+
+#[test]
+fn test_entry_with_several_commands() {
+    use rustyhook::testing::{assert_failed, assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("file.txt", "content\n");
+    let log = repo.path().join("commands.log");
+    let generate = repo.write("generate.sh", format!("echo generate \"$@\" >> '{}'\n", log.display()));
+    let check = repo.write("check.sh", format!("echo check \"$@\" >> '{}'\n", log.display()));
+
+    let pipeline = |id: &str, commands: &[String]| Hook {
+        entry: rustyhook::config::parser::join_commands(commands),
+        args: vec!["--arg".to_string()],
+        files: r"\.txt$".to_string(),
+        ..hook(id, "system", "")
+    };
+    let generate = format!("sh {}", generate.display());
+    let check = format!("sh {}", check.display());
+
+    // The commands run in order, only the last with the hook's arguments and files
+    let report = repo.run(local_config(vec![pipeline("pipeline", &[generate.clone(), check.clone()])]));
+    assert_passed(&report, "pipeline");
+    let file = repo.path().join("file.txt");
+    assert_eq!(repo.read("commands.log"), format!("generate\ncheck --arg {}\n", file.display()));
+
+    // Earlier commands get the files where they say
+    std::fs::remove_file(&log).unwrap();
+    let report = repo.run(local_config(vec![pipeline("placed", &[format!("{} {{files}} --after", generate), check.clone()])]));
+    assert_passed(&report, "placed");
+    assert_eq!(repo.read("commands.log"), format!("generate {} --after\ncheck --arg {}\n", file.display(), file.display()));
+
+    // The first failure stops the hook
+    std::fs::remove_file(&log).unwrap();
+    let report = repo.run(local_config(vec![pipeline("failing", &["false".to_string(), check])]));
+    assert_failed(&report, "failing");
+    assert!(!log.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_tool_home() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::testing::{assert_passed, hook, local_config, TestRepo};
+    use rustyhook::toolchains::ready::mark_ready;
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "one\n");
+    let home_hook = Hook { files: r"\.txt$".to_string(), hook_type: HookType::BuiltIn, ..hook("print-home", "python", "print-home") };
+    let config = local_config(vec![home_hook.clone()]);
+
+    // An environment that is set up already, whose tool prints its home directory
+    let resolver = HookResolver::new(config.clone(), repo.cache_dir().to_path_buf());
+    let (install_dir, manifest) = resolver.environment(&home_hook).unwrap().unwrap();
+    let bin = install_dir.join("bin");
+    fs::create_dir_all(&bin).unwrap();
+    for (name, script) in [("python", "#!/bin/sh\n".to_string()), ("print-home", format!("#!/bin/sh\necho \"$HOME $XDG_CACHE_HOME\" > '{}'\n", repo.path().join("home.txt").display()))] {
+        fs::write(bin.join(name), script).unwrap();
+        fs::set_permissions(bin.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    mark_ready(&install_dir, &manifest).unwrap();
+
+    // The tool runs with a home directory of its environment, under the cache
+    let report = repo.run(config);
+    assert_passed(&report, "print-home");
+    let home = repo.cache_dir().join("home").join(HookResolver::tool_key(&home_hook));
+    assert!(home.is_dir());
+    assert_eq!(repo.read("home.txt"), format!("{} {}\n", home.display(), home.join(".cache").display()));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_repo_node_modules() {
+    use rustyhook::config::parser::NodeModules;
+    use rustyhook::testing::{assert_failed, assert_passed, hook, hook_result, local_config, TestRepo};
+    use std::os::unix::fs::PermissionsExt;
+
+    if which::which("npm").is_err() {
+        eprintln!("Skipping test_repo_node_modules: npm is not installed");
+        return;
+    }
+
+    let repo = TestRepo::new();
+    repo.write_staged("good.txt", "ok\n");
+    repo.write_staged("bad.txt", "bad\n");
+    repo.write("package.json", r#"{"name": "demo", "version": "1.0.0", "scripts": {"lint": "node check.js"}}"#);
+    repo.write(
+        "check.js",
+        "const fs = require('fs');\nprocess.exit(process.argv.slice(2).some(f => fs.readFileSync(f, 'utf8').includes('bad')) ? 1 : 0);\n",
+    );
+    let lint = repo.write("node_modules/.bin/repo-lint", "#!/bin/sh\n! grep -q bad \"$@\"\n");
+    std::fs::set_permissions(&lint, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let node_hook = |id: &str, file: &str| Hook {
+        working_dir: Some(repo.path().display().to_string()),
+        files: format!("^{}", file),
+        ..hook(id, "node", "repo-lint")
+    };
+    let script = |id: &str, file: &str| Hook { npm_script: Some("lint".to_string()), entry: String::new(), ..node_hook(id, file) };
+    let repo_bin = |id: &str, file: &str| Hook { node_modules: NodeModules::Repo, ..node_hook(id, file) };
+
+    // Scripts of package.json and commands of node_modules/.bin run without a managed environment
+    let report = repo.run(local_config(vec![
+        script("script-good", "good"),
+        script("script-bad", "bad"),
+        repo_bin("bin-good", "good"),
+        repo_bin("bin-bad", "bad"),
+    ]));
+    assert_passed(&report, "script-good");
+    assert_failed(&report, "script-bad");
+    assert_passed(&report, "bin-good");
+    assert_failed(&report, "bin-bad");
+
+    // node_modules that wasn't installed from the lockfile fails, unless the hook installs it
+    repo.write("package-lock.json", r#"{"name": "demo", "version": "1.0.0", "lockfileVersion": 3, "requires": true, "packages": {"": {"name": "demo", "version": "1.0.0"}}}"#);
+    let report = repo.run(local_config(vec![
+        repo_bin("not-installed", "good"),
+        Hook { node_modules: NodeModules::Ci, ..script("installed", "good") },
+    ]));
+    assert_failed(&report, "not-installed");
+    let message = hook_result(&report, "not-installed").message.clone().unwrap();
+    assert!(message.contains("Run `npm ci`"), "{}", message);
+    assert_passed(&report, "installed");
+}
+
+#[test]
+fn test_setup_and_run_parallelism() {
+    use rustyhook::runner::HookStatus;
+    use rustyhook::testing::{hook, TestRepo};
+
+    // Both limits default to parallelism
+    let config: Config = serde_yaml::from_str("parallelism: 3\nrepos: []\n").unwrap();
+    assert_eq!((config.setup_limit(), config.run_limit()), (3, 3));
+    let config: Config = serde_yaml::from_str("parallelism: 3\nsetup_parallelism: 1\nrun_parallelism: 0\nrepos: []\n").unwrap();
+    assert_eq!((config.setup_limit(), config.run_limit()), (1, 0));
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "notes\n");
+    let in_process = |id: &str| {
+        let mut hook = hook(id, "system", &format!("echo {}", id));
+        hook.hook_type = HookType::BuiltIn;
+        hook
+    };
+    let config = Config {
+        parallelism: 4,
+        setup_parallelism: Some(1),
+        run_parallelism: Some(2),
+        repos: vec![Repo { repo: "local".to_string(), hooks: vec![in_process("one"), in_process("two"), in_process("three")] }],
+        ..Default::default()
+    };
+
+    // Hooks run at the run limit, after their environments were set up one at a time
+    let executor = ParallelExecutor::new(config, repo.cache_dir().to_path_buf());
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let plan = rt.block_on(executor.plan(&repo.staged_files())).unwrap();
+    assert!(plan.to_text().starts_with("Execution plan (parallelism: 2)"));
+    rt.block_on(executor.run_all_hooks(repo.staged_files())).unwrap();
+    let report = rt.block_on(executor.report());
+    assert_eq!(report.hooks.len(), 3);
+    assert!(report.hooks.iter().all(|result| result.status == HookStatus::Passed));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_success_exit_codes() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::testing::{assert_failed, assert_passed, hook, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("notes.txt", "notes\n");
+    let script = repo.write("findings.sh", "#!/bin/sh\nexit \"$1\"\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Configured codes pass and others fail, in a separate process or with the hook's tool
+    let exiting = |code: &str, separate_process: bool| {
+        let mut hook = hook("findings", "system", &format!("{} {}", script.display(), code));
+        hook.separate_process = separate_process;
+        if !separate_process {
+            hook.hook_type = HookType::BuiltIn;
+        }
+        hook.success_exit_codes = vec![0, 1];
+        hook
+    };
+    for separate_process in [true, false] {
+        assert_passed(&repo.run(local_config(vec![exiting("1", separate_process)])), "findings");
+        assert_failed(&repo.run(local_config(vec![exiting("2", separate_process)])), "findings");
+    }
+    assert_failed(&repo.run(local_config(vec![hook("findings", "system", &format!("{} 1", script.display()))])), "findings");
+
+    let config: Config = serde_yaml::from_str("repos:\n  - repo: local\n    hooks:\n      - id: grep\n        name: grep\n        entry: grep\n        language: system\n        files: ''\n        success_exit_codes: [0, 1]\n").unwrap();
+    assert_eq!(config.repos[0].hooks[0].success_exit_codes, [0, 1]);
+}
+
+#[test]
+fn test_output_parsers() {
+    use std::os::unix::fs::PermissionsExt;
+    use rustyhook::config::parse_config;
+    use rustyhook::hooks::Severity;
+    use rustyhook::testing::{assert_failed, hook, hook_result, local_config, TestRepo};
+    use rustyhook::toolchains::parsers::{OutputFormat, OutputParser};
+
+    let repo = TestRepo::new();
+    repo.write_staged("main.c", "int main;\n");
+    let script = repo.write("compile.sh", "#!/bin/sh\necho 'compiling'\necho 'main.c:1:5: warning: unused variable'\necho 'main.c:2: error: expected ;'\nexit 1\n");
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Diagnostics are parsed from the output of failing hooks, in a separate process or with the hook's tool
+    for separate_process in [true, false] {
+        let mut compile = hook("compile", "system", &script.display().to_string());
+        compile.separate_process = separate_process;
+        if !separate_process {
+            compile.hook_type = HookType::BuiltIn;
+        }
+        compile.output_format = OutputFormat::Gcc;
+        let report = repo.run(local_config(vec![compile]));
+        assert_failed(&report, "compile");
+        let diagnostics = &hook_result(&report, "compile").diagnostics;
+        assert_eq!(diagnostics.len(), 2, "{:?}", diagnostics);
+        assert_eq!((diagnostics[0].path.as_path(), diagnostics[0].line, diagnostics[0].column), (Path::new("main.c"), 1, Some(5)));
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!((diagnostics[1].column, diagnostics[1].severity), (None, Severity::Error));
+        assert_eq!(diagnostics[1].message, "expected ;");
+    }
+
+    let flake8 = OutputParser::new(OutputFormat::Flake8, None).unwrap().parse("app.py:3:1: W291 trailing whitespace\napp.py:4:80: E501 line too long\n");
+    assert_eq!(flake8.iter().map(|d| (d.rule.as_str(), d.severity)).collect::<Vec<_>>(), [("W291", Severity::Warning), ("E501", Severity::Error)]);
+
+    let eslint = r#"[{"filePath":"/src/a.js","messages":[{"ruleId":"semi","severity":1,"message":"Missing semicolon.","line":2,"column":9}]}]"#;
+    let eslint = OutputParser::new(OutputFormat::EslintJson, None).unwrap().parse(eslint);
+    assert_eq!((eslint[0].rule.as_str(), eslint[0].line, eslint[0].column, eslint[0].severity), ("semi", 2, Some(9), Severity::Warning));
+
+    let ruff = r#"[{"code":"F401","filename":"/src/b.py","location":{"row":1,"column":8},"message":"`os` imported but unused"}]"#;
+    let ruff = OutputParser::new(OutputFormat::RuffJson, None).unwrap().parse(ruff);
+    assert_eq!((ruff[0].path.as_path(), ruff[0].rule.as_str(), ruff[0].line), (Path::new("/src/b.py"), "F401", 1));
+
+    let custom = OutputParser::new(OutputFormat::Regex, Some(r"^(?P<file>\S+) line (?P<line>\d+): (?P<message>.+)$")).unwrap();
+    assert_eq!(custom.parse("x.sh line 7: bad quoting\nnot a problem\n").len(), 1);
+    assert!(OutputParser::new(OutputFormat::Regex, None).is_err());
+    assert!(OutputParser::new(OutputFormat::Regex, Some(r"(?P<file>\S+): (?P<message>.+)")).unwrap_err().contains("line"));
+
+    // Configs with a bad output_pattern are rejected
+    let config = "repos:\n  - repo: local\n    hooks:\n      - id: lint\n        name: lint\n        entry: lint\n        language: system\n        output_format: regex\n        output_pattern: '(?P<file>.+)'\n";
+    assert!(parse_config(repo.write("bad.yaml", config)).is_err());
+    let config = "repos:\n  - repo: local\n    hooks:\n      - id: lint\n        name: lint\n        entry: lint\n        language: system\n        output_format: flake8\n";
+    assert_eq!(parse_config(repo.write("flake8.yaml", config)).unwrap().repos[0].hooks[0].output_format, OutputFormat::Flake8);
+}
+
+#[test]
+fn test_require_clean() {
+    use rustyhook::error_codes::{ErrorCode, HasErrorCode};
+    use rustyhook::runner::HookResolverError;
+    use rustyhook::testing::{assert_failed, assert_passed, hook, hook_result, local_config, TestRepo};
+
+    let repo = TestRepo::new();
+    repo.write_staged("clean.txt", "clean\n");
+    repo.write_staged("wip.txt", "staged\n");
+    let fixer = || {
+        let mut fixer = hook("fixer", "system", "true");
+        fixer.require_clean = true;
+        fixer
+    };
+
+    // Files matching the index run
+    assert_passed(&repo.run(local_config(vec![fixer()])), "fixer");
+
+    // Unstaged changes to the hook's files refuse the run, naming the files
+    repo.write("wip.txt", "work in progress\n");
+    let report = repo.run(local_config(vec![fixer()]));
+    assert_failed(&report, "fixer");
+    let error = hook_result(&report, "fixer").error.as_ref().unwrap();
+    assert_eq!(error.code, ErrorCode::UncleanFiles);
+    assert!(error.fields["files"].ends_with("wip.txt"), "{:?}", error.fields);
+    assert!(!error.fields["files"].contains("clean.txt"));
+    assert!(error.message.contains("git add"), "{}", error.message);
+
+    // Hooks not requiring a clean tree, and hooks on other files, still run
+    assert_passed(&repo.run(local_config(vec![hook("fixer", "system", "true")])), "fixer");
+    assert_passed(&repo.run_on(local_config(vec![fixer()]), vec![PathBuf::from("clean.txt")]), "fixer");
+
+    let error = HookResolverError::UncleanFiles { hook_id: "fixer".to_string(), files: vec![PathBuf::from("wip.txt")] };
+    assert_eq!(error.error_info().code.code(), "RH3010");
+}
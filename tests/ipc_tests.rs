@@ -0,0 +1,78 @@
+//! Tests for the local IPC transport
+
+use std::net::SocketAddr;
+
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use rustyhook::ipc::{self, Endpoint, Listener};
+
+#[tokio::test]
+async fn test_ipc_transport() {
+    let cache = TempDir::new().unwrap();
+    let mut listener = Listener::bind(cache.path()).await.unwrap();
+    if cfg!(unix) {
+        assert!(matches!(listener.endpoint(), Endpoint::Unix(_) | Endpoint::Tcp(_)));
+    }
+
+    // The token is private to the user
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let token = cache.path().join(ipc::IPC_DIR).join(ipc::TOKEN_FILE);
+        assert_eq!(std::fs::metadata(token).unwrap().mode() & 0o077, 0);
+    }
+
+    let server = tokio::spawn(async move {
+        let mut connection = listener.accept().await.unwrap();
+        let mut request = [0u8; 4];
+        connection.read_exact(&mut request).await.unwrap();
+        connection.write_all(b"pong").await.unwrap();
+        request
+    });
+
+    // Clients find the server and its token through the cache root
+    let mut client = ipc::connect(cache.path()).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut response = [0u8; 4];
+    client.read_exact(&mut response).await.unwrap();
+    assert_eq!(&response, b"pong");
+    assert_eq!(&server.await.unwrap(), b"ping");
+}
+
+#[tokio::test]
+async fn test_ipc_rejects_wrong_token() {
+    let loopback = Endpoint::Tcp(SocketAddr::from(([127, 0, 0, 1], 0)));
+    let mut listener = Listener::bind_endpoint(&loopback, "secret".to_string()).await.unwrap();
+    let endpoint = listener.endpoint().clone();
+    assert_ne!(endpoint, loopback);
+
+    let server = tokio::spawn(async move {
+        let mut connection = listener.accept().await.unwrap();
+        connection.write_all(b"hi").await.unwrap();
+    });
+
+    let denied = ipc::connect_endpoint(&endpoint, "guess").await.err().unwrap();
+    assert_eq!(denied.kind(), std::io::ErrorKind::PermissionDenied);
+
+    // The server keeps accepting after turning a client away
+    let mut client = ipc::connect_endpoint(&endpoint, "secret").await.unwrap();
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await.unwrap();
+    assert_eq!(&greeting, b"hi");
+    server.await.unwrap();
+
+    // A client that never sends its token doesn't hold up the others
+    let mut listener = Listener::bind_endpoint(&loopback, "secret".to_string()).await.unwrap();
+    let endpoint = listener.endpoint().clone();
+    let Endpoint::Tcp(addr) = endpoint else { unreachable!() };
+    let _idle = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let server = tokio::spawn(async move { listener.accept().await.map(|_| ()) });
+    let client = tokio::time::timeout(std::time::Duration::from_secs(2), ipc::connect_endpoint(&endpoint, "secret"));
+    assert!(client.await.expect("an idle client delayed the handshake").is_ok());
+    server.await.unwrap().unwrap();
+
+    // Only loopback TCP endpoints are accepted
+    assert!("tcp:10.0.0.1:80".parse::<Endpoint>().is_err());
+    assert_eq!(endpoint.to_string().parse::<Endpoint>().unwrap(), endpoint);
+}
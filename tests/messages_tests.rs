@@ -0,0 +1,75 @@
+//! Tests for the message catalog
+
+use std::collections::HashSet;
+
+use rustyhook::messages::{catalog_for, en, format_with, MessageId, CATALOGS};
+use rustyhook::runner::HookResolverError;
+
+#[test]
+fn test_catalogs_are_complete() {
+    let keys: HashSet<&str> = MessageId::ALL.iter().map(MessageId::key).collect();
+    assert_eq!(keys.len(), MessageId::ALL.len(), "message keys must be unique");
+
+    for id in MessageId::ALL {
+        let english = en(*id).unwrap_or_else(|| panic!("{} has no English message", id.key()));
+        for catalog in CATALOGS {
+            // Translations must keep the placeholders of the English message
+            if let Some(translated) = (catalog.messages)(*id) {
+                for placeholder in english.split('{').skip(1).filter_map(|part| part.split('}').next()) {
+                    let placeholder = format!("{{{}}}", placeholder);
+                    assert!(translated.contains(&placeholder), "{} of {} lacks {}", id.key(), catalog.language, placeholder);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_locale_fallback_and_placeholders() {
+    assert_eq!(catalog_for("en_US.UTF-8").language, "en");
+    assert_eq!(catalog_for("xx_XX").language, "en");
+
+    let message = format_with(catalog_for("C"), MessageId::DoctorToolFound, &[("tool", &"Ruby"), ("path", &"/usr/bin/ruby")]);
+    assert_eq!(message, "Ruby is installed at: /usr/bin/ruby");
+
+    // Blocked Git hooks tell how to get past their own stage
+    assert_eq!(MessageId::blocked("pre-commit"), MessageId::CommitBlocked);
+    assert_eq!(MessageId::blocked("pre-push"), MessageId::PushBlocked);
+    let message = format_with(catalog_for("en"), MessageId::blocked("commit-msg"), &[("stage", &"commit-msg"), ("hooks", &"conventional"), ("bin", &"rh")]);
+    assert!(message.starts_with("The commit-msg hook rejected the commit message: conventional failed"), "{}", message);
+    assert!(!message.contains("git add -u"), "{}", message);
+}
+
+#[test]
+fn test_errors_have_stable_keys() {
+    let error = HookResolverError::HookNotFound("Hook lint not found".to_string());
+    assert_eq!(error.message_id().key(), "error.hook-not-found");
+    assert!(error.to_string().contains("Hook lint not found"));
+
+    let error = HookResolverError::IoError(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied"));
+    assert_eq!(error.message_id(), MessageId::PermissionDenied);
+}
+
+#[test]
+fn test_error_codes_are_stable() {
+    use rustyhook::error_codes::{ErrorCode, HasErrorCode};
+    use rustyhook::toolchains::ToolError;
+
+    let codes: HashSet<&str> = ErrorCode::all().map(|code| code.code()).collect();
+    assert_eq!(codes.len(), ErrorCode::all().count(), "error codes must be unique");
+    assert!(codes.iter().all(|code| code.len() == 6 && code.starts_with("RH") && code[2..].chars().all(|c| c.is_ascii_digit())));
+
+    // Wrapped errors keep the code of the error they wrap, with structured fields
+    let error = HookResolverError::ToolError(ToolError::InstallationError("download failed".to_string()));
+    let info = error.error_info();
+    assert_eq!((info.code.code(), info.category), ("RH2002", "toolchain"));
+
+    let error = HookResolverError::ProvisioningError { hook_id: "ruff".to_string(), command: "ruff --version".to_string(), details: "exit 1".to_string() };
+    let json = serde_json::to_value(error.error_info()).unwrap();
+    assert_eq!(json["code"], "RH2004");
+    assert_eq!(json["fields"]["hook"], "ruff");
+    assert_eq!(json["fields"]["command"], "ruff --version");
+
+    // Errors are described by the message of their code
+    assert_eq!(error.message_id(), ErrorCode::ProvisioningFailed.message_id().unwrap());
+}
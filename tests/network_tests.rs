@@ -0,0 +1,46 @@
+//! Tests for forbidding network access during runs
+//!
+//! The network policy is process-wide, so these tests live in their own binary.
+
+use std::path::PathBuf;
+
+use rustyhook::runner::container::{command, Mount};
+use rustyhook::toolchains::network;
+use rustyhook::toolchains::ToolError;
+
+#[test]
+fn test_forbid_network() {
+    assert!(network::check_allowed("Python").is_ok());
+    network::set_forbidden(true);
+
+    // Downloads fail loudly instead of falling back
+    match network::check_allowed("Node.js 20.10.0") {
+        Err(ToolError::InstallationError(message)) => {
+            assert!(message.contains("--forbid-network"));
+            assert!(message.contains("Node.js 20.10.0"));
+        }
+        other => panic!("expected an installation error, got {:?}", other),
+    }
+
+    // Containers neither pull nor get a network
+    let mount = Mount { host_root: PathBuf::from("/repo"), working_dir: PathBuf::from("/repo") };
+    let cmd = command("docker", "alpine", "lint", &mount, &Default::default(), &["true".to_string()]);
+    let args: Vec<String> = cmd.get_args().map(|arg| arg.to_string_lossy().into_owned()).collect();
+    assert!(args.windows(2).any(|pair| pair == ["--network", "none"]));
+    assert!(args.windows(2).any(|pair| pair == ["--pull", "never"]));
+
+    // Hook processes still run, isolated where the system supports it
+    #[cfg(unix)]
+    {
+        let status = network::hook_command("true").status().unwrap();
+        assert!(status.success());
+
+        // Missing programs aren't run through unshare, so they fail as missing
+        let mut missing = network::hook_command("rustyhook-no-such-command");
+        assert_eq!(missing.get_program(), "rustyhook-no-such-command");
+        assert_eq!(missing.status().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+
+    network::set_forbidden(false);
+    assert!(network::check_allowed("Python").is_ok());
+}
@@ -50,10 +50,8 @@ fn test_node_tool_with_direct_download() {
         // List the contents of the installation directory
         if let Ok(entries) = std::fs::read_dir(&install_dir) {
             println!("Contents of installation directory:");
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    println!("  {:?}", entry.path());
-                }
+            for entry in entries.flatten() {
+                println!("  {:?}", entry.path());
             }
         } else {
             println!("Failed to read installation directory");
@@ -68,10 +66,8 @@ fn test_node_tool_with_direct_download() {
             // List the contents of the bin directory
             if let Ok(entries) = std::fs::read_dir(&bin_dir) {
                 println!("Contents of bin directory:");
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        println!("  {:?}", entry.path());
-                    }
+                for entry in entries.flatten() {
+                    println!("  {:?}", entry.path());
                 }
             } else {
                 println!("Failed to read bin directory");
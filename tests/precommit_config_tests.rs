@@ -25,7 +25,7 @@ fn test_convert_to_rustyhook_config() {
 
     // Check that the conversion was successful
     assert_eq!(rustyhook_config.default_stages, vec!["commit".to_string(), "push".to_string()]);
-    assert_eq!(rustyhook_config.fail_fast, true);
+    assert!(rustyhook_config.fail_fast);
 
     // Check that all repositories were converted
     assert!(rustyhook_config.repos.len() >= 5); // We have 5 repos in our test config
@@ -77,6 +77,27 @@ fn test_convert_to_rustyhook_config() {
     // Note: additional_dependencies is not supported in the current implementation
 }
 
+#[test]
+fn test_convert_keeps_separators() {
+    let precommit_config = serde_yaml::from_str(r#"
+repos:
+  - repo: local
+    hooks:
+      - id: tool
+        entry: tool
+        language: system
+        args: [--check, --, --not-an-option]
+"#).unwrap();
+    let config = convert_to_rustyhook_config(&precommit_config);
+
+    // Files are still appended after the args, so `--` keeps applying to them
+    let hook = &config.repos[0].hooks[0];
+    assert_eq!(hook.args, vec!["--check", "--", "--not-an-option"]);
+    assert!(hook.args_after_files.is_empty());
+    let reparsed: rustyhook::config::Config = serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+    assert_eq!(reparsed.repos[0].hooks[0].args, hook.args);
+}
+
 #[test]
 fn test_convert_from_precommit() {
     // Create a temporary directory for the test
@@ -48,10 +48,8 @@ fn test_ruby_tool_with_direct_download() {
         // List the contents of the installation directory
         if let Ok(entries) = std::fs::read_dir(&install_dir) {
             println!("Contents of installation directory:");
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    println!("  {:?}", entry.path());
-                }
+            for entry in entries.flatten() {
+                println!("  {:?}", entry.path());
             }
         } else {
             println!("Failed to read installation directory");
@@ -66,10 +64,8 @@ fn test_ruby_tool_with_direct_download() {
             // List the contents of the bin directory
             if let Ok(entries) = std::fs::read_dir(&bin_dir) {
                 println!("Contents of bin directory:");
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        println!("  {:?}", entry.path());
-                    }
+                for entry in entries.flatten() {
+                    println!("  {:?}", entry.path());
                 }
             } else {
                 println!("Failed to read bin directory");
@@ -114,7 +110,7 @@ fn test_ruby_tool_with_ruby_version_file() {
     println!("Created .ruby-version file at {:?} with version {}", ruby_version_file, ruby_version);
 
     // Save the original directory
-    let original_dir = std::env::current_dir().unwrap();
+    let _original_dir = std::env::current_dir().unwrap();
 
     // Create a Ruby tool with a test gem
     let ruby_tool = RubyTool::new("rubocop", "1.0.0", vec!["rubocop".to_string()]);
@@ -212,3 +208,27 @@ fn test_ruby_tool_with_monorepo_structure() {
     // Reset the current directory
     env::set_current_dir(original_dir).unwrap();
 }
+
+#[test]
+fn test_reuse_installed_rubies() {
+    use rustyhook::toolchains::ruby::{find_installed_ruby, version_manager_dirs};
+
+    let home = tempfile::tempdir().unwrap();
+    let install = |dir: &std::path::Path| {
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::write(dir.join("bin").join(if cfg!(windows) { "ruby.exe" } else { "ruby" }), "").unwrap();
+    };
+    assert_eq!(find_installed_ruby("3.3.0", Some(home.path())), None);
+    assert!(version_manager_dirs("3.3.0", Some(home.path())).contains(&home.path().join(".asdf/installs/ruby/3.3.0")));
+
+    // A Ruby of the requested version is found, whichever manager installed it
+    let rvm_ruby = home.path().join(".rvm").join("rubies").join("ruby-3.3.0");
+    install(&rvm_ruby);
+    assert_eq!(find_installed_ruby("3.3.0", Some(home.path())), Some(rvm_ruby));
+    assert_eq!(find_installed_ruby("3.2.2", Some(home.path())), None);
+
+    // rbenv is looked at first
+    let rbenv_ruby = home.path().join(".rbenv").join("versions").join("3.3.0");
+    install(&rbenv_ruby);
+    assert_eq!(find_installed_ruby("3.3.0", Some(home.path())), Some(rbenv_ruby));
+}
@@ -1,7 +1,6 @@
 //! Tests for toolchain functionality
 
-use rustyhook::toolchains::{PythonTool, NodeTool, Tool, SetupContext};
-use std::path::PathBuf;
+use rustyhook::toolchains::{PythonTool, Tool, SetupContext};
 
 #[test]
 fn test_python_tool_with_uv() {
@@ -40,10 +39,8 @@ fn test_python_tool_with_uv() {
         // List the contents of the installation directory
         if let Ok(entries) = std::fs::read_dir(&install_dir) {
             println!("Contents of installation directory:");
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    println!("  {:?}", entry.path());
-                }
+            for entry in entries.flatten() {
+                println!("  {:?}", entry.path());
             }
         } else {
             println!("Failed to read installation directory");
@@ -62,10 +59,8 @@ fn test_python_tool_with_uv() {
             // List the contents of the bin directory
             if let Ok(entries) = std::fs::read_dir(&bin_dir) {
                 println!("Contents of bin directory:");
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        println!("  {:?}", entry.path());
-                    }
+                for entry in entries.flatten() {
+                    println!("  {:?}", entry.path());
                 }
             } else {
                 println!("Failed to read bin directory");
@@ -209,10 +204,8 @@ fn test_python_build_standalone() {
         // List the contents of the installation directory
         if let Ok(entries) = std::fs::read_dir(&install_dir) {
             println!("Contents of installation directory:");
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    println!("  {:?}", entry.path());
-                }
+            for entry in entries.flatten() {
+                println!("  {:?}", entry.path());
             }
         } else {
             println!("Failed to read installation directory");
@@ -231,10 +224,8 @@ fn test_python_build_standalone() {
             // List the contents of the bin directory
             if let Ok(entries) = std::fs::read_dir(&bin_dir) {
                 println!("Contents of bin directory:");
-                for entry in entries {
-                    if let Ok(entry) = entry {
-                        println!("  {:?}", entry.path());
-                    }
+                for entry in entries.flatten() {
+                    println!("  {:?}", entry.path());
                 }
             } else {
                 println!("Failed to read bin directory");
@@ -275,3 +266,329 @@ fn test_python_build_standalone() {
     // Assert that the black package is installed
     assert!(black_path.exists(), "black package is not installed");
 }
+
+#[cfg(unix)]
+#[test]
+fn test_hermetic_path() {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::process::Command;
+    use rustyhook::toolchains::hermetic::{apply_hook_env, hermetic_path};
+
+    let tool = PythonTool::new("black", "1.0.0", vec!["black".to_string()]);
+    let bin_dirs = tool.bin_dirs();
+    assert_eq!(bin_dirs, vec![tool.install_dir().join("bin")]);
+
+    // The tool's bin dir comes first, followed by the system fallback only
+    let path = hermetic_path(&bin_dirs);
+    let dirs: Vec<PathBuf> = std::env::split_paths(&path).collect();
+    assert_eq!(dirs[0], tool.install_dir().join("bin"));
+    assert!(dirs.contains(&PathBuf::from("/usr/bin")));
+    assert!(!dirs.contains(&PathBuf::from("/opt/global/bin")));
+
+    // Relative bin dirs are made absolute, since hooks may run elsewhere
+    let path = hermetic_path(&[PathBuf::from("relative/bin")]);
+    assert_eq!(std::env::split_paths(&path).next().unwrap(), std::env::current_dir().unwrap().join("relative/bin"));
+
+    // A PATH override in the hook env replaces the fallback, and can extend the inherited PATH
+    let mut env = HashMap::new();
+    env.insert("PATH".to_string(), "/opt/extra/bin:$PATH".to_string());
+    env.insert("HOOK_VAR".to_string(), "value".to_string());
+
+    let mut command = Command::new("/bin/sh");
+    command.arg("-c").arg("echo \"$PATH|$HOOK_VAR\"").env("PATH", "/opt/global/bin");
+    apply_hook_env(&mut command, &bin_dirs, &env);
+    let output = command.output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    let inherited = std::env::var("PATH").unwrap();
+    let expected = format!("{}:/opt/extra/bin:{}|value", tool.install_dir().join("bin").display(), inherited);
+    assert_eq!(stdout, expected);
+}
+
+#[test]
+fn test_ready_marker() {
+    use rustyhook::toolchains::ready::{is_ready, mark_ready, READY_MARKER};
+    use rustyhook::toolchains::{FailTool, NodeTool};
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let install_dir = temp_dir.path().join("venvs").join("python-ruff");
+
+    // Tools that provision an environment describe it, the others don't
+    let tool = PythonTool::new("ruff", "0.4.0", vec!["ruff".to_string()]);
+    let manifest = tool.setup_manifest().unwrap();
+    let other = PythonTool::new("ruff", "0.5.0", vec!["ruff".to_string()]).setup_manifest().unwrap();
+    assert_ne!(manifest, other);
+    assert!(NodeTool::new("biome", "1.6.2", vec!["@biomejs/biome".to_string()], true, None).setup_manifest().is_some());
+    assert!(FailTool::new("fail".to_string(), "latest".to_string(), "message".to_string()).setup_manifest().is_none());
+
+    // A directory without the marker, as left by an interrupted setup, isn't ready
+    std::fs::create_dir_all(install_dir.join("bin")).unwrap();
+    assert!(!is_ready(&install_dir, &manifest));
+
+    mark_ready(&install_dir, &manifest).unwrap();
+    assert!(install_dir.join(READY_MARKER).exists());
+    assert!(is_ready(&install_dir, &manifest));
+
+    // The marker is tied to the manifest it was written for
+    assert!(!is_ready(&install_dir, &other));
+}
+
+#[test]
+fn test_latest_versions() {
+    use rustyhook::config::{Config, Lockfile, Repo};
+    use rustyhook::cache::{self, CacheCategory, CacheManager, CacheStats};
+    use rustyhook::config::parser::CacheTtl;
+    use rustyhook::runner::latest::{pin_latest_versions_with, pin_locked_versions, resolve, Package, Registry};
+    use rustyhook::toolchains::ToolError;
+    use rustyhook::testing::hook;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let lockfile = temp_dir.path().join(".rustyhook").join("lock.yaml");
+    let cache_root = temp_dir.path().join("cache");
+    std::fs::create_dir_all(&cache_root).unwrap();
+
+    let versioned = |id: &str, language: &str, entry: &str, version: &str| rustyhook::config::Hook { version: Some(version.to_string()), ..hook(id, language, entry) };
+    let config = Config {
+        repos: vec![
+            Repo {
+                repo: "local".to_string(),
+                hooks: vec![
+                    versioned("ruff", "python", "ruff check", "latest"),
+                    versioned("biome", "node", "biome check", "latest"),
+                    versioned("rubocop", "ruby", "rubocop", "0.9.0"),
+                    versioned("lint", "system", "lint", "latest"),
+                ],
+            },
+            Repo { repo: "https://github.com/owner/tools.git".to_string(), hooks: vec![versioned("tools", "script", "check.sh", "latest")] },
+        ],
+        ..Default::default()
+    };
+
+    // Packages missing from the lockfile are asked for and recorded
+    let mut asked = Vec::new();
+    let pinned = pin_latest_versions_with(&config, &lockfile, &cache_root, |package| {
+        asked.push(package.key());
+        Ok(if package.registry == Registry::GitHub { "v2.0.0".to_string() } else { "1.2.3".to_string() })
+    });
+    assert_eq!(asked, ["pypi:ruff", "npm:@biomejs/biome", "github:owner/tools"]);
+    let versions = |config: &Config| -> Vec<String> { config.repos.iter().flat_map(|repo| repo.hooks.iter().map(|hook| hook.version.clone().unwrap())).collect() };
+    assert_eq!(versions(&pinned), ["1.2.3", "1.2.3", "0.9.0", "latest", "v2.0.0"]);
+    assert_eq!(Lockfile::load(&lockfile).unwrap().versions.get("pypi:ruff").map(String::as_str), Some("1.2.3"));
+
+    // Later runs keep the recorded versions without asking
+    let pinned_again = pin_latest_versions_with(&config, &lockfile, &cache_root, |_| panic!("registry asked again"));
+    assert_eq!(versions(&pinned_again), versions(&pinned));
+    assert_eq!(versions(&pin_locked_versions(&config, &lockfile)), versions(&pinned));
+
+    // Removing an entry resolves it again, from the cached answer while it's fresh
+    std::fs::remove_file(&lockfile).unwrap();
+    assert_eq!(versions(&pin_locked_versions(&config, &lockfile)), ["latest", "latest", "0.9.0", "latest", "latest"]);
+    let from_cache = pin_latest_versions_with(&config, &lockfile, &cache_root, |_| panic!("registry asked again"));
+    assert_eq!(versions(&from_cache), versions(&pinned));
+
+    // Answers expire after `cache_ttl.versions`, and an expired answer is used when the registry fails
+    let ruff = Package::for_hook("local", &versioned("ruff", "python", "ruff check", "latest")).unwrap();
+    let expired = CacheTtl { versions: 0, ..Default::default() };
+    let hits = |stats: Vec<CacheStats>| stats.iter().find(|stats| stats.category == CacheCategory::Versions).map(|stats| (stats.hits, stats.misses)).unwrap();
+    let (hits_before, misses_before) = hits(cache::stats());
+    assert_eq!(resolve(&ruff, &cache_root, &CacheTtl::default(), |_| panic!("registry asked again")).unwrap(), "1.2.3");
+    assert_eq!(resolve(&ruff, &cache_root, &expired, |_| Ok("1.3.0".to_string())).unwrap(), "1.3.0");
+    assert_eq!(resolve(&ruff, &cache_root, &expired, |package| Err(ToolError::ExecutionError(format!("{} unreachable", package.key())))).unwrap(), "1.3.0");
+    let (hits_after, misses_after) = hits(cache::stats());
+    assert!(hits_after > hits_before && misses_after >= misses_before + 2);
+    assert!(cache_root.join(cache::METADATA_DIR).join("versions").join(CacheManager::entry_name("pypi:ruff")).is_file());
+
+    // Exact versions pin the installed package
+    assert_eq!(Registry::PyPI.pin("ruff", "1.2.3"), "ruff==1.2.3");
+    assert_eq!(Registry::Npm.pin("@biomejs/biome", "1.2.3"), "@biomejs/biome@1.2.3");
+    assert_eq!(Registry::RubyGems.pin("rubocop", "1.2.3"), "rubocop:1.2.3");
+    assert_eq!(Registry::Npm.pin("eslint", "^8.0.0"), "eslint");
+    assert_eq!(Package::for_hook("local", &hook("codespell", "python", "codespell")).map(|package| package.key()), Some("pypi:codespell".to_string()));
+}
+
+#[test]
+fn test_queries() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use rustyhook::toolchains::queries::{batch, concurrency, get};
+
+    // Batches run in parallel up to the limit and keep the order of the items
+    let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let items: Vec<usize> = (0..12).collect();
+    let doubled = batch(&items, |item| {
+        most.fetch_max(running.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        running.fetch_sub(1, Ordering::SeqCst);
+        item * 2
+    });
+    assert_eq!(doubled, items.iter().map(|item| item * 2).collect::<Vec<_>>());
+    assert!(most.load(Ordering::SeqCst) > 1 && most.load(Ordering::SeqCst) <= concurrency());
+
+    // Responses are revalidated with their ETag, and rate limits are explained
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/releases/latest", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut conditional = Vec::new();
+        for response in [
+            "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 17\r\n\r\n{\"tag_name\":\"v1\"}",
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nContent-Length: 0\r\n\r\n",
+            "HTTP/1.1 403 Forbidden\r\nX-RateLimit-Remaining: 0\r\nContent-Length: 0\r\n\r\n",
+        ] {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            let mut if_none_match = None;
+            while reader.read_line(&mut line).unwrap() > 2 {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("if-none-match:") {
+                    if_none_match = Some(value.trim().to_string());
+                }
+                line.clear();
+            }
+            conditional.push(if_none_match);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        conditional
+    });
+    let cache_root = tempfile::tempdir().unwrap();
+    assert_eq!(get(&url, Some(cache_root.path())).unwrap(), "{\"tag_name\":\"v1\"}");
+    assert_eq!(get(&url, Some(cache_root.path())).unwrap(), "{\"tag_name\":\"v1\"}");
+    assert!(get(&url, Some(cache_root.path())).unwrap_err().contains("rate limit exceeded"));
+    assert_eq!(server.join().unwrap(), [None, Some("\"v1\"".to_string()), Some("\"v1\"".to_string())]);
+}
+
+#[test]
+fn test_clean_up_install() {
+    use rustyhook::toolchains::downloads::{self, DOWNLOADS_DIR, EXTRACTED_DIR};
+
+    let cache_dir = tempfile::tempdir().unwrap();
+    let env_cache = cache_dir.path().join("python-abc");
+    let archive = env_cache.join(DOWNLOADS_DIR).join("python.tar.zst");
+    let extracted = env_cache.join(EXTRACTED_DIR).join("python");
+    std::fs::create_dir_all(archive.parent().unwrap()).unwrap();
+    std::fs::create_dir_all(extracted.join("bin")).unwrap();
+    std::fs::write(&archive, vec![0u8; 2048]).unwrap();
+    std::fs::write(extracted.join("bin").join("python3"), vec![0u8; 1024]).unwrap();
+    assert_eq!(downloads::leftover_sizes(cache_dir.path()), (2048, 1024));
+    assert_eq!(downloads::format_size(2048), "2.0 KiB");
+    assert_eq!(downloads::format_size(300 * 1024 * 1024), "300.0 MiB");
+
+    // Extracted trees go, archives stay by default
+    downloads::clean_up_install(&archive, Some(&extracted));
+    assert!(!env_cache.join(EXTRACTED_DIR).exists());
+    assert!(archive.exists());
+
+    // Unless keep_downloads is off
+    downloads::set_keep_downloads(false);
+    downloads::clean_up_install(&archive, None);
+    downloads::set_keep_downloads(true);
+    assert!(!env_cache.join(DOWNLOADS_DIR).exists());
+    assert_eq!(downloads::leftover_sizes(cache_dir.path()), (0, 0));
+}
+
+#[test]
+fn test_python_version_forms() {
+    use rustyhook::toolchains::python::{PythonImplementation, PythonRequest};
+
+    let cpython = PythonRequest::parse("cpython-3.12.1").unwrap();
+    assert_eq!(cpython, PythonRequest::parse("3.12.1").unwrap());
+    assert_eq!(cpython.implementation, PythonImplementation::CPython);
+    assert!(!cpython.free_threaded);
+    assert!(cpython.download_url("linux", "x86_64").unwrap().ends_with("/cpython-3.12.1-20240224-linux-x86_64-shared-install_only.tar.zst"));
+
+    let free_threaded = PythonRequest::parse("3.13.0t").unwrap();
+    assert!(free_threaded.free_threaded);
+    assert_eq!(free_threaded.version, "3.13.0");
+    assert!(free_threaded.download_url("linux", "x86_64").unwrap().contains("-x86_64-unknown-linux-gnu-freethreaded+"));
+
+    // Minor versions are the latest patch version with a build
+    assert_eq!(PythonRequest::parse("3.12").unwrap().version, "3.12.2");
+    assert!(PythonRequest::parse("3.12").unwrap().download_url("linux", "x86_64").unwrap().contains("/cpython-3.12.2-20240224-"));
+    let free_threaded = PythonRequest::parse("cpython-3.13t").unwrap();
+    assert_eq!(free_threaded.version, "3.13.0");
+    assert!(free_threaded.free_threaded);
+
+    let pypy = PythonRequest::parse("pypy3.10").unwrap();
+    assert_eq!(pypy, PythonRequest::parse("PyPy-3.10").unwrap());
+    assert_eq!(pypy.implementation, PythonImplementation::PyPy);
+    assert_eq!(pypy.download_url("linux", "x86_64").unwrap(), "https://downloads.python.org/pypy/pypy3.10-v7.3.16-linux64.tar.bz2");
+    assert!(pypy.download_url("windows", "x86_64").unwrap().ends_with("-win64.zip"));
+    assert!(pypy.download_url("freebsd", "x86_64").is_err());
+
+    // Invalid forms list the supported ones
+    for invalid in ["3.12t", "pypy3.10t", "pypy3.8", "2.7.18", "latest", "3", "jython-3.12", "3.7", "3.14t"] {
+        match PythonRequest::parse(invalid) {
+            Err(rustyhook::toolchains::ToolError::InstallationError(message)) => {
+                assert!(message.contains(invalid), "{}", message);
+                assert!(message.contains("pypy3.10"), "{}", message);
+            }
+            other => panic!("expected {} to be rejected, got {:?}", invalid, other),
+        }
+    }
+}
+
+#[test]
+fn test_uv_scripts() {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use rustyhook::config::parser::Hook;
+    use rustyhook::runner::HookResolver;
+    use rustyhook::toolchains::uv_script::{self, UvScriptTool};
+
+    let dir = tempfile::tempdir().unwrap();
+    let source = "#!/usr/bin/env python3\n# /// script\n# requires-python = \">=3.11\"\n# dependencies = [\n#   \"tomli-w\",\n# ]\n# ///\nimport sys\n";
+    assert_eq!(uv_script::metadata(source).unwrap(), "requires-python = \">=3.11\"\ndependencies = [\n  \"tomli-w\",\n]");
+    assert_eq!(uv_script::metadata("# /// pyproject\n# x = 1\n# ///\n"), None);
+    assert_eq!(uv_script::metadata("import sys\n"), None);
+
+    let script = dir.path().join("check_imports.py");
+    std::fs::write(&script, source).unwrap();
+    let plain = dir.path().join("plain.py");
+    std::fs::write(&plain, "import sys\n").unwrap();
+    assert!(uv_script::is_script(&script));
+    assert!(!uv_script::is_script(&plain));
+
+    // Scripts have no package to install or look up
+    let hook = Hook { id: "imports".to_string(), entry: format!("{} --strict", script.display()), language: "python".to_string(), ..Default::default() };
+    assert_eq!(HookResolver::package(&hook), None);
+    assert_eq!(uv_script::entry_script(&hook.entry), Some(script.clone()));
+
+    // The script runs with uv, in uv's cache
+    let log = dir.path().join("uv.log");
+    let uv = dir.path().join("uv");
+    std::fs::write(&uv, format!("#!/bin/sh\necho \"$UV_CACHE_DIR $*\" >> {}\n", log.display())).unwrap();
+    std::fs::set_permissions(&uv, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let cache = dir.path().join("cache").join(uv_script::UV_CACHE_DIR);
+    let tool = UvScriptTool::new("imports".to_string(), "latest".to_string(), script.clone(), vec!["--strict".to_string()])
+        .with_install_dir(cache.clone())
+        .with_uv(uv);
+    assert!(tool.is_installed());
+    let ctx = SetupContext { install_dir: cache.clone(), cache_dir: cache.clone(), force: false, version: None };
+    tool.setup(&ctx).unwrap();
+    tool.run(&[PathBuf::from("a.py")]).unwrap();
+    let calls = std::fs::read_to_string(&log).unwrap();
+    let calls: Vec<&str> = calls.lines().collect();
+    assert_eq!(calls[0], format!("{} sync --script {}", cache.display(), script.display()));
+    assert_eq!(calls[1], format!("{} run --quiet --script {} --strict a.py", cache.display(), script.display()));
+
+    // An older uv without `sync --script` resolves on first run instead
+    let old_uv = dir.path().join("old-uv");
+    std::fs::write(&old_uv, "#!/bin/sh\necho \"error: unexpected argument '--script' found\" >&2\nexit 2\n").unwrap();
+    std::fs::set_permissions(&old_uv, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let tool = UvScriptTool::new("imports".to_string(), "latest".to_string(), script.clone(), Vec::new())
+        .with_install_dir(cache.clone())
+        .with_uv(old_uv);
+    tool.setup(&ctx).unwrap();
+
+    // Other sync failures are reported
+    let broken_uv = dir.path().join("broken-uv");
+    std::fs::write(&broken_uv, "#!/bin/sh\necho \"error: No solution found for tomli-w\" >&2\nexit 1\n").unwrap();
+    std::fs::set_permissions(&broken_uv, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let tool = UvScriptTool::new("imports".to_string(), "latest".to_string(), script.clone(), Vec::new())
+        .with_install_dir(cache.clone())
+        .with_uv(broken_uv);
+    match tool.setup(&ctx) {
+        Err(rustyhook::toolchains::ToolError::InstallationError(message)) => assert!(message.contains("No solution found"), "{}", message),
+        other => panic!("expected the sync failure to be reported, got {:?}", other),
+    }
+}